@@ -0,0 +1,130 @@
+//! The cost of finding and recovering datagrams in a [`RecoveryQueue`], against the map it
+//! used to keep them in.
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use rak_rs::connection::queue::{NetQueue, RecoveryQueue};
+use rak_rs::protocol::index::{unwrapped, DatagramSeq, U24_MAX};
+
+/// The amounts of datagrams in flight that are measured.
+const WINDOWS: [u32; 2] = [256, 2048];
+
+/// The queue as it was before the ring, a map visited in full for every range.
+#[derive(Clone, Default)]
+struct MapQueue {
+    queue: HashMap<DatagramSeq, (u64, Vec<u8>)>,
+}
+
+impl MapQueue {
+    fn insert_id(&mut self, seq: DatagramSeq, item: Vec<u8>) {
+        self.queue.insert(seq, (0, item));
+    }
+
+    fn held_in(&self, range: RangeInclusive<DatagramSeq>) -> Vec<DatagramSeq> {
+        unwrapped(*range.start(), *range.end())
+            .into_iter()
+            .flat_map(|(first, last)| {
+                let mut held = self
+                    .queue
+                    .keys()
+                    .filter(|seq| (first..=last).contains(&seq.get()))
+                    .copied()
+                    .collect::<Vec<_>>();
+                held.sort_unstable();
+                held
+            })
+            .collect()
+    }
+
+    fn get(&self, seq: DatagramSeq) -> Option<&Vec<u8>> {
+        self.queue.get(&seq).map(|(_, item)| item)
+    }
+
+    fn remove(&mut self, seq: DatagramSeq) -> Option<Vec<u8>> {
+        self.queue.remove(&seq).map(|(_, item)| item)
+    }
+}
+
+/// The sequences of a window of `size` datagrams that wraps around halfway.
+fn window(size: u32) -> RangeInclusive<DatagramSeq> {
+    let first = DatagramSeq::new(U24_MAX - size / 2);
+    first..=first.wrapping_add(size - 1)
+}
+
+fn sequences(size: u32) -> impl Iterator<Item = DatagramSeq> {
+    let first = *window(size).start();
+    (0..size).map(move |n| first.wrapping_add(n))
+}
+
+fn filled(size: u32) -> (MapQueue, RecoveryQueue<Vec<u8>>) {
+    let mut map = MapQueue::default();
+    let mut ring = RecoveryQueue::new();
+    for seq in sequences(size) {
+        map.insert_id(seq, vec![0; 64]);
+        ring.insert_id(seq, vec![0; 64]);
+    }
+    (map, ring)
+}
+
+/// Looks up every datagram of the window, as a NACK of all of them does.
+fn lookup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("recovery_queue/lookup");
+    for size in WINDOWS {
+        let (map, mut ring) = filled(size);
+        group.bench_with_input(BenchmarkId::new("map", size), &size, |b, size| {
+            b.iter(|| {
+                for seq in sequences(*size) {
+                    black_box(map.get(seq));
+                }
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("ring", size), &size, |b, size| {
+            b.iter(|| {
+                for seq in sequences(*size) {
+                    black_box(ring.get(seq).ok());
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Acknowledges the window one datagram at a time, oldest first, as the ACKs of a steady
+/// stream do.
+fn recover(c: &mut Criterion) {
+    let mut group = c.benchmark_group("recovery_queue/recover");
+    for size in WINDOWS {
+        let (map, ring) = filled(size);
+        group.bench_with_input(BenchmarkId::new("map", size), &size, |b, size| {
+            b.iter_batched_ref(
+                || map.clone(),
+                |map| {
+                    for seq in sequences(*size) {
+                        for held in map.held_in(seq..=seq) {
+                            black_box(map.remove(held));
+                        }
+                    }
+                },
+                BatchSize::SmallInput,
+            )
+        });
+        group.bench_with_input(BenchmarkId::new("ring", size), &size, |b, size| {
+            b.iter_batched_ref(
+                || ring.clone(),
+                |ring| {
+                    for seq in sequences(*size) {
+                        for held in ring.held_in(seq..=seq) {
+                            black_box(ring.remove(held).ok());
+                        }
+                    }
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, lookup, recover);
+criterion_main!(benches);
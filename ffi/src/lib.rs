@@ -0,0 +1,5 @@
+//! The C interface of rak-rs, built into the shared and the static library C programs link
+//! against. The functions are declared in `include/rakrs.h`, at the root of the repository.
+//!
+//! Build the libraries with `cargo build --release -p rak-rs-ffi`.
+pub use rak_rs::ffi::*;
@@ -0,0 +1,22 @@
+//! The header the C interface is declared in.
+fn root(path: &str) -> String {
+    format!("{}/../{}", env!("CARGO_MANIFEST_DIR"), path)
+}
+
+#[test]
+fn test_header_is_generated() {
+    let config = cbindgen::Config::from_file(root("cbindgen.toml")).unwrap();
+    let mut generated = Vec::new();
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(root("src/ffi.rs"))
+        .generate()
+        .unwrap()
+        .write(&mut generated);
+
+    assert!(
+        String::from_utf8(generated).unwrap()
+            == std::fs::read_to_string(root("include/rakrs.h")).unwrap(),
+        "include/rakrs.h is out of date, regenerate it with the command in cbindgen.toml"
+    );
+}
@@ -0,0 +1,193 @@
+/* The C interface of rak-rs, built with the `ffi` feature. See src/ffi.rs. */
+
+#ifndef RAKRS_H
+#define RAKRS_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * The protocol version used when [`RakrsOptions::protocol_version`] is 0.
+ */
+#define RAKRS_DEFAULT_PROTOCOL_VERSION 11
+
+/**
+ * The MTU used when [`RakrsOptions::mtu`] is 0.
+ */
+#define RAKRS_DEFAULT_MTU 1400
+
+/**
+ * The connect timeout used when [`RakrsOptions::connect_timeout_ms`] is 0.
+ */
+#define RAKRS_DEFAULT_CONNECT_TIMEOUT_MS 10000
+
+/**
+ * What a [`RakrsEvent`] is about.
+ */
+typedef enum RakrsEventKind {
+  /**
+   * The server sent a message, it is in [`RakrsEvent::data`].
+   */
+  RAKRS_EVENT_KIND_MESSAGE = 0,
+  /**
+   * The connection is gone, why is in [`RakrsEvent::reason`]. It is the last event.
+   */
+  RAKRS_EVENT_KIND_DISCONNECTED = 1,
+} RakrsEventKind;
+
+/**
+ * The result of a call.
+ */
+typedef enum RakrsStatus {
+  /**
+   * The call succeeded.
+   */
+  RAKRS_STATUS_OK = 0,
+  /**
+   * A pointer was null, the address wasn't utf-8, or the reliability unknown.
+   */
+  RAKRS_STATUS_INVALID_ARGUMENT = 1,
+  /**
+   * The server could not be reached, or the handshake failed.
+   */
+  RAKRS_STATUS_CONNECT_FAILED = 2,
+  /**
+   * The connect didn't complete within [`RakrsOptions::connect_timeout_ms`].
+   */
+  RAKRS_STATUS_TIMED_OUT = 3,
+  /**
+   * The message could not be queued, for example because it is too large.
+   */
+  RAKRS_STATUS_SEND_FAILED = 4,
+  /**
+   * The client is not connected.
+   */
+  RAKRS_STATUS_NOT_CONNECTED = 5,
+  /**
+   * The library panicked, the client should be destroyed.
+   */
+  RAKRS_STATUS_PANIC = 6,
+} RakrsStatus;
+
+/**
+ * A client connected with [`rakrs_client_connect()`], opaque to the caller.
+ */
+typedef struct RakrsClient RakrsClient;
+
+/**
+ * How [`rakrs_client_connect()`] connects, a field left 0 takes its default.
+ */
+typedef struct RakrsOptions {
+  /**
+   * The RakNet protocol version, [`RAKRS_DEFAULT_PROTOCOL_VERSION`] by default.
+   */
+  uint8_t protocol_version;
+  /**
+   * The largest datagram to use, [`RAKRS_DEFAULT_MTU`] by default.
+   */
+  uint16_t mtu;
+  /**
+   * How long to wait for the connection, [`RAKRS_DEFAULT_CONNECT_TIMEOUT_MS`] by default.
+   */
+  uint32_t connect_timeout_ms;
+} RakrsOptions;
+
+/**
+ * Something that happened on the connection, handed out by [`rakrs_client_poll()`].
+ */
+typedef struct RakrsEvent {
+  enum RakrsEventKind kind;
+  /**
+   * The payload of a message, null otherwise. Freed with [`rakrs_buffer_free()`].
+   */
+  uint8_t *data;
+  /**
+   * The length of `data`.
+   */
+  uintptr_t len;
+  /**
+   * Why the connection is gone, the byte a disconnect carries on the wire,
+   * see [`DisconnectReason`]. 0 for a message.
+   */
+  uint8_t reason;
+} RakrsEvent;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Connects to `addr`, a `host:port` string, and writes the client to `out`.
+ *
+ * `opts` may be null for the defaults. `out` is only written on [`RakrsStatus::Ok`].
+ *
+ * # Safety
+ *
+ * `addr` must be a nul terminated string, `opts` null or a valid [`RakrsOptions`] and `out`
+ * valid for a write.
+ */
+enum RakrsStatus rakrs_client_connect(const char *addr,
+                                      const struct RakrsOptions *opts,
+                                      struct RakrsClient **out);
+
+/**
+ * Queues `len` bytes at `data` with `reliability`, the value of a [`Reliability`] from
+ * 0 (unreliable) to 4 (reliable sequenced). Ordered messages go on channel 0.
+ *
+ * # Safety
+ *
+ * `client` must come from [`rakrs_client_connect()`], and `data` be valid for `len` bytes.
+ */
+enum RakrsStatus rakrs_client_send(struct RakrsClient *client,
+                                   const uint8_t *data,
+                                   uintptr_t len,
+                                   uint8_t reliability);
+
+/**
+ * Writes the next event to `out_event`, returns `false` when nothing happened since the
+ * last call. Never blocks.
+ *
+ * Messages are handed out before the [`RakrsEventKind::Disconnected`] that ends them, after
+ * it this only returns `false`.
+ *
+ * # Safety
+ *
+ * `client` must come from [`rakrs_client_connect()`], and `out_event` be valid for a write.
+ */
+bool rakrs_client_poll(struct RakrsClient *client, struct RakrsEvent *out_event);
+
+/**
+ * Tells the server the client is leaving, and closes the connection. The client still has
+ * to be destroyed.
+ *
+ * # Safety
+ *
+ * `client` must come from [`rakrs_client_connect()`].
+ */
+enum RakrsStatus rakrs_client_disconnect(struct RakrsClient *client);
+
+/**
+ * Frees a client, closing its connection if it is still open. Null is ignored.
+ *
+ * # Safety
+ *
+ * `client` must come from [`rakrs_client_connect()`], and not be used afterwards.
+ */
+void rakrs_client_destroy(struct RakrsClient *client);
+
+/**
+ * Frees the payload of a [`RakrsEvent`]. Null is ignored.
+ *
+ * # Safety
+ *
+ * `data` and `len` must be those of an event, and the payload not be used afterwards.
+ */
+void rakrs_buffer_free(uint8_t *data, uintptr_t len);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* RAKRS_H */
@@ -0,0 +1,142 @@
+//! Hands the messages of the server to the application, without holding up the client.
+use std::collections::VecDeque;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "async_std")]
+use async_std::channel::{Sender, TrySendError};
+#[cfg(feature = "async_tokio")]
+use tokio::sync::mpsc::{error::TrySendError, Sender};
+
+use crate::connection::controller::consumer::{ConsumerGuard, DEFAULT_CONSUMER_TIMEOUT};
+use crate::connection::controller::memory::{self, MemAccount, MemBudget};
+use crate::connection::stats::SharedStats;
+use crate::rakrs_debug;
+use crate::util;
+
+/// Hands the messages of the server to the channel [`Client::recv()`] reads from.
+///
+/// The client never waits for room in that channel, so it keeps reading its socket, and the
+/// connection is acknowledged and kept alive while the application is busy. Messages that find
+/// the channel full wait here, charged to the memory ledger of the connection, and move into
+/// the channel as the application makes room. Once the application made no room for
+/// [`ConnOptions::consumer_timeout`], or the ledger is full, messages are dropped until it
+/// receives again, like a [`Connection`] drops them.
+///
+/// [`Client::recv()`]: crate::client::Client::recv
+/// [`ConnOptions::consumer_timeout`]: crate::connection::options::ConnOptions::consumer_timeout
+/// [`Connection`]: crate::connection::Connection
+#[derive(Debug, Clone)]
+pub(crate) struct Delivery {
+    sender: Sender<Vec<u8>>,
+    backlog: Arc<Mutex<Backlog>>,
+}
+
+#[derive(Debug)]
+struct Backlog {
+    /// The messages that found the channel full, in the order they go into it.
+    waiting: VecDeque<Vec<u8>>,
+    /// When the application last made room, while messages are waiting.
+    since: Option<Instant>,
+    consumer: ConsumerGuard,
+    memory: MemAccount,
+    /// Where the messages dropped for a gone application are counted.
+    shared_stats: Arc<SharedStats>,
+}
+
+impl Delivery {
+    pub fn new(sender: Sender<Vec<u8>>, shared_stats: Arc<SharedStats>) -> Self {
+        Self {
+            sender,
+            backlog: Arc::new(Mutex::new(Backlog {
+                waiting: VecDeque::new(),
+                since: None,
+                consumer: ConsumerGuard::new(Some(DEFAULT_CONSUMER_TIMEOUT)),
+                memory: MemAccount::default(),
+                shared_stats,
+            })),
+        }
+    }
+
+    /// Sets up the delivery for a new connection, the messages still waiting are charged to
+    /// its `budget` from now on.
+    pub fn restart(&self, consumer_timeout: Option<Duration>, budget: MemBudget) {
+        let mut backlog = self.backlog.lock().unwrap();
+        backlog.consumer = ConsumerGuard::new(consumer_timeout);
+        backlog.memory.move_to(budget);
+    }
+
+    /// Hands `message` to the application, or keeps it until there is room for it.
+    pub fn deliver(&self, message: Vec<u8>) {
+        let mut backlog = self.backlog.lock().unwrap();
+        if !backlog.waiting.is_empty() {
+            backlog.wait(message);
+            return;
+        }
+
+        match self.sender.try_send(message) {
+            Ok(()) => backlog.delivered(),
+            Err(TrySendError::Full(message)) => {
+                backlog.since = Some(util::now());
+                backlog.wait(message);
+            }
+            Err(TrySendError::Closed(_)) => {}
+        }
+    }
+
+    /// Moves the messages that are waiting into the room the application made.
+    pub fn refill(&self) {
+        let mut backlog = self.backlog.lock().unwrap();
+        while let Some(message) = backlog.waiting.pop_front() {
+            let cost = memory::cost(message.len());
+            match self.sender.try_send(message) {
+                Ok(()) => {
+                    backlog.memory.credit(cost);
+                    backlog.since = Some(util::now());
+                    backlog.delivered();
+                }
+                Err(TrySendError::Full(message)) => {
+                    backlog.waiting.push_front(message);
+                    break;
+                }
+                Err(TrySendError::Closed(_)) => backlog.memory.credit(cost),
+            }
+        }
+
+        if backlog.waiting.is_empty() {
+            backlog.since = None;
+        }
+    }
+}
+
+impl Backlog {
+    fn wait(&mut self, message: Vec<u8>) {
+        let cost = memory::cost(message.len());
+        let gone = match (self.consumer.wait(), self.since) {
+            (Some(wait), Some(since)) => util::now().saturating_duration_since(since) >= wait,
+            _ => false,
+        };
+
+        if gone || !self.memory.budget().fits(cost) {
+            if self.consumer.dropped() {
+                rakrs_debug!(
+                    "[CLIENT] The application stopped receiving, dropping messages until it is back!"
+                );
+            }
+            self.shared_stats
+                .events_dropped_no_consumer
+                .store(self.consumer.dropped_count(), Ordering::Relaxed);
+            return;
+        }
+
+        self.memory.debit(cost);
+        self.waiting.push_back(message);
+    }
+
+    fn delivered(&mut self) {
+        if self.consumer.delivered() {
+            rakrs_debug!("[CLIENT] The application receives again, delivering messages.");
+        }
+    }
+}
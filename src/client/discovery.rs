@@ -1,6 +1,7 @@
+use std::net::SocketAddr;
 use std::sync::Arc;
 use std::sync::Mutex;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 #[cfg(feature = "async_std")]
 use async_std::{
@@ -10,8 +11,7 @@ use async_std::{
     task::{self, Context, Poll, Waker},
 };
 
-use binary_util::interfaces::Reader;
-use binary_util::io::ByteReader;
+use binary_util::interfaces::Writer;
 
 #[cfg(feature = "async_tokio")]
 use std::future::Future;
@@ -24,10 +24,14 @@ use tokio::{
     time::timeout,
 };
 
+use crate::connection::controller::jitter::{RngProvider, HANDSHAKE_RESEND_JITTER};
 use crate::match_ids;
+use crate::protocol::packet::decode_packet;
 use crate::protocol::packet::offline::IncompatibleProtocolVersion;
 use crate::protocol::packet::offline::OpenConnectReply;
 use crate::protocol::packet::offline::OpenConnectRequest;
+use crate::protocol::packet::{PacketId, RakPacket};
+use crate::protocol::MTU_LADDER;
 use crate::rakrs_debug;
 
 use super::util::send_packet;
@@ -62,6 +66,10 @@ pub enum DiscoveryStatus {
     /// We failed to discover the MTU size.
     /// This is probably cause the server is offline.
     Failed,
+    /// The server replied with something that is not an open connection reply.
+    Rejected,
+    /// The server uses a different protocol version.
+    IncompatibleVersion,
     /// We're still trying to find the MTU size.
     Undiscovered,
 }
@@ -71,11 +79,21 @@ pub struct MtuDiscoveryMeta {
     pub id: i64,
     pub version: u8,
     pub mtu: u16,
+    /// Spreads the time every open connection request waits for its reply.
+    pub rng: RngProvider,
 }
 
+/// How long an open connection request waits for its reply before the next one is sent,
+/// spread by [`HANDSHAKE_RESEND_JITTER`].
+pub const OPEN_REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
 struct DiscoveryState {
     status: DiscoveryStatus,
     waker: Option<Waker>,
+    /// The amount of open connection requests sent.
+    attempts: u8,
+    /// The MTU of the last request sent.
+    mtu: u16,
 }
 
 pub struct MtuDiscovery {
@@ -87,19 +105,25 @@ impl MtuDiscovery {
         let state = Arc::new(Mutex::new(DiscoveryState {
             status: DiscoveryStatus::Initiated,
             waker: None,
+            attempts: 0,
+            mtu: discovery_info.mtu,
         }));
 
         let shared_state = state.clone();
+        let mut rng = discovery_info.rng.clone();
 
         task::spawn(async move {
-            // try to use the mtu provided by the user
-            let valid_mtus: Vec<u16> = vec![discovery_info.mtu, 1506, 1492, 1400, 1200, 576];
-            for mtu in valid_mtus.iter() {
+            for mtu in mtu_ladder(discovery_info.mtu).iter() {
                 // send a connection request
                 let request = OpenConnectRequest {
                     protocol: discovery_info.version,
                     mtu_size: *mtu,
                 };
+                {
+                    let mut state = shared_state.lock().unwrap();
+                    state.attempts += 1;
+                    state.mtu = *mtu;
+                }
 
                 if !send_packet(&socket, request.into()).await {
                     rakrs_debug!(
@@ -111,12 +135,12 @@ impl MtuDiscovery {
                     continue;
                 };
 
+                let wait = rng.jitter(OPEN_REPLY_TIMEOUT, HANDSHAKE_RESEND_JITTER);
                 let reply = match_ids!(
+                    wait wait,
                     socket.clone(),
-                    // Open connect Reply
-                    0x06,
-                    // Incompatible protocol version
-                    0x19
+                    OpenConnectReply::ID,
+                    IncompatibleProtocolVersion::ID
                 );
 
                 if reply.is_none() {
@@ -125,28 +149,8 @@ impl MtuDiscovery {
                     continue;
                 }
 
-                if let Ok(_) = IncompatibleProtocolVersion::read(&mut ByteReader::from(
-                    &reply.clone().unwrap()[1..],
-                )) {
-                    update_state!(shared_state, DiscoveryStatus::Failed);
-                    break;
-                }
-
-                let open_reply =
-                    OpenConnectReply::read(&mut ByteReader::from(&reply.unwrap()[1..]));
-
-                if open_reply.is_err() {
-                    update_state!(shared_state, DiscoveryStatus::Failed);
-                    return;
-                }
-
-                if let Ok(response) = open_reply {
-                    rakrs_debug!(true, "[CLIENT] Received OpenConnectReply from server!");
-                    update_state!(shared_state, DiscoveryStatus::Discovered(response.mtu_size));
-                    return;
-                } else {
-                    update_state!(shared_state, DiscoveryStatus::Undiscovered);
-                }
+                update_state!(shared_state, open_reply(&reply.unwrap()));
+                return;
             }
 
             update_state!(shared_state, DiscoveryStatus::Failed);
@@ -154,6 +158,72 @@ impl MtuDiscovery {
 
         Self { state }
     }
+
+    /// The amount of open connection requests sent so far.
+    pub fn attempts(&self) -> u8 {
+        self.state.lock().unwrap().attempts
+    }
+
+    /// The MTU of the last open connection request sent.
+    pub fn mtu(&self) -> u16 {
+        self.state.lock().unwrap().mtu
+    }
+}
+
+/// The mtus an open connection request is sent at, the one provided by the user first.
+pub(crate) fn mtu_ladder(mtu: u16) -> Vec<u16> {
+    let mut valid_mtus: Vec<u16> = vec![mtu];
+    valid_mtus.extend(MTU_LADDER.iter().rev());
+    valid_mtus
+}
+
+/// What the reply to an open connection request tells of the discovery.
+pub(crate) fn open_reply(reply: &[u8]) -> DiscoveryStatus {
+    if decode_packet::<IncompatibleProtocolVersion>(reply).is_ok() {
+        return DiscoveryStatus::IncompatibleVersion;
+    }
+
+    match decode_packet::<OpenConnectReply>(reply) {
+        Ok(response) => {
+            rakrs_debug!(true, "[CLIENT] Received OpenConnectReply from server!");
+            DiscoveryStatus::Discovered(response.mtu_size)
+        }
+        Err(_) => DiscoveryStatus::Rejected,
+    }
+}
+
+/// Sends a single open connection request from a socket that is not connected, and returns the
+/// address the reply came from. Some deployments answer from another port than the one asked,
+/// which a socket connected to that port never gets to see.
+pub async fn reply_source(meta: &MtuDiscoveryMeta, address: SocketAddr) -> Option<SocketAddr> {
+    let local = if address.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    };
+    let socket = UdpSocket::bind(local).await.ok()?;
+    let request = RakPacket::from(OpenConnectRequest {
+        protocol: meta.version,
+        mtu_size: meta.mtu,
+    });
+    socket
+        .send_to(request.write_to_bytes().ok()?.as_slice(), address)
+        .await
+        .ok()?;
+
+    let mut recv_buf: [u8; 2048] = [0; 2048];
+    let deadline = Instant::now() + Duration::from_secs(2);
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        let (len, source) = timeout(left, socket.recv_from(&mut recv_buf))
+            .await
+            .ok()?
+            .ok()?;
+        // anyone can send to a socket that is not connected, only the host we asked counts.
+        if source.ip() == address.ip() && len > 0 && matches!(recv_buf[0], 0x06 | 0x19) {
+            return Some(source);
+        }
+    }
 }
 
 impl Future for MtuDiscovery {
@@ -162,7 +232,10 @@ impl Future for MtuDiscovery {
     fn poll(self: std::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let mut state = self.state.lock().unwrap();
         match state.status {
-            DiscoveryStatus::Failed | DiscoveryStatus::Discovered(_) => Poll::Ready(state.status),
+            DiscoveryStatus::Failed
+            | DiscoveryStatus::Rejected
+            | DiscoveryStatus::IncompatibleVersion
+            | DiscoveryStatus::Discovered(_) => Poll::Ready(state.status),
             _ => {
                 state.waker = Some(cx.waker().clone());
                 Poll::Pending
@@ -0,0 +1,460 @@
+//! The drivers of a connected [`Client`], its tick and its packet handler.
+//!
+//! Like the ones of a [`Connection`], each one does one turn of its task at a time, the tasks
+//! of the client only wait for their next turn and run it, and the
+//! [`simulation`](crate::simulation) runs the turns itself.
+//!
+//! [`Client`]: crate::client::Client
+//! [`Connection`]: crate::connection::Connection
+use std::{
+    net::SocketAddr,
+    sync::{atomic::AtomicU64, Arc},
+    time::{Duration, Instant},
+};
+
+use binary_util::interfaces::{Reader, Writer};
+use binary_util::io::ByteReader;
+
+#[cfg(feature = "async_std")]
+use async_std::{
+    channel::Sender,
+    sync::{Mutex, MutexGuard, RwLock},
+};
+#[cfg(feature = "async_tokio")]
+use tokio::sync::{mpsc::Sender, Mutex, MutexGuard, RwLock};
+
+use crate::{
+    connection::{
+        controller::{
+            clock::{rak_time_now, ClockEstimator},
+            flood::{FloodVerdict, UnknownFloodGuard},
+            one_way::OneWayDetector,
+        },
+        event::{CloseSignal, DisconnectReason, RakEvent, ViolationKind},
+        queue::{EventSender, RecvQueue, SendQueue},
+        state::ConnectionState,
+        stats::SharedStats,
+        transform::SharedTransform,
+    },
+    notify::Notify,
+    protocol::{
+        ack::{Ack, Ackable, ACK, NACK},
+        frame::FramePacket,
+        packet::{
+            online::{ConnectedPing, ConnectedPong, OnlinePacket},
+            RakPacket,
+        },
+        reliability::Reliability,
+    },
+    rakrs_debug,
+    util::{self, current_epoch},
+};
+
+use super::{delivery::Delivery, KEEPALIVE_INTERVAL};
+
+/// How long the tick of a client waits between two runs.
+pub(crate) const TICK_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The tick of a client: it times the connection out, keeps it alive, retransmits what was
+/// lost and acknowledges what was received.
+pub(crate) struct Ticker {
+    pub(crate) close_signal: CloseSignal,
+    pub(crate) event_sender: EventSender,
+    pub(crate) one_way: OneWayDetector,
+    pub(crate) recv_queue: Arc<Mutex<RecvQueue>>,
+    pub(crate) send_queue: Arc<RwLock<SendQueue>>,
+    pub(crate) state: Arc<Mutex<ConnectionState>>,
+    pub(crate) last_recv: Arc<AtomicU64>,
+    pub(crate) clock: Arc<std::sync::Mutex<ClockEstimator>>,
+    pub(crate) closer: Arc<Notify>,
+    /// The address of the server, the handshake may have followed it to another port.
+    pub(crate) peer: SocketAddr,
+    /// Tells the packet handler the tick gave up on a stalled order channel.
+    pub(crate) release_wake: Sender<()>,
+    pub(crate) next_tick: Instant,
+}
+
+impl Ticker {
+    /// When the next tick is due.
+    pub(crate) fn next_tick(&self) -> Instant {
+        self.next_tick
+    }
+
+    /// Runs the tick, this is false once the connection closed and the tick stops for good.
+    pub(crate) async fn tick(&mut self) -> bool {
+        self.next_tick = util::now() + TICK_INTERVAL;
+        rakrs_debug!(true, "[CLIENT] Running connect tick task");
+        let recv = self.last_recv.load(std::sync::atomic::Ordering::Relaxed);
+        let mut state = self.state.lock().await;
+
+        if *state == ConnectionState::Disconnected {
+            rakrs_debug!(
+                true,
+                "[CLIENT] Client is disconnected. Closing connect tick task"
+            );
+            self.close_signal.close(DisconnectReason::Closed);
+            self.closer.notify().await;
+            return false;
+        }
+
+        if *state == ConnectionState::Connecting {
+            rakrs_debug!(
+                true,
+                "[CLIENT] Client is not fully connected to the server yet."
+            );
+            return true;
+        }
+
+        if (recv + 20) <= current_epoch() {
+            *state = ConnectionState::Disconnected;
+            rakrs_debug!(true, "[CLIENT] Client timed out. Closing connection...");
+            self.close_signal.close(DisconnectReason::TimedOut {
+                one_way: self.one_way.suspected(),
+            });
+            self.closer.notify().await;
+            return false;
+        }
+
+        let timing_out = recv + 10 <= current_epoch() && state.is_reliable();
+        if timing_out {
+            *state = ConnectionState::TimingOut;
+        }
+        drop(state);
+
+        // taken out first, the packet task keeps reassembling while this sends.
+        let (acks, nacks) = {
+            let mut recv_q = self.recv_queue.lock().await;
+            // a stalled channel is given up on even while nothing arrives.
+            if recv_q.poll_stalls(util::now()) {
+                let _ = self.release_wake.try_send(());
+            }
+            (recv_q.ack_flush(), recv_q.nack_queue().clone())
+        };
+        let mut send_q = self.send_queue.write().await;
+
+        if timing_out {
+            rakrs_debug!(true, "[CLIENT] Connection is timing out, sending a ping!",);
+            let ping = ConnectedPing {
+                time: rak_time_now(),
+            };
+            if let Ok(_) = send_q
+                .send_packet(ping.into(), Reliability::Reliable, true)
+                .await
+            {}
+        }
+
+        let now = util::now();
+        // the receive time is in whole seconds, so this errs on the side of
+        // the peer not having been heard.
+        let heard_for = Duration::from_secs(current_epoch().saturating_sub(recv) + 1);
+        if self.one_way.check(send_q.unacked_for(now), heard_for) {
+            rakrs_debug!(
+                true,
+                "[CLIENT] Nothing we send seems to arrive, while the server still reaches us!"
+            );
+            let event = RakEvent::SuspectedOneWayLoss { addr: self.peer };
+            if self.event_sender.try_send(event).is_err() {
+                rakrs_debug!(true, "[CLIENT] Event channel is full, dropping event!");
+            }
+        }
+        let keepalive = send_q.keepalive_due(KEEPALIVE_INTERVAL, now);
+        let clock_ping = self.clock.lock().map_or(false, |clock| clock.ping_due(now));
+        if keepalive {
+            let ping = OnlinePacket::ConnectedPing(ConnectedPing {
+                time: rak_time_now(),
+            });
+            if let Ok(ping) = ping.write_to_bytes() {
+                send_q.keepalive(ping.as_slice(), KEEPALIVE_INTERVAL).await;
+            }
+        } else if clock_ping {
+            // a busy connection never needs a keepalive, so the clock
+            // ping rides along with what is sent anyway.
+            send_q.ride_along_ping();
+        }
+        if keepalive || clock_ping {
+            if let Ok(mut clock) = self.clock.lock() {
+                clock.ping_sent(now);
+            }
+        }
+
+        send_q.update().await;
+
+        // Flush the queue of acks and nacks, and respond to them
+        send_q.send_ack_ranges(acks).await;
+
+        // flush nacks from recv queue
+        let nack = Ack::from_ranges(&nacks, true);
+        if nack.records.len() > 0 {
+            if let Ok(p) = nack.write_to_bytes() {
+                send_q.send_stream(p.as_slice()).await;
+            }
+        }
+        // the tick is over, the counters agree with each other.
+        send_q.publish_stats();
+        true
+    }
+}
+
+/// The packet handler of a client: it takes the datagrams of the server apart, and hands what
+/// they carry to the application.
+pub(crate) struct PacketHandler {
+    pub(crate) recv_queue: Arc<Mutex<RecvQueue>>,
+    pub(crate) send_queue: Arc<RwLock<SendQueue>>,
+    pub(crate) delivery: Delivery,
+    pub(crate) event_sender: EventSender,
+    pub(crate) close_signal: CloseSignal,
+    pub(crate) state: Arc<Mutex<ConnectionState>>,
+    pub(crate) recv_time: Arc<AtomicU64>,
+    pub(crate) shared_stats: Arc<SharedStats>,
+    pub(crate) transform: SharedTransform,
+    pub(crate) clock: Arc<std::sync::Mutex<ClockEstimator>>,
+    pub(crate) ack_immediately_above: Option<usize>,
+    pub(crate) flood_guard: UnknownFloodGuard,
+    /// Held so the release channel never closes, which would wake the task for good.
+    pub(crate) _release_wake: Sender<()>,
+}
+
+impl PacketHandler {
+    /// Handles a datagram of the server, this is false once the connection closed and the
+    /// packet task stops for good.
+    pub(crate) async fn datagram(&mut self, payload: Vec<u8>) -> bool {
+        // a datagram that doesn't open isn't from the server.
+        let payload = match self.transform.open(payload) {
+            Ok(payload) => payload,
+            Err(_) => {
+                self.shared_stats
+                    .rejected_datagrams
+                    .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                return true;
+            }
+        };
+
+        self.recv_time
+            .store(current_epoch(), std::sync::atomic::Ordering::Relaxed);
+
+        rakrs_debug!(true, "[CLIENT] (recv_task) Recieved packet!");
+
+        let mut client_state = self.state.lock().await;
+
+        if *client_state == ConnectionState::TimingOut {
+            rakrs_debug!(true, "[CLIENT] (recv_task) Client is no longer timing out!");
+            *client_state = ConnectionState::Connected;
+        }
+
+        if *client_state == ConnectionState::Disconnecting {
+            rakrs_debug!(true, "[CLIENT] (recv_task) Client is disconnecting!");
+            return false;
+        }
+
+        // drop here so the lock isn't held for too long
+        drop(client_state);
+
+        let mut buffer = ByteReader::from(payload);
+
+        match buffer.as_slice()[0] {
+            0x80..=0x8f => {
+                let recv_queue = self.recv_queue.clone();
+                let mut recv_q = recv_queue.lock().await;
+                // a damaged datagram is dropped before its sequence is recorded.
+                let datagram = recv_q
+                    .verify(buffer.as_slice())
+                    .map(FramePacket::read_from_slice);
+
+                if let Some(Ok(frame_packet)) = datagram {
+                    if let Err(_) = recv_q.insert(frame_packet) {
+                        rakrs_debug!(
+                            true,
+                            "[CLIENT] Failed to push frame packet into send queue."
+                        );
+                    }
+
+                    if !self.release_ready(recv_q).await {
+                        return false;
+                    }
+                }
+            }
+            NACK => {
+                if let Ok(nack) = Ack::read(&mut buffer) {
+                    let mut send_q = self.send_queue.write().await;
+                    let to_resend = send_q.nack(nack);
+                    send_q.resend(to_resend).await;
+                }
+            }
+            ACK => {
+                if let Ok(ack) = Ack::read(&mut buffer) {
+                    let mut send_q = self.send_queue.write().await;
+                    send_q.ack(ack);
+                }
+            }
+            _ => {
+                // we don't know what this is, so we're going to send it to the user, maybe
+                // this is a custom packet
+                if self.deliver_unknown(buffer.as_slice()) {
+                    self.delivery.deliver(buffer.as_slice().to_vec());
+                }
+            }
+        }
+
+        self.shared_stats.unknown_suppressed.store(
+            self.flood_guard.suppressed(),
+            std::sync::atomic::Ordering::Relaxed,
+        );
+        true
+    }
+
+    /// Hands out what the receive queue released after the tick gave up on a stalled channel,
+    /// this is false once the connection closed.
+    pub(crate) async fn release(&mut self) -> bool {
+        let recv_queue = self.recv_queue.clone();
+        let recv_q = recv_queue.lock().await;
+        self.release_ready(recv_q).await
+    }
+
+    /// Checks whether a packet with an unknown id should be handed to the user.
+    fn deliver_unknown(&mut self, buf: &[u8]) -> bool {
+        match buf
+            .first()
+            .map(|id| self.flood_guard.unknown(*id, util::now()))
+        {
+            Some(FloodVerdict::Suppress) => false,
+            Some(FloodVerdict::Flooded) => {
+                rakrs_debug!(
+                    true,
+                    "[CLIENT] Server is flooding unknown packets, dropping them!"
+                );
+                let event = RakEvent::ProtocolViolation {
+                    kind: ViolationKind::UnknownFlood,
+                };
+                if let Err(_) = self.event_sender.try_send(event) {
+                    rakrs_debug!(true, "[CLIENT] Event channel is full, dropping event!");
+                }
+                false
+            }
+            _ => true,
+        }
+    }
+
+    /// Hands out whatever the receive queue has ready, this is false once the connection
+    /// closed.
+    async fn release_ready(&mut self, mut recv_q: MutexGuard<'_, RecvQueue>) -> bool {
+        // give up on any order channel that has been stalled for too long.
+        recv_q.poll_stalls(util::now());
+
+        for event in recv_q.flush_events() {
+            if let Err(_) = self.event_sender.try_send(event) {
+                rakrs_debug!(true, "[CLIENT] Event channel is full, dropping event!");
+            }
+        }
+
+        let buffers = recv_q.flush();
+        let abusive = recv_q.exceeds_budget();
+        // the server is waiting on a lot of acknowledgements, don't wait for the tick.
+        let acks = match self.ack_immediately_above {
+            Some(limit) if recv_q.ack_debt() > limit => Some(recv_q.ack_flush()),
+            _ => None,
+        };
+        drop(recv_q);
+
+        if let Some(acks) = acks {
+            self.send_queue.write().await.send_ack_ranges(acks).await;
+        }
+
+        if abusive {
+            rakrs_debug!(
+                true,
+                "[CLIENT] Server made the client hold more than its memory budget, disconnecting!"
+            );
+            let reason = DisconnectReason::ResourceAbuse;
+            if self
+                .send_queue
+                .write()
+                .await
+                .insert(&reason.to_packet(), Reliability::ReliableOrd, true, Some(0))
+                .await
+                .is_err()
+            {
+                rakrs_debug!(
+                    true,
+                    "[CLIENT] Failed to send disconnect packet when closing!"
+                );
+            }
+            if self
+                .event_sender
+                .try_send(RakEvent::Disconnected { reason })
+                .is_err()
+            {
+                rakrs_debug!(
+                    true,
+                    "[CLIENT] The events are not read anymore, dropping the disconnect!"
+                );
+            }
+            *self.state.lock().await = ConnectionState::Disconnected;
+            self.close_signal.close(reason);
+            return false;
+        }
+
+        'buf_loop: for pk_buf_raw in buffers {
+            let mut pk_buf = ByteReader::from(&pk_buf_raw[..]);
+            if let Ok(rak_packet) = RakPacket::read(&mut pk_buf) {
+                match rak_packet {
+                    RakPacket::Online(pk) => {
+                        self.flood_guard.known(util::now());
+                        match pk {
+                            OnlinePacket::ConnectedPing(pk) => {
+                                let response = ConnectedPong {
+                                    ping_time: pk.time,
+                                    pong_time: rak_time_now(),
+                                };
+                                let mut q = self.send_queue.write().await;
+                                if let Err(_) = q
+                                    .send_packet(response.into(), Reliability::Unreliable, true)
+                                    .await
+                                {
+                                    rakrs_debug!(true, "[CLIENT] Failed to send pong packet!");
+                                }
+                                continue 'buf_loop;
+                            }
+                            OnlinePacket::ConnectedPong(pk) => {
+                                rakrs_debug!(true, "[CLIENT] Recieved pong packet!");
+                                if let Ok(mut clock) = self.clock.lock() {
+                                    clock.sample(pk.ping_time, pk.pong_time, rak_time_now());
+                                }
+                            }
+                            OnlinePacket::Disconnect(_) => {
+                                rakrs_debug!(true, "[CLIENT] Recieved disconnect packet!");
+                                let reason = DisconnectReason::from_packet(&pk_buf_raw);
+                                if let Some(reason) = reason {
+                                    if self
+                                        .event_sender
+                                        .try_send(RakEvent::Disconnected { reason })
+                                        .is_err()
+                                    {
+                                        rakrs_debug!(true, "[CLIENT] The events are not read anymore, dropping the disconnect!");
+                                    }
+                                }
+                                // the server waits for its disconnect to be acknowledged.
+                                let acks = self.recv_queue.lock().await.ack_flush();
+                                self.send_queue.write().await.send_ack_ranges(acks).await;
+                                self.close_signal
+                                    .close(reason.unwrap_or(DisconnectReason::Closed));
+                                return false;
+                            }
+                            _ => {
+                                rakrs_debug!(true, "[CLIENT] Processing fault packet... {:#?}", pk);
+
+                                self.delivery.deliver(pk_buf_raw);
+                            }
+                        }
+                    }
+                    RakPacket::Offline(_) => {
+                        rakrs_debug!("[CLIENT] Recieved offline packet after handshake! In future versions this will kill the client.");
+                    }
+                }
+            } else if self.deliver_unknown(&pk_buf_raw) {
+                // we send this packet
+                self.delivery.deliver(pk_buf_raw);
+            }
+        }
+        true
+    }
+}
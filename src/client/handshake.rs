@@ -2,16 +2,24 @@ use crate::client::discovery;
 use crate::client::discovery::DiscoveryStatus;
 use crate::client::discovery::MtuDiscovery;
 use crate::client::util::send_packet;
+use crate::connection::controller::clock::rak_time_now;
+use crate::connection::controller::jitter::HANDSHAKE_RESEND_JITTER;
 use crate::connection::queue::send::SendQueue;
 use crate::connection::queue::RecvQueue;
+use crate::error::client::HandshakeError;
 use crate::protocol::frame::FramePacket;
+use crate::protocol::packet::decode_packet;
 use crate::protocol::packet::offline::{SessionInfoReply, SessionInfoRequest};
 use crate::protocol::packet::online::ConnectedPong;
-use crate::protocol::packet::online::{ConnectionRequest, NewConnection, OnlinePacket};
+use crate::protocol::packet::online::{
+    Capabilities, ConnectionRequest, NewConnection, OnlinePacket,
+};
 use crate::protocol::reliability::Reliability;
 use crate::protocol::Magic;
 use crate::rakrs_debug;
-use crate::server::current_epoch;
+use crate::util::current_epoch;
+#[cfg(feature = "async_std")]
+use async_std::sync::{Mutex as AsyncMutex, RwLock};
 #[cfg(feature = "async_std")]
 use async_std::{
     future::timeout,
@@ -23,11 +31,14 @@ use binary_util::interfaces::Reader;
 use binary_util::io::ByteReader;
 #[cfg(feature = "async_tokio")]
 use std::future::Future;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use std::sync::Mutex;
 #[cfg(feature = "async_tokio")]
 use std::task::{Context, Poll, Waker};
-use std::time::Duration;
+use std::time::{Duration, Instant};
+#[cfg(feature = "async_tokio")]
+use tokio::sync::{Mutex as AsyncMutex, RwLock};
 #[cfg(feature = "async_tokio")]
 use tokio::{
     net::UdpSocket,
@@ -35,9 +46,13 @@ use tokio::{
     time::timeout,
 };
 
+/// How long a connection request waits for the accept before the next one is sent,
+/// spread by [`HANDSHAKE_RESEND_JITTER`].
+pub const CONNECT_RESEND_INTERVAL: Duration = Duration::from_secs(2);
+
 #[macro_export]
 macro_rules! match_ids {
-    ($socket: expr, $($ids: expr),*) => {
+    (wait $wait: expr, $socket: expr, $($ids: expr),*) => {
         {
             let mut recv_buf: [u8; 2048] = [0; 2048];
             let mut tries: u8 = 0;
@@ -51,7 +66,7 @@ macro_rules! match_ids {
 
                 let len: usize;
                 let send_result = timeout(
-                    Duration::from_secs(2),
+                    $wait,
                     $socket.recv(&mut recv_buf)
                 ).await;
 
@@ -70,7 +85,7 @@ macro_rules! match_ids {
 
                 // rakrs_debug!(true, "[CLIENT] Received packet from server: {:x?}", &recv_buf[..len]);
 
-                if ids.contains(&recv_buf[0]) {
+                if len > 0 && ids.contains(&recv_buf[0]) {
                     pk = Some(recv_buf[..len].to_vec());
                     break 'try_conn;
                 }
@@ -79,6 +94,9 @@ macro_rules! match_ids {
             pk
         }
     };
+    ($socket: expr, $($ids: expr),*) => {
+        $crate::match_ids!(wait Duration::from_secs(2), $socket, $($ids),*)
+    };
 }
 
 macro_rules! expect_reply {
@@ -113,8 +131,8 @@ macro_rules! expect_reply {
 
             // rakrs_debug!(true, "[CLIENT] Received packet from server: {:x?}", &recv_buf[..len]);
 
-            let mut reader = ByteReader::from(&recv_buf[1..len]);
-            if let Ok(packet) = <$reply>::read(&mut reader) {
+            // anything else the server still had in flight starts with another id.
+            if let Ok(packet) = decode_packet::<$reply>(&recv_buf[..len]) {
                 pk = Some(packet);
                 break;
             } else {
@@ -146,6 +164,62 @@ macro_rules! update_state {
     }};
 }
 
+/// Marks the handshake as failed with the given error, and stops it.
+macro_rules! fail {
+    ($shared_state: expr, $error: expr) => {{
+        let mut state = $shared_state.lock().unwrap();
+        state.status = match $error {
+            HandshakeError::IncompatibleVersion => HandshakeStatus::IncompatibleVersion,
+            _ => HandshakeStatus::Failed,
+        };
+        state.error = Some($error);
+        state.done = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+        return;
+    }};
+}
+
+/// A stage of the handshake, each one has to be answered by the server before the next one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Stage {
+    /// `OpenConnectRequest`, sent padded at every MTU of the ladder until one gets through.
+    Open1 = 0,
+    /// `SessionInfoRequest`, where the server opens a session at the discovered MTU.
+    Open2 = 1,
+    /// `ConnectionRequest`, the first reliable frame, answered with `ConnectionAccept`.
+    Connect = 2,
+}
+
+impl Stage {
+    /// The amount of stages.
+    pub const COUNT: usize = 3;
+}
+
+/// How many attempts and how much time every stage of the handshake took,
+/// indexed by [`Stage`]. Stages that were never reached are zero.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct HandshakeTimings {
+    pub attempts: [u8; Stage::COUNT],
+    pub elapsed: [Duration; Stage::COUNT],
+}
+
+impl HandshakeTimings {
+    pub fn attempts(&self, stage: Stage) -> u8 {
+        self.attempts[stage as usize]
+    }
+
+    pub fn elapsed(&self, stage: Stage) -> Duration {
+        self.elapsed[stage as usize]
+    }
+
+    fn record(&mut self, stage: Stage, attempts: u8, started: Instant) {
+        self.attempts[stage as usize] = attempts;
+        self.elapsed[stage as usize] = started.elapsed();
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum HandshakeStatus {
     Created,
@@ -156,10 +230,42 @@ pub enum HandshakeStatus {
     Completed,
 }
 
+/// The local addresses of the sockets a handshake is running on.
+static CLAIMED_SOCKETS: Mutex<Vec<SocketAddr>> = Mutex::new(Vec::new());
+
+/// Exclusive ownership of a socket for the duration of a handshake, two handshakes sharing
+/// a socket would read each other's replies. The socket is released once this is dropped.
+pub(crate) struct SocketClaim(SocketAddr);
+
+impl SocketClaim {
+    /// Claims the socket, `None` if a handshake is running on it already.
+    pub(crate) fn take(socket: &UdpSocket) -> Option<Self> {
+        let address = socket.local_addr().ok()?;
+        let mut claimed = CLAIMED_SOCKETS.lock().unwrap();
+        if claimed.contains(&address) {
+            return None;
+        }
+        claimed.push(address);
+        Some(Self(address))
+    }
+}
+
+impl Drop for SocketClaim {
+    fn drop(&mut self) {
+        if let Ok(mut claimed) = CLAIMED_SOCKETS.lock() {
+            claimed.retain(|address| *address != self.0);
+        }
+    }
+}
+
 pub(crate) struct HandshakeState {
     status: HandshakeStatus,
     done: bool,
     waker: Option<Waker>,
+    timings: HandshakeTimings,
+    error: Option<HandshakeError>,
+    /// The address the socket is connected to once the server answered.
+    peer: Option<SocketAddr>,
 }
 
 pub struct ClientHandshake {
@@ -167,207 +273,241 @@ pub struct ClientHandshake {
 }
 
 impl ClientHandshake {
-    pub fn new(socket: Arc<UdpSocket>, id: i64, version: u8, mut mtu: u16, attempts: u8) -> Self {
+    /// Starts the handshake, the given queues are used to send and receive the online part of the
+    /// handshake, so their sequences carry over to the connection once it is established.
+    ///
+    /// The handshake owns the socket until it is done, a second handshake started on the same
+    /// socket in the meantime fails right away with [`HandshakeError::SocketInUse`].
+    ///
+    /// A server answering from another port than the one the socket is connected to never gets
+    /// through, the handshake then fails with [`HandshakeError::PortMismatch`]. With
+    /// `allow_port_rebind` the socket is connected to that port instead, see [`Self::peer()`].
+    pub fn new(
+        socket: Arc<UdpSocket>,
+        meta: discovery::MtuDiscoveryMeta,
+        attempts: u8,
+        allow_port_rebind: bool,
+        send_queue: Arc<RwLock<SendQueue>>,
+        recv_queue: Arc<AsyncMutex<RecvQueue>>,
+    ) -> Self {
         let state = Arc::new(Mutex::new(HandshakeState {
             done: false,
             status: HandshakeStatus::Created,
             waker: None,
+            timings: HandshakeTimings::default(),
+            error: None,
+            peer: None,
         }));
 
         let shared_state = state.clone();
+        let claim = SocketClaim::take(&socket);
+        let mut meta = meta;
+        let mut rng = meta.rng.fork();
 
         task::spawn(async move {
+            let _claim = match claim {
+                Some(claim) => claim,
+                None => fail!(shared_state, HandshakeError::SocketInUse),
+            };
             update_state!(shared_state, HandshakeStatus::Opening);
 
             rakrs_debug!(true, "[CLIENT] Sending OpenConnectRequest to server...");
 
-            match MtuDiscovery::new(
-                socket.clone(),
-                discovery::MtuDiscoveryMeta { id, version, mtu },
-            )
-            .await
-            {
-                DiscoveryStatus::Discovered(m) => {
-                    rakrs_debug!(true, "[CLIENT] Discovered MTU size: {}", m);
-                    mtu = m;
+            let mut timings = HandshakeTimings::default();
+            let started = Instant::now();
+            let requested = socket.peer_addr().unwrap();
+
+            if allow_port_rebind {
+                if let Some(source) = discovery::reply_source(&meta, requested)
+                    .await
+                    .filter(|source| *source != requested)
+                {
+                    rakrs_debug!(
+                        true,
+                        "[CLIENT] Server answers from {} instead of {}, following it",
+                        source,
+                        requested
+                    );
+                    if socket.connect(source).await.is_err() {
+                        fail!(
+                            shared_state,
+                            HandshakeError::TimedOut {
+                                last_stage: Stage::Open1,
+                                mtu: meta.mtu,
+                                timings
+                            }
+                        );
+                    }
+                    send_queue.write().await.set_address(source);
                 }
-                _ => update_state!(true, shared_state, HandshakeStatus::Failed),
             }
+            shared_state.lock().unwrap().peer = socket.peer_addr().ok();
 
-            let session_info = SessionInfoRequest {
-                magic: Magic::new(),
-                address: socket.peer_addr().unwrap(),
-                mtu_size: mtu,
-                client_id: id,
+            let id = meta.id;
+            let mut discovery = MtuDiscovery::new(socket.clone(), meta.clone());
+            let discovered = (&mut discovery).await;
+            timings.record(Stage::Open1, discovery.attempts(), started);
+
+            let mtu = match discovered {
+                DiscoveryStatus::Discovered(m) => {
+                    rakrs_debug!(true, "[CLIENT] Discovered MTU size: {}", m);
+                    m
+                }
+                DiscoveryStatus::IncompatibleVersion => {
+                    fail!(shared_state, HandshakeError::IncompatibleVersion)
+                }
+                DiscoveryStatus::Rejected => fail!(
+                    shared_state,
+                    HandshakeError::Rejected {
+                        stage: Stage::Open1,
+                        timings
+                    }
+                ),
+                _ => {
+                    // a connected socket never sees replies from another port, see if that's why.
+                    if !allow_port_rebind {
+                        if let Some(source) = discovery::reply_source(&meta, requested)
+                            .await
+                            .filter(|source| *source != requested)
+                        {
+                            fail!(
+                                shared_state,
+                                HandshakeError::PortMismatch {
+                                    expected: requested.port(),
+                                    observed: source.port()
+                                }
+                            );
+                        }
+                    }
+                    fail!(
+                        shared_state,
+                        HandshakeError::TimedOut {
+                            last_stage: Stage::Open1,
+                            mtu: discovery.mtu(),
+                            timings
+                        }
+                    )
+                }
             };
 
+            let wanted = wanted_capabilities(&*send_queue.read().await, &*recv_queue.lock().await);
+            let session_info = session_info_request(socket.peer_addr().unwrap(), mtu, id, wanted);
+
             rakrs_debug!(true, "[CLIENT] Sending SessionInfoRequest to server...");
 
             update_state!(shared_state, HandshakeStatus::SessionOpen);
 
-            if !send_packet(&socket, session_info.into()).await {
-                update_state!(true, shared_state, HandshakeStatus::Failed);
-            }
-
-            let session_reply = expect_reply!(socket, SessionInfoReply);
-
-            if session_reply.is_none() {
-                update_state!(true, shared_state, HandshakeStatus::Failed);
-            }
-
-            let session_reply = session_reply.unwrap();
+            let started = Instant::now();
+            let sent = send_packet(&socket, session_info.into()).await;
+            let session_reply = if sent {
+                expect_reply!(socket, SessionInfoReply)
+            } else {
+                None
+            };
+            timings.record(Stage::Open2, 1, started);
+
+            let session_reply = match session_reply {
+                Some(reply) => reply,
+                None => fail!(
+                    shared_state,
+                    HandshakeError::TimedOut {
+                        last_stage: Stage::Open2,
+                        mtu,
+                        timings
+                    }
+                ),
+            };
 
             if session_reply.mtu_size != mtu {
-                update_state!(true, shared_state, HandshakeStatus::Failed);
+                fail!(
+                    shared_state,
+                    HandshakeError::Rejected {
+                        stage: Stage::Open2,
+                        timings
+                    }
+                );
             }
 
             rakrs_debug!(true, "[CLIENT] Received SessionInfoReply from server!");
 
-            // create a temporary sendq
-            let mut send_q = SendQueue::new(
-                mtu,
-                5000,
-                attempts.clone().into(),
-                socket.clone(),
+            // these are held until the handshake is done, the client does not touch them before then.
+            let mut send_q = send_queue.write().await;
+            let mut recv_q = recv_queue.lock().await;
+
+            let mut stage = ConnectStage::new(
+                id,
+                offered_capabilities(&session_reply, wanted),
                 socket.peer_addr().unwrap(),
             );
-            let mut recv_q = RecvQueue::new();
 
-            if let Err(_) = Self::send_connection_request(&mut send_q, id).await {
-                update_state!(true, shared_state, HandshakeStatus::Failed);
-            }
+            let started = Instant::now();
+            // a request that could not be sent is retried like one that was never answered.
+            let _ = stage.request(&mut send_q).await;
 
             rakrs_debug!(true, "[CLIENT] Sent ConnectionRequest to server!");
 
-            let mut send_time = current_epoch() as i64;
-            let mut tries = 0_u8;
+            let mut resend_at =
+                Instant::now() + rng.jitter(CONNECT_RESEND_INTERVAL, HANDSHAKE_RESEND_JITTER);
+            let mut tries = 1_u8;
 
             let mut buf: [u8; 2048] = [0; 2048];
 
             loop {
                 let len: usize;
-                let rec = socket.recv_from(&mut buf).await;
-
-                if (send_time + 2) <= current_epoch() as i64 {
-                    send_time = current_epoch() as i64;
+                // the server may never answer, so the resends can't wait on it.
+                let wait = resend_at
+                    .saturating_duration_since(Instant::now())
+                    .min(Duration::from_millis(500));
+                let rec = timeout(wait, socket.recv_from(&mut buf)).await;
+
+                if resend_at <= Instant::now() {
+                    resend_at = Instant::now()
+                        + rng.jitter(CONNECT_RESEND_INTERVAL, HANDSHAKE_RESEND_JITTER);
+
+                    if tries >= attempts {
+                        timings.record(Stage::Connect, tries, started);
+                        fail!(
+                            shared_state,
+                            HandshakeError::TimedOut {
+                                last_stage: Stage::Connect,
+                                mtu,
+                                timings
+                            }
+                        );
+                    }
 
                     rakrs_debug!(
                         true,
                         "[CLIENT] Server did not reply with ConnectAccept, sending another..."
                     );
 
-                    if let Err(_) = Self::send_connection_request(&mut send_q, id).await {
-                        update_state!(true, shared_state, HandshakeStatus::Failed);
-                    }
-
+                    let _ = stage.request(&mut send_q).await;
                     tries += 1;
-                    if tries >= 5 {
-                        update_state!(true, shared_state, HandshakeStatus::Failed);
-                    }
                 }
 
                 match rec {
-                    Err(_) => {
-                        continue;
-                    }
-                    Ok((l, _)) => len = l,
+                    Ok(Ok((l, _))) => len = l,
+                    _ => continue,
                 };
 
-                let mut reader = ByteReader::from(&buf[..len]);
-
-                // proccess frame packet
-                match buf[0] {
-                    0x80..=0x8d => {
-                        if let Ok(pk) = FramePacket::read(&mut reader) {
-                            if let Err(_) = recv_q.insert(pk) {
-                                continue;
-                            }
-
-                            let raw_packets = recv_q.flush();
-
-                            for raw_pk in raw_packets {
-                                let mut pk = ByteReader::from(&raw_pk[..]);
-
-                                if let Ok(pk) = OnlinePacket::read(&mut pk) {
-                                    match pk {
-                                        OnlinePacket::ConnectedPing(pk) => {
-                                            rakrs_debug!(
-                                                true,
-                                                "[CLIENT] Received ConnectedPing from server!"
-                                            );
-                                            let response = ConnectedPong {
-                                                ping_time: pk.time,
-                                                pong_time: current_epoch() as i64,
-                                            };
-
-                                            if let Err(_) = send_q
-                                                .send_packet(
-                                                    response.into(),
-                                                    Reliability::Reliable,
-                                                    true,
-                                                )
-                                                .await
-                                            {
-                                                rakrs_debug!(
-                                                    true,
-                                                    "[CLIENT] Failed to send pong packet!"
-                                                );
-                                            }
-
-                                            continue;
-                                        }
-                                        OnlinePacket::ConnectionAccept(pk) => {
-                                            // send new incoming connection
-                                            let new_incoming = NewConnection {
-                                                server_address: socket.peer_addr().unwrap(),
-                                                system_address: vec![
-                                                    socket.peer_addr().unwrap(),
-                                                    socket.peer_addr().unwrap(),
-                                                    socket.peer_addr().unwrap(),
-                                                    socket.peer_addr().unwrap(),
-                                                    socket.peer_addr().unwrap(),
-                                                    socket.peer_addr().unwrap(),
-                                                    socket.peer_addr().unwrap(),
-                                                    socket.peer_addr().unwrap(),
-                                                    socket.peer_addr().unwrap(),
-                                                    socket.peer_addr().unwrap(),
-                                                ],
-                                                request_time: pk.request_time,
-                                                timestamp: pk.timestamp,
-                                            };
-                                            if let Err(_) = send_q
-                                                .send_packet(
-                                                    new_incoming.into(),
-                                                    Reliability::Reliable,
-                                                    true,
-                                                )
-                                                .await
-                                            {
-                                                update_state!(
-                                                    true,
-                                                    shared_state,
-                                                    HandshakeStatus::Failed
-                                                );
-                                            } else {
-                                                update_state!(
-                                                    true,
-                                                    shared_state,
-                                                    HandshakeStatus::Completed
-                                                );
-                                            }
-                                        }
-                                        _ => {
-                                            rakrs_debug!(
-                                                true,
-                                                "[CLIENT] Received unknown packet from server!"
-                                            );
-                                        }
-                                    }
-                                }
+                match stage.datagram(&buf[..len], &mut send_q, &mut recv_q).await {
+                    Ok(false) => {}
+                    Ok(true) => {
+                        timings.record(Stage::Connect, tries, started);
+                        shared_state.lock().unwrap().timings = timings;
+                        update_state!(true, shared_state, HandshakeStatus::Completed);
+                    }
+                    Err(()) => {
+                        timings.record(Stage::Connect, tries, started);
+                        fail!(
+                            shared_state,
+                            HandshakeError::TimedOut {
+                                last_stage: Stage::Connect,
+                                mtu,
+                                timings
                             }
-                        }
+                        );
                     }
-                    _ => {}
                 }
             }
         });
@@ -375,14 +515,90 @@ impl ClientHandshake {
         Self { status: state }
     }
 
-    pub(crate) async fn send_connection_request(
-        send_q: &mut SendQueue,
-        id: i64,
-    ) -> std::io::Result<()> {
+    /// The address the socket is connected to, which is not the one it was connected to before
+    /// the handshake if the server answered from another port. `None` until the handshake
+    /// started.
+    pub fn peer(&self) -> Option<SocketAddr> {
+        self.status.lock().unwrap().peer
+    }
+}
+
+/// The capabilities a client wants, it can only offer the checksums it is able to verify.
+pub(crate) fn wanted_capabilities(send_q: &SendQueue, recv_q: &RecvQueue) -> Capabilities {
+    let wanted = if recv_q.datagram_checksum() {
+        Capabilities::DATAGRAM_CHECKSUM
+    } else {
+        Capabilities::NONE
+    };
+    // offered only when coalescing ourselves, so other clients keep vanilla requests.
+    if !send_q.coalesce_channels().is_empty() {
+        wanted.with(Capabilities::COALESCING)
+    } else {
+        wanted
+    }
+}
+
+/// The request that opens a session at the discovered `mtu`.
+pub(crate) fn session_info_request(
+    peer: SocketAddr,
+    mtu: u16,
+    id: i64,
+    wanted: Capabilities,
+) -> SessionInfoRequest {
+    SessionInfoRequest {
+        magic: Magic::new(),
+        address: peer,
+        mtu_size: mtu,
+        client_id: id,
+        // only a client with something to offer asks whether the server runs rak-rs.
+        capabilities: if wanted.is_empty() {
+            Capabilities::NONE
+        } else {
+            Capabilities::hello()
+        },
+    }
+}
+
+/// What the connection request offers, after the server answered the session info request.
+pub(crate) fn offered_capabilities(reply: &SessionInfoReply, wanted: Capabilities) -> Capabilities {
+    // a vanilla server reads whatever follows the request as a password.
+    match reply.capabilities.crate_version() {
+        Some(_) => wanted.offer(),
+        None => Capabilities::NONE,
+    }
+}
+
+/// The online part of the handshake, from the `ConnectionRequest` to the `NewConnection`.
+///
+/// It runs on the queues of the connection, so their sequences carry over to it once it is
+/// established. [`ClientHandshake`] drives it over the socket, the
+/// [`simulation`](crate::simulation) drives it over its link.
+pub(crate) struct ConnectStage {
+    id: i64,
+    capabilities: Capabilities,
+    /// The address of the server.
+    peer: SocketAddr,
+    /// Game packets that arrived before the handshake was done.
+    early: Vec<Vec<u8>>,
+}
+
+impl ConnectStage {
+    pub(crate) fn new(id: i64, capabilities: Capabilities, peer: SocketAddr) -> Self {
+        Self {
+            id,
+            capabilities,
+            peer,
+            early: Vec::new(),
+        }
+    }
+
+    /// Sends a connection request, every resend is a new one.
+    pub(crate) async fn request(&self, send_q: &mut SendQueue) -> std::io::Result<()> {
         let connect_request = ConnectionRequest {
             time: current_epoch() as i64,
-            client_id: id,
+            client_id: self.id,
             security: false,
+            capabilities: self.capabilities,
         };
 
         if let Err(_) = send_q
@@ -396,17 +612,106 @@ impl ClientHandshake {
         }
         return Ok(());
     }
+
+    /// Handles a datagram of the server. This is true once the server accepted the connection
+    /// and the `NewConnection` went out, and an error if it could not be sent.
+    pub(crate) async fn datagram(
+        &mut self,
+        buf: &[u8],
+        send_q: &mut SendQueue,
+        recv_q: &mut RecvQueue,
+    ) -> Result<bool, ()> {
+        // proccess frame packet
+        if !matches!(buf.first(), Some(0x80..=0x8f)) {
+            return Ok(false);
+        }
+        let datagram = recv_q.verify(buf).map(FramePacket::read_from_slice);
+
+        let Some(Ok(pk)) = datagram else {
+            return Ok(false);
+        };
+        if let Err(_) = recv_q.insert(pk) {
+            return Ok(false);
+        }
+
+        let mut raw_packets = recv_q.flush().into_iter();
+
+        while let Some(raw_pk) = raw_packets.next() {
+            let mut pk = ByteReader::from(&raw_pk[..]);
+
+            if let Ok(pk) = OnlinePacket::read(&mut pk) {
+                match pk {
+                    OnlinePacket::ConnectedPing(pk) => {
+                        rakrs_debug!(true, "[CLIENT] Received ConnectedPing from server!");
+                        let response = ConnectedPong {
+                            ping_time: pk.time,
+                            pong_time: rak_time_now(),
+                        };
+
+                        if let Err(_) = send_q
+                            .send_packet(response.into(), Reliability::Reliable, true)
+                            .await
+                        {
+                            rakrs_debug!(true, "[CLIENT] Failed to send pong packet!");
+                        }
+
+                        continue;
+                    }
+                    OnlinePacket::ConnectionAccept(pk) => {
+                        // the server only agrees to what we offered.
+                        if pk.capabilities.contains(Capabilities::DATAGRAM_CHECKSUM) {
+                            send_q.set_datagram_checksum(true);
+                        }
+                        if pk.capabilities.contains(Capabilities::COALESCING) {
+                            send_q.set_coalescing(true);
+                            recv_q.set_coalescing(true);
+                        }
+                        // send new incoming connection
+                        let new_incoming = NewConnection {
+                            server_address: self.peer,
+                            system_address: vec![self.peer; 10],
+                            request_time: pk.request_time,
+                            timestamp: pk.timestamp,
+                        };
+                        // ordered, so nothing the client sends next can overtake it.
+                        if send_q
+                            .send_packet(new_incoming.into(), Reliability::ReliableOrd, true)
+                            .await
+                            .is_err()
+                        {
+                            return Err(());
+                        }
+                        // the server may already be sending us data, keep it for the client.
+                        let mut early = std::mem::take(&mut self.early);
+                        early.extend(raw_packets);
+                        recv_q.requeue(early);
+                        return Ok(true);
+                    }
+                    _ => {
+                        rakrs_debug!(true, "[CLIENT] Received unknown packet from server!");
+                    }
+                }
+            } else {
+                self.early.push(raw_pk);
+            }
+        }
+        Ok(false)
+    }
 }
 
 impl Future for ClientHandshake {
-    type Output = HandshakeStatus;
+    /// The timings of every stage once the handshake completed.
+    type Output = Result<HandshakeTimings, HandshakeError>;
 
     fn poll(self: std::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         // see if we can finish
         let mut state = self.status.lock().unwrap();
 
         if state.done {
-            return Poll::Ready(state.status);
+            return Poll::Ready(match state.error {
+                Some(error) => Err(error),
+                None => Ok(state.timings),
+            });
         } else {
             state.waker = Some(cx.waker().clone());
             return Poll::Pending;
@@ -34,19 +34,22 @@
 //!     client.close().await;
 //! }
 //! ```
+pub(crate) mod delivery;
 pub mod discovery;
+pub(crate) mod driver;
 pub mod handshake;
+pub mod pool;
 pub(crate) mod util;
 
 use std::{
-    net::SocketAddr,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
     sync::{atomic::AtomicU64, Arc},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 #[cfg(feature = "async_std")]
 use async_std::{
-    channel::{bounded, Receiver, RecvError, Sender},
+    channel::{bounded, Receiver, RecvError},
     future::timeout,
     net::UdpSocket,
     sync::{Mutex, RwLock},
@@ -54,17 +57,19 @@ use async_std::{
 };
 
 #[cfg(feature = "async_std")]
-use futures::{select, FutureExt};
+use futures::select;
 
 use binary_util::interfaces::{Reader, Writer};
-use binary_util::io::ByteReader;
+use futures::channel::oneshot;
+use futures::future::Shared;
+use futures::FutureExt;
 
 #[cfg(feature = "async_tokio")]
 use tokio::{
     net::UdpSocket,
     select,
     sync::{
-        mpsc::{channel as bounded, Receiver, Sender},
+        mpsc::{channel as bounded, Receiver},
         Mutex, RwLock,
     },
     task::{self, JoinHandle},
@@ -74,26 +79,43 @@ use tokio::{
 #[cfg(feature = "async_tokio")]
 use crate::connection::RecvError;
 
+pub use crate::util::PossiblySocketAddr;
 use crate::{
     connection::{
-        queue::{RecvQueue, SendQueue},
+        controller::{
+            checksum::CHECKSUM_LEN,
+            clock::{ClockEstimator, RakTime},
+            flood::UnknownFloodGuard,
+            jitter::RngProvider,
+            memory::MemBudget,
+            one_way::OneWayDetector,
+            rtt::RttStats,
+        },
+        event::{CloseSignal, Closed, DisconnectReason, RakEvent},
+        options::ConnOptions,
+        queue::{event_queue, EventReceiver, EventSender, RecvQueue, SendQueue},
         state::ConnectionState,
+        stats::{ConnectionStats, SharedStats},
+        transform::{DatagramTransform, SharedTransform},
+        ConnMeta,
     },
-    error::client::ClientError,
+    error::client::{ClientError, HandshakeError},
     notify::Notify,
     protocol::{
-        ack::{Ack, Ackable, ACK, NACK},
-        frame::FramePacket,
+        ack::Ack,
+        frame::FrameRef,
         packet::{
             offline::{OfflinePacket, UnconnectedPing},
-            online::{ConnectedPing, ConnectedPong, OnlinePacket},
             RakPacket,
         },
         reliability::Reliability,
-        Magic,
+        Magic, MTU_LADDER, RAKNET_HEADER_FRAME_OVERHEAD,
     },
     rakrs_debug,
-    server::{current_epoch, PossiblySocketAddr},
+    util::{
+        current_epoch,
+        socket::{self, EcnSupport},
+    },
 };
 
 #[cfg(feature = "mcpe")]
@@ -103,7 +125,32 @@ use crate::protocol::packet::offline::UnconnectedPong;
 
 pub const DEFAULT_MTU: u16 = 1400;
 
-use self::handshake::{ClientHandshake, HandshakeStatus};
+/// The amount of pings sent before the server is given up on. A stateful firewall may only
+/// let replies through after a few packets went out, so this is never less than 3.
+pub const PING_PROBES: u8 = 4;
+/// How long each ping is waited on.
+const PING_INTERVAL: Duration = Duration::from_millis(2500);
+
+/// How long the client may go without sending anything before it pings the server.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The largest [first payload](Client::first_payload), a single frame at the smallest MTU
+/// the handshake settles on, with room for a checksum.
+pub const MAX_FIRST_PAYLOAD: usize =
+    (MTU_LADDER[0] - RAKNET_HEADER_FRAME_OVERHEAD) as usize - CHECKSUM_LEN;
+
+use self::delivery::Delivery;
+use self::discovery::MtuDiscoveryMeta;
+use self::driver::{PacketHandler, Ticker};
+use self::handshake::ClientHandshake;
+
+/// The drivers of a connected client, built once its handshake completed.
+pub(crate) struct ClientDrivers {
+    pub(crate) ticker: Ticker,
+    pub(crate) packets: PacketHandler,
+    /// Woken once the tick gave up on a stalled order channel.
+    pub(crate) released: Receiver<()>,
+}
 
 /// This is the client implementation of RakNet.
 /// This struct includes a few designated methods for sending and receiving packets.
@@ -165,22 +212,59 @@ use self::handshake::{ClientHandshake, HandshakeStatus};
 pub struct Client {
     /// The connection state of the client.
     pub(crate) state: Arc<Mutex<ConnectionState>>,
+    /// The connection attempt in progress, resolved with its result once it is done.
+    attempt: Attempt,
+    /// Whether [`Client::connect()`] waits for an attempt in progress rather than failing.
+    join_existing: bool,
+    /// Whether the client follows a server that answers the handshake from another port.
+    allow_port_rebind: bool,
+    /// The message sent right after the handshake, before [`Client::connect()`] returns.
+    first_payload: Option<Vec<u8>>,
+    /// The address connected to, when the server answered from another port.
+    rebound_from: std::sync::Mutex<Option<SocketAddr>>,
     /// The send queue is used internally to send packets to the server.
-    send_queue: Option<Arc<RwLock<SendQueue>>>,
+    /// Every connection gets a queue of its own.
+    send_queue: std::sync::Mutex<Option<Arc<RwLock<SendQueue>>>>,
     /// The receive queue is used internally to receive packets from the server.
     /// This is read from before sending
     recv_queue: Arc<Mutex<RecvQueue>>,
-    /// The network recieve channel is used to receive raw packets from the server.
-    network_recv: Option<Arc<Mutex<Receiver<Vec<u8>>>>>,
     /// The internal channel that is used to dispatch packets to a higher level.
+    #[cfg(feature = "async_std")]
     internal_recv: Receiver<Vec<u8>>,
-    internal_send: Sender<Vec<u8>>,
+    /// The internal channel that is used to dispatch packets to a higher level, locked so it
+    /// can be read from a shared client.
+    #[cfg(feature = "async_tokio")]
+    internal_recv: Mutex<Receiver<Vec<u8>>>,
+    /// Feeds the channel above, without waiting for the application to make room.
+    delivery: Delivery,
+    /// The internal channel that is used to dispatch events to a higher level.
+    internal_event_recv: EventReceiver,
+    internal_event_send: EventSender,
+    /// The options applied to the connection once it is established.
+    options: ConnOptions,
+    /// The reliability and channel of [`Client::send_default()`].
+    send_defaults: std::sync::Mutex<(Reliability, u8)>,
+    /// The round trip state of the connection, shared with the send queue.
+    rtt_stats: Arc<std::sync::Mutex<RttStats>>,
+    /// The estimate of the clock of the server, fed by the pongs to our pings.
+    clock: Arc<std::sync::Mutex<ClockEstimator>>,
+    /// The counters of the connection, published by the queues and the packet task.
+    shared_stats: Arc<SharedStats>,
+    /// Seals and opens the datagrams of the connection, shared with the send queue.
+    transform: SharedTransform,
     /// A list of tasks that are killed when the connection drops.
     tasks: Arc<Mutex<Vec<JoinHandle<()>>>>,
-    /// A notifier for when the client should kill threads.
-    close_notifier: Arc<Mutex<Notify>>,
+    /// A notifier for when the client should kill threads, every connection gets one of its own.
+    close_notifier: std::sync::Mutex<Arc<Notify>>,
+    /// Fired with the reason once the connection has terminated, and the [`Closed`] handed out
+    /// by [`Client::closed()`]. Replaced once a new connection is made after it fired.
+    close: std::sync::Mutex<(CloseSignal, Closed)>,
     /// A int for the last time a packet was received.
     recv_time: Arc<AtomicU64>,
+    /// The address the socket of the client is bound to, once it connected.
+    local_addr: std::sync::Mutex<Option<SocketAddr>>,
+    /// How much of ECN the socket of the client supports, once it connected.
+    ecn: std::sync::Mutex<EcnSupport>,
     /// The maximum packet size that can be sent to the server.
     mtu: u16,
     /// The RakNet version of the client.
@@ -203,32 +287,136 @@ impl Client {
     /// [Client::connect()]: crate::client::Client::connect
     pub fn new(version: u8, mtu: u16) -> Self {
         let (internal_send, internal_recv) = bounded::<Vec<u8>>(10);
+        let (internal_event_send, internal_event_recv) = event_queue();
+        let (close_signal, closed) = CloseSignal::new();
+        let shared_stats = Arc::new(SharedStats::default());
         Self {
             state: Arc::new(Mutex::new(ConnectionState::Offline)),
-            send_queue: None,
+            attempt: std::sync::Mutex::new(None),
+            join_existing: false,
+            allow_port_rebind: false,
+            first_payload: None,
+            rebound_from: std::sync::Mutex::new(None),
+            send_queue: std::sync::Mutex::new(None),
             recv_queue: Arc::new(Mutex::new(RecvQueue::new())),
-            network_recv: None,
             mtu,
             version,
             tasks: Arc::new(Mutex::new(Vec::new())),
-            close_notifier: Arc::new(Mutex::new(Notify::new())),
+            close_notifier: std::sync::Mutex::new(Arc::new(Notify::new())),
+            close: std::sync::Mutex::new((close_signal, closed)),
             recv_time: Arc::new(AtomicU64::new(0)),
+            local_addr: std::sync::Mutex::new(None),
+            ecn: std::sync::Mutex::new(EcnSupport::Off),
+            #[cfg(feature = "async_std")]
             internal_recv,
-            internal_send,
+            #[cfg(feature = "async_tokio")]
+            internal_recv: Mutex::new(internal_recv),
+            delivery: Delivery::new(internal_send, shared_stats.clone()),
+            internal_event_recv,
+            internal_event_send,
+            options: ConnOptions::default(),
+            send_defaults: std::sync::Mutex::new((Reliability::ReliableOrd, 0)),
+            rtt_stats: Arc::new(std::sync::Mutex::new(RttStats::default())),
+            clock: Arc::new(std::sync::Mutex::new(ClockEstimator::new())),
+            shared_stats,
+            transform: SharedTransform::default(),
             id: rand::random::<u64>(),
         }
     }
 
+    /// Sets the options used for the connection, these are applied when [`Client::connect()`] is called.
+    ///
+    /// # Example
+    /// ```rust ignore
+    /// use std::time::Duration;
+    /// use rak_rs::client::Client;
+    /// use rak_rs::connection::options::ConnOptions;
+    ///
+    /// let mut client = Client::new(10, 1400).with_options(ConnOptions {
+    ///     ordered_stall_limit: Some(Duration::from_secs(2)),
+    ///     ..Default::default()
+    /// });
+    /// ```
+    ///
+    /// [`Client::connect()`]: crate::client::Client::connect
+    pub fn with_options(mut self, options: ConnOptions) -> Self {
+        self.send_defaults =
+            std::sync::Mutex::new((options.default_reliability, options.default_channel));
+        self.options = options;
+        self
+    }
+
+    /// Sets whether [`Client::connect()`] waits for an attempt that is still in progress and
+    /// returns its result, rather than failing with [`ClientError::AlreadyConnecting`].
+    ///
+    /// # Example
+    /// ```rust ignore
+    /// use rak_rs::client::Client;
+    ///
+    /// // a second click on "Join" waits for the first one.
+    /// let client = Client::new(10, 1400).join_existing(true);
+    /// ```
+    pub fn join_existing(mut self, join: bool) -> Self {
+        self.join_existing = join;
+        self
+    }
+
+    /// Sets whether the client follows a server that answers the handshake from another port
+    /// than the one connected to, as some load balancers do. The connection is then made with
+    /// that port, a [`RakEvent::PortRebound`] is emitted and [`ConnMeta::rebound_from()`]
+    /// names the original address.
+    ///
+    /// This is off by default, as anyone on the host of the server could answer in its place.
+    /// The handshake then fails with [`HandshakeError::PortMismatch`] naming the port the
+    /// reply came from.
+    ///
+    /// # Example
+    /// ```rust ignore
+    /// use rak_rs::client::Client;
+    ///
+    /// let client = Client::new(10, 1400).allow_port_rebind(true);
+    /// ```
+    ///
+    /// [`ConnMeta::rebound_from()`]: crate::connection::ConnMeta::rebound_from
+    pub fn allow_port_rebind(mut self, allow: bool) -> Self {
+        self.allow_port_rebind = allow;
+        self
+    }
+
+    /// Sets a message that is sent as soon as the handshake completes, as the first reliable
+    /// ordered message on channel 0. The server receives it right after
+    /// [`RakEvent::Connected`], so a login can start without waiting for another round trip.
+    ///
+    /// The payload has to fit a single frame at the smallest MTU, at most
+    /// [`MAX_FIRST_PAYLOAD`] bytes. [`Client::connect()`] fails with
+    /// [`ClientError::FirstPayloadTooLarge`] otherwise, before anything is sent.
+    ///
+    /// # Example
+    /// ```rust ignore
+    /// use rak_rs::client::Client;
+    ///
+    /// let client = Client::new(10, 1400).first_payload(vec![0xfe, 0x01]);
+    /// ```
+    pub fn first_payload(mut self, payload: Vec<u8>) -> Self {
+        self.first_payload = Some(payload);
+        self
+    }
+
     /// This method should be used after [`Client::new()`] to start the connection.
     /// This method will start the connection, and will return a [`ClientError`] if the connection fails.
     ///
+    /// Only one connection attempt runs at a time. While one is in progress, connecting again
+    /// fails with [`ClientError::AlreadyConnecting`], or waits for its result if the client
+    /// [joins existing attempts](Client::join_existing). A connected client is
+    /// [`ClientError::AlreadyOnline`] until it is closed.
+    ///
     /// # Example
     /// ```rust ignore
     /// use rak_rs::client::Client;
     ///
     /// #[async_std::main]
     /// async fn main() {
-    ///     let mut client = Client::new(10, 1400);
+    ///     let client = Client::new(10, 1400);
     ///     if let Err(_) = client.connect("my_server.net:19132").await {
     ///         println!("Failed to connect to server!");
     ///         return;
@@ -238,13 +426,9 @@ impl Client {
     ///
     /// [`Client::new()`]: crate::client::Client::new
     pub async fn connect<Addr: for<'a> Into<PossiblySocketAddr<'a>>>(
-        &mut self,
+        &self,
         addr: Addr,
     ) -> Result<(), ClientError> {
-        if self.state.lock().await.is_available() {
-            return Err(ClientError::AlreadyOnline);
-        }
-
         let addrr: PossiblySocketAddr = addr.into();
         let address: SocketAddr = match addrr.to_socket_addr() {
             Some(a) => a,
@@ -254,14 +438,68 @@ impl Client {
             }
         };
 
-        let sock = match UdpSocket::bind("0.0.0.0:0").await {
-            Ok(s) => s,
-            Err(e) => {
-                rakrs_debug!("Failed to bind to address: {}", e);
-                return Err(ClientError::Killed);
+        let attempt = {
+            let mut attempt = self.attempt.lock().unwrap();
+            match attempt.as_ref() {
+                Some(result) if self.join_existing => Err(result.clone()),
+                Some(_) => return Err(ClientError::AlreadyConnecting),
+                None => {
+                    let (sender, receiver) = oneshot::channel();
+                    *attempt = Some(receiver.shared());
+                    Ok(AttemptGuard {
+                        attempt: &self.attempt,
+                        result: Some(sender),
+                    })
+                }
             }
         };
 
+        match attempt {
+            Ok(guard) => {
+                let result = self.establish(address).await;
+                guard.finish(result);
+                result
+            }
+            // the attempt was dropped before it was done.
+            Err(existing) => existing.await.unwrap_or(Err(ClientError::Killed)),
+        }
+    }
+
+    /// Connects to the server, [`Client::connect()`] makes sure only one of these runs at a time.
+    async fn establish(&self, address: SocketAddr) -> Result<(), ClientError> {
+        if self.state.lock().await.is_available() {
+            return Err(ClientError::AlreadyOnline);
+        }
+
+        if let Some(payload) = self.first_payload.as_ref() {
+            if payload.len() > MAX_FIRST_PAYLOAD {
+                return Err(ClientError::FirstPayloadTooLarge {
+                    len: payload.len(),
+                    max: MAX_FIRST_PAYLOAD,
+                });
+            }
+        }
+
+        let sock = match self.options.bind_device.as_deref() {
+            Some(device) => {
+                let local = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0);
+                match socket::bind_udp(local, Some(device)).and_then(socket::into_async) {
+                    Ok(s) => s,
+                    Err(e) => {
+                        rakrs_debug!("Failed to bind to device: {}", e);
+                        return Err(ClientError::BindDeviceErr);
+                    }
+                }
+            }
+            None => match UdpSocket::bind("0.0.0.0:0").await {
+                Ok(s) => s,
+                Err(e) => {
+                    rakrs_debug!("Failed to bind to address: {}", e);
+                    return Err(ClientError::Killed);
+                }
+            },
+        };
+
         rakrs_debug!(
             true,
             "[CLIENT] Attempting to connect to address: {}",
@@ -272,50 +510,71 @@ impl Client {
 
         if res.is_err() {
             rakrs_debug!("[CLIENT] Failed to connect to address");
-            // todo: properly handle lock.
-            self.close_notifier.lock().await.notify().await;
             return Err(ClientError::Killed);
         }
 
+        *self.local_addr.lock().unwrap() = sock.local_addr().ok();
+        *self.ecn.lock().unwrap() = socket::apply_ecn_async(&sock, self.options.enable_ecn);
+        *self.rebound_from.lock().unwrap() = None;
         let socket = Arc::new(sock);
-        let send_queue = Arc::new(RwLock::new(SendQueue::new(
-            self.mtu,
-            12000,
-            5,
-            socket.clone(),
-            address,
-        )));
-
-        self.send_queue = Some(send_queue.clone());
+        let send_queue = SendQueue::new(self.mtu, 12000, 5, socket.clone(), address);
+        // the connection is told apart by its port, the address it connects to is shared.
+        let (send_queue, rng, closer) = self
+            .reset_session(send_queue, socket.local_addr().unwrap_or(address))
+            .await;
         let (net_send, net_recv) = bounded::<Vec<u8>>(10);
-
-        self.network_recv = Some(Arc::new(Mutex::new(net_recv)));
-
-        let closer = self.close_notifier.clone();
+        let task_closer = closer.clone();
 
         Self::ping(socket.clone()).await?;
 
         self.update_state(ConnectionState::Unidentified).await;
         rakrs_debug!(true, "[CLIENT] Starting connection handshake");
         // before we even start the connection, we need to complete the handshake
-        let handshake =
-            ClientHandshake::new(socket.clone(), self.id as i64, self.version, self.mtu, 5).await;
+        let mut handshake = ClientHandshake::new(
+            socket.clone(),
+            self.discovery_meta(rng),
+            5,
+            self.allow_port_rebind,
+            send_queue.clone(),
+            self.recv_queue(),
+        );
 
-        if handshake != HandshakeStatus::Completed {
-            rakrs_debug!("Failed to complete handshake: {:?}", handshake);
-            return Err(ClientError::Killed);
+        if let Err(e) = (&mut handshake).await {
+            rakrs_debug!("Failed to complete handshake: {:?}", e);
+            return Err(ClientError::Handshake(e));
         }
         self.update_state(ConnectionState::Identified).await;
 
+        if let Some(peer) = handshake.peer().filter(|peer| *peer != address) {
+            *self.rebound_from.lock().unwrap() = Some(address);
+            let event = RakEvent::PortRebound {
+                requested: address,
+                peer,
+            };
+            if self.internal_event_send.try_send(event).is_err() {
+                rakrs_debug!(true, "[CLIENT] Event channel is full, dropping event!");
+            }
+        }
+
         rakrs_debug!(true, "[CLIENT] Handshake completed!");
+        self.handshake_completed(&send_queue).await?;
 
+        let read_budget = self.options.read_budget.max(1);
         let socket_task = task::spawn(async move {
             let mut buf: [u8; 2048] = [0; 2048];
-            let notifier = closer.lock().await;
+            let notifier = closer;
+            let mut handled: usize = 0;
 
             loop {
                 let length: usize;
 
+                // the server can keep the socket ready, give the ticks a chance to run.
+                if handled >= read_budget {
+                    handled = 0;
+                    task::yield_now().await;
+                }
+                handled += 1;
+
                 #[cfg(feature = "async_std")]
                 select! {
                     killed = notifier.wait().fuse() => {
@@ -369,8 +628,18 @@ impl Client {
             }
         });
 
-        let recv_task = self.init_recv_task();
-        let tisk_task = self.init_connect_tick(send_queue.clone());
+        let drivers = self.drivers(
+            send_queue.clone(),
+            task_closer.clone(),
+            handshake.peer().unwrap_or(address),
+        );
+        let recv_task = self.init_recv_task(
+            Arc::new(Mutex::new(net_recv)),
+            drivers.packets,
+            drivers.released,
+            task_closer.clone(),
+        );
+        let tisk_task = self.init_connect_tick(drivers.ticker, task_closer);
 
         if let Err(e) = recv_task {
             rakrs_debug!(true, "[CLIENT] Failed to start recv task: {:?}", e);
@@ -404,17 +673,175 @@ impl Client {
         Ok(())
     }
 
+    /// Starts the session of a new connection over `send_queue`, nothing of a previous
+    /// connection carries over. `local` is the address the connection is told apart by.
+    pub(crate) async fn reset_session(
+        &self,
+        mut send_queue: SendQueue,
+        local: SocketAddr,
+    ) -> (Arc<RwLock<SendQueue>>, RngProvider, Arc<Notify>) {
+        send_queue.set_mtu_reprobe_interval(self.options.mtu_reprobe_interval);
+        send_queue.set_frame_budget(self.options.frame_budget);
+        send_queue.set_coalesce_channels(self.options.coalesce_channels.clone());
+        let mut rng = RngProvider::for_address(self.options.rng_seed, local);
+        send_queue.set_rng(rng.fork());
+        *self.rtt_stats.lock().unwrap() = RttStats::default();
+        send_queue.set_rtt_stats(self.rtt_stats.clone());
+        self.shared_stats
+            .unknown_suppressed
+            .store(0, std::sync::atomic::Ordering::Relaxed);
+        send_queue.set_shared_stats(self.shared_stats.clone());
+        // the handshake of a new connection goes out in the clear.
+        self.transform.install(None);
+        send_queue.set_transform(self.transform.clone());
+        let memory = MemBudget::new(self.options.max_connection_memory);
+        send_queue.set_memory_budget(memory.clone());
+        self.delivery
+            .restart(self.options.consumer_timeout, memory.clone());
+        *self.clock.lock().unwrap() = ClockEstimator::new();
+        let send_queue = Arc::new(RwLock::new(send_queue));
+
+        // nothing of a previous connection carries over, its sequences least of all.
+        let mut recv_queue = RecvQueue::new();
+        recv_queue.set_ordered_stall_limit(self.options.ordered_stall_limit);
+        recv_queue.set_order_jump_limit(self.options.order_jump_limit);
+        recv_queue.set_datagram_checksum(self.options.datagram_checksum);
+        recv_queue.set_shared_stats(self.shared_stats.clone());
+        recv_queue.set_memory_budget(memory);
+        *self.recv_queue.lock().await = recv_queue;
+
+        *self.send_queue.lock().unwrap() = Some(send_queue.clone());
+
+        let closer = Arc::new(Notify::new());
+        *self.close_notifier.lock().unwrap() = closer.clone();
+        {
+            let mut close = self.close.lock().unwrap();
+            if close.0.is_closed() {
+                *close = CloseSignal::new();
+            }
+        }
+        (send_queue, rng, closer)
+    }
+
+    /// What the handshake of a new connection starts out with, `rng` spreads its resends.
+    pub(crate) fn discovery_meta(&self, rng: RngProvider) -> MtuDiscoveryMeta {
+        MtuDiscoveryMeta {
+            id: self.id as i64,
+            version: self.version,
+            mtu: self.mtu,
+            rng,
+        }
+    }
+
+    /// The receive queue of the connection, the handshake receives on it.
+    pub(crate) fn recv_queue(&self) -> Arc<Mutex<RecvQueue>> {
+        self.recv_queue.clone()
+    }
+
+    /// Queues the first payload once the handshake completed, and counts the server as heard.
+    pub(crate) async fn handshake_completed(
+        &self,
+        send_queue: &RwLock<SendQueue>,
+    ) -> Result<(), ClientError> {
+        if let Some(payload) = self.first_payload.as_ref() {
+            // ordered right behind the NewConnection, so the server can't see it before.
+            let mut q = send_queue.write().await;
+            if let Err(e) = q
+                .insert(payload, Reliability::ReliableOrd, true, Some(0))
+                .await
+            {
+                rakrs_debug!("[CLIENT] Failed to send the first payload: {:?}", e);
+                return Err(ClientError::SendQueueError(e));
+            }
+        }
+        // the server just answered us, this also prevents the tick from timing out immediately.
+        self.recv_time
+            .store(current_epoch(), std::sync::atomic::Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Builds the drivers of the connection to `peer`, after its handshake completed.
+    pub(crate) fn drivers(
+        &self,
+        send_queue: Arc<RwLock<SendQueue>>,
+        closer: Arc<Notify>,
+        peer: SocketAddr,
+    ) -> ClientDrivers {
+        // the tick gave up on a stalled order channel, the recv task hands out what it released.
+        let (release_wake, released) = bounded::<()>(1);
+        let ticker = Ticker {
+            close_signal: self.close_signal(),
+            event_sender: self.internal_event_send.clone(),
+            one_way: OneWayDetector::new(self.options.one_way_fail_threshold),
+            recv_queue: self.recv_queue.clone(),
+            send_queue: send_queue.clone(),
+            state: self.state.clone(),
+            last_recv: self.recv_time.clone(),
+            clock: self.clock.clone(),
+            closer,
+            peer,
+            release_wake: release_wake.clone(),
+            next_tick: crate::util::now() + driver::TICK_INTERVAL,
+        };
+        let packets = PacketHandler {
+            recv_queue: self.recv_queue.clone(),
+            send_queue,
+            delivery: self.delivery.clone(),
+            event_sender: self.internal_event_send.clone(),
+            close_signal: self.close_signal(),
+            state: self.state.clone(),
+            recv_time: self.recv_time.clone(),
+            shared_stats: self.shared_stats.clone(),
+            transform: self.transform.clone(),
+            clock: self.clock.clone(),
+            ack_immediately_above: self.options.ack_immediately_above,
+            flood_guard: UnknownFloodGuard::new(
+                self.options.unknown_flood_threshold,
+                self.options.unknown_flood_window,
+                &self.options.claimed_ids,
+            ),
+            _release_wake: release_wake,
+        };
+        ClientDrivers {
+            ticker,
+            packets,
+            released,
+        }
+    }
+
     /// Updates the client state
     pub async fn update_state(&self, new_state: ConnectionState) {
         let mut state = self.state.lock().await;
         *state = new_state;
     }
 
-    /// Todo: send disconnect packet.
+    /// Notifies the server that we are leaving, and stops the client.
+    ///
+    /// This returns once the server acknowledged the disconnect, or
+    /// [`ConnOptions::disconnect_linger`] passed without it doing so.
+    /// The client can connect again afterwards.
     pub async fn close(&self) {
+        if self.state.lock().await.is_available() {
+            if let Some(send_queue) = self.send_queue() {
+                let disconnect = DisconnectReason::Closed.to_packet();
+                let mut send_q = send_queue.write().await;
+                if send_q
+                    .insert(&disconnect, Reliability::ReliableOrd, true, Some(0))
+                    .await
+                    .is_err()
+                {
+                    rakrs_debug!(
+                        true,
+                        "[CLIENT] Failed to send disconnect packet when closing!"
+                    );
+                }
+                drop(send_q);
+                self.linger(&send_queue).await;
+            }
+        }
         self.update_state(ConnectionState::Disconnecting).await;
-        let notifier = self.close_notifier.clone();
-        notifier.lock().await.notify().await;
+        let notifier = self.close_notifier();
+        notifier.notify().await;
         let mut tasks = self.tasks.lock().await;
         for task in tasks.drain(..) {
             #[cfg(feature = "async_std")]
@@ -422,11 +849,169 @@ impl Client {
             #[cfg(feature = "async_tokio")]
             task.abort();
         }
+        drop(tasks);
+
+        self.close_signal().close(DisconnectReason::Closed);
+        // nothing runs for the connection anymore, a new one may be made.
+        self.update_state(ConnectionState::Disconnected).await;
+    }
+
+    /// Keeps the tasks running until the server acknowledged everything that was sent, the
+    /// disconnect included, for at most [`ConnOptions::disconnect_linger`].
+    async fn linger(&self, send_queue: &RwLock<SendQueue>) {
+        let start = Instant::now();
+        let closed = self.closed();
+        while start.elapsed() < self.options.disconnect_linger {
+            // the server closed as well, or the connection timed out.
+            if closed.clone().now_or_never().is_some() {
+                return;
+            }
+            if send_queue.read().await.pending() == 0 {
+                return;
+            }
+            sleep(Duration::from_millis(10)).await;
+        }
+        rakrs_debug!(
+            true,
+            "[CLIENT] The disconnect was not acknowledged in time, closing anyway!"
+        );
+    }
+
+    /// The local address of the client, `None` until [`Client::connect()`] bound its socket.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        *self.local_addr.lock().unwrap()
+    }
+
+    /// How much of ECN the socket of the client supports, see [`Connection::ecn_supported()`].
+    /// This is [`EcnSupport::Off`] until [`Client::connect()`] bound its socket.
+    pub fn ecn_supported(&self) -> EcnSupport {
+        *self.ecn.lock().unwrap()
+    }
+
+    /// Resolves with the reason once the connection to the server has terminated,
+    /// see [`Connection::closed()`].
+    ///
+    /// [`Connection::closed()`]: crate::connection::Connection::closed
+    pub fn closed(&self) -> Closed {
+        self.close.lock().unwrap().1.clone()
+    }
+
+    fn close_signal(&self) -> CloseSignal {
+        self.close.lock().unwrap().0.clone()
+    }
+
+    fn close_notifier(&self) -> Arc<Notify> {
+        self.close_notifier.lock().unwrap().clone()
+    }
+
+    /// The send queue of the current connection, `None` until the client connected once.
+    fn send_queue(&self) -> Option<Arc<RwLock<SendQueue>>> {
+        self.send_queue.lock().unwrap().clone()
+    }
+
+    /// Returns a copy of the round trip state of the connection.
+    pub fn rtt_stats(&self) -> RttStats {
+        self.rtt_stats
+            .lock()
+            .map(|stats| *stats)
+            .unwrap_or_default()
+    }
+
+    /// The smoothed round trip time, `None` until the first reliable datagram has been acknowledged.
+    pub fn rtt(&self) -> Option<Duration> {
+        self.rtt_stats().rtt
+    }
+
+    /// How many milliseconds the clock of the server is ahead of ours, `None` until the
+    /// first pong to one of our pings came back.
+    ///
+    /// This is an estimate, see [`ClockEstimator`] for how far off it can be. Vanilla servers
+    /// don't count from the unix epoch, so their offset is only good for
+    /// [`Client::server_time_now()`].
+    pub fn clock_offset(&self) -> Option<RakTime> {
+        self.clock.lock().ok()?.offset()
+    }
+
+    /// The current time on the clock of the server, `None` until the first pong to one of
+    /// our pings came back. Useful to line events up with timestamps of the server, such as
+    /// the end of a countdown.
+    pub fn server_time_now(&self) -> Option<RakTime> {
+        self.clock.lock().ok()?.peer_time_now()
+    }
+
+    /// The variance of the round trip time, `None` until the first reliable datagram has been acknowledged.
+    pub fn rtt_variance(&self) -> Option<Duration> {
+        self.rtt_stats().rtt_variance
+    }
+
+    /// How long an unacknowledged reliable datagram is kept before it is resent.
+    pub fn current_rto(&self) -> Duration {
+        self.rtt_stats().rto
+    }
+
+    /// How many times in a row the retransmission timer fired without an acknowledgement.
+    pub fn consecutive_timeouts(&self) -> u32 {
+        self.rtt_stats().consecutive_timeouts
+    }
+
+    /// Returns the meta data of the connection, like the address it was rebound from.
+    pub async fn meta(&self) -> ConnMeta {
+        let mut meta = ConnMeta::new(match self.send_queue() {
+            Some(_) => self
+                .shared_stats
+                .mtu_size
+                .load(std::sync::atomic::Ordering::Relaxed),
+            None => self.mtu,
+        });
+        meta.recv_time = self.recv_time.load(std::sync::atomic::Ordering::Relaxed);
+        meta.rebound_from = *self.rebound_from.lock().unwrap();
+        meta
+    }
+
+    /// Returns a snapshot of the statistics of the connection.
+    pub async fn stats(&self) -> ConnectionStats {
+        let (channels, memory_used) = {
+            let recv_queue = self.recv_queue.lock().await;
+            (
+                recv_queue.channel_stats(crate::util::now()),
+                recv_queue.memory_budget().used(),
+            )
+        };
+        // published while nothing updates the counters, so they agree with each other.
+        match self.send_queue() {
+            Some(send_queue) => send_queue.read().await.publish_stats(),
+            None => self.shared_stats.publish(),
+        }
+
+        ConnectionStats {
+            rtt: self.rtt_stats(),
+            memory_used,
+            max_channel_blocked: channels
+                .iter()
+                .map(|channel| channel.blocked_for)
+                .max()
+                .unwrap_or_default(),
+            channels,
+            ..Default::default()
+        }
+        .with_counters(self.shared_stats.snapshot())
+    }
+
+    /// The amount of packets with an unknown id that were dropped because the server was flooding them.
+    /// See [`ConnOptions::unknown_flood_threshold`].
+    pub fn unknown_suppressed(&self) -> u64 {
+        self.shared_stats
+            .unknown_suppressed
+            .load(std::sync::atomic::Ordering::Relaxed)
     }
 
     pub async fn send_ord(&self, buffer: &[u8], channel: u8) -> Result<(), ClientError> {
         if self.state.lock().await.is_available() {
-            let mut send_q = self.send_queue.as_ref().unwrap().write().await;
+            let send_queue = self.send_queue().ok_or(ClientError::NotListening)?;
+            let mut send_q = send_queue.write().await;
+            send_q
+                .reserve(buffer.len())
+                .map_err(ClientError::SendQueueError)?;
             if let Err(send) = send_q
                 .insert(buffer, Reliability::ReliableOrd, false, Some(channel))
                 .await
@@ -447,7 +1032,11 @@ impl Client {
 
     pub async fn send_seq(&self, buffer: &[u8], channel: u8) -> Result<(), ClientError> {
         if self.state.lock().await.is_available() {
-            let mut send_q = self.send_queue.as_ref().unwrap().write().await;
+            let send_queue = self.send_queue().ok_or(ClientError::NotListening)?;
+            let mut send_q = send_queue.write().await;
+            send_q
+                .reserve(buffer.len())
+                .map_err(ClientError::SendQueueError)?;
             if let Err(send) = send_q
                 .insert(buffer, Reliability::ReliableSeq, false, Some(channel))
                 .await
@@ -468,7 +1057,11 @@ impl Client {
         channel: u8,
     ) -> Result<(), ClientError> {
         if self.state.lock().await.is_available() {
-            let mut send_q = self.send_queue.as_ref().unwrap().write().await;
+            let send_queue = self.send_queue().ok_or(ClientError::NotListening)?;
+            let mut send_q = send_queue.write().await;
+            send_q
+                .reserve(buffer.len())
+                .map_err(ClientError::SendQueueError)?;
             if let Err(send) = send_q
                 .insert(buffer, reliability, false, Some(channel))
                 .await
@@ -482,6 +1075,55 @@ impl Client {
         }
     }
 
+    /// Sends a payload with the reliability and on the channel set as the defaults of the
+    /// client, see [`Client::set_default_reliability()`] and [`Client::set_default_channel()`].
+    /// They start out as [`ConnOptions::default_reliability`] and
+    /// [`ConnOptions::default_channel`], and are kept across connections.
+    ///
+    /// The defaults are read when this is called, changing them afterwards doesn't affect
+    /// the payload.
+    pub async fn send_default(&self, buffer: &[u8]) -> Result<(), ClientError> {
+        let (reliability, channel) = *self.send_defaults.lock().unwrap();
+        self.send(buffer, reliability, channel).await
+    }
+
+    /// Changes the reliability [`Client::send_default()`] sends with, for the sends after
+    /// this one.
+    pub fn set_default_reliability(&self, reliability: Reliability) {
+        self.send_defaults.lock().unwrap().0 = reliability;
+    }
+
+    /// Seals every datagram the client sends with `transform` from now on, and opens every
+    /// datagram of the server with it, `None` goes back to datagrams in the clear. See
+    /// [`transform`](crate::connection::transform).
+    ///
+    /// This is meant to be called once [`Client::connect()`] returned, the server has to
+    /// install the same transform. The next connection starts out in the clear again.
+    pub fn set_transform(&self, transform: Option<Arc<dyn DatagramTransform>>) {
+        self.transform.install(transform);
+    }
+
+    /// Changes the order channel [`Client::send_default()`] sends on, for the sends after
+    /// this one.
+    pub fn set_default_channel(&self, channel: u8) {
+        self.send_defaults.lock().unwrap().1 = channel;
+    }
+
+    /// The reliability [`Client::send_default()`] sends with.
+    pub fn default_reliability(&self) -> Reliability {
+        self.send_defaults.lock().unwrap().0
+    }
+
+    /// The order channel [`Client::send_default()`] sends on.
+    pub fn default_channel(&self) -> u8 {
+        self.send_defaults.lock().unwrap().1
+    }
+
+    /// Sends a payload right away rather than on the next tick.
+    ///
+    /// Like every send of the client, this is cancellation safe: once the send queue was
+    /// locked, the payload is queued in full and whatever the future didn't get to send
+    /// goes out on the next tick.
     pub async fn send_immediate(
         &self,
         buffer: &[u8],
@@ -489,7 +1131,11 @@ impl Client {
         channel: u8,
     ) -> Result<(), ClientError> {
         if self.state.lock().await.is_available() {
-            let mut send_q = self.send_queue.as_ref().unwrap().write().await;
+            let send_queue = self.send_queue().ok_or(ClientError::NotListening)?;
+            let mut send_q = send_queue.write().await;
+            send_q
+                .reserve(buffer.len())
+                .map_err(ClientError::SendQueueError)?;
             if let Err(send) = send_q
                 .insert(buffer, reliability, true, Some(channel))
                 .await
@@ -503,19 +1149,49 @@ impl Client {
         }
     }
 
-    pub async fn flush_ack(&self) {
-        let mut send_q = self.send_queue.as_ref().unwrap().write().await;
-        let mut recv_q = self.recv_queue.lock().await;
-        // Flush the queue of acks and nacks, and respond to them
-        let ack = Ack::from_records(recv_q.ack_flush(), false);
-        if ack.records.len() > 0 {
-            if let Ok(p) = ack.write_to_bytes() {
-                send_q.send_stream(p.as_slice()).await;
+    /// Forwards a frame received from another peer as is, for proxies that relay a client's
+    /// traffic to a backend without reassembling it.
+    ///
+    /// See [`Connection::send_raw_frame()`](crate::connection::Connection::send_raw_frame).
+    pub async fn send_raw_frame(
+        &self,
+        frame: FrameRef<'_>,
+        reliability_override: Option<Reliability>,
+    ) -> Result<(), ClientError> {
+        if self.state.lock().await.is_available() {
+            let send_queue = self.send_queue().ok_or(ClientError::NotListening)?;
+            let mut send_q = send_queue.write().await;
+            send_q
+                .reserve(frame.body.len())
+                .map_err(ClientError::SendQueueError)?;
+            if let Err(send) = send_q
+                .insert_frame(frame, reliability_override, false)
+                .await
+            {
+                rakrs_debug!(true, "[CLIENT] Failed to insert frame into send queue!");
+                return Err(ClientError::SendQueueError(send));
             }
+            Ok(())
+        } else {
+            Err(ClientError::Unavailable)
         }
+    }
+
+    pub async fn flush_ack(&self) {
+        let send_queue = match self.send_queue() {
+            Some(send_queue) => send_queue,
+            None => return,
+        };
+        let (acks, nacks) = {
+            let mut recv_q = self.recv_queue.lock().await;
+            (recv_q.ack_flush(), recv_q.nack_queue().clone())
+        };
+        let mut send_q = send_queue.write().await;
+        // Flush the queue of acks and nacks, and respond to them
+        send_q.send_ack_ranges(acks).await;
 
         // flush nacks from recv queue
-        let nack = Ack::from_records(recv_q.nack_queue(), true);
+        let nack = Ack::from_ranges(&nacks, true);
         if nack.records.len() > 0 {
             if let Ok(p) = nack.write_to_bytes() {
                 send_q.send_stream(p.as_slice()).await;
@@ -523,269 +1199,119 @@ impl Client {
         }
     }
 
+    /// Recieves the next packet sent by the server. This is cancellation safe, a packet is
+    /// only taken once it is returned.
     #[cfg(feature = "async_std")]
     pub async fn recv(&self) -> Result<Vec<u8>, RecvError> {
-        match self.internal_recv.recv().await {
-            #[cfg(feature = "async_std")]
-            Ok(packet) => Ok(packet),
-            #[cfg(feature = "async_std")]
-            Err(e) => Err(e),
-        }
+        let packet = self.internal_recv.recv().await;
+        self.delivery.refill();
+        packet
     }
 
+    /// Recieves the next packet sent by the server. This is cancellation safe, a packet is
+    /// only taken once it is returned.
     #[cfg(feature = "async_tokio")]
-    pub async fn recv(&mut self) -> Result<Vec<u8>, RecvError> {
-        match self.internal_recv.recv().await {
-            Some(packet) => Ok(packet),
-            None => Err(RecvError::Closed),
-        }
+    pub async fn recv(&self) -> Result<Vec<u8>, RecvError> {
+        let packet = self.internal_recv.lock().await.recv().await;
+        self.delivery.refill();
+        packet.ok_or(RecvError::Closed)
+    }
+
+    /// Recieves the next event that happened on the connection, for example
+    /// a [`RakEvent::OrderedGap`] when an ordered message was given up on.
+    ///
+    /// Lifecycle events are read ahead of the others, see [`Connection::recv_event()`].
+    ///
+    /// [`RakEvent::OrderedGap`]: crate::connection::event::RakEvent::OrderedGap
+    pub async fn recv_event(&self) -> Result<RakEvent, RecvError> {
+        self.internal_event_recv.recv().await
     }
 
+    /// Pings the server the socket is connected to, sending up to [`PING_PROBES`] pings.
+    ///
+    /// When nothing at all came back, not even an error such as an ICMP port unreachable,
+    /// this is [`HandshakeError::NoInboundTraffic`] rather than [`ClientError::ServerOffline`].
+    ///
+    /// [`HandshakeError::NoInboundTraffic`]: crate::error::client::HandshakeError::NoInboundTraffic
     pub async fn ping(socket: Arc<UdpSocket>) -> Result<UnconnectedPong, ClientError> {
         let mut buf: [u8; 2048] = [0; 2048];
-        let unconnected_ping = UnconnectedPing {
-            timestamp: current_epoch(),
-            magic: Magic::new(),
-            client_id: rand::random::<i64>(),
-        };
+        // whether the socket saw anything at all, replies we can't use and errors included.
+        let mut inbound = false;
+
+        for _ in 0..PING_PROBES {
+            let unconnected_ping = UnconnectedPing {
+                timestamp: current_epoch(),
+                magic: Magic::new(),
+                client_id: rand::random::<i64>(),
+            };
+
+            if socket
+                .send(
+                    RakPacket::from(unconnected_ping)
+                        .write_to_bytes()
+                        .unwrap()
+                        .as_slice(),
+                )
+                .await
+                .is_err()
+            {
+                rakrs_debug!(true, "[CLIENT] Failed to send ping packet!");
+                return Err(ClientError::ServerOffline);
+            }
 
-        if let Err(_) = socket
-            .send(
-                RakPacket::from(unconnected_ping)
-                    .write_to_bytes()
-                    .unwrap()
-                    .as_slice(),
-            )
-            .await
-        {
-            rakrs_debug!(true, "[CLIENT] Failed to send ping packet!");
-            return Err(ClientError::ServerOffline);
-        }
-
-        loop {
-            rakrs_debug!(true, "[CLIENT] Waiting for pong packet...");
-            if let Ok(recvd) = timeout(Duration::from_millis(10000), socket.recv(&mut buf)).await {
-                match recvd {
-                    Ok(l) => {
-                        let mut reader = ByteReader::from(&buf[..l]);
-                        let packet = RakPacket::read(&mut reader).unwrap();
-
-                        match packet {
-                            RakPacket::Offline(offline) => match offline {
-                                OfflinePacket::UnconnectedPong(pong) => {
-                                    rakrs_debug!(true, "[CLIENT] Recieved pong packet!");
-                                    return Ok(pong);
-                                }
-                                _ => {}
-                            },
-                            _ => {}
+            let deadline = Instant::now() + PING_INTERVAL;
+            loop {
+                rakrs_debug!(true, "[CLIENT] Waiting for pong packet...");
+                let left = deadline.saturating_duration_since(Instant::now());
+                match timeout(left, socket.recv(&mut buf)).await {
+                    Ok(Ok(l)) => {
+                        inbound = true;
+                        if let Ok(RakPacket::Offline(OfflinePacket::UnconnectedPong(pong))) =
+                            RakPacket::read_from_slice(&buf[..l])
+                        {
+                            rakrs_debug!(true, "[CLIENT] Recieved pong packet!");
+                            return Ok(pong);
                         }
                     }
-                    Err(_) => {
-                        rakrs_debug!(true, "[CLIENT] Failed to recieve anything on netowrk channel, is there a sender?");
-                        continue;
+                    Ok(Err(_)) => {
+                        // the path works, the port just isn't open.
+                        inbound = true;
+                        rakrs_debug!(
+                            true,
+                            "[CLIENT] The socket reported an error while waiting for the pong"
+                        );
                     }
+                    Err(_) => break,
                 }
-            } else {
-                rakrs_debug!(true, "[CLIENT] Ping Failed, server did not respond!");
-                return Err(ClientError::ServerOffline);
             }
         }
-    }
-
-    fn init_recv_task(&self) -> Result<JoinHandle<()>, ClientError> {
-        let net_recv = match self.network_recv {
-            Some(ref n) => n.clone(),
-            None => {
-                rakrs_debug!("[CLIENT] (recv_task) Network recv channel is not initialized");
-                return Err(ClientError::Killed);
-            }
-        };
 
-        let send_queue = match self.send_queue {
-            Some(ref s) => s.clone(),
-            None => {
-                rakrs_debug!("[CLIENT] (recv_task) Send queue is not initialized");
-                return Err(ClientError::Killed);
-            }
-        };
-
-        let recv_queue = self.recv_queue.clone();
-        let internal_sender = self.internal_send.clone();
-        let closed = self.close_notifier.clone();
-        let state = self.state.clone();
-        let recv_time = self.recv_time.clone();
+        rakrs_debug!(true, "[CLIENT] Ping Failed, server did not respond!");
+        if inbound {
+            Err(ClientError::ServerOffline)
+        } else {
+            Err(ClientError::Handshake(HandshakeError::NoInboundTraffic {
+                probes: PING_PROBES,
+            }))
+        }
+    }
 
+    fn init_recv_task(
+        &self,
+        net_recv: Arc<Mutex<Receiver<Vec<u8>>>>,
+        mut packets: PacketHandler,
+        #[cfg(feature = "async_std")] released: Receiver<()>,
+        #[cfg(feature = "async_tokio")] mut released: Receiver<()>,
+        closed: Arc<Notify>,
+    ) -> Result<JoinHandle<()>, ClientError> {
         return Ok(task::spawn(async move {
-            'task_loop: loop {
+            loop {
                 #[cfg(feature = "async_std")]
                 let net_dispatch = net_recv.lock().await;
                 #[cfg(feature = "async_tokio")]
                 let mut net_dispatch = net_recv.lock().await;
 
-                let closed_dispatch = closed.lock().await;
-                macro_rules! recv_body {
-                    ($pk_recv: expr) => {
-                        #[cfg(feature = "async_std")]
-                        if let Err(_) = $pk_recv {
-                            rakrs_debug!(true, "[CLIENT] (recv_task) Failed to recieve anything on netowrk channel, is there a sender?");
-                            continue;
-                        }
-
-                        #[cfg(feature = "async_tokio")]
-                        if let None = $pk_recv {
-                            rakrs_debug!(true, "[CLIENT] (recv_task) Failed to recieve anything on netowrk channel, is there a sender?");
-                            continue;
-                        }
-
-                        recv_time.store(current_epoch(), std::sync::atomic::Ordering::Relaxed);
-
-                        rakrs_debug!(true, "[CLIENT] (recv_task) Recieved packet!");
-
-                        let mut client_state = state.lock().await;
-
-                        if *client_state == ConnectionState::TimingOut {
-                            rakrs_debug!(true, "[CLIENT] (recv_task) Client is no longer timing out!");
-                            *client_state = ConnectionState::Connected;
-                        }
-
-                        if *client_state == ConnectionState::Disconnecting {
-                            rakrs_debug!(true, "[CLIENT] (recv_task) Client is disconnecting!");
-                            break;
-                        }
-
-                        // drop here so the lock isn't held for too long
-                        drop(client_state);
-
-                        let mut buffer = ByteReader::from($pk_recv.unwrap());
-
-                        match buffer.as_slice()[0] {
-                            0x80..=0x8d => {
-                                if let Ok(frame_packet) = FramePacket::read(&mut buffer) {
-                                    let mut recv_q = recv_queue.lock().await;
-                                    if let Err(_) = recv_q.insert(frame_packet) {
-                                        rakrs_debug!(
-                                            true,
-                                            "[CLIENT] Failed to push frame packet into send queue."
-                                        );
-                                    }
-
-                                    let buffers = recv_q.flush();
-
-                                    'buf_loop: for pk_buf_raw in buffers {
-                                        let mut pk_buf = ByteReader::from(&pk_buf_raw[..]);
-                                        if let Ok(rak_packet) = RakPacket::read(&mut pk_buf) {
-                                            match rak_packet {
-                                                RakPacket::Online(pk) => {
-                                                    match pk {
-                                                        OnlinePacket::ConnectedPing(pk) => {
-                                                            let response = ConnectedPong {
-                                                                ping_time: pk.time,
-                                                                pong_time: current_epoch() as i64,
-                                                            };
-                                                            let mut q = send_queue.write().await;
-                                                            if let Err(_) = q
-                                                                .send_packet(
-                                                                    response.into(),
-                                                                    Reliability::Unreliable,
-                                                                    true,
-                                                                )
-                                                                .await
-                                                            {
-                                                                rakrs_debug!(
-                                                                    true,
-                                                                    "[CLIENT] Failed to send pong packet!"
-                                                                );
-                                                            }
-                                                            continue 'buf_loop;
-                                                        }
-                                                        OnlinePacket::ConnectedPong(_) => {
-                                                            // todo: add ping time to client
-                                                            rakrs_debug!(
-                                                                true,
-                                                                "[CLIENT] Recieved pong packet!"
-                                                            );
-                                                        }
-                                                        OnlinePacket::Disconnect(_) => {
-                                                            rakrs_debug!(
-                                                                true,
-                                                                "[CLIENT] Recieved disconnect packet!"
-                                                            );
-                                                            break 'task_loop;
-                                                        }
-                                                        _ => {
-                                                            rakrs_debug!(
-                                                                true,
-                                                                "[CLIENT] Processing fault packet... {:#?}",
-                                                                pk
-                                                            );
-
-                                                            if let Err(_) = internal_sender.send(pk_buf_raw).await {
-                                                                rakrs_debug!(true, "[CLIENT] Failed to send packet to internal recv channel. Is the client closed?");
-                                                            }
-                                                        }
-                                                    }
-                                                },
-                                                RakPacket::Offline(_) => {
-                                                    rakrs_debug!("[CLIENT] Recieved offline packet after handshake! In future versions this will kill the client.");
-                                                }
-                                            }
-                                        } else {
-                                            // we send this packet
-                                            if let Err(_) = internal_sender.send(pk_buf_raw).await {
-                                                rakrs_debug!(true, "[CLIENT] Failed to send packet to internal recv channel. Is the client closed?");
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                            NACK => {
-                                if let Ok(nack) = Ack::read(&mut buffer) {
-                                    let mut send_q = send_queue.write().await;
-                                    let to_resend = send_q.nack(nack);
-
-                                    if to_resend.len() > 0 {
-                                        for ack_packet in to_resend {
-                                            if let Ok(buffer) = ack_packet.write_to_bytes() {
-                                                if let Err(_) = send_q
-                                                    .insert(buffer.as_slice(), Reliability::Unreliable, true, Some(0))
-                                                    .await
-                                                {
-                                                    rakrs_debug!(
-                                                        true,
-                                                        "[CLIENT] Failed to insert ack packet into send queue!"
-                                                    );
-                                                }
-                                            } else {
-                                                rakrs_debug!(
-                                                    true,
-                                                    "[CLIENT] Failed to send packet to client (parsing failed)!",
-                                                );
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                            ACK => {
-                                if let Ok(ack) = Ack::read(&mut buffer) {
-                                    let mut send_q = send_queue.write().await;
-                                    send_q.ack(ack.clone());
-
-                                    drop(send_q);
-
-                                    recv_queue.lock().await.ack(ack);
-                                }
-                            }
-                            _ => {
-                                // we don't know what this is, so we're going to send it to the user, maybe
-                                // this is a custom packet
-                                if let Err(_) = internal_sender.send(buffer.as_slice().to_vec()).await {
-                                    rakrs_debug!(true, "[CLIENT] Failed to send packet to internal recv channel. Is the client closed?");
-                                }
-                            }
-                        }
-                    };
-                }
+                let closed_dispatch = &closed;
 
                 #[cfg(feature = "async_std")]
                 select! {
@@ -796,7 +1322,19 @@ impl Client {
                         }
                     }
                     pk_recv = net_dispatch.recv().fuse() => {
-                        recv_body!(pk_recv);
+                        match pk_recv {
+                            Ok(payload) => if !packets.datagram(payload).await {
+                                break;
+                            },
+                            Err(_) => {
+                                rakrs_debug!(true, "[CLIENT] (recv_task) Failed to recieve anything on netowrk channel, is there a sender?");
+                            }
+                        }
+                    }
+                    _ = released.recv().fuse() => {
+                        if !packets.release().await {
+                            break;
+                        }
                     }
                 }
 
@@ -809,7 +1347,19 @@ impl Client {
                         }
                     }
                     pk_recv = net_dispatch.recv() => {
-                        recv_body!(pk_recv);
+                        match pk_recv {
+                            Some(payload) => if !packets.datagram(payload).await {
+                                break;
+                            },
+                            None => {
+                                rakrs_debug!(true, "[CLIENT] (recv_task) Failed to recieve anything on netowrk channel, is there a sender?");
+                            }
+                        }
+                    }
+                    _ = released.recv() => {
+                        if !packets.release().await {
+                            break;
+                        }
                     }
                 }
             }
@@ -820,104 +1370,22 @@ impl Client {
     /// This is called by `Client::connect()`.
     fn init_connect_tick(
         &self,
-        send_queue: Arc<RwLock<SendQueue>>,
+        mut ticker: Ticker,
+        closer_dispatch: Arc<Notify>,
     ) -> Result<task::JoinHandle<()>, ClientError> {
-        // verify that the client is offline
-        let closer_dispatch = self.close_notifier.clone();
-        let recv_queue = self.recv_queue.clone();
-        let state = self.state.clone();
-        let last_recv = self.recv_time.clone();
-        let mut last_ping: u16 = 0;
-
         return Ok(task::spawn(async move {
             loop {
-                let closer = closer_dispatch.lock().await;
-
-                macro_rules! tick_body {
-                    () => {
-                        rakrs_debug!(true, "[CLIENT] Running connect tick task");
-                        let recv = last_recv.load(std::sync::atomic::Ordering::Relaxed);
-                        let mut state = state.lock().await;
-
-                        if *state == ConnectionState::Disconnected {
-                            rakrs_debug!(
-                                true,
-                                "[CLIENT] Client is disconnected. Closing connect tick task"
-                            );
-                            closer.notify().await;
-                            break;
-                        }
-
-                        if *state == ConnectionState::Connecting {
-                            rakrs_debug!(
-                                true,
-                                "[CLIENT] Client is not fully connected to the server yet."
-                            );
-                            continue;
-                        }
-
-                        if (recv + 20) <= current_epoch() {
-                            *state = ConnectionState::Disconnected;
-                            rakrs_debug!(true, "[CLIENT] Client timed out. Closing connection...");
-                            closer.notify().await;
-                            break;
-                        }
-
-                        let mut send_q = send_queue.write().await;
-                        let mut recv_q = recv_queue.lock().await;
-
-                        if recv + 10 <= current_epoch() && state.is_reliable() {
-                            *state = ConnectionState::TimingOut;
-                            rakrs_debug!(
-                                true,
-                                "[CLIENT] Connection is timing out, sending a ping!",
-                            );
-                            let ping = ConnectedPing {
-                                time: current_epoch() as i64,
-                            };
-                            if let Ok(_) = send_q
-                                .send_packet(ping.into(), Reliability::Reliable, true)
-                                .await
-                            {}
-                        }
-
-                        if last_ping >= 500 {
-                            let ping = ConnectedPing {
-                                time: current_epoch() as i64,
-                            };
-                            if let Ok(_) = send_q
-                                .send_packet(ping.into(), Reliability::Reliable, true)
-                                .await
-                            {}
-                            last_ping = 0;
-                        } else {
-                            last_ping += 50;
-                        }
-
-                        send_q.update().await;
-
-                        // Flush the queue of acks and nacks, and respond to them
-                        let ack = Ack::from_records(recv_q.ack_flush(), false);
-                        if ack.records.len() > 0 {
-                            if let Ok(p) = ack.write_to_bytes() {
-                                send_q.send_stream(p.as_slice()).await;
-                            }
-                        }
-
-                        // flush nacks from recv queue
-                        let nack = Ack::from_records(recv_q.nack_queue(), true);
-                        if nack.records.len() > 0 {
-                            if let Ok(p) = nack.write_to_bytes() {
-                                send_q.send_stream(p.as_slice()).await;
-                            }
-                        }
-                    };
-                }
+                let closer = &closer_dispatch;
+                let wait = ticker
+                    .next_tick()
+                    .saturating_duration_since(crate::util::now());
 
                 #[cfg(feature = "async_std")]
                 select! {
-                    _ = sleep(Duration::from_millis(50)).fuse() => {
-                        tick_body!();
+                    _ = sleep(wait).fuse() => {
+                        if !ticker.tick().await {
+                            break;
+                        }
                     },
                     killed = closer.wait().fuse() => {
                         if killed {
@@ -929,8 +1397,10 @@ impl Client {
 
                 #[cfg(feature = "async_tokio")]
                 select! {
-                    _ = sleep(Duration::from_millis(50)) => {
-                        tick_body!();
+                    _ = sleep(wait) => {
+                        if !ticker.tick().await {
+                            break;
+                        }
                     },
                     killed = closer.wait() => {
                         if killed {
@@ -944,9 +1414,139 @@ impl Client {
     }
 }
 
+/// Dropping a client stops its tasks right away, which releases its socket once they had
+/// their next turn.
+///
+/// This does not tell the server, which times the session out on its own. Prefer
+/// [`Client::close()`], dropping only guarantees nothing keeps running for a client that is gone.
 impl Drop for Client {
     fn drop(&mut self) {
-        // todo: There is DEFINITELY a better way to do this...
-        futures_executor::block_on(async move { self.close_notifier.lock().await.notify().await });
+        #[cfg(feature = "async_std")]
+        let state = self.state.try_lock();
+        #[cfg(feature = "async_tokio")]
+        let state = self.state.try_lock().ok();
+        if let Some(mut state) = state {
+            *state = ConnectionState::Disconnected;
+        }
+        if let Ok(close) = self.close.lock() {
+            close.0.close(DisconnectReason::Closed);
+        }
+        if let Ok(notifier) = self.close_notifier.lock() {
+            notifier.notify_now();
+        }
+    }
+}
+
+/// Pings the server at `addr` from a socket of its own, without connecting to it.
+///
+/// This is [`Client::ping()`] for when there is no client yet, like filling a server list.
+///
+/// # Example
+/// ```ignore
+/// if let Ok(pong) = rak_rs::ping("my_server.net:19132").await {
+///     println!("Server {} is online", pong.server_id);
+/// }
+/// ```
+pub async fn ping<Addr: for<'a> Into<PossiblySocketAddr<'a>>>(
+    addr: Addr,
+) -> Result<UnconnectedPong, ClientError> {
+    let addr: PossiblySocketAddr = addr.into();
+    let address = addr.to_socket_addr().ok_or(ClientError::AddrBindErr)?;
+
+    let socket = match UdpSocket::bind("0.0.0.0:0").await {
+        Ok(s) => s,
+        Err(e) => {
+            rakrs_debug!("Failed to bind to address: {}", e);
+            return Err(ClientError::SocketBindErr(e.kind()));
+        }
+    };
+    if socket.connect(address).await.is_err() {
+        return Err(ClientError::ServerOffline);
+    }
+
+    Client::ping(Arc::new(socket)).await
+}
+
+/// Broadcasts a ping to `port` on the local network, and returns every server that answered
+/// within `wait`, along with its pong. A server that answers more than once is listed once.
+///
+/// Fails with [`ClientError::Unavailable`] when the ping can't be broadcast at all,
+/// for instance on a host without a network to broadcast on.
+pub async fn discover_lan(
+    port: u16,
+    wait: Duration,
+) -> Result<Vec<(SocketAddr, UnconnectedPong)>, ClientError> {
+    let socket = match UdpSocket::bind("0.0.0.0:0").await {
+        Ok(s) => s,
+        Err(e) => {
+            rakrs_debug!("Failed to bind to address: {}", e);
+            return Err(ClientError::SocketBindErr(e.kind()));
+        }
+    };
+
+    let ping = RakPacket::from(UnconnectedPing {
+        timestamp: current_epoch(),
+        magic: Magic::new(),
+        client_id: rand::random::<i64>(),
+    })
+    .write_to_bytes()
+    .unwrap();
+    let broadcast = SocketAddr::new(IpAddr::V4(Ipv4Addr::BROADCAST), port);
+    if let Err(e) = socket.set_broadcast(true) {
+        rakrs_debug!("[CLIENT] Failed to enable broadcasts: {}", e);
+        return Err(ClientError::Unavailable);
+    }
+    if let Err(e) = socket.send_to(ping.as_slice(), broadcast).await {
+        rakrs_debug!("[CLIENT] Failed to broadcast ping packet: {}", e);
+        return Err(ClientError::Unavailable);
+    }
+
+    let mut buf: [u8; 2048] = [0; 2048];
+    let mut found: Vec<(SocketAddr, UnconnectedPong)> = Vec::new();
+    let deadline = Instant::now() + wait;
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        match timeout(left, socket.recv_from(&mut buf)).await {
+            Ok(Ok((l, from))) => {
+                if let Ok(RakPacket::Offline(OfflinePacket::UnconnectedPong(pong))) =
+                    RakPacket::read_from_slice(&buf[..l])
+                {
+                    if !found.iter().any(|(address, _)| *address == from) {
+                        found.push((from, pong));
+                    }
+                }
+            }
+            // an ICMP error of some host on the network, the others may still answer.
+            Ok(Err(_)) => {}
+            Err(_) => break,
+        }
+    }
+
+    Ok(found)
+}
+
+/// The connection attempt of a [`Client`], shared by every call that joins it.
+type Attempt = std::sync::Mutex<Option<Shared<oneshot::Receiver<Result<(), ClientError>>>>>;
+
+/// Clears the attempt of [`Client::connect()`] once it is done, also when it is dropped halfway.
+struct AttemptGuard<'a> {
+    attempt: &'a Attempt,
+    result: Option<oneshot::Sender<Result<(), ClientError>>>,
+}
+
+impl AttemptGuard<'_> {
+    /// Hands the result to every call that joined the attempt.
+    fn finish(mut self, result: Result<(), ClientError>) {
+        if let Some(sender) = self.result.take() {
+            let _ = sender.send(result);
+        }
+    }
+}
+
+impl Drop for AttemptGuard<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut attempt) = self.attempt.lock() {
+            *attempt = None;
+        }
     }
 }
@@ -0,0 +1,222 @@
+//! Opens many connections at once, for tools like stress testers, or proxies warming up their
+//! links to backend servers.
+//!
+//! ```rust ignore
+//! use rak_rs::client::pool::{connect_pool, PoolOptions};
+//!
+//! #[async_std::main]
+//! async fn main() {
+//!     let targets = vec!["10.0.0.1:19132".parse().unwrap(), "10.0.0.2:19132".parse().unwrap()];
+//!     let mut pool = connect_pool(targets, PoolOptions::new(10, 1400));
+//!
+//!     while let Some(outcome) = pool.next().await {
+//!         match outcome.result {
+//!             Ok(client) => println!("{} connected", outcome.target),
+//!             Err(e) => println!("{} failed: {:?}", outcome.target, e),
+//!         }
+//!     }
+//!     println!("{:?}", pool.summary());
+//! }
+//! ```
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "async_std")]
+use async_std::{
+    channel::{bounded, Receiver},
+    future::timeout,
+    task::{self, sleep},
+};
+#[cfg(feature = "async_tokio")]
+use tokio::{
+    sync::mpsc::{channel as bounded, Receiver},
+    task,
+    time::{sleep, timeout},
+};
+
+use crate::client::Client;
+use crate::connection::controller::jitter::RngProvider;
+use crate::connection::options::ConnOptions;
+use crate::error::client::ClientError;
+
+/// The default amount of handshakes a pool runs at once.
+pub const DEFAULT_POOL_CONCURRENCY: usize = 8;
+/// The default upper bound of the random delay before each handshake.
+pub const DEFAULT_POOL_STAGGER: Duration = Duration::from_millis(50);
+/// The default time a single target is given to connect, enough for every ping and
+/// every retransmission of the handshake.
+pub const DEFAULT_POOL_DEADLINE: Duration = Duration::from_secs(30);
+
+/// Options of [`connect_pool()`].
+#[derive(Debug, Clone)]
+pub struct PoolOptions {
+    /// The RakNet version of every client, see [`Client::new()`].
+    pub version: u8,
+    /// The MTU every client starts its discovery with, see [`Client::new()`].
+    pub mtu: u16,
+    /// The options every client connects with, see [`Client::with_options()`].
+    pub conn_options: ConnOptions,
+    /// How many handshakes may be in progress at once, at least one is.
+    pub concurrency: usize,
+    /// Every handshake waits a random time up to this before it starts, so handshakes that
+    /// would start together don't retransmit together either. The time is drawn like the
+    /// retry timers, see [`ConnOptions::rng_seed`].
+    pub stagger: Duration,
+    /// How long a single target may take to connect, the time it was staggered by aside.
+    /// A target that takes longer fails with [`ClientError::TimedOut`].
+    pub deadline: Duration,
+}
+
+impl PoolOptions {
+    pub fn new(version: u8, mtu: u16) -> Self {
+        Self {
+            version,
+            mtu,
+            conn_options: ConnOptions::default(),
+            concurrency: DEFAULT_POOL_CONCURRENCY,
+            stagger: DEFAULT_POOL_STAGGER,
+            deadline: DEFAULT_POOL_DEADLINE,
+        }
+    }
+}
+
+/// How connecting to a single target of a pool went.
+pub struct PoolOutcome {
+    pub target: SocketAddr,
+    /// The connected client, or why it couldn't connect.
+    pub result: Result<Client, ClientError>,
+    /// How long the handshake took, the time it was staggered by aside.
+    pub elapsed: Duration,
+}
+
+/// What happened to the targets of a pool, see [`ConnectPool::summary()`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PoolSummary {
+    /// The amount of targets that connected.
+    pub connected: usize,
+    /// The amount of targets that failed, every one of them for the reason in its outcome.
+    pub failed: usize,
+    /// The most handshakes that were in progress at once.
+    pub peak_handshakes: usize,
+    /// The time from the start of the pool until the last outcome it reported.
+    pub elapsed: Duration,
+}
+
+/// The connections a [`connect_pool()`] is establishing.
+///
+/// Dropping this doesn't stop the handshakes in progress, the clients they connect are
+/// dropped, and released, as soon as they are done.
+pub struct ConnectPool {
+    outcomes: Receiver<PoolOutcome>,
+    /// The amount of targets that didn't report an outcome yet.
+    remaining: usize,
+    started: Instant,
+    peak: Arc<AtomicUsize>,
+    summary: PoolSummary,
+}
+
+impl ConnectPool {
+    /// Waits for the next target to finish, in the order they finish in rather than the
+    /// order they were given in. Returns `None` once every target was reported.
+    pub async fn next(&mut self) -> Option<PoolOutcome> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        #[cfg(feature = "async_std")]
+        let outcome = self.outcomes.recv().await.ok()?;
+        #[cfg(feature = "async_tokio")]
+        let outcome = self.outcomes.recv().await?;
+
+        self.remaining -= 1;
+        match outcome.result {
+            Ok(_) => self.summary.connected += 1,
+            Err(_) => self.summary.failed += 1,
+        }
+        self.summary.peak_handshakes = self.peak.load(Ordering::Relaxed);
+        self.summary.elapsed = self.started.elapsed();
+        Some(outcome)
+    }
+
+    /// The amount of targets that are yet to be reported by [`ConnectPool::next()`].
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+
+    /// A summary of the outcomes reported so far, this is final once
+    /// [`ConnectPool::next()`] returned `None`.
+    pub fn summary(&self) -> &PoolSummary {
+        &self.summary
+    }
+}
+
+/// Connects a [`Client`] to every target, running at most [`PoolOptions::concurrency`]
+/// handshakes at once.
+///
+/// Every target is reported through [`ConnectPool::next()`] as soon as it connected or
+/// failed, a target that never answers holds up its own slot until its deadline, and
+/// nothing else.
+pub fn connect_pool(targets: Vec<SocketAddr>, options: PoolOptions) -> ConnectPool {
+    let remaining = targets.len();
+    let (outcome_send, outcomes) = bounded::<PoolOutcome>(remaining.max(1));
+    let queue = Arc::new(std::sync::Mutex::new(VecDeque::from(targets)));
+    let options = Arc::new(options);
+    let in_progress = Arc::new(AtomicUsize::new(0));
+    let peak = Arc::new(AtomicUsize::new(0));
+
+    for _ in 0..options.concurrency.max(1).min(remaining) {
+        let queue = queue.clone();
+        let options = options.clone();
+        let outcome_send = outcome_send.clone();
+        let in_progress = in_progress.clone();
+        let peak = peak.clone();
+
+        task::spawn(async move {
+            loop {
+                let target = match queue.lock().unwrap().pop_front() {
+                    Some(target) => target,
+                    None => break,
+                };
+
+                if !options.stagger.is_zero() {
+                    let mut rng = RngProvider::for_address(options.conn_options.rng_seed, target);
+                    sleep(rng.full_jitter(options.stagger)).await;
+                }
+
+                let handshakes = in_progress.fetch_add(1, Ordering::Relaxed) + 1;
+                peak.fetch_max(handshakes, Ordering::Relaxed);
+
+                let started = Instant::now();
+                let client = Client::new(options.version, options.mtu)
+                    .with_options(options.conn_options.clone());
+                let result = match timeout(options.deadline, client.connect(target)).await {
+                    Ok(Ok(())) => Ok(client),
+                    Ok(Err(e)) => Err(e),
+                    Err(_) => Err(ClientError::TimedOut),
+                };
+                in_progress.fetch_sub(1, Ordering::Relaxed);
+
+                let outcome = PoolOutcome {
+                    target,
+                    result,
+                    elapsed: started.elapsed(),
+                };
+                // the pool was dropped, nobody wants the rest.
+                if outcome_send.send(outcome).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    ConnectPool {
+        outcomes,
+        remaining,
+        started: Instant::now(),
+        peak,
+        summary: PoolSummary::default(),
+    }
+}
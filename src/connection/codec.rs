@@ -0,0 +1,239 @@
+//! Typed messages over a [`Connection`], for applications that layer their own protocol
+//! over the raw payloads.
+//!
+//! [`Encoder`] and [`Decoder`] mirror the traits of `tokio-util`, without depending on it,
+//! so they work the same on both runtimes. A [`Framed`] connection encodes every item into
+//! a single payload, and decodes every payload it receives on its own: RakNet already keeps
+//! the boundaries of payloads, so a payload holding half an item is an error rather than
+//! something to wait on.
+//!
+//! Payloads starting with an id RakNet uses itself would be taken for protocol packets, so
+//! every payload starts with the id of the adapter, [`Framed::id`], which the codec never sees.
+//!
+//! ```ignore
+//! use rak_rs::connection::codec::LengthDelimited;
+//!
+//! async fn echo(conn: Connection) {
+//!     let mut framed = conn.framed(LengthDelimited::new());
+//!     while let Ok(item) = framed.recv().await {
+//!         match item {
+//!             Ok(message) => framed.send(message).await.unwrap(),
+//!             // the connection is still usable after a malformed payload.
+//!             Err(e) => println!("Dropped a malformed payload: {}", e),
+//!         }
+//!     }
+//! }
+//! ```
+use std::io::{Error, ErrorKind};
+
+use crate::protocol::reliability::Reliability;
+
+use super::{queue::SendQueueError, Connection, RecvError};
+
+/// Turns items into the bytes of a payload.
+pub trait Encoder<Item> {
+    type Error: From<Error>;
+
+    /// Appends the encoded item to `dst`.
+    fn encode(&mut self, item: Item, dst: &mut Vec<u8>) -> Result<(), Self::Error>;
+}
+
+/// Turns the bytes of a payload back into items.
+pub trait Decoder {
+    type Item;
+    type Error: From<Error>;
+
+    /// Decodes an item from the front of `src`, removing the bytes it used.
+    ///
+    /// Returns `Ok(None)` when `src` doesn't hold a complete item.
+    fn decode(&mut self, src: &mut Vec<u8>) -> Result<Option<Self::Item>, Self::Error>;
+
+    /// Decodes an item when no more bytes will follow, by default anything left over that
+    /// doesn't make up an item is an error.
+    fn decode_eof(&mut self, src: &mut Vec<u8>) -> Result<Option<Self::Item>, Self::Error> {
+        match self.decode(src)? {
+            Some(item) => Ok(Some(item)),
+            None if src.is_empty() => Ok(None),
+            None => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("{} bytes remaining in the payload", src.len()),
+            )
+            .into()),
+        }
+    }
+}
+
+/// Items prefixed with their length, as a big endian `u32`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LengthDelimited {
+    /// Longer items are an error, both ways. Defaults to 8 MiB.
+    pub max_length: usize,
+}
+
+impl LengthDelimited {
+    pub fn new() -> Self {
+        Self {
+            max_length: 8 * 1024 * 1024,
+        }
+    }
+
+    fn check(&self, length: usize) -> Result<(), Error> {
+        if length > self.max_length {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "item of {} bytes exceeds the maximum of {}",
+                    length, self.max_length
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Default for LengthDelimited {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: AsRef<[u8]>> Encoder<T> for LengthDelimited {
+    type Error = Error;
+
+    fn encode(&mut self, item: T, dst: &mut Vec<u8>) -> Result<(), Error> {
+        let item = item.as_ref();
+        self.check(item.len())?;
+        dst.extend_from_slice(&(item.len() as u32).to_be_bytes());
+        dst.extend_from_slice(item);
+        Ok(())
+    }
+}
+
+impl Decoder for LengthDelimited {
+    type Item = Vec<u8>;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut Vec<u8>) -> Result<Option<Vec<u8>>, Error> {
+        if src.len() < 4 {
+            return Ok(None);
+        }
+        let length = u32::from_be_bytes([src[0], src[1], src[2], src[3]]) as usize;
+        self.check(length)?;
+        if src.len() < 4 + length {
+            return Ok(None);
+        }
+        let item = src[4..4 + length].to_vec();
+        src.drain(..4 + length);
+        Ok(Some(item))
+    }
+}
+
+/// Why a [`Framed`] connection failed to send or receive an item.
+#[derive(Debug)]
+pub enum FramedError<E> {
+    /// The codec failed, the connection is still usable.
+    Codec(E),
+    /// The payload couldn't be queued.
+    Send(SendQueueError),
+}
+
+/// A [`Connection`] sending and receiving the items of a codec, see [`Connection::framed()`].
+pub struct Framed<C> {
+    conn: Connection,
+    codec: C,
+    /// What is left of the last payload, after the items returned so far.
+    pending: Vec<u8>,
+    /// The reliability every item is sent with, `ReliableOrd` by default.
+    pub reliability: Reliability,
+    /// The channel every item is sent on, 0 by default.
+    pub channel: u8,
+    /// The id every payload starts with, `0xfe` by default.
+    pub id: u8,
+}
+
+impl<C> Framed<C> {
+    pub(crate) fn new(conn: Connection, codec: C) -> Self {
+        Self {
+            conn,
+            codec,
+            pending: Vec::new(),
+            reliability: Reliability::ReliableOrd,
+            channel: 0,
+            id: 0xfe,
+        }
+    }
+
+    /// Encodes the item into a single payload and sends it.
+    pub async fn send<Item>(
+        &mut self,
+        item: Item,
+    ) -> Result<(), FramedError<<C as Encoder<Item>>::Error>>
+    where
+        C: Encoder<Item>,
+    {
+        let mut payload = vec![self.id];
+        self.codec
+            .encode(item, &mut payload)
+            .map_err(FramedError::Codec)?;
+        self.conn
+            .send_with(&payload, self.reliability, self.channel, false)
+            .await
+            .map_err(FramedError::Send)
+    }
+
+    /// Receives the next item.
+    ///
+    /// A payload with another id, or that fails to decode, is returned as `Ok(Err(..))` and
+    /// dropped, the next call continues with the payload after it. `Err` is only returned
+    /// once the connection is closed.
+    pub async fn recv(&mut self) -> Result<Result<C::Item, <C as Decoder>::Error>, RecvError>
+    where
+        C: Decoder,
+    {
+        loop {
+            // empty payloads never hold an item.
+            while self.pending.is_empty() {
+                self.pending = self.conn.recv().await?;
+                match self.pending.first() {
+                    Some(id) if *id == self.id => {
+                        self.pending.remove(0);
+                    }
+                    Some(id) => {
+                        let e = Error::new(
+                            ErrorKind::InvalidData,
+                            format!("payload starts with {:#04x}, not {:#04x}", id, self.id),
+                        );
+                        self.pending.clear();
+                        return Ok(Err(e.into()));
+                    }
+                    None => {}
+                }
+            }
+            match self.codec.decode_eof(&mut self.pending) {
+                Ok(Some(item)) => return Ok(Ok(item)),
+                Ok(None) => self.pending.clear(),
+                Err(e) => {
+                    self.pending.clear();
+                    return Ok(Err(e));
+                }
+            }
+        }
+    }
+
+    pub fn codec(&self) -> &C {
+        &self.codec
+    }
+
+    pub fn codec_mut(&mut self) -> &mut C {
+        &mut self.codec
+    }
+
+    pub fn get_ref(&self) -> &Connection {
+        &self.conn
+    }
+
+    /// Gives the connection back, dropping anything left of the last payload.
+    pub fn into_inner(self) -> Connection {
+        self.conn
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+/// A send rate in bytes per second.
+pub type BytesPerSec = u64;
+
+/// The default amount of bytes immediate frames may borrow from an empty budget.
+pub const DEFAULT_SEND_OVERDRAFT: u64 = 16 * 1024;
+
+/// How much of the budget can be spent in a single burst, this is one connection tick.
+pub const BUDGET_BURST: Duration = Duration::from_millis(50);
+
+/// How long a connection counts towards the fair share after it last asked to send.
+const ACTIVE_WINDOW: Duration = Duration::from_millis(250);
+
+/// How the budget has been used since it was created.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SendBudgetStats {
+    /// The budget, in bytes per second.
+    pub budget: BytesPerSec,
+    /// The total amount of bytes sent.
+    pub sent: u64,
+    /// The ratio (`0.0` to `1.0`, or more while in overdraft) of the budget that was used.
+    pub utilization: f32,
+    /// How long connections had to wait for the budget to refill.
+    pub throttled: Duration,
+}
+
+/// The state of a single connection drawing from a [`SendBudget`].
+#[derive(Debug, Clone, Copy)]
+struct Share {
+    /// The bytes the connection may still send while the budget is contended,
+    /// this goes negative when a datagram overshoots the grant.
+    deficit: f64,
+    /// The last time the connection asked to send.
+    seen: Instant,
+}
+
+/// A token bucket that limits the bytes sent by many connections together.
+///
+/// While the bucket has never run dry recently, every connection may spend whatever is in it.
+/// Once it has, the budget is contended, and each tick a connection is granted an equal share
+/// of what the bucket refills per tick, deficit round robin style: a connection that overshoots
+/// its grant with a large datagram gets that much less on the next tick. This keeps the
+/// connections fair regardless of the order their ticks run in.
+///
+/// This struct does not do any IO, it only decides how much may be sent.
+#[derive(Debug, Clone)]
+pub struct SendBudget {
+    /// The bytes added to the bucket every second.
+    rate: BytesPerSec,
+    /// The most the bucket can hold.
+    capacity: f64,
+    /// How far below empty immediate frames may take the bucket.
+    overdraft: f64,
+    tokens: f64,
+    refilled: Instant,
+    /// The last time the bucket ran dry.
+    exhausted: Option<Instant>,
+    shares: HashMap<u64, Share>,
+    next_id: u64,
+    started: Instant,
+    sent: u64,
+    throttled: Duration,
+    throttled_since: Option<Instant>,
+}
+
+impl SendBudget {
+    pub fn new(rate: BytesPerSec, overdraft: u64, now: Instant) -> Self {
+        let capacity = (rate as f64 * BUDGET_BURST.as_secs_f64()).max(1.0);
+        Self {
+            rate,
+            capacity,
+            overdraft: overdraft as f64,
+            tokens: capacity,
+            refilled: now,
+            exhausted: None,
+            shares: HashMap::new(),
+            next_id: 0,
+            started: now,
+            sent: 0,
+            throttled: Duration::ZERO,
+            throttled_since: None,
+        }
+    }
+
+    /// Hands out an id for a connection that draws from this budget.
+    pub fn register(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.refilled);
+        self.refilled = self.refilled.max(now);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate as f64).min(self.capacity);
+    }
+
+    /// Whether the bucket ran dry recently, and connections only get their share of it.
+    pub fn is_contended(&self, now: Instant) -> bool {
+        matches!(self.exhausted, Some(at) if now.saturating_duration_since(at) < ACTIVE_WINDOW)
+    }
+
+    /// The amount of bytes the connection may send this tick, it should only ask when it has
+    /// something to send. The connection may overshoot this with its last datagram.
+    pub fn grant(&mut self, id: u64, now: Instant) -> usize {
+        self.refill(now);
+        self.shares.retain(|other, share| {
+            *other == id || now.saturating_duration_since(share.seen) < ACTIVE_WINDOW
+        });
+        let active = self.shares.len() + usize::from(!self.shares.contains_key(&id));
+        let quantum = self.capacity / active as f64;
+        let contended = self.is_contended(now);
+        let share = self.shares.entry(id).or_insert(Share {
+            deficit: 0.0,
+            seen: now,
+        });
+        share.seen = now;
+
+        let grant = if contended {
+            share.deficit = (share.deficit + quantum).min(self.capacity);
+            share.deficit.min(self.tokens)
+        } else {
+            share.deficit = 0.0;
+            self.tokens
+        };
+
+        if grant <= 0.0 {
+            self.exhausted = Some(now);
+            if self.throttled_since.is_none() {
+                self.throttled_since = Some(now);
+            }
+            return 0;
+        }
+
+        if let Some(since) = self.throttled_since.take() {
+            self.throttled += now.saturating_duration_since(since);
+        }
+        grant.ceil() as usize
+    }
+
+    /// Takes bytes that were sent with a grant from the bucket.
+    pub fn spend(&mut self, id: u64, bytes: usize, now: Instant) {
+        self.refill(now);
+        self.take(bytes, now);
+        if let Some(share) = self.shares.get_mut(&id) {
+            share.deficit -= bytes as f64;
+        }
+    }
+
+    /// Takes bytes for an immediate frame, which may borrow up to the overdraft.
+    /// Returns `false` if the frame would exceed it, and should wait for a grant.
+    pub fn borrow(&mut self, bytes: usize, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens - bytes as f64 >= -self.overdraft {
+            self.take(bytes, now);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn take(&mut self, bytes: usize, now: Instant) {
+        self.tokens -= bytes as f64;
+        self.sent += bytes as u64;
+        if self.tokens <= 0.0 {
+            self.exhausted = Some(now);
+        }
+    }
+
+    pub fn stats(&self, now: Instant) -> SendBudgetStats {
+        let elapsed = now.saturating_duration_since(self.started).as_secs_f64();
+        let throttled = match self.throttled_since {
+            Some(since) => self.throttled + now.saturating_duration_since(since),
+            None => self.throttled,
+        };
+        SendBudgetStats {
+            budget: self.rate,
+            sent: self.sent,
+            utilization: if elapsed > 0.0 && self.rate > 0 {
+                (self.sent as f64 / (self.rate as f64 * elapsed)) as f32
+            } else {
+                0.0
+            },
+            throttled,
+        }
+    }
+}
+
+/// A connection's handle on a [`SendBudget`] shared with other connections.
+#[derive(Debug, Clone)]
+pub struct BudgetShare {
+    budget: Arc<Mutex<SendBudget>>,
+    id: u64,
+}
+
+impl BudgetShare {
+    pub fn new(budget: Arc<Mutex<SendBudget>>) -> Self {
+        let id = budget
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .register();
+        Self { budget, id }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SendBudget> {
+        self.budget.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn grant(&self, now: Instant) -> usize {
+        self.lock().grant(self.id, now)
+    }
+
+    pub fn spend(&self, bytes: usize, now: Instant) {
+        self.lock().spend(self.id, bytes, now)
+    }
+
+    pub fn borrow(&self, bytes: usize, now: Instant) -> bool {
+        self.lock().borrow(bytes, now)
+    }
+}
@@ -0,0 +1,129 @@
+/// The bit in the datagram id that marks a datagram as carrying a checksum trailer.
+/// RakNet never sets this bit, so a vanilla peer can't be mistaken for one that checksums.
+pub const CHECKSUM_FLAG: u8 = 0x01;
+
+/// The length of the checksum trailer.
+pub const CHECKSUM_LEN: usize = 4;
+
+/// The amount of corrupt datagrams after which the path is reported as damaging traffic.
+pub const CORRUPT_DATAGRAM_THRESHOLD: u64 = 8;
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// The CRC32 (IEEE) of the given bytes.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc = CRC32_TABLE[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+/// Marks the datagram as checksummed and appends the checksum of everything before it.
+pub fn seal(datagram: &mut Vec<u8>) {
+    if let Some(id) = datagram.first_mut() {
+        *id |= CHECKSUM_FLAG;
+    }
+    let crc = crc32(datagram);
+    datagram.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// What should happen to a datagram that was checked by the [`ChecksumGuard`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumVerdict {
+    /// The datagram can be decoded.
+    Valid {
+        /// The length of the datagram without its trailer.
+        len: usize,
+    },
+    /// The datagram was damaged in transit, and should be dropped without recording its sequence.
+    Corrupt,
+    /// Like [`ChecksumVerdict::Corrupt`], and the path just crossed [`CORRUPT_DATAGRAM_THRESHOLD`].
+    /// This is only returned once.
+    Damaging,
+}
+
+/// Verifies the checksum trailers of the frame-set datagrams a peer sends.
+///
+/// Datagrams without the checksum flag are accepted until the peer sent its first valid
+/// checksummed datagram, after that the flag itself is expected on every datagram.
+///
+/// This struct does not do any IO, it only decides what to do with each datagram.
+#[derive(Debug, Clone, Default)]
+pub struct ChecksumGuard {
+    /// Whether trailers are verified at all.
+    enabled: bool,
+    /// Whether the peer has been seen checksumming its datagrams.
+    required: bool,
+    /// The total amount of corrupt datagrams.
+    corrupt: u64,
+}
+
+impl ChecksumGuard {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            required: false,
+            corrupt: 0,
+        }
+    }
+
+    /// Whether trailers are verified at all.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// The total amount of datagrams that were dropped because they were corrupt.
+    pub fn corrupt(&self) -> u64 {
+        self.corrupt
+    }
+
+    /// Checks the given frame-set datagram.
+    pub fn verify(&mut self, datagram: &[u8]) -> ChecksumVerdict {
+        let flagged = matches!(datagram.first(), Some(id) if id & CHECKSUM_FLAG != 0);
+
+        if !self.enabled || (!flagged && !self.required) {
+            return ChecksumVerdict::Valid {
+                len: datagram.len(),
+            };
+        }
+
+        if flagged && datagram.len() > CHECKSUM_LEN {
+            let len = datagram.len() - CHECKSUM_LEN;
+            let mut trailer = [0u8; CHECKSUM_LEN];
+            trailer.copy_from_slice(&datagram[len..]);
+
+            if crc32(&datagram[..len]) == u32::from_be_bytes(trailer) {
+                self.required = true;
+                return ChecksumVerdict::Valid { len };
+            }
+        }
+
+        self.corrupt += 1;
+        if self.corrupt == CORRUPT_DATAGRAM_THRESHOLD {
+            ChecksumVerdict::Damaging
+        } else {
+            ChecksumVerdict::Corrupt
+        }
+    }
+}
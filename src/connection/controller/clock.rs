@@ -0,0 +1,101 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// A point in time on the clock of a peer, in milliseconds, as carried by
+/// [`ConnectedPing`] and [`ConnectedPong`].
+///
+/// rak-rs peers count from the unix epoch, other implementations may count from whenever
+/// they started, so these are only comparable with timestamps of the same peer.
+///
+/// [`ConnectedPing`]: crate::protocol::packet::online::ConnectedPing
+/// [`ConnectedPong`]: crate::protocol::packet::online::ConnectedPong
+pub type RakTime = i64;
+
+/// How many of the latest ping/pong pairs the offset is estimated from.
+pub const CLOCK_SAMPLES: usize = 8;
+
+/// How often a ping rides along for the clock estimate when the connection is busy
+/// enough to never need a keepalive.
+pub const CLOCK_PING_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Pongs that took longer than this to come back say next to nothing about the clock of
+/// the peer, and are most likely not an answer to a ping of ours at all.
+pub const MAX_CLOCK_RTT: RakTime = 10_000;
+
+/// The current time on the clock of this side.
+pub fn rak_time_now() -> RakTime {
+    crate::util::since_epoch().as_millis() as RakTime
+}
+
+/// Estimates how far the clock of the peer is ahead of ours, NTP style.
+///
+/// Every pong tells when the peer answered a ping of ours, assuming the answer was sent
+/// halfway through the round trip the sample is `pong_time - (ping_time + rtt / 2)`.
+/// The estimate is the average of the latest [`CLOCK_SAMPLES`], weighted by the inverse of
+/// their round trip, a short round trip leaves less room for error.
+///
+/// The halfway assumption is where the error comes from: a sample is off by half the
+/// difference between the way there and the way back, which can't be measured. On a link
+/// that is slower in one direction the estimate is off by up to half of that difference,
+/// no matter how many samples were taken.
+#[derive(Debug, Clone, Default)]
+pub struct ClockEstimator {
+    /// The offset and round trip of the latest samples, in milliseconds.
+    samples: VecDeque<(RakTime, RakTime)>,
+    last_ping: Option<Instant>,
+}
+
+impl ClockEstimator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds the pong to a ping sent at `ping_time`, answered at `pong_time` on the clock
+    /// of the peer and received `now`. Returns whether the pong was taken as a sample.
+    pub fn sample(&mut self, ping_time: RakTime, pong_time: RakTime, now: RakTime) -> bool {
+        let rtt = now - ping_time;
+        if !(0..=MAX_CLOCK_RTT).contains(&rtt) {
+            return false;
+        }
+
+        if self.samples.len() == CLOCK_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples
+            .push_back((pong_time - (ping_time + rtt / 2), rtt));
+        true
+    }
+
+    /// How many milliseconds the clock of the peer is ahead of ours,
+    /// `None` until the first pong was received.
+    pub fn offset(&self) -> Option<RakTime> {
+        if self.samples.is_empty() {
+            return None;
+        }
+
+        let (mut sum, mut weights) = (0.0, 0.0);
+        for (offset, rtt) in self.samples.iter() {
+            // a round trip under a millisecond is as good as it gets.
+            let weight = 1.0 / (*rtt).max(1) as f64;
+            sum += *offset as f64 * weight;
+            weights += weight;
+        }
+        Some((sum / weights).round() as RakTime)
+    }
+
+    /// The current time on the clock of the peer, `None` until the first pong was received.
+    pub fn peer_time_now(&self) -> Option<RakTime> {
+        self.offset().map(|offset| rak_time_now() + offset)
+    }
+
+    /// Whether a ping should go out so the estimate keeps up, see [`CLOCK_PING_INTERVAL`].
+    pub fn ping_due(&self, now: Instant) -> bool {
+        self.last_ping
+            .is_none_or(|last| now.saturating_duration_since(last) >= CLOCK_PING_INTERVAL)
+    }
+
+    /// Called whenever a ping goes out, be it for the estimate or as a keepalive.
+    pub fn ping_sent(&mut self, now: Instant) {
+        self.last_ping = Some(now);
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+
+/// The id of a message carrying several messages of the peer, see [`Coalescer`].
+///
+/// Only used once both peers agreed to [`Capabilities::COALESCING`], a message of the
+/// application starting with this id is then sent as a bundle of one, so it is never
+/// mistaken for one.
+///
+/// [`Capabilities::COALESCING`]: crate::protocol::packet::online::Capabilities::COALESCING
+pub const COALESCED_ID: u8 = 0x7f;
+
+/// The bytes a bundle adds: its id, and the length of every message in it.
+const BUNDLE_HEADER: usize = 1;
+const LENGTH_PREFIX: usize = 2;
+
+/// Merges the small reliable-ordered messages queued on a channel until the next tick into a
+/// single message, which saves a frame header and a recovery entry for every one of them.
+///
+/// A bundle is [`COALESCED_ID`] followed by every message prefixed with its big endian `u16`
+/// length. The receiving side splits it back with [`split()`], the messages are delivered one
+/// by one and in order, as if they had been sent on their own.
+#[derive(Debug, Clone, Default)]
+pub struct Coalescer {
+    channels: Vec<u8>,
+    enabled: bool,
+    bundles: BTreeMap<u8, Bundle>,
+}
+
+#[derive(Debug, Clone)]
+struct Bundle {
+    body: Vec<u8>,
+    messages: usize,
+}
+
+impl Coalescer {
+    /// Coalesces the messages on `channels`, once the peer agreed to it.
+    pub fn new(channels: Vec<u8>) -> Self {
+        Self {
+            channels,
+            ..Self::default()
+        }
+    }
+
+    /// The channels messages are coalesced on.
+    pub fn channels(&self) -> &[u8] {
+        &self.channels
+    }
+
+    /// Sets whether the peer agreed to split bundles.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Whether the peer agreed to split bundles, messages starting with [`COALESCED_ID`]
+    /// have to be wrapped then, see [`Coalescer::wrap()`].
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Whether a reliable-ordered message on `channel` that is not sent right away
+    /// is coalesced.
+    pub fn coalesces(&self, channel: u8) -> bool {
+        self.enabled && self.channels.contains(&channel)
+    }
+
+    /// Adds `message` to the bundle of `channel`, bundles are kept to `max` bytes.
+    ///
+    /// Returns the bundle that had no room left for the message, it has to be queued before
+    /// anything else on the channel. A message too large to share a bundle is given back as
+    /// `Err`, it is sent on its own.
+    pub fn push<'a>(
+        &mut self,
+        channel: u8,
+        message: &'a [u8],
+        max: usize,
+    ) -> Result<Option<Vec<u8>>, &'a [u8]> {
+        if BUNDLE_HEADER + LENGTH_PREFIX + message.len() > max || message.len() > u16::MAX as usize
+        {
+            return Err(message);
+        }
+
+        let full = match self.bundles.get(&channel) {
+            Some(bundle) if bundle.body.len() + LENGTH_PREFIX + message.len() > max => {
+                self.take(channel)
+            }
+            _ => None,
+        };
+
+        let bundle = self.bundles.entry(channel).or_insert_with(|| Bundle {
+            body: vec![COALESCED_ID],
+            messages: 0,
+        });
+        bundle
+            .body
+            .extend_from_slice(&(message.len() as u16).to_be_bytes());
+        bundle.body.extend_from_slice(message);
+        bundle.messages += 1;
+        Ok(full)
+    }
+
+    /// Takes the bundle of `channel`, a bundle of a single message is unwrapped unless
+    /// the message has to stay wrapped.
+    pub fn take(&mut self, channel: u8) -> Option<Vec<u8>> {
+        let bundle = self.bundles.remove(&channel)?;
+        let first = BUNDLE_HEADER + LENGTH_PREFIX;
+        if bundle.messages == 1 && bundle.body.get(first) != Some(&COALESCED_ID) {
+            return Some(bundle.body[first..].to_vec());
+        }
+        Some(bundle.body)
+    }
+
+    /// Takes the bundles of every channel.
+    pub fn take_all(&mut self) -> Vec<(u8, Vec<u8>)> {
+        let channels = self.bundles.keys().copied().collect::<Vec<u8>>();
+        channels
+            .into_iter()
+            .filter_map(|channel| Some((channel, self.take(channel)?)))
+            .collect()
+    }
+
+    /// The amount of bundles waiting for the next tick.
+    pub fn pending(&self) -> usize {
+        self.bundles.len()
+    }
+
+    /// A bundle of `message` alone, `None` if it is too large for a bundle.
+    pub fn wrap(message: &[u8]) -> Option<Vec<u8>> {
+        if message.len() > u16::MAX as usize {
+            return None;
+        }
+        let mut bundle = Vec::with_capacity(BUNDLE_HEADER + LENGTH_PREFIX + message.len());
+        bundle.push(COALESCED_ID);
+        bundle.extend_from_slice(&(message.len() as u16).to_be_bytes());
+        bundle.extend_from_slice(message);
+        Some(bundle)
+    }
+}
+
+/// The messages of a bundle, `None` if `bundle` is not one.
+pub fn split(bundle: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let mut rest = bundle.strip_prefix(&[COALESCED_ID])?;
+    let mut messages = Vec::new();
+
+    while !rest.is_empty() {
+        if rest.len() < LENGTH_PREFIX {
+            return None;
+        }
+        let len = u16::from_be_bytes([rest[0], rest[1]]) as usize;
+        rest = &rest[LENGTH_PREFIX..];
+        if rest.len() < len {
+            return None;
+        }
+        messages.push(rest[..len].to_vec());
+        rest = &rest[len..];
+    }
+
+    Some(messages)
+}
@@ -0,0 +1,63 @@
+use std::time::Duration;
+
+/// The default time a message may wait for the application to make room for it.
+pub const DEFAULT_CONSUMER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Keeps track of whether the application still receives the messages of a connection.
+///
+/// A message waits at most the timeout for room in the channel the application receives
+/// from. Once one didn't get it, the application is considered gone: messages are dropped
+/// right away instead, so the connection keeps acknowledging, pinging and timing out as
+/// usual. As soon as a message fits again, the application is back and delivery resumes.
+///
+/// This struct does not do any IO, it only decides how long to wait.
+#[derive(Debug, Clone)]
+pub struct ConsumerGuard {
+    /// How long a message may wait for room, `None` waits for as long as it takes.
+    timeout: Option<Duration>,
+    /// Whether the application stopped receiving.
+    gone: bool,
+    /// The total amount of messages that were dropped.
+    dropped: u64,
+}
+
+impl ConsumerGuard {
+    pub fn new(timeout: Option<Duration>) -> Self {
+        Self {
+            timeout,
+            gone: false,
+            dropped: 0,
+        }
+    }
+
+    /// How long the next message may wait for room, `None` waits for as long as it takes.
+    /// This is zero while the application is gone.
+    pub fn wait(&self) -> Option<Duration> {
+        match self.gone {
+            true => Some(Duration::ZERO),
+            false => self.timeout,
+        }
+    }
+
+    /// Records a delivered message, this returns whether the application just came back.
+    pub fn delivered(&mut self) -> bool {
+        std::mem::replace(&mut self.gone, false)
+    }
+
+    /// Records a message that found no room, this returns whether the application was
+    /// just considered gone. This is only returned once, until it comes back.
+    pub fn dropped(&mut self) -> bool {
+        self.dropped += 1;
+        !std::mem::replace(&mut self.gone, true)
+    }
+
+    /// Whether the application stopped receiving.
+    pub fn is_gone(&self) -> bool {
+        self.gone
+    }
+
+    /// The total amount of messages that were dropped.
+    pub fn dropped_count(&self) -> u64 {
+        self.dropped
+    }
+}
@@ -0,0 +1,165 @@
+use std::time::{Duration, Instant};
+
+/// The default window the unknown packet ratio is measured over.
+pub const DEFAULT_UNKNOWN_FLOOD_WINDOW: Duration = Duration::from_secs(5);
+
+/// The first id RakNet leaves to applications (`ID_USER_PACKET_ENUM`), game packets like `0xfe`
+/// are in this range. These ids are claimed by default, they are never treated as unknown.
+pub const USER_PACKET_ID: u8 = 0x86;
+
+/// The least amount of packets within the window before the ratio is trusted,
+/// otherwise a single unknown packet on a quiet connection would count as a flood.
+const MIN_FLOOD_SAMPLES: u64 = 32;
+
+/// The amount of buckets the window is split in. Packets are counted per bucket rather than
+/// one by one, so the guard takes the same memory however fast the peer sends.
+const FLOOD_BUCKETS: usize = 8;
+
+/// What should happen to a packet that was observed by the [`UnknownFloodGuard`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FloodVerdict {
+    /// The packet should be handed to the application.
+    Deliver,
+    /// The packet should be dropped silently.
+    Suppress,
+    /// The packet should be dropped, and the connection just started suppressing.
+    /// This is only returned once per flood.
+    Flooded,
+}
+
+/// Keeps track of how many packets with an unknown id a peer sends compared to
+/// everything else it sends.
+///
+/// Once the ratio of unknown packets exceeds the threshold within the sliding window,
+/// unknown packets are suppressed until the ratio falls back below the threshold.
+/// The window slides a bucket at a time, an eighth of the window.
+/// Ids claimed by the application, and every id from [`USER_PACKET_ID`] up, are never
+/// considered unknown.
+///
+/// This struct does not do any IO, it only decides what to do with each packet.
+#[derive(Debug, Clone)]
+pub struct UnknownFloodGuard {
+    /// The ratio of unknown packets that is considered a flood, `None` disables the guard.
+    threshold: Option<f32>,
+    /// How far back packets are considered.
+    window: Duration,
+    /// The ids the application handles itself.
+    claimed: Vec<u8>,
+    /// When the first packet was recorded, the buckets are counted from here.
+    origin: Option<Instant>,
+    /// The packets counted per bucket of the window, a ring indexed by the bucket number.
+    buckets: [FloodBucket; FLOOD_BUCKETS],
+    /// Whether we are currently suppressing unknown packets.
+    suppressing: bool,
+    /// The total amount of packets that were suppressed.
+    suppressed: u64,
+}
+
+impl UnknownFloodGuard {
+    pub fn new(threshold: Option<f32>, window: Duration, claimed: &[u8]) -> Self {
+        Self {
+            threshold,
+            window,
+            claimed: claimed.to_vec(),
+            origin: None,
+            buckets: [FloodBucket::default(); FLOOD_BUCKETS],
+            suppressing: false,
+            suppressed: 0,
+        }
+    }
+
+    /// Whether the application has claimed the given id.
+    pub fn is_claimed(&self, id: u8) -> bool {
+        id >= USER_PACKET_ID || self.claimed.contains(&id)
+    }
+
+    /// Whether unknown packets are currently being suppressed.
+    pub fn is_suppressing(&self) -> bool {
+        self.suppressing
+    }
+
+    /// The total amount of unknown packets that have been dropped.
+    pub fn suppressed(&self) -> u64 {
+        self.suppressed
+    }
+
+    /// Records a packet that was understood by the connection.
+    pub fn known(&mut self, now: Instant) {
+        self.record(false, now);
+    }
+
+    /// Records a packet with an id the connection does not understand.
+    pub fn unknown(&mut self, id: u8, now: Instant) -> FloodVerdict {
+        if self.is_claimed(id) {
+            self.record(false, now);
+            return FloodVerdict::Deliver;
+        }
+
+        let was_suppressing = self.suppressing;
+        self.record(true, now);
+
+        match (was_suppressing, self.suppressing) {
+            (_, false) => FloodVerdict::Deliver,
+            (false, true) => {
+                self.suppressed += 1;
+                FloodVerdict::Flooded
+            }
+            (true, true) => {
+                self.suppressed += 1;
+                FloodVerdict::Suppress
+            }
+        }
+    }
+
+    fn record(&mut self, unknown: bool, now: Instant) {
+        let threshold = match self.threshold {
+            Some(threshold) => threshold,
+            None => return,
+        };
+
+        // the number of the bucket `now` falls in, counted from the first packet.
+        let origin = *self.origin.get_or_insert(now);
+        let width = (self.window / FLOOD_BUCKETS as u32).as_nanos().max(1);
+        let number = (now.saturating_duration_since(origin).as_nanos() / width) as u64;
+
+        let bucket = &mut self.buckets[number as usize % FLOOD_BUCKETS];
+        if bucket.number != number {
+            *bucket = FloodBucket {
+                number,
+                ..Default::default()
+            };
+        }
+        bucket.total = bucket.total.saturating_add(1);
+        if unknown {
+            bucket.unknown = bucket.unknown.saturating_add(1);
+        }
+
+        // buckets that have not been reused since they fell out of the window still hold
+        // their old counts, those are left out.
+        let (total, unknown) = self
+            .buckets
+            .iter()
+            .filter(|bucket| number.saturating_sub(bucket.number) < FLOOD_BUCKETS as u64)
+            .fold((0u64, 0u64), |(total, unknown), bucket| {
+                (total + bucket.total as u64, unknown + bucket.unknown as u64)
+            });
+        let ratio = unknown as f32 / total as f32;
+
+        if self.suppressing {
+            self.suppressing = ratio > threshold;
+        } else {
+            self.suppressing = total >= MIN_FLOOD_SAMPLES && ratio > threshold;
+        }
+    }
+}
+
+/// The packets counted within one bucket of the window.
+#[derive(Debug, Clone, Copy, Default)]
+struct FloodBucket {
+    /// Which bucket since the first packet this is, the ring slot is reused once it is old.
+    number: u64,
+    /// The amount of packets.
+    total: u32,
+    /// The amount of those packets that were unknown.
+    unknown: u32,
+}
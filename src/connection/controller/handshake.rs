@@ -0,0 +1,67 @@
+use crate::protocol::packet::online::ConnectionAccept;
+
+/// Keeps the handshake of a connection idempotent.
+///
+/// A peer retransmits its `ConnectionRequest` or `NewConnection` when our reply or our
+/// acknowledgement got lost. These retransmissions must not move an established connection
+/// back into the handshake, or complete it a second time.
+/// A retransmitted `ConnectionRequest` is answered with the same `ConnectionAccept` as the
+/// first one, a retransmitted `NewConnection` is ignored.
+///
+/// This struct does not do any IO, it only decides what to do with each handshake packet.
+#[derive(Debug, Clone, Default)]
+pub struct HandshakeGuard {
+    /// The accept that was sent for the first request.
+    accept: Option<ConnectionAccept>,
+    /// Whether the handshake completed.
+    completed: bool,
+    /// The amount of handshake packets that were retransmissions.
+    duplicates: u64,
+}
+
+impl HandshakeGuard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Observes a `ConnectionRequest`, returning the accept to reply with and whether this is
+    /// the first request, only then should the connection move into the handshake.
+    ///
+    /// `established` is whether the connection is already connected, for instance when it was
+    /// imported from another listener.
+    pub fn request(
+        &mut self,
+        accept: ConnectionAccept,
+        established: bool,
+    ) -> (ConnectionAccept, bool) {
+        if let Some(sent) = self.accept.as_ref() {
+            self.duplicates += 1;
+            return (sent.clone(), false);
+        }
+
+        self.accept = Some(accept.clone());
+        if established {
+            self.duplicates += 1;
+            return (accept, false);
+        }
+
+        (accept, true)
+    }
+
+    /// Observes a `NewConnection`, returns whether it completes the handshake.
+    pub fn complete(&mut self, established: bool) -> bool {
+        if self.completed || established {
+            self.completed = true;
+            self.duplicates += 1;
+            return false;
+        }
+
+        self.completed = true;
+        true
+    }
+
+    /// The amount of handshake packets that were retransmissions, and were not acted on.
+    pub fn duplicates(&self) -> u64 {
+        self.duplicates
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// How far the resend interval of a handshake stage is spread, up to 25% either way.
+pub const HANDSHAKE_RESEND_JITTER: f64 = 0.25;
+/// How far the retransmission timeout of a reliable datagram is spread, up to 10% either way.
+pub const RTO_JITTER: f64 = 0.10;
+
+/// The randomness a connection spreads its retry timers with.
+///
+/// Peers that lost their server at the same time would otherwise retry on the same fixed
+/// timers, and hit it in waves once it is back. Every connection draws from its own provider,
+/// one seeded with [`ConnOptions::rng_seed`] draws the same timers on every run.
+///
+/// [`ConnOptions::rng_seed`]: crate::connection::options::ConnOptions::rng_seed
+#[derive(Debug, Clone)]
+pub struct RngProvider {
+    rng: StdRng,
+}
+
+impl Default for RngProvider {
+    fn default() -> Self {
+        Self::seeded(rand::random())
+    }
+}
+
+impl RngProvider {
+    /// A provider that draws different timers on every run.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn seeded(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// The provider of the connection with `address`. With a seed the connection draws the same
+    /// timers on every run, while connections with other addresses still draw other ones.
+    pub fn for_address(seed: Option<u64>, address: SocketAddr) -> Self {
+        match seed {
+            Some(seed) => {
+                let mut hasher = DefaultHasher::new();
+                (seed, address).hash(&mut hasher);
+                Self::seeded(hasher.finish())
+            }
+            None => Self::new(),
+        }
+    }
+
+    /// A provider for another part of the connection, seeded from this one.
+    pub fn fork(&mut self) -> Self {
+        Self::seeded(self.rng.gen())
+    }
+
+    /// A factor uniformly between `1 - spread` and `1 + spread`, the spread is clamped to `0..=1`.
+    pub fn factor(&mut self, spread: f64) -> f64 {
+        let spread = spread.clamp(0.0, 1.0);
+        if spread == 0.0 {
+            return 1.0;
+        }
+        self.rng.gen_range(1.0 - spread..=1.0 + spread)
+    }
+
+    /// `interval` stretched or shrunk by up to `spread` of it, see [`RngProvider::factor()`].
+    pub fn jitter(&mut self, interval: Duration, spread: f64) -> Duration {
+        interval.mul_f64(self.factor(spread))
+    }
+
+    /// A time uniformly between zero and `ceiling`.
+    pub fn full_jitter(&mut self, ceiling: Duration) -> Duration {
+        ceiling.mul_f64(self.rng.gen::<f64>())
+    }
+
+    /// The wait before retry `attempt` of an exponential backoff, counted from zero: a full
+    /// jitter of `base` doubled for every attempt, up to `cap`.
+    pub fn backoff(&mut self, base: Duration, attempt: u32, cap: Duration) -> Duration {
+        let ceiling = base
+            .checked_mul(1 << attempt.min(31))
+            .map_or(cap, |ceiling| ceiling.min(cap));
+        self.full_jitter(ceiling)
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::util;
+
+/// The amount of tasks that drive a connection of a listener, its tick and its packet handler.
+pub const CONNECTION_DRIVERS: usize = 2;
+
+/// Tells whether the tasks driving a connection are still running, and still making progress.
+///
+/// Every driver task holds a [`DriverGuard`] for as long as it runs, the guard is dropped with
+/// the task however it ends, whether it returned, was cancelled or panicked.
+#[derive(Debug, Clone)]
+pub struct Liveness {
+    drivers: Arc<AtomicUsize>,
+    ticks: Arc<AtomicU64>,
+    created: Instant,
+    /// When the next tick is due, in milliseconds since `created`.
+    next_tick: Arc<AtomicU64>,
+}
+
+impl Default for Liveness {
+    fn default() -> Self {
+        Self {
+            drivers: Arc::default(),
+            ticks: Arc::default(),
+            created: util::now(),
+            next_tick: Arc::default(),
+        }
+    }
+}
+
+impl Liveness {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a driver task, it counts as running until the guard is dropped.
+    pub fn driver(&self) -> DriverGuard {
+        self.drivers.fetch_add(1, Ordering::Relaxed);
+        DriverGuard(self.drivers.clone())
+    }
+
+    /// The amount of driver tasks that are running.
+    pub fn drivers(&self) -> usize {
+        self.drivers.load(Ordering::Relaxed)
+    }
+
+    /// Records that the tick of the connection ran.
+    pub fn tick(&self) {
+        self.ticks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The amount of times the tick of the connection ran.
+    pub fn ticks(&self) -> u64 {
+        self.ticks.load(Ordering::Relaxed)
+    }
+
+    /// Records when the tick of the connection is due next, an idle connection ticks rarely.
+    pub fn tick_due(&self, at: Instant) {
+        let due = at.saturating_duration_since(self.created).as_millis() as u64;
+        self.next_tick.store(due, Ordering::Relaxed);
+    }
+
+    /// Whether the tick of the connection is late at `now`. Until a tick was scheduled with
+    /// [`Liveness::tick_due()`], it is expected to run right away.
+    pub fn overdue(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.created).as_millis() as u64
+            >= self.next_tick.load(Ordering::Relaxed)
+    }
+
+    /// Whether both belong to the same connection.
+    fn same(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.ticks, &other.ticks)
+    }
+}
+
+/// Held by a driver task of a connection, see [`Liveness::driver()`].
+#[derive(Debug)]
+pub struct DriverGuard(Arc<AtomicUsize>);
+
+impl Drop for DriverGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// What the janitor saw of a connection on its last pass.
+#[derive(Debug)]
+struct Inspection {
+    liveness: Liveness,
+    ticks: u64,
+    dead: bool,
+}
+
+/// Finds the zombies among the connections of a listener: sessions whose driver tasks died,
+/// or stopped making progress, so nothing will ever remove them.
+///
+/// A connection is dead when one of its [`CONNECTION_DRIVERS`] is gone, or when its tick
+/// didn't run since the last pass while it was due. It is only reclaimed once it was dead on
+/// two passes in a row, a connection that is closing normally is removed by its own tick
+/// before then. An idle connection ticks rarely but on time, so traffic has nothing to do
+/// with it.
+///
+/// This struct does not do any IO, it only decides which connections to reclaim.
+#[derive(Debug, Default)]
+pub struct Janitor {
+    inspected: HashMap<SocketAddr, Inspection>,
+}
+
+impl Janitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inspects every session of the listener, and returns the ones to reclaim.
+    ///
+    /// Sessions that are not passed anymore are forgotten.
+    pub fn pass<'a>(
+        &mut self,
+        sessions: impl IntoIterator<Item = (SocketAddr, &'a Liveness)>,
+    ) -> Vec<SocketAddr> {
+        let now = util::now();
+        let mut previous = std::mem::take(&mut self.inspected);
+        let mut reclaim = Vec::new();
+
+        for (address, liveness) in sessions {
+            // a new connection on the same address starts over.
+            let last = previous
+                .remove(&address)
+                .filter(|last| last.liveness.same(liveness));
+            let ticks = liveness.ticks();
+            let stalled =
+                last.as_ref().is_some_and(|last| last.ticks == ticks) && liveness.overdue(now);
+            let dead = liveness.drivers() < CONNECTION_DRIVERS || stalled;
+
+            if dead && last.is_some_and(|last| last.dead) {
+                reclaim.push(address);
+                continue;
+            }
+
+            self.inspected.insert(
+                address,
+                Inspection {
+                    liveness: liveness.clone(),
+                    ticks,
+                    dead,
+                },
+            );
+        }
+
+        reclaim
+    }
+}
@@ -0,0 +1,135 @@
+/// The largest datagram of each bucket of a [`LossBySize`], headers included, one more
+/// bucket holds every datagram larger than the last.
+pub const SIZE_BUCKETS: [usize; 5] = [300, 600, 900, 1200, 1400];
+
+/// The amount of buckets of a [`LossBySize`].
+pub const BUCKETS: usize = SIZE_BUCKETS.len() + 1;
+
+/// The amount of datagrams too large for the step below the MTU that have to be sent
+/// before their retransmissions say anything about the path.
+pub const BLACKHOLE_MIN_SAMPLES: u64 = 8;
+
+/// The least ratio of the datagrams too large for the step below the MTU that have to be
+/// retransmitted for the path to be taken to drop them.
+pub const BLACKHOLE_LOSS: f32 = 0.5;
+
+/// How many times higher than the retransmit ratio of the datagrams that fit the step below
+/// the MTU the ratio of the larger ones has to be, for their loss to be put down to their size.
+pub const BLACKHOLE_SKEW: f32 = 4.0;
+
+/// How many of the reliable datagrams of a connection had to be retransmitted, by their
+/// size, see [`ConnectionStats::loss_by_size`].
+///
+/// A path that only loses the larger datagrams, while the small ones get through, drops
+/// datagrams near its MTU rather than losing them at random.
+///
+/// This struct does not do any IO, it only counts.
+///
+/// [`ConnectionStats::loss_by_size`]: crate::connection::stats::ConnectionStats::loss_by_size
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LossBySize {
+    /// The datagrams sent in each bucket, counted on their first send.
+    pub sent: [u64; BUCKETS],
+    /// The datagrams in each bucket that were retransmitted at least once.
+    pub retransmitted: [u64; BUCKETS],
+}
+
+impl LossBySize {
+    /// The bucket of a datagram of `size`, headers included.
+    pub fn bucket(size: usize) -> usize {
+        SIZE_BUCKETS
+            .iter()
+            .position(|max| size <= *max)
+            .unwrap_or(SIZE_BUCKETS.len())
+    }
+
+    /// Counts the first send of a datagram of `size`.
+    pub fn record_sent(&mut self, size: usize) {
+        self.sent[Self::bucket(size)] += 1;
+    }
+
+    /// Counts the first retransmission of a datagram of `size`.
+    pub fn record_retransmitted(&mut self, size: usize) {
+        self.retransmitted[Self::bucket(size)] += 1;
+    }
+
+    /// The ratio (`0.0` to `1.0`) of the datagrams in `bucket` that had to be retransmitted,
+    /// zero while nothing was sent in it.
+    pub fn ratio(&self, bucket: usize) -> f32 {
+        ratio(self.sent[bucket], self.retransmitted[bucket])
+    }
+
+    /// The retransmit ratio of the buckets that only hold datagrams of at most `size`, and
+    /// the one of the buckets above, with the amount of datagrams sent in them.
+    pub fn split(&self, size: usize) -> ((u64, f32), (u64, f32)) {
+        let small = SIZE_BUCKETS.iter().take_while(|max| **max <= size).count();
+        let sum = |counts: &[u64]| counts.iter().sum::<u64>();
+        let (sent, retransmitted) = (sum(&self.sent[..small]), sum(&self.retransmitted[..small]));
+        let (large_sent, large_retransmitted) =
+            (sum(&self.sent[small..]), sum(&self.retransmitted[small..]));
+        (
+            (sent, ratio(sent, retransmitted)),
+            (large_sent, ratio(large_sent, large_retransmitted)),
+        )
+    }
+
+    /// What was counted since `earlier`, a copy of these counts taken before.
+    pub fn since(&self, earlier: &LossBySize) -> LossBySize {
+        LossBySize {
+            sent: std::array::from_fn(|i| self.sent[i].saturating_sub(earlier.sent[i])),
+            retransmitted: std::array::from_fn(|i| {
+                self.retransmitted[i].saturating_sub(earlier.retransmitted[i])
+            }),
+        }
+    }
+}
+
+/// Tells a path that drops the datagrams near the MTU from one that loses datagrams at random,
+/// from the [`LossBySize`] of a connection.
+///
+/// When the datagrams too large for the step below the MTU are retransmitted far more often
+/// than the ones that fit it (for instance after a path change), the path is taken to be a
+/// black hole for them. Only what was counted since the MTU last changed is looked at.
+///
+/// This struct does not do any IO, the MTU is lowered by the caller.
+#[derive(Debug, Clone)]
+pub struct BlackholeDetector {
+    /// The loss counts of the connection when the MTU was last changed.
+    /// `None` until the next timeout after a change.
+    window: Option<LossBySize>,
+}
+
+impl BlackholeDetector {
+    /// Creates a detector for a connection that has not sent anything yet.
+    pub fn new() -> Self {
+        Self {
+            window: Some(LossBySize::default()),
+        }
+    }
+
+    /// Forgets what was counted so far, called whenever the MTU changes.
+    pub fn reset(&mut self) {
+        self.window = None;
+    }
+
+    /// Called when the retransmission timer fired, `loss` being the loss counts of the
+    /// connection so far and `below` the step on the ladder below the MTU.
+    ///
+    /// Returns whether the path looks like it drops the datagrams too large for `below`.
+    pub fn is_blackhole(&mut self, loss: &LossBySize, below: u16) -> bool {
+        let window = loss.since(self.window.get_or_insert(*loss));
+        let ((_, small), (large_sent, large)) = window.split(below as usize);
+        // datagrams that fit the step below get lost as well, that's plain loss.
+        large_sent >= BLACKHOLE_MIN_SAMPLES
+            && large >= BLACKHOLE_LOSS
+            && large >= small * BLACKHOLE_SKEW
+    }
+}
+
+/// A datagram sent before `earlier` may be retransmitted after it, so the ratio is capped.
+fn ratio(sent: u64, retransmitted: u64) -> f32 {
+    if sent == 0 {
+        return 0.0;
+    }
+    (retransmitted as f64 / sent as f64).min(1.0) as f32
+}
@@ -0,0 +1,120 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// The default amount of bytes the buffers of a single connection may hold together.
+pub const DEFAULT_MAX_CONNECTION_MEMORY: usize = 8 * 1024 * 1024;
+
+/// What a buffered item is charged on top of its payload, for the structure holding it.
+/// The ledger is accurate to this much per item.
+pub const ENTRY_OVERHEAD: usize = 64;
+
+/// What a buffered item of `len` payload bytes is charged.
+pub fn cost(len: usize) -> usize {
+    len + ENTRY_OVERHEAD
+}
+
+/// The byte ledger of a connection, every structure buffering data for it debits what it
+/// holds and credits it back once it lets go of it.
+///
+/// The handle is cheap to clone, all clones share the same ledger, so the send and receive
+/// queue of a connection can be charged without locking each other. The ledger only keeps
+/// count, what happens once the cap is exceeded is up to the queues, see
+/// [`ConnOptions::max_connection_memory`].
+///
+/// [`ConnOptions::max_connection_memory`]: crate::connection::options::ConnOptions::max_connection_memory
+#[derive(Debug, Clone)]
+pub struct MemBudget {
+    used: Arc<AtomicUsize>,
+    cap: usize,
+}
+
+impl MemBudget {
+    pub fn new(cap: usize) -> Self {
+        Self {
+            used: Arc::new(AtomicUsize::new(0)),
+            cap,
+        }
+    }
+
+    /// The most bytes the ledger should hold.
+    pub fn cap(&self) -> usize {
+        self.cap
+    }
+
+    /// The bytes currently charged to the ledger.
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    /// Whether `bytes` more can be charged without exceeding the cap.
+    pub fn fits(&self, bytes: usize) -> bool {
+        self.used().saturating_add(bytes) <= self.cap
+    }
+
+    /// Whether more is charged than the cap allows.
+    pub fn is_exceeded(&self) -> bool {
+        self.used() > self.cap
+    }
+
+    fn debit(&self, bytes: usize) {
+        self.used.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    fn credit(&self, bytes: usize) {
+        let _ = self
+            .used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                Some(used.saturating_sub(bytes))
+            });
+    }
+}
+
+impl Default for MemBudget {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_CONNECTION_MEMORY)
+    }
+}
+
+/// The share of a [`MemBudget`] one queue is charged for.
+///
+/// This keeps count of what the queue itself holds, so it can be moved over to another
+/// ledger, and so the receive queue can tell whether its own obligations exceed the cap.
+#[derive(Debug, Clone, Default)]
+pub struct MemAccount {
+    budget: MemBudget,
+    charged: usize,
+}
+
+impl MemAccount {
+    pub fn new(budget: MemBudget) -> Self {
+        Self { budget, charged: 0 }
+    }
+
+    /// The ledger this account is charged to.
+    pub fn budget(&self) -> &MemBudget {
+        &self.budget
+    }
+
+    /// The bytes this account holds.
+    pub fn charged(&self) -> usize {
+        self.charged
+    }
+
+    pub fn debit(&mut self, bytes: usize) {
+        self.charged += bytes;
+        self.budget.debit(bytes);
+    }
+
+    pub fn credit(&mut self, bytes: usize) {
+        let bytes = bytes.min(self.charged);
+        self.charged -= bytes;
+        self.budget.credit(bytes);
+    }
+
+    /// Charges what this account holds to `budget` from now on.
+    pub fn move_to(&mut self, budget: MemBudget) {
+        self.budget.credit(self.charged);
+        budget.debit(self.charged);
+        self.budget = budget;
+    }
+}
@@ -1,6 +1,22 @@
 // TODO
+pub mod budget;
+pub mod checksum;
+pub mod clock;
+pub mod coalesce;
+pub mod consumer;
+pub mod flood;
+pub mod handshake;
+pub mod jitter;
+pub mod liveness;
+pub mod loss;
+pub mod memory;
+pub mod mtu;
+pub mod one_way;
+pub mod profile;
+pub mod rtt;
+pub mod tick;
 pub mod window;
 
 pub struct Controller {
-    pub window: window::ReliableWindow,
+    pub window: window::ReliableWindow<crate::protocol::index::DatagramSeq>,
 }
@@ -0,0 +1,228 @@
+use std::time::{Duration, Instant};
+
+use crate::protocol::index::DatagramSeq;
+use crate::protocol::MTU_LADDER;
+
+/// The default interval between upward MTU probes.
+pub const DEFAULT_MTU_REPROBE_INTERVAL: Duration = Duration::from_secs(300);
+
+/// How long a probe may remain unacknowledged before it is considered lost.
+pub const MTU_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The amount of times a probe may be lost before we stop trying and back off.
+const MAX_PROBE_LOSSES: u8 = 2;
+
+/// The largest factor the re-probe interval will be backed off by.
+const MAX_BACKOFF: u32 = 64;
+
+/// The id of the padded no-op message a probe carries, the receiving queue drops it without
+/// handing it out, only its acknowledgement matters.
+///
+/// The ids below `0x86` are reserved by RakNet, so no message of the application starts with it.
+pub const MTU_PROBE_ID: u8 = 0x7e;
+
+/// A probe that is currently in flight.
+#[derive(Debug, Clone, Copy)]
+struct InflightProbe {
+    /// The datagram sequence the probe was sent with.
+    sequence: DatagramSeq,
+    /// The MTU the probe was padded to.
+    mtu: u16,
+    /// When the probe was sent.
+    sent_at: Instant,
+}
+
+/// Keeps track of upward MTU re-probing for a connection.
+///
+/// The MTU never exceeds the one negotiated during the handshake, the ceiling. Once it was
+/// stepped down the [`MTU_LADDER`], for instance after a path change, it can be climbed back.
+///
+/// A connection below its ceiling periodically sends a padded no-op datagram at the next step,
+/// capped at the ceiling. If the peer acknowledges the probe, the effective MTU is raised one
+/// step. If the probe is lost twice in a row, the MTU stays put and the re-probe interval is
+/// backed off exponentially.
+///
+/// This struct does not do any IO, it only decides *when* to probe, and *what* the MTU is.
+///
+/// [`MTU_LADDER`]: crate::protocol::MTU_LADDER
+#[derive(Debug, Clone)]
+pub struct MtuProber {
+    /// The current effective MTU.
+    mtu: u16,
+    /// The MTU negotiated during the handshake, probes never go above it.
+    ceiling: u16,
+    /// The base interval between probes, zero disables probing.
+    interval: Duration,
+    /// The current backoff multiplier of the interval.
+    backoff: u32,
+    /// The next time we are allowed to probe.
+    next_probe: Instant,
+    /// How many times the current step has been lost.
+    losses: u8,
+    /// The probe currently in flight, if any.
+    inflight: Option<InflightProbe>,
+}
+
+impl MtuProber {
+    /// Creates a new prober for a connection currently using `mtu`, that negotiated `ceiling`.
+    pub fn new(mtu: u16, ceiling: u16, interval: Duration, now: Instant) -> Self {
+        Self {
+            mtu: mtu.min(ceiling),
+            ceiling,
+            interval,
+            backoff: 1,
+            next_probe: now + interval,
+            losses: 0,
+            inflight: None,
+        }
+    }
+
+    /// The current effective MTU.
+    pub fn mtu(&self) -> u16 {
+        self.mtu
+    }
+
+    /// Changes the base re-probe interval, a zero duration disables probing entirely.
+    pub fn set_interval(&mut self, interval: Duration, now: Instant) {
+        self.interval = interval;
+        self.backoff = 1;
+        self.next_probe = now + interval;
+    }
+
+    /// Whether or not upward probing is enabled.
+    pub fn is_enabled(&self) -> bool {
+        !self.interval.is_zero()
+    }
+
+    /// The MTU negotiated during the handshake.
+    pub fn ceiling(&self) -> u16 {
+        self.ceiling
+    }
+
+    /// The next step on the ladder above the current MTU, capped at the ceiling.
+    /// `None` once the MTU is back at the ceiling.
+    pub fn next_step(&self) -> Option<u16> {
+        if self.mtu >= self.ceiling {
+            return None;
+        }
+        MTU_LADDER
+            .iter()
+            .copied()
+            .find(|step| *step > self.mtu)
+            .map(|step| step.min(self.ceiling))
+    }
+
+    /// The step on the ladder below the current MTU, if there is one.
+    pub fn step_below(&self) -> Option<u16> {
+        MTU_LADDER
+            .iter()
+            .rev()
+            .copied()
+            .find(|step| *step < self.mtu)
+    }
+
+    /// Lowers the MTU to `mtu`, probing resumes an interval later, so the MTU climbs back once
+    /// the path heals.
+    pub fn step_down(&mut self, mtu: u16, now: Instant) {
+        self.mtu = mtu;
+        self.inflight = None;
+        self.losses = 0;
+        self.backoff = 1;
+        self.next_probe = now + self.interval;
+    }
+
+    /// Called when the socket refused to send a datagram of `size`, headers included, because
+    /// the route to the peer can't carry it (`EMSGSIZE`).
+    ///
+    /// Unlike a timeout this leaves no doubt, so the MTU is stepped down right away, to the
+    /// largest step on the ladder below `size`. Returns the new MTU, `None` when `size` is
+    /// above the MTU, that datagram was a probe and simply counts as lost.
+    pub fn too_large(&mut self, size: usize, now: Instant) -> Option<u16> {
+        if size > self.mtu as usize {
+            return None;
+        }
+
+        let step = MTU_LADDER
+            .iter()
+            .rev()
+            .copied()
+            .find(|step| (*step as usize) < size)?;
+        self.step_down(step, now);
+        Some(step)
+    }
+
+    /// Checks whether a probe should be sent at `now`, returning the MTU the probe
+    /// should be padded to. This will also expire any probe that has been in flight
+    /// for longer than [`MTU_PROBE_TIMEOUT`].
+    pub fn poll(&mut self, now: Instant) -> Option<u16> {
+        if !self.is_enabled() {
+            return None;
+        }
+
+        if let Some(probe) = self.inflight {
+            if now.duration_since(probe.sent_at) < MTU_PROBE_TIMEOUT {
+                return None;
+            }
+
+            // the probe was lost.
+            self.inflight = None;
+            self.losses += 1;
+
+            if self.losses >= MAX_PROBE_LOSSES {
+                self.losses = 0;
+                self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+                self.next_probe = now + self.interval * self.backoff;
+                return None;
+            }
+
+            // try again right away, a single loss may just be bad luck.
+            return self.next_step();
+        }
+
+        if now < self.next_probe {
+            return None;
+        }
+
+        self.next_step()
+    }
+
+    /// Records that a probe padded to `mtu` was sent with the given datagram sequence.
+    pub fn sent(&mut self, sequence: DatagramSeq, mtu: u16, now: Instant) {
+        self.inflight = Some(InflightProbe {
+            sequence,
+            mtu,
+            sent_at: now,
+        });
+    }
+
+    /// The datagram sequence of the probe in flight, if any.
+    pub fn probe_sequence(&self) -> Option<DatagramSeq> {
+        self.inflight.map(|probe| probe.sequence)
+    }
+
+    /// Whether the given datagram sequence belongs to the probe in flight.
+    pub fn is_probe(&self, sequence: DatagramSeq) -> bool {
+        matches!(self.inflight, Some(probe) if probe.sequence == sequence)
+    }
+
+    /// Called when a datagram sequence is acknowledged by the peer.
+    /// Returns the new MTU if the acknowledgement was for a probe and the MTU was raised.
+    pub fn ack(&mut self, sequence: DatagramSeq, now: Instant) -> Option<u16> {
+        match self.inflight {
+            Some(probe) if probe.sequence == sequence => {
+                self.inflight = None;
+                self.losses = 0;
+                self.backoff = 1;
+                self.next_probe = now + self.interval;
+
+                if probe.mtu > self.mtu {
+                    self.mtu = probe.mtu;
+                    Some(self.mtu)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+/// The default of [`ConnOptions::one_way_fail_threshold`].
+///
+/// [`ConnOptions::one_way_fail_threshold`]: crate::connection::options::ConnOptions::one_way_fail_threshold
+pub const DEFAULT_ONE_WAY_FAIL_THRESHOLD: Duration = Duration::from_secs(5);
+
+/// Notices when the peer keeps reaching us, but nothing we send reaches it.
+///
+/// This happens when a NAT mapping breaks in one direction only. The peer's datagrams keep the
+/// connection from timing out on our side, until the peer gives up on us and the connection
+/// seems to drop out of nowhere.
+#[derive(Debug, Clone)]
+pub struct OneWayDetector {
+    threshold: Option<Duration>,
+    suspected: bool,
+}
+
+impl OneWayDetector {
+    /// A detector suspecting one-way loss once reliable data went unacknowledged for
+    /// `threshold`, `None` never suspects it.
+    pub fn new(threshold: Option<Duration>) -> Self {
+        Self {
+            threshold,
+            suspected: false,
+        }
+    }
+
+    /// Checks the connection, returns `true` when one-way loss is suspected where it wasn't
+    /// before.
+    ///
+    /// `unacked_for` is how long reliable data has been waiting for an acknowledgement without
+    /// any arriving, see [`SendQueue::unacked_for()`]. `heard_for` is how long ago the peer was
+    /// last heard from. The suspicion is lifted as soon as an acknowledgement arrives.
+    ///
+    /// [`SendQueue::unacked_for()`]: crate::connection::queue::SendQueue::unacked_for
+    pub fn check(&mut self, unacked_for: Option<Duration>, heard_for: Duration) -> bool {
+        let threshold = match self.threshold {
+            Some(threshold) => threshold,
+            None => return false,
+        };
+
+        match unacked_for {
+            Some(unacked_for) if unacked_for < threshold => {
+                self.suspected = false;
+                false
+            }
+            // the peer was heard from while it didn't hear us.
+            Some(unacked_for) if heard_for <= unacked_for => {
+                let fresh = !self.suspected;
+                self.suspected = true;
+                fresh
+            }
+            // a peer that went quiet altogether is left to the timeout.
+            Some(_) => false,
+            None => {
+                self.suspected = false;
+                false
+            }
+        }
+    }
+
+    /// Whether one-way loss is currently suspected.
+    pub fn suspected(&self) -> bool {
+        self.suspected
+    }
+}
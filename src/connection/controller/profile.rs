@@ -0,0 +1,69 @@
+use crate::connection::controller::memory::{MemBudget, ENTRY_OVERHEAD};
+use crate::protocol::RAKNET_HEADER_FRAME_OVERHEAD;
+
+/// How close the peer of a connection is, as far as the application knows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LatencyClass {
+    /// The peer is on the same network, and trusted not to be flooded by a higher frame
+    /// budget, see [`ConnOptions::lan_frame_budget`].
+    ///
+    /// [`ConnOptions::lan_frame_budget`]: crate::connection::options::ConnOptions::lan_frame_budget
+    Lan,
+    /// Any other peer.
+    #[default]
+    Internet,
+}
+
+/// What the application expects a connection to carry shortly, like the world download
+/// right after a player joined, see [`Connection::hint_profile()`].
+///
+/// [`Connection::hint_profile()`]: crate::connection::Connection::hint_profile
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Profile {
+    /// The bytes about to be sent to the peer in one burst.
+    pub expected_burst_bytes: usize,
+    /// The messages per second expected from the peer.
+    pub expected_message_rate: usize,
+    /// How close the peer is.
+    pub latency_class: LatencyClass,
+}
+
+/// How many entries the structures of a connection have room for without growing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Capacity {
+    /// Frames queued to be sent.
+    pub frames: usize,
+    /// Datagrams sent and waiting for an acknowledgement.
+    pub datagrams: usize,
+    /// Split messages of the peer being put back together.
+    pub splits: usize,
+    /// Messages of the peer waiting to be handed out.
+    pub messages: usize,
+}
+
+impl Profile {
+    /// The capacity the structures of a connection with `mtu` are given for this profile.
+    ///
+    /// The burst is clamped to what `budget` has room for, and no structure is sized for
+    /// more entries than the budget could be charged for, so a hint never lets a connection
+    /// hold more than its budget allows.
+    ///
+    /// This struct does not do any IO, it only decides how large the structures should be.
+    pub fn capacity(&self, mtu: u16, budget: &MemBudget) -> Capacity {
+        let room = budget.cap().saturating_sub(budget.used());
+        let entries = room / ENTRY_OVERHEAD;
+        let burst = self.expected_burst_bytes.min(room);
+        let payload = mtu.saturating_sub(RAKNET_HEADER_FRAME_OVERHEAD).max(1) as usize;
+        let frames = ((burst + payload - 1) / payload).min(entries);
+        let messages = self.expected_message_rate.min(entries);
+
+        Capacity {
+            frames,
+            // every frame of the burst fills a datagram of its own.
+            datagrams: frames,
+            // a message of the peer is split when it doesn't fit a datagram.
+            splits: messages.min(frames),
+            messages,
+        }
+    }
+}
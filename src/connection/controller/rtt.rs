@@ -0,0 +1,96 @@
+use std::time::Duration;
+
+/// The retransmission timeout used before the first round trip has been measured.
+pub const INITIAL_RTO: Duration = Duration::from_secs(1);
+
+/// The smallest retransmission timeout, a connection ticks every 50ms so anything lower
+/// than a couple of ticks would just cause spurious resends.
+pub const MIN_RTO: Duration = Duration::from_millis(100);
+
+/// The largest retransmission timeout, the connection times out well before this is hit twice.
+pub const MAX_RTO: Duration = Duration::from_secs(10);
+
+/// The least time between two retransmissions of the same datagram, see
+/// [`RttEstimator::resend_spacing()`].
+pub const MIN_RESEND_SPACING: Duration = Duration::from_millis(20);
+
+/// A copy of the round trip state of a connection at some point in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RttStats {
+    /// The smoothed round trip time, `None` until the first sample.
+    pub rtt: Option<Duration>,
+    /// The round trip time variance, `None` until the first sample.
+    pub rtt_variance: Option<Duration>,
+    /// The current retransmission timeout.
+    pub rto: Duration,
+    /// How many times in a row the retransmission timer fired without an acknowledgement.
+    pub consecutive_timeouts: u32,
+}
+
+impl Default for RttStats {
+    fn default() -> Self {
+        Self {
+            rtt: None,
+            rtt_variance: None,
+            rto: INITIAL_RTO,
+            consecutive_timeouts: 0,
+        }
+    }
+}
+
+/// Estimates the round trip time and retransmission timeout of a connection,
+/// following the estimator described in RFC 6298.
+///
+/// Only acknowledgements of datagrams that were sent exactly once should be fed into
+/// [`RttEstimator::sample()`], otherwise it is unknown which send the ACK belongs to.
+#[derive(Debug, Clone, Default)]
+pub struct RttEstimator {
+    stats: RttStats,
+}
+
+impl RttEstimator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds a measured round trip into the estimator.
+    pub fn sample(&mut self, rtt: Duration) {
+        let (srtt, rttvar) = match (self.stats.rtt, self.stats.rtt_variance) {
+            (Some(srtt), Some(rttvar)) => {
+                let delta = srtt.abs_diff(rtt);
+                ((srtt * 7 + rtt) / 8, (rttvar * 3 + delta) / 4)
+            }
+            _ => (rtt, rtt / 2),
+        };
+
+        self.stats.rtt = Some(srtt);
+        self.stats.rtt_variance = Some(rttvar);
+        self.stats.rto = (srtt + rttvar * 4).clamp(MIN_RTO, MAX_RTO);
+        self.stats.consecutive_timeouts = 0;
+    }
+
+    /// Called when the retransmission timer fires, this backs off the timeout.
+    pub fn timeout(&mut self) {
+        self.stats.rto = (self.stats.rto * 2).min(MAX_RTO);
+        self.stats.consecutive_timeouts = self.stats.consecutive_timeouts.saturating_add(1);
+    }
+
+    /// The current retransmission timeout.
+    pub fn rto(&self) -> Duration {
+        self.stats.rto
+    }
+
+    /// How long a retransmitted datagram is not retransmitted again, whether the timeout or
+    /// a NACK asks for it. Neither can tell whether the retransmission arrived before a round
+    /// trip passed, so this is the smoothed round trip time, at least [`MIN_RESEND_SPACING`].
+    pub fn resend_spacing(&self) -> Duration {
+        self.stats
+            .rtt
+            .unwrap_or(MIN_RESEND_SPACING)
+            .clamp(MIN_RESEND_SPACING, MAX_RTO)
+    }
+
+    pub fn stats(&self) -> RttStats {
+        self.stats
+    }
+}
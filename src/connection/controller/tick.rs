@@ -0,0 +1,89 @@
+use std::time::{Duration, Instant};
+
+/// The default of [`ConnOptions::tick_interval_min`].
+///
+/// [`ConnOptions::tick_interval_min`]: crate::connection::options::ConnOptions::tick_interval_min
+pub const DEFAULT_TICK_INTERVAL_MIN: Duration = Duration::from_millis(10);
+
+/// The default of [`ConnOptions::tick_interval_max`].
+///
+/// [`ConnOptions::tick_interval_max`]: crate::connection::options::ConnOptions::tick_interval_max
+pub const DEFAULT_TICK_INTERVAL_MAX: Duration = Duration::from_millis(50);
+
+/// What a connection has to do on its next ticks, as seen at the end of a tick.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TickDemand {
+    /// Frames are waiting to be sent, because they were over the budget of the tick,
+    /// or the socket was out of buffer space.
+    pub backlog: bool,
+    /// The peer is owed negative acknowledgements, which are repeated until the gap is filled.
+    pub ack_debt: bool,
+    /// How long until the oldest datagram in flight is due for retransmission,
+    /// `None` when nothing is waiting for an acknowledgement.
+    pub retransmit_in: Option<Duration>,
+    /// How long until the connection needs a tick anyway, the keepalive that proves
+    /// the connection is alive to the peer.
+    pub idle_in: Duration,
+}
+
+/// Decides when the tick of a connection runs next.
+///
+/// A connection with nothing to do is only visited when its keepalive is due, one waiting on
+/// acknowledgements is visited when its retransmission timeout runs out, but never less than
+/// `min` nor more than `max` apart. Datagrams arriving from the peer and sends of the
+/// application don't wait for an idle tick, they pull the next one in with [`TickPacer::wake()`].
+///
+/// A datagram is therefore never retransmitted more than `min` after its timeout.
+#[derive(Debug, Clone, Copy)]
+pub struct TickPacer {
+    min: Duration,
+    max: Duration,
+}
+
+impl TickPacer {
+    /// A pacer ticking at most every `min` and, while anything is in flight, at least every
+    /// `max`. A `max` below `min` is raised to it.
+    pub fn new(min: Duration, max: Duration) -> Self {
+        Self {
+            min,
+            max: max.max(min),
+        }
+    }
+
+    /// The shortest time between two ticks.
+    pub fn min(&self) -> Duration {
+        self.min
+    }
+
+    /// The longest time between two ticks of a connection that has anything in flight.
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    /// How long until the next tick of a connection with `demand`.
+    pub fn interval(&self, demand: &TickDemand) -> Duration {
+        if demand.backlog {
+            return self.min;
+        }
+
+        let mut interval = demand.idle_in;
+        if demand.ack_debt {
+            interval = interval.min(self.max);
+        }
+        if let Some(retransmit_in) = demand.retransmit_in {
+            interval = interval.min(retransmit_in.min(self.max));
+        }
+        interval.max(self.min)
+    }
+
+    /// When to run the next tick, for a tick that ended at `now` leaving `demand`.
+    pub fn next_tick(&self, now: Instant, demand: &TickDemand) -> Instant {
+        now + self.interval(demand)
+    }
+
+    /// Pulls the tick scheduled at `deadline` in, as something happened at `now` the tick
+    /// has to handle: a datagram arrived, or the application queued something.
+    pub fn wake(&self, deadline: Instant, now: Instant) -> Instant {
+        deadline.min(now + self.min)
+    }
+}
@@ -1,86 +1,148 @@
-use std::collections::HashMap;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
 
-use crate::server::current_epoch;
+use crate::protocol::index::WrappingIndex;
+use crate::protocol::MAX_FRAGS;
+use crate::util::now;
 
+/// The amount of indexes a [`ReliableWindow`] spans.
+pub const DEFAULT_WINDOW_SIZE: u32 = 2048;
+
+/// The amount of reliable message indexes the window of a receive queue spans. A datagram
+/// carries many of these, and a single message up to [`MAX_FRAGS`] of them.
+pub const RELIABLE_WINDOW_SIZE: u32 = MAX_FRAGS * 8;
+
+/// How long a [`ReliableWindow`] waits on a missing index before it gives up on it, to let in
+/// an index that is too far ahead of it.
+pub const DEFAULT_GAP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Why a [`ReliableWindow`] did not take an index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowError {
+    /// The index was taken before, or the window already moved past it.
+    Duplicate,
+    /// The index is more than the size of the window past its start.
+    TooFarAhead,
+}
+
+/// A sliding window over one of the 24-bit index spaces, for example
+/// [`DatagramSeq`] or [`MessageIndex`].
+///
+/// The window starts at the first index it is still missing, and only takes indexes up to its
+/// size past that, so what it holds stays bounded whatever the peer sends. A missing index is
+/// given up on once an index its size ahead of it arrives, like after a lost unreliable datagram,
+/// or once it was waited on for [`DEFAULT_GAP_TIMEOUT`].
+///
+/// [`DatagramSeq`]: crate::protocol::index::DatagramSeq
+/// [`MessageIndex`]: crate::protocol::index::MessageIndex
 #[derive(Debug, Clone)]
-pub struct ReliableWindow {
-    // The current window start and end
-    window: (u32, u32),
-    // The current window size
+pub struct ReliableWindow<I> {
+    /// The first index that was not taken yet.
+    start: I,
+    /// How far past the start indexes are taken.
     size: u32,
-    // the current queue of packets by timestamp
-    queue: HashMap<u32, u64>,
+    /// The indexes taken past the start, all of these are less than `size` past it.
+    queue: HashSet<I>,
+    /// Since when an index past the start is known of, while the start is still missing.
+    stalled_since: Option<Instant>,
 }
 
-impl ReliableWindow {
+impl<I> ReliableWindow<I>
+where
+    I: WrappingIndex + From<u32>,
+{
     pub fn new() -> Self {
+        Self::with_size(DEFAULT_WINDOW_SIZE)
+    }
+
+    /// Creates a window that takes indexes up to `size` past its start.
+    pub fn with_size(size: u32) -> Self {
         Self {
-            window: (0, 2048),
-            size: 2048,
-            queue: HashMap::new(),
+            start: I::from(0),
+            size: size.max(1),
+            queue: HashSet::new(),
+            stalled_since: None,
         }
     }
 
-    pub fn insert(&mut self, index: u32) -> bool {
-        // We already got this packet
-        if index < self.window.0 || index > self.window.1 || self.queue.contains_key(&index) {
-            return false;
-        }
+    /// Moves the window to start at the given index, forgetting everything it has seen.
+    pub fn reset(&mut self, start: I) {
+        self.queue.clear();
+        self.start = start;
+        self.stalled_since = None;
+    }
 
-        self.queue.insert(index, current_epoch());
+    /// Takes `index`, this errors if it was taken before, or if it is too far ahead of the
+    /// window to be held.
+    pub fn insert(&mut self, index: I) -> Result<(), WindowError> {
+        if index.is_before(self.start) || self.queue.contains(&index) {
+            return Err(WindowError::Duplicate);
+        }
 
-        // we need to update the window to check if the is within it.
-        if index == self.window.0 {
-            self.adjust();
+        let now = now();
+        let distance = self.start.distance_to(index);
+        if distance > self.size {
+            // a gap the sender never fills, like a burst of lost unreliable datagrams, is only
+            // given up on in time.
+            let since = *self.stalled_since.get_or_insert(now);
+            if now.duration_since(since) < DEFAULT_GAP_TIMEOUT {
+                return Err(WindowError::TooFarAhead);
+            }
+        }
+        if distance >= self.size {
+            self.skip_to(index.wrapping_sub(self.size - 1));
         }
 
-        return true;
+        self.queue.insert(index);
+        self.adjust();
+        if !self.queue.is_empty() {
+            self.stalled_since.get_or_insert(now);
+        }
+        Ok(())
     }
 
-    /// Attempts to adjust the window size, removing all out of date packets
-    /// from the queue.
+    /// Moves the start of the window past every index it holds from the start on.
     pub fn adjust(&mut self) {
-        // remove all packets that are out of date, that we got before the window,
-        // increasing the window start and end if we can.
-        while self.queue.contains_key(&self.window.0) {
-            self.queue.remove(&self.window.0);
-            self.window.0 = self.window.0.wrapping_add(1);
-            self.window.1 = self.window.1.wrapping_add(1);
+        while self.queue.remove(&self.start) {
+            self.start = self.start.wrapping_add(1);
+            self.stalled_since = None;
         }
+    }
 
-        // if the window is too small or too big, make sure it's the right size.
-        // corresponding to self.size
-        let curr_size = self.window.1.wrapping_sub(self.window.0);
-        if curr_size < self.size {
-            self.window.1 = self.window.0.wrapping_add(self.size);
-        } else if curr_size > self.size {
-            self.window.0 = self.window.1.wrapping_sub(self.size);
-        }
+    /// Gives up on every index before `start`, forgetting the ones it holds.
+    fn skip_to(&mut self, start: I) {
+        self.queue.retain(|index| !index.is_before(start));
+        self.start = start;
+        self.stalled_since = None;
     }
 
-    /// Returns all the packets that are in the window.
-    pub fn missing(&self) -> Vec<u32> {
-        let mut missing = Vec::new();
+    /// Returns the indexes the window is still waiting on, up to the furthest one it holds.
+    pub fn missing(&self) -> Vec<I> {
+        let end = self
+            .queue
+            .iter()
+            .map(|index| self.start.distance_to(*index))
+            .max()
+            .unwrap_or(0);
 
-        for i in self.window.0..self.window.1 {
-            if !self.queue.contains_key(&i) {
-                missing.push(i);
-            }
-        }
+        (0..end)
+            .map(|n| self.start.wrapping_add(n))
+            .filter(|index| !self.queue.contains(index))
+            .collect()
+    }
 
-        missing
+    /// The indexes the window takes, from its start up to, but not including, the end.
+    pub fn range(&self) -> (I, I) {
+        (self.start, self.start.wrapping_add(self.size))
     }
 
-    pub fn range(&self) -> (u32, u32) {
-        self.window
+    /// The amount of indexes the window holds past its start.
+    pub fn len(&self) -> usize {
+        self.queue.len()
     }
 
-    /// Forcefully clears packets that are not in the window.
-    /// This is used when the window is too small to fit all the packets.
-    pub fn clear_outdated(&mut self) {
-        //todo actually clear packets that are out of date!
-        self.queue
-            .retain(|k, _| *k >= self.window.0 && *k <= self.window.1);
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
     }
 }
 
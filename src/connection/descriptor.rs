@@ -0,0 +1,150 @@
+//! Descriptors of live connections, used to hand established sessions over to another
+//! [`Listener`], for example a new server binary during a zero-downtime upgrade.
+//!
+//! A descriptor only holds what is needed to keep the session alive: the negotiated MTU
+//! and the sequence and order counters of both directions. Payloads that were in flight are
+//! not part of it, the peer retransmits its own, so sessions should be exported once the
+//! connection has drained.
+//!
+//! Descriptors are written with the same [`Reader`] and [`Writer`] traits as packets, so they
+//! can be passed to the new process over any pipe or file.
+//!
+//! [`Listener`]: crate::server::Listener
+use std::net::SocketAddr;
+
+use binary_util::interfaces::{Reader, Writer};
+use binary_util::io::{ByteReader, ByteWriter};
+
+use crate::connection::controller::window::DEFAULT_WINDOW_SIZE;
+use crate::connection::ConnMeta;
+use crate::protocol::packet::online::Capabilities;
+
+/// How far the send sequence is bumped when a session is imported, so acknowledgements
+/// for datagrams the previous owner sent can never be mistaken for ones of the new owner.
+pub const SEQUENCE_SAFETY_MARGIN: u32 = DEFAULT_WINDOW_SIZE;
+
+/// The counters of a single order channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelCounters {
+    pub channel: u8,
+    /// The next sequence index, sequenced messages are not tracked on the receiving side
+    /// so this is always zero there.
+    pub sequence_index: u32,
+    /// The next order index, on the receiving side this is the next index delivered.
+    pub order_index: u32,
+}
+
+/// The counters of the sending side of a session.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SendCounters {
+    /// The next datagram sequence.
+    pub datagram_seq: u32,
+    /// The next reliable message index.
+    pub reliable_index: u32,
+    pub channels: Vec<ChannelCounters>,
+}
+
+/// The counters of the receiving side of a session.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecvCounters {
+    /// The datagram sequence expected next.
+    pub datagram_seq: u32,
+    /// The lowest reliable message index that has not been received, anything below this
+    /// is a duplicate.
+    pub reliable_index: u32,
+    pub channels: Vec<ChannelCounters>,
+}
+
+/// Everything needed to resume an established session in another [`Listener`].
+///
+/// This can be retrieved with [`Listener::export_sessions()`] and resumed with
+/// [`Listener::import_sessions()`].
+///
+/// [`Listener`]: crate::server::Listener
+/// [`Listener::export_sessions()`]: crate::server::Listener::export_sessions
+/// [`Listener::import_sessions()`]: crate::server::Listener::import_sessions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionDescriptor {
+    /// The address of the peer.
+    pub address: SocketAddr,
+    /// The negotiated MTU.
+    pub mtu_size: u16,
+    /// The last time the peer sent anything, in seconds since the unix epoch.
+    pub recv_time: u64,
+    pub send: SendCounters,
+    pub recv: RecvCounters,
+    /// The extensions the peer agreed to.
+    pub capabilities: Capabilities,
+}
+
+impl SessionDescriptor {
+    /// The meta data the listener keeps for this session.
+    pub fn meta(&self) -> ConnMeta {
+        ConnMeta {
+            mtu_size: self.mtu_size,
+            recv_time: self.recv_time,
+            peer_crate_version: self.capabilities.crate_version(),
+            rebound_from: None,
+        }
+    }
+}
+
+fn write_channels(buf: &mut ByteWriter, channels: &[ChannelCounters]) -> std::io::Result<()> {
+    buf.write_u8(channels.len() as u8)?;
+    for channel in channels {
+        buf.write_u8(channel.channel)?;
+        buf.write_u32(channel.sequence_index)?;
+        buf.write_u32(channel.order_index)?;
+    }
+    Ok(())
+}
+
+fn read_channels(buf: &mut ByteReader) -> std::io::Result<Vec<ChannelCounters>> {
+    let count = buf.read_u8()?;
+    let mut channels = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        channels.push(ChannelCounters {
+            channel: buf.read_u8()?,
+            sequence_index: buf.read_u32()?,
+            order_index: buf.read_u32()?,
+        });
+    }
+    Ok(channels)
+}
+
+impl Reader<SessionDescriptor> for SessionDescriptor {
+    fn read(buf: &mut ByteReader) -> std::io::Result<Self> {
+        Ok(Self {
+            address: buf.read_type::<SocketAddr>()?,
+            mtu_size: buf.read_u16()?,
+            recv_time: buf.read_u64()?,
+            send: SendCounters {
+                datagram_seq: buf.read_u32()?,
+                reliable_index: buf.read_u32()?,
+                channels: read_channels(buf)?,
+            },
+            recv: RecvCounters {
+                datagram_seq: buf.read_u32()?,
+                reliable_index: buf.read_u32()?,
+                channels: read_channels(buf)?,
+            },
+            capabilities: Capabilities::read(buf)?,
+        })
+    }
+}
+
+impl Writer for SessionDescriptor {
+    fn write(&self, buf: &mut ByteWriter) -> std::io::Result<()> {
+        buf.write_type::<SocketAddr>(&self.address)?;
+        buf.write_u16(self.mtu_size)?;
+        buf.write_u64(self.recv_time)?;
+        buf.write_u32(self.send.datagram_seq)?;
+        buf.write_u32(self.send.reliable_index)?;
+        write_channels(buf, &self.send.channels)?;
+        buf.write_u32(self.recv.datagram_seq)?;
+        buf.write_u32(self.recv.reliable_index)?;
+        write_channels(buf, &self.recv.channels)?;
+        self.capabilities.write(buf)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,447 @@
+//! The drivers of a [`Connection`], its tick and its packet handler.
+//!
+//! Each one is a struct that does one turn of its task at a time, the tasks of a connection
+//! only wait for their next turn and run it. Nothing in here reads the clock of the system,
+//! so the [`simulation`](crate::simulation) drives a connection by calling the turns itself.
+use std::{
+    net::SocketAddr,
+    sync::{atomic::AtomicU64, Arc},
+    time::{Duration, Instant},
+};
+
+use binary_util::interfaces::{Reader, Writer};
+
+#[cfg(feature = "async_std")]
+use async_std::{
+    channel::Sender,
+    sync::{Mutex, MutexGuard, RwLock},
+};
+#[cfg(feature = "async_tokio")]
+use tokio::sync::{mpsc::Sender, Mutex, MutexGuard, RwLock};
+
+use crate::{
+    notify::Notify,
+    protocol::{
+        ack::{Ack, Ackable, ACK, NACK},
+        frame::FramePacket,
+        packet::online::{ConnectedPing, OnlinePacket},
+        reliability::Reliability,
+    },
+    rakrs_debug,
+    util::{self, current_epoch, to_address_token},
+};
+
+use super::{
+    controller::{
+        clock::{rak_time_now, ClockEstimator},
+        liveness::{DriverGuard, Liveness},
+        one_way::OneWayDetector,
+        tick::{TickDemand, TickPacer},
+    },
+    event::{CloseSignal, DisconnectReason, RakEvent},
+    queue::{EventSender, RecvQueue, SendQueue},
+    state::ConnectionState,
+    stats::SharedStats,
+    transform::SharedTransform,
+    Connection, PacketGuards, KEEPALIVE_INTERVAL,
+};
+
+/// The tick of a connection: it times the connection out, keeps it alive, retransmits what
+/// was lost and acknowledges what was received.
+pub(crate) struct Ticker {
+    pub(crate) address: SocketAddr,
+    pub(crate) closer: Arc<Notify>,
+    pub(crate) close_signal: CloseSignal,
+    pub(crate) last_recv: Arc<AtomicU64>,
+    pub(crate) send_queue: Arc<RwLock<SendQueue>>,
+    pub(crate) recv_queue: Arc<Mutex<RecvQueue>>,
+    pub(crate) state: Arc<Mutex<ConnectionState>>,
+    pub(crate) liveness: Liveness,
+    pub(crate) clock: Arc<std::sync::Mutex<ClockEstimator>>,
+    pub(crate) event_sender: EventSender,
+    pub(crate) one_way: OneWayDetector,
+    pub(crate) pacer: TickPacer,
+    /// Tells the packet handler the tick gave up on a stalled order channel.
+    pub(crate) release_wake: Sender<()>,
+    pub(crate) next_tick: Instant,
+    pub(crate) _driver: DriverGuard,
+}
+
+impl Ticker {
+    /// When the next tick is due.
+    pub(crate) fn next_tick(&self) -> Instant {
+        self.next_tick
+    }
+
+    /// Pulls the next tick in, see [`TickPacer::wake()`].
+    pub(crate) fn wake(&mut self) {
+        self.next_tick = self.pacer.wake(self.next_tick, util::now());
+    }
+
+    /// Runs the tick, this is false once the connection closed and the tick stops for good.
+    pub(crate) async fn tick(&mut self) -> bool {
+        let address = self.address;
+        self.liveness.tick();
+        let recv = self.last_recv.load(std::sync::atomic::Ordering::Relaxed);
+        let mut cstate = self.state.lock().await;
+
+        if *cstate == ConnectionState::Disconnected {
+            rakrs_debug!(
+                true,
+                "[{}] Connection has been closed due to state!",
+                to_address_token(address)
+            );
+            self.close_signal.close(DisconnectReason::Closed);
+            // closer.notify_all();
+            self.closer.notify().await;
+            return false;
+        }
+
+        if recv + 15 <= current_epoch() {
+            *cstate = ConnectionState::Disconnected;
+            rakrs_debug!(
+                true,
+                "[{}] Connection has been closed due to inactivity!",
+                to_address_token(address)
+            );
+            self.close_signal.close(DisconnectReason::TimedOut {
+                one_way: self.one_way.suspected(),
+            });
+            // closer.notify_all();
+            self.closer.notify().await;
+            return false;
+        }
+
+        if recv + 10 <= current_epoch() && cstate.is_reliable() {
+            *cstate = ConnectionState::TimingOut;
+            rakrs_debug!(
+                true,
+                "[{}] Connection is timing out, sending a ping!",
+                to_address_token(address)
+            );
+        }
+        drop(cstate);
+
+        // taken out first, the packet task keeps reassembling while this sends.
+        let (acks, nacks) = {
+            let mut recv_q = self.recv_queue.lock().await;
+            // a stalled channel is given up on even while nothing arrives.
+            if recv_q.poll_stalls(util::now()) {
+                let _ = self.release_wake.try_send(());
+            }
+            (recv_q.ack_flush(), recv_q.nack_queue().clone())
+        };
+        let mut sendq = self.send_queue.write().await;
+
+        let now = util::now();
+        // the receive time is in whole seconds, so this errs on the side of
+        // the peer not having been heard.
+        let heard_for = Duration::from_secs(current_epoch().saturating_sub(recv) + 1);
+        if self.one_way.check(sendq.unacked_for(now), heard_for) {
+            rakrs_debug!(
+                true,
+                "[{}] Nothing we send seems to arrive, while the peer still reaches us!",
+                to_address_token(address)
+            );
+            let event = RakEvent::SuspectedOneWayLoss { addr: address };
+            if self.event_sender.try_send(event).is_err() {
+                rakrs_debug!(
+                    true,
+                    "[{}] Event channel is full, dropping event!",
+                    to_address_token(address)
+                );
+            }
+        }
+        let keepalive = sendq.keepalive_due(KEEPALIVE_INTERVAL, now);
+        let clock_ping = self.clock.lock().map_or(false, |clock| clock.ping_due(now));
+        if keepalive {
+            let ping = OnlinePacket::ConnectedPing(ConnectedPing {
+                time: rak_time_now(),
+            });
+            if let Ok(ping) = ping.write_to_bytes() {
+                sendq.keepalive(ping.as_slice(), KEEPALIVE_INTERVAL).await;
+            }
+        } else if clock_ping {
+            // a busy connection never needs a keepalive, so the clock
+            // ping rides along with what is sent anyway.
+            sendq.ride_along_ping();
+        }
+        if keepalive || clock_ping {
+            if let Ok(mut clock) = self.clock.lock() {
+                clock.ping_sent(now);
+            }
+        }
+
+        sendq.update().await;
+
+        // Flush the queue of acks and nacks, and respond to them
+        sendq.send_ack_ranges(acks).await;
+
+        // flush nacks from recv queue
+        let nack = Ack::from_ranges(&nacks, true);
+        if nack.records.len() > 0 {
+            if let Ok(p) = nack.write_to_bytes() {
+                sendq.send_stream(p.as_slice()).await;
+            }
+        }
+        // the tick is over, the counters agree with each other.
+        sendq.publish_stats();
+
+        let now = util::now();
+        let demand = TickDemand {
+            backlog: sendq.has_backlog(),
+            ack_debt: !nacks.is_empty(),
+            retransmit_in: sendq.next_retransmit(now),
+            idle_in: sendq.keepalive_in(KEEPALIVE_INTERVAL, now),
+        };
+        self.next_tick = self.pacer.next_tick(now, &demand);
+        true
+    }
+}
+
+/// The packet handler of a connection: it takes the datagrams of the peer apart, and hands
+/// what they carry to the application.
+pub(crate) struct PacketHandler {
+    pub(crate) address: SocketAddr,
+    pub(crate) recv_time: Arc<AtomicU64>,
+    pub(crate) shared_stats: Arc<SharedStats>,
+    pub(crate) transform: SharedTransform,
+    pub(crate) recv_q: Arc<Mutex<RecvQueue>>,
+    pub(crate) send_q: Arc<RwLock<SendQueue>>,
+    pub(crate) disconnect: Arc<Notify>,
+    pub(crate) close_signal: CloseSignal,
+    pub(crate) state: Arc<Mutex<ConnectionState>>,
+    pub(crate) ack_immediately_above: Option<usize>,
+    /// Pulls the next tick in, what was received is acknowledged on it.
+    pub(crate) tick_wake: Sender<()>,
+    /// Where the messages of the peer go, [`Connection::recv()`] reads them.
+    pub(crate) sender: Sender<Vec<u8>>,
+    pub(crate) event_sender: EventSender,
+    pub(crate) guards: PacketGuards,
+    /// Held so the release channel never closes, which would wake the task for good.
+    pub(crate) _release_wake: Sender<()>,
+    pub(crate) _driver: DriverGuard,
+}
+
+impl PacketHandler {
+    /// Handles a datagram of the peer.
+    pub(crate) async fn datagram(&mut self, payload: Vec<u8>) {
+        let address = self.address;
+        // a datagram that doesn't open isn't from the peer, it doesn't keep
+        // the connection alive either.
+        let payload = match self.transform.open(payload) {
+            Ok(payload) => payload,
+            Err(_) => {
+                self.shared_stats
+                    .rejected_datagrams
+                    .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                return;
+            }
+        };
+        // We've recieved a payload!
+        self.recv_time
+            .store(current_epoch(), std::sync::atomic::Ordering::Relaxed);
+        let mut cstate = self.state.lock().await;
+
+        if *cstate == ConnectionState::TimingOut {
+            rakrs_debug!(
+                "[{}] Connection is no longer timing out!",
+                to_address_token(address)
+            );
+            *cstate = ConnectionState::Connected;
+        }
+
+        drop(cstate);
+
+        let id = payload[0];
+        match id {
+            // This is a frame packet.
+            // This packet will be handled by the recv_queue
+            0x80..=0x8f => {
+                let recv_q = self.recv_q.clone();
+                let mut rq = recv_q.lock().await;
+                if self.guards.coalescing {
+                    rq.set_coalescing(true);
+                }
+                // a damaged datagram is dropped before its sequence is recorded,
+                // the peer resends it like a lost one.
+                let datagram = rq.verify(&payload[..]).map(FramePacket::read_from_slice);
+
+                if let Some(Ok(pk)) = datagram {
+                    if let Err(e) = rq.insert(pk) {
+                        rakrs_debug!(
+                            true,
+                            "[{}] Failed to insert frame packet! {:?}",
+                            to_address_token(address),
+                            e
+                        );
+                    };
+
+                    self.release_ready(rq).await;
+                } else {
+                    rakrs_debug!(
+                        true,
+                        "[{}] Failed to parse frame packet!",
+                        to_address_token(address)
+                    );
+                }
+            }
+            NACK => {
+                // Validate this is a nack packet
+
+                if let Ok(nack) = Ack::read_from_slice(&payload[..]) {
+                    // The client acknowledges it did not recieve these packets
+                    // We should resend them.
+                    let mut sq = self.send_q.write().await;
+                    let resend = sq.nack(nack);
+                    sq.resend(resend).await;
+                }
+            }
+            ACK => {
+                // first lets validate this is an ack packet
+                if let Ok(ack) = Ack::read_from_slice(&payload[..]) {
+                    // The client acknowledges it recieved these packets
+                    // We should remove them from the queue.
+                    let mut sq = self.send_q.write().await;
+                    sq.ack(ack);
+                    drop(sq);
+                }
+            }
+            _ => {
+                rakrs_debug!(
+                    "[{}] Unknown RakNet packet recieved (Or packet is sent out of scope).",
+                    to_address_token(address)
+                );
+            }
+        };
+    }
+
+    /// Hands out what the receive queue released after the tick gave up on a stalled channel.
+    pub(crate) async fn release(&mut self) {
+        let recv_q = self.recv_q.clone();
+        let rq = recv_q.lock().await;
+        self.release_ready(rq).await;
+    }
+
+    /// Hands out whatever the receive queue has ready.
+    async fn release_ready(&mut self, mut rq: MutexGuard<'_, RecvQueue>) {
+        let address = self.address;
+        // give up on any order channel that has been stalled for too long.
+        rq.poll_stalls(util::now());
+
+        for event in rq.flush_events() {
+            if let Err(_) = self.event_sender.try_send(event) {
+                rakrs_debug!(
+                    true,
+                    "[{}] Event channel is full, dropping event!",
+                    to_address_token(address)
+                );
+            }
+        }
+
+        let buffers = rq.flush();
+        let abusive = rq.exceeds_budget();
+        // what was received is acknowledged on the next tick.
+        let _ = self.tick_wake.try_send(());
+        // the peer is waiting on a lot of acknowledgements, don't wait for the tick.
+        let acks = match self.ack_immediately_above {
+            Some(limit) if rq.ack_debt() > limit => Some(rq.ack_flush()),
+            _ => None,
+        };
+        drop(rq);
+
+        if let Some(acks) = acks {
+            self.send_q.write().await.send_ack_ranges(acks).await;
+        }
+
+        let mut closing = None;
+
+        for buffer in buffers {
+            let res = Connection::process_packet(
+                &buffer,
+                &address,
+                &self.sender,
+                &self.event_sender,
+                &mut self.guards,
+                &self.send_q,
+                &self.state,
+            )
+            .await;
+            if let Ok(v) = res {
+                if v == true {
+                    // DISCONNECT
+                    // disconnect.close();
+                    rakrs_debug!(
+                        true,
+                        "[{}] Connection::process_packet returned true!",
+                        to_address_token(address)
+                    );
+                    closing = Some(
+                        DisconnectReason::from_packet(&buffer).unwrap_or(DisconnectReason::Closed),
+                    );
+                    break;
+                }
+            }
+            if let Err(e) = res {
+                rakrs_debug!(
+                    "[{}] Failed to process packet: {:?}!",
+                    to_address_token(address),
+                    e
+                );
+            };
+        }
+
+        if abusive && closing.is_none() {
+            rakrs_debug!(
+                true,
+                "[{}] Peer made the connection hold more than its memory budget, disconnecting!",
+                to_address_token(address)
+            );
+            let reason = DisconnectReason::ResourceAbuse;
+            if let Err(_) = self
+                .send_q
+                .write()
+                .await
+                .insert(&reason.to_packet(), Reliability::ReliableOrd, true, Some(0))
+                .await
+            {
+                rakrs_debug!(
+                    true,
+                    "[{}] Failed to send disconnect packet when closing!",
+                    to_address_token(address)
+                );
+            }
+            if self
+                .event_sender
+                .try_send(RakEvent::Disconnected { reason })
+                .is_err()
+            {
+                rakrs_debug!(
+                    true,
+                    "[{}] The events are not read anymore, dropping the disconnect!",
+                    to_address_token(address)
+                );
+            }
+            closing = Some(reason);
+        }
+
+        if let Some(reason) = closing {
+            // a peer that closed waits for its disconnect to be acknowledged.
+            let acks = self.recv_q.lock().await.ack_flush();
+            self.send_q.write().await.send_ack_ranges(acks).await;
+            *self.state.lock().await = ConnectionState::Disconnected;
+            // any event about the disconnect has been queued by now.
+            self.close_signal.close(reason);
+            self.disconnect.notify().await;
+        }
+
+        self.shared_stats.unknown_suppressed.store(
+            self.guards.flood.suppressed(),
+            std::sync::atomic::Ordering::Relaxed,
+        );
+        self.shared_stats.duplicate_handshakes.store(
+            self.guards.handshake.duplicates(),
+            std::sync::atomic::Ordering::Relaxed,
+        );
+    }
+}
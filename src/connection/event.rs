@@ -0,0 +1,263 @@
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use futures::channel::oneshot;
+use futures::future::{FutureExt, Shared};
+
+use crate::protocol::index::OrderIndex;
+#[cfg(feature = "server")]
+use crate::server::advertisement::PingResponder;
+
+/// Events that happen on a connection outside of the normal packet stream.
+/// These can be read with [`Connection::recv_event()`] or [`Client::recv_event()`], and the
+/// events of a listener itself with [`Listener::recv_event()`].
+///
+/// [`Connection::recv_event()`]: crate::connection::Connection::recv_event
+/// [`Client::recv_event()`]: crate::client::Client::recv_event
+/// [`Listener::recv_event()`]: crate::server::Listener::recv_event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RakEvent {
+    /// A reliable-ordered message was missing for longer than
+    /// [`ConnOptions::ordered_stall_limit`] and was skipped, the messages after it have
+    /// been delivered without it.
+    ///
+    /// [`ConnOptions::ordered_stall_limit`]: crate::connection::options::ConnOptions::ordered_stall_limit
+    OrderedGap {
+        /// The order channel the gap occurred on.
+        channel: u8,
+        /// The order index that was given up on.
+        skipped_index: OrderIndex,
+    },
+    /// The peer completed the handshake, this is emitted once per connection even if the
+    /// peer retransmits the end of its handshake.
+    Connected,
+    /// The peer closed the connection in an orderly fashion, giving a reason.
+    /// This is only emitted when the peer sent a reason, see [`Connection::begin_drain_and_close()`],
+    /// or with [`DisconnectReason::Internal`] when the listener reclaimed a connection whose
+    /// tasks died.
+    ///
+    /// [`Connection::begin_drain_and_close()`]: crate::connection::Connection::begin_drain_and_close
+    Disconnected {
+        /// Why the peer closed the connection.
+        reason: DisconnectReason,
+    },
+    /// The server answered the handshake from another port than the one the client connected
+    /// to, and the client followed it, see
+    /// [`Client::allow_port_rebind()`](crate::client::Client::allow_port_rebind).
+    /// Only a client emits this, once, before anything else.
+    PortRebound {
+        /// The address the client connected to.
+        requested: SocketAddr,
+        /// The address the connection is with.
+        peer: SocketAddr,
+    },
+    /// The peer broke the protocol in a way that did not warrant closing the connection.
+    ProtocolViolation {
+        /// What the peer did.
+        kind: ViolationKind,
+    },
+    /// A peer pinged the listener, which leaves the answer to the application, see
+    /// [`Listener::offline_ping_passthrough`]. Only a listener emits this, from
+    /// [`Listener::recv_event()`].
+    ///
+    /// [`Listener::offline_ping_passthrough`]: crate::server::Listener::offline_ping_passthrough
+    /// [`Listener::recv_event()`]: crate::server::Listener::recv_event
+    #[cfg(feature = "server")]
+    PingRequest {
+        /// Who is pinging.
+        addr: SocketAddr,
+        /// The guid the peer gave in its ping.
+        client_guid: i64,
+        /// Answers the ping.
+        respond: PingResponder,
+    },
+    /// Reliable data went unacknowledged for longer than
+    /// [`ConnOptions::one_way_fail_threshold`] while the peer kept sending, so what we send
+    /// likely doesn't reach it anymore. The peer will probably give up on the connection soon,
+    /// this is the chance to warn the user or move the connection elsewhere.
+    ///
+    /// [`ConnOptions::one_way_fail_threshold`]: crate::connection::options::ConnOptions::one_way_fail_threshold
+    SuspectedOneWayLoss {
+        /// The peer that can't be reached.
+        addr: SocketAddr,
+    },
+}
+
+impl RakEvent {
+    /// Whether this event is about the connection itself rather than about the messages it
+    /// carries, which is every event but [`RakEvent::OrderedGap`].
+    ///
+    /// Lifecycle events are read ahead of the others, so a flood of messages never holds up
+    /// a [`RakEvent::Disconnected`]. They still come after the events that happened before them.
+    pub fn is_lifecycle(&self) -> bool {
+        !matches!(self, Self::OrderedGap { .. })
+    }
+}
+
+/// The events of a [`Client`], read with [`Client::recv_event()`].
+///
+/// [`Client`]: crate::client::Client
+/// [`Client::recv_event()`]: crate::client::Client::recv_event
+pub type ClientEvent = RakEvent;
+
+/// The kinds of [`RakEvent::ProtocolViolation`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// The peer sent too many packets with an unknown id, these are now being dropped.
+    /// See [`ConnOptions::unknown_flood_threshold`].
+    ///
+    /// [`ConnOptions::unknown_flood_threshold`]: crate::connection::options::ConnOptions::unknown_flood_threshold
+    UnknownFlood,
+    /// The path to the peer damaged more than [`CORRUPT_DATAGRAM_THRESHOLD`] datagrams,
+    /// see [`ConnOptions::datagram_checksum`]. The damaged datagrams are resent like lost ones.
+    ///
+    /// [`CORRUPT_DATAGRAM_THRESHOLD`]: crate::connection::CORRUPT_DATAGRAM_THRESHOLD
+    /// [`ConnOptions::datagram_checksum`]: crate::connection::options::ConnOptions::datagram_checksum
+    CorruptDatagrams,
+    /// The peer sent an ordered or sequenced message too far ahead of the one its channel
+    /// expects, it was dropped. See [`ConnOptions::order_jump_limit`].
+    ///
+    /// [`ConnOptions::order_jump_limit`]: crate::connection::options::ConnOptions::order_jump_limit
+    OrderJump,
+    /// More than [`SPOOFED_DATAGRAM_THRESHOLD`] datagrams of the connection arrived from
+    /// another port of its host, see [`AddressBinding::Strict`]. They were dropped.
+    ///
+    /// [`SPOOFED_DATAGRAM_THRESHOLD`]: crate::server::SPOOFED_DATAGRAM_THRESHOLD
+    /// [`AddressBinding::Strict`]: crate::connection::options::AddressBinding::Strict
+    SpoofedSource {
+        /// The address the connection is bound to.
+        bound: SocketAddr,
+        /// The address the datagrams came from.
+        from: SocketAddr,
+    },
+}
+
+/// Why a connection was closed.
+///
+/// This is sent as a single byte after the disconnect packet id, peers that don't know about
+/// it will ignore the byte. [`DisconnectReason::TimedOut`] is never sent by rak-rs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum DisconnectReason {
+    /// The connection was closed without anything special happening.
+    Closed = 0,
+    /// The peer is being moved to another server.
+    Transferred = 1,
+    /// The server is shutting down.
+    Shutdown = 2,
+    /// The peer was kicked.
+    Kicked = 3,
+    /// The peer stopped responding, the connection was closed without telling it.
+    TimedOut {
+        /// Whether the peer kept reaching us for a while after we stopped reaching it,
+        /// see [`RakEvent::SuspectedOneWayLoss`].
+        one_way: bool,
+    } = 4,
+    /// The tasks driving the connection died, and the listener reclaimed it.
+    /// This points at a bug, like a panic while handling a packet.
+    Internal = 5,
+    /// The socket of the listener failed for good, like when its interface was removed.
+    /// The peer could not be told, see [`Listener::socket_failure_threshold`].
+    ///
+    /// [`Listener::socket_failure_threshold`]: crate::server::Listener::socket_failure_threshold
+    SocketFailure = 6,
+    /// The peer made the connection hold more than
+    /// [`ConnOptions::max_connection_memory`] of data it can't let go of, like fragments
+    /// of messages that never complete.
+    ///
+    /// [`ConnOptions::max_connection_memory`]: crate::connection::options::ConnOptions::max_connection_memory
+    ResourceAbuse = 7,
+}
+
+impl DisconnectReason {
+    /// Reads the reason from a raw disconnect packet, if it has one.
+    pub fn from_packet(buffer: &[u8]) -> Option<Self> {
+        match buffer.get(1)? {
+            0 => Some(Self::Closed),
+            1 => Some(Self::Transferred),
+            2 => Some(Self::Shutdown),
+            3 => Some(Self::Kicked),
+            4 => Some(Self::TimedOut { one_way: false }),
+            5 => Some(Self::Internal),
+            6 => Some(Self::SocketFailure),
+            7 => Some(Self::ResourceAbuse),
+            _ => None,
+        }
+    }
+
+    /// Writes a raw disconnect packet carrying this reason.
+    pub fn to_packet(self) -> Vec<u8> {
+        let reason = match self {
+            Self::Closed => 0,
+            Self::Transferred => 1,
+            Self::Shutdown => 2,
+            Self::Kicked => 3,
+            Self::TimedOut { .. } => 4,
+            Self::Internal => 5,
+            Self::SocketFailure => 6,
+            Self::ResourceAbuse => 7,
+        };
+        vec![0x15, reason]
+    }
+}
+
+/// Resolves with the [`DisconnectReason`] once a connection has terminated, this is returned by
+/// [`Connection::closed()`] and [`Client::closed()`].
+///
+/// Any amount of clones can be awaited at once, they all resolve with the same reason.
+/// Awaiting it after the connection terminated resolves right away.
+///
+/// [`Connection::closed()`]: crate::connection::Connection::closed
+/// [`Client::closed()`]: crate::client::Client::closed
+#[derive(Clone)]
+pub struct Closed(Shared<oneshot::Receiver<DisconnectReason>>);
+
+impl Future for Closed {
+    type Output = DisconnectReason;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // the signal is only dropped without a reason when every part of the connection is gone.
+        self.0
+            .poll_unpin(cx)
+            .map(|reason| reason.unwrap_or(DisconnectReason::Closed))
+    }
+}
+
+/// The side of [`Closed`] that is fired by whichever teardown path runs first.
+#[derive(Clone)]
+pub(crate) struct CloseSignal(Arc<Mutex<Option<oneshot::Sender<DisconnectReason>>>>);
+
+impl CloseSignal {
+    pub(crate) fn new() -> (Self, Closed) {
+        let (sender, receiver) = oneshot::channel();
+        (
+            Self(Arc::new(Mutex::new(Some(sender)))),
+            Closed(receiver.shared()),
+        )
+    }
+
+    /// Whether [`Closed`] was resolved already.
+    pub(crate) fn is_closed(&self) -> bool {
+        self.0.lock().map(|sender| sender.is_none()).unwrap_or(true)
+    }
+
+    /// Resolves [`Closed`] with the reason, returns `false` if it was already resolved.
+    pub(crate) fn close(&self, reason: DisconnectReason) -> bool {
+        let sender = match self.0.lock() {
+            Ok(mut sender) => sender.take(),
+            Err(_) => None,
+        };
+
+        match sender {
+            Some(sender) => {
+                // nobody might be waiting for it, which is fine.
+                let _ = sender.send(reason);
+                true
+            }
+            None => false,
+        }
+    }
+}
@@ -8,8 +8,11 @@
 //! - [`ConnectionMeta`]: The connection meta struct, which is used to hold the meta information of the connection.
 //!
 //! This module also contains the following submodules:
-//! - [`controller`]: The controller submodule, which is used to handle relability of the connection.
-//! - [`queue`]: The queue submodule, which is used to handle the connection queues.
+//! - [`codec`]: The codec submodule, which sends and receives typed messages (`codec` feature).
+//! - [`descriptor`]: The descriptor submodule, which is used to hand sessions over to another listener.
+//! - [`event`]: The event submodule, which contains the events a connection can emit.
+//! - [`options`]: The options submodule, which is used to configure a connection.
+//! - [`stats`]: The stats submodule, which contains the statistics a connection collects.
 //! - [`state`]: The state submodule, which is used to handle the connection state.
 //!
 //! # Example
@@ -39,18 +42,58 @@
 //! [`Connection`]: crate::connection::Connection
 //! [`ConnectionState`]: crate::connection::state::ConnectionState
 //! [`ConnectionMeta`]: crate::connection::ConnectionMeta
-//! [`controller`]: crate::connection::controller
-//! [`queue`]: crate::connection::queue
+//! [`codec`]: crate::connection::codec
+//! [`descriptor`]: crate::connection::descriptor
+//! [`event`]: crate::connection::event
+//! [`options`]: crate::connection::options
 //! [`state`]: crate::connection::state
+//! [`stats`]: crate::connection::stats
+//! [`transform`]: crate::connection::transform
+/// Typed messages over a connection, with the `codec` feature.
+#[cfg(feature = "codec")]
+pub mod codec;
+#[doc(hidden)]
 pub mod controller;
+/// Descriptors of live sessions, to resume them in another listener.
+pub mod descriptor;
+/// The tick and the packet handler that drive a connection.
+pub(crate) mod driver;
+/// Events that can happen on a connection.
+pub mod event;
+/// Options for a connection.
+pub mod options;
 /// Necessary queues for the connection.
+#[doc(hidden)]
 pub mod queue;
 pub mod state;
+/// Statistics collected by a connection.
+pub mod stats;
+/// Transforms applied to every datagram of a connection, like encryption.
+pub mod transform;
+
+pub use crate::util::socket::EcnSupport;
+pub use controller::budget::{BytesPerSec, SendBudgetStats};
+pub use controller::checksum::CORRUPT_DATAGRAM_THRESHOLD;
+pub use controller::clock::RakTime;
+pub use controller::flood::USER_PACKET_ID;
+pub use controller::jitter::RngProvider;
+pub use controller::loss::LossBySize;
+pub use controller::profile::{Capacity, LatencyClass, Profile};
+pub use controller::rtt::RttStats;
+pub use queue::SendQueueError;
+
+/// The address a connection is known by, in a [`Listener`] and in the events about it.
+///
+/// [`Listener`]: crate::server::Listener
+pub type ConnId = SocketAddr;
 
 use std::{
     net::{IpAddr, Ipv4Addr, SocketAddr},
-    sync::{atomic::AtomicU64, Arc},
-    time::Duration,
+    sync::{
+        atomic::{AtomicBool, AtomicU64},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
 use binary_util::interfaces::{Reader, Writer};
@@ -58,12 +101,14 @@ use binary_util::interfaces::{Reader, Writer};
 #[cfg(feature = "async_std")]
 use async_std::{
     channel::{bounded, Receiver, RecvError, Sender},
+    future::timeout,
     net::UdpSocket,
     sync::{Mutex, RwLock},
     task::{self, sleep, JoinHandle},
 };
 #[cfg(feature = "async_std")]
-use futures::{select, FutureExt};
+use futures::select;
+use futures::FutureExt;
 #[cfg(feature = "async_tokio")]
 use tokio::{
     net::UdpSocket,
@@ -73,7 +118,7 @@ use tokio::{
         Mutex, RwLock,
     },
     task::{self, JoinHandle},
-    time::sleep,
+    time::{sleep, timeout},
 };
 #[cfg(feature = "async_tokio")]
 pub enum RecvError {
@@ -84,32 +129,59 @@ pub enum RecvError {
 use crate::{
     notify::Notify,
     protocol::{
-        ack::{Ack, Ackable, ACK, NACK},
-        frame::FramePacket,
+        frame::FrameRef,
         packet::{
             offline::OfflinePacket,
-            online::{ConnectedPing, ConnectedPong, ConnectionAccept, Disconnect, OnlinePacket},
+            online::{
+                Capabilities, ConnectedPong, ConnectionAccept, CrateVersion, Disconnect,
+                OnlinePacket,
+            },
         },
+        primitives::decode,
         reliability::Reliability,
     },
     rakrs_debug,
-    server::current_epoch,
-    util::to_address_token,
+    util::{self, current_epoch, to_address_token},
 };
 
+#[cfg(feature = "server")]
+use self::{controller::budget::BudgetShare, descriptor::SessionDescriptor};
 use self::{
-    queue::{RecvQueue, SendQueue, SendQueueError},
+    controller::{
+        clock::{rak_time_now, ClockEstimator},
+        consumer::ConsumerGuard,
+        flood::{FloodVerdict, UnknownFloodGuard},
+        handshake::HandshakeGuard,
+        liveness::Liveness,
+        memory::MemBudget,
+        one_way::OneWayDetector,
+        tick::TickPacer,
+    },
+    driver::{PacketHandler, Ticker},
+    event::{CloseSignal, Closed, DisconnectReason, RakEvent, ViolationKind},
+    options::ConnOptions,
+    queue::{event_queue, EventReceiver, EventSender, RecvQueue, SendQueue},
     state::ConnectionState,
+    stats::{ConnectionStats, SharedStats},
+    transform::{DatagramTransform, SharedTransform},
 };
+#[cfg(feature = "server")]
+use crate::protocol::index::DatagramSeq;
 pub(crate) type ConnNetChan = Arc<Mutex<Receiver<Vec<u8>>>>;
 
+/// How long a connection may go without sending anything before it pings the peer.
+/// Every datagram received, acknowledgements included, proves the peer is alive.
+pub(crate) const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(3);
+
 #[derive(Debug, Clone, Copy)]
 pub struct ConnMeta {
     /// This is important, and is stored within the server itself
-    /// This value is 0 until the connection state is `Connecting`
+    /// This is the mtu the session was created with, it doesn't change afterwards.
     pub mtu_size: u16,
     /// The time this connection last sent any data. This will be used during server tick.
     pub recv_time: u64,
+    peer_crate_version: Option<CrateVersion>,
+    pub(crate) rebound_from: Option<SocketAddr>,
 }
 
 impl ConnMeta {
@@ -117,8 +189,26 @@ impl ConnMeta {
         Self {
             mtu_size,
             recv_time: current_epoch(),
+            peer_crate_version: None,
+            rebound_from: None,
         }
     }
+
+    /// The version of rak-rs the peer runs, useful to track a rollout across a fleet.
+    ///
+    /// This is `None` for vanilla peers, for older versions of rak-rs, and for peers that
+    /// didn't offer any extension, as the version is only sent along with one.
+    pub fn peer_crate_version(&self) -> Option<CrateVersion> {
+        self.peer_crate_version
+    }
+
+    /// The address a client connected to, if the server answered from another port and the
+    /// client followed it, see [`Client::allow_port_rebind()`]. Always `None` on a server.
+    ///
+    /// [`Client::allow_port_rebind()`]: crate::client::Client::allow_port_rebind
+    pub fn rebound_from(&self) -> Option<SocketAddr> {
+        self.rebound_from
+    }
 }
 
 /// The connection struct contains the logic for a connection to the server.
@@ -162,6 +252,19 @@ impl ConnMeta {
 ///         struct.
 ///     </p>
 /// </div>
+///
+/// ## Locking
+/// The state of a connection is split over independent locks, so the task reading datagrams,
+/// the tick and the handles don't hold each other up: the send queue, the receive queue and
+/// the [`ConnectionState`]. Counters are published in a [`SharedStats`] and read without any
+/// lock. The few operations that need more than one, like exporting or tearing down a session,
+/// take them in this order, and hold none of them across the other ones:
+/// 1. the [`ConnectionState`],
+/// 2. the send queue,
+/// 3. the receive queue.
+///
+/// The receive queue is never held while a datagram is sent, what the send side needs from
+/// it (the sequences to acknowledge) is taken out first.
 pub struct Connection {
     /// The address of the connection
     /// This is internally tokenized by rak-rs
@@ -175,9 +278,34 @@ pub struct Connection {
     /// The network channel, this is where the connection will be recieving it's packets.
     /// This is interfaced to provide the api for `Connection::recv()`
     internal_net_recv: ConnNetChan,
+    /// The channel events for this connection are dispatched on.
+    /// This is interfaced to provide the api for `Connection::recv_event()`
+    internal_event_recv: Arc<EventReceiver>,
+    /// The round trip state of the connection, shared with the send queue.
+    rtt_stats: Arc<std::sync::Mutex<RttStats>>,
+    /// The estimate of the clock of the peer, fed by the pongs to our pings.
+    clock: Arc<std::sync::Mutex<ClockEstimator>>,
+    /// The counters of the connection, published by the queues and the packet task.
+    shared_stats: Arc<SharedStats>,
+    /// Seals and opens the datagrams of the connection, shared with the send queue.
+    transform: SharedTransform,
+    /// Whether the connection is draining before it closes, new payloads are rejected while it is.
+    draining: Arc<AtomicBool>,
+    /// See [`ConnOptions::ack_immediately_above`].
+    ack_immediately_above: Option<usize>,
+    /// See [`ConnOptions::lan_frame_budget`].
+    lan_frame_budget: Option<usize>,
+    /// See [`ConnOptions::disconnect_linger`].
+    disconnect_linger: Duration,
+    /// The version of rak-rs the peer advertised during the handshake.
+    peer_crate_version: Arc<std::sync::Mutex<Option<CrateVersion>>>,
     /// A notifier for when the connection should close.
     /// This is used for absolute cleanup withtin the connection
     disconnect: Arc<Notify>,
+    /// Fired with the reason once the connection has terminated.
+    close_signal: CloseSignal,
+    /// Handed out by [`Connection::closed()`].
+    closed: Closed,
     /// The event dispatcher for the connection.
     // evt_sender: Sender<(ServerEvent, oneshot::Sender<ServerEventResponse>)>,
     /// The event receiver for the connection.
@@ -186,6 +314,272 @@ pub struct Connection {
     /// being in memory longer than it should be.
     recv_time: Arc<AtomicU64>,
     tasks: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    /// Whether the tasks of the connection are still running, the listener checks on it.
+    liveness: Liveness,
+    /// Handed to the listener with the first [`Connection::handle()`], so it can tell a user
+    /// the connection was reclaimed. The connection does not keep it, or
+    /// [`Connection::recv_event()`] would never see the tasks stop.
+    #[cfg(feature = "server")]
+    reclaim_events: std::sync::Mutex<Option<EventSender>>,
+    /// Pulls the next tick in, see [`TickPacer::wake()`].
+    tick_wake: Sender<()>,
+    /// The reliability and channel of [`Connection::send_default()`].
+    send_defaults: Arc<std::sync::Mutex<(Reliability, u8)>>,
+    /// How much of ECN the socket of the connection supports.
+    ecn: EcnSupport,
+}
+
+/// The controllers [`Connection::process_packet()`] consults, owned by the task
+/// handling the packets of a connection.
+pub(crate) struct PacketGuards {
+    pub(crate) flood: UnknownFloodGuard,
+    pub(crate) handshake: HandshakeGuard,
+    /// The extensions this side offers to the peer.
+    pub(crate) capabilities: Capabilities,
+    /// Where the version of rak-rs the peer advertised is kept.
+    pub(crate) peer_crate_version: Arc<std::sync::Mutex<Option<CrateVersion>>>,
+    /// Where the pongs to our pings go.
+    pub(crate) clock: Arc<std::sync::Mutex<ClockEstimator>>,
+    /// Whether the peer agreed to coalescing, see [`Capabilities::COALESCING`].
+    pub(crate) coalescing: bool,
+    /// Whether the application still receives the messages of the peer.
+    pub(crate) consumer: ConsumerGuard,
+    /// Where the messages dropped for a gone application are counted.
+    pub(crate) shared_stats: Arc<SharedStats>,
+}
+
+/// The drivers of a [`Connection`] built by [`Connection::with_send_queue()`], along with the
+/// channels that wake them.
+pub(crate) struct ConnDrivers {
+    pub(crate) ticker: Ticker,
+    pub(crate) packets: PacketHandler,
+    /// Pulls the next tick in, see [`Ticker::wake()`].
+    pub(crate) tick_woken: Receiver<()>,
+    /// The tick gave up on a stalled order channel, see [`PacketHandler::release()`].
+    pub(crate) released: Receiver<()>,
+}
+
+/// The parts of a [`Connection`] its listener holds on to, so the session can be exported
+/// after the connection itself was handed out.
+#[cfg(feature = "server")]
+#[derive(Clone)]
+pub(crate) struct ConnHandle {
+    address: SocketAddr,
+    state: Arc<Mutex<ConnectionState>>,
+    send_queue: Arc<RwLock<SendQueue>>,
+    recv_queue: Arc<Mutex<RecvQueue>>,
+    recv_time: Arc<AtomicU64>,
+    peer_crate_version: Arc<std::sync::Mutex<Option<CrateVersion>>>,
+    tasks: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    close_signal: CloseSignal,
+    liveness: Liveness,
+    events: Option<EventSender>,
+    shared_stats: Arc<SharedStats>,
+}
+
+#[cfg(feature = "server")]
+impl ConnHandle {
+    /// Describes the session, `None` if it is not established.
+    pub(crate) async fn export(&self) -> Option<SessionDescriptor> {
+        if !matches!(
+            *self.state.lock().await,
+            ConnectionState::Connected | ConnectionState::TimingOut
+        ) {
+            return None;
+        }
+
+        let send_queue = self.send_queue.read().await;
+        Some(SessionDescriptor {
+            address: send_queue.address(),
+            mtu_size: send_queue.mtu_size(),
+            recv_time: self.recv_time.load(std::sync::atomic::Ordering::Relaxed),
+            send: send_queue.counters(),
+            recv: self.recv_queue.lock().await.counters(),
+            capabilities: {
+                let mut capabilities = if send_queue.datagram_checksum() {
+                    Capabilities::DATAGRAM_CHECKSUM
+                } else {
+                    Capabilities::NONE
+                };
+                if send_queue.coalescing() {
+                    capabilities = capabilities.with(Capabilities::COALESCING);
+                }
+                match *self.peer_crate_version.lock().unwrap() {
+                    Some(version) => capabilities.with_crate_version(version),
+                    None => capabilities,
+                }
+            },
+        })
+    }
+
+    /// The time in seconds since the epoch a packet was last received from the peer.
+    pub(crate) fn recv_time(&self) -> u64 {
+        self.recv_time.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// The address the connection was made from, the peer may have moved since.
+    pub(crate) fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Whether `sequence` is one of the datagrams of the established connection, under way
+    /// or retransmitted, see [`RecvQueue::is_near()`].
+    pub(crate) async fn expects(&self, sequence: DatagramSeq) -> bool {
+        matches!(
+            *self.state.lock().await,
+            ConnectionState::Connected | ConnectionState::TimingOut
+        ) && self.recv_queue.lock().await.is_near(sequence)
+    }
+
+    /// Counts a datagram of the connection that came from `from` rather than `bound`, the
+    /// application is told once there were more than [`SPOOFED_DATAGRAM_THRESHOLD`].
+    ///
+    /// [`SPOOFED_DATAGRAM_THRESHOLD`]: crate::server::SPOOFED_DATAGRAM_THRESHOLD
+    pub(crate) fn spoofed(&self, bound: SocketAddr, from: SocketAddr) {
+        let spoofed = self
+            .shared_stats
+            .spoofed_datagrams
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        if spoofed == crate::server::SPOOFED_DATAGRAM_THRESHOLD {
+            rakrs_debug!(
+                "[{}] Datagrams of the connection keep arriving from {}!",
+                to_address_token(bound),
+                to_address_token(from)
+            );
+            if let Some(events) = &self.events {
+                let _ = events.try_send(RakEvent::ProtocolViolation {
+                    kind: ViolationKind::SpoofedSource { bound, from },
+                });
+            }
+        }
+    }
+
+    /// Sends everything to `address` from now on, the peer moved there.
+    pub(crate) async fn migrate(&self, address: SocketAddr) {
+        self.send_queue.write().await.set_address(address);
+    }
+
+    /// Whether both are handles of the same connection.
+    pub(crate) fn is(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.tasks, &other.tasks)
+    }
+
+    pub(crate) fn liveness(&self) -> &Liveness {
+        &self.liveness
+    }
+
+    /// Stops whatever is left of a connection the listener found dead, see
+    /// [`Janitor`](controller::liveness::Janitor).
+    ///
+    /// A connection that was closed already is only cleaned up, otherwise it is closed with
+    /// [`DisconnectReason::Internal`], and its state is logged for the bug report.
+    pub(crate) async fn reclaim(&self) {
+        let state = std::mem::replace(&mut *self.state.lock().await, ConnectionState::Disconnected);
+        if self.close_signal.close(DisconnectReason::Internal) {
+            rakrs_debug!(
+                "[{}] Reclaimed a connection whose tasks died! state: {:?}, tasks alive: {}, ticks: {}, last received: {}, mtu: {}, memory: {}",
+                to_address_token(self.address),
+                state,
+                self.liveness.drivers(),
+                self.liveness.ticks(),
+                self.recv_time(),
+                self.send_queue.read().await.mtu_size(),
+                self.recv_queue.lock().await.memory_budget().used()
+            );
+            if let Some(events) = &self.events {
+                let _ = events.try_send(RakEvent::Disconnected {
+                    reason: DisconnectReason::Internal,
+                });
+            }
+        }
+
+        for task in self.tasks.lock().await.drain(..) {
+            #[cfg(feature = "async_std")]
+            task.cancel().await;
+            #[cfg(feature = "async_tokio")]
+            task.abort();
+        }
+    }
+
+    /// Stops all tasks of the connection without telling the peer, the session lives on
+    /// in whichever listener imports it.
+    pub(crate) async fn detach(&self) {
+        self.stop(DisconnectReason::Transferred).await;
+    }
+
+    /// Stops all tasks of the connection without telling the peer, once its listener shut down.
+    /// The socket of the listener is released, even if the connection is still held.
+    pub(crate) async fn abandon(&self) {
+        self.stop(DisconnectReason::Shutdown).await;
+        self.send_queue.write().await.release_socket();
+    }
+
+    /// Stops all tasks of the connection once the socket of its listener failed, the
+    /// application is told with [`DisconnectReason::SocketFailure`], the peer can't be anymore.
+    pub(crate) async fn fail(&self) {
+        if self.stop(DisconnectReason::SocketFailure).await {
+            if let Some(events) = &self.events {
+                let _ = events.try_send(RakEvent::Disconnected {
+                    reason: DisconnectReason::SocketFailure,
+                });
+            }
+        }
+        self.send_queue.write().await.release_socket();
+    }
+
+    /// Returns whether the connection was still open.
+    async fn stop(&self, reason: DisconnectReason) -> bool {
+        *self.state.lock().await = ConnectionState::Disconnected;
+        // before the tasks are stopped, a tick in between would see the state as closed.
+        let closed = self.close_signal.close(reason);
+
+        for task in self.tasks.lock().await.drain(..) {
+            #[cfg(feature = "async_std")]
+            task.cancel().await;
+            #[cfg(feature = "async_tokio")]
+            task.abort();
+        }
+        // a task that was cancelled halfway through may have set it back.
+        *self.state.lock().await = ConnectionState::Disconnected;
+        closed
+    }
+}
+
+/// Dropping a connection stops its tasks right away, and its listener forgets the session.
+///
+/// This does not tell the peer, which times out on its own. Prefer [`Connection::close()`],
+/// dropping only guarantees nothing keeps running for a connection that is gone.
+impl Drop for Connection {
+    fn drop(&mut self) {
+        #[cfg(feature = "async_std")]
+        let state = self.state.try_lock();
+        #[cfg(feature = "async_tokio")]
+        let state = self.state.try_lock().ok();
+        if let Some(mut state) = state {
+            *state = ConnectionState::Disconnected;
+        }
+        self.close_signal.close(DisconnectReason::Closed);
+        // the tasks stop at their next turn, the tick removes the session from the listener.
+        self.disconnect.notify_now();
+    }
+}
+
+/// The outcome of [`Connection::begin_drain_and_close()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrainResult {
+    /// Everything that was queued was acknowledged by the peer before closing.
+    Drained,
+    /// The drain timeout passed before everything was acknowledged.
+    TimedOut {
+        /// The amount of frames that were still unacknowledged.
+        unacknowledged: usize,
+    },
+    /// The connection was closed for another reason before the drain finished, for instance
+    /// because the peer left or timed out. The drain gives up on it right away.
+    Aborted {
+        /// Why the connection was closed.
+        reason: DisconnectReason,
+    },
 }
 
 impl Connection {
@@ -196,124 +590,224 @@ impl Connection {
         net: Receiver<Vec<u8>>,
         notifier: Arc<Sender<SocketAddr>>,
         mtu: u16,
+        options: ConnOptions,
     ) -> Self {
+        let send_queue = SendQueue::new(mtu, 12000, 5, socket.clone(), address);
+        let (c, drivers) = Self::with_send_queue(address, send_queue, options);
+
+        let tk = c.tasks.clone();
+        let mut tasks = tk.lock().await;
+        tasks.push(c.init_tick(notifier, drivers.ticker, drivers.tick_woken));
+        tasks.push(c.init_net_recv(net, drivers.packets, drivers.released));
+
+        return c;
+    }
+
+    /// Builds a connection sending through `send_queue`, without starting its drivers.
+    /// [`Connection::new()`] spawns a task for each, the [`simulation`](crate::simulation)
+    /// runs them itself.
+    pub(crate) fn with_send_queue(
+        address: SocketAddr,
+        mut send_queue: SendQueue,
+        options: ConnOptions,
+    ) -> (Self, ConnDrivers) {
         let (net_sender, net_receiver) = bounded::<Vec<u8>>(100);
+        let (event_sender, event_receiver) = event_queue();
+        // a wake that is already pending covers the ones after it.
+        let (tick_wake, tick_woken) = bounded::<()>(1);
+        // the tick gave up on a stalled order channel, the packet task hands out what it released.
+        let (release_wake, released) = bounded::<()>(1);
+        send_queue.set_mtu_reprobe_interval(options.mtu_reprobe_interval);
+        send_queue.set_frame_budget(options.frame_budget);
+        send_queue.set_coalesce_channels(options.coalesce_channels.clone());
+        send_queue.set_rng(RngProvider::for_address(options.rng_seed, address));
+        let rtt_stats = send_queue.rtt_stats();
+        let shared_stats = send_queue.shared_stats();
+        let transform = send_queue.transform();
+        let memory = MemBudget::new(options.max_connection_memory);
+        send_queue.set_memory_budget(memory.clone());
+        let mut recv_queue = RecvQueue::new();
+        recv_queue.set_shared_stats(shared_stats.clone());
+        recv_queue.set_memory_budget(memory);
+        recv_queue.set_ordered_stall_limit(options.ordered_stall_limit);
+        recv_queue.set_order_jump_limit(options.order_jump_limit);
+        recv_queue.set_datagram_checksum(options.datagram_checksum);
+        let flood_guard = UnknownFloodGuard::new(
+            options.unknown_flood_threshold,
+            options.unknown_flood_window,
+            &options.claimed_ids,
+        );
+        let (close_signal, closed) = CloseSignal::new();
         // let (evt_sender, evt_receiver) = mpsc::channel::<(ServerEvent, oneshot::Sender<ServerEventResponse>)>(10);
         let c = Self {
             address,
-            send_queue: Arc::new(RwLock::new(SendQueue::new(
-                mtu,
-                12000,
-                5,
-                socket.clone(),
-                address,
-            ))),
-            recv_queue: Arc::new(Mutex::new(RecvQueue::new())),
+            send_queue: Arc::new(RwLock::new(send_queue)),
+            recv_queue: Arc::new(Mutex::new(recv_queue)),
             internal_net_recv: Arc::new(Mutex::new(net_receiver)),
+            internal_event_recv: Arc::new(event_receiver),
+            rtt_stats,
+            clock: Arc::new(std::sync::Mutex::new(ClockEstimator::new())),
+            shared_stats,
+            transform,
             // evt_sender,
             // evt_receiver,
             state: Arc::new(Mutex::new(ConnectionState::Unidentified)),
             // disconnect: Arc::new(Condvar::new()),
+            draining: Arc::new(AtomicBool::new(false)),
+            ack_immediately_above: options.ack_immediately_above,
+            lan_frame_budget: options.lan_frame_budget,
+            disconnect_linger: options.disconnect_linger,
+            peer_crate_version: Arc::new(std::sync::Mutex::new(None)),
             disconnect: Arc::new(Notify::new()),
+            close_signal,
+            closed,
             recv_time: Arc::new(AtomicU64::new(current_epoch())),
             tasks: Arc::new(Mutex::new(Vec::new())),
+            liveness: Liveness::new(),
+            #[cfg(feature = "server")]
+            reclaim_events: std::sync::Mutex::new(Some(event_sender.clone())),
+            tick_wake,
+            send_defaults: Arc::new(std::sync::Mutex::new((
+                options.default_reliability,
+                options.default_channel,
+            ))),
+            ecn: EcnSupport::Off,
         };
 
-        let tk = c.tasks.clone();
-        let mut tasks = tk.lock().await;
-        tasks.push(c.init_tick(notifier));
-        tasks.push(c.init_net_recv(net, net_sender));
+        let pacer = TickPacer::new(options.tick_interval_min, options.tick_interval_max);
+        let ticker = Ticker {
+            address,
+            closer: c.disconnect.clone(),
+            close_signal: c.close_signal.clone(),
+            last_recv: c.recv_time.clone(),
+            send_queue: c.send_queue.clone(),
+            recv_queue: c.recv_queue.clone(),
+            state: c.state.clone(),
+            liveness: c.liveness.clone(),
+            clock: c.clock.clone(),
+            event_sender: event_sender.clone(),
+            one_way: OneWayDetector::new(options.one_way_fail_threshold),
+            pacer,
+            release_wake: release_wake.clone(),
+            next_tick: util::now() + pacer.max(),
+            _driver: c.liveness.driver(),
+        };
+        let guards = PacketGuards {
+            flood: flood_guard,
+            handshake: HandshakeGuard::new(),
+            // only versioned offers get a versioned reply, so vanilla peers never see it.
+            // bundles can always be split, whether this side coalesces or not.
+            capabilities: if options.datagram_checksum {
+                Capabilities::DATAGRAM_CHECKSUM
+            } else {
+                Capabilities::NONE
+            }
+            .with(Capabilities::COALESCING)
+            .with_crate_version(CrateVersion::current()),
+            peer_crate_version: c.peer_crate_version.clone(),
+            clock: c.clock.clone(),
+            coalescing: false,
+            consumer: ConsumerGuard::new(options.consumer_timeout),
+            shared_stats: c.shared_stats.clone(),
+        };
+        let packets = PacketHandler {
+            address,
+            recv_time: c.recv_time.clone(),
+            shared_stats: c.shared_stats.clone(),
+            transform: c.transform.clone(),
+            recv_q: c.recv_queue.clone(),
+            send_q: c.send_queue.clone(),
+            disconnect: c.disconnect.clone(),
+            close_signal: c.close_signal.clone(),
+            state: c.state.clone(),
+            ack_immediately_above: c.ack_immediately_above,
+            tick_wake: c.tick_wake.clone(),
+            sender: net_sender,
+            event_sender,
+            guards,
+            _release_wake: release_wake,
+            _driver: c.liveness.driver(),
+        };
 
-        return c;
+        let drivers = ConnDrivers {
+            ticker,
+            packets,
+            tick_woken,
+            released,
+        };
+        (c, drivers)
+    }
+
+    /// The handle the listener keeps for this connection.
+    #[cfg(feature = "server")]
+    pub(crate) fn handle(&self) -> ConnHandle {
+        ConnHandle {
+            address: self.address,
+            state: self.state.clone(),
+            send_queue: self.send_queue.clone(),
+            recv_queue: self.recv_queue.clone(),
+            recv_time: self.recv_time.clone(),
+            peer_crate_version: self.peer_crate_version.clone(),
+            tasks: self.tasks.clone(),
+            close_signal: self.close_signal.clone(),
+            liveness: self.liveness.clone(),
+            events: self.reclaim_events.lock().unwrap().take(),
+            shared_stats: self.shared_stats.clone(),
+        }
+    }
+
+    /// Resumes a session exported by another listener, the connection is established
+    /// right away.
+    #[cfg(feature = "server")]
+    pub(crate) async fn restore(&self, descriptor: &SessionDescriptor) {
+        let mut send_queue = self.send_queue.write().await;
+        send_queue.restore_counters(&descriptor.send);
+        send_queue.set_datagram_checksum(
+            descriptor
+                .capabilities
+                .contains(Capabilities::DATAGRAM_CHECKSUM),
+        );
+        let coalescing = descriptor.capabilities.contains(Capabilities::COALESCING);
+        send_queue.set_coalescing(coalescing);
+        drop(send_queue);
+        let mut recv_queue = self.recv_queue.lock().await;
+        recv_queue.restore_counters(&descriptor.recv);
+        recv_queue.set_coalescing(coalescing);
+        drop(recv_queue);
+        self.recv_time
+            .store(descriptor.recv_time, std::sync::atomic::Ordering::Relaxed);
+        *self.peer_crate_version.lock().unwrap() = descriptor.capabilities.crate_version();
+        *self.state.lock().await = ConnectionState::Connected;
+    }
+
+    /// Draws every datagram this connection sends from a budget shared with other connections.
+    #[cfg(feature = "server")]
+    pub(crate) async fn set_send_budget(&self, budget: Option<BudgetShare>) {
+        self.send_queue.write().await.set_send_budget(budget);
     }
 
     /// Initializes the client ticking process!
-    pub(crate) fn init_tick(&self, notifier: Arc<Sender<SocketAddr>>) -> task::JoinHandle<()> {
+    pub(crate) fn init_tick(
+        &self,
+        notifier: Arc<Sender<SocketAddr>>,
+        mut ticker: Ticker,
+        #[cfg(feature = "async_std")] woken: Receiver<()>,
+        #[cfg(feature = "async_tokio")] mut woken: Receiver<()>,
+    ) -> task::JoinHandle<()> {
         let address = self.address;
         let closer = self.disconnect.clone();
-        let last_recv = self.recv_time.clone();
-        let send_queue = self.send_queue.clone();
-        let recv_queue = self.recv_queue.clone();
-        let state = self.state.clone();
-        let mut last_ping: u16 = 0;
+        let liveness = self.liveness.clone();
+        // held so the wake channel never closes, which would wake the tick for good.
+        let tick_wake = self.tick_wake.clone();
 
         // initialize the event io
         // we initialize the ticking function here, it's purpose is to update the state of the current connection
         // while handling throttle
         return task::spawn(async move {
+            let _tick_wake = tick_wake;
             loop {
-                macro_rules! tick_body {
-                    () => {
-                        let recv = last_recv.load(std::sync::atomic::Ordering::Relaxed);
-                        let mut cstate = state.lock().await;
-
-                        if *cstate == ConnectionState::Disconnected {
-                            rakrs_debug!(
-                                true,
-                                "[{}] Connection has been closed due to state!",
-                                to_address_token(address)
-                            );
-                            // closer.notify_all();
-                            closer.notify().await;
-                            break;
-                        }
-
-                        if recv + 15 <= current_epoch() {
-                            *cstate = ConnectionState::Disconnected;
-                            rakrs_debug!(
-                                true,
-                                "[{}] Connection has been closed due to inactivity!",
-                                to_address_token(address)
-                            );
-                            // closer.notify_all();
-                            closer.notify().await;
-                            break;
-                        }
-
-                        if recv + 10 <= current_epoch() && cstate.is_reliable() {
-                            *cstate = ConnectionState::TimingOut;
-                            rakrs_debug!(
-                                true,
-                                "[{}] Connection is timing out, sending a ping!",
-                                to_address_token(address)
-                            );
-                        }
-
-                        let mut sendq = send_queue.write().await;
-                        let mut recv_q = recv_queue.lock().await;
-
-                        if last_ping >= 3000 {
-                            let ping = ConnectedPing {
-                                time: current_epoch() as i64,
-                            };
-                            if let Ok(_) = sendq
-                                .send_packet(ping.into(), Reliability::Reliable, true)
-                                .await
-                            {};
-                            last_ping = 0;
-                        } else {
-                            last_ping += 50;
-                        }
-
-                        sendq.update().await;
-
-                        // Flush the queue of acks and nacks, and respond to them
-                        let ack = Ack::from_records(recv_q.ack_flush(), false);
-                        if ack.records.len() > 0 {
-                            if let Ok(p) = ack.write_to_bytes() {
-                                sendq.send_stream(p.as_slice()).await;
-                            }
-                        }
-
-                        // flush nacks from recv queue
-                        let nack = Ack::from_records(recv_q.nack_queue(), true);
-                        if nack.records.len() > 0 {
-                            if let Ok(p) = nack.write_to_bytes() {
-                                sendq.send_stream(p.as_slice()).await;
-                            }
-                        }
-                    };
-                }
+                liveness.tick_due(ticker.next_tick());
+                let wait = ticker.next_tick().saturating_duration_since(util::now());
 
                 #[cfg(feature = "async_std")]
                 select! {
@@ -321,8 +815,13 @@ impl Connection {
                         rakrs_debug!(true, "[{}] [task: tick] Connection has been closed due to closer!", to_address_token(address));
                         break;
                     }
-                    _ = sleep(Duration::from_millis(50)).fuse() => {
-                       tick_body!();
+                    _ = woken.recv().fuse() => {
+                        ticker.wake();
+                    }
+                    _ = sleep(wait).fuse() => {
+                        if !ticker.tick().await {
+                            break;
+                        }
                     }
                 }
 
@@ -332,12 +831,16 @@ impl Connection {
                         rakrs_debug!(true, "[{}] [task: tick] Connection has been closed due to closer!", to_address_token(address));
                         break;
                     }
-                    _ = sleep(Duration::from_millis(50)) => {
-                       tick_body!();
+                    _ = woken.recv() => {
+                        ticker.wake();
+                    }
+                    _ = sleep(wait) => {
+                        if !ticker.tick().await {
+                            break;
+                        }
                     }
                 }
             }
-
             #[cfg(feature = "async_std")]
             if let Ok(_) = notifier.send(address).await {
                 rakrs_debug!(
@@ -383,135 +886,15 @@ impl Connection {
         #[cfg(feature = "async_std")] net: Receiver<Vec<u8>>,
         // ONLY ACTIVATED ON TOKIO
         #[cfg(feature = "async_tokio")] mut net: Receiver<Vec<u8>>,
-        sender: Sender<Vec<u8>>,
+        mut packets: PacketHandler,
+        #[cfg(feature = "async_std")] released: Receiver<()>,
+        #[cfg(feature = "async_tokio")] mut released: Receiver<()>,
     ) -> task::JoinHandle<()> {
-        let recv_time = self.recv_time.clone();
-        let recv_q = self.recv_queue.clone();
-        let send_q = self.send_queue.clone();
         let disconnect = self.disconnect.clone();
-        let state = self.state.clone();
         let address = self.address;
 
         return task::spawn(async move {
             loop {
-                macro_rules! handle_payload {
-                    ($payload: ident) => {
-                        // We've recieved a payload!
-                        recv_time.store(current_epoch(), std::sync::atomic::Ordering::Relaxed);
-                        let mut cstate = state.lock().await;
-
-                        if *cstate == ConnectionState::TimingOut {
-                            rakrs_debug!(
-                                "[{}] Connection is no longer timing out!",
-                                to_address_token(address)
-                            );
-                            *cstate = ConnectionState::Connected;
-                        }
-
-                        drop(cstate);
-
-                        let id = $payload[0];
-                        match id {
-                            // This is a frame packet.
-                            // This packet will be handled by the recv_queue
-                            0x80..=0x8d => {
-                                if let Ok(pk) = FramePacket::read_from_slice(&$payload[..]) {
-                                    let mut rq = recv_q.lock().await;
-
-                                    if let Err(e) = rq.insert(pk) {
-                                        rakrs_debug!(
-                                            true,
-                                            "[{}] Failed to insert frame packet! {:?}",
-                                            to_address_token(address),
-                                            e
-                                        );
-                                    };
-
-                                    let buffers = rq.flush();
-
-                                    for buffer in buffers {
-                                        let res = Connection::process_packet(
-                                            &buffer, &address, &sender, &send_q, &state,
-                                        )
-                                        .await;
-                                        if let Ok(v) = res {
-                                            if v == true {
-                                                // DISCONNECT
-                                                // disconnect.close();
-                                                rakrs_debug!(true, "[{}] Connection::process_packet returned true!", to_address_token(address));
-                                                disconnect.notify().await;
-                                                break;
-                                            }
-                                        }
-                                        if let Err(e) = res {
-                                            rakrs_debug!(
-                                                "[{}] Failed to process packet: {:?}!",
-                                                to_address_token(address),
-                                                e
-                                            );
-                                        };
-                                    }
-
-                                    drop(rq);
-                                } else {
-                                    rakrs_debug!(
-                                        true,
-                                        "[{}] Failed to parse frame packet!",
-                                        to_address_token(address)
-                                    );
-                                }
-                            }
-                            NACK => {
-                                // Validate this is a nack packet
-
-                                if let Ok(nack) = Ack::read_from_slice(&$payload[..]) {
-                                    // The client acknowledges it did not recieve these packets
-                                    // We should resend them.
-                                    let mut sq = send_q.write().await;
-                                    let resend = sq.nack(nack);
-
-                                    if resend.len() > 0 {
-                                        for packet in resend {
-                                            if let Ok(buffer) = packet.write_to_bytes() {
-                                                if let Err(_) = sq.insert(buffer.as_slice(), Reliability::Unreliable, true, Some(0)).await {
-                                                    rakrs_debug!(
-                                                        true,
-                                                        "[{}] Failed to insert packet into send queue!",
-                                                        to_address_token(address)
-                                                    );
-                                                }
-                                            } else {
-                                                rakrs_debug!(
-                                                    true,
-                                                    "[{}] Failed to send packet to client (parsing failed)!",
-                                                    to_address_token(address)
-                                                );
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                            ACK => {
-                                // first lets validate this is an ack packet
-                                if let Ok(ack) = Ack::read_from_slice(&$payload[..]) {
-                                    // The client acknowledges it recieved these packets
-                                    // We should remove them from the queue.
-                                    let mut sq = send_q.write().await;
-                                    sq.ack(ack.clone());
-                                    drop(sq);
-                                    recv_q.lock().await.ack(ack);
-                                }
-                            }
-                            _ => {
-                                rakrs_debug!(
-                                    "[{}] Unknown RakNet packet recieved (Or packet is sent out of scope).",
-                                    to_address_token(address)
-                                );
-                            }
-                        };
-                    };
-                }
-
                 #[cfg(feature = "async_std")]
                 select! {
                     _ = disconnect.wait().fuse() => {
@@ -520,12 +903,13 @@ impl Connection {
                     }
                     res = net.recv().fuse() => {
                         match res {
-                            Ok(payload) => {
-                                handle_payload!(payload);
-                            }
+                            Ok(payload) => packets.datagram(payload).await,
                             _ => continue,
                         }
                     }
+                    _ = released.recv().fuse() => {
+                        packets.release().await;
+                    }
                 };
 
                 #[cfg(feature = "async_tokio")]
@@ -536,12 +920,13 @@ impl Connection {
                     }
                     res = net.recv() => {
                         match res {
-                            Some(payload) => {
-                                handle_payload!(payload);
-                            }
+                            Some(payload) => packets.datagram(payload).await,
                             _ => continue,
                         }
                     }
+                    _ = released.recv() => {
+                        packets.release().await;
+                    }
                 };
             }
         });
@@ -551,15 +936,30 @@ impl Connection {
         buffer: &[u8],
         address: &SocketAddr,
         sender: &Sender<Vec<u8>>,
+        event_sender: &EventSender,
+        guards: &mut PacketGuards,
         send_q: &Arc<RwLock<SendQueue>>,
         state: &Arc<Mutex<ConnectionState>>,
     ) -> Result<bool, ()> {
-        if let Ok(online_packet) = OnlinePacket::read_from_slice(&buffer) {
+        let online = decode::<OnlinePacket>(buffer);
+        if let Err(error) = &online {
+            if !error.is_unknown_packet() {
+                rakrs_debug!(
+                    true,
+                    "[{}] Failed to decode an online packet, {}: {}",
+                    to_address_token(*address),
+                    error,
+                    error.hexdump(buffer)
+                );
+            }
+        }
+        if let Ok(online_packet) = online {
+            guards.flood.known(util::now());
             match online_packet {
                 OnlinePacket::ConnectedPing(pk) => {
                     let response = ConnectedPong {
                         ping_time: pk.time,
-                        pong_time: current_epoch() as i64,
+                        pong_time: rak_time_now(),
                     };
                     let mut q = send_q.write().await;
                     if let Ok(_) = q
@@ -576,12 +976,14 @@ impl Connection {
                         return Err(());
                     }
                 }
-                OnlinePacket::ConnectedPong(_pk) => {
-                    // do nothing rn
-                    // TODO: add ping calculation
+                OnlinePacket::ConnectedPong(pk) => {
+                    if let Ok(mut clock) = guards.clock.lock() {
+                        clock.sample(pk.ping_time, pk.pong_time, rak_time_now());
+                    }
                     return Ok(false);
                 }
                 OnlinePacket::ConnectionRequest(pk) => {
+                    *guards.peer_crate_version.lock().unwrap() = pk.capabilities.crate_version();
                     let internal_ids = vec![
                         SocketAddr::new(IpAddr::V4(Ipv4Addr::new(255, 255, 255, 255)), 19132),
                         SocketAddr::new(IpAddr::V4(Ipv4Addr::new(255, 255, 255, 255)), 19133),
@@ -595,9 +997,36 @@ impl Connection {
                         internal_ids,
                         request_time: pk.time,
                         timestamp: current_epoch() as i64,
+                        capabilities: guards.capabilities.intersection(pk.capabilities),
                     };
+                    let mut cstate = state.lock().await;
+                    let (response, first) = guards
+                        .handshake
+                        .request(response, *cstate == ConnectionState::Connected);
+                    if first {
+                        *cstate = ConnectionState::Connecting;
+                    } else {
+                        rakrs_debug!(
+                            true,
+                            "[{}] Connection request was retransmitted, sending the same accept!",
+                            to_address_token(*address)
+                        );
+                    }
+                    drop(cstate);
+
+                    // the peer coalesces as soon as it has the accept, we only do once we know
+                    // it has it, see the new connection. Its bundles are split from the next
+                    // frame set on.
+                    guards.coalescing = response.capabilities.contains(Capabilities::COALESCING);
+
                     let mut q = send_q.write().await;
-                    *state.lock().await = ConnectionState::Connecting;
+                    // the peer verifies checksums as soon as it offers them, even on the accept.
+                    if response
+                        .capabilities
+                        .contains(Capabilities::DATAGRAM_CHECKSUM)
+                    {
+                        q.set_datagram_checksum(true);
+                    }
                     if let Ok(_) = q
                         .send_packet(response.clone().into(), Reliability::Reliable, true)
                         .await
@@ -615,6 +1044,18 @@ impl Connection {
                 OnlinePacket::Disconnect(_) => {
                     // Disconnect the client immediately.
                     // connection.disconnect("Client disconnected.", false);
+                    if let Some(reason) = DisconnectReason::from_packet(buffer) {
+                        if event_sender
+                            .try_send(RakEvent::Disconnected { reason })
+                            .is_err()
+                        {
+                            rakrs_debug!(
+                                true,
+                                "[{}] The events are not read anymore, dropping the disconnect!",
+                                to_address_token(*address)
+                            );
+                        }
+                    }
                     return Ok(true);
                 }
                 OnlinePacket::LostConnection(_) => {
@@ -623,17 +1064,35 @@ impl Connection {
                     return Ok(true);
                 }
                 OnlinePacket::NewConnection(_) => {
-                    // if we are already connected, disconnect the client.
-                    if *state.lock().await == ConnectionState::Connected {
+                    let mut cstate = state.lock().await;
+
+                    // the peer retransmits this when it missed our acknowledgement.
+                    if !guards
+                        .handshake
+                        .complete(*cstate == ConnectionState::Connected)
+                    {
                         rakrs_debug!(
                             true,
-                            "[{}] Client is already connected, disconnecting client!",
+                            "[{}] Client is already connected, ignoring retransmitted handshake!",
                             to_address_token(*address)
                         );
-                        return Ok(true);
+                        return Ok(false);
+                    }
+
+                    *cstate = ConnectionState::Connected;
+                    drop(cstate);
+
+                    if guards.coalescing {
+                        send_q.write().await.set_coalescing(true);
                     }
 
-                    *state.lock().await = ConnectionState::Connected;
+                    if event_sender.try_send(RakEvent::Connected).is_err() {
+                        rakrs_debug!(
+                            true,
+                            "[{}] Event channel is full, dropping event!",
+                            to_address_token(*address)
+                        );
+                    }
                     return Ok(false);
                 }
                 _ => {
@@ -663,17 +1122,63 @@ impl Connection {
             return Err(());
         }
 
+        if let Some(id) = buffer.first() {
+            match guards.flood.unknown(*id, util::now()) {
+                FloodVerdict::Deliver => {}
+                FloodVerdict::Suppress => return Ok(false),
+                FloodVerdict::Flooded => {
+                    rakrs_debug!(
+                        true,
+                        "[{}] Peer is flooding unknown packets, dropping them!",
+                        to_address_token(*address)
+                    );
+                    let event = RakEvent::ProtocolViolation {
+                        kind: ViolationKind::UnknownFlood,
+                    };
+                    if event_sender.try_send(event).is_err() {
+                        rakrs_debug!(
+                            true,
+                            "[{}] Event channel is full, dropping event!",
+                            to_address_token(*address)
+                        );
+                    }
+                    return Ok(false);
+                }
+            }
+        }
+
         rakrs_debug!(
             true,
             "[{}] Either Game-packet or unknown packet, sending buffer to client...",
             to_address_token(*address)
         );
-        if let Err(_) = sender.send(buffer.to_vec()).await {
-            rakrs_debug!(
-                "[{}] Failed to to forward packet to recv channel...",
-                to_address_token(*address)
+        let delivered = match guards.consumer.wait() {
+            None => sender.send(buffer.to_vec()).await.is_ok(),
+            Some(wait) if wait.is_zero() => sender.try_send(buffer.to_vec()).is_ok(),
+            Some(wait) => matches!(
+                timeout(wait, sender.send(buffer.to_vec())).await,
+                Ok(Ok(()))
+            ),
+        };
+
+        if delivered {
+            if guards.consumer.delivered() {
+                rakrs_debug!(
+                    "[{}] The application receives again, delivering messages.",
+                    to_address_token(*address)
+                );
+            }
+        } else {
+            if guards.consumer.dropped() {
+                rakrs_debug!(
+                    "[{}] The application stopped receiving, dropping messages until it is back!",
+                    to_address_token(*address)
+                );
+            }
+            guards.shared_stats.events_dropped_no_consumer.store(
+                guards.consumer.dropped_count(),
+                std::sync::atomic::Ordering::Relaxed,
             );
-            return Err(());
         }
         Ok(false)
     }
@@ -700,6 +1205,10 @@ impl Connection {
     ///     }
     /// }
     /// ```
+    ///
+    /// # Cancellation
+    /// This is cancellation safe, a packet is only taken from the connection once it is
+    /// returned. Dropping the future, for instance when it loses a `select!`, never loses one.
     pub async fn recv(&mut self) -> Result<Vec<u8>, RecvError> {
         #[allow(unused_mut)]
         let mut q = self.internal_net_recv.as_ref().lock().await;
@@ -715,6 +1224,19 @@ impl Connection {
         }
     }
 
+    /// This method is used to recieve events that happened on the connection, for example
+    /// a [`RakEvent::OrderedGap`] when an ordered message was given up on.
+    ///
+    /// Lifecycle events, like a [`RakEvent::Disconnected`], are read ahead of the others once
+    /// the events that happened before them were read, see [`RakEvent::is_lifecycle()`].
+    ///
+    /// [`RakEvent::OrderedGap`]: crate::connection::event::RakEvent::OrderedGap
+    /// [`RakEvent::Disconnected`]: crate::connection::event::RakEvent::Disconnected
+    /// [`RakEvent::is_lifecycle()`]: crate::connection::event::RakEvent::is_lifecycle
+    pub async fn recv_event(&self) -> Result<RakEvent, RecvError> {
+        self.internal_event_recv.recv().await
+    }
+
     // /// Handle a RakNet Event. These are sent as they happen.
     // ///
     // /// EG:
@@ -746,6 +1268,210 @@ impl Connection {
     //     }
     // }
 
+    /// Returns a copy of the round trip state of this connection.
+    pub fn rtt_stats(&self) -> RttStats {
+        self.rtt_stats
+            .lock()
+            .map(|stats| *stats)
+            .unwrap_or_default()
+    }
+
+    /// The smoothed round trip time, `None` until the first reliable datagram has been acknowledged.
+    pub fn rtt(&self) -> Option<Duration> {
+        self.rtt_stats().rtt
+    }
+
+    /// How many milliseconds the clock of the peer is ahead of ours, `None` until the
+    /// first pong to one of our pings came back.
+    ///
+    /// This is an estimate, see [`ClockEstimator`] for how far off it can be. Vanilla peers
+    /// don't count from the unix epoch, so their offset is only good for
+    /// [`Connection::peer_time_now()`].
+    pub fn clock_offset(&self) -> Option<RakTime> {
+        self.clock.lock().ok()?.offset()
+    }
+
+    /// The current time on the clock of the peer, `None` until the first pong to one of
+    /// our pings came back. On the client this is [`Client::server_time_now()`].
+    ///
+    /// [`Client::server_time_now()`]: crate::client::Client::server_time_now
+    pub fn peer_time_now(&self) -> Option<RakTime> {
+        self.clock.lock().ok()?.peer_time_now()
+    }
+
+    /// The variance of the round trip time, `None` until the first reliable datagram has been acknowledged.
+    pub fn rtt_variance(&self) -> Option<Duration> {
+        self.rtt_stats().rtt_variance
+    }
+
+    /// How long an unacknowledged reliable datagram is kept before it is resent.
+    pub fn current_rto(&self) -> Duration {
+        self.rtt_stats().rto
+    }
+
+    /// How many times in a row the retransmission timer fired without an acknowledgement.
+    pub fn consecutive_timeouts(&self) -> u32 {
+        self.rtt_stats().consecutive_timeouts
+    }
+
+    /// The amount of datagrams received from the peer that weren't acknowledged yet.
+    ///
+    /// These are acknowledged on the next tick, or right away once there are more than
+    /// [`ConnOptions::ack_immediately_above`].
+    pub async fn ack_debt(&self) -> usize {
+        self.recv_queue.lock().await.ack_debt()
+    }
+
+    /// Acknowledges every datagram received so far in a single ACK, without waiting for the tick.
+    ///
+    /// This is useful right after handling a request the peer is waiting on, so its retransmission
+    /// timer doesn't fire while the acknowledgement is held back. Returns the amount of datagrams
+    /// acknowledged, a sequence is never acknowledged again by the tick afterwards.
+    pub async fn flush_acks(&self) -> usize {
+        let acks = self.recv_queue.lock().await.ack_flush();
+        self.send_queue.write().await.send_ack_ranges(acks).await
+    }
+
+    /// Prepares the connection for the traffic the application expects, like the world
+    /// download right after a player joined, and returns the capacity its structures got.
+    ///
+    /// The send and receive queues make room for the burst up front, rather than growing while
+    /// it is sent, and a peer hinted to be on the same network gets
+    /// [`ConnOptions::lan_frame_budget`]. Hints are only advisory: the queues are never sized
+    /// past what [`ConnOptions::max_connection_memory`] has room for, and the budget of the
+    /// connection is enforced like it is without a hint. The event channel has a fixed size,
+    /// a hint doesn't change it.
+    ///
+    /// # Example
+    /// ```ignore
+    /// use rak_rs::connection::{LatencyClass, Profile};
+    ///
+    /// async fn joined(conn: &Connection) {
+    ///     conn.hint_profile(Profile {
+    ///         expected_burst_bytes: 2 * 1024 * 1024,
+    ///         expected_message_rate: 200,
+    ///         latency_class: LatencyClass::Internet,
+    ///     })
+    ///     .await;
+    /// }
+    /// ```
+    pub async fn hint_profile(&self, profile: Profile) -> Capacity {
+        // the queues are never held together, like everywhere else.
+        let mtu = self.send_queue.read().await.mtu_size();
+        let mut recv_q = self.recv_queue.lock().await;
+        let capacity = profile.capacity(mtu, recv_q.memory_budget());
+        recv_q.prewarm(&capacity);
+        drop(recv_q);
+
+        let mut send_q = self.send_queue.write().await;
+        send_q.prewarm(&capacity);
+        if profile.latency_class == LatencyClass::Lan {
+            if let (Some(budget), Some(lan)) = (send_q.frame_budget(), self.lan_frame_budget) {
+                send_q.set_frame_budget(Some(budget.max(lan)));
+            }
+        }
+        capacity
+    }
+
+    /// The capacity the structures of the connection have, see [`Connection::hint_profile()`].
+    pub async fn capacity(&self) -> Capacity {
+        let send = self.send_queue.read().await.capacity();
+        let recv = self.recv_queue.lock().await.capacity();
+        Capacity {
+            splits: recv.splits,
+            messages: recv.messages,
+            ..send
+        }
+    }
+
+    /// Returns the frame budget of the connection, see [`ConnOptions::frame_budget`].
+    pub async fn frame_budget(&self) -> Option<usize> {
+        self.send_queue.read().await.frame_budget()
+    }
+
+    /// Returns the meta data of this connection, like the version of rak-rs the peer runs.
+    /// This doesn't wait on the queues of the connection.
+    pub async fn meta(&self) -> ConnMeta {
+        ConnMeta {
+            mtu_size: self
+                .shared_stats
+                .mtu_size
+                .load(std::sync::atomic::Ordering::Relaxed),
+            recv_time: self.recv_time.load(std::sync::atomic::Ordering::Relaxed),
+            peer_crate_version: *self.peer_crate_version.lock().unwrap(),
+            rebound_from: None,
+        }
+    }
+
+    /// Returns a snapshot of the statistics of this connection.
+    ///
+    /// Only the statistics of the order channels are read from the receive queue, the
+    /// counters are published while the send queue is borrowed, so they are all from the
+    /// same instant, see [`SharedStats::publish()`].
+    pub async fn stats(&self) -> ConnectionStats {
+        let (channels, memory_used) = {
+            let recv_queue = self.recv_queue.lock().await;
+            (
+                recv_queue.channel_stats(util::now()),
+                recv_queue.memory_budget().used(),
+            )
+        };
+        // published while nothing updates the counters, so they agree with each other.
+        self.send_queue.read().await.publish_stats();
+
+        ConnectionStats {
+            rtt: self.rtt_stats(),
+            memory_used,
+            max_channel_blocked: channels
+                .iter()
+                .map(|channel| channel.blocked_for)
+                .max()
+                .unwrap_or_default(),
+            channels,
+            ..Default::default()
+        }
+        .with_counters(self.shared_stats.snapshot())
+    }
+
+    /// The amount of packets with an unknown id that were dropped because the peer was flooding them.
+    /// See [`ConnOptions::unknown_flood_threshold`].
+    pub fn unknown_suppressed(&self) -> u64 {
+        self.shared_stats
+            .unknown_suppressed
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// How much of ECN the socket of this connection supports, this is [`EcnSupport::Off`]
+    /// unless [`ConnOptions::enable_ecn`] is set.
+    pub fn ecn_supported(&self) -> EcnSupport {
+        self.ecn
+    }
+
+    /// Records how much of ECN the socket the connection sends on supports.
+    #[cfg(feature = "server")]
+    pub(crate) fn with_ecn(mut self, ecn: EcnSupport) -> Self {
+        self.ecn = ecn;
+        self
+    }
+
+    /// Resolves with the reason once this connection has terminated, however that happened.
+    ///
+    /// The returned [`Closed`] can be cloned and awaited from as many tasks as needed,
+    /// it resolves right away if the connection already terminated. When the peer closed the
+    /// connection, its [`RakEvent::Disconnected`] is queued before this resolves.
+    ///
+    /// # Example
+    /// ```ignore
+    /// async fn game_loop(conn: &Connection) {
+    ///     let closed = conn.closed();
+    ///     // ... stop the loop once `closed` resolves
+    ///     let reason = closed.await;
+    /// }
+    /// ```
+    pub fn closed(&self) -> Closed {
+        self.closed.clone()
+    }
+
     pub async fn is_closed(&self) -> bool {
         !self.state.lock().await.is_available()
     }
@@ -762,34 +1488,271 @@ impl Connection {
     ///     conn.send(&[0x01, 0x02, 0x03], true).await.unwrap();
     /// }
     /// ```
+    ///
+    /// A connection that holds as much as [`ConnOptions::max_connection_memory`] allows
+    /// refuses the payload with [`SendQueueError::OutOfBudget`].
+    ///
+    /// # Cancellation
+    /// This is cancellation safe. A payload is either not sent at all, when the future is
+    /// dropped before the send queue was locked, or queued in full: the frames an immediate
+    /// send didn't get to write to the socket go out on the next tick, so the peer never sees
+    /// half a message or a gap in the order indexes.
     pub async fn send(&self, buffer: &[u8], immediate: bool) -> Result<(), SendQueueError> {
+        self.send_with(buffer, Reliability::ReliableOrd, 0, immediate)
+            .await
+    }
+
+    /// Sends a payload with the reliability and on the channel set as the defaults of this
+    /// connection, see [`Connection::set_default_reliability()`] and
+    /// [`Connection::set_default_channel()`]. They start out as
+    /// [`ConnOptions::default_reliability`] and [`ConnOptions::default_channel`].
+    ///
+    /// The defaults are read when this is called, changing them afterwards doesn't affect
+    /// the payload. This is cancellation safe like [`Connection::send()`].
+    pub async fn send_default(&self, buffer: &[u8], immediate: bool) -> Result<(), SendQueueError> {
+        let (reliability, channel) = *self.send_defaults.lock().unwrap();
+        self.send_with(buffer, reliability, channel, immediate)
+            .await
+    }
+
+    /// Changes the reliability [`Connection::send_default()`] sends with, for the sends
+    /// after this one.
+    pub fn set_default_reliability(&self, reliability: Reliability) {
+        self.send_defaults.lock().unwrap().0 = reliability;
+    }
+
+    /// Seals every datagram this connection sends with `transform` from now on, and opens
+    /// every datagram of the peer with it, `None` goes back to datagrams in the clear. See
+    /// [`transform`](crate::connection::transform).
+    ///
+    /// This is meant to be called once the connection is established, the peer has to
+    /// install the same transform. Frames queued before are already sized for the MTU, so
+    /// they may end up [`DatagramTransform::overhead()`] bytes larger.
+    pub fn set_transform(&self, transform: Option<Arc<dyn DatagramTransform>>) {
+        self.transform.install(transform);
+    }
+
+    /// Changes the order channel [`Connection::send_default()`] sends on, for the sends
+    /// after this one.
+    pub fn set_default_channel(&self, channel: u8) {
+        self.send_defaults.lock().unwrap().1 = channel;
+    }
+
+    /// The reliability [`Connection::send_default()`] sends with.
+    pub fn default_reliability(&self) -> Reliability {
+        self.send_defaults.lock().unwrap().0
+    }
+
+    /// The order channel [`Connection::send_default()`] sends on.
+    pub fn default_channel(&self) -> u8 {
+        self.send_defaults.lock().unwrap().1
+    }
+
+    /// Like [`Connection::send()`], with the reliability and channel of the payload, for the
+    /// payloads that don't go with the defaults of [`Connection::send_default()`].
+    /// This is cancellation safe the same way.
+    pub async fn send_with(
+        &self,
+        buffer: &[u8],
+        reliability: Reliability,
+        channel: u8,
+        immediate: bool,
+    ) -> Result<(), SendQueueError> {
         let mut q = self.send_queue.write().await;
+
+        // checked while holding the queue, so a payload is either fully queued or rejected.
+        if self.draining.load(std::sync::atomic::Ordering::SeqCst) {
+            return Err(SendQueueError::Draining);
+        }
+        q.reserve(buffer.len())?;
+
         if let Err(e) = q
-            .insert(buffer, Reliability::ReliableOrd, immediate, Some(0))
+            .insert(buffer, reliability, immediate, Some(channel))
             .await
         {
             return Err(e);
         }
+        self.wake_tick();
+        Ok(())
+    }
+
+    /// Forwards a frame received from another peer as is, for proxies that relay traffic
+    /// between two connections without reassembling it.
+    ///
+    /// The frame keeps its order channel and split metadata, its indexes are issued anew
+    /// on this connection, see [`SendQueue::insert_frame()`]. `reliability_override` replaces
+    /// the reliability of the frame when given. Forwarded frames are sent on the next tick.
+    ///
+    /// # Example
+    /// ```ignore
+    /// use rak_rs::protocol::frame::FramePacket;
+    ///
+    /// async fn forward(backend: &Connection, datagram: &FramePacket) {
+    ///     for frame in datagram.frames.iter() {
+    ///         backend.send_raw_frame(frame.into(), None).await.unwrap();
+    ///     }
+    /// }
+    /// ```
+    pub async fn send_raw_frame(
+        &self,
+        frame: FrameRef<'_>,
+        reliability_override: Option<Reliability>,
+    ) -> Result<(), SendQueueError> {
+        let mut q = self.send_queue.write().await;
+
+        if self.draining.load(std::sync::atomic::Ordering::SeqCst) {
+            return Err(SendQueueError::Draining);
+        }
+        q.reserve(frame.body.len())?;
+
+        q.insert_frame(frame, reliability_override, false).await?;
+        self.wake_tick();
         Ok(())
     }
 
+    /// Runs the tick soon, for what was just queued.
+    fn wake_tick(&self) {
+        let _ = self.tick_wake.try_send(());
+    }
+
+    /// Sends and receives the items of `codec` rather than raw payloads, see [`codec`].
+    ///
+    /// [`codec`]: crate::connection::codec
+    #[cfg(feature = "codec")]
+    pub fn framed<C>(self, codec: C) -> codec::Framed<C> {
+        codec::Framed::new(self, codec)
+    }
+
+    /// Closes the connection in an orderly fashion, for instance when the peer is transferred
+    /// to another server.
+    ///
+    /// From the moment this is called, [`Connection::send()`] fails with [`SendQueueError::Draining`].
+    /// Everything that was queued before is given up to `drain_timeout` to be acknowledged by the
+    /// peer, after which the connection is closed with `reason`. The peer receives the reason
+    /// as a [`RakEvent::Disconnected`] event.
+    ///
+    /// Should the connection be closed for another reason in the meantime, the drain resolves
+    /// within a tick with [`DrainResult::Aborted`] and that reason.
+    ///
+    /// # Example
+    /// ```ignore
+    /// use std::time::Duration;
+    /// use rak_rs::connection::event::DisconnectReason;
+    ///
+    /// async fn transfer(conn: Connection) {
+    ///     conn.begin_drain_and_close(DisconnectReason::Transferred, Duration::from_secs(2)).await;
+    /// }
+    /// ```
+    pub async fn begin_drain_and_close(
+        &self,
+        reason: DisconnectReason,
+        drain_timeout: Duration,
+    ) -> DrainResult {
+        // taking the queue makes sure no send is halfway through being queued.
+        let q = self.send_queue.write().await;
+        self.draining
+            .store(true, std::sync::atomic::Ordering::SeqCst);
+        drop(q);
+
+        let start = Instant::now();
+        let closed = self.closed();
+        let result = loop {
+            if let Some(reason) = closed.clone().now_or_never() {
+                return DrainResult::Aborted { reason };
+            }
+
+            let pending = self.send_queue.read().await.pending();
+
+            if pending == 0 {
+                break DrainResult::Drained;
+            }
+
+            if start.elapsed() >= drain_timeout {
+                break DrainResult::TimedOut {
+                    unacknowledged: pending,
+                };
+            }
+
+            sleep(Duration::from_millis(10)).await;
+        };
+
+        self.shutdown(reason.to_packet(), reason).await;
+        result
+    }
+
     /// This method should be used when you are ready to disconnect the client.
     /// this method will attempt to send a disconnect packet to the client, and
     /// then close the connection.
+    ///
+    /// This returns once the client acknowledged the disconnect, or
+    /// [`ConnOptions::disconnect_linger`] passed without it doing so.
     pub async fn close(&mut self) {
+        self.shutdown(
+            OnlinePacket::Disconnect(Disconnect {})
+                .write_to_bytes()
+                .unwrap()
+                .as_slice()
+                .to_vec(),
+            DisconnectReason::Closed,
+        )
+        .await;
+    }
+
+    /// Makes the next sends of this connection fail with the OS errors in `codes`, as if the
+    /// socket returned them. Only meant for testing how the connection copes with them.
+    #[doc(hidden)]
+    pub async fn fail_sends(&self, codes: impl IntoIterator<Item = i32>) {
+        self.send_queue.write().await.fail_sends(codes);
+    }
+
+    /// Kills the tasks of this connection as a panic in them would, without closing it.
+    /// Only meant for testing how the listener recovers.
+    #[doc(hidden)]
+    pub async fn abort_tasks(&self) {
+        for task in self.tasks.lock().await.drain(..) {
+            #[cfg(feature = "async_std")]
+            task.cancel().await;
+            #[cfg(feature = "async_tokio")]
+            task.abort();
+        }
+    }
+
+    /// Keeps the tasks running until the peer acknowledged everything that was sent, the
+    /// disconnect included, for at most [`ConnOptions::disconnect_linger`]. The tick resends
+    /// the disconnect like any other reliable message in the meantime.
+    async fn linger(&self) {
+        let start = Instant::now();
+        let closed = self.closed();
+        while start.elapsed() < self.disconnect_linger {
+            // the peer closed as well, or the connection timed out.
+            if closed.clone().now_or_never().is_some() {
+                return;
+            }
+            if self.send_queue.read().await.pending() == 0 {
+                return;
+            }
+            sleep(Duration::from_millis(10)).await;
+        }
+        rakrs_debug!(
+            true,
+            "[{}] The disconnect was not acknowledged in time, closing anyway!",
+            to_address_token(self.address)
+        );
+    }
+
+    /// Sends the disconnect packet, and stops all tasks of this connection once the peer
+    /// acknowledged it, see [`Connection::linger()`].
+    async fn shutdown(&self, disconnect: Vec<u8>, reason: DisconnectReason) {
         rakrs_debug!(
             true,
             "[{}] Dropping connection!",
             to_address_token(self.address)
         );
         if let Err(_) = self
-            .send(
-                &OnlinePacket::Disconnect(Disconnect {})
-                    .write_to_bytes()
-                    .unwrap()
-                    .as_slice(),
-                true,
-            )
+            .send_queue
+            .write()
+            .await
+            .insert(&disconnect, Reliability::ReliableOrd, true, Some(0))
             .await
         {
             rakrs_debug!(
@@ -798,6 +1761,7 @@ impl Connection {
                 to_address_token(self.address)
             );
         }
+        self.linger().await;
         let tasks = self.tasks.clone();
 
         for task in tasks.lock().await.drain(..) {
@@ -806,5 +1770,8 @@ impl Connection {
             #[cfg(feature = "async_tokio")]
             task.abort();
         }
+
+        *self.state.lock().await = ConnectionState::Disconnected;
+        self.close_signal.close(reason);
     }
 }
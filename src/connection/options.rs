@@ -0,0 +1,326 @@
+use std::time::Duration;
+
+use crate::connection::controller::consumer::DEFAULT_CONSUMER_TIMEOUT;
+use crate::connection::controller::flood::DEFAULT_UNKNOWN_FLOOD_WINDOW;
+use crate::connection::controller::memory::DEFAULT_MAX_CONNECTION_MEMORY;
+use crate::connection::controller::mtu::DEFAULT_MTU_REPROBE_INTERVAL;
+use crate::connection::controller::one_way::DEFAULT_ONE_WAY_FAIL_THRESHOLD;
+use crate::connection::controller::tick::{DEFAULT_TICK_INTERVAL_MAX, DEFAULT_TICK_INTERVAL_MIN};
+use crate::protocol::reliability::Reliability;
+
+/// The default amount of datagrams a socket read loop handles before it yields.
+pub const DEFAULT_READ_BUDGET: usize = 64;
+
+/// The default amount of unacknowledged sequences after which they are acknowledged right away.
+pub const DEFAULT_ACK_IMMEDIATELY_ABOVE: usize = 32;
+
+/// A frame budget that suits most connections, see [`ConnOptions::frame_budget`].
+pub const DEFAULT_FRAME_BUDGET: usize = 512;
+
+/// The default distance past the expected order or sequence index an ordered or sequenced
+/// message may be at, the messages further ahead are dropped.
+pub const DEFAULT_ORDER_JUMP_LIMIT: u32 = 4096;
+
+/// The default time a closing connection waits for the peer to acknowledge its disconnect.
+pub const DEFAULT_DISCONNECT_LINGER: Duration = Duration::from_secs(1);
+
+/// The default amount of times a connection may move to another port, see
+/// [`MigrationOptions::max_migrations`].
+pub const DEFAULT_MAX_MIGRATIONS: u32 = 4;
+
+/// What a listener makes of a frame set from an address it has no session for, that belongs to
+/// the session of another port of the same host, see [`ConnOptions::address_binding`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AddressBinding {
+    /// A connection is bound to the address it was made from, a datagram of its sequences from
+    /// any other port is spoofed. It is dropped and counted in
+    /// [`ConnectionStats::spoofed_datagrams`], and once there were more than
+    /// [`SPOOFED_DATAGRAM_THRESHOLD`] of them, a [`RakEvent::ProtocolViolation`] names both
+    /// addresses.
+    ///
+    /// [`ConnectionStats::spoofed_datagrams`]: crate::connection::stats::ConnectionStats::spoofed_datagrams
+    /// [`SPOOFED_DATAGRAM_THRESHOLD`]: crate::server::SPOOFED_DATAGRAM_THRESHOLD
+    /// [`RakEvent::ProtocolViolation`]: crate::connection::event::RakEvent::ProtocolViolation
+    #[default]
+    Strict,
+    /// A connection follows its peer to another port, like after its NAT mapping expired.
+    /// The session is moved to the new address, and everything is sent there from then on.
+    AllowMigration(MigrationOptions),
+}
+
+/// How connections move between the ports of their peer, see [`AddressBinding::AllowMigration`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MigrationOptions {
+    /// How many times a connection may move, after that it is bound to where it is like under
+    /// [`AddressBinding::Strict`].
+    pub max_migrations: u32,
+}
+
+impl Default for MigrationOptions {
+    fn default() -> Self {
+        Self {
+            max_migrations: DEFAULT_MAX_MIGRATIONS,
+        }
+    }
+}
+
+/// Options that change how a single connection behaves.
+///
+/// These are shared by the [`Listener`] (applied to every accepted [`Connection`])
+/// and the [`Client`].
+///
+/// [`Listener`]: crate::server::Listener
+/// [`Connection`]: crate::connection::Connection
+/// [`Client`]: crate::client::Client
+#[derive(Debug, Clone)]
+pub struct ConnOptions {
+    /// How long a missing reliable-ordered message may hold up its order channel before
+    /// it is skipped and the buffered messages behind it are delivered.
+    ///
+    /// When a message is skipped, a [`RakEvent::OrderedGap`] is emitted and the skipped
+    /// index is discarded if it ever arrives. The stall is checked whenever a datagram arrives,
+    /// which happens at least as often as the peer's keep-alive pings.
+    /// `None` (the default) keeps strict ordering.
+    ///
+    /// [`RakEvent::OrderedGap`]: crate::connection::event::RakEvent::OrderedGap
+    pub ordered_stall_limit: Option<Duration>,
+    /// How far past the index an order channel expects next a reliable-ordered message may be,
+    /// the same goes for the sequence index of sequenced messages.
+    ///
+    /// A message further ahead would leave everything in between missing, and hold up its
+    /// channel for good under strict ordering. It is dropped instead, counted in
+    /// [`ConnectionStats::order_jumps`] and reported with a [`RakEvent::ProtocolViolation`].
+    ///
+    /// [`ConnectionStats::order_jumps`]: crate::connection::stats::ConnectionStats::order_jumps
+    /// [`RakEvent::ProtocolViolation`]: crate::connection::event::RakEvent::ProtocolViolation
+    pub order_jump_limit: u32,
+    /// How often a connection that lowered its MTU, after datagrams of that size kept getting
+    /// lost, attempts to raise it to the next step of the ladder. The MTU is never raised
+    /// above the one negotiated during the handshake. A zero duration disables re-probing.
+    pub mtu_reprobe_interval: Duration,
+    /// The ratio (`0.0` to `1.0`) of packets with an unknown id to all packets from the peer
+    /// that is considered a flood.
+    ///
+    /// While the ratio is exceeded, packets with an unknown id are dropped instead of being
+    /// handed to the application, and a single [`RakEvent::ProtocolViolation`] is emitted.
+    /// `None` (the default) always hands unknown packets to the application.
+    ///
+    /// [`RakEvent::ProtocolViolation`]: crate::connection::event::RakEvent::ProtocolViolation
+    pub unknown_flood_threshold: Option<f32>,
+    /// The sliding window the unknown packet ratio is measured over.
+    pub unknown_flood_window: Duration,
+    /// Packet ids below the user range the application handles itself, these are never treated
+    /// as unknown. Ids from [`USER_PACKET_ID`] up, like game packets, are always claimed.
+    ///
+    /// [`USER_PACKET_ID`]: crate::connection::USER_PACKET_ID
+    pub claimed_ids: Vec<u8>,
+    /// The network interface the socket is pinned to, for example `"eth0"`.
+    ///
+    /// This is done with `SO_BINDTODEVICE` on Linux (which requires `CAP_NET_RAW`), `IP_BOUND_IF`
+    /// on macOS and `IP_UNICAST_IF` on Windows, where the interface is given by its index.
+    /// The socket is only pinned when it is created by rak-rs, that is with
+    /// [`Listener::bind_with_options()`] or [`Client::connect()`].
+    ///
+    /// [`Listener::bind_with_options()`]: crate::server::Listener::bind_with_options
+    /// [`Client::connect()`]: crate::client::Client::connect
+    pub bind_device: Option<String>,
+    /// Whether to mark every datagram sent as ECN capable (ECT(0)), so routers along the path
+    /// may signal congestion rather than drop, for experimenting with congestion control.
+    ///
+    /// This is set on the whole socket with `IP_TOS` or `IPV6_TCLASS`, on Linux and macOS.
+    /// Elsewhere, or when the socket refuses it, datagrams are sent unmarked. Whether it took
+    /// effect is told by [`Connection::ecn_supported()`] and [`Client::ecn_supported()`].
+    /// Disabled by default.
+    ///
+    /// [`Connection::ecn_supported()`]: crate::connection::Connection::ecn_supported
+    /// [`Client::ecn_supported()`]: crate::client::Client::ecn_supported
+    pub enable_ecn: bool,
+    /// How many datagrams the socket read loop handles in a row before it yields to the other
+    /// tasks on the runtime, so a burst of traffic can't starve the ticks that flush
+    /// acknowledgements. This is the read loop of the [`Listener`] or the [`Client`],
+    /// a budget of zero is treated as one.
+    pub read_budget: usize,
+    /// Whether to seal every frame-set datagram with a CRC32 trailer, for paths that damage
+    /// payloads without the UDP checksum noticing (like some carrier NATs).
+    ///
+    /// This is only used when both peers are rak-rs and enabled it. A client that enables it
+    /// asks whether the server runs rak-rs during the handshake, which vanilla servers ignore,
+    /// and only offers the checksum to a server that answered, see [`Capabilities`].
+    /// Damaged datagrams are dropped and resent like lost ones, they are counted in
+    /// [`ConnectionStats::corrupt_datagrams`].
+    ///
+    /// [`Capabilities`]: crate::protocol::packet::online::Capabilities
+    /// [`ConnectionStats::corrupt_datagrams`]: crate::connection::stats::ConnectionStats::corrupt_datagrams
+    pub datagram_checksum: bool,
+    /// Received datagrams are acknowledged in batches every tick, unless more than this many
+    /// are waiting, in which case they are acknowledged as soon as they arrive.
+    /// `None` always waits for the tick.
+    ///
+    /// The application can acknowledge everything pending at any time with
+    /// [`Connection::flush_acks()`], for instance right after handling a request the peer is
+    /// waiting on.
+    ///
+    /// [`Connection::flush_acks()`]: crate::connection::Connection::flush_acks
+    pub ack_immediately_above: Option<usize>,
+    /// The maximum amount of queued frames sent per tick, `None` (the default) sends all of them.
+    ///
+    /// Frames are taken round-robin over the order channels, a few at a time, so a bulk transfer
+    /// on one channel can't hold up the small messages of another for longer than a tick.
+    /// Frames over the budget are sent on the next tick. [`DEFAULT_FRAME_BUDGET`] is a good
+    /// start for connections that mix bulk transfers with latency sensitive messages.
+    pub frame_budget: Option<usize>,
+    /// How long reliable data may go unacknowledged, while the peer is still heard from,
+    /// before the connection suspects that nothing it sends arrives anymore. A
+    /// [`RakEvent::SuspectedOneWayLoss`] is then emitted, and should the connection time out
+    /// later on, its reason is [`DisconnectReason::TimedOut`] with `one_way` set.
+    /// `None` never suspects it.
+    ///
+    /// [`RakEvent::SuspectedOneWayLoss`]: crate::connection::event::RakEvent::SuspectedOneWayLoss
+    /// [`DisconnectReason::TimedOut`]: crate::connection::event::DisconnectReason::TimedOut
+    pub one_way_fail_threshold: Option<Duration>,
+    /// The shortest time between two ticks of a connection, which send what was queued,
+    /// acknowledge what was received and retransmit what was lost.
+    ///
+    /// A connection with nothing to do only ticks when its keepalive is due, datagrams from
+    /// the peer and sends of the application wake it up at most this long after. A lost
+    /// datagram is retransmitted at most this long after its retransmission timeout.
+    pub tick_interval_min: Duration,
+    /// The longest time between two ticks of a connection that is waiting on acknowledgements,
+    /// even when its retransmission timeout is further away. Raised to
+    /// [`ConnOptions::tick_interval_min`] when below it.
+    pub tick_interval_max: Duration,
+    /// The order channels whose small reliable-ordered messages are coalesced: the ones
+    /// queued until the next tick, that aren't sent right away, are merged into a single
+    /// message, which saves a frame and a recovery entry for each of them.
+    ///
+    /// The peer splits them back and delivers them one by one and in order, the application
+    /// can't tell. This needs a rak-rs peer that agreed to [`Capabilities::COALESCING`],
+    /// with any other peer nothing is coalesced. A client only offers it when it coalesces
+    /// itself, a listener agrees to it whatever its own channels.
+    /// Empty (the default) coalesces nothing.
+    ///
+    /// [`Capabilities::COALESCING`]: crate::protocol::packet::online::Capabilities::COALESCING
+    pub coalesce_channels: Vec<u8>,
+    /// Seeds the randomness the retry timers of every connection are spread with, see
+    /// [`RngProvider`]. Connections still draw their own timers, mixed from the seed and the
+    /// address, but the same ones on every run. `None` (the default) draws from entropy.
+    ///
+    /// [`RngProvider`]: crate::connection::RngProvider
+    pub rng_seed: Option<u64>,
+    /// How long a message of the peer may wait for the application to receive it, with
+    /// [`Connection::recv()`], when the channel it is received from is full.
+    ///
+    /// An application that didn't make room in time is considered gone: the messages of the
+    /// peer are dropped from then on, and counted in
+    /// [`ConnectionStats::events_dropped_no_consumer`]. The connection itself carries on,
+    /// so the peer has its messages acknowledged, and the connection still times out or can be
+    /// closed. Delivery resumes with the first message there is room for again, once the
+    /// application receives again. `None` waits for the application for as long as it takes,
+    /// which holds up everything the peer sends after it.
+    ///
+    /// A [`Client`] never holds up what the server sends, its messages wait for
+    /// [`Client::recv()`] next to the channel, charged to [`ConnOptions::max_connection_memory`],
+    /// and are dropped once that is full as well. `None` only lets them wait until then.
+    ///
+    /// [`Connection::recv()`]: crate::connection::Connection::recv
+    /// [`Client`]: crate::client::Client
+    /// [`Client::recv()`]: crate::client::Client::recv
+    /// [`ConnectionStats::events_dropped_no_consumer`]: crate::connection::stats::ConnectionStats::events_dropped_no_consumer
+    pub consumer_timeout: Option<Duration>,
+    /// The most bytes the buffers of a connection may hold together: the messages queued to
+    /// be sent or waiting for an acknowledgement, and the fragments and out of order messages
+    /// received from the peer, along with the bookkeeping of the datagrams it skipped.
+    ///
+    /// Once this is exceeded, the unreliable messages still queued are dropped first, then new
+    /// sends fail with [`SendQueueError::OutOfBudget`] until the peer acknowledged enough.
+    /// Should what was received from the peer alone exceed it, the connection is closed with
+    /// [`DisconnectReason::ResourceAbuse`]. What a connection holds is told by
+    /// [`ConnectionStats::memory_used`].
+    ///
+    /// [`SendQueueError::OutOfBudget`]: crate::connection::SendQueueError::OutOfBudget
+    /// [`DisconnectReason::ResourceAbuse`]: crate::connection::event::DisconnectReason::ResourceAbuse
+    /// [`ConnectionStats::memory_used`]: crate::connection::stats::ConnectionStats::memory_used
+    pub max_connection_memory: usize,
+    /// The reliability payloads are sent with by [`Connection::send_default()`] and
+    /// [`Client::send_default()`], until it is changed on the connection.
+    /// Defaults to [`Reliability::ReliableOrd`].
+    ///
+    /// [`Connection::send_default()`]: crate::connection::Connection::send_default
+    /// [`Client::send_default()`]: crate::client::Client::send_default
+    pub default_reliability: Reliability,
+    /// The order channel payloads are sent on by [`Connection::send_default()`] and
+    /// [`Client::send_default()`], until it is changed on the connection. Defaults to 0.
+    ///
+    /// [`Connection::send_default()`]: crate::connection::Connection::send_default
+    /// [`Client::send_default()`]: crate::client::Client::send_default
+    pub default_channel: u8,
+    /// The frame budget a connection hinted to be on the same network gets, see
+    /// [`LatencyClass::Lan`]. This is the most a hint can raise the budget to, it never
+    /// lowers [`ConnOptions::frame_budget`]. `None` (the default) leaves the frame budget of
+    /// every connection as it is, whatever its hint.
+    ///
+    /// [`LatencyClass::Lan`]: crate::connection::LatencyClass::Lan
+    pub lan_frame_budget: Option<usize>,
+    /// How long a connection that is closed keeps running to retransmit its disconnect, until
+    /// the peer acknowledged it. The disconnect is the last packet the peer is sent, should it
+    /// be lost the peer would only learn of it once the connection timed out, holding on to
+    /// its slot until then. [`Connection::close()`] and [`Client::close()`] return once the
+    /// disconnect was acknowledged, or this passed. Zero doesn't wait at all.
+    ///
+    /// [`Connection::close()`]: crate::connection::Connection::close
+    /// [`Client::close()`]: crate::client::Client::close
+    pub disconnect_linger: Duration,
+    /// What a listener makes of a frame set from an address it has no session for, whose
+    /// sequence fits the receive window of the one session of another port of the same host.
+    /// Either the datagram is spoofed, or the peer moved, there is no handling besides these.
+    ///
+    /// Datagrams from the address of a session are handed to it as they are, only the ones
+    /// from an address without a session are looked into. Defaults to
+    /// [`AddressBinding::Strict`], this is not used by a [`Client`].
+    ///
+    /// [`Client`]: crate::client::Client
+    pub address_binding: AddressBinding,
+}
+
+impl Default for ConnOptions {
+    fn default() -> Self {
+        Self {
+            ordered_stall_limit: None,
+            order_jump_limit: DEFAULT_ORDER_JUMP_LIMIT,
+            mtu_reprobe_interval: DEFAULT_MTU_REPROBE_INTERVAL,
+            unknown_flood_threshold: None,
+            unknown_flood_window: DEFAULT_UNKNOWN_FLOOD_WINDOW,
+            claimed_ids: Vec::new(),
+            bind_device: None,
+            enable_ecn: false,
+            read_budget: DEFAULT_READ_BUDGET,
+            datagram_checksum: false,
+            ack_immediately_above: Some(DEFAULT_ACK_IMMEDIATELY_ABOVE),
+            frame_budget: None,
+            one_way_fail_threshold: Some(DEFAULT_ONE_WAY_FAIL_THRESHOLD),
+            tick_interval_min: DEFAULT_TICK_INTERVAL_MIN,
+            tick_interval_max: DEFAULT_TICK_INTERVAL_MAX,
+            coalesce_channels: Vec::new(),
+            rng_seed: None,
+            consumer_timeout: Some(DEFAULT_CONSUMER_TIMEOUT),
+            max_connection_memory: DEFAULT_MAX_CONNECTION_MEMORY,
+            default_reliability: Reliability::ReliableOrd,
+            default_channel: 0,
+            lan_frame_budget: None,
+            disconnect_linger: DEFAULT_DISCONNECT_LINGER,
+            address_binding: AddressBinding::Strict,
+        }
+    }
+}
+
+/// The options of a [`Client`], passed to [`Client::with_options()`].
+///
+/// [`Client`]: crate::client::Client
+/// [`Client::with_options()`]: crate::client::Client::with_options
+pub type ClientOptions = ConnOptions;
+
+/// The options of every connection a [`Listener`] accepts, see [`Listener::conn_options`].
+///
+/// [`Listener`]: crate::server::Listener
+/// [`Listener::conn_options`]: crate::server::Listener::conn_options
+pub type ServerOptions = ConnOptions;
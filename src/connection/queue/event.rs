@@ -0,0 +1,264 @@
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[cfg(feature = "async_std")]
+use futures::FutureExt;
+
+#[cfg(feature = "async_std")]
+use async_std::{
+    channel::{bounded, Receiver, RecvError, Sender},
+    sync::Mutex as AsyncMutex,
+};
+#[cfg(feature = "async_tokio")]
+use tokio::sync::{
+    mpsc::{channel as bounded, Receiver, Sender},
+    Mutex as AsyncMutex,
+};
+
+#[cfg(feature = "async_tokio")]
+use crate::connection::RecvError;
+
+use crate::connection::event::{RakEvent, ViolationKind};
+
+/// How many events about the messages of a connection, like [`RakEvent::OrderedGap`], wait for
+/// the application. The ones after it are dropped.
+pub const EVENT_BACKLOG: usize = 10;
+/// How many lifecycle events wait for the application, see [`RakEvent::is_lifecycle()`].
+/// A connection only has a handful of these, the same violation is never waiting twice.
+///
+/// The lane holds one more, which is kept for the [`RakEvent::Disconnected`], so that one is
+/// never dropped.
+pub const LIFECYCLE_BACKLOG: usize = 16;
+
+/// Creates the event queue of a connection, the events are read from the [`EventReceiver`].
+pub fn event_queue() -> (EventSender, EventReceiver) {
+    let (data, data_recv) = bounded::<RakEvent>(EVENT_BACKLOG);
+    let (lifecycle, lifecycle_recv) = bounded::<(u64, RakEvent)>(LIFECYCLE_BACKLOG + 1);
+    let violations = Arc::new(AtomicU8::new(0));
+    let disconnect = Arc::new(AtomicBool::new(false));
+    let sender = EventSender {
+        data,
+        lifecycle,
+        queued: Arc::new(Mutex::new(0)),
+        violations: violations.clone(),
+        disconnect: disconnect.clone(),
+    };
+    let receiver = EventReceiver {
+        lanes: AsyncMutex::new(Lanes {
+            data: data_recv,
+            lifecycle: lifecycle_recv,
+            received: 0,
+            waiting: None,
+            violations,
+            disconnect,
+        }),
+    };
+    (sender, receiver)
+}
+
+fn violation_bit(kind: ViolationKind) -> u8 {
+    match kind {
+        ViolationKind::UnknownFlood => 1,
+        ViolationKind::CorruptDatagrams => 1 << 1,
+        ViolationKind::OrderJump => 1 << 2,
+        ViolationKind::SpoofedSource { .. } => 1 << 3,
+    }
+}
+
+/// Queues the events of a connection in two lanes, so lifecycle events are never stuck behind,
+/// or dropped for, a backlog of events about its messages.
+///
+/// A lifecycle event still comes after the events that were queued before it.
+#[derive(Debug, Clone)]
+pub struct EventSender {
+    data: Sender<RakEvent>,
+    lifecycle: Sender<(u64, RakEvent)>,
+    /// How many events were queued in the data lane, a lifecycle event is handed out
+    /// once as many were read.
+    queued: Arc<Mutex<u64>>,
+    /// The kinds of violations waiting to be read, a bit each.
+    violations: Arc<AtomicU8>,
+    /// Whether a [`RakEvent::Disconnected`] is waiting to be read.
+    disconnect: Arc<AtomicBool>,
+}
+
+impl EventSender {
+    /// Queues `event`, this hands it back when there is no room for it.
+    ///
+    /// A [`RakEvent::ProtocolViolation`] of a kind that is still waiting to be read is not
+    /// queued again, the application already has it coming. The same goes for a
+    /// [`RakEvent::Disconnected`], which always has room otherwise: this only hands one back
+    /// once the [`EventReceiver`] is gone.
+    pub fn try_send(&self, event: RakEvent) -> Result<(), RakEvent> {
+        if !event.is_lifecycle() {
+            let mut queued = self.queued.lock().unwrap();
+            self.data.try_send(event).map_err(into_inner)?;
+            *queued += 1;
+            return Ok(());
+        }
+
+        let bit = match event {
+            RakEvent::ProtocolViolation { kind } => violation_bit(kind),
+            _ => 0,
+        };
+        if bit != 0 && self.violations.fetch_or(bit, Ordering::AcqRel) & bit != 0 {
+            return Ok(());
+        }
+        let disconnect = matches!(event, RakEvent::Disconnected { .. });
+        if disconnect && self.disconnect.swap(true, Ordering::AcqRel) {
+            return Ok(());
+        }
+
+        let queued = self.queued.lock().unwrap();
+        // the last spot in the lane is kept for the disconnect, the lock keeps others out of it.
+        let sent = if !disconnect && lane_len(&self.lifecycle) >= LIFECYCLE_BACKLOG {
+            Err(event)
+        } else {
+            self.lifecycle
+                .try_send((*queued, event))
+                .map_err(|e| into_inner(e).1)
+        };
+        if sent.is_err() {
+            if bit != 0 {
+                self.violations.fetch_and(!bit, Ordering::AcqRel);
+            }
+            if disconnect {
+                self.disconnect.store(false, Ordering::Release);
+            }
+        }
+        sent
+    }
+}
+
+#[cfg(feature = "async_std")]
+fn lane_len<T>(lane: &Sender<T>) -> usize {
+    lane.len()
+}
+
+#[cfg(feature = "async_tokio")]
+fn lane_len<T>(lane: &Sender<T>) -> usize {
+    lane.max_capacity() - lane.capacity()
+}
+
+#[cfg(feature = "async_std")]
+fn into_inner<T>(e: async_std::channel::TrySendError<T>) -> T {
+    e.into_inner()
+}
+
+#[cfg(feature = "async_tokio")]
+fn into_inner<T>(e: tokio::sync::mpsc::error::TrySendError<T>) -> T {
+    e.into_inner()
+}
+
+/// Reads the events of a connection, lifecycle events ahead of the others.
+#[derive(Debug)]
+pub struct EventReceiver {
+    lanes: AsyncMutex<Lanes>,
+}
+
+impl EventReceiver {
+    /// Receives the next event, this errors once every [`EventSender`] is gone and every
+    /// event was read.
+    ///
+    /// This is cancellation safe, an event is only taken once it is returned.
+    pub async fn recv(&self) -> Result<RakEvent, RecvError> {
+        self.lanes.lock().await.recv().await
+    }
+}
+
+#[derive(Debug)]
+struct Lanes {
+    data: Receiver<RakEvent>,
+    lifecycle: Receiver<(u64, RakEvent)>,
+    /// How many events were read from the data lane.
+    received: u64,
+    /// The lifecycle event that is handed out next, with the events it comes after.
+    waiting: Option<(u64, RakEvent)>,
+    violations: Arc<AtomicU8>,
+    disconnect: Arc<AtomicBool>,
+}
+
+impl Lanes {
+    async fn recv(&mut self) -> Result<RakEvent, RecvError> {
+        if self.waiting.is_none() {
+            self.waiting = try_next(&mut self.lifecycle);
+        }
+
+        if self.waiting.is_none() {
+            let data = &mut self.data;
+            let lifecycle = &mut self.lifecycle;
+
+            #[cfg(feature = "async_std")]
+            let next_event = futures::select_biased! {
+                event = next(lifecycle).fuse() => event.map(Ok),
+                event = next(data).fuse() => event.map(Err),
+            };
+            #[cfg(feature = "async_tokio")]
+            let next_event = tokio::select! {
+                biased;
+                event = next(lifecycle) => event.map(Ok),
+                event = next(data) => event.map(Err),
+            };
+
+            match next_event {
+                Some(Ok(event)) => self.waiting = Some(event),
+                Some(Err(event)) => {
+                    self.received += 1;
+                    return Ok(event);
+                }
+                // both lanes close together, the data lane may still have events.
+                None => {}
+            }
+        }
+
+        // the events that were queued before it go first, these are in the lane already.
+        if matches!(self.waiting, Some((after, _)) if self.received >= after) {
+            return Ok(self.hand_out());
+        }
+        match next(&mut self.data).await {
+            Some(event) => {
+                self.received += 1;
+                Ok(event)
+            }
+            None if self.waiting.is_some() => Ok(self.hand_out()),
+            None => Err(closed()),
+        }
+    }
+
+    fn hand_out(&mut self) -> RakEvent {
+        let (_, event) = self.waiting.take().unwrap();
+        match event {
+            RakEvent::ProtocolViolation { kind } => {
+                self.violations
+                    .fetch_and(!violation_bit(kind), Ordering::AcqRel);
+            }
+            RakEvent::Disconnected { .. } => self.disconnect.store(false, Ordering::Release),
+            _ => {}
+        }
+        event
+    }
+}
+
+#[cfg(feature = "async_std")]
+async fn next<T>(lane: &mut Receiver<T>) -> Option<T> {
+    lane.recv().await.ok()
+}
+
+#[cfg(feature = "async_tokio")]
+async fn next<T>(lane: &mut Receiver<T>) -> Option<T> {
+    lane.recv().await
+}
+
+fn try_next<T>(lane: &mut Receiver<T>) -> Option<T> {
+    lane.try_recv().ok()
+}
+
+#[cfg(feature = "async_std")]
+fn closed() -> RecvError {
+    RecvError
+}
+
+#[cfg(feature = "async_tokio")]
+fn closed() -> RecvError {
+    RecvError::Closed
+}
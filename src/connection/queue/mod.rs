@@ -1,17 +1,26 @@
+pub(crate) mod event;
 pub(crate) mod recv;
+pub(crate) mod schedule;
 pub(crate) mod send;
 
+pub use self::event::*;
 pub use self::recv::*;
+pub use self::schedule::*;
 pub use self::send::*;
 
 use std::collections::BTreeMap;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::ops::RangeInclusive;
+use std::time::{Duration, Instant};
 
+use crate::connection::stats::ChannelStats;
 use crate::protocol::frame::FragmentMeta;
 use crate::protocol::frame::Frame;
+use crate::protocol::index::{unwrapped, DatagramSeq, OrderIndex, SplitId, U24_MAX};
+use crate::protocol::ranges::SequenceRanges;
 use crate::protocol::reliability::Reliability;
 use crate::protocol::RAKNET_HEADER_FRAME_OVERHEAD;
-use crate::server::current_epoch;
+use crate::util::current_epoch;
 
 #[derive(Debug, Clone)]
 pub enum NetQueueError<E> {
@@ -53,14 +62,27 @@ pub trait NetQueue<Item> {
     fn flush(&mut self) -> Result<Vec<Item>, NetQueueError<Self::Error>>;
 }
 
+/// The widest a [`RecoveryQueue`] spans, past half the sequence space it is ambiguous which of
+/// two sequences is the older one.
+const MAX_RECOVERY_SPAN: usize = (U24_MAX / 2) as usize;
+
 /// A recovery queue is used to store packets that need to be resent.
 /// This is used for sequenced and ordered packets.
+///
+/// The packets are held in a ring indexed by the distance of their sequence from the oldest one
+/// held, so finding, adding or removing one takes the same time however many are in flight.
+/// The slot of a packet removed out of order stays empty until the ones before it are gone.
 #[derive(Debug, Clone)]
 pub struct RecoveryQueue<Item> {
-    /// The current queue of packets by timestamp
-    /// (seq, (packet, timestamp))
-    // TODO use the timestamp for round trip time (RTT)
-    queue: HashMap<u32, (u64, Item)>,
+    /// The packet of the sequence `start + n`, with the time it was held at, is in slot `n`.
+    /// The first and last slots are never empty.
+    slots: VecDeque<Option<(u64, Item)>>,
+    /// The sequence of the first slot.
+    start: DatagramSeq,
+    /// The amount of slots that are taken.
+    len: usize,
+    /// The amount of sequences the ring spans at most.
+    capacity: usize,
 }
 
 impl<Item> RecoveryQueue<Item>
@@ -68,47 +90,169 @@ where
     Item: Clone,
 {
     pub fn new() -> Self {
+        Self::with_capacity(MAX_RECOVERY_SPAN)
+    }
+
+    /// Creates a queue spanning at most `capacity` sequences. Holding a sequence further ahead
+    /// of the oldest one evicts the oldest ones, a sequence that far behind the newest one
+    /// is not held at all.
+    pub fn with_capacity(capacity: usize) -> Self {
         Self {
-            queue: HashMap::new(),
+            slots: VecDeque::new(),
+            start: DatagramSeq::new(0),
+            len: 0,
+            capacity: capacity.clamp(1, MAX_RECOVERY_SPAN),
         }
     }
 
-    pub fn insert_id(&mut self, seq: u32, item: Item) {
-        self.queue.insert(seq, (current_epoch(), item));
+    pub fn len(&self) -> usize {
+        self.len
     }
 
-    pub fn get_all(&mut self) -> Vec<(u32, Item)> {
-        self.queue
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn insert_id(&mut self, seq: DatagramSeq, item: Item) {
+        self.hold(seq, (current_epoch(), item));
+    }
+
+    /// The sequences within `range` that are held, in order. Only those are visited however
+    /// wide the range is. A range that ends below its start wraps around at 24 bits.
+    pub fn held_in(&self, range: RangeInclusive<DatagramSeq>) -> Vec<DatagramSeq> {
+        let Some(end) = self.end() else {
+            return Vec::new();
+        };
+        let spanned = unwrapped(self.start, end);
+        unwrapped(*range.start(), *range.end())
+            .into_iter()
+            .flat_map(|(first, last)| {
+                // the part of the range the ring spans, lowest sequence first.
+                let mut within = spanned
+                    .iter()
+                    .map(|(from, to)| (first.max(*from), last.min(*to)))
+                    .filter(|(from, to)| from <= to)
+                    .collect::<Vec<_>>();
+                within.sort_unstable();
+                within
+            })
+            .flat_map(|(from, to)| (from..=to).map(DatagramSeq::new))
+            .filter(|seq| self.slot(*seq).is_some())
+            .collect()
+    }
+
+    /// Every packet held with its sequence, the oldest first.
+    pub fn get_all(&mut self) -> Vec<(DatagramSeq, Item)> {
+        self.slots
             .iter()
-            .map(|(seq, (_, item))| (*seq, item.clone()))
+            .enumerate()
+            .filter_map(|(n, slot)| {
+                slot.as_ref()
+                    .map(|(_, item)| (self.start.wrapping_add(n as u32), item.clone()))
+            })
             .collect::<Vec<_>>()
     }
 
     pub fn flush_old(&mut self, threshold: u64) -> Vec<Item> {
-        let old = self
-            .queue
-            .iter()
-            .filter(|(_, (time, _))| (*time + threshold) < current_epoch())
-            .map(|(_, (_, item))| item.clone())
-            .collect::<Vec<_>>();
-        self.queue
-            .retain(|_, (time, _)| (*time + threshold) > current_epoch());
+        let now = current_epoch();
+        let mut old = Vec::new();
+        for slot in self.slots.iter_mut() {
+            if matches!(slot, Some((time, _)) if *time + threshold < now) {
+                if let Some((_, item)) = slot.take() {
+                    old.push(item);
+                }
+            }
+        }
+        self.len -= old.len();
+        self.trim();
         old
     }
 }
 
+impl<Item> RecoveryQueue<Item> {
+    /// The sequence of the last slot, `None` when nothing is held.
+    fn end(&self) -> Option<DatagramSeq> {
+        (!self.slots.is_empty()).then(|| self.start.wrapping_add(self.slots.len() as u32 - 1))
+    }
+
+    /// The index of the taken slot of `seq`.
+    fn slot(&self, seq: DatagramSeq) -> Option<usize> {
+        let n = self.start.distance_to(seq) as usize;
+        self.slots.get(n)?.as_ref().map(|_| n)
+    }
+
+    fn hold(&mut self, seq: DatagramSeq, entry: (u64, Item)) {
+        if self.slots.is_empty() {
+            self.start = seq;
+        } else if seq.is_before(self.start) {
+            // older than anything held, the ring grows at the front if it may span that far.
+            let behind = seq.distance_to(self.start) as usize;
+            if behind + self.slots.len() > self.capacity {
+                return;
+            }
+            for _ in 0..behind {
+                self.slots.push_front(None);
+            }
+            self.start = seq;
+        } else {
+            let ahead = self.start.distance_to(seq) as usize;
+            if ahead >= self.capacity {
+                self.evict(ahead + 1 - self.capacity);
+                if self.slots.is_empty() {
+                    self.start = seq;
+                }
+            }
+        }
+
+        let n = self.start.distance_to(seq) as usize;
+        if n >= self.slots.len() {
+            self.slots.resize_with(n + 1, || None);
+        }
+        if self.slots[n].replace(entry).is_none() {
+            self.len += 1;
+        }
+    }
+
+    /// Drops the first `count` slots, and what they hold.
+    fn evict(&mut self, count: usize) {
+        let count = count.min(self.slots.len());
+        self.len -= self.slots.drain(..count).flatten().count();
+        self.start = self.start.wrapping_add(count as u32);
+        self.trim();
+    }
+
+    fn take(&mut self, seq: DatagramSeq) -> Option<(u64, Item)> {
+        let n = self.slot(seq)?;
+        let entry = self.slots[n].take();
+        self.len -= 1;
+        self.trim();
+        entry
+    }
+
+    /// Drops the empty slots at either end, so that the first slot holds the oldest packet.
+    fn trim(&mut self) {
+        while let Some(None) = self.slots.front() {
+            self.slots.pop_front();
+            self.start = self.start.wrapping_add(1);
+        }
+        while let Some(None) = self.slots.back() {
+            self.slots.pop_back();
+        }
+    }
+}
+
 impl<Item> NetQueue<Item> for RecoveryQueue<Item> {
-    type KeyId = u32;
+    type KeyId = DatagramSeq;
     type Error = ();
 
     fn insert(&mut self, item: Item) -> Result<Self::KeyId, NetQueueError<Self::Error>> {
-        let index = self.queue.len() as u32;
-        self.queue.insert(index, (current_epoch(), item));
+        let index = DatagramSeq::new(self.len as u32);
+        self.hold(index, (current_epoch(), item));
         Ok(index)
     }
 
     fn remove(&mut self, key: Self::KeyId) -> Result<Item, NetQueueError<Self::Error>> {
-        if let Some((_, item)) = self.queue.remove(&key) {
+        if let Some((_, item)) = self.take(key) {
             Ok(item)
         } else {
             Err(NetQueueError::ItemDeletionFail)
@@ -116,7 +260,7 @@ impl<Item> NetQueue<Item> for RecoveryQueue<Item> {
     }
 
     fn get(&mut self, key: Self::KeyId) -> Result<&Item, NetQueueError<Self::Error>> {
-        if let Some((_, item)) = self.queue.get(&key) {
+        if let Some(Some((_, item))) = self.slot(key).map(|n| &self.slots[n]) {
             Ok(item)
         } else {
             Err(NetQueueError::ItemDeletionFail)
@@ -124,10 +268,13 @@ impl<Item> NetQueue<Item> for RecoveryQueue<Item> {
     }
 
     fn flush(&mut self) -> Result<Vec<Item>, NetQueueError<Self::Error>> {
-        let mut items = Vec::new();
-        for (_, (_, item)) in self.queue.drain() {
-            items.push(item);
-        }
+        let items = self
+            .slots
+            .drain(..)
+            .flatten()
+            .map(|(_, item)| item)
+            .collect::<Vec<_>>();
+        self.len = 0;
         Ok(items)
     }
 }
@@ -138,19 +285,21 @@ impl<Item> NetQueue<Item> for RecoveryQueue<Item> {
 /// Usage:
 /// ```ignore
 /// use rak_rs::connection::queue::OrderedQueue;
+/// use rak_rs::protocol::index::OrderIndex;
+/// use rak_rs::protocol::ranges::SequenceRanges;
 /// let mut ord_qu: OrderedQueue<Vec<u8>> = OrderedQueue::new();
 /// // Insert a packet with the id of "1"
-/// ord_qu.insert(1, vec![0, 1]);
-/// ord_qu.insert(5, vec![1, 0]);
-/// ord_qu.insert(3, vec![2, 0]);
+/// ord_qu.insert(OrderIndex::new(1), vec![0, 1]);
+/// ord_qu.insert(OrderIndex::new(5), vec![1, 0]);
+/// ord_qu.insert(OrderIndex::new(3), vec![2, 0]);
 ///
 /// // Get the packets we still need.
-/// let needed: Vec<u32> = ord_qu.missing();
-/// assert_eq!(needed, vec![0, 2, 4]);
+/// let needed: SequenceRanges = ord_qu.missing();
+/// assert_eq!(needed.iter_ids().collect::<Vec<u32>>(), vec![0, 2, 4]);
 ///
 /// // We would in theory, request these packets, but we're going to insert them
-/// ord_qu.insert(4, vec![2, 0, 0, 1]);
-/// ord_qu.insert(2, vec![1, 0, 0, 2]);
+/// ord_qu.insert(OrderIndex::new(4), vec![2, 0, 0, 1]);
+/// ord_qu.insert(OrderIndex::new(2), vec![1, 0, 0, 2]);
 ///
 /// // Now let's return our packets in order.
 /// // Will return a vector of these packets in order by their "id".
@@ -160,9 +309,17 @@ impl<Item> NetQueue<Item> for RecoveryQueue<Item> {
 pub struct OrderedQueue<Item: Clone + std::fmt::Debug> {
     /// The current ordered queue channels
     /// Channel, (Highest Index, Ord Index, Item)
-    pub queue: BTreeMap<u32, Item>,
+    pub queue: BTreeMap<OrderIndex, Item>,
     /// The window for this queue.
-    pub window: (u32, u32),
+    pub window: (OrderIndex, OrderIndex),
+    /// When the head of this queue was first seen missing while items were buffered behind it.
+    stalled_since: Option<Instant>,
+    /// The amount of items released by this queue.
+    delivered: u64,
+    /// The total time this queue has been blocked by a missing head, excluding the current block.
+    total_blocked: Duration,
+    /// The longest time this queue has been blocked by a missing head.
+    max_blocked: Duration,
 }
 
 impl<Item> OrderedQueue<Item>
@@ -172,17 +329,21 @@ where
     pub fn new() -> Self {
         Self {
             queue: BTreeMap::new(),
-            window: (0, 0),
+            window: (OrderIndex::new(0), OrderIndex::new(0)),
+            stalled_since: None,
+            delivered: 0,
+            total_blocked: Duration::ZERO,
+            max_blocked: Duration::ZERO,
         }
     }
 
-    pub fn next(&mut self) -> u32 {
+    pub fn next_index(&mut self) -> OrderIndex {
         self.window.0 = self.window.0.wrapping_add(1);
         return self.window.0;
     }
 
-    pub fn insert(&mut self, index: u32, item: Item) -> bool {
-        if index < self.window.0 {
+    pub fn insert(&mut self, index: OrderIndex, item: Item) -> bool {
+        if index.is_before(self.window.0) {
             return false;
         }
 
@@ -190,48 +351,139 @@ where
             return false;
         }
 
-        if index >= self.window.1 {
-            self.window.1 = index + 1;
+        if !index.is_before(self.window.1) {
+            self.window.1 = index.wrapping_add(1);
         }
 
         self.queue.insert(index, item);
         true
     }
 
-    pub fn insert_abs(&mut self, index: u32, item: Item) {
-        if index >= self.window.1 {
-            self.window.1 = index + 1;
+    pub fn insert_abs(&mut self, index: OrderIndex, item: Item) {
+        if !index.is_before(self.window.1) {
+            self.window.1 = index.wrapping_add(1);
         }
 
         self.queue.insert(index, item);
     }
 
-    pub fn missing(&self) -> Vec<u32> {
-        let mut missing = Vec::new();
-        for i in self.window.0..self.window.1 {
-            if !self.queue.contains_key(&i) {
-                missing.push(i);
+    /// The buffered indexes from the start of the window on, in the order they are released.
+    /// Indexes past the wrap around at 24 bits come after the ones before it.
+    fn buffered(&self) -> impl Iterator<Item = &OrderIndex> {
+        self.queue
+            .range(self.window.0..)
+            .chain(self.queue.range(..self.window.0))
+            .map(|(index, _)| index)
+            .filter(|index| !index.is_before(self.window.0))
+    }
+
+    /// Returns the indexes within the window that have not been received yet.
+    pub fn missing(&self) -> SequenceRanges {
+        let mut missing = SequenceRanges::new();
+        let mut next = self.window.0;
+
+        // the indexes from `from` up to `to`, split in two when they wrap around.
+        let mut add = |from: OrderIndex, to: OrderIndex| {
+            let last = to.wrapping_sub(1);
+            if from.get() <= last.get() {
+                missing.insert_range(from.get(), last.get());
+            } else {
+                missing.insert_range(from.get(), U24_MAX);
+                missing.insert_range(0, last.get());
             }
+        };
+
+        for index in self.buffered() {
+            if next.is_before(*index) {
+                add(next, *index);
+            }
+            next = index.wrapping_add(1);
         }
+
+        if next.is_before(self.window.1) {
+            add(next, self.window.1);
+        }
+
         missing
     }
 
     pub fn flush(&mut self) -> Vec<Item> {
-        let mut items = Vec::<(u32, Item)>::new();
-        while self.queue.contains_key(&self.window.0) {
-            if let Some(item) = self.queue.remove(&self.window.0) {
-                items.push((self.window.0, item));
-            } else {
-                break;
-            }
+        let mut items = Vec::<Item>::new();
+        while let Some(item) = self.queue.remove(&self.window.0) {
+            items.push(item);
             self.window.0 = self.window.0.wrapping_add(1);
         }
 
-        items.sort_by(|a, b| a.0.cmp(&b.0));
-        return items
-            .iter()
-            .map(|(_, item)| item.clone())
-            .collect::<Vec<Item>>();
+        self.delivered += items.len() as u64;
+        items
+    }
+
+    /// Gives up on the head of the queue if it has been missing for at least `limit`
+    /// while items are buffered behind it.
+    ///
+    /// Every missing index before the next buffered item is skipped and returned, after which
+    /// [`OrderedQueue::flush()`] will release the buffered items. Skipped indexes fall behind
+    /// the window, so they are rejected by [`OrderedQueue::insert()`] if they arrive late.
+    pub fn skip_stalled(&mut self, now: Instant, limit: Duration) -> Vec<OrderIndex> {
+        let since = match self.observe(now) {
+            Some(since) => since,
+            None => return Vec::new(),
+        };
+
+        if now.duration_since(since) < limit {
+            return Vec::new();
+        }
+
+        let mut skipped = Vec::new();
+        while matches!(self.buffered().next(), Some(next) if self.window.0.is_before(*next)) {
+            skipped.push(self.window.0);
+            self.window.0 = self.window.0.wrapping_add(1);
+        }
+
+        self.end_block(now);
+        skipped
+    }
+
+    /// Updates the head-of-line blocking state of this queue at `now`.
+    /// Returns when the current block started, or `None` if nothing is being held up.
+    pub fn observe(&mut self, now: Instant) -> Option<Instant> {
+        if self.is_blocked() {
+            Some(*self.stalled_since.get_or_insert(now))
+        } else {
+            self.end_block(now);
+            None
+        }
+    }
+
+    /// Whether the head of this queue is missing while items are buffered behind it.
+    pub fn is_blocked(&self) -> bool {
+        matches!(self.buffered().next(), Some(next) if *next != self.window.0)
+    }
+
+    /// Returns the delivery statistics of this queue at `now`.
+    pub fn stats(&self, channel: u8, now: Instant) -> ChannelStats {
+        let blocked_for = match self.stalled_since {
+            Some(since) if self.is_blocked() => now.saturating_duration_since(since),
+            _ => Duration::ZERO,
+        };
+
+        ChannelStats {
+            channel,
+            delivered: self.delivered,
+            buffered: self.queue.len(),
+            head_gap: self.is_blocked().then_some(self.window.0),
+            blocked_for,
+            total_blocked: self.total_blocked + blocked_for,
+            max_blocked: self.max_blocked.max(blocked_for),
+        }
+    }
+
+    fn end_block(&mut self, now: Instant) {
+        if let Some(since) = self.stalled_since.take() {
+            let blocked = now.saturating_duration_since(since);
+            self.total_blocked += blocked;
+            self.max_blocked = self.max_blocked.max(blocked);
+        }
     }
 }
 
@@ -245,22 +497,33 @@ pub struct FragmentQueue {
     /// The current fragment id to use
     /// If for some reason this wraps back to 0,
     /// and the fragment queue is full, 0 is then cleared and reused.
-    fragment_id: u16,
+    fragment_id: SplitId,
 
     /// The current Fragments
     /// Hashmap is by Fragment id, with the value being
     /// (`size`, Vec<Frame>)
-    fragments: HashMap<u16, (u32, Vec<Frame>)>,
+    fragments: HashMap<SplitId, (u32, Vec<Frame>)>,
 }
 
 impl FragmentQueue {
     pub fn new() -> Self {
         Self {
-            fragment_id: 0,
+            fragment_id: SplitId::new(0),
             fragments: HashMap::new(),
         }
     }
 
+    /// Makes room for `splits` split messages being put back together at once.
+    pub fn reserve(&mut self, splits: usize) {
+        self.fragments
+            .reserve(splits.saturating_sub(self.fragments.len()));
+    }
+
+    /// The split messages the queue has room for without growing.
+    pub fn capacity(&self) -> usize {
+        self.fragments.capacity()
+    }
+
     /// Inserts the frame into the fragment queue.
     /// Returns a result tuple of (`fragment_size`, `fragment_index`)
     pub fn insert(&mut self, fragment: Frame) -> Result<(u32, u32), FragmentQueueError> {
@@ -298,7 +561,7 @@ impl FragmentQueue {
 
     /// Attempts to collect all fragments from a given fragment id.
     /// Will fail if not all fragments are specified.
-    pub fn collect(&mut self, id: u16) -> Result<Vec<u8>, FragmentQueueError> {
+    pub fn collect(&mut self, id: SplitId) -> Result<Vec<u8>, FragmentQueueError> {
         if let Some((size, frames)) = self.fragments.get_mut(&id) {
             if *size == frames.len() as u32 {
                 // sort all frames by id,
@@ -328,14 +591,8 @@ impl FragmentQueue {
 
     /// This will split a given frame into a bunch of smaller frames within the specified
     /// restriction.
-    pub fn split_insert(&mut self, buffer: &[u8], mtu: u16) -> Result<u16, FragmentQueueError> {
-        self.fragment_id += self.fragment_id.wrapping_add(1);
-
-        let id = self.fragment_id;
-
-        if self.fragments.contains_key(&id) {
-            self.fragments.remove(&id);
-        }
+    pub fn split_insert(&mut self, buffer: &[u8], mtu: u16) -> Result<SplitId, FragmentQueueError> {
+        let id = self.next_id();
 
         if let Ok(frames) = Self::split(buffer, id, mtu) {
             self.fragments.insert(id, (frames.len() as u32, frames));
@@ -345,7 +602,14 @@ impl FragmentQueue {
         return Err(FragmentQueueError::DoesNotNeedSplit);
     }
 
-    pub fn split(buffer: &[u8], id: u16, mtu: u16) -> Result<Vec<Frame>, FragmentQueueError> {
+    /// Hands out the next fragment id, dropping any fragments still stored under it.
+    pub fn next_id(&mut self) -> SplitId {
+        self.fragment_id = self.fragment_id.wrapping_add(1);
+        self.fragments.remove(&self.fragment_id);
+        self.fragment_id
+    }
+
+    pub fn split(buffer: &[u8], id: SplitId, mtu: u16) -> Result<Vec<Frame>, FragmentQueueError> {
         let max_mtu = mtu - RAKNET_HEADER_FRAME_OVERHEAD;
 
         if buffer.len() > max_mtu.into() {
@@ -375,7 +639,7 @@ impl FragmentQueue {
         return Err(FragmentQueueError::DoesNotNeedSplit);
     }
 
-    pub fn get(&self, id: &u16) -> Result<&(u32, Vec<Frame>), FragmentQueueError> {
+    pub fn get(&self, id: &SplitId) -> Result<&(u32, Vec<Frame>), FragmentQueueError> {
         if let Some(v) = self.fragments.get(id) {
             return Ok(v);
         }
@@ -383,7 +647,7 @@ impl FragmentQueue {
         return Err(FragmentQueueError::FragmentInvalid);
     }
 
-    pub fn get_mut(&mut self, id: &u16) -> Result<&mut (u32, Vec<Frame>), FragmentQueueError> {
+    pub fn get_mut(&mut self, id: &SplitId) -> Result<&mut (u32, Vec<Frame>), FragmentQueueError> {
         if let Some(v) = self.fragments.get_mut(id) {
             return Ok(v);
         }
@@ -391,13 +655,13 @@ impl FragmentQueue {
         return Err(FragmentQueueError::FragmentInvalid);
     }
 
-    pub fn remove(&mut self, id: &u16) -> bool {
+    pub fn remove(&mut self, id: &SplitId) -> bool {
         self.fragments.remove(id).is_some()
     }
 
     /// This will hard clear the fragment queue, this should only be used if memory becomes an issue!
     pub fn clear(&mut self) {
-        self.fragment_id = 0;
+        self.fragment_id = SplitId::new(0);
         self.fragments.clear();
     }
 }
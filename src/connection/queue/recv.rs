@@ -1,150 +1,631 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use crate::connection::controller::window::ReliableWindow;
-use crate::protocol::ack::{Ack, Ackable, Record, SingleRecord};
+use crate::connection::controller::checksum::{ChecksumGuard, ChecksumVerdict};
+use crate::connection::controller::coalesce::{self, COALESCED_ID};
+use crate::connection::controller::memory::{self, MemAccount, MemBudget, ENTRY_OVERHEAD};
+use crate::connection::controller::mtu::MTU_PROBE_ID;
+use crate::connection::controller::profile::Capacity;
+use crate::connection::controller::window::{ReliableWindow, WindowError, RELIABLE_WINDOW_SIZE};
+use crate::connection::descriptor::{ChannelCounters, RecvCounters};
+use crate::connection::event::{RakEvent, ViolationKind};
+use crate::connection::options::DEFAULT_ORDER_JUMP_LIMIT;
+use crate::connection::stats::{ChannelStats, SharedStats};
 use crate::protocol::frame::{Frame, FramePacket};
+use crate::protocol::index::{unwrapped, DatagramSeq, MessageIndex, OrderIndex, SequenceIndex};
+use crate::protocol::ranges::SequenceRanges;
 use crate::protocol::reliability::Reliability;
 use crate::protocol::MAX_FRAGS;
 use crate::rakrs_debug;
-use crate::server::current_epoch;
 
 use super::{FragmentQueue, OrderedQueue};
 
 #[derive(Debug, Clone)]
 pub enum RecvQueueError {
     OldSeq,
+    /// The datagram is too far ahead of the ones still missing, it is not acknowledged so the
+    /// peer sends it again.
+    TooFarAhead,
 }
 
 #[derive(Debug, Clone)]
 pub struct RecvQueue {
     frag_queue: FragmentQueue,
-    pub(crate) window: ReliableWindow,
-    pub(crate) reliable_window: ReliableWindow,
-    order_channels: HashMap<u8, OrderedQueue<Vec<u8>>>,
-    /// Set of sequences that we've acknowledged.
-    /// (seq, time)
-    ack: HashSet<(u32, u64)>,
-    nack: HashSet<u32>,
+    pub(crate) window: ReliableWindow<DatagramSeq>,
+    pub(crate) reliable_window: ReliableWindow<MessageIndex>,
+    /// Kept in channel order, so stalls are given up on in the same order every time.
+    order_channels: BTreeMap<u8, OrderedQueue<Vec<u8>>>,
+    /// The highest sequence index received on every channel sequenced messages were sent on.
+    sequence_heads: BTreeMap<u8, SequenceIndex>,
+    /// Sequences we've received, and still need to acknowledge.
+    ack: SequenceRanges,
+    /// Sequences we've skipped over, and haven't received yet.
+    nack: SequenceRanges,
+    /// The highest sequence we've received so far.
+    highest_seq: Option<DatagramSeq>,
+    /// The messages released so far, in the order they were released. This is never sorted,
+    /// so the messages of a datagram keep the order they had within it.
     ready: Vec<Vec<u8>>,
+    /// How long an order channel may be held up by a missing message, `None` is strict ordering.
+    ordered_stall_limit: Option<Duration>,
+    /// How far ahead of its channel an ordered or sequenced message may be.
+    order_jump_limit: u32,
+    /// Ordered and sequenced messages dropped for being too far ahead of their channel.
+    order_jumps: u64,
+    /// Verifies the checksum trailers of the datagrams, if the peer sends them.
+    checksum: ChecksumGuard,
+    /// Whether the peer coalesces messages, which are split back before they are handed out.
+    coalescing: bool,
+    events: Vec<RakEvent>,
+    /// Sequences acknowledged again after their acknowledgement was sent.
+    repeated_acks: u64,
+    /// Where the counters are published, so they can be read without locking the queue.
+    shared_stats: Arc<SharedStats>,
+    /// What the fragments, order channels and ready messages of the queue are charged to,
+    /// along with the indexes its windows hold and the sequences it is missing.
+    memory: MemAccount,
+    /// What the windows and the missing sequences are charged, see [`RecvQueue::settle()`].
+    bookkeeping: usize,
 }
 
 impl RecvQueue {
     pub fn new() -> Self {
         Self {
             frag_queue: FragmentQueue::new(),
-            ack: HashSet::new(),
-            nack: HashSet::new(),
+            ack: SequenceRanges::new(),
+            nack: SequenceRanges::new(),
+            highest_seq: None,
             window: ReliableWindow::new(),
-            reliable_window: ReliableWindow::new(),
+            reliable_window: ReliableWindow::with_size(RELIABLE_WINDOW_SIZE),
             ready: Vec::new(),
-            order_channels: HashMap::new(),
+            order_channels: BTreeMap::new(),
+            sequence_heads: BTreeMap::new(),
+            ordered_stall_limit: None,
+            order_jump_limit: DEFAULT_ORDER_JUMP_LIMIT,
+            order_jumps: 0,
+            checksum: ChecksumGuard::new(false),
+            coalescing: false,
+            events: Vec::new(),
+            repeated_acks: 0,
+            shared_stats: Arc::new(SharedStats::default()),
+            memory: MemAccount::default(),
+            bookkeeping: 0,
         }
     }
 
+    /// Publishes the counters of the queue into `stats` from now on, this is usually shared
+    /// with the send queue, see [`SendQueue::shared_stats()`].
+    ///
+    /// [`SendQueue::shared_stats()`]: crate::connection::queue::SendQueue::shared_stats
+    pub fn set_shared_stats(&mut self, stats: Arc<SharedStats>) {
+        self.shared_stats = stats;
+        self.publish_counters();
+    }
+
+    /// Charges what the queue buffers to `budget` from now on, this is usually shared with the
+    /// send queue, see [`SendQueue::set_memory_budget()`].
+    ///
+    /// [`SendQueue::set_memory_budget()`]: crate::connection::queue::SendQueue::set_memory_budget
+    pub fn set_memory_budget(&mut self, budget: MemBudget) {
+        self.memory.move_to(budget);
+    }
+
+    /// The ledger the queue is charged to.
+    pub fn memory_budget(&self) -> &MemBudget {
+        self.memory.budget()
+    }
+
+    /// The bytes the queue buffers, as charged to its ledger.
+    pub fn memory_charged(&self) -> usize {
+        self.memory.charged()
+    }
+
+    /// Whether what the queue buffers alone exceeds the cap of its ledger.
+    ///
+    /// Everything buffered here was acknowledged to the peer, so none of it can be dropped:
+    /// a peer that makes us hold this much, with fragments that never complete, messages far
+    /// ahead of a missing one or gaps it never fills, has to be disconnected.
+    pub fn exceeds_budget(&self) -> bool {
+        self.memory.charged() > self.memory.budget().cap()
+    }
+
+    fn publish_counters(&self) {
+        self.shared_stats
+            .corrupt_datagrams
+            .store(self.checksum.corrupt(), Ordering::Relaxed);
+        self.shared_stats
+            .repeated_acks
+            .store(self.repeated_acks, Ordering::Relaxed);
+        self.shared_stats
+            .order_jumps
+            .store(self.order_jumps, Ordering::Relaxed);
+    }
+
+    /// Sets how long a missing reliable-ordered message may hold up its channel
+    /// before it is skipped, `None` keeps strict ordering.
+    pub fn set_ordered_stall_limit(&mut self, limit: Option<Duration>) {
+        self.ordered_stall_limit = limit;
+    }
+
+    /// Sets how far past the index its channel expects next an ordered or sequenced message
+    /// may be, the ones further ahead are dropped.
+    pub fn set_order_jump_limit(&mut self, limit: u32) {
+        self.order_jump_limit = limit;
+    }
+
+    /// Sets whether the checksum trailers of frame-set datagrams are verified.
+    pub fn set_datagram_checksum(&mut self, enabled: bool) {
+        self.checksum = ChecksumGuard::new(enabled);
+        self.publish_counters();
+    }
+
+    /// Whether the checksum trailers of frame-set datagrams are verified.
+    pub fn datagram_checksum(&self) -> bool {
+        self.checksum.is_enabled()
+    }
+
+    /// Sets whether messages the peer coalesced are split back, this should only be enabled
+    /// once we agreed to it.
+    pub fn set_coalescing(&mut self, enabled: bool) {
+        self.coalescing = enabled;
+    }
+
+    /// Whether messages the peer coalesced are split back.
+    pub fn coalescing(&self) -> bool {
+        self.coalescing
+    }
+
+    /// The amount of datagrams that were dropped because their checksum didn't match.
+    pub fn corrupt_datagrams(&self) -> u64 {
+        self.checksum.corrupt()
+    }
+
+    /// The amount of ordered and sequenced messages that were dropped because they were too
+    /// far ahead of their channel.
+    pub fn order_jumps(&self) -> u64 {
+        self.order_jumps
+    }
+
+    /// Checks the checksum trailer of a raw frame-set datagram, this returns the datagram
+    /// without its trailer, or `None` if it was damaged in transit.
+    ///
+    /// A damaged datagram should be dropped before it is decoded, its sequence is then
+    /// never recorded and the peer is asked to resend it like any other lost datagram.
+    pub fn verify<'a>(&mut self, datagram: &'a [u8]) -> Option<&'a [u8]> {
+        match self.checksum.verify(datagram) {
+            ChecksumVerdict::Valid { len } => Some(&datagram[..len]),
+            ChecksumVerdict::Corrupt => {
+                self.publish_counters();
+                None
+            }
+            ChecksumVerdict::Damaging => {
+                self.publish_counters();
+                self.events.push(RakEvent::ProtocolViolation {
+                    kind: ViolationKind::CorruptDatagrams,
+                });
+                None
+            }
+        }
+    }
+
+    /// Records the datagram `packet` and handles its frames in the order they were written.
+    ///
+    /// Messages that don't wait on an order channel are released as their frame is handled,
+    /// so the frames of a datagram are delivered in the order they had on the wire, whether
+    /// they arrive in the first transmission of the datagram or a retransmission of it.
     pub fn insert(&mut self, packet: FramePacket) -> Result<(), RecvQueueError> {
-        if !self.window.insert(packet.sequence) {
-            return Err(RecvQueueError::OldSeq);
+        let start = self.window.range().0;
+        match self.window.insert(packet.sequence) {
+            Ok(()) => {}
+            Err(WindowError::Duplicate) => {
+                // our acknowledgement might have been lost, acknowledge it again.
+                if self.ack.insert(packet.sequence.get()) {
+                    self.repeated_acks += 1;
+                    self.publish_counters();
+                }
+                return Err(RecvQueueError::OldSeq);
+            }
+            Err(WindowError::TooFarAhead) => return Err(RecvQueueError::TooFarAhead),
         }
 
-        if self.window.range().0 < packet.sequence {
-            for i in self.window.range().0..packet.sequence {
-                self.nack.insert(i);
+        // the window gave up on whatever it moved past, those are not asked for anymore.
+        let (now_start, _) = self.window.range();
+        if start != now_start {
+            for (first, last) in unwrapped(start, now_start.wrapping_sub(1)) {
+                self.nack.remove_range(first, last);
             }
         }
 
-        self.ack.insert((packet.sequence, current_epoch()));
+        // anything between the highest sequence and this one was skipped, as far back as the
+        // window reaches.
+        let expected = match self.highest_seq {
+            Some(highest) if !highest.wrapping_add(1).is_before(now_start) => {
+                highest.wrapping_add(1)
+            }
+            _ => now_start,
+        };
+
+        if expected.is_before(packet.sequence) {
+            for (first, last) in unwrapped(expected, packet.sequence.wrapping_sub(1)) {
+                self.nack.insert_range(first, last);
+            }
+        }
+
+        if !matches!(self.highest_seq, Some(highest) if !highest.is_before(packet.sequence)) {
+            self.highest_seq = Some(packet.sequence);
+        }
+
+        self.nack.remove(packet.sequence.get());
+        self.ack.insert(packet.sequence.get());
 
         for frame in packet.frames.iter() {
             self.handle_frame(frame);
         }
 
+        self.settle();
         return Ok(());
     }
 
+    /// Whether `sequence` is within the size of the window of either side of its start, where
+    /// the datagrams of the peer that are under way or retransmitted are.
+    pub fn is_near(&self, sequence: DatagramSeq) -> bool {
+        let (start, end) = self.window.range();
+        let size = start.distance_to(end);
+        if sequence.is_before(start) {
+            sequence.distance_to(start) <= size
+        } else {
+            start.distance_to(sequence) < size
+        }
+    }
+
+    /// Charges the indexes the windows hold and the ranges of missing sequences to the ledger,
+    /// these grow with the gaps the peer leaves rather than with what it sends.
+    fn settle(&mut self) {
+        let held = (self.window.len() + self.reliable_window.len() + self.nack.range_count())
+            * ENTRY_OVERHEAD;
+        if held > self.bookkeeping {
+            self.memory.debit(held - self.bookkeeping);
+        } else {
+            self.memory.credit(self.bookkeeping - held);
+        }
+        self.bookkeeping = held;
+    }
+
     pub fn flush(&mut self) -> Vec<Vec<u8>> {
-        self.ready.drain(..).collect::<Vec<Vec<u8>>>()
+        let ready = self.ready.drain(..).collect::<Vec<Vec<u8>>>();
+        for buffer in ready.iter() {
+            self.memory.credit(memory::cost(buffer.len()));
+        }
+        ready
+    }
+
+    /// Makes room for the split and ready messages of `capacity` up front, so a burst from
+    /// the peer doesn't grow the queue while it is received.
+    pub fn prewarm(&mut self, capacity: &Capacity) {
+        self.frag_queue.reserve(capacity.splits);
+        self.ready
+            .reserve(capacity.messages.saturating_sub(self.ready.len()));
+    }
+
+    /// The split and ready messages the queue has room for without growing.
+    pub fn capacity(&self) -> Capacity {
+        Capacity {
+            splits: self.frag_queue.capacity(),
+            messages: self.ready.capacity(),
+            ..Default::default()
+        }
+    }
+
+    /// Puts buffers back, they are handed out by the next flush ahead of anything newer.
+    pub fn requeue(&mut self, buffers: Vec<Vec<u8>>) {
+        for buffer in buffers.iter() {
+            self.memory.debit(memory::cost(buffer.len()));
+        }
+        self.ready.splice(0..0, buffers);
+    }
+
+    /// Returns all events that happened since the last call.
+    pub fn flush_events(&mut self) -> Vec<RakEvent> {
+        self.events.drain(..).collect::<Vec<RakEvent>>()
+    }
+
+    /// Returns the delivery statistics of every order channel that has been used, by channel.
+    pub fn channel_stats(&self, now: Instant) -> Vec<ChannelStats> {
+        let mut stats = self
+            .order_channels
+            .iter()
+            .map(|(channel, queue)| queue.stats(*channel, now))
+            .collect::<Vec<ChannelStats>>();
+        stats.sort_by_key(|stats| stats.channel);
+        stats
+    }
+
+    /// Skips any reliable-ordered message that has been holding up its channel for longer
+    /// than the stall limit, releasing the messages buffered behind it.
+    /// In strict ordering mode this only keeps the blocked time of each channel up to date.
+    ///
+    /// Returns whether any message was given up on, what it released is handed out on the
+    /// next [`RecvQueue::flush()`].
+    pub fn poll_stalls(&mut self, now: Instant) -> bool {
+        let mut released = false;
+        for (channel, queue) in self.order_channels.iter_mut() {
+            // keeps the blocked time of the channel up to date.
+            queue.observe(now);
+
+            let skipped = match self.ordered_stall_limit {
+                Some(limit) => queue.skip_stalled(now, limit),
+                None => continue,
+            };
+
+            if skipped.is_empty() {
+                continue;
+            }
+            released = true;
+
+            for skipped_index in skipped {
+                rakrs_debug!(
+                    true,
+                    "Giving up on {} in order channel {}",
+                    skipped_index,
+                    channel
+                );
+                self.events.push(RakEvent::OrderedGap {
+                    channel: *channel,
+                    skipped_index,
+                });
+            }
+
+            for buffer in queue.flush() {
+                self.memory.credit(memory::cost(buffer.len()));
+                Self::push_ready(&mut self.ready, &mut self.memory, self.coalescing, buffer);
+            }
+        }
+        released
+    }
+
+    /// Hands `buffer` out on the next flush, split back into the messages it carries
+    /// if the peer coalesced them.
+    fn push_ready(
+        ready: &mut Vec<Vec<u8>>,
+        account: &mut MemAccount,
+        coalescing: bool,
+        buffer: Vec<u8>,
+    ) {
+        if coalescing && buffer.first() == Some(&COALESCED_ID) {
+            if let Some(messages) = coalesce::split(&buffer) {
+                for message in messages.iter() {
+                    account.debit(memory::cost(message.len()));
+                }
+                ready.extend(messages);
+                return;
+            }
+        }
+        account.debit(memory::cost(buffer.len()));
+        ready.push(buffer);
+    }
+
+    /// Takes every sequence that still needs to be acknowledged.
+    pub fn ack_flush(&mut self) -> SequenceRanges {
+        std::mem::take(&mut self.ack)
+    }
+
+    /// The amount of sequences received but not acknowledged yet.
+    pub fn ack_debt(&self) -> usize {
+        self.ack.len()
     }
 
-    pub fn ack_flush(&mut self) -> Vec<u32> {
-        self.ack.drain().map(|(seq, _)| seq).collect()
+    /// The amount of sequences acknowledged in more than one datagram, because the peer
+    /// resent them before our acknowledgement reached it.
+    pub fn repeated_acks(&self) -> u64 {
+        self.repeated_acks
     }
 
-    pub fn nack_queue(&mut self) -> Vec<u32> {
-        self.nack.iter().map(|x| *x).collect::<Vec<u32>>()
+    /// The sequences we are still missing, these are not cleared until they arrive.
+    pub fn nack_queue(&self) -> &SequenceRanges {
+        &self.nack
+    }
+
+    /// The counters of this queue, used to describe the session to another listener.
+    pub fn counters(&self) -> RecvCounters {
+        let mut channels = self
+            .order_channels
+            .keys()
+            .chain(self.sequence_heads.keys())
+            .copied()
+            .collect::<BTreeSet<u8>>()
+            .into_iter()
+            .map(|channel| ChannelCounters {
+                channel,
+                sequence_index: self
+                    .sequence_heads
+                    .get(&channel)
+                    .map_or(0, |head| head.get()),
+                order_index: self
+                    .order_channels
+                    .get(&channel)
+                    .map_or(0, |queue| queue.window.0.get()),
+            })
+            .collect::<Vec<ChannelCounters>>();
+        channels.sort_by_key(|channel| channel.channel);
+
+        RecvCounters {
+            datagram_seq: match self.highest_seq {
+                Some(highest) => highest.wrapping_add(1).get(),
+                None => self.window.range().0.get(),
+            },
+            reliable_index: self.reliable_window.range().0.get(),
+            channels,
+        }
+    }
+
+    /// Resumes the counters of a session exported by another listener.
+    pub fn restore_counters(&mut self, counters: &RecvCounters) {
+        self.window.reset(DatagramSeq::new(counters.datagram_seq));
+        self.reliable_window
+            .reset(MessageIndex::new(counters.reliable_index));
+        self.highest_seq = None;
+        self.ack.clear();
+        self.nack.clear();
+
+        for queue in self.order_channels.values() {
+            for buffer in queue.queue.values() {
+                self.memory.credit(memory::cost(buffer.len()));
+            }
+        }
+        self.order_channels.clear();
+        self.sequence_heads.clear();
+        self.settle();
+        for channel in counters.channels.iter() {
+            let mut queue = OrderedQueue::new();
+            let index = OrderIndex::new(channel.order_index);
+            queue.window = (index, index);
+            self.order_channels.insert(channel.channel, queue);
+            self.sequence_heads
+                .insert(channel.channel, SequenceIndex::new(channel.sequence_index));
+        }
+    }
+
+    /// Whether `frame` is further past the index its channel expects next than the jump limit
+    /// allows, this keeps track of the highest sequence index of every channel.
+    ///
+    /// Every fragment of a split message carries its indexes, so the message is dropped
+    /// before any of it is buffered.
+    fn jumps_ahead(&mut self, frame: &Frame) -> bool {
+        let Some(channel) = frame.order_channel else {
+            return false;
+        };
+
+        if frame.reliability.is_ordered() {
+            let Some(index) = frame.order_index else {
+                return false;
+            };
+            let expected = self
+                .order_channels
+                .get(&channel)
+                .map_or(OrderIndex::new(0), |queue| queue.window.0);
+            return !index.is_before(expected)
+                && expected.distance_to(index) > self.order_jump_limit;
+        }
+
+        if frame.reliability.is_sequenced() {
+            let Some(index) = frame.sequence_index else {
+                return false;
+            };
+            let head = self
+                .sequence_heads
+                .entry(channel)
+                .or_insert(SequenceIndex::new(0));
+            let expected = head.wrapping_add(1);
+            if index.is_before(expected) {
+                return false;
+            }
+            if expected.distance_to(index) > self.order_jump_limit {
+                return true;
+            }
+            *head = index;
+        }
+        false
     }
 
     fn handle_frame(&mut self, frame: &Frame) {
+        // an MTU probe only had to arrive, the acknowledgement of its datagram is all it is for.
+        if frame.reliability == Reliability::Unreliable
+            && frame.fragment_meta.is_none()
+            && frame.body.first() == Some(&MTU_PROBE_ID)
+        {
+            return;
+        }
+
         if let Some(reliable_index) = frame.reliable_index {
-            if !self.reliable_window.insert(reliable_index) {
+            if self.reliable_window.insert(reliable_index).is_err() {
                 return;
             }
         }
 
-        if let Some(meta) = frame.fragment_meta.as_ref() {
+        if self.jumps_ahead(frame) {
+            rakrs_debug!(
+                true,
+                "Dropping a frame too far ahead of order channel {:?}!",
+                frame.order_channel
+            );
+            self.order_jumps += 1;
+            self.publish_counters();
+            self.events.push(RakEvent::ProtocolViolation {
+                kind: ViolationKind::OrderJump,
+            });
+            return;
+        }
+
+        let body = if let Some(meta) = frame.fragment_meta.as_ref() {
             if meta.size > MAX_FRAGS {
                 rakrs_debug!(true, "Fragment size is too large, rejected {}!", meta.size);
                 return;
             }
-            if let Err(_) = self.frag_queue.insert(frame.clone()) {}
+            if self.frag_queue.insert(frame.clone()).is_ok() {
+                self.memory.debit(memory::cost(frame.body.len()));
+            }
 
-            let res = self.frag_queue.collect(meta.id);
-            if let Ok(data) = res {
+            // the fragments held for the message, as they were charged when inserted.
+            let held = self.frag_queue.get(&meta.id).map_or(0, |(_, fragments)| {
+                fragments
+                    .iter()
+                    .map(|fragment| memory::cost(fragment.body.len()))
+                    .sum::<usize>()
+            });
+
+            match self.frag_queue.collect(meta.id) {
                 // reconstructed frame packet!
-                self.ready.push(data);
-            } else {
-                rakrs_debug!(
-                    true,
-                    "Still Missing some fragments! {:?}",
-                    frame.fragment_meta.as_ref().unwrap()
-                );
+                // it is ordered like any other message, using the order index of its fragments.
+                Ok(data) => {
+                    self.memory.credit(held);
+                    data
+                }
+                Err(_) => {
+                    rakrs_debug!(
+                        true,
+                        "Still Missing some fragments! {:?}",
+                        frame.fragment_meta.as_ref().unwrap()
+                    );
+                    return;
+                }
             }
-            return;
-        }
+        } else {
+            frame.body.clone()
+        };
 
         match frame.reliability {
-            Reliability::Unreliable => {
-                self.ready.push(frame.body.clone());
+            // unreliable messages are the first to go once the connection holds too much.
+            Reliability::Unreliable | Reliability::UnreliableSeq | Reliability::UnreliableAck
+                if self.memory.budget().is_exceeded() =>
+            {
+                rakrs_debug!(
+                    true,
+                    "Dropping an unreliable message, the connection is over its memory budget!"
+                );
             }
-            Reliability::Reliable => {
-                self.ready.push(frame.body.clone());
+            Reliability::Unreliable
+            | Reliability::UnreliableSeq
+            | Reliability::Reliable
+            | Reliability::ReliableSeq
+            | Reliability::UnreliableAck
+            | Reliability::ReliableAck => {
+                Self::push_ready(&mut self.ready, &mut self.memory, self.coalescing, body);
             }
-            Reliability::ReliableOrd => {
+            Reliability::ReliableOrd | Reliability::ReliableOrdAck => {
                 let channel = frame.order_channel.unwrap();
                 let queue = self
                     .order_channels
                     .entry(channel)
                     .or_insert(OrderedQueue::new());
 
-                if queue.insert(frame.order_index.unwrap(), frame.body.clone()) {
+                let len = body.len();
+                if queue.insert(frame.order_index.unwrap(), body) {
+                    self.memory.debit(memory::cost(len));
                     for pk in queue.flush() {
-                        self.ready.push(pk);
-                    }
-                }
-            }
-            _ => {
-                self.ready.push(frame.body.clone());
-            }
-        }
-    }
-}
-
-impl Ackable for RecvQueue {
-    type NackItem = ();
-
-    fn ack(&mut self, ack: Ack) {
-        if ack.is_nack() {
-            return;
-        }
-
-        // these packets are acknowledged, so we can remove them from the queue.
-        for record in ack.records.iter() {
-            match record {
-                Record::Single(SingleRecord { sequence }) => {
-                    self.nack.remove(&sequence);
-                }
-                Record::Range(ranged) => {
-                    for i in ranged.start.0..ranged.end.0 {
-                        self.nack.remove(&i);
+                        self.memory.credit(memory::cost(pk.len()));
+                        Self::push_ready(&mut self.ready, &mut self.memory, self.coalescing, pk);
                     }
                 }
             }
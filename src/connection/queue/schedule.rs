@@ -0,0 +1,91 @@
+use std::collections::{BTreeMap, VecDeque};
+
+/// The default amount of items taken from a channel before moving on to the next one.
+pub const DEFAULT_CHANNEL_QUANTUM: usize = 4;
+
+/// A round-robin scheduler over order channels.
+///
+/// Each call to [`ChannelScheduler::take()`] visits the channels in rotating order, taking at
+/// most `quantum` items from each before moving on to the next. This prevents a channel doing a
+/// bulk transfer from starving the other channels when only a limited amount of items can be
+/// sent per tick. The rotation persists between calls, so no channel is always served first.
+#[derive(Debug, Clone)]
+pub struct ChannelScheduler<Item> {
+    /// The pending items of each channel, in the order they were pushed.
+    channels: BTreeMap<u8, VecDeque<Item>>,
+    /// The channel that is visited first on the next call to `take`.
+    cursor: u8,
+    /// The amount of items taken from a channel per visit.
+    quantum: usize,
+}
+
+impl<Item> ChannelScheduler<Item> {
+    pub fn new(quantum: usize) -> Self {
+        Self {
+            channels: BTreeMap::new(),
+            cursor: 0,
+            quantum: quantum.max(1),
+        }
+    }
+
+    /// Queues an item on the given channel.
+    pub fn push(&mut self, channel: u8, item: Item) {
+        self.channels.entry(channel).or_default().push_back(item);
+    }
+
+    /// The amount of items waiting over all channels.
+    pub fn len(&self) -> usize {
+        self.channels.values().map(|c| c.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty()
+    }
+
+    /// Keeps only the items `keep` returns `true` for, in the order they were pushed.
+    pub fn retain(&mut self, mut keep: impl FnMut(&Item) -> bool) {
+        for queue in self.channels.values_mut() {
+            queue.retain(&mut keep);
+        }
+        self.channels.retain(|_, queue| !queue.is_empty());
+    }
+
+    /// Takes up to `budget` items, fairly spread over all channels.
+    /// Items of a single channel are always returned in the order they were pushed.
+    pub fn take(&mut self, budget: usize) -> Vec<Item> {
+        let mut taken = Vec::new();
+        let mut first = true;
+
+        while taken.len() < budget && !self.channels.is_empty() {
+            // visit channels starting at the cursor, then wrap around.
+            let order = self
+                .channels
+                .range(self.cursor..)
+                .chain(self.channels.range(..self.cursor))
+                .map(|(channel, _)| *channel)
+                .collect::<Vec<u8>>();
+
+            for channel in order {
+                if taken.len() >= budget {
+                    break;
+                }
+
+                if first {
+                    // the next call starts after the channel we served first this time.
+                    self.cursor = channel.wrapping_add(1);
+                    first = false;
+                }
+
+                let queue = self.channels.get_mut(&channel).unwrap();
+                let amount = self.quantum.min(budget - taken.len()).min(queue.len());
+                taken.extend(queue.drain(..amount));
+
+                if queue.is_empty() {
+                    self.channels.remove(&channel);
+                }
+            }
+        }
+
+        taken
+    }
+}
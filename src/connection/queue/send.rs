@@ -1,23 +1,58 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::io;
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 #[cfg(feature = "async_std")]
 use async_std::net::UdpSocket;
 
 use binary_util::interfaces::Writer;
+use futures::channel::mpsc::UnboundedReceiver;
+use futures::StreamExt;
 #[cfg(feature = "async_tokio")]
 use tokio::net::UdpSocket;
 
-use crate::protocol::ack::{Ack, Ackable, Record, SingleRecord};
-use crate::protocol::frame::{Frame, FramePacket};
+use crate::connection::controller::budget::BudgetShare;
+use crate::connection::controller::checksum::{self, CHECKSUM_LEN};
+use crate::connection::controller::clock::rak_time_now;
+use crate::connection::controller::coalesce::{Coalescer, COALESCED_ID};
+use crate::connection::controller::jitter::{RngProvider, RTO_JITTER};
+use crate::connection::controller::loss::{BlackholeDetector, LossBySize};
+use crate::connection::controller::memory::{self, MemAccount, MemBudget};
+use crate::connection::controller::mtu::{MtuProber, DEFAULT_MTU_REPROBE_INTERVAL, MTU_PROBE_ID};
+use crate::connection::controller::profile::Capacity;
+use crate::connection::controller::rtt::{RttEstimator, RttStats};
+use crate::connection::descriptor::{ChannelCounters, SendCounters, SEQUENCE_SAFETY_MARGIN};
+use crate::connection::stats::SharedStats;
+use crate::connection::transform::SharedTransform;
+use crate::protocol::ack::{Ack, Ackable};
+use crate::protocol::frame::{FragmentMeta, Frame, FramePacket, FrameRef};
+use crate::protocol::index::{DatagramSeq, MessageIndex, OrderIndex, SequenceIndex, SplitId};
+use crate::protocol::packet::online::{ConnectedPing, OnlinePacket};
 use crate::protocol::packet::RakPacket;
+use crate::protocol::ranges::SequenceRanges;
 use crate::protocol::reliability::Reliability;
-use crate::protocol::RAKNET_HEADER_FRAME_OVERHEAD;
+use crate::protocol::{
+    MAX_FRAGS, RAKNET_HEADER_FRAME_OVERHEAD, RAKNET_HEADER_OVERHEAD, UDP_HEADER_OVERHEAD,
+};
 use crate::rakrs_debug;
-use crate::util::{to_address_token, SafeGenerator};
+use crate::util::socket::SendFailure;
+use crate::util::{self, to_address_token, SafeGenerator};
 
-use super::{FragmentQueue, FragmentQueueError, NetQueue, RecoveryQueue};
+use super::{
+    ChannelScheduler, FragmentQueue, FragmentQueueError, NetQueue, RecoveryQueue, RecvQueue,
+    DEFAULT_CHANNEL_QUANTUM,
+};
+
+/// How long sends are held back once the socket ran out of buffer space,
+/// the datagrams that didn't fit go out again on the next tick.
+pub const ENOBUFS_BACKOFF: Duration = Duration::from_millis(10);
+
+/// The most datagrams held back while the socket is out of buffer space, any more are dropped
+/// and left to the retransmission timeout.
+pub const MAX_STALLED: usize = 512;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum SendQueueError {
@@ -29,6 +64,14 @@ pub enum SendQueueError {
     FragmentError(FragmentQueueError),
     /// Send queue error
     SendError,
+    /// The connection is draining before it closes, and no longer accepts new payloads.
+    Draining,
+    /// The connection holds as much as [`ConnOptions::max_connection_memory`] allows, even
+    /// after dropping the unreliable messages it had queued. The payload can be sent again
+    /// once the peer acknowledged some of what is queued.
+    ///
+    /// [`ConnOptions::max_connection_memory`]: crate::connection::options::ConnOptions::max_connection_memory
+    OutOfBudget,
 }
 
 /// This queue is used to prioritize packets being sent out
@@ -49,11 +92,11 @@ pub struct SendQueue {
     /// The current sequence number. This is incremented every time
     /// a packet is sent reliably. We can resend these if they are
     /// NAcked.
-    send_seq: SafeGenerator<u32>,
+    send_seq: SafeGenerator<DatagramSeq>,
 
     /// The current reliable index number.
     /// a packet is sent reliably an sequenced.
-    reliable_seq: SafeGenerator<u32>,
+    reliable_seq: SafeGenerator<MessageIndex>,
 
     /// The current recovery queue.
     ack: RecoveryQueue<FramePacket>,
@@ -62,16 +105,111 @@ pub struct SendQueue {
     fragment_queue: FragmentQueue,
 
     /// The ordered channels.
-    /// (send_seq, reliable_seq)
-    order_channels: HashMap<u8, (u32, u32)>,
+    /// (sequence_index, order_index)
+    order_channels: HashMap<u8, (SequenceIndex, OrderIndex)>,
+
+    /// Frames waiting for the next tick, scheduled fairly over their order channels.
+    ready: ChannelScheduler<Frame>,
+    /// Frames to go out right away. A message is queued here in full before its first frame
+    /// is sent, what a cancelled send didn't get to goes out on the next tick.
+    outgoing: VecDeque<Frame>,
+
+    /// The split messages being forwarded with [`SendQueue::insert_frame()`], by the split id
+    /// the peer they came from gave them.
+    forwarded_splits: HashMap<SplitId, ForwardedSplit>,
+
+    /// The maximum amount of ready frames sent per tick, `None` sends all of them.
+    frame_budget: Option<usize>,
+
+    /// Upward MTU re-probing, used to recover from a degraded MTU after a path change.
+    mtu_prober: MtuProber,
+
+    /// The round trip estimator, fed by acknowledgements of reliable datagrams.
+    rtt: RttEstimator,
+
+    /// A copy of the estimator's state that connection handles can read without
+    /// locking the whole queue.
+    rtt_stats: Arc<Mutex<RttStats>>,
+    /// Where the counters of the queue are published for the same reason.
+    shared_stats: Arc<SharedStats>,
+
+    /// When each reliable datagram still in the recovery queue was last sent,
+    /// whether it has been retransmitted since, and the factor of the retransmission timeout
+    /// it was given, see [`RTO_JITTER`].
+    in_flight: HashMap<DatagramSeq, (Instant, bool, f64)>,
+    /// Where the jitter of the retransmission timeouts is drawn from.
+    rng: RngProvider,
+    /// Since when reliable datagrams have been waiting for an acknowledgement without any
+    /// arriving, `None` while everything is acknowledged.
+    unacked_since: Option<Instant>,
+
+    /// Whether frame-set datagrams are sealed with a checksum trailer, this is only
+    /// enabled once the peer agreed to it.
+    datagram_checksum: bool,
+    /// Applied to every datagram right before it reaches the socket, once installed.
+    transform: SharedTransform,
+    /// Merges the small messages of the coalescing channels until the next tick.
+    coalescer: Coalescer,
+
+    /// The budget shared with the other connections of the listener, if it has one.
+    send_budget: Option<BudgetShare>,
+    /// What the queued frames, the recovery queue and the held back datagrams are charged to.
+    memory: MemAccount,
+
+    /// The last time a frame set was sent, the peer acknowledges every one of them.
+    last_sent: Instant,
+    /// A keepalive waiting to ride along in the next datagram.
+    keepalive: Option<Frame>,
+    /// Whether a ping for the clock estimate rides along in the next datagram.
+    clock_ping: bool,
 
-    ready: Vec<Frame>,
+    /// Datagrams the socket had no buffer space for, sent again in order once the backoff
+    /// is over.
+    stalled: VecDeque<Vec<u8>>,
+    /// Until when sends are held back, set when the socket ran out of buffer space.
+    backoff_until: Option<Instant>,
+    /// How many times sends were held back because the socket ran out of buffer space.
+    enobufs_backoffs: u64,
+    /// How many unreliable messages were dropped under pressure.
+    unreliable_dropped: u64,
+    /// How many datagrams were retransmitted after their timeout, after a NACK, and how many
+    /// retransmissions were skipped because the datagram was just retransmitted.
+    retransmits: (u64, u64, u64),
+    /// How many reliable datagrams were sent and retransmitted, by their size.
+    loss_by_size: LossBySize,
+    /// Lowers the MTU when the loss counts say the path drops the largest datagrams.
+    blackhole: BlackholeDetector,
+    /// How many datagrams were handed to the socket, and their bytes.
+    sent: (u64, u64),
+    /// Where unreliable messages are encoded, kept between sends so they don't allocate.
+    datagram_buf: Vec<u8>,
+    /// OS errors the next sends fail with before they reach the socket,
+    /// see [`SendQueue::fail_sends()`].
+    send_faults: VecDeque<i32>,
+    /// Permits every datagram has to wait for before it reaches the socket,
+    /// see [`SendQueue::gate_sends()`].
+    send_gate: Option<Arc<futures::lock::Mutex<UnboundedReceiver<()>>>>,
 
-    socket: Arc<UdpSocket>,
+    /// The socket datagrams are sent on, `None` once released.
+    socket: Option<Arc<UdpSocket>>,
+    /// Where datagrams go instead of the socket in a simulation.
+    #[cfg(feature = "simulation")]
+    outbox: Option<Arc<Mutex<Vec<Vec<u8>>>>>,
 
     address: SocketAddr,
 }
 
+/// The indexes a split message being forwarded was given on this queue, every fragment
+/// of the message is sent with them.
+#[derive(Debug, Clone, Copy)]
+struct ForwardedSplit {
+    id: SplitId,
+    order_index: Option<OrderIndex>,
+    sequence_index: Option<SequenceIndex>,
+    /// The fragments that still have to be forwarded.
+    remaining: u32,
+}
+
 impl SendQueue {
     pub fn new(
         mtu_size: u16,
@@ -79,6 +217,29 @@ impl SendQueue {
         _max_tries: u16,
         socket: Arc<UdpSocket>,
         address: SocketAddr,
+    ) -> Self {
+        Self::build(mtu_size, _timeout, _max_tries, Some(socket), address)
+    }
+
+    /// A queue that hands every datagram to `outbox` instead of a socket,
+    /// used by the [`simulation`](crate::simulation).
+    #[cfg(feature = "simulation")]
+    pub(crate) fn with_outbox(
+        mtu_size: u16,
+        address: SocketAddr,
+        outbox: Arc<Mutex<Vec<Vec<u8>>>>,
+    ) -> Self {
+        let mut queue = Self::build(mtu_size, 12000, 5, None, address);
+        queue.outbox = Some(outbox);
+        queue
+    }
+
+    fn build(
+        mtu_size: u16,
+        _timeout: u16,
+        _max_tries: u16,
+        socket: Option<Arc<UdpSocket>>,
+        address: SocketAddr,
     ) -> Self {
         Self {
             mtu_size,
@@ -89,15 +250,435 @@ impl SendQueue {
             ack: RecoveryQueue::new(),
             fragment_queue: FragmentQueue::new(),
             order_channels: HashMap::new(),
-            ready: Vec::new(),
+            ready: ChannelScheduler::new(DEFAULT_CHANNEL_QUANTUM),
+            outgoing: VecDeque::new(),
+            forwarded_splits: HashMap::new(),
+            frame_budget: None,
+            // the MTU the queue starts with is the one negotiated, it is never probed above.
+            mtu_prober: MtuProber::new(
+                mtu_size,
+                mtu_size,
+                DEFAULT_MTU_REPROBE_INTERVAL,
+                util::now(),
+            ),
+            rtt: RttEstimator::new(),
+            rtt_stats: Arc::new(Mutex::new(RttStats::default())),
+            shared_stats: Arc::new(SharedStats::new(mtu_size)),
+            in_flight: HashMap::new(),
+            rng: RngProvider::new(),
+            unacked_since: None,
+            datagram_checksum: false,
+            transform: SharedTransform::default(),
+            coalescer: Coalescer::default(),
+            send_budget: None,
+            memory: MemAccount::default(),
+            last_sent: util::now(),
+            keepalive: None,
+            clock_ping: false,
+            stalled: VecDeque::new(),
+            backoff_until: None,
+            enobufs_backoffs: 0,
+            unreliable_dropped: 0,
+            retransmits: (0, 0, 0),
+            loss_by_size: LossBySize::default(),
+            blackhole: BlackholeDetector::new(),
+            sent: (0, 0),
+            datagram_buf: Vec::new(),
+            send_faults: VecDeque::new(),
+            send_gate: None,
             socket,
+            #[cfg(feature = "simulation")]
+            outbox: None,
             address,
         }
     }
 
+    /// The MTU currently used when fragmenting packets.
+    pub fn mtu_size(&self) -> u16 {
+        self.mtu_size
+    }
+
+    /// The amount of frames that are either waiting to be sent, or waiting to be acknowledged.
+    pub fn pending(&self) -> usize {
+        self.outgoing.len() + self.ready.len() + self.ack.len() + self.coalescer.pending()
+    }
+
+    /// How long reliable datagrams have been waiting for an acknowledgement, without the peer
+    /// acknowledging anything in the meantime. `None` when nothing is waiting.
+    pub fn unacked_for(&self, now: Instant) -> Option<Duration> {
+        self.unacked_since
+            .map(|since| now.saturating_duration_since(since))
+    }
+
+    /// How long until the oldest datagram in flight is due for retransmission, zero if it is
+    /// overdue. `None` when nothing is waiting for an acknowledgement.
+    pub fn next_retransmit(&self, now: Instant) -> Option<Duration> {
+        let rto = self.rtt.rto();
+        self.in_flight
+            .values()
+            .map(|(sent_at, _, factor)| *sent_at + rto.mul_f64(*factor))
+            .min()
+            .map(|due| due.saturating_duration_since(now))
+    }
+
+    /// Whether frames are waiting to be sent on the next [`SendQueue::update()`], be it because
+    /// they were over the frame budget, or the socket was out of buffer space.
+    pub fn has_backlog(&self) -> bool {
+        !self.ready.is_empty()
+            || !self.outgoing.is_empty()
+            || !self.stalled.is_empty()
+            || self.keepalive.is_some()
+            || self.coalescer.pending() > 0
+    }
+
+    /// The counters of this queue, used to describe the session to another listener.
+    pub fn counters(&self) -> SendCounters {
+        let mut channels = self
+            .order_channels
+            .iter()
+            .map(|(channel, (seq_index, ord_index))| ChannelCounters {
+                channel: *channel,
+                sequence_index: seq_index.get(),
+                order_index: ord_index.get(),
+            })
+            .collect::<Vec<ChannelCounters>>();
+        channels.sort_by_key(|channel| channel.channel);
+
+        SendCounters {
+            datagram_seq: self.send_seq.get().get(),
+            reliable_index: self.reliable_seq.get().get(),
+            channels,
+        }
+    }
+
+    /// Resumes the counters of a session exported by another listener.
+    ///
+    /// The datagram sequence is bumped by [`SEQUENCE_SAFETY_MARGIN`], acknowledgements of
+    /// datagrams the previous owner had in flight then never match one of ours. The reliable
+    /// and order indexes are kept as is, the peer waits for every one of them.
+    pub fn restore_counters(&mut self, counters: &SendCounters) {
+        self.send_seq.sequence =
+            DatagramSeq::new(counters.datagram_seq).wrapping_add(SEQUENCE_SAFETY_MARGIN);
+        self.reliable_seq.sequence = MessageIndex::new(counters.reliable_index);
+
+        self.order_channels.clear();
+        for channel in counters.channels.iter() {
+            self.order_channels.insert(
+                channel.channel,
+                (
+                    SequenceIndex::new(channel.sequence_index),
+                    OrderIndex::new(channel.order_index),
+                ),
+            );
+        }
+    }
+
+    /// A shared copy of the round trip state, this is updated whenever an acknowledgement
+    /// is processed or the retransmission timer fires.
+    pub fn rtt_stats(&self) -> Arc<Mutex<RttStats>> {
+        self.rtt_stats.clone()
+    }
+
+    /// Publishes the round trip state into `stats` from now on, rather than into a copy
+    /// of its own. This lets an owner keep handing out the same state across queues.
+    pub fn set_rtt_stats(&mut self, stats: Arc<Mutex<RttStats>>) {
+        self.rtt_stats = stats;
+        self.publish_rtt();
+    }
+
+    /// The counters of the queue, and its MTU, as they are published whenever they change.
+    pub fn shared_stats(&self) -> Arc<SharedStats> {
+        self.shared_stats.clone()
+    }
+
+    /// Publishes the counters into `stats` from now on, like [`SendQueue::set_rtt_stats()`].
+    pub fn set_shared_stats(&mut self, stats: Arc<SharedStats>) {
+        self.shared_stats = stats;
+        self.publish_mtu();
+        self.shared_stats
+            .enobufs_backoffs
+            .store(self.enobufs_backoffs, Ordering::Relaxed);
+        self.publish_sent();
+        self.publish_retransmits();
+    }
+
+    /// Publishes the counters as they are now, for [`SharedStats::snapshot()`]. Nothing
+    /// updates them while the queue is borrowed, so the snapshot is consistent.
+    pub fn publish_stats(&self) {
+        self.shared_stats.publish();
+    }
+
+    fn publish_sent(&self) {
+        let (datagrams, bytes) = self.sent;
+        self.shared_stats
+            .datagrams_sent
+            .store(datagrams, Ordering::Relaxed);
+        self.shared_stats.bytes_sent.store(bytes, Ordering::Relaxed);
+    }
+
+    fn publish_mtu(&self) {
+        self.shared_stats
+            .mtu_size
+            .store(self.mtu_size, Ordering::Relaxed);
+    }
+
+    /// Whether frame-set datagrams are sealed with a checksum trailer.
+    pub fn datagram_checksum(&self) -> bool {
+        self.datagram_checksum
+    }
+
+    /// Seals every frame-set datagram sent from now on with a checksum trailer,
+    /// this should only be enabled once the peer agreed to it.
+    pub fn set_datagram_checksum(&mut self, enabled: bool) {
+        self.datagram_checksum = enabled;
+    }
+
+    /// Coalesces the small reliable-ordered messages queued on `channels` until the next
+    /// [`SendQueue::update()`], once the peer agreed to it with [`SendQueue::set_coalescing()`].
+    pub fn set_coalesce_channels(&mut self, channels: Vec<u8>) {
+        let enabled = self.coalescer.is_enabled();
+        self.coalescer = Coalescer::new(channels);
+        self.coalescer.set_enabled(enabled);
+    }
+
+    /// The channels small messages are coalesced on.
+    pub fn coalesce_channels(&self) -> &[u8] {
+        self.coalescer.channels()
+    }
+
+    /// Whether the peer splits coalesced messages.
+    pub fn coalescing(&self) -> bool {
+        self.coalescer.is_enabled()
+    }
+
+    /// Sets whether the peer splits coalesced messages, this should only be enabled once it
+    /// agreed to it.
+    pub fn set_coalescing(&mut self, enabled: bool) {
+        self.coalescer.set_enabled(enabled);
+    }
+
+    /// The transform applied to the datagrams of this queue, shared with whoever opens the
+    /// datagrams of the peer.
+    pub(crate) fn transform(&self) -> SharedTransform {
+        self.transform.clone()
+    }
+
+    /// Applies `transform` from now on, like [`SendQueue::set_shared_stats()`].
+    pub(crate) fn set_transform(&mut self, transform: SharedTransform) {
+        self.transform = transform;
+    }
+
+    /// The bytes of every datagram taken by the checksum trailer and the transform.
+    fn reserved(&self) -> u16 {
+        let trailer = if self.datagram_checksum {
+            CHECKSUM_LEN
+        } else {
+            0
+        };
+        (trailer + self.transform.overhead()).min(u16::MAX as usize) as u16
+    }
+
+    /// The largest payload that fits a single frame at the current MTU.
+    fn max_payload(&self) -> usize {
+        self.mtu_size
+            .saturating_sub(RAKNET_HEADER_FRAME_OVERHEAD)
+            .saturating_sub(self.reserved()) as usize
+    }
+
+    /// Sets how often the queue attempts to raise the MTU to the next step of the ladder.
+    /// A zero duration disables re-probing.
+    pub fn set_mtu_reprobe_interval(&mut self, interval: Duration) {
+        self.mtu_prober.set_interval(interval, util::now());
+    }
+
+    /// Limits the amount of queued frames sent per tick, `None` sends every queued frame.
+    /// Frames over the budget are sent on a later tick, with every order channel getting a turn.
+    pub fn set_frame_budget(&mut self, budget: Option<usize>) {
+        self.frame_budget = budget;
+    }
+
+    /// The amount of queued frames sent per tick, see [`SendQueue::set_frame_budget()`].
+    pub fn frame_budget(&self) -> Option<usize> {
+        self.frame_budget
+    }
+
+    /// Makes room for the frames and datagrams of `capacity` up front, so a burst doesn't
+    /// grow the queue while it is sent.
+    pub fn prewarm(&mut self, capacity: &Capacity) {
+        self.outgoing
+            .reserve(capacity.frames.saturating_sub(self.outgoing.len()));
+        self.in_flight
+            .reserve(capacity.datagrams.saturating_sub(self.in_flight.len()));
+    }
+
+    /// The frames and datagrams the queue has room for without growing.
+    pub fn capacity(&self) -> Capacity {
+        Capacity {
+            frames: self.outgoing.capacity(),
+            datagrams: self.in_flight.capacity(),
+            ..Default::default()
+        }
+    }
+
+    /// Draws the jitter of the retransmission timeouts from `rng`, every reliable datagram is
+    /// retransmitted up to [`RTO_JITTER`] sooner or later than the timeout.
+    pub fn set_rng(&mut self, rng: RngProvider) {
+        self.rng = rng;
+    }
+
+    /// Points the queue at another address of the peer, everything is sent there from now on.
+    pub(crate) fn set_address(&mut self, address: SocketAddr) {
+        self.address = address;
+    }
+
+    /// The address of the peer the queue sends to.
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Lets go of the socket, so it can close while the queue is still around.
+    /// Nothing is sent after this.
+    pub fn release_socket(&mut self) {
+        self.socket = None;
+    }
+
+    /// Draws every datagram sent from now on from the given budget.
+    /// Frames that don't fit the budget stay queued until a later tick.
+    pub fn set_send_budget(&mut self, budget: Option<BudgetShare>) {
+        self.send_budget = budget;
+    }
+
+    /// Charges what the queue holds to `budget` from now on, this is usually shared with the
+    /// receive queue, see [`RecvQueue::set_memory_budget()`].
+    pub fn set_memory_budget(&mut self, budget: MemBudget) {
+        self.memory.move_to(budget);
+    }
+
+    /// The ledger the queue is charged to.
+    pub fn memory_budget(&self) -> &MemBudget {
+        self.memory.budget()
+    }
+
+    /// The bytes the queue holds, as charged to its ledger.
+    pub fn memory_charged(&self) -> usize {
+        self.memory.charged()
+    }
+
+    /// Makes room for an application payload of `len` bytes in the ledger, see
+    /// [`ConnOptions::max_connection_memory`].
+    ///
+    /// When the payload doesn't fit, the unreliable frames that are still queued are dropped
+    /// first. If it still doesn't fit after that, the payload is refused with
+    /// [`SendQueueError::OutOfBudget`]. What the connection sends on its own, like pongs and
+    /// acknowledgements, is never refused.
+    ///
+    /// [`ConnOptions::max_connection_memory`]: crate::connection::options::ConnOptions::max_connection_memory
+    pub fn reserve(&mut self, len: usize) -> Result<(), SendQueueError> {
+        let cost = memory::cost(len);
+        if self.memory.budget().fits(cost) {
+            return Ok(());
+        }
+
+        self.drop_queued_unreliable();
+        if self.memory.budget().fits(cost) {
+            Ok(())
+        } else {
+            Err(SendQueueError::OutOfBudget)
+        }
+    }
+
+    /// Drops the unreliable frames waiting to be sent, counting them like the ones dropped
+    /// under pressure.
+    fn drop_queued_unreliable(&mut self) {
+        let mut dropped = 0;
+        let mut freed = 0;
+        let mut unreliable = |frame: &Frame| {
+            if frame.reliability.is_reliable() {
+                return true;
+            }
+            dropped += 1;
+            freed += memory::cost(frame.body.len());
+            false
+        };
+        self.ready.retain(&mut unreliable);
+        self.outgoing.retain(&mut unreliable);
+
+        if dropped > 0 {
+            rakrs_debug!(
+                true,
+                "[{}] Over the memory budget, dropped {} queued unreliable frames",
+                to_address_token(self.address),
+                dropped
+            );
+            self.memory.credit(freed);
+            self.unreliable_dropped += dropped;
+            self.shared_stats
+                .unreliable_dropped
+                .store(self.unreliable_dropped, Ordering::Relaxed);
+        }
+    }
+
+    /// Whether an immediate frame of the given size may be sent right away, borrowing from
+    /// the budget if needed. Frames that may not are queued instead.
+    fn admit_immediate(&self, size: usize) -> bool {
+        match &self.send_budget {
+            Some(budget) => {
+                budget.borrow(size + RAKNET_HEADER_FRAME_OVERHEAD as usize, util::now())
+            }
+            None => true,
+        }
+    }
+
+    /// Whether nothing was sent for `interval`, so the peer needs a keepalive to hear from us.
+    pub fn keepalive_due(&self, interval: Duration, now: Instant) -> bool {
+        self.keepalive.is_none() && now.saturating_duration_since(self.last_sent) >= interval
+    }
+
+    /// How long until a keepalive is due, zero if it is.
+    pub fn keepalive_in(&self, interval: Duration, now: Instant) -> Duration {
+        interval.saturating_sub(now.saturating_duration_since(self.last_sent))
+    }
+
+    /// Makes sure the peer hears from us at least once per `interval`.
+    ///
+    /// The peer acknowledges every frame set, which is all the liveness both sides need,
+    /// so the packet is only sent if nothing else was for that long. When frames are waiting
+    /// for the next [`SendQueue::update()`], it rides along in the first datagram instead of
+    /// taking one of its own. Returns whether a keepalive was scheduled.
+    pub async fn keepalive(&mut self, packet: &[u8], interval: Duration) -> bool {
+        if !self.keepalive_due(interval, util::now()) {
+            return false;
+        }
+
+        if self.ready.is_empty() {
+            // nothing to ride along with.
+            self.insert(packet, Reliability::Reliable, true, None)
+                .await
+                .is_ok()
+        } else {
+            self.keepalive = Some(Frame::new(Reliability::Unreliable, Some(packet)));
+            true
+        }
+    }
+
+    /// Lets a [`ConnectedPing`] ride along in the next datagram with room for it, it never
+    /// takes a datagram of its own. The ping is stamped when it goes out, so the pong
+    /// measures the round trip and nothing else.
+    ///
+    /// [`ConnectedPing`]: crate::protocol::packet::online::ConnectedPing
+    pub fn ride_along_ping(&mut self) {
+        self.clock_ping = true;
+    }
+
     /// Send a packet based on its reliability.
     /// Note, reliability will be set to `Reliability::ReliableOrd` if
     /// the buffer is larger than max MTU.
+    ///
+    /// This is cancellation safe: the indexes of the packet are issued and all of its frames
+    /// are queued before anything is awaited. Dropping the future before it was first polled
+    /// has no effect at all, dropping it later leaves the packet fully queued, the frames it
+    /// didn't get to send go out on the next [`SendQueue::update()`]. The exception is an
+    /// [unreliable](Reliability::Unreliable) packet, which may be lost as it could be on the way.
     pub async fn insert(
         &mut self,
         packet: &[u8],
@@ -105,145 +686,624 @@ impl SendQueue {
         immediate: bool,
         channel: Option<u8>,
     ) -> Result<(), SendQueueError> {
-        let reliable = if packet.len() > (self.mtu_size + RAKNET_HEADER_FRAME_OVERHEAD) as usize {
+        let channel_id = channel.unwrap_or(0);
+        if reliability == Reliability::ReliableOrd
+            && !immediate
+            && self.coalescer.coalesces(channel_id)
+        {
+            if let Ok(full) = self.coalescer.push(channel_id, packet, self.max_payload()) {
+                if let Some(full) = full {
+                    self.queue_bundle(channel_id, full);
+                }
+                return Ok(());
+            }
+        }
+
+        // a message that looks like a bundle is sent as one, so the peer doesn't split it.
+        let wrapped;
+        let packet = if self.coalescer.is_enabled() && packet.first() == Some(&COALESCED_ID) {
+            wrapped = Coalescer::wrap(packet).ok_or(SendQueueError::PacketTooLarge)?;
+            &wrapped[..]
+        } else {
+            packet
+        };
+
+        let reliable = if packet.len() > self.max_payload() {
             Reliability::ReliableOrd
         } else {
             reliability
         };
 
-        match reliability {
-            Reliability::Unreliable => {
-                // we can just send this packet out immediately.
-                let frame = Frame::new(Reliability::Unreliable, Some(packet));
-                self.send_frame(frame).await;
+        // anything else on the channel goes after what was coalesced so far.
+        if reliable.is_sequenced_or_ordered() {
+            self.flush_bundle(channel_id);
+        }
+
+        match reliable {
+            Reliability::Unreliable if self.send_unreliable(packet, immediate).await => {
                 return Ok(());
             }
-            Reliability::Reliable => {
-                // we need to send this packet out reliably.
-                let frame = Frame::new(Reliability::Reliable, Some(packet));
-                self.send_frame(frame).await;
+            Reliability::Unreliable | Reliability::Reliable => {
+                // these are sent out right away, unless they don't fit the budget.
+                let admitted =
+                    self.send_budget.is_none() || (immediate && self.admit_immediate(packet.len()));
+                if !admitted && immediate && reliable == Reliability::Unreliable {
+                    // like on the fast path, unreliable packets are the first to go.
+                    self.drop_unreliable();
+                    return Ok(());
+                }
+                let frame = Frame::new(reliable, Some(packet));
+                self.memory.debit(memory::cost(packet.len()));
+                if admitted {
+                    self.outgoing.push_back(frame);
+                } else {
+                    self.ready.push(channel.unwrap_or(0), frame);
+                }
+                self.send_outgoing().await;
                 return Ok(());
             }
-            _ => {}
+            Reliability::UnreliableSeq
+            | Reliability::ReliableOrd
+            | Reliability::ReliableSeq
+            | Reliability::UnreliableAck
+            | Reliability::ReliableAck
+            | Reliability::ReliableOrdAck => {}
         };
 
         // do another integrity check
         // this is to check to see if we really need to split this packet.
-        if packet.len() > (self.mtu_size + RAKNET_HEADER_FRAME_OVERHEAD) as usize {
+        if packet.len() > self.max_payload() {
             // we need to split this packet!
             // pass the buffer to the fragment queue.
-            let mut pk = FramePacket::new();
-            pk.sequence = self.send_seq.next();
-            pk.reliability = reliability;
-
-            let fragmented = self.fragment_queue.split_insert(&packet, self.mtu_size);
-
-            if fragmented.is_ok() {
-                let frag_id = fragmented.unwrap();
-                let (_, frames) = self.fragment_queue.get_mut(&frag_id).unwrap();
-                let (ord_seq, ord_index) = self
-                    .order_channels
-                    .entry(channel.unwrap_or(0))
-                    .or_insert((0, 0));
-
-                for frame in frames.iter_mut() {
-                    frame.reliability = reliability;
-                    frame.sequence_index = Some(*ord_seq);
-                    frame.order_channel = Some(channel.unwrap_or(0));
-                    frame.order_index = Some(*ord_index);
-
-                    if frame.reliability.is_reliable() {
-                        frame.reliable_index = Some(self.reliable_seq.next());
-                    }
-                }
+            let mtu = self.mtu_size.saturating_sub(self.reserved());
+            let frag_id = self
+                .fragment_queue
+                .split_insert(packet, mtu)
+                .map_err(SendQueueError::FragmentError)?;
 
-                *ord_index = ord_index.wrapping_add(1);
-                *ord_seq = ord_seq.wrapping_add(1);
+            // the fragments are tracked by the recovery queue once they are sent.
+            let (_, mut frames) = self.fragment_queue.get(&frag_id).unwrap().clone();
+            self.fragment_queue.remove(&frag_id);
 
-                // Add this frame packet to the recovery queue.
-                if let Ok(p) = pk.write_to_bytes() {
-                    self.send_stream(p.as_slice()).await;
-                    self.ack.insert_id(pk.sequence, pk);
-                    return Ok(());
-                } else {
-                    return Err(SendQueueError::SendError);
-                }
-            } else {
-                // we couldn't send this frame!
-                return Err(SendQueueError::FragmentError(fragmented.unwrap_err()));
+            if frames.len() > MAX_FRAGS as usize {
+                return Err(SendQueueError::PacketTooLarge);
+            }
+
+            let channel = channel.unwrap_or(0);
+            let (_, ord_index) = self.order_channels.entry(channel).or_default();
+            let order_index = *ord_index;
+            *ord_index = ord_index.wrapping_add(1);
+
+            // every fragment shares the order index of the message it belongs to.
+            for frame in frames.iter_mut() {
+                frame.reliability = reliable;
+                frame.order_channel = Some(channel);
+                frame.order_index = Some(order_index);
+                frame.reliable_index = Some(self.reliable_seq.next_index());
             }
+
+            for frame in frames {
+                self.push_frame(frame, channel, immediate);
+            }
+            self.send_outgoing().await;
+
+            return Ok(());
         } else {
             // we're not gonna send this frame out yet!
             // we need to wait for the next tick.
             let mut frame = Frame::new(reliable, Some(packet));
+            self.stamp(&mut frame, channel.unwrap_or(0));
+            self.push_frame(frame, channel.unwrap_or(0), immediate);
+            self.send_outgoing().await;
 
-            if frame.reliability.is_reliable() {
-                frame.reliable_index = Some(self.reliable_seq.next());
-            }
-
-            if frame.reliability.is_ordered() {
-                let (_, ord_index) = self
-                    .order_channels
-                    .entry(channel.unwrap_or(0))
-                    .or_insert((0, 0));
-                frame.order_index = Some(*ord_index);
-                frame.sequence_index = Some(self.send_seq.get());
-                *ord_index = ord_index.wrapping_add(1);
-            } else if frame.reliability.is_sequenced() {
-                let (seq_index, ord_index) = self
-                    .order_channels
-                    .entry(channel.unwrap_or(0))
-                    .or_insert((0, 0));
-                *seq_index = seq_index.wrapping_add(1);
-                frame.order_index = Some(*ord_index);
-                frame.sequence_index = Some(*seq_index);
-            }
-
-            if immediate {
-                self.send_frame(frame).await;
-            } else {
-                self.ready.push(frame);
+            return Ok(());
+        }
+    }
+
+    /// Sends an unreliable packet that fits a single frame without any of the bookkeeping of
+    /// the other reliabilities, the datagram is encoded into a buffer kept by the queue so
+    /// nothing is allocated. Returns `false` if the packet has to take the regular path.
+    ///
+    /// Unreliable packets are the first to go under pressure: while the socket is out of
+    /// buffer space, or when an immediate packet doesn't fit the send budget, they are
+    /// dropped and counted rather than queued.
+    async fn send_unreliable(&mut self, packet: &[u8], immediate: bool) -> bool {
+        let now = util::now();
+        if !self.stalled.is_empty() || self.backoff_until.is_some_and(|until| now < until) {
+            self.drop_unreliable();
+            return true;
+        }
+
+        // ride-alongs and frames left behind by a cancelled send need the regular path,
+        // which takes the budget itself.
+        if (!immediate && self.send_budget.is_some())
+            || self.keepalive.is_some()
+            || self.clock_ping
+            || !self.outgoing.is_empty()
+        {
+            return false;
+        }
+
+        if immediate && !self.admit_immediate(packet.len()) {
+            self.drop_unreliable();
+            return true;
+        }
+
+        let mut buf = std::mem::take(&mut self.datagram_buf);
+        buf.clear();
+        buf.push(0x84);
+        buf.extend_from_slice(&self.send_seq.next_index().get().to_le_bytes()[..3]);
+        buf.push(Reliability::Unreliable.to_flags());
+        buf.extend_from_slice(&(packet.len() as u16 * 8).to_be_bytes());
+        buf.extend_from_slice(packet);
+        if self.datagram_checksum {
+            checksum::seal(&mut buf);
+        }
+
+        match self.send_to_socket(&buf).await {
+            Ok(()) => self.last_sent = util::now(),
+            Err(e) => match SendFailure::classify(&e) {
+                SendFailure::NoBuffers => {
+                    self.back_off(util::now());
+                    self.drop_unreliable();
+                }
+                SendFailure::TooLarge => self.refused(buf.len()),
+                SendFailure::Other => {
+                    rakrs_debug!(
+                        true,
+                        "[{}] Failed to send packet! {:?}",
+                        to_address_token(self.address),
+                        e
+                    );
+                }
+            },
+        }
+        self.datagram_buf = buf;
+        true
+    }
+
+    fn publish_retransmits(&self) {
+        let (timeout, nack, suppressed) = self.retransmits;
+        let stats = &self.shared_stats;
+        stats.timeout_retransmits.store(timeout, Ordering::Relaxed);
+        stats.nack_retransmits.store(nack, Ordering::Relaxed);
+        stats
+            .suppressed_retransmits
+            .store(suppressed, Ordering::Relaxed);
+        self.publish_loss();
+    }
+
+    fn publish_loss(&self) {
+        let loss = &self.loss_by_size;
+        let stats = &self.shared_stats;
+        for (counter, sent) in stats.sent_by_size.iter().zip(loss.sent) {
+            counter.store(sent, Ordering::Relaxed);
+        }
+        for (counter, retransmitted) in stats.retransmitted_by_size.iter().zip(loss.retransmitted) {
+            counter.store(retransmitted, Ordering::Relaxed);
+        }
+    }
+
+    /// Whether the datagram was retransmitted too recently to be retransmitted again,
+    /// see [`RttEstimator::resend_spacing()`]. The first send doesn't count.
+    fn resent_recently(&self, sequence: DatagramSeq, now: Instant) -> bool {
+        matches!(
+            self.in_flight.get(&sequence),
+            Some((sent_at, true, _)) if now.duration_since(*sent_at) < self.rtt.resend_spacing()
+        )
+    }
+
+    fn drop_unreliable(&mut self) {
+        self.unreliable_dropped += 1;
+        self.shared_stats
+            .unreliable_dropped
+            .store(self.unreliable_dropped, Ordering::Relaxed);
+    }
+
+    /// Gives a new frame its reliable index, and its order and sequence index on `channel`
+    /// if its reliability calls for them.
+    fn stamp(&mut self, frame: &mut Frame, channel: u8) {
+        if frame.reliability.is_reliable() {
+            frame.reliable_index = Some(self.reliable_seq.next_index());
+        }
+
+        if frame.reliability.is_ordered() {
+            let (seq_index, ord_index) = self.order_channels.entry(channel).or_default();
+            frame.order_index = Some(*ord_index);
+            frame.sequence_index = Some(*seq_index);
+            frame.order_channel = Some(channel);
+            *ord_index = ord_index.wrapping_add(1);
+        } else if frame.reliability.is_sequenced() {
+            let (seq_index, ord_index) = self.order_channels.entry(channel).or_default();
+            *seq_index = seq_index.wrapping_add(1);
+            frame.order_index = Some(*ord_index);
+            frame.sequence_index = Some(*seq_index);
+            frame.order_channel = Some(channel);
+        }
+    }
+
+    /// Forwards a frame received from another peer, without reassembling or re-encoding it.
+    ///
+    /// The frame keeps its order channel and its place in a split message, but is given
+    /// indexes from this queue: order and sequence indexes are issued per channel in the order
+    /// frames are inserted, and every fragment of a split message shares the split id and
+    /// order index issued for its first fragment. `reliability` replaces the reliability
+    /// of the frame when given.
+    ///
+    /// An unfragmented payload too large for this queue is split like [`SendQueue::insert()`]
+    /// would, a fragment that doesn't fit is [`SendQueueError::PacketTooLarge`].
+    pub async fn insert_frame(
+        &mut self,
+        frame: FrameRef<'_>,
+        reliability: Option<Reliability>,
+        immediate: bool,
+    ) -> Result<(), SendQueueError> {
+        let reliability = reliability.unwrap_or(frame.reliability);
+        let channel = frame.order_channel.unwrap_or(0);
+        if reliability.is_sequenced_or_ordered() {
+            self.flush_bundle(channel);
+        }
+
+        let meta = match frame.fragment_meta {
+            Some(meta) => meta,
+            None if frame.body.len() > self.max_payload() => {
+                return self
+                    .insert(frame.body, reliability, immediate, Some(channel))
+                    .await;
+            }
+            None => {
+                let mut out = Frame::new(reliability, Some(frame.body));
+                self.stamp(&mut out, channel);
+                self.push_frame(out, channel, immediate);
+                self.send_outgoing().await;
+                return Ok(());
+            }
+        };
+
+        if frame.body.len() > self.max_payload() || meta.size > MAX_FRAGS {
+            return Err(SendQueueError::PacketTooLarge);
+        }
+
+        let mut out = Frame::new(reliability, Some(frame.body));
+        let split = match self.forwarded_splits.get_mut(&meta.id) {
+            Some(split) => {
+                if out.reliability.is_reliable() {
+                    out.reliable_index = Some(self.reliable_seq.next_index());
+                }
+                if out.reliability.is_sequenced_or_ordered() {
+                    out.order_channel = Some(channel);
+                    out.order_index = split.order_index;
+                    out.sequence_index = split.sequence_index;
+                }
+                split.remaining = split.remaining.saturating_sub(1);
+                *split
             }
+            None => {
+                // the first fragment seen takes the message's place on its channel.
+                self.stamp(&mut out, channel);
+                let split = ForwardedSplit {
+                    id: self.fragment_queue.next_id(),
+                    order_index: out.order_index,
+                    sequence_index: out.sequence_index,
+                    remaining: meta.size.saturating_sub(1),
+                };
+                self.forwarded_splits.insert(meta.id, split);
+                split
+            }
+        };
 
-            return Ok(());
+        if split.remaining == 0 {
+            self.forwarded_splits.remove(&meta.id);
+        }
+
+        out.fragment_meta = Some(FragmentMeta::new(meta.size, split.id, meta.index));
+        self.push_frame(out, channel, immediate);
+        self.send_outgoing().await;
+        Ok(())
+    }
+
+    /// Queues the messages coalesced on `channel` so far, ahead of anything queued on it after.
+    fn flush_bundle(&mut self, channel: u8) {
+        if let Some(bundle) = self.coalescer.take(channel) {
+            self.queue_bundle(channel, bundle);
+        }
+    }
+
+    /// Queues a bundle of coalesced messages for the next tick, it always fits a single frame.
+    fn queue_bundle(&mut self, channel: u8, bundle: Vec<u8>) {
+        let mut frame = Frame::new(Reliability::ReliableOrd, Some(&bundle));
+        self.stamp(&mut frame, channel);
+        self.memory.debit(memory::cost(frame.body.len()));
+        self.ready.push(channel, frame);
+    }
+
+    /// Queues a frame to be sent right away if asked to and the budget allows it,
+    /// or for the next tick.
+    fn push_frame(&mut self, frame: Frame, channel: u8, immediate: bool) {
+        self.memory.debit(memory::cost(frame.body.len()));
+        if immediate && self.admit_immediate(frame.body.len()) {
+            self.outgoing.push_back(frame);
+        } else {
+            self.ready.push(channel, frame);
+        }
+    }
+
+    /// Sends the frames queued to go out right away.
+    ///
+    /// A reliable frame is tracked for retransmission before its datagram is awaited, so one
+    /// that is cut off halfway is resent like a lost one.
+    async fn send_outgoing(&mut self) {
+        while let Some(frame) = self.outgoing.pop_front() {
+            self.send_frame(frame).await;
         }
     }
 
     /// A wrapper to send a single frame over the wire.
-    /// While also reliabily tracking it.
-    async fn send_frame(&mut self, mut frame: Frame) {
+    /// While also reliabily tracking it. Returns the size of the datagram.
+    async fn send_frame(&mut self, mut frame: Frame) -> usize {
+        // once sent, a frame is only held by the recovery queue, if it is reliable.
+        self.memory.credit(memory::cost(frame.body.len()));
         let mut pk = FramePacket::new();
-        pk.sequence = self.send_seq.next();
+        pk.sequence = self.send_seq.next_index();
         pk.reliability = frame.reliability;
 
-        if pk.reliability.is_reliable() {
-            frame.reliable_index = Some(self.reliable_seq.next());
+        // frames coming from `insert` already have their reliable index.
+        if pk.reliability.is_reliable() && frame.reliable_index.is_none() {
+            frame.reliable_index = Some(self.reliable_seq.next_index());
         }
 
-        pk.frames.push(frame);
+        if let Some(keepalive) = self.keepalive.take() {
+            let size =
+                frame.body.len() + keepalive.body.len() + RAKNET_HEADER_FRAME_OVERHEAD as usize;
+            if size <= self.max_payload() {
+                pk.frames.push(frame);
+                pk.frames.push(keepalive);
+            } else {
+                pk.frames.push(frame);
+                self.keepalive = Some(keepalive);
+            }
+        } else {
+            pk.frames.push(frame);
+        }
+
+        if self.clock_ping {
+            let ping = OnlinePacket::ConnectedPing(ConnectedPing {
+                time: rak_time_now(),
+            });
+            if let Ok(ping) = ping.write_to_bytes() {
+                if wire_size(&pk) + ping.as_slice().len() <= self.max_payload() {
+                    pk.frames
+                        .push(Frame::new(Reliability::Unreliable, Some(ping.as_slice())));
+                    self.clock_ping = false;
+                }
+            }
+        }
 
         if pk.reliability.is_reliable() {
-            // this seems redundant, but we need to insert the packet into the ACK queue
-            self.ack.insert_id(self.reliable_seq.get(), pk.clone());
+            // the ride-alongs are unreliable, a resend must not repeat a keepalive or a ping
+            // stamped with the time of the first send.
+            let mut recovered = pk.clone();
+            recovered.frames.truncate(1);
+            self.memory.debit(memory::cost(wire_size(&recovered)));
+            self.loss_by_size.record_sent(wire_size(&recovered));
+            // ACKs refer to the datagram sequence, so that's what we have to key by.
+            self.ack.insert_id(pk.sequence, recovered);
+            let factor = self.rng.factor(RTO_JITTER);
+            self.in_flight
+                .insert(pk.sequence, (util::now(), false, factor));
+            self.unacked_since.get_or_insert_with(util::now);
+            self.publish_loss();
+        }
+
+        self.send_datagram(&pk).await
+    }
+
+    /// Encodes a frame-set datagram and sends it, sealing it if the peer agreed to checksums.
+    /// Returns the size of the datagram.
+    async fn send_datagram(&mut self, pk: &FramePacket) -> usize {
+        match pk.write_to_bytes() {
+            Ok(buf) if self.datagram_checksum => {
+                let mut buf = buf.as_slice().to_vec();
+                checksum::seal(&mut buf);
+                self.send_stream(&buf).await;
+                self.last_sent = util::now();
+                buf.len()
+            }
+            Ok(buf) => {
+                self.send_stream(buf.as_slice()).await;
+                self.last_sent = util::now();
+                buf.as_slice().len()
+            }
+            Err(_) => 0,
         }
+    }
+
+    /// Acknowledges everything `recv_q` has pending in a single ACK datagram,
+    /// returns the amount of sequences acknowledged.
+    pub async fn send_acks(&mut self, recv_q: &mut RecvQueue) -> usize {
+        self.send_ack_ranges(recv_q.ack_flush()).await
+    }
 
-        if let Ok(buf) = pk.write_to_bytes() {
-            self.send_stream(buf.as_slice()).await;
+    /// Acknowledges `ranges`, taken from a receive queue with [`RecvQueue::ack_flush()`], in a
+    /// single ACK datagram. Unlike [`SendQueue::send_acks()`], the receive queue doesn't have
+    /// to stay locked while the datagram is sent.
+    pub async fn send_ack_ranges(&mut self, ranges: SequenceRanges) -> usize {
+        if ranges.is_empty() {
+            return 0;
         }
+        if let Ok(p) = Ack::from_ranges(&ranges, false).write_to_bytes() {
+            self.send_stream(p.as_slice()).await;
+        }
+        ranges.len()
     }
 
     pub(crate) async fn send_stream(&mut self, packet: &[u8]) {
-        if let Err(e) = self.socket.send_to(packet, &self.address).await {
-            // we couldn't sent the packet!
+        // while backing off, everything queues up behind what is held back to keep the order.
+        if !self.stalled.is_empty() {
+            self.stall(packet.to_vec());
+            return;
+        }
+
+        if let Err(e) = self.send_to_socket(packet).await {
+            match SendFailure::classify(&e) {
+                SendFailure::NoBuffers => {
+                    self.stall(packet.to_vec());
+                    self.back_off(util::now());
+                }
+                SendFailure::TooLarge => self.refused(packet.len()),
+                SendFailure::Other => {
+                    // we couldn't sent the packet!
+                    rakrs_debug!(
+                        true,
+                        "[{}] Failed to send packet! {:?}",
+                        to_address_token(self.address),
+                        e
+                    );
+                }
+            }
+        }
+    }
+
+    async fn send_to_socket(&mut self, packet: &[u8]) -> io::Result<()> {
+        if let Some(gate) = self.send_gate.clone() {
+            // once the permits are dropped, everything goes through.
+            if gate.lock().await.next().await.is_none() {
+                self.send_gate = None;
+            }
+        }
+        if let Some(code) = self.send_faults.pop_front() {
+            return Err(io::Error::from_raw_os_error(code));
+        }
+        // sealed last, everything before works on the datagram the way the peer opens it.
+        let sealed = self.transform.get().map(|transform| {
+            let mut sealed = Vec::with_capacity(packet.len() + transform.overhead());
+            transform.seal(seq_hint(packet), packet, &mut sealed);
+            sealed
+        });
+        let packet = sealed.as_deref().unwrap_or(packet);
+
+        #[cfg(feature = "simulation")]
+        let sent = match &self.outbox {
+            Some(outbox) => {
+                outbox.lock().unwrap().push(packet.to_vec());
+                Ok(())
+            }
+            None => self.send_to_peer(packet).await,
+        };
+        #[cfg(not(feature = "simulation"))]
+        let sent = self.send_to_peer(packet).await;
+
+        if sent.is_ok() {
+            self.sent.0 += 1;
+            self.sent.1 += packet.len() as u64;
+            self.publish_sent();
+        }
+        sent
+    }
+
+    async fn send_to_peer(&self, packet: &[u8]) -> io::Result<()> {
+        match &self.socket {
+            Some(socket) => socket.send_to(packet, &self.address).await.map(|_| ()),
+            None => Ok(()),
+        }
+    }
+
+    /// Holds a datagram back until the socket has buffer space again.
+    fn stall(&mut self, packet: Vec<u8>) {
+        if self.stalled.len() < MAX_STALLED {
+            self.memory.debit(memory::cost(packet.len()));
+            self.stalled.push_back(packet);
+        }
+    }
+
+    /// The socket ran out of buffer space, nothing is sent until [`ENOBUFS_BACKOFF`] passed.
+    /// This is no reason to give up on the connection, the datagrams held back don't count as
+    /// retransmissions either.
+    fn back_off(&mut self, now: Instant) {
+        rakrs_debug!(
+            true,
+            "[{}] Socket is out of buffer space, holding back {} datagrams",
+            to_address_token(self.address),
+            self.stalled.len()
+        );
+        self.backoff_until = Some(now + ENOBUFS_BACKOFF);
+        self.enobufs_backoffs += 1;
+        self.shared_stats
+            .enobufs_backoffs
+            .store(self.enobufs_backoffs, Ordering::Relaxed);
+    }
+
+    /// The socket refused a datagram of `len` bytes as too large for the route, so the route
+    /// MTU dropped below ours. Only packets inserted after this point will be fragmented with
+    /// the new MTU.
+    fn refused(&mut self, len: usize) {
+        let size = len + UDP_HEADER_OVERHEAD as usize;
+        if let Some(mtu) = self.mtu_prober.too_large(size, util::now()) {
             rakrs_debug!(
                 true,
-                "[{}] Failed to send packet! {:?}",
+                "[{}] Datagrams of {} bytes are too large for the route, lowering MTU to {}",
                 to_address_token(self.address),
-                e
+                size,
+                mtu
             );
+            self.mtu_size = mtu;
+            self.blackhole.reset();
+            self.publish_mtu();
         }
     }
 
+    /// Sends the datagrams that were held back once the backoff is over.
+    /// Returns whether the socket took all of them.
+    async fn send_stalled(&mut self, now: Instant) -> bool {
+        if self.backoff_until.is_some_and(|until| now < until) {
+            return false;
+        }
+        self.backoff_until = None;
+
+        while let Some(packet) = self.stalled.pop_front() {
+            self.memory.credit(memory::cost(packet.len()));
+            if let Err(e) = self.send_to_socket(&packet).await {
+                match SendFailure::classify(&e) {
+                    SendFailure::NoBuffers => {
+                        self.memory.debit(memory::cost(packet.len()));
+                        self.stalled.push_front(packet);
+                        self.back_off(now);
+                        return false;
+                    }
+                    SendFailure::TooLarge => self.refused(packet.len()),
+                    SendFailure::Other => {}
+                }
+            }
+        }
+        true
+    }
+
+    /// How many times sends were held back because the socket ran out of buffer space.
+    pub fn enobufs_backoffs(&self) -> u64 {
+        self.enobufs_backoffs
+    }
+
+    /// How many unreliable messages were dropped because the socket or the send budget was
+    /// under pressure.
+    pub fn unreliable_dropped(&self) -> u64 {
+        self.unreliable_dropped
+    }
+
+    /// Makes the next sends fail with the OS errors in `codes`, one each, as if the socket
+    /// returned them. Only meant for testing how the queue copes with a struggling socket.
+    #[doc(hidden)]
+    pub fn fail_sends(&mut self, codes: impl IntoIterator<Item = i32>) {
+        self.send_faults.extend(codes);
+    }
+
+    /// Makes every datagram wait for a permit from `gate` before it reaches the socket, until
+    /// the sending side is dropped. Only meant for testing what a send cancelled at each of
+    /// its await points leaves behind.
+    #[doc(hidden)]
+    pub fn gate_sends(&mut self, gate: UnboundedReceiver<()>) {
+        self.send_gate = Some(Arc::new(futures::lock::Mutex::new(gate)));
+    }
+
     pub async fn send_packet(
         &mut self,
         packet: RakPacket,
@@ -270,31 +1330,250 @@ impl SendQueue {
         }
     }
 
+    /// Sends a padded no-op datagram at the given MTU, this is never added to the
+    /// recovery queue, so losing it costs nothing.
+    async fn send_mtu_probe(&mut self, mtu: u16) -> usize {
+        let room = mtu
+            .saturating_sub(RAKNET_HEADER_FRAME_OVERHEAD)
+            .saturating_sub(self.reserved());
+        let mut body = vec![MTU_PROBE_ID];
+        body.resize(room.max(1) as usize, 0);
+
+        let mut pk = FramePacket::new();
+        pk.sequence = self.send_seq.next_index();
+        pk.reliability = Reliability::Unreliable;
+        pk.frames
+            .push(Frame::new(Reliability::Unreliable, Some(&body[..])));
+
+        rakrs_debug!(
+            true,
+            "[{}] Probing MTU {} (currently {})",
+            to_address_token(self.address),
+            mtu,
+            self.mtu_size
+        );
+        self.mtu_prober.sent(pk.sequence, mtu, util::now());
+        self.send_datagram(&pk).await
+    }
+
+    /// Raises the MTU if the given sequence acknowledges a probe.
+    /// Only packets inserted after this point will be fragmented with the new MTU.
+    fn ack_mtu_probe(&mut self, sequence: DatagramSeq) {
+        if !self.mtu_prober.is_probe(sequence) {
+            return;
+        }
+
+        if let Some(mtu) = self.mtu_prober.ack(sequence, util::now()) {
+            rakrs_debug!(
+                true,
+                "[{}] MTU probe acknowledged, raising MTU from {} to {}",
+                to_address_token(self.address),
+                self.mtu_size,
+                mtu
+            );
+            self.mtu_size = mtu;
+            self.blackhole.reset();
+            self.publish_mtu();
+        }
+    }
+
+    /// Called when a reliable datagram is acknowledged.
+    fn ack_sequence(&mut self, sequence: DatagramSeq, now: Instant) {
+        if let Ok(packet) = self.ack.remove(sequence) {
+            self.memory.credit(memory::cost(wire_size(&packet)));
+            self.unacked_since = (!self.ack.is_empty()).then_some(now);
+        }
+
+        // a retransmitted datagram can't be timed, we don't know which send was acknowledged.
+        if let Some((sent_at, false, _)) = self.in_flight.remove(&sequence) {
+            self.rtt.sample(now.duration_since(sent_at));
+        }
+
+        self.ack_mtu_probe(sequence);
+    }
+
+    fn publish_rtt(&self) {
+        if let Ok(mut stats) = self.rtt_stats.lock() {
+            *stats = self.rtt.stats();
+        }
+    }
+
+    /// Takes sent bytes from the budget, if there is one.
+    fn spend(&self, bytes: usize, now: Instant) {
+        if let Some(budget) = &self.send_budget {
+            budget.spend(bytes, now);
+        }
+    }
+
     pub async fn update(&mut self) {
-        // send all the ready packets
+        let now = util::now();
+        // what was coalesced since the last tick goes out with it.
+        for (channel, bundle) in self.coalescer.take_all() {
+            self.queue_bundle(channel, bundle);
+        }
+        // the socket is out of buffer space, the whole flush waits for it.
+        if !self.stalled.is_empty() && !self.send_stalled(now).await {
+            return;
+        }
+        // whatever a cancelled send left behind goes out first.
+        self.send_outgoing().await;
+
+        if let Some(mtu) = self.mtu_prober.poll(now) {
+            let sent = self.send_mtu_probe(mtu).await;
+            self.spend(sent, now);
+        }
+
+        // find anything that has not been acknowledged within the retransmission timeout.
+        let rto = self.rtt.rto();
+        let mut resend_queue = self
+            .ack
+            .get_all()
+            .into_iter()
+            .filter(|(seq, _)| {
+                matches!(self.in_flight.get(seq), Some((sent_at, _, factor)) if now.duration_since(*sent_at) >= rto.mul_f64(*factor))
+                    && !self.resent_recently(*seq, now)
+            })
+            .collect::<Vec<_>>();
+
+        // retransmissions are real bytes too, so they draw from the budget before new frames.
+        let mut allowance = match &self.send_budget {
+            Some(budget) if !resend_queue.is_empty() || !self.ready.is_empty() => budget.grant(now),
+            Some(_) => 0,
+            None => usize::MAX,
+        };
+
+        let mut resent = false;
+        for (seq, packet) in resend_queue.drain(..) {
+            if allowance == 0 {
+                // the rest stays due, and is resent once the budget allows it.
+                break;
+            }
+            if let Some(entry) = self.in_flight.get_mut(&seq) {
+                if !entry.1 {
+                    self.loss_by_size.record_retransmitted(wire_size(&packet));
+                }
+                *entry = (now, true, self.rng.factor(RTO_JITTER));
+            }
+            let sent = self.send_datagram(&packet).await;
+            self.spend(sent, now);
+            allowance = allowance.saturating_sub(sent);
+            resent = true;
+            self.retransmits.0 += 1;
+        }
+
+        if resent {
+            self.rtt.timeout();
+            self.publish_rtt();
+            self.publish_retransmits();
+
+            let below = self.mtu_prober.step_below();
+            if let Some(mtu) =
+                below.filter(|below| self.blackhole.is_blackhole(&self.loss_by_size, *below))
+            {
+                self.mtu_prober.step_down(mtu, now);
+                rakrs_debug!(
+                    true,
+                    "[{}] Datagrams of {} bytes keep getting lost, lowering MTU to {}",
+                    to_address_token(self.address),
+                    self.mtu_size,
+                    mtu
+                );
+                self.mtu_size = mtu;
+                self.blackhole.reset();
+                self.publish_mtu();
+            }
+        }
+
+        // send the ready packets, round-robin over the order channels
         // TODO batch these packets together
         // TODO by lengths
-        for frame in self.ready.drain(..).collect::<Vec<Frame>>() {
-            self.send_frame(frame).await;
+        let budget = self.frame_budget.unwrap_or(usize::MAX);
+        if self.send_budget.is_none() {
+            for frame in self.ready.take(budget) {
+                self.send_frame(frame).await;
+            }
+        } else {
+            // with a send budget, frames are taken one by one until the allowance runs out.
+            let mut taken = 0;
+            while allowance > 0 && taken < budget {
+                let frame = match self.ready.take(1).pop() {
+                    Some(frame) => frame,
+                    None => break,
+                };
+                let sent = self.send_frame(frame).await;
+                self.spend(sent, now);
+                allowance = allowance.saturating_sub(sent);
+                taken += 1;
+            }
         }
 
-        // Flush ACK
-        // check to see if we need to resend any packets.
-        // TODO actually implement this
-        let resend_queue = self.ack.flush().unwrap();
-        // let mut resend_queue = Vec::<FramePacket>::new();
+        // a keepalive that found no room in what was sent can't wait for the next frames.
+        if self.ready.is_empty() {
+            if let Some(keepalive) = self.keepalive.take() {
+                let _ = self
+                    .insert(&keepalive.body, Reliability::Reliable, true, None)
+                    .await;
+            }
+        }
+    }
+}
+
+/// The sequence of a frame-set datagram, zero for any other, see [`DatagramTransform::seal()`].
+///
+/// [`DatagramTransform::seal()`]: crate::connection::transform::DatagramTransform::seal
+fn seq_hint(datagram: &[u8]) -> u32 {
+    match datagram {
+        [0x80..=0x8f, a, b, c, ..] => u32::from_le_bytes([*a, *b, *c, 0]),
+        _ => 0,
+    }
+}
 
-        // for (seq, packet) in self.ack.get_all() {
-        //     if packet.resend_time < Instant::now() {
-        //         resend_queue.push(packet.clone());
-        //     }
-        // }
+/// The size of a datagram on the wire as the MTU counts it, headers included.
+fn wire_size(pk: &FramePacket) -> usize {
+    pk.frames
+        .iter()
+        .map(|frame| frame.header_len() + frame.body.len())
+        .sum::<usize>()
+        + RAKNET_HEADER_OVERHEAD as usize
+}
 
-        for packet in resend_queue.iter() {
-            if let Ok(buf) = packet.write_to_bytes() {
-                self.send_stream(buf.as_slice()).await;
+impl SendQueue {
+    /// Resends datagrams the peer reported missing, with their original sequence.
+    /// Datagrams that don't fit the send budget are left to the retransmission timeout.
+    ///
+    /// A datagram that was retransmitted less than a round trip ago is not resent again, the
+    /// peer may well have reported it missing before that retransmission arrived.
+    pub async fn resend(&mut self, packets: Vec<FramePacket>) {
+        let now = util::now();
+        let (packets, suppressed): (Vec<_>, Vec<_>) = packets
+            .into_iter()
+            .partition(|packet| !self.resent_recently(packet.sequence, now));
+        self.retransmits.2 += suppressed.len() as u64;
+
+        let mut allowance = match &self.send_budget {
+            Some(budget) if !packets.is_empty() => budget.grant(now),
+            _ => usize::MAX,
+        };
+
+        for packet in packets {
+            if allowance == 0 {
+                break;
             }
+
+            // the datagram can no longer be timed.
+            if let Some(entry) = self.in_flight.get_mut(&packet.sequence) {
+                if !entry.1 {
+                    self.loss_by_size.record_retransmitted(wire_size(&packet));
+                }
+                *entry = (now, true, self.rng.factor(RTO_JITTER));
+            }
+
+            let sent = self.send_datagram(&packet).await;
+            self.spend(sent, now);
+            allowance = allowance.saturating_sub(sent);
+            self.retransmits.1 += 1;
         }
+        self.publish_retransmits();
     }
 }
 
@@ -306,19 +1585,23 @@ impl Ackable for SendQueue {
             return;
         }
 
+        let now = util::now();
+
         // these packets are acknowledged, so we can remove them from the queue.
-        for record in ack.records.iter() {
-            match record {
-                Record::Single(SingleRecord { sequence }) => {
-                    if let Ok(_) = self.ack.remove(sequence.0) {};
-                }
-                Record::Range(ranged) => {
-                    for i in ranged.start.0..ranged.end.0 {
-                        if let Ok(_) = self.ack.remove(i) {};
-                    }
+        for range in ack.sequence_ranges() {
+            for sequence in self.ack.held_in(range.clone()) {
+                self.ack_sequence(sequence, now);
+            }
+
+            // the MTU probe is unreliable, so it isn't held with the others.
+            if let Some(sequence) = self.mtu_prober.probe_sequence() {
+                if range.contains(&sequence) {
+                    self.ack_mtu_probe(sequence);
                 }
             }
         }
+
+        self.publish_rtt();
     }
 
     fn nack(&mut self, nack: Ack) -> Vec<FramePacket> {
@@ -329,19 +1612,10 @@ impl Ackable for SendQueue {
         let mut resend_queue = Vec::<FramePacket>::new();
 
         // we need to get the packets to resend.
-        for record in nack.records.iter() {
-            match record {
-                Record::Single(single) => {
-                    if let Ok(packet) = self.ack.get(single.sequence.0) {
-                        resend_queue.push(packet.clone());
-                    }
-                }
-                Record::Range(ranged) => {
-                    for i in ranged.start.0..ranged.end.0 {
-                        if let Ok(packet) = self.ack.get(i) {
-                            resend_queue.push(packet.clone());
-                        }
-                    }
+        for range in nack.sequence_ranges() {
+            for sequence in self.ack.held_in(range) {
+                if let Ok(packet) = self.ack.get(sequence) {
+                    resend_queue.push(packet.clone());
                 }
             }
         }
@@ -0,0 +1,336 @@
+use std::sync::atomic::{fence, AtomicU16, AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use crate::connection::controller::loss::{LossBySize, BUCKETS};
+use crate::connection::controller::rtt::RttStats;
+use crate::protocol::index::OrderIndex;
+
+/// The smallest datagram a connection sends, an acknowledgement of a single sequence.
+pub const MIN_DATAGRAM_SIZE: usize = 7;
+
+/// A snapshot of the state of a connection, this can be retrieved with
+/// [`Connection::stats()`] or [`Client::stats()`].
+///
+/// The counters of a snapshot were all published at once, see [`SharedStats::publish()`],
+/// so they never disagree with each other, and never go back from one snapshot to the next.
+///
+/// [`Connection::stats()`]: crate::connection::Connection::stats
+/// [`Client::stats()`]: crate::client::Client::stats
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionStats {
+    /// The publish the counters were taken from, a later snapshot has a higher epoch.
+    pub epoch: u64,
+    /// The round trip state of the connection.
+    pub rtt: RttStats,
+    /// The amount of datagrams handed to the socket, acknowledgements included.
+    pub datagrams_sent: u64,
+    /// The bytes of the datagrams in [`ConnectionStats::datagrams_sent`].
+    pub bytes_sent: u64,
+    /// The amount of packets with an unknown id that were dropped during a flood.
+    pub unknown_suppressed: u64,
+    /// The amount of retransmitted handshake packets that were ignored, this is only counted
+    /// by the server side of a connection.
+    pub duplicate_handshakes: u64,
+    /// The amount of datagrams that were dropped because their checksum didn't match,
+    /// see [`ConnOptions::datagram_checksum`].
+    ///
+    /// [`ConnOptions::datagram_checksum`]: crate::connection::options::ConnOptions::datagram_checksum
+    pub corrupt_datagrams: u64,
+    /// The amount of datagrams that were dropped because the transform of the connection
+    /// couldn't open them, see [`DatagramTransform`].
+    ///
+    /// [`DatagramTransform`]: crate::connection::transform::DatagramTransform
+    pub rejected_datagrams: u64,
+    /// The amount of datagrams of the connection that arrived from another port of its host
+    /// and were dropped, see [`AddressBinding::Strict`].
+    ///
+    /// [`AddressBinding::Strict`]: crate::connection::options::AddressBinding::Strict
+    pub spoofed_datagrams: u64,
+    /// The amount of sequences that were acknowledged in more than one datagram, because the
+    /// peer resent them before our acknowledgement reached it.
+    pub repeated_acks: u64,
+    /// How many times sending was held back because the socket ran out of buffer space
+    /// (`ENOBUFS`), which bursts easily cause on macOS and the BSDs.
+    pub enobufs_backoffs: u64,
+    /// The amount of unreliable messages that were dropped rather than queued, because the
+    /// socket was out of buffer space or the send budget was spent.
+    pub unreliable_dropped: u64,
+    /// The amount of ordered and sequenced messages that were dropped because they were too
+    /// far ahead of their channel, see [`ConnOptions::order_jump_limit`].
+    ///
+    /// [`ConnOptions::order_jump_limit`]: crate::connection::options::ConnOptions::order_jump_limit
+    pub order_jumps: u64,
+    /// The amount of messages of the peer that were dropped because the application stopped
+    /// receiving them, see [`ConnOptions::consumer_timeout`].
+    ///
+    /// [`ConnOptions::consumer_timeout`]: crate::connection::options::ConnOptions::consumer_timeout
+    pub events_dropped_no_consumer: u64,
+    /// The amount of datagrams that were retransmitted because their retransmission timeout
+    /// expired.
+    pub timeout_retransmits: u64,
+    /// The amount of datagrams that were retransmitted because the peer reported them missing.
+    pub nack_retransmits: u64,
+    /// The amount of retransmissions that were skipped because the datagram had been
+    /// retransmitted less than a round trip ago, like when a NACK for it arrives right as
+    /// its timeout expired.
+    pub suppressed_retransmits: u64,
+    /// The reliable datagrams sent and the ones of them that had to be retransmitted, by
+    /// their size, to tell a path that drops large datagrams from one that loses any.
+    pub loss_by_size: LossBySize,
+    /// The bytes the buffers of the connection hold, as charged to its ledger, see
+    /// [`ConnOptions::max_connection_memory`].
+    ///
+    /// [`ConnOptions::max_connection_memory`]: crate::connection::options::ConnOptions::max_connection_memory
+    pub memory_used: usize,
+    /// The delivery statistics of every order channel that has been used.
+    pub channels: Vec<ChannelStats>,
+    /// How long the worst channel in [`ConnectionStats::channels`] is currently blocked for.
+    pub max_channel_blocked: Duration,
+}
+
+impl ConnectionStats {
+    /// The average size of a datagram sent, zero before anything was sent.
+    pub fn average_datagram_size(&self) -> u64 {
+        self.bytes_sent
+            .checked_div(self.datagrams_sent)
+            .unwrap_or_default()
+    }
+
+    /// The ratio (`0.0` to `1.0`) of the datagrams sent that had to be retransmitted, an
+    /// estimate of the loss on the way to the peer.
+    pub fn loss_ratio(&self) -> f32 {
+        if self.datagrams_sent == 0 {
+            return 0.0;
+        }
+        let retransmits = self.timeout_retransmits + self.nack_retransmits;
+        (retransmits as f64 / self.datagrams_sent as f64).min(1.0) as f32
+    }
+
+    /// Takes the counters of `counters` over.
+    pub(crate) fn with_counters(mut self, counters: Counters) -> Self {
+        self.epoch = counters.epoch;
+        self.unknown_suppressed = counters.unknown_suppressed;
+        self.duplicate_handshakes = counters.duplicate_handshakes;
+        self.corrupt_datagrams = counters.corrupt_datagrams;
+        self.repeated_acks = counters.repeated_acks;
+        self.enobufs_backoffs = counters.enobufs_backoffs;
+        self.unreliable_dropped = counters.unreliable_dropped;
+        self.order_jumps = counters.order_jumps;
+        self.events_dropped_no_consumer = counters.events_dropped_no_consumer;
+        self.timeout_retransmits = counters.timeout_retransmits;
+        self.nack_retransmits = counters.nack_retransmits;
+        self.suppressed_retransmits = counters.suppressed_retransmits;
+        self.datagrams_sent = counters.datagrams_sent;
+        self.bytes_sent = counters.bytes_sent;
+        self.rejected_datagrams = counters.rejected_datagrams;
+        self.spoofed_datagrams = counters.spoofed_datagrams;
+        self.loss_by_size = counters.loss_by_size;
+        self
+    }
+}
+
+/// The delivery statistics of a single order channel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelStats {
+    /// The order channel these statistics are for.
+    pub channel: u8,
+    /// The amount of messages delivered in order.
+    pub delivered: u64,
+    /// The amount of messages buffered, waiting for a missing message.
+    pub buffered: usize,
+    /// The missing order index holding up the channel, if any.
+    pub head_gap: Option<OrderIndex>,
+    /// How long the channel has been held up by `head_gap`, zero when it isn't.
+    pub blocked_for: Duration,
+    /// The total time the channel has been held up.
+    pub total_blocked: Duration,
+    /// The longest time the channel has been held up.
+    pub max_blocked: Duration,
+}
+
+/// The amount of counters a [`SharedStats`] publishes on their own.
+const SCALARS: usize = 15;
+
+/// The amount of counters a [`SharedStats`] publishes, the scalars and two per size bucket.
+const COUNTERS: usize = SCALARS + 2 * BUCKETS;
+
+/// The counters of a [`SharedStats`] as of one publish, see [`SharedStats::snapshot()`].
+/// Every counter is the field of [`ConnectionStats`] with the same name.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counters {
+    /// The publish these were taken from, zero before the first.
+    pub epoch: u64,
+    pub unknown_suppressed: u64,
+    pub duplicate_handshakes: u64,
+    pub corrupt_datagrams: u64,
+    pub repeated_acks: u64,
+    pub enobufs_backoffs: u64,
+    pub unreliable_dropped: u64,
+    pub order_jumps: u64,
+    pub events_dropped_no_consumer: u64,
+    pub timeout_retransmits: u64,
+    pub nack_retransmits: u64,
+    pub suppressed_retransmits: u64,
+    pub datagrams_sent: u64,
+    pub bytes_sent: u64,
+    pub rejected_datagrams: u64,
+    pub spoofed_datagrams: u64,
+    pub loss_by_size: LossBySize,
+}
+
+impl Counters {
+    /// Takes `values` in the order of [`SharedStats::live()`].
+    fn new(epoch: u64, values: [u64; COUNTERS]) -> Self {
+        Self {
+            epoch,
+            unknown_suppressed: values[0],
+            duplicate_handshakes: values[1],
+            corrupt_datagrams: values[2],
+            repeated_acks: values[3],
+            enobufs_backoffs: values[4],
+            unreliable_dropped: values[5],
+            order_jumps: values[6],
+            events_dropped_no_consumer: values[7],
+            timeout_retransmits: values[8],
+            nack_retransmits: values[9],
+            suppressed_retransmits: values[10],
+            datagrams_sent: values[11],
+            bytes_sent: values[12],
+            rejected_datagrams: values[13],
+            spoofed_datagrams: values[14],
+            loss_by_size: LossBySize {
+                sent: std::array::from_fn(|i| values[SCALARS + i]),
+                retransmitted: std::array::from_fn(|i| values[SCALARS + BUCKETS + i]),
+            },
+        }
+    }
+}
+
+/// The counters of a connection, published by its queues and tasks whenever they change, so
+/// [`Connection::stats()`] and [`Connection::meta()`] read them without locking the queues.
+///
+/// The counters are updated one at a time, so reading them directly can catch the connection
+/// halfway through an update, like a datagram counted in `datagrams_sent` but not yet in
+/// `bytes_sent`. [`SharedStats::publish()`] copies them into one of two blocks while nothing
+/// updates them, at every tick of the connection, and [`SharedStats::snapshot()`] reads the
+/// block published last.
+///
+/// [`Connection::stats()`]: crate::connection::Connection::stats
+/// [`Connection::meta()`]: crate::connection::Connection::meta
+#[derive(Debug, Default)]
+pub struct SharedStats {
+    /// See [`ConnectionStats::unknown_suppressed`].
+    pub unknown_suppressed: AtomicU64,
+    /// See [`ConnectionStats::duplicate_handshakes`].
+    pub duplicate_handshakes: AtomicU64,
+    /// See [`ConnectionStats::corrupt_datagrams`].
+    pub corrupt_datagrams: AtomicU64,
+    /// See [`ConnectionStats::repeated_acks`].
+    pub repeated_acks: AtomicU64,
+    /// See [`ConnectionStats::enobufs_backoffs`].
+    pub enobufs_backoffs: AtomicU64,
+    /// See [`ConnectionStats::unreliable_dropped`].
+    pub unreliable_dropped: AtomicU64,
+    /// See [`ConnectionStats::order_jumps`].
+    pub order_jumps: AtomicU64,
+    /// See [`ConnectionStats::events_dropped_no_consumer`].
+    pub events_dropped_no_consumer: AtomicU64,
+    /// See [`ConnectionStats::timeout_retransmits`].
+    pub timeout_retransmits: AtomicU64,
+    /// See [`ConnectionStats::nack_retransmits`].
+    pub nack_retransmits: AtomicU64,
+    /// See [`ConnectionStats::suppressed_retransmits`].
+    pub suppressed_retransmits: AtomicU64,
+    /// See [`ConnectionStats::datagrams_sent`].
+    pub datagrams_sent: AtomicU64,
+    /// See [`ConnectionStats::bytes_sent`].
+    pub bytes_sent: AtomicU64,
+    /// See [`ConnectionStats::rejected_datagrams`].
+    pub rejected_datagrams: AtomicU64,
+    /// See [`ConnectionStats::spoofed_datagrams`].
+    pub spoofed_datagrams: AtomicU64,
+    /// See [`LossBySize::sent`].
+    pub sent_by_size: [AtomicU64; BUCKETS],
+    /// See [`LossBySize::retransmitted`].
+    pub retransmitted_by_size: [AtomicU64; BUCKETS],
+    /// The MTU the send queue currently fragments with.
+    pub mtu_size: AtomicU16,
+    /// Twice the epoch of the block published last, plus one while the next is written.
+    sequence: AtomicU64,
+    /// The block of an even epoch comes first, the one of an odd epoch second.
+    blocks: [[AtomicU64; COUNTERS]; 2],
+    /// Held while publishing, there is only ever one block being written.
+    publishing: Mutex<()>,
+}
+
+impl SharedStats {
+    /// Counters that are all zero, for a send queue fragmenting with `mtu_size`.
+    pub fn new(mtu_size: u16) -> Self {
+        Self {
+            mtu_size: AtomicU16::new(mtu_size),
+            ..Default::default()
+        }
+    }
+
+    fn live(&self) -> [&AtomicU64; COUNTERS] {
+        let scalars = [
+            &self.unknown_suppressed,
+            &self.duplicate_handshakes,
+            &self.corrupt_datagrams,
+            &self.repeated_acks,
+            &self.enobufs_backoffs,
+            &self.unreliable_dropped,
+            &self.order_jumps,
+            &self.events_dropped_no_consumer,
+            &self.timeout_retransmits,
+            &self.nack_retransmits,
+            &self.suppressed_retransmits,
+            &self.datagrams_sent,
+            &self.bytes_sent,
+            &self.rejected_datagrams,
+            &self.spoofed_datagrams,
+        ];
+        std::array::from_fn(|i| match i {
+            i if i < SCALARS => scalars[i],
+            i if i < SCALARS + BUCKETS => &self.sent_by_size[i - SCALARS],
+            i => &self.retransmitted_by_size[i - SCALARS - BUCKETS],
+        })
+    }
+
+    /// Copies the counters into the block that isn't read, and makes it the one that is.
+    ///
+    /// This has to be called while the send queue is locked, which every update of the
+    /// counters that belong together holds.
+    pub fn publish(&self) {
+        let _publishing = self
+            .publishing
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let sequence = self.sequence.load(Ordering::Relaxed);
+        self.sequence.store(sequence + 1, Ordering::Relaxed);
+        // a reader that sees any of the block written below sees the sequence is odd.
+        fence(Ordering::Release);
+
+        let spare = &self.blocks[(sequence / 2 + 1) as usize % 2];
+        for (slot, counter) in spare.iter().zip(self.live()) {
+            slot.store(counter.load(Ordering::Relaxed), Ordering::Relaxed);
+        }
+        self.sequence.store(sequence + 2, Ordering::Release);
+    }
+
+    /// The counters as of the last [`SharedStats::publish()`], this never waits on a publish.
+    pub fn snapshot(&self) -> Counters {
+        loop {
+            let sequence = self.sequence.load(Ordering::Acquire);
+            let epoch = sequence / 2;
+            let block = &self.blocks[epoch as usize % 2];
+            let values = std::array::from_fn(|i| block[i].load(Ordering::Relaxed));
+            fence(Ordering::Acquire);
+            // the block is only written again by the publish after the one in progress.
+            if self.sequence.load(Ordering::Relaxed) < epoch * 2 + 3 {
+                return Counters::new(epoch, values);
+            }
+        }
+    }
+}
@@ -0,0 +1,191 @@
+//! Whole-datagram transforms, for deployments that encrypt their connections with a key
+//! they agreed on out of band.
+//!
+//! A [`DatagramTransform`] sits right above the socket: every datagram a connection sends,
+//! frame sets, acknowledgements and retransmissions alike, is sealed just before it is handed
+//! to the socket, and every datagram it receives is opened before its header is looked at.
+//! This is unlike a [codec](crate::connection::codec), which sees the payloads of single
+//! messages above the framing.
+//!
+//! A transform is installed on an established connection with
+//! [`Connection::set_transform()`] or [`Client::set_transform()`], the handshake itself always
+//! goes out in the clear so any RakNet peer can connect. Both sides have to install the same
+//! transform: until the peer did, its datagrams fail to open and are dropped, and the
+//! reliable ones are resent once it has.
+//!
+//! [`Connection::set_transform()`]: crate::connection::Connection::set_transform
+//! [`Client::set_transform()`]: crate::client::Client::set_transform
+use std::sync::{Arc, RwLock};
+
+/// Why a datagram couldn't be opened, it is dropped and counted in
+/// [`ConnectionStats::rejected_datagrams`].
+///
+/// [`ConnectionStats::rejected_datagrams`]: crate::connection::stats::ConnectionStats::rejected_datagrams
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransformError {
+    /// The datagram is too short to have been sealed by the transform.
+    Truncated,
+    /// The datagram was not sealed with the same key, or was altered on the way.
+    Rejected,
+}
+
+/// Seals every datagram of a connection before it is sent, and opens every datagram it
+/// receives, see the [module](self) documentation.
+pub trait DatagramTransform: Send + Sync {
+    /// Appends the sealed `plaintext` to `out`.
+    ///
+    /// `seq_hint` is the sequence of a frame-set datagram, and zero for any other. A
+    /// retransmission is sealed again with the same sequence, so it is no nonce on its own.
+    fn seal(&self, seq_hint: u32, plaintext: &[u8], out: &mut Vec<u8>);
+
+    /// Appends the opened `ciphertext` to `out`.
+    fn open(&self, ciphertext: &[u8], out: &mut Vec<u8>) -> Result<(), TransformError>;
+
+    /// How many bytes a sealed datagram is larger than the plaintext, the fragmenter leaves
+    /// room for them below the MTU.
+    fn overhead(&self) -> usize {
+        0
+    }
+}
+
+/// Leaves datagrams as they are, the transform of a connection until another is installed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Identity;
+
+impl DatagramTransform for Identity {
+    fn seal(&self, _seq_hint: u32, plaintext: &[u8], out: &mut Vec<u8>) {
+        out.extend_from_slice(plaintext);
+    }
+
+    fn open(&self, ciphertext: &[u8], out: &mut Vec<u8>) -> Result<(), TransformError> {
+        out.extend_from_slice(ciphertext);
+        Ok(())
+    }
+}
+
+/// The transform of a connection, shared by its send queue and the task receiving its
+/// datagrams. `None` until one is installed, which costs nothing per datagram.
+#[derive(Clone, Default)]
+pub(crate) struct SharedTransform(Arc<RwLock<Option<Arc<dyn DatagramTransform>>>>);
+
+impl SharedTransform {
+    pub fn install(&self, transform: Option<Arc<dyn DatagramTransform>>) {
+        *self.0.write().unwrap_or_else(|e| e.into_inner()) = transform;
+    }
+
+    pub fn get(&self) -> Option<Arc<dyn DatagramTransform>> {
+        self.0.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// The overhead of the installed transform, zero without one.
+    pub fn overhead(&self) -> usize {
+        self.get().map_or(0, |transform| transform.overhead())
+    }
+
+    /// Opens `datagram` with the installed transform, as is without one.
+    pub fn open(&self, datagram: Vec<u8>) -> Result<Vec<u8>, TransformError> {
+        match self.get() {
+            Some(transform) => {
+                let mut out = Vec::with_capacity(datagram.len());
+                transform.open(&datagram, &mut out)?;
+                Ok(out)
+            }
+            None => Ok(datagram),
+        }
+    }
+}
+
+impl std::fmt::Debug for SharedTransform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedTransform")
+            .field("installed", &self.get().is_some())
+            .finish()
+    }
+}
+
+/// XChaCha20-Poly1305 with a pre-shared key, every datagram is sealed with a nonce of its own.
+///
+/// The sealed datagram is the 24 byte nonce, the ciphertext and the 16 byte tag, so it is
+/// [`PSK_OVERHEAD`] bytes larger. The nonce starts with the sequence hint and is random after
+/// it, which the extended nonce of XChaCha20 has room for.
+#[cfg(feature = "encryption")]
+pub struct XChaCha20Psk {
+    cipher: chacha20poly1305::XChaCha20Poly1305,
+}
+
+/// The length of the nonce of [`XChaCha20Psk`].
+#[cfg(feature = "encryption")]
+pub const PSK_NONCE_LEN: usize = 24;
+
+/// The length of the tag of [`XChaCha20Psk`].
+#[cfg(feature = "encryption")]
+pub const PSK_TAG_LEN: usize = 16;
+
+/// How many bytes [`XChaCha20Psk`] adds to a datagram.
+#[cfg(feature = "encryption")]
+pub const PSK_OVERHEAD: usize = PSK_NONCE_LEN + PSK_TAG_LEN;
+
+#[cfg(feature = "encryption")]
+impl XChaCha20Psk {
+    /// Creates the transform for `key`, which both sides have to agree on out of band.
+    pub fn new(key: &[u8; 32]) -> Self {
+        use chacha20poly1305::KeyInit;
+
+        Self {
+            cipher: chacha20poly1305::XChaCha20Poly1305::new(key.into()),
+        }
+    }
+}
+
+#[cfg(feature = "encryption")]
+impl DatagramTransform for XChaCha20Psk {
+    fn seal(&self, seq_hint: u32, plaintext: &[u8], out: &mut Vec<u8>) {
+        use chacha20poly1305::aead::AeadInPlace;
+        use rand::RngCore;
+
+        let mut nonce = [0u8; PSK_NONCE_LEN];
+        nonce[..4].copy_from_slice(&seq_hint.to_be_bytes());
+        rand::thread_rng().fill_bytes(&mut nonce[4..]);
+
+        let start = out.len();
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(plaintext);
+        match self.cipher.encrypt_in_place_detached(
+            chacha20poly1305::XNonce::from_slice(&nonce),
+            &[],
+            &mut out[start + PSK_NONCE_LEN..],
+        ) {
+            Ok(tag) => out.extend_from_slice(&tag),
+            // only a datagram of gigabytes can't be sealed, it is dropped by the peer.
+            Err(_) => out.truncate(start),
+        }
+    }
+
+    fn open(&self, ciphertext: &[u8], out: &mut Vec<u8>) -> Result<(), TransformError> {
+        use chacha20poly1305::aead::AeadInPlace;
+
+        if ciphertext.len() < PSK_OVERHEAD {
+            return Err(TransformError::Truncated);
+        }
+        let (nonce, rest) = ciphertext.split_at(PSK_NONCE_LEN);
+        let (body, tag) = rest.split_at(rest.len() - PSK_TAG_LEN);
+
+        let start = out.len();
+        out.extend_from_slice(body);
+        let opened = self.cipher.decrypt_in_place_detached(
+            chacha20poly1305::XNonce::from_slice(nonce),
+            &[],
+            &mut out[start..],
+            chacha20poly1305::Tag::from_slice(tag),
+        );
+        if opened.is_err() {
+            out.truncate(start);
+            return Err(TransformError::Rejected);
+        }
+        Ok(())
+    }
+
+    fn overhead(&self) -> usize {
+        PSK_OVERHEAD
+    }
+}
@@ -1,4 +1,5 @@
 //! Client errors are errors that can occur when using the [`Client`](crate::client::Client) api.
+use crate::client::handshake::{HandshakeTimings, Stage};
 use crate::connection::queue::SendQueueError;
 
 /// These are errors that can occur when using the [`Client`](crate::client::Client) api.
@@ -8,8 +9,19 @@ use crate::connection::queue::SendQueueError;
 pub enum ClientError {
     /// The client is already connected to the peer on this address.
     AddrBindErr,
+    /// The client is unable to pin its socket to the network interface in
+    /// [`ConnOptions::bind_device`](crate::connection::options::ConnOptions::bind_device).
+    /// On Linux this requires `CAP_NET_RAW`.
+    BindDeviceErr,
+    /// The socket [`ping()`](crate::client::ping) or
+    /// [`discover_lan()`](crate::client::discover_lan) sends from could not be bound, for the
+    /// reason the OS gave.
+    SocketBindErr(std::io::ErrorKind),
     /// The client is already connected a peer.
     AlreadyOnline,
+    /// Another call to [`Client::connect()`](crate::client::Client::connect) is still
+    /// connecting this client, see [`Client::join_existing()`](crate::client::Client::join_existing).
+    AlreadyConnecting,
     /// The client is offline and can not send packets.
     NotListening,
     /// The client is unable to connect to the peer.
@@ -24,4 +36,119 @@ pub enum ClientError {
     ServerOffline,
     /// The client failed to process a packet you sent.
     SendQueueError(SendQueueError),
+    /// The peer answered the ping, but the handshake did not complete.
+    Handshake(HandshakeError),
+    /// The connection was given up on before it completed, see
+    /// [`PoolOptions::deadline`](crate::client::pool::PoolOptions::deadline).
+    TimedOut,
+    /// The [first payload](crate::client::Client::first_payload) does not fit a single frame
+    /// at the smallest MTU, so it is refused before connecting.
+    FirstPayloadTooLarge {
+        /// The size of the payload.
+        len: usize,
+        /// The largest payload allowed, [`MAX_FIRST_PAYLOAD`](crate::client::MAX_FIRST_PAYLOAD).
+        max: usize,
+    },
+}
+
+/// Why a handshake failed, and how far it got.
+///
+/// A peer that doesn't answer the ping at all is reported as [`ClientError::ServerOffline`]
+/// instead, these errors are only returned once the peer is known to be there. The exception
+/// is [`HandshakeError::NoInboundTraffic`], where nothing came back to tell either way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum HandshakeError {
+    /// The peer stopped answering. Every stage before `last_stage` succeeded.
+    TimedOut {
+        /// The stage that was reached, and never answered.
+        last_stage: Stage,
+        /// The MTU of the last attempt of that stage.
+        mtu: u16,
+        timings: HandshakeTimings,
+    },
+    /// The peer answered a stage with something the handshake can't continue with.
+    Rejected {
+        stage: Stage,
+        timings: HandshakeTimings,
+    },
+    /// The peer uses a different protocol version.
+    IncompatibleVersion,
+    /// Every ping was sent, yet the socket didn't receive a single datagram, not even an error.
+    ///
+    /// An offline server usually causes an ICMP error, and an unrelated reply proves the path
+    /// works. Complete silence points at a NAT or firewall dropping the replies.
+    NoInboundTraffic {
+        /// The amount of pings that were sent.
+        probes: u8,
+    },
+    /// Another handshake is already running on the socket, their replies would interleave.
+    SocketInUse,
+    /// The server answered the first stage from another port than the one connected to, and
+    /// the client does not [follow it](crate::client::Client::allow_port_rebind).
+    PortMismatch {
+        /// The port the client connected to.
+        expected: u16,
+        /// The port the reply came from.
+        observed: u16,
+    },
+}
+
+impl HandshakeError {
+    /// The stage the handshake failed at.
+    pub fn stage(&self) -> Stage {
+        match self {
+            HandshakeError::TimedOut { last_stage, .. } => *last_stage,
+            HandshakeError::Rejected { stage, .. } => *stage,
+            HandshakeError::IncompatibleVersion
+            | HandshakeError::NoInboundTraffic { .. }
+            | HandshakeError::SocketInUse
+            | HandshakeError::PortMismatch { .. } => Stage::Open1,
+        }
+    }
+
+    /// The attempts and time spent on each stage, if the handshake got to sending any.
+    pub fn timings(&self) -> Option<HandshakeTimings> {
+        match self {
+            HandshakeError::TimedOut { timings, .. } | HandshakeError::Rejected { timings, .. } => {
+                Some(*timings)
+            }
+            HandshakeError::IncompatibleVersion
+            | HandshakeError::NoInboundTraffic { .. }
+            | HandshakeError::SocketInUse
+            | HandshakeError::PortMismatch { .. } => None,
+        }
+    }
+
+    /// A diagnosis of the failure that can be shown to users as is.
+    pub fn advice(&self) -> &'static str {
+        match self {
+            HandshakeError::TimedOut {
+                last_stage: Stage::Open1,
+                ..
+            } => "the server answers pings but not connection requests, it may not accept new connections or a firewall drops the padded requests",
+            HandshakeError::TimedOut {
+                last_stage: Stage::Open2,
+                ..
+            } => "stage 1 succeeded, stage 2 timed out, this is likely fragmentation or MTU filtering on the path, retry with a smaller MTU such as 576",
+            HandshakeError::TimedOut {
+                last_stage: Stage::Connect,
+                ..
+            } => "the session was opened but the server never accepted the connection, the server software may be overloaded or broken",
+            HandshakeError::Rejected { .. } => {
+                "the server answered with something unexpected, it may not be a RakNet server or its implementation is broken"
+            }
+            HandshakeError::IncompatibleVersion => {
+                "the server uses a different protocol version, update the client or the server"
+            }
+            HandshakeError::NoInboundTraffic { .. } => {
+                "nothing came back at all, not even an error, a NAT or firewall likely drops the replies of the server, check it and try again as the attempts themselves may open it"
+            }
+            HandshakeError::SocketInUse => {
+                "another handshake is still running on this socket, wait for it to finish or use a socket of its own"
+            }
+            HandshakeError::PortMismatch { .. } => {
+                "the server answers from another port than the one connected to, likely a load balancer or NAT in front of it, connect to that port or allow the client to rebind to it"
+            }
+        }
+    }
 }
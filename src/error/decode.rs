@@ -0,0 +1,143 @@
+//! # Decode Error
+//! Why a packet could not be read, and where in its bytes that was noticed.
+//!
+//! These are produced by [`primitives::decode()`](crate::protocol::primitives::decode), which
+//! every packet of the [`protocol`](crate::protocol) module can be read with.
+use std::fmt;
+
+/// A packet that failed to decode.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DecodeError {
+    /// The first byte of the buffer, `None` if it was empty.
+    pub packet_id: Option<u8>,
+    /// The position in the buffer of the field that failed, for an [`UnexpectedEof`] this is
+    /// where the field would have started.
+    ///
+    /// [`UnexpectedEof`]: DecodeErrorKind::UnexpectedEof
+    pub offset: usize,
+    /// What was wrong at `offset`.
+    pub kind: DecodeErrorKind,
+}
+
+/// What was wrong with a packet, see [`DecodeError`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DecodeErrorKind {
+    /// The buffer ended in the middle of a field.
+    UnexpectedEof {
+        /// The bytes left that would have let the field be read.
+        needed: usize,
+        /// The bytes that were left.
+        remaining: usize,
+    },
+    /// The offline magic was not the one of [`Magic`](crate::protocol::Magic).
+    InvalidMagic,
+    /// An address had a version other than 4 or 6.
+    InvalidAddressVersion(u8),
+    /// A field that only has a few meanings had none of them, such as an unknown packet id.
+    InvalidEnumValue {
+        /// The name of the field.
+        field: &'static str,
+        /// The byte it had.
+        value: u8,
+    },
+    /// The packet was read, but bytes were left after it. Only reported by
+    /// [`primitives::decode_strict()`](crate::protocol::primitives::decode_strict).
+    TrailingBytes {
+        /// How many bytes were left.
+        count: usize,
+    },
+    /// Any other error reported while reading, such as a motd that isn't utf-8.
+    Malformed(String),
+}
+
+impl DecodeError {
+    /// Whether the buffer is not a packet this type knows, rather than a broken one.
+    /// Payloads of the application are never packets of the protocol.
+    pub fn is_unknown_packet(&self) -> bool {
+        self.offset == 0
+            && matches!(
+                self.kind,
+                DecodeErrorKind::InvalidEnumValue {
+                    field: "packet id",
+                    ..
+                }
+            )
+    }
+
+    /// The bytes of `buffer` around [`offset`](DecodeError::offset) in hex, the byte at
+    /// the offset in brackets.
+    ///
+    /// ```rust
+    /// use rak_rs::error::decode::{DecodeError, DecodeErrorKind};
+    ///
+    /// let error = DecodeError {
+    ///     packet_id: Some(0x05),
+    ///     offset: 2,
+    ///     kind: DecodeErrorKind::InvalidMagic,
+    /// };
+    /// assert_eq!(error.hexdump(&[0x05, 0x00, 0xfe, 0x01]), "0000: 05 00 [fe] 01");
+    /// ```
+    pub fn hexdump(&self, buffer: &[u8]) -> String {
+        const CONTEXT: usize = 8;
+        let start = self.offset.saturating_sub(CONTEXT).min(buffer.len());
+        let end = (self.offset + CONTEXT + 1).min(buffer.len());
+
+        let mut dump = format!("{:04x}:", start);
+        for (i, byte) in buffer[start..end].iter().enumerate() {
+            if start + i == self.offset {
+                dump.push_str(&format!(" [{:02x}]", byte));
+            } else {
+                dump.push_str(&format!(" {:02x}", byte));
+            }
+        }
+        if self.offset >= buffer.len() {
+            dump.push_str(" []");
+        }
+        dump
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.packet_id {
+            Some(id) => write!(f, "packet 0x{:02x}", id)?,
+            None => write!(f, "empty packet")?,
+        }
+        write!(f, " at offset {}: {}", self.offset, self.kind)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl fmt::Display for DecodeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeErrorKind::UnexpectedEof { needed, remaining } => {
+                write!(f, "needed {} bytes, {} were left", needed, remaining)
+            }
+            DecodeErrorKind::InvalidMagic => write!(f, "invalid magic"),
+            DecodeErrorKind::InvalidAddressVersion(version) => {
+                write!(f, "invalid address version {}", version)
+            }
+            DecodeErrorKind::InvalidEnumValue { field, value } => {
+                write!(f, "invalid {} 0x{:02x}", field, value)
+            }
+            DecodeErrorKind::TrailingBytes { count } => {
+                write!(f, "{} bytes left after the packet", count)
+            }
+            DecodeErrorKind::Malformed(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for DecodeErrorKind {}
+
+impl From<DecodeErrorKind> for std::io::Error {
+    fn from(kind: DecodeErrorKind) -> Self {
+        let io_kind = match kind {
+            DecodeErrorKind::UnexpectedEof { .. } => std::io::ErrorKind::UnexpectedEof,
+            _ => std::io::ErrorKind::InvalidData,
+        };
+        std::io::Error::new(io_kind, kind)
+    }
+}
@@ -1,3 +1,51 @@
+#[cfg(feature = "client")]
 pub mod client;
 pub mod connection;
+pub mod decode;
+#[cfg(feature = "server")]
 pub mod server;
+
+use crate::connection::queue::SendQueueError;
+
+/// Any of the errors rak-rs returns, for applications that handle them in one place.
+///
+/// Every error converts into this with `?`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RakError {
+    /// An error of the [`Client`](crate::client::Client).
+    #[cfg(feature = "client")]
+    Client(client::ClientError),
+    /// An error of the [`Listener`](crate::server::Listener).
+    #[cfg(feature = "server")]
+    Server(server::ServerError),
+    /// An error of a [`Connection`](crate::connection::Connection).
+    Connection(connection::ConnectionError),
+    /// A payload could not be queued, see [`Connection::send()`](crate::connection::Connection::send).
+    Send(SendQueueError),
+}
+
+#[cfg(feature = "client")]
+impl From<client::ClientError> for RakError {
+    fn from(error: client::ClientError) -> Self {
+        Self::Client(error)
+    }
+}
+
+#[cfg(feature = "server")]
+impl From<server::ServerError> for RakError {
+    fn from(error: server::ServerError) -> Self {
+        Self::Server(error)
+    }
+}
+
+impl From<connection::ConnectionError> for RakError {
+    fn from(error: connection::ConnectionError) -> Self {
+        Self::Connection(error)
+    }
+}
+
+impl From<SendQueueError> for RakError {
+    fn from(error: SendQueueError) -> Self {
+        Self::Send(error)
+    }
+}
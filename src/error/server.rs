@@ -4,6 +4,10 @@
 pub enum ServerError {
     /// The server is unable to bind to the given address.
     AddrBindErr,
+    /// The server is unable to pin its socket to the network interface in
+    /// [`ConnOptions::bind_device`](crate::connection::options::ConnOptions::bind_device).
+    /// On Linux this requires `CAP_NET_RAW`.
+    BindDeviceErr,
     /// The server is already online and can not be started again.
     AlreadyOnline,
     /// The server is offline and can not send packets.
@@ -12,4 +16,8 @@ pub enum ServerError {
     Killed,
     /// The server has been closed, and can not be used again.
     Reset,
+    /// The socket of the server failed for good, with an error of this kind on every receive.
+    /// Every connection was closed with
+    /// [`DisconnectReason::SocketFailure`](crate::connection::event::DisconnectReason::SocketFailure).
+    SocketFailed(std::io::ErrorKind),
 }
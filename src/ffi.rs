@@ -0,0 +1,293 @@
+//! A C interface to the [`Client`], for applications that can't link against rust directly.
+//!
+//! The declarations are in `include/rakrs.h`, generated by cbindgen from this module. The
+//! libraries to link against, a shared and a static one, are built by the `rak-rs-ffi` crate
+//! in `ffi/`, with `cargo build --release -p rak-rs-ffi`. This crate itself stays an rlib.
+//!
+//! Everything is driven by polling, the library never calls back into the application:
+//!
+//! - [`rakrs_client_connect()`] blocks until the client connected, or failed to.
+//! - [`rakrs_client_send()`] queues a message, it goes out on the next tick.
+//! - [`rakrs_client_poll()`] never blocks, it hands out the next [`RakrsEvent`] if there is one.
+//! - [`rakrs_client_disconnect()`] closes the connection, [`rakrs_client_destroy()`] frees
+//!   the client.
+//!
+//! ## Ownership
+//!
+//! A [`RakrsClient`] is owned by the caller from [`rakrs_client_connect()`] until it is given
+//! to [`rakrs_client_destroy()`]. The payload of a [`RakrsEvent`] is allocated by the library
+//! and owned by the caller, who frees it with [`rakrs_buffer_free()`]. Buffers the caller
+//! passes in are only read during the call.
+//!
+//! A panic never unwinds into the caller, it is reported as [`RakrsStatus::Panic`] instead.
+use std::ffi::{c_char, CStr};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::time::Duration;
+
+use async_std::{future::timeout, task};
+use futures::FutureExt;
+
+use crate::connection::event::DisconnectReason;
+use crate::protocol::reliability::Reliability;
+use crate::Client;
+
+/// The protocol version used when [`RakrsOptions::protocol_version`] is 0.
+pub const RAKRS_DEFAULT_PROTOCOL_VERSION: u8 = 11;
+/// The MTU used when [`RakrsOptions::mtu`] is 0.
+pub const RAKRS_DEFAULT_MTU: u16 = 1400;
+/// The connect timeout used when [`RakrsOptions::connect_timeout_ms`] is 0.
+pub const RAKRS_DEFAULT_CONNECT_TIMEOUT_MS: u32 = 10_000;
+
+/// The result of a call.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RakrsStatus {
+    /// The call succeeded.
+    Ok = 0,
+    /// A pointer was null, the address wasn't utf-8, or the reliability unknown.
+    InvalidArgument = 1,
+    /// The server could not be reached, or the handshake failed.
+    ConnectFailed = 2,
+    /// The connect didn't complete within [`RakrsOptions::connect_timeout_ms`].
+    TimedOut = 3,
+    /// The message could not be queued, for example because it is too large.
+    SendFailed = 4,
+    /// The client is not connected.
+    NotConnected = 5,
+    /// The library panicked, the client should be destroyed.
+    Panic = 6,
+}
+
+/// How [`rakrs_client_connect()`] connects, a field left 0 takes its default.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RakrsOptions {
+    /// The RakNet protocol version, [`RAKRS_DEFAULT_PROTOCOL_VERSION`] by default.
+    pub protocol_version: u8,
+    /// The largest datagram to use, [`RAKRS_DEFAULT_MTU`] by default.
+    pub mtu: u16,
+    /// How long to wait for the connection, [`RAKRS_DEFAULT_CONNECT_TIMEOUT_MS`] by default.
+    pub connect_timeout_ms: u32,
+}
+
+/// What a [`RakrsEvent`] is about.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RakrsEventKind {
+    /// The server sent a message, it is in [`RakrsEvent::data`].
+    Message = 0,
+    /// The connection is gone, why is in [`RakrsEvent::reason`]. It is the last event.
+    Disconnected = 1,
+}
+
+/// Something that happened on the connection, handed out by [`rakrs_client_poll()`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct RakrsEvent {
+    pub kind: RakrsEventKind,
+    /// The payload of a message, null otherwise. Freed with [`rakrs_buffer_free()`].
+    pub data: *mut u8,
+    /// The length of `data`.
+    pub len: usize,
+    /// Why the connection is gone, the byte a disconnect carries on the wire,
+    /// see [`DisconnectReason`]. 0 for a message.
+    pub reason: u8,
+}
+
+/// A client connected with [`rakrs_client_connect()`], opaque to the caller.
+pub struct RakrsClient {
+    client: Client,
+    /// Whether the [`RakrsEventKind::Disconnected`] event was handed out.
+    disconnected: bool,
+}
+
+/// `value`, or `default` if it was left 0.
+fn or<T: Default + PartialEq>(value: T, default: T) -> T {
+    if value == T::default() {
+        default
+    } else {
+        value
+    }
+}
+
+/// Runs `f`, turning a panic into `fallback`.
+fn guard<T>(fallback: T, f: impl FnOnce() -> T) -> T {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or(fallback)
+}
+
+/// Connects to `addr`, a `host:port` string, and writes the client to `out`.
+///
+/// `opts` may be null for the defaults. `out` is only written on [`RakrsStatus::Ok`].
+///
+/// # Safety
+///
+/// `addr` must be a nul terminated string, `opts` null or a valid [`RakrsOptions`] and `out`
+/// valid for a write.
+#[no_mangle]
+pub unsafe extern "C" fn rakrs_client_connect(
+    addr: *const c_char,
+    opts: *const RakrsOptions,
+    out: *mut *mut RakrsClient,
+) -> RakrsStatus {
+    guard(RakrsStatus::Panic, || {
+        if addr.is_null() || out.is_null() {
+            return RakrsStatus::InvalidArgument;
+        }
+        let Ok(addr) = CStr::from_ptr(addr).to_str() else {
+            return RakrsStatus::InvalidArgument;
+        };
+        let opts = opts.as_ref().copied().unwrap_or_default();
+
+        let client = Client::new(
+            or(opts.protocol_version, RAKRS_DEFAULT_PROTOCOL_VERSION),
+            or(opts.mtu, RAKRS_DEFAULT_MTU),
+        );
+        let wait = Duration::from_millis(or(
+            opts.connect_timeout_ms,
+            RAKRS_DEFAULT_CONNECT_TIMEOUT_MS,
+        ) as u64);
+        match task::block_on(timeout(wait, client.connect(addr))) {
+            Ok(Ok(())) => {
+                *out = Box::into_raw(Box::new(RakrsClient {
+                    client,
+                    disconnected: false,
+                }));
+                RakrsStatus::Ok
+            }
+            Ok(Err(_)) => RakrsStatus::ConnectFailed,
+            Err(_) => RakrsStatus::TimedOut,
+        }
+    })
+}
+
+/// Queues `len` bytes at `data` with `reliability`, the value of a [`Reliability`] from
+/// 0 (unreliable) to 4 (reliable sequenced). Ordered messages go on channel 0.
+///
+/// # Safety
+///
+/// `client` must come from [`rakrs_client_connect()`], and `data` be valid for `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rakrs_client_send(
+    client: *mut RakrsClient,
+    data: *const u8,
+    len: usize,
+    reliability: u8,
+) -> RakrsStatus {
+    guard(RakrsStatus::Panic, || {
+        let Some(client) = client.as_ref() else {
+            return RakrsStatus::InvalidArgument;
+        };
+        if data.is_null() || reliability > Reliability::ReliableSeq as u8 {
+            return RakrsStatus::InvalidArgument;
+        }
+        let data = std::slice::from_raw_parts(data, len);
+        let reliability = Reliability::from_flags(reliability << 5);
+
+        match task::block_on(client.client.send(data, reliability, 0)) {
+            Ok(()) => RakrsStatus::Ok,
+            Err(crate::ClientError::Unavailable) | Err(crate::ClientError::NotListening) => {
+                RakrsStatus::NotConnected
+            }
+            Err(_) => RakrsStatus::SendFailed,
+        }
+    })
+}
+
+/// Writes the next event to `out_event`, returns `false` when nothing happened since the
+/// last call. Never blocks.
+///
+/// Messages are handed out before the [`RakrsEventKind::Disconnected`] that ends them, after
+/// it this only returns `false`.
+///
+/// # Safety
+///
+/// `client` must come from [`rakrs_client_connect()`], and `out_event` be valid for a write.
+#[no_mangle]
+pub unsafe extern "C" fn rakrs_client_poll(
+    client: *mut RakrsClient,
+    out_event: *mut RakrsEvent,
+) -> bool {
+    guard(false, || {
+        let (Some(client), false) = (client.as_mut(), out_event.is_null()) else {
+            return false;
+        };
+        if client.disconnected {
+            return false;
+        }
+
+        // the receive is cancellation safe, a message is only taken once it is returned.
+        if let Some(Ok(message)) = client.client.recv().now_or_never() {
+            let message = Box::into_raw(message.into_boxed_slice());
+            *out_event = RakrsEvent {
+                kind: RakrsEventKind::Message,
+                data: message as *mut u8,
+                len: message.len(),
+                reason: 0,
+            };
+            return true;
+        }
+
+        if let Some(reason) = client.client.closed().now_or_never() {
+            client.disconnected = true;
+            *out_event = RakrsEvent {
+                kind: RakrsEventKind::Disconnected,
+                data: std::ptr::null_mut(),
+                len: 0,
+                reason: reason_byte(reason),
+            };
+            return true;
+        }
+        false
+    })
+}
+
+fn reason_byte(reason: DisconnectReason) -> u8 {
+    reason.to_packet().get(1).copied().unwrap_or_default()
+}
+
+/// Tells the server the client is leaving, and closes the connection. The client still has
+/// to be destroyed.
+///
+/// # Safety
+///
+/// `client` must come from [`rakrs_client_connect()`].
+#[no_mangle]
+pub unsafe extern "C" fn rakrs_client_disconnect(client: *mut RakrsClient) -> RakrsStatus {
+    guard(RakrsStatus::Panic, || {
+        let Some(client) = client.as_ref() else {
+            return RakrsStatus::InvalidArgument;
+        };
+        task::block_on(client.client.close());
+        RakrsStatus::Ok
+    })
+}
+
+/// Frees a client, closing its connection if it is still open. Null is ignored.
+///
+/// # Safety
+///
+/// `client` must come from [`rakrs_client_connect()`], and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn rakrs_client_destroy(client: *mut RakrsClient) {
+    guard((), || {
+        if client.is_null() {
+            return;
+        }
+        let client = Box::from_raw(client);
+        task::block_on(client.client.close());
+    })
+}
+
+/// Frees the payload of a [`RakrsEvent`]. Null is ignored.
+///
+/// # Safety
+///
+/// `data` and `len` must be those of an event, and the payload not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn rakrs_buffer_free(data: *mut u8, len: usize) {
+    guard((), || {
+        if !data.is_null() {
+            drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(data, len)));
+        }
+    })
+}
@@ -19,10 +19,28 @@
 //!
 //! ```toml
 //! [dependencies]
-//! rak-rs = { version = "0.3.2", default-features = false, features = [ "async_tokio", "mcpe" ] }
+//! rak-rs = { version = "0.3.2", default-features = false, features = [ "async_tokio", "client", "server", "mcpe" ] }
 //! ```
 //!
+//! The client and the server are behind the `client` and `server` features, both enabled by default.
+//! A dedicated server can leave out the client stack, and a game the listener:
 //!
+//! ```toml
+//! [dependencies]
+//! rak-rs = { version = "0.3.2", default-features = false, features = [ "async_std", "server" ] }
+//! ```
+//!
+//!
+//!
+//! ## Public API
+//!
+//! Everything needed to run a client or a server is re-exported from the crate root:
+//! [`Client`], [`Listener`] (also named [`Server`] and [`RakNetServer`]), [`Connection`],
+//! [`ConnId`], [`ConnMeta`], [`ConnectionStats`], [`ConnOptions`] (also named [`ClientOptions`]
+//! and [`ServerOptions`]), [`RakEvent`] (also named [`ClientEvent`]), [`DisconnectReason`],
+//! [`Reliability`], [`Motd`], [`ClientError`], [`ServerError`] and [`RakError`], which any of
+//! the errors converts into. Servers are found with [`ping()`] and [`discover_lan()`].
+//! Prefer these over importing from the modules below, which may be restructured between releases.
 //!
 //! rak-rs also provides the following modules:
 //!
@@ -31,7 +49,6 @@
 //! - [`rak_rs::error`](crate::error) - A module with errors that both the Client and Server can respond with.
 //! - [`rak_rs::protocol`](crate::protocol) - A lower level implementation of RakNet, responsible for encoding and decoding packets.
 //! - [`rak_rs::server`](crate::server) - The base server implementation of RakNet.
-//! - [`rak_rs::util`](crate::util)  - General utilities used within `rak-rs`.
 //!
 //! # Client
 //!
@@ -99,6 +116,7 @@
 //! }
 //! ```
 /// A client implementation of RakNet, allowing you to connect to a RakNet server.
+#[cfg(feature = "client")]
 pub mod client;
 /// The connection implementation of RakNet, allowing you to send and receive packets.
 /// This is barebones, and you should use the client or server implementations instead, this is mainly
@@ -106,16 +124,40 @@ pub mod client;
 pub mod connection;
 /// The error implementation of RakNet, allowing you to handle errors.
 pub mod error;
+/// A C interface to the client, polled rather than calling back.
+#[cfg(feature = "ffi")]
+pub mod ffi;
 /// The packet implementation of RakNet.
 /// This is a lower level implementation responsible for serializing and deserializing packets.
 pub mod protocol;
 /// The server implementation of RakNet, allowing you to create a RakNet server.
+#[cfg(feature = "server")]
 pub mod server;
+/// A deterministic simulation of a server and its clients under a virtual clock, for debugging the reliability layer.
+#[cfg(feature = "simulation")]
+pub mod simulation;
 /// Utilties for RakNet, like epoch time.
+#[doc(hidden)]
 pub mod util;
+/// The socket options and OS errors the client and the server deal with.
+#[doc(hidden)]
+pub use util::socket;
 
+#[cfg(feature = "client")]
+pub use client::{discover_lan, ping, Client};
+pub use connection::event::{ClientEvent, DisconnectReason, RakEvent};
+pub use connection::options::{ClientOptions, ConnOptions, ServerOptions};
+pub use connection::stats::{ChannelStats, ConnectionStats};
+pub use connection::{ConnId, ConnMeta, Connection, DrainResult};
+#[cfg(feature = "client")]
+pub use error::client::ClientError;
+#[cfg(feature = "server")]
+pub use error::server::ServerError;
+pub use error::RakError;
 pub use protocol::mcpe::{self, motd::Motd};
-pub use server::Listener;
+pub use protocol::reliability::Reliability;
+#[cfg(feature = "server")]
+pub use server::{Listener, RakNetServer, Server};
 
 /// An internal module for notifying the connection of state updates.
 pub(crate) mod notify;
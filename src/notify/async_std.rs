@@ -14,6 +14,11 @@ impl Notify {
 
     /// Sends a message to all listeners.
     pub async fn notify(&self) -> bool {
+        self.notify_now()
+    }
+
+    /// Sends a message to all listeners without awaiting, for places like `Drop`.
+    pub fn notify_now(&self) -> bool {
         if let Some(sender) = &self.0 {
             sender.close();
             true
@@ -23,11 +28,9 @@ impl Notify {
     }
 
     /// Waits for a message from the main thread.
+    /// Notifying closes the channel, so an error here means we were notified.
     pub async fn wait(&self) -> bool {
-        if let Err(_) = self.1.recv().await {
-            false
-        } else {
-            true
-        }
+        let _ = self.1.recv().await;
+        true
     }
 }
@@ -22,6 +22,15 @@ impl Notify {
         }
     }
 
+    /// Sends a message to all listeners without awaiting, for places like `Drop`.
+    pub fn notify_now(&self) -> bool {
+        match self.0.try_write() {
+            Ok(mut sender) => sender.take().is_some(),
+            // whoever holds the sender is notifying already.
+            Err(_) => false,
+        }
+    }
+
     /// Waits for a message from the main thread.
     pub async fn wait(&self) -> bool {
         let mut receiver = self.1.write().await;
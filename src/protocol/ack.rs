@@ -1,27 +1,27 @@
 pub const ACK: u8 = 0xc0;
 pub const NACK: u8 = 0xa0;
 
-use std::ops::Range;
+use std::ops::RangeInclusive;
 
 use binary_util::{
     interfaces::{Reader, Writer},
-    types::u24,
     BinaryIo,
 };
 
-pub(crate) trait Ackable {
+use super::index::{unwrapped, DatagramSeq};
+use super::ranges::SequenceRanges;
+
+pub trait Ackable {
     type NackItem;
 
     /// When an ack packet is recieved.
     /// We should ack the queue
-    fn ack(&mut self, _: Ack) {}
+    fn ack(&mut self, ack: Ack);
 
     /// When an NACK packet is recieved.
     /// We should nack the queue
     /// This should return the packets that need to be resent.
-    fn nack(&mut self, _: Ack) -> Vec<Self::NackItem> {
-        todo!()
-    }
+    fn nack(&mut self, nack: Ack) -> Vec<Self::NackItem>;
 }
 
 /// An ack record.
@@ -34,15 +34,29 @@ pub enum Record {
     Range(RangeRecord) = 0,
 }
 
+impl Record {
+    /// The sequences this record covers, ranges on the wire are inclusive.
+    /// A range that ends below its start wrapped around at 24 bits, and is split where it did.
+    pub fn ranges(&self) -> Vec<RangeInclusive<u32>> {
+        match self {
+            Record::Single(single) => vec![single.sequence.get()..=single.sequence.get()],
+            Record::Range(range) => unwrapped(range.start, range.end)
+                .into_iter()
+                .map(|(first, last)| first..=last)
+                .collect(),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct SingleRecord {
-    pub sequence: u24,
+    pub sequence: DatagramSeq,
 }
 
 impl Reader<SingleRecord> for SingleRecord {
     fn read(buf: &mut binary_util::ByteReader) -> Result<SingleRecord, std::io::Error> {
         Ok(SingleRecord {
-            sequence: buf.read_u24_le()?.into(),
+            sequence: buf.read_type::<DatagramSeq>()?,
         })
     }
 }
@@ -56,15 +70,15 @@ impl Writer for SingleRecord {
 
 #[derive(Debug, Clone)]
 pub struct RangeRecord {
-    pub start: u24,
-    pub end: u24,
+    pub start: DatagramSeq,
+    pub end: DatagramSeq,
 }
 
 impl Reader<RangeRecord> for RangeRecord {
     fn read(buf: &mut binary_util::ByteReader) -> Result<RangeRecord, std::io::Error> {
         Ok(RangeRecord {
-            start: buf.read_u24_le()?.into(),
-            end: buf.read_u24_le()?.into(),
+            start: buf.read_type::<DatagramSeq>()?,
+            end: buf.read_type::<DatagramSeq>()?,
         })
     }
 }
@@ -107,72 +121,45 @@ impl Ack {
         self.id == 0xa0
     }
 
-    pub fn from_records(mut sequences: Vec<u32>, nack: bool) -> Self {
-        // there at least one record
-        if sequences.len() > 0 {
-            // these sequences may not be in order.
-            sequences.sort_unstable();
-
-            // however sometimes we need to create a range for each record if the numbers are in order
-            // this is because the client will send a range if the numbers are in order
-            let mut ack_records: Vec<Record> = Vec::new();
-            let mut current_range: Range<u32> = 0..0;
-
-            for sequence in sequences.iter() {
-                // now check if the current range is 0
-                if current_range.start == 0 && current_range.end == 0 && *sequence != 0 {
-                    // if it is, set the start and end to the current sequence
-                    current_range.start = *sequence;
-                    current_range.end = *sequence;
-                    // skip the rest of the loop
-                    continue;
-                }
-
-                // first check if the difference is 1
-                if *sequence == current_range.end + 1 {
-                    current_range.end = *sequence;
-                    // skip the rest of the loop
-                    continue;
+    /// Encodes a set of sequences as ack (or nack) records.
+    pub fn from_ranges(ranges: &SequenceRanges, nack: bool) -> Self {
+        let records = ranges
+            .iter_ranges()
+            .map(|range| {
+                if range.start() == range.end() {
+                    Record::Single(SingleRecord {
+                        sequence: (*range.start()).into(),
+                    })
                 } else {
-                    // the difference is not 1, so we need to add the current range to the ack records
-                    // we also need to reset the current range
-                    // before we do this we need to verify the range start and end are not 0
-                    if current_range.start == current_range.end {
-                        // this is a single record
-                        ack_records.push(Record::Single(SingleRecord {
-                            sequence: current_range.start.into(),
-                        }));
-                    } else {
-                        // this is a range record
-                        ack_records.push(Record::Range(RangeRecord {
-                            start: current_range.start.into(),
-                            end: current_range.end.into(),
-                        }));
-                    }
-                    current_range.start = *sequence;
-                    current_range.end = *sequence;
+                    Record::Range(RangeRecord {
+                        start: (*range.start()).into(),
+                        end: (*range.end()).into(),
+                    })
                 }
-            }
-
-            // verify last range is not equal to end
-            if current_range.start == current_range.end {
-                // this is a single record
-                ack_records.push(Record::Single(SingleRecord {
-                    sequence: current_range.start.into(),
-                }));
-            } else {
-                // this is a range record
-                ack_records.push(Record::Range(RangeRecord {
-                    start: current_range.start.into(),
-                    end: current_range.end.into(),
-                }));
-            }
-
-            Self::new(ack_records.len() as u16, nack, ack_records)
-        } else {
-            // There is only one record, we can just add it as a single record
-            Self::new(0_u16, nack, Vec::new())
+            })
+            .collect::<Vec<Record>>();
+
+        Self::new(records.len() as u16, nack, records)
+    }
+
+    /// Decodes the records of this packet into a set of sequences.
+    #[allow(dead_code)]
+    pub fn ranges(&self) -> SequenceRanges {
+        let mut ranges = SequenceRanges::new();
+        for range in self.records.iter().flat_map(Record::ranges) {
+            ranges.insert_range(*range.start(), *range.end());
         }
+        ranges
+    }
+
+    /// The ranges of sequences the records of this packet cover, one per record, or two for a
+    /// record that wraps around.
+    /// A single record can cover millions of sequences, look them up by range rather than one by one.
+    pub fn sequence_ranges(&self) -> impl Iterator<Item = RangeInclusive<DatagramSeq>> + '_ {
+        self.records
+            .iter()
+            .flat_map(Record::ranges)
+            .map(|range| DatagramSeq::new(*range.start())..=DatagramSeq::new(*range.end()))
     }
 }
 
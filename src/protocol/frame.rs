@@ -3,22 +3,25 @@ use binary_util::{
     BinaryIo,
 };
 
+use super::index::{DatagramSeq, MessageIndex, OrderIndex, SequenceIndex, SplitId};
+
 /// The information for the given fragment.
 /// This is used to determine how to reassemble the frame.
 #[derive(Debug, Clone, BinaryIo)]
 pub struct FragmentMeta {
     pub(crate) size: u32,
-    pub(crate) id: u16,
+    pub(crate) id: SplitId,
     pub(crate) index: u32,
 }
 
 impl FragmentMeta {
     /// Creates a new fragment meta with the given size, id, and index.
-    pub fn new(size: u32, id: u16, index: u32) -> Self {
+    pub fn new(size: u32, id: SplitId, index: u32) -> Self {
         Self { size, id, index }
     }
 }
 
+use crate::error::decode::DecodeErrorKind;
 use crate::rakrs_debug;
 
 use super::reliability::Reliability;
@@ -27,7 +30,7 @@ use super::reliability::Reliability;
 /// They are used to send packets to the connection in a reliable way.
 #[derive(Debug, Clone)]
 pub struct FramePacket {
-    pub sequence: u32,
+    pub sequence: DatagramSeq,
     pub frames: Vec<Frame>,
     pub reliability: Reliability,
 }
@@ -36,7 +39,7 @@ impl FramePacket {
     /// Creates an empty frame packet.
     pub fn new() -> Self {
         Self {
-            sequence: 0,
+            sequence: DatagramSeq::default(),
             frames: Vec::new(),
             reliability: Reliability::ReliableOrd,
         }
@@ -46,19 +49,20 @@ impl FramePacket {
 impl Reader<FramePacket> for FramePacket {
     fn read(buf: &mut binary_util::ByteReader) -> Result<FramePacket, std::io::Error> {
         // FRAME PACKET HEADER
-        let id = buf.read_u8()?;
-        match id {
-            0x80..=0x8d => {}
-            _ => {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::InvalidData,
-                    "Invalid Frame Packet ID",
-                ))
+        match buf.as_slice().first() {
+            Some(0x80..=0x8f) | None => {}
+            Some(&value) => {
+                return Err(DecodeErrorKind::InvalidEnumValue {
+                    field: "frame packet id",
+                    value,
+                }
+                .into())
             }
         }
+        buf.read_u8()?;
         let mut frames: Vec<Frame> = Vec::new();
 
-        let sequence = buf.read_u24_le()?;
+        let sequence = buf.read_type::<DatagramSeq>()?;
 
         loop {
             let frame_pos = buf.read_type::<Frame>();
@@ -100,15 +104,15 @@ pub struct Frame {
     /// This is sized to 24 bits internally, so any number here must be within that range.
     pub size: u16,
     /// The Reliable index of the frame (if reliable)
-    pub reliable_index: Option<u32>,
+    pub reliable_index: Option<MessageIndex>,
     /// The sequenced index of the frame (if sequenced)
     /// This is used to determine the position in frame list.
-    pub sequence_index: Option<u32>,
+    pub sequence_index: Option<SequenceIndex>,
     /// The order index of the frame (if ordered)
     /// This is used to determine the position in frame list,
     /// This is different from the sequence index in that it is
     /// used more to sequence packets in a specific manner.
-    pub order_index: Option<u32>,
+    pub order_index: Option<OrderIndex>,
     /// The order channel of the frame (if ordered)
     /// This is used to store order information for the frame.
     pub order_channel: Option<u8>,
@@ -168,12 +172,76 @@ impl Frame {
         self.reliability.is_sequenced()
     }
 
+    /// The length of the header this frame is written with, everything but its body.
+    pub fn header_len(&self) -> usize {
+        // flags and the body length in bits.
+        let mut len = 3;
+        if self.reliability.is_reliable() {
+            len += 3;
+        }
+        if self.reliability.is_sequenced() {
+            len += 3;
+        }
+        if self.reliability.is_sequenced_or_ordered() {
+            len += 4;
+        }
+        if self.fragment_meta.is_some() {
+            len += 10;
+        }
+        len
+    }
+
     pub fn with_meta(mut self, meta: FragmentMeta) -> Self {
         self.fragment_meta = Some(meta);
         self
     }
 }
 
+/// A borrowed view of a frame received from one peer, to be forwarded to another
+/// without decoding or copying its payload first.
+///
+/// Only what survives forwarding is kept, the indexes of the frame belong to the sequence
+/// spaces of the peer it came from, and are issued anew by the connection it is sent on.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameRef<'a> {
+    /// The reliability the frame was sent with.
+    pub reliability: Reliability,
+    /// The order channel of the frame, if it is ordered or sequenced.
+    pub order_channel: Option<u8>,
+    /// Where this frame fits in its split message, if it is a fragment.
+    pub fragment_meta: Option<&'a FragmentMeta>,
+    /// The payload of the frame.
+    pub body: &'a [u8],
+}
+
+impl<'a> FrameRef<'a> {
+    /// Creates a view of an unfragmented payload.
+    pub fn new(reliability: Reliability, order_channel: Option<u8>, body: &'a [u8]) -> Self {
+        Self {
+            reliability,
+            order_channel,
+            fragment_meta: None,
+            body,
+        }
+    }
+
+    /// Whether or not the frame is a fragment of a split message.
+    pub fn is_fragmented(&self) -> bool {
+        self.fragment_meta.is_some()
+    }
+}
+
+impl<'a> From<&'a Frame> for FrameRef<'a> {
+    fn from(frame: &'a Frame) -> Self {
+        Self {
+            reliability: frame.reliability,
+            order_channel: frame.order_channel,
+            fragment_meta: frame.fragment_meta.as_ref(),
+            body: &frame.body,
+        }
+    }
+}
+
 impl Reader<Frame> for Frame {
     fn read(buf: &mut binary_util::ByteReader) -> Result<Frame, std::io::Error> {
         let mut frame = Frame::init();
@@ -188,15 +256,16 @@ impl Reader<Frame> for Frame {
         }
 
         if frame.reliability.is_reliable() {
-            frame.reliable_index = Some(buf.read_u24_le()?);
+            frame.reliable_index = Some(buf.read_type::<MessageIndex>()?);
         }
 
         if frame.reliability.is_sequenced() {
-            frame.sequence_index = Some(buf.read_u24_le()?);
+            frame.sequence_index = Some(buf.read_type::<SequenceIndex>()?);
         }
 
-        if frame.reliability.is_ordered() {
-            frame.order_index = Some(buf.read_u24_le()?);
+        // sequenced frames are also ordered against the other frames on their channel.
+        if frame.reliability.is_sequenced_or_ordered() {
+            frame.order_index = Some(buf.read_type::<OrderIndex>()?);
             frame.order_channel = Some(buf.read_u8()?);
         }
 
@@ -228,6 +297,11 @@ impl Reader<Frame> for Frame {
 impl Writer for Frame {
     fn write(&self, buf: &mut binary_util::ByteWriter) -> Result<(), std::io::Error> {
         let mut flags = self.reliability.to_flags();
+        debug_assert_eq!(
+            Reliability::from_flags(flags),
+            self.reliability,
+            "the frame flags don't decode to the reliability they were written from"
+        );
 
         // check whether or not this frame is fragmented, if it is, set the fragment flag
         if self.fragment_meta.is_some() {
@@ -238,15 +312,15 @@ impl Writer for Frame {
         buf.write_u16(self.size * 8)?;
 
         if self.reliability.is_reliable() {
-            buf.write_u24_le(self.reliable_index.unwrap_or(0))?;
+            buf.write_u24_le(self.reliable_index.unwrap_or_default())?;
         }
 
         if self.reliability.is_sequenced() {
-            buf.write_u24_le(self.sequence_index.unwrap_or(0))?;
+            buf.write_u24_le(self.sequence_index.unwrap_or_default())?;
         }
 
-        if self.reliability.is_ordered() {
-            buf.write_u24_le(self.order_index.unwrap_or(0))?;
+        if self.reliability.is_sequenced_or_ordered() {
+            buf.write_u24_le(self.order_index.unwrap_or_default())?;
             buf.write_u8(self.order_channel.unwrap_or(0))?;
         }
 
@@ -0,0 +1,244 @@
+//! Distinct index types for the different counter spaces used within RakNet.
+//!
+//! RakNet has several 24-bit counters that all look like a `u32`, but are never interchangeable:
+//! - [`DatagramSeq`]: The sequence number of a [`FramePacket`] (datagram), this is what ACKs and NACKs refer to.
+//! - [`MessageIndex`]: The reliable index of a [`Frame`], used to deduplicate reliable messages.
+//! - [`SequenceIndex`]: The sequenced index of a [`Frame`] on an order channel.
+//! - [`OrderIndex`]: The order index of a [`Frame`] on an order channel.
+//! - [`SplitId`]: The id of a fragmented (split) message.
+//!
+//! Passing one of these where another is expected is a type error, if you really need
+//! the underlying value you can use `get()`.
+//!
+//! [`FramePacket`]: crate::protocol::frame::FramePacket
+//! [`Frame`]: crate::protocol::frame::Frame
+use binary_util::interfaces::{Reader, Writer};
+use binary_util::io::{ByteReader, ByteWriter};
+use binary_util::types::u24;
+
+use crate::util::SafeGenerator;
+
+/// The largest value a 24-bit index can hold.
+pub const U24_MAX: u32 = 0x00ff_ffff;
+
+/// The datagram sequences from `first` to `last`, split where they wrap around at 24 bits.
+pub fn unwrapped(first: DatagramSeq, last: DatagramSeq) -> Vec<(u32, u32)> {
+    if last.get() < first.get() {
+        vec![(first.get(), U24_MAX), (0, last.get())]
+    } else {
+        vec![(first.get(), last.get())]
+    }
+}
+
+/// The serial number arithmetic of the 24-bit indexes, for code that works with any of them
+/// like the [`ReliableWindow`].
+///
+/// [`ReliableWindow`]: crate::connection::controller::window::ReliableWindow
+pub trait WrappingIndex: Copy + Eq + std::hash::Hash {
+    /// Adds `n` to this index, wrapping around at 24 bits.
+    fn wrapping_add(&self, n: u32) -> Self;
+    /// Subtracts `n` from this index, wrapping around at 24 bits.
+    fn wrapping_sub(&self, n: u32) -> Self;
+    /// How far `other` is ahead of this index, wrapping around at 24 bits.
+    fn distance_to(&self, other: Self) -> u32;
+    /// Whether this index comes before `other`, in serial number arithmetic.
+    fn is_before(&self, other: Self) -> bool;
+}
+
+macro_rules! u24_index {
+    ($(#[$meta: meta])* $name: ident) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub struct $name(u32);
+
+        impl $name {
+            /// Creates a new index, the value is truncated to 24 bits.
+            pub const fn new(value: u32) -> Self {
+                Self(value & U24_MAX)
+            }
+
+            /// Returns the raw value of this index.
+            pub const fn get(&self) -> u32 {
+                self.0
+            }
+
+            /// Adds `n` to this index, wrapping around at 24 bits.
+            pub const fn wrapping_add(&self, n: u32) -> Self {
+                Self::new(self.0.wrapping_add(n))
+            }
+
+            /// Subtracts `n` from this index, wrapping around at 24 bits.
+            pub const fn wrapping_sub(&self, n: u32) -> Self {
+                Self::new(self.0.wrapping_sub(n))
+            }
+
+            /// How far `other` is ahead of this index, wrapping around at 24 bits.
+            pub const fn distance_to(&self, other: Self) -> u32 {
+                other.0.wrapping_sub(self.0) & U24_MAX
+            }
+
+            /// Whether this index comes before `other`, in serial number arithmetic.
+            /// An index is before the ones less than half the index space ahead of it,
+            /// so this holds across the wrap from [`U24_MAX`] to zero.
+            pub const fn is_before(&self, other: Self) -> bool {
+                let distance = self.distance_to(other);
+                distance != 0 && distance <= U24_MAX / 2
+            }
+        }
+
+        impl WrappingIndex for $name {
+            fn wrapping_add(&self, n: u32) -> Self {
+                $name::wrapping_add(self, n)
+            }
+
+            fn wrapping_sub(&self, n: u32) -> Self {
+                $name::wrapping_sub(self, n)
+            }
+
+            fn distance_to(&self, other: Self) -> u32 {
+                $name::distance_to(self, other)
+            }
+
+            fn is_before(&self, other: Self) -> bool {
+                $name::is_before(self, other)
+            }
+        }
+
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "{}#{}", stringify!($name), self.0)
+            }
+        }
+
+        impl From<u32> for $name {
+            fn from(value: u32) -> Self {
+                Self::new(value)
+            }
+        }
+
+        impl From<$name> for u32 {
+            fn from(index: $name) -> Self {
+                index.0
+            }
+        }
+
+        impl From<u24> for $name {
+            fn from(value: u24) -> Self {
+                Self::new(value.0)
+            }
+        }
+
+        impl From<$name> for u24 {
+            fn from(index: $name) -> Self {
+                u24(index.0)
+            }
+        }
+
+        impl Reader<$name> for $name {
+            fn read(buf: &mut ByteReader) -> Result<$name, std::io::Error> {
+                Ok(Self::new(buf.read_u24_le()?))
+            }
+        }
+
+        impl Writer for $name {
+            fn write(&self, buf: &mut ByteWriter) -> Result<(), std::io::Error> {
+                buf.write_u24_le(self.0)
+            }
+        }
+
+        // not every index is handed out by a generator.
+        #[allow(dead_code)]
+        impl SafeGenerator<$name> {
+            /// Hands out the next index, starting at zero like the peer expects.
+            pub fn next_index(&mut self) -> $name {
+                let current = self.sequence;
+                self.sequence = self.sequence.wrapping_add(1);
+                current
+            }
+
+            /// The index that will be handed out next.
+            pub fn get(&self) -> $name {
+                self.sequence
+            }
+        }
+    };
+}
+
+u24_index!(
+    /// The sequence number of a datagram ([`FramePacket`]), acknowledged by ACK and NACK records.
+    ///
+    /// [`FramePacket`]: crate::protocol::frame::FramePacket
+    DatagramSeq
+);
+
+u24_index!(
+    /// The reliable message index of a [`Frame`].
+    ///
+    /// [`Frame`]: crate::protocol::frame::Frame
+    MessageIndex
+);
+
+u24_index!(
+    /// The sequenced index of a [`Frame`] within its order channel.
+    ///
+    /// [`Frame`]: crate::protocol::frame::Frame
+    SequenceIndex
+);
+
+u24_index!(
+    /// The order index of a [`Frame`] within its order channel.
+    ///
+    /// [`Frame`]: crate::protocol::frame::Frame
+    OrderIndex
+);
+
+/// The id of a split (fragmented) message.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SplitId(u16);
+
+impl SplitId {
+    /// Creates a new split id.
+    pub const fn new(value: u16) -> Self {
+        Self(value)
+    }
+
+    /// Returns the raw value of this id.
+    pub const fn get(&self) -> u16 {
+        self.0
+    }
+
+    /// Adds `n` to this id, wrapping around at 16 bits.
+    pub const fn wrapping_add(&self, n: u16) -> Self {
+        Self(self.0.wrapping_add(n))
+    }
+}
+
+impl std::fmt::Display for SplitId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SplitId#{}", self.0)
+    }
+}
+
+impl From<u16> for SplitId {
+    fn from(value: u16) -> Self {
+        Self(value)
+    }
+}
+
+impl From<SplitId> for u16 {
+    fn from(id: SplitId) -> Self {
+        id.0
+    }
+}
+
+impl Reader<SplitId> for SplitId {
+    fn read(buf: &mut ByteReader) -> Result<SplitId, std::io::Error> {
+        Ok(Self(buf.read_u16()?))
+    }
+}
+
+impl Writer for SplitId {
+    fn write(&self, buf: &mut ByteWriter) -> Result<(), std::io::Error> {
+        buf.write_u16(self.0)
+    }
+}
@@ -1,6 +1,8 @@
 use binary_util::interfaces::{Reader, Writer};
 use binary_util::io::{ByteReader, ByteWriter};
 
+use crate::error::decode::DecodeErrorKind;
+
 /// A unique identifier recoginzing the client as offline.
 pub(crate) const MAGIC: [u8; 16] = [
     0x00, 0xff, 0xff, 0x0, 0xfe, 0xfe, 0xfe, 0xfe, 0xfd, 0xfd, 0xfd, 0xfd, 0x12, 0x34, 0x56, 0x78,
@@ -19,16 +21,21 @@ impl Magic {
 
 impl Reader<Magic> for Magic {
     fn read(buf: &mut ByteReader) -> Result<Magic, std::io::Error> {
-        let mut magic = [0u8; 16];
-        buf.read(&mut magic)?;
-
-        if magic != MAGIC {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Invalid magic",
-            ));
+        // nothing is taken from a buffer without the magic, so an error points at its start.
+        let rest = buf.as_slice();
+        if rest.len() < MAGIC.len() {
+            return Err(DecodeErrorKind::UnexpectedEof {
+                needed: MAGIC.len(),
+                remaining: rest.len(),
+            }
+            .into());
+        }
+        if rest[..MAGIC.len()] != MAGIC {
+            return Err(DecodeErrorKind::InvalidMagic.into());
         }
 
+        let mut magic = [0u8; 16];
+        buf.read(&mut magic)?;
         Ok(Magic)
     }
 }
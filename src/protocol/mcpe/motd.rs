@@ -35,7 +35,10 @@ impl std::fmt::Display for Gamemode {
 
 /// Protocol wise, motd is just a string
 /// However we're using this struct to represent the motd
-#[derive(Debug, Clone)]
+///
+/// The fields are separated by semicolons, a semicolon within a string field is escaped
+/// with a backslash when written, as is a backslash itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Motd {
     /// The name of the server
     pub name: String,
@@ -51,11 +54,18 @@ pub struct Motd {
     pub gamemode: Gamemode,
     /// The server's GUID
     pub server_guid: u64,
+    /// The second line of the motd, this is the level name and is shown under the name
+    pub sub_motd: String,
     /// The server's port
     pub port: String,
     /// The IPv6 port
     // TODO: Implement this
     pub ipv6_port: String,
+    /// Whether the server is limited on Nintendo Switch, `None` if the field is neither
+    /// `0` (limited) nor `1`
+    pub nintendo_limited: Option<bool>,
+    /// Fields some server software appends after the known ones, these are written verbatim
+    pub extra_fields: Vec<String>,
 }
 
 impl Motd {
@@ -68,34 +78,119 @@ impl Motd {
             gamemode: Gamemode::Survival,
             version: "1.18.0".into(),
             server_guid,
+            sub_motd: "Netrex".into(),
             port: port.into(),
             ipv6_port: "19133".into(),
+            nintendo_limited: Some(false),
+            extra_fields: Vec::new(),
         }
     }
 
     /// Takes the Motd and parses it into a valid MCPE
     /// MOTD buffer.
     pub fn write(&self) -> String {
-        let props: Vec<String> = vec![
+        let mut props: Vec<String> = vec![
             "MCPE".into(),
-            self.name.clone(),
+            escape(&self.name),
             self.protocol.to_string(),
-            self.version.clone(),
+            escape(&self.version),
             self.player_count.to_string(),
             self.player_max.to_string(),
             self.server_guid.to_string(),
-            "Netrex".to_string(),
+            escape(&self.sub_motd),
             self.gamemode.as_str().to_string(),
-            "1".to_string(),
-            // TODO: Figure out why this is not working
-            // self.gamemode.to_string(),
-            self.port.to_string(),
-            self.ipv6_port.to_string(),
+            match self.nintendo_limited {
+                Some(true) => "0".into(),
+                Some(false) => "1".into(),
+                None => String::new(),
+            },
+            escape(&self.port),
+            escape(&self.ipv6_port),
         ];
-        props.join(";").into()
+        props.extend(self.extra_fields.iter().map(|field| escape(field)));
+
+        props.join(";")
+    }
+
+    /// Parses a MCPE MOTD string, this is the inverse of [`Motd::write()`].
+    ///
+    /// Fields after the known ones are kept in [`Motd::extra_fields`].
+    pub fn parse(motd: &str) -> Result<Motd, std::io::Error> {
+        let parts = split(motd);
+
+        macro_rules! field {
+            ($index: expr, $name: literal) => {
+                parts.get($index).ok_or(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    concat!("Invalid motd ", $name),
+                ))?
+            };
+            ($index: expr, $name: literal, $ty: ty) => {
+                field!($index, $name).parse::<$ty>().map_err(|_| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        concat!("Invalid motd ", $name),
+                    )
+                })?
+            };
+        }
+
+        let gamemode = match field!(8, "gamemode").as_str() {
+            "Survival" | "0" => Gamemode::Survival,
+            "Creative" | "1" => Gamemode::Creative,
+            "Adventure" | "2" => Gamemode::Adventure,
+            "Spectator" | "3" => Gamemode::Spectator,
+            _ => Gamemode::Survival,
+        };
+
+        let nintendo_limited = match field!(9, "nintendo limited").as_str() {
+            "0" => Some(true),
+            "1" => Some(false),
+            _ => None,
+        };
+
+        Ok(Motd {
+            name: field!(1, "name").clone(),
+            protocol: field!(2, "protocol", u16),
+            version: field!(3, "version").clone(),
+            player_count: field!(4, "player count", u32),
+            player_max: field!(5, "player max", u32),
+            server_guid: field!(6, "server guid", u64),
+            sub_motd: field!(7, "sub motd").clone(),
+            gamemode,
+            port: field!(10, "port").clone(),
+            ipv6_port: field!(11, "ipv6 port").clone(),
+            nintendo_limited,
+            extra_fields: parts.iter().skip(12).cloned().collect(),
+        })
     }
 }
 
+/// Escapes the separators within a field.
+fn escape(field: &str) -> String {
+    field.replace('\\', "\\\\").replace(';', "\\;")
+}
+
+/// Splits a motd on the separators that are not escaped, unescaping every field.
+fn split(motd: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut chars = motd.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(escaped) => current.push(escaped),
+                None => current.push(c),
+            },
+            ';' => parts.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    parts.push(current);
+    parts
+}
+
 impl Reader<Motd> for Motd {
     fn read(buf: &mut ByteReader) -> Result<Motd, std::io::Error> {
         let str_len = buf.read_u16()?;
@@ -103,106 +198,11 @@ impl Reader<Motd> for Motd {
 
         buf.read(&mut str_buf)?;
 
-        let motd = String::from_utf8(str_buf).unwrap();
-
-        let parts = motd
-            .split(";")
-            .map(|c| c.to_string())
-            .collect::<Vec<String>>();
-
-        let name = parts
-            .get(1)
-            .ok_or(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Invalid motd name",
-            ))?
-            .clone();
-
-        let protocol = parts
-            .get(2)
-            .ok_or(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Invalid motd protocol",
-            ))?
-            .clone();
-
-        let version = parts
-            .get(3)
-            .ok_or(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Invalid motd version",
-            ))?
-            .clone();
-
-        let player_count = parts
-            .get(4)
-            .ok_or(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Invalid motd player count",
-            ))?
-            .clone();
-
-        let player_max = parts
-            .get(5)
-            .ok_or(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Invalid motd player max",
-            ))?
-            .clone();
-
-        let server_guid = parts
-            .get(6)
-            .ok_or(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Invalid motd server guid",
-            ))?
-            .clone();
-
-        let gamemode = parts
-            .get(8)
-            .ok_or(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Invalid motd gamemode",
-            ))?
-            .clone();
-
-        let port = parts
-            .get(10)
-            .ok_or(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Invalid motd port",
-            ))?
-            .clone();
-
-        let ipv6_port = parts
-            .get(11)
-            .ok_or(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Invalid motd ipv6 port",
-            ))?
-            .clone();
+        let motd = String::from_utf8(str_buf).map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid motd encoding")
+        })?;
 
-        Ok(Motd {
-            name,
-            protocol: protocol.as_str().parse().unwrap(),
-            version,
-            player_count: player_count.parse().unwrap(),
-            player_max: player_max.parse().unwrap(),
-            server_guid: server_guid.parse().unwrap(),
-            gamemode: match gamemode
-                .as_str()
-                .parse::<u8>()
-                .expect("Gamemode is not a byte")
-            {
-                0 => Gamemode::Survival,
-                1 => Gamemode::Creative,
-                2 => Gamemode::Adventure,
-                3 => Gamemode::Spectator,
-                _ => Gamemode::Survival,
-            },
-            port,
-            ipv6_port,
-        })
+        Motd::parse(&motd)
     }
 }
 
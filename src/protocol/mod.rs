@@ -25,13 +25,15 @@
 //! ```
 /// This is an internal module that contains the logic to implement the Ack system within
 /// RakNet.
-pub(crate) mod ack;
+pub mod ack;
 /// This is an internal module that contains the logic to implement the frame system within
 /// RakNet. This is also called the "Datagram" or "Encapsulated" packet in different implementations.
 ///
 /// You can find the original implementation from RakNet [here](https://github.com/facebookarchive/RakNet/blob/1a169895a900c9fc4841c556e16514182b75faf8/Source/ReliabilityLayer.cpp#L110-L231)
 // pub(crate) mod frame;
 pub mod frame;
+/// Distinct types for each of the sequence and index counters used within RakNet.
+pub mod index;
 /// This is the constant added to all offline packets to identify them as RakNet packets.
 pub(crate) mod magic;
 /// This module contains the MCPE specific packets that are used within RakNet, this is guarded
@@ -44,6 +46,10 @@ pub(crate) mod magic;
 /// ```
 pub mod mcpe;
 pub mod packet;
+/// Fields RakNet writes differently from `binary_util`, such as addresses.
+pub mod primitives;
+/// A compact set of sequence numbers, used for acknowledgements and gap reporting.
+pub mod ranges;
 pub mod reliability;
 
 pub use magic::*;
@@ -51,7 +57,9 @@ pub use magic::*;
 /// The maximum amount of fragments that can be sent within a single frame.
 /// This constant is used to prevent a client from sending too many fragments,
 /// or from bad actors from sending too many fragments.
-pub const MAX_FRAGS: u32 = 1024;
+///
+/// This allows payloads of roughly 10 MB at the default MTU of 1400.
+pub const MAX_FRAGS: u32 = 8192;
 /// The maximum amount of channels that can be used on a single connection.
 /// This is a raknet limitation, and is not configurable.
 pub const MAX_ORD_CHANS: u8 = 32;
@@ -60,8 +68,14 @@ pub const MAX_ORD_CHANS: u8 = 32;
 pub const RAKNET_HEADER_FRAME_OVERHEAD: u16 = 20 + 8 + 8 + 4 + 20;
 /// IP Header + UDP Header + RakNet Header
 pub const RAKNET_HEADER_OVERHEAD: u16 = 20 + 8 + 8;
+/// IP Header + UDP Header, an offline packet padded to the MTU is this much smaller than it.
+pub const UDP_HEADER_OVERHEAD: u16 = 20 + 8;
 
 /// The maximum possible amount of bytes that can be sent within a single frame.
 pub const MTU_MAX: u16 = 2400;
 /// The minimum possible amount of bytes that can be sent within a single frame.
 pub const MTU_MIN: u16 = 400;
+
+/// The MTU sizes a connection steps through when discovering or re-probing its MTU,
+/// from smallest to largest.
+pub const MTU_LADDER: [u16; 5] = [576, 1200, 1400, 1492, 1506];
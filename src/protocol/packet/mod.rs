@@ -3,6 +3,11 @@
 //! - [`offline`]: Any packet that is not sent within a [`Frame`].
 //! - [`online`]: Any packet considered to be online, which is sent within a [`Frame`].
 //!
+//! Only [`RakPacket`], [`OfflinePacket`] and [`OnlinePacket`] read and write the packet id,
+//! which they dispatch on. The packets themselves never see it: their reader starts right
+//! after the id and their writer leaves it out. A packet that is expected on its own is read
+//! with [`decode_packet()`], which checks its id first.
+//!
 //! [`offline`]: crate::protocol::packet::offline
 //! [`online`]: crate::protocol::packet::online
 // /// Handlers for both online & offline packets!
@@ -14,6 +19,9 @@ pub mod online;
 
 use binary_util::interfaces::{Reader, Writer};
 
+use crate::error::decode::{DecodeError, DecodeErrorKind};
+use crate::protocol::primitives::decode;
+
 use self::offline::OfflinePacket;
 use self::online::OnlinePacket;
 
@@ -28,7 +36,71 @@ pub enum RakPacket {
     Online(OnlinePacket),
 }
 
+/// The id a packet is sent with, the byte [`OfflinePacket`] or [`OnlinePacket`] dispatch on.
+pub trait PacketId {
+    const ID: u8;
+}
+
+/// Reads a `P` from a whole datagram or message, id included.
+///
+/// Fails with an [`InvalidEnumValue`] at offset 0 if the buffer starts with the id of
+/// another packet, the offsets of any other error count the id.
+///
+/// ```rust
+/// use binary_util::interfaces::Writer;
+/// use rak_rs::protocol::packet::decode_packet;
+/// use rak_rs::protocol::packet::offline::{IncompatibleProtocolVersion, OpenConnectReply};
+/// use rak_rs::protocol::packet::RakPacket;
+/// use rak_rs::protocol::Magic;
+///
+/// let reply = RakPacket::from(IncompatibleProtocolVersion {
+///     protocol: 11,
+///     magic: Magic::new(),
+///     server_id: 7,
+/// })
+/// .write_to_bytes()
+/// .unwrap();
+///
+/// assert_eq!(decode_packet::<IncompatibleProtocolVersion>(reply.as_slice()).unwrap().server_id, 7);
+/// assert!(decode_packet::<OpenConnectReply>(reply.as_slice()).unwrap_err().is_unknown_packet());
+/// ```
+///
+/// [`InvalidEnumValue`]: DecodeErrorKind::InvalidEnumValue
+pub fn decode_packet<P: PacketId + Reader<P>>(buffer: &[u8]) -> Result<P, DecodeError> {
+    match buffer.first() {
+        Some(&id) if id == P::ID => decode::<P>(&buffer[1..]).map_err(|error| DecodeError {
+            packet_id: Some(id),
+            offset: error.offset + 1,
+            kind: error.kind,
+        }),
+        Some(&id) => Err(DecodeError {
+            packet_id: Some(id),
+            offset: 0,
+            kind: DecodeErrorKind::InvalidEnumValue {
+                field: "packet id",
+                value: id,
+            },
+        }),
+        None => Err(DecodeError {
+            packet_id: None,
+            offset: 0,
+            kind: DecodeErrorKind::UnexpectedEof {
+                needed: 1,
+                remaining: 0,
+            },
+        }),
+    }
+}
+
 impl RakPacket {
+    /// The id this packet is sent with.
+    pub fn id(&self) -> u8 {
+        match self {
+            RakPacket::Offline(packet) => packet.id(),
+            RakPacket::Online(packet) => packet.id(),
+        }
+    }
+
     pub fn is_online(&self) -> bool {
         match self {
             RakPacket::Online(_) => true,
@@ -62,20 +134,52 @@ impl Writer for RakPacket {
 
 impl Reader<RakPacket> for RakPacket {
     fn read(buf: &mut binary_util::ByteReader) -> Result<RakPacket, std::io::Error> {
-        if let Ok(packet) = OfflinePacket::read(buf) {
-            return Ok(RakPacket::Offline(packet));
+        // a failed read still consumes the id, so every attempt needs its own reader.
+        // the error of a known id is kept, with the reader where it broke.
+        let id = buf.as_slice().first().copied();
+        let mut offline = buf.clone();
+        match OfflinePacket::read(&mut offline) {
+            Ok(packet) => {
+                debug_assert_eq!(id, Some(packet.id()), "dispatched to the wrong packet");
+                *buf = offline;
+                return Ok(RakPacket::Offline(packet));
+            }
+            Err(e) if !is_unknown_id(&e) => {
+                *buf = offline;
+                return Err(e);
+            }
+            Err(_) => {}
         }
-        if let Ok(packet) = OnlinePacket::read(buf) {
-            return Ok(RakPacket::Online(packet));
+
+        let mut online = buf.clone();
+        match OnlinePacket::read(&mut online) {
+            Ok(packet) => {
+                debug_assert_eq!(id, Some(packet.id()), "dispatched to the wrong packet");
+                *buf = online;
+                return Ok(RakPacket::Online(packet));
+            }
+            Err(e) if !is_unknown_id(&e) => {
+                *buf = online;
+                return Err(e);
+            }
+            Err(_) => {}
         }
 
-        Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            "Invalid packet",
-        ))
+        // both reads got as far as the id, an empty buffer would have stopped the first.
+        Err(DecodeErrorKind::InvalidEnumValue {
+            field: "packet id",
+            value: buf.as_slice()[0],
+        }
+        .into())
     }
 }
 
+/// Whether `error` is the one binary_util derives for an id none of the variants have.
+fn is_unknown_id(error: &std::io::Error) -> bool {
+    error.kind() == std::io::ErrorKind::InvalidData
+        && error.to_string() == "Invalid enum discriminant."
+}
+
 impl From<OfflinePacket> for RakPacket {
     fn from(packet: OfflinePacket) -> Self {
         RakPacket::Offline(packet)
@@ -178,3 +282,15 @@ macro_rules! register_packets {
         )*
     };
 }
+
+/// Implements [`PacketId`] for every packet, with the id it has in its packet enum.
+macro_rules! packet_ids {
+    ($($packet: ident = $id: expr),*) => {
+        $(
+            impl PacketId for $packet {
+                const ID: u8 = $id;
+            }
+        )*
+    };
+}
+pub(crate) use packet_ids;
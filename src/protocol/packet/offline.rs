@@ -12,11 +12,13 @@
 //! the server id, the client id, the mtu size, etc, to prepare for the connection handshake.
 use std::net::SocketAddr;
 
-use super::RakPacket;
+use super::online::Capabilities;
+use super::{packet_ids, PacketId, RakPacket};
 #[cfg(feature = "mcpe")]
 pub use crate::protocol::mcpe::UnconnectedPong;
+use crate::protocol::primitives::RakAddress;
 use crate::protocol::Magic;
-use crate::protocol::RAKNET_HEADER_FRAME_OVERHEAD;
+use crate::protocol::UDP_HEADER_OVERHEAD;
 use crate::register_packets;
 
 use binary_util::interfaces::{Reader, Writer};
@@ -39,6 +41,31 @@ pub enum OfflinePacket {
     IncompatibleProtocolVersion(IncompatibleProtocolVersion) = 0x19,
 }
 
+impl OfflinePacket {
+    /// The id this packet is sent with.
+    pub fn id(&self) -> u8 {
+        match self {
+            Self::UnconnectedPing(_) => UnconnectedPing::ID,
+            Self::UnconnectedPong(_) => UnconnectedPong::ID,
+            Self::OpenConnectRequest(_) => OpenConnectRequest::ID,
+            Self::OpenConnectReply(_) => OpenConnectReply::ID,
+            Self::SessionInfoRequest(_) => SessionInfoRequest::ID,
+            Self::SessionInfoReply(_) => SessionInfoReply::ID,
+            Self::IncompatibleProtocolVersion(_) => IncompatibleProtocolVersion::ID,
+        }
+    }
+}
+
+packet_ids! {
+    UnconnectedPing = 0x01,
+    UnconnectedPong = 0x1c,
+    OpenConnectRequest = 0x05,
+    OpenConnectReply = 0x06,
+    SessionInfoRequest = 0x07,
+    SessionInfoReply = 0x08,
+    IncompatibleProtocolVersion = 0x19
+}
+
 register_packets! {
     Offline is OfflinePacket,
     UnconnectedPing,
@@ -161,11 +188,12 @@ pub struct OpenConnectRequest {
 
 impl Reader<OpenConnectRequest> for OpenConnectRequest {
     fn read(buf: &mut ByteReader) -> Result<OpenConnectRequest, std::io::Error> {
-        let len = buf.as_slice().len();
+        // the packet id has already been read.
+        let len = buf.as_slice().len() + 1;
         buf.read_type::<Magic>()?;
         Ok(OpenConnectRequest {
             protocol: buf.read_u8()?,
-            mtu_size: (len + RAKNET_HEADER_FRAME_OVERHEAD as usize) as u16,
+            mtu_size: (len + UDP_HEADER_OVERHEAD as usize) as u16,
         })
     }
 }
@@ -176,8 +204,10 @@ impl Writer for OpenConnectRequest {
         buf.write_u8(self.protocol)?;
         // padding
         // remove 28 bytes from the mtu size
-        let mtu_size = self.mtu_size - RAKNET_HEADER_FRAME_OVERHEAD as u16;
-        for _ in 0..mtu_size {
+        // the request is padded so the whole datagram is exactly the mtu.
+        // (id + magic + protocol)
+        let padding = self.mtu_size.saturating_sub(UDP_HEADER_OVERHEAD + 18);
+        for _ in 0..padding {
             buf.write_u8(0)?;
         }
         Ok(())
@@ -205,7 +235,7 @@ pub struct OpenConnectReply {
 /// is primarily used to get the external address of the peer.
 ///
 /// This packet is the equivalent of the `Open Connect Request 2` within the original RakNet implementation.
-#[derive(Debug, Clone, BinaryIo)]
+#[derive(Debug, Clone)]
 pub struct SessionInfoRequest {
     pub magic: Magic,
     /// The socket address of the peer you are sending
@@ -215,6 +245,33 @@ pub struct SessionInfoRequest {
     pub mtu_size: u16,
     /// Your internal client id.
     pub client_id: i64,
+    /// The hello of a rak-rs client that has extensions to offer, see
+    /// [`Capabilities::hello()`]. Vanilla servers don't read past the client id.
+    pub capabilities: Capabilities,
+}
+
+impl Reader<SessionInfoRequest> for SessionInfoRequest {
+    fn read(buf: &mut ByteReader) -> Result<SessionInfoRequest, std::io::Error> {
+        Ok(SessionInfoRequest {
+            magic: buf.read_type::<Magic>()?,
+            address: buf.read_type::<RakAddress>()?.0,
+            mtu_size: buf.read_u16()?,
+            client_id: buf.read_i64()?,
+            // whatever else another client sends is not ours to read.
+            capabilities: Capabilities::read(buf).unwrap_or_default(),
+        })
+    }
+}
+
+impl Writer for SessionInfoRequest {
+    fn write(&self, buf: &mut ByteWriter) -> Result<(), std::io::Error> {
+        buf.write_type::<Magic>(&self.magic)?;
+        RakAddress(self.address).write(buf)?;
+        buf.write_u16(self.mtu_size)?;
+        buf.write_i64(self.client_id)?;
+        self.capabilities.write(buf)?;
+        Ok(())
+    }
 }
 
 /// This packet is sent in response to a [`SessionInfoRequest`] packet, and confirms
@@ -223,13 +280,41 @@ pub struct SessionInfoRequest {
 /// encryption at the RakNet level is enabled on the server.
 ///
 /// This packet is the equivalent of the `Open Connect Reply 2` within the original RakNet implementation.
-#[derive(Debug, Clone, BinaryIo)]
+#[derive(Debug, Clone)]
 pub struct SessionInfoReply {
     pub magic: Magic,
     pub server_id: u64,
     pub client_address: SocketAddr,
     pub mtu_size: u16,
     pub security: bool,
+    /// The hello of a rak-rs server, only sent back to a client that sent its own, see
+    /// [`Capabilities::hello()`].
+    pub capabilities: Capabilities,
+}
+
+impl Reader<SessionInfoReply> for SessionInfoReply {
+    fn read(buf: &mut ByteReader) -> Result<SessionInfoReply, std::io::Error> {
+        Ok(SessionInfoReply {
+            magic: buf.read_type::<Magic>()?,
+            server_id: buf.read_u64()?,
+            client_address: buf.read_type::<RakAddress>()?.0,
+            mtu_size: buf.read_u16()?,
+            security: buf.read_bool()?,
+            capabilities: Capabilities::read(buf).unwrap_or_default(),
+        })
+    }
+}
+
+impl Writer for SessionInfoReply {
+    fn write(&self, buf: &mut ByteWriter) -> Result<(), std::io::Error> {
+        buf.write_type::<Magic>(&self.magic)?;
+        buf.write_u64(self.server_id)?;
+        RakAddress(self.client_address).write(buf)?;
+        buf.write_u16(self.mtu_size)?;
+        buf.write_bool(self.security)?;
+        self.capabilities.write(buf)?;
+        Ok(())
+    }
 }
 
 /// This packet is sent by the server to indicate that the server does not support the
@@ -14,7 +14,8 @@
 //! to initialize the connection within raknet, and completing the connection handshake.
 use std::net::SocketAddr;
 
-use super::RakPacket;
+use super::{packet_ids, PacketId, RakPacket};
+use crate::protocol::primitives::RakAddress;
 use crate::register_packets;
 
 use binary_util::interfaces::{Reader, Writer};
@@ -37,6 +38,31 @@ pub enum OnlinePacket {
     Disconnect(Disconnect) = 0x15,
 }
 
+impl OnlinePacket {
+    /// The id this packet is sent with.
+    pub fn id(&self) -> u8 {
+        match self {
+            Self::ConnectedPing(_) => ConnectedPing::ID,
+            Self::ConnectedPong(_) => ConnectedPong::ID,
+            Self::LostConnection(_) => LostConnection::ID,
+            Self::ConnectionRequest(_) => ConnectionRequest::ID,
+            Self::ConnectionAccept(_) => ConnectionAccept::ID,
+            Self::NewConnection(_) => NewConnection::ID,
+            Self::Disconnect(_) => Disconnect::ID,
+        }
+    }
+}
+
+packet_ids! {
+    ConnectedPing = 0x00,
+    ConnectedPong = 0x03,
+    LostConnection = 0x04,
+    ConnectionRequest = 0x09,
+    ConnectionAccept = 0x10,
+    NewConnection = 0x13,
+    Disconnect = 0x15
+}
+
 register_packets! {
     Online is OnlinePacket,
     ConnectedPing,
@@ -81,6 +107,233 @@ pub struct ConnectionRequest {
     pub client_id: i64,
     pub time: i64,
     pub security: bool,
+    /// The extensions the client supports, see [`Capabilities`]. Vanilla servers read these as
+    /// a password, they are only sent to a server that answered the hello of the client.
+    pub capabilities: Capabilities,
+}
+
+/// Extensions to the protocol that only rak-rs peers understand.
+///
+/// The client advertises these after its [`ConnectionRequest`], and the server sends the ones
+/// it agrees to back after its [`ConnectionAccept`]. Vanilla RakNet reads anything after a
+/// [`ConnectionRequest`] as a password, and refuses the connection when there is one, so the
+/// client only advertises them once the server showed it runs rak-rs: a client with extensions
+/// to offer attaches its [`Capabilities::hello()`] to the [`SessionInfoRequest`], which vanilla
+/// servers don't read that far, and a rak-rs server answers with its own in the
+/// [`SessionInfoReply`]. A client without extensions sends neither, so its handshake is
+/// vanilla byte for byte.
+///
+/// They are encoded in one of two ways:
+/// - The legacy encoding is a single byte of flags, it is what older versions of rak-rs send.
+/// - The versioned encoding starts with [`Capabilities::TLV_MARKER`] and the encoding version,
+///   followed by every capability as a `u8` type, a big endian `u16` length and its payload.
+///   Types this version doesn't know are skipped. It is used whenever a [`CrateVersion`] is
+///   attached, and the marker never carries a legacy flag, so an older peer agrees to nothing.
+///
+/// A reply is always encoded the way the offer was, a peer that sent the legacy byte can't
+/// read anything else.
+///
+/// [`SessionInfoRequest`]: crate::protocol::packet::offline::SessionInfoRequest
+/// [`SessionInfoReply`]: crate::protocol::packet::offline::SessionInfoReply
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    flags: u8,
+    /// The version of the crate that wrote these, only sent with the versioned encoding.
+    crate_version: Option<CrateVersion>,
+}
+
+impl Capabilities {
+    /// No extensions.
+    pub const NONE: Self = Self::from_flags(0);
+    /// Frame-set datagrams carry a checksum trailer, see [`ConnOptions::datagram_checksum`].
+    ///
+    /// [`ConnOptions::datagram_checksum`]: crate::connection::options::ConnOptions::datagram_checksum
+    pub const DATAGRAM_CHECKSUM: Self = Self::from_flags(0x01);
+    /// Small reliable-ordered messages may arrive merged into one, see
+    /// [`ConnOptions::coalesce_channels`].
+    ///
+    /// [`ConnOptions::coalesce_channels`]: crate::connection::options::ConnOptions::coalesce_channels
+    pub const COALESCING: Self = Self::from_flags(0x02);
+
+    /// The first byte of the versioned encoding, legacy flags never set the high bit.
+    pub const TLV_MARKER: u8 = 0x80;
+    /// The version of the versioned encoding this crate writes.
+    pub const TLV_VERSION: u8 = 1;
+    /// The type of the capability carrying the [`CrateVersion`].
+    pub const TLV_CRATE_VERSION: u8 = 0x00;
+    /// The type of the capability enabling [`Capabilities::DATAGRAM_CHECKSUM`].
+    pub const TLV_DATAGRAM_CHECKSUM: u8 = 0x01;
+    /// The type of the capability enabling [`Capabilities::COALESCING`].
+    pub const TLV_COALESCING: u8 = 0x02;
+
+    const fn from_flags(flags: u8) -> Self {
+        Self {
+            flags,
+            crate_version: None,
+        }
+    }
+
+    /// Whether every extension in `other` is in these capabilities.
+    pub fn contains(self, other: Self) -> bool {
+        self.flags & other.flags == other.flags
+    }
+
+    /// The extensions both sides support.
+    ///
+    /// The crate version of `self` is only kept if `other` is versioned as well,
+    /// so a reply to a legacy offer stays legacy.
+    pub fn intersection(self, other: Self) -> Self {
+        Self {
+            flags: self.flags & other.flags,
+            crate_version: other.crate_version.and(self.crate_version),
+        }
+    }
+
+    /// Adds the extensions in `other`.
+    pub fn with(self, other: Self) -> Self {
+        Self {
+            flags: self.flags | other.flags,
+            crate_version: self.crate_version.or(other.crate_version),
+        }
+    }
+
+    /// Attaches the version of the crate, which switches to the versioned encoding.
+    pub fn with_crate_version(self, version: CrateVersion) -> Self {
+        Self {
+            crate_version: Some(version),
+            ..self
+        }
+    }
+
+    /// The version of rak-rs the peer runs, `None` if it sent the legacy encoding or nothing.
+    pub fn crate_version(self) -> Option<CrateVersion> {
+        self.crate_version
+    }
+
+    /// Whether no extension is enabled, the crate version doesn't count.
+    pub fn is_empty(self) -> bool {
+        self.flags == 0
+    }
+
+    /// Tells the peer this side runs rak-rs, without offering any extension.
+    pub fn hello() -> Self {
+        Self::NONE.with_crate_version(CrateVersion::current())
+    }
+
+    /// The versioned capabilities this side offers, or nothing when it doesn't offer any
+    /// extension, so vanilla peers keep seeing vanilla packets.
+    pub fn offer(self) -> Self {
+        if self.is_empty() {
+            Self::NONE
+        } else {
+            self.with_crate_version(CrateVersion::current())
+        }
+    }
+
+    fn read_tlv(buf: &mut ByteReader) -> std::io::Result<Self> {
+        // newer encodings only add capability types, they are read the same.
+        let _version = buf.read_u8()?;
+        let mut capabilities = Self::NONE;
+
+        while !buf.as_slice().is_empty() {
+            let kind = buf.read_u8()?;
+            let mut payload = vec![0; buf.read_u16()? as usize];
+            buf.read(&mut payload)?;
+
+            match kind {
+                Self::TLV_CRATE_VERSION if payload.len() >= 6 => {
+                    let mut payload = ByteReader::from(&payload[..]);
+                    capabilities.crate_version = Some(CrateVersion {
+                        major: payload.read_u16()?,
+                        minor: payload.read_u16()?,
+                        patch: payload.read_u16()?,
+                    });
+                }
+                Self::TLV_DATAGRAM_CHECKSUM => {
+                    capabilities = capabilities.with(Self::DATAGRAM_CHECKSUM);
+                }
+                Self::TLV_COALESCING => {
+                    capabilities = capabilities.with(Self::COALESCING);
+                }
+                // a capability of a newer version, the peer doesn't get to use it with us.
+                _ => {}
+            }
+        }
+
+        Ok(capabilities)
+    }
+
+    fn write_tlv(&self, buf: &mut ByteWriter) -> std::io::Result<()> {
+        buf.write_u8(Self::TLV_MARKER)?;
+        buf.write_u8(Self::TLV_VERSION)?;
+
+        if let Some(version) = self.crate_version {
+            buf.write_u8(Self::TLV_CRATE_VERSION)?;
+            buf.write_u16(6)?;
+            buf.write_u16(version.major)?;
+            buf.write_u16(version.minor)?;
+            buf.write_u16(version.patch)?;
+        }
+        if self.contains(Self::DATAGRAM_CHECKSUM) {
+            buf.write_u8(Self::TLV_DATAGRAM_CHECKSUM)?;
+            buf.write_u16(0)?;
+        }
+        if self.contains(Self::COALESCING) {
+            buf.write_u8(Self::TLV_COALESCING)?;
+            buf.write_u16(0)?;
+        }
+        Ok(())
+    }
+}
+
+impl Reader<Capabilities> for Capabilities {
+    fn read(buf: &mut ByteReader) -> std::io::Result<Self> {
+        // peers that don't know about capabilities don't send anything.
+        match buf.as_slice().first() {
+            None => Ok(Self::NONE),
+            Some(&first) if first & Self::TLV_MARKER != 0 => {
+                buf.read_u8()?;
+                Self::read_tlv(buf)
+            }
+            Some(_) => Ok(Self::from_flags(buf.read_u8()?)),
+        }
+    }
+}
+
+impl Writer for Capabilities {
+    fn write(&self, buf: &mut ByteWriter) -> std::io::Result<()> {
+        if self.crate_version.is_some() {
+            self.write_tlv(buf)?;
+        } else if !self.is_empty() {
+            buf.write_u8(self.flags)?;
+        }
+        Ok(())
+    }
+}
+
+/// The version of rak-rs a peer runs, it is sent along with versioned [`Capabilities`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CrateVersion {
+    pub major: u16,
+    pub minor: u16,
+    pub patch: u16,
+}
+
+impl CrateVersion {
+    /// The version of this crate.
+    pub fn current() -> Self {
+        Self {
+            major: env!("CARGO_PKG_VERSION_MAJOR").parse().unwrap_or(0),
+            minor: env!("CARGO_PKG_VERSION_MINOR").parse().unwrap_or(0),
+            patch: env!("CARGO_PKG_VERSION_PATCH").parse().unwrap_or(0),
+        }
+    }
+}
+
+impl std::fmt::Display for CrateVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
 }
 
 /// A connection Accept packet, this is sent by the server to the client.
@@ -99,26 +352,32 @@ pub struct ConnectionAccept {
     pub request_time: i64,
     /// The time on the server.
     pub timestamp: i64,
+    /// The extensions the server agreed to, see [`Capabilities`].
+    pub capabilities: Capabilities,
 }
 
 impl Reader<ConnectionAccept> for ConnectionAccept {
     fn read(buf: &mut ByteReader) -> std::io::Result<Self> {
-        let client_address = buf.read_type::<SocketAddr>()?;
+        let client_address = buf.read_type::<RakAddress>()?.0;
 
         // read the system index, this is
         let system_index = buf.read_i16()?;
         let mut internal_ids = Vec::<SocketAddr>::new();
 
         for _ in 0..20 {
-            // we only have the request time and timestamp left...
-            if buf.as_slice().len() <= 16 {
+            // we only have the request time, timestamp and maybe the capabilities left...
+            // no address fits in the single byte of the legacy capabilities, and the versioned
+            // ones follow the request time, which never starts like an address does.
+            let rest = buf.as_slice();
+            if rest.len() <= 17 || !matches!(rest[0], 4 | 6) {
                 break;
             }
-            internal_ids.push(buf.read_type::<SocketAddr>()?);
+            internal_ids.push(buf.read_type::<RakAddress>()?.0);
         }
 
         let request_time = buf.read_i64()?;
         let timestamp = buf.read_i64()?;
+        let capabilities = Capabilities::read(buf)?;
 
         Ok(Self {
             client_address,
@@ -126,13 +385,14 @@ impl Reader<ConnectionAccept> for ConnectionAccept {
             internal_ids,
             request_time,
             timestamp,
+            capabilities,
         })
     }
 }
 
 impl Writer for ConnectionAccept {
     fn write(&self, buf: &mut ByteWriter) -> std::io::Result<()> {
-        buf.write_type::<SocketAddr>(&self.client_address)?;
+        RakAddress(self.client_address).write(buf)?;
         buf.write_i16(self.system_index)?;
 
         if self.internal_ids.len() > 20 {
@@ -143,11 +403,12 @@ impl Writer for ConnectionAccept {
         }
 
         for internal_id in &self.internal_ids {
-            buf.write_type::<SocketAddr>(internal_id)?;
+            RakAddress(*internal_id).write(buf)?;
         }
 
         buf.write_i64(self.request_time)?;
         buf.write_i64(self.timestamp)?;
+        self.capabilities.write(buf)?;
 
         Ok(())
     }
@@ -169,16 +430,16 @@ pub struct NewConnection {
 
 impl Reader<NewConnection> for NewConnection {
     fn read(buf: &mut ByteReader) -> std::io::Result<Self> {
-        let server_address = buf.read_type::<SocketAddr>()?;
+        let server_address = buf.read_type::<RakAddress>()?.0;
 
         let mut system_address = Vec::<SocketAddr>::new();
 
         for _ in 0..20 {
             // we only have the request time and timestamp left...
-            if buf.as_slice().len() < 16 {
+            if buf.as_slice().len() <= 16 {
                 break;
             }
-            system_address.push(buf.read_type::<SocketAddr>()?);
+            system_address.push(buf.read_type::<RakAddress>()?.0);
         }
 
         let request_time = buf.read_i64()?;
@@ -195,7 +456,7 @@ impl Reader<NewConnection> for NewConnection {
 
 impl Writer for NewConnection {
     fn write(&self, buf: &mut ByteWriter) -> std::io::Result<()> {
-        buf.write_type::<SocketAddr>(&self.server_address)?;
+        RakAddress(self.server_address).write(buf)?;
 
         if self.system_address.len() > 20 {
             return Err(std::io::Error::new(
@@ -205,7 +466,7 @@ impl Writer for NewConnection {
         }
 
         for system_address in &self.system_address {
-            buf.write_type::<SocketAddr>(system_address)?;
+            RakAddress(*system_address).write(buf)?;
         }
 
         buf.write_i64(self.request_time)?;
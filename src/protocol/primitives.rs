@@ -0,0 +1,252 @@
+//! Fields RakNet encodes differently from how `binary_util` would write them.
+//!
+//! Addresses are the prime example, they show up in four packets and are where most
+//! implementations disagree, so every edge case is decided (and tested) here:
+//!
+//! - IPv4 addresses are written with every octet inverted.
+//! - IPv6 addresses are a 29 byte `sockaddr_in6`, with the family in little endian and
+//!   everything else in big endian. The flow info is kept both ways.
+//! - IPv4-mapped IPv6 addresses (`::ffff:a.b.c.d`) are written as the native IPv4 address,
+//!   this is what vanilla peers do, and what they expect to read.
+//! - The scope id of an IPv6 address is kept when reading, but zeroed when writing, as it
+//!   only means something on the host that chose it. See [`ScopeId`].
+//! - Port 0 and the unspecified addresses are allowed, vanilla fills unused internal ids
+//!   with them.
+//! - Any version byte other than 4 or 6 is an error naming the byte.
+//!
+//! Packets are read with [`decode()`], which tells where in the buffer a packet broke and how,
+//! see [`DecodeError`].
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+
+use binary_util::interfaces::{Reader, Writer};
+use binary_util::io::{ByteReader, ByteWriter};
+
+use crate::error::decode::{DecodeError, DecodeErrorKind};
+
+/// The most a read is padded by to find how short a buffer was, see [`DecodeErrorKind::UnexpectedEof`].
+const MAX_NEEDED: usize = u16::MAX as usize;
+
+/// Reads a `T` from `buffer`, bytes left after it are ignored.
+///
+/// ```rust
+/// use rak_rs::error::decode::DecodeErrorKind;
+/// use rak_rs::protocol::packet::offline::OfflinePacket;
+/// use rak_rs::protocol::primitives::decode;
+///
+/// // an unconnected ping cut off in its timestamp.
+/// let error = decode::<OfflinePacket>(&[0x01, 0, 0, 0, 0]).unwrap_err();
+/// assert_eq!(error.packet_id, Some(0x01));
+/// assert_eq!(error.offset, 1);
+/// assert_eq!(
+///     error.kind,
+///     DecodeErrorKind::UnexpectedEof {
+///         needed: 8,
+///         remaining: 4
+///     }
+/// );
+/// ```
+pub fn decode<T: Reader<T>>(buffer: &[u8]) -> Result<T, DecodeError> {
+    let mut reader = ByteReader::from(buffer);
+    T::read(&mut reader).map_err(|error| locate::<T>(buffer, reader.as_slice().len(), error))
+}
+
+/// Reads a `T` from `buffer`, like [`decode()`], but bytes left after it are an error.
+pub fn decode_strict<T: Reader<T>>(buffer: &[u8]) -> Result<T, DecodeError> {
+    let mut reader = ByteReader::from(buffer);
+    let value = T::read(&mut reader)
+        .map_err(|error| locate::<T>(buffer, reader.as_slice().len(), error))?;
+
+    match reader.as_slice().len() {
+        0 => Ok(value),
+        count => Err(DecodeError {
+            packet_id: buffer.first().copied(),
+            offset: buffer.len() - count,
+            kind: DecodeErrorKind::TrailingBytes { count },
+        }),
+    }
+}
+
+/// Where and why reading a `T` from `buffer` failed with `error`, `remaining` bytes after
+/// what was read.
+fn locate<T: Reader<T>>(buffer: &[u8], remaining: usize, error: std::io::Error) -> DecodeError {
+    let mut offset = buffer.len() - remaining;
+    let kind = match error
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<DecodeErrorKind>())
+    {
+        Some(kind) => kind.clone(),
+        None if error.kind() == std::io::ErrorKind::UnexpectedEof => {
+            DecodeErrorKind::UnexpectedEof {
+                needed: needed::<T>(buffer, offset),
+                remaining,
+            }
+        }
+        // the readers binary_util derives for packet enums read the id before rejecting it,
+        // their error is only known by its message.
+        None if offset > 0 && error.to_string() == "Invalid enum discriminant." => {
+            offset -= 1;
+            DecodeErrorKind::InvalidEnumValue {
+                field: "packet id",
+                value: buffer[offset],
+            }
+        }
+        None => DecodeErrorKind::Malformed(error.to_string()),
+    };
+
+    DecodeError {
+        packet_id: buffer.first().copied(),
+        offset,
+        kind,
+    }
+}
+
+/// How many bytes after `offset` reading a `T` needed, where `buffer` ended.
+///
+/// binary_util doesn't say, so `buffer` is read again padded with zeroes, until the read
+/// gets past `offset`.
+fn needed<T: Reader<T>>(buffer: &[u8], offset: usize) -> usize {
+    let remaining = buffer.len() - offset;
+    let stuck = |padding: usize| {
+        let mut padded = buffer.to_vec();
+        padded.resize(buffer.len() + padding, 0);
+        let mut reader = ByteReader::from(&padded[..]);
+        match T::read(&mut reader) {
+            Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => {
+                padded.len() - reader.as_slice().len() == offset
+            }
+            _ => false,
+        }
+    };
+
+    let mut padding = 1;
+    while stuck(padding) {
+        if padding >= MAX_NEEDED {
+            return remaining + 1;
+        }
+        padding *= 2;
+    }
+    // the smallest padding that got past it is between the last two.
+    let mut short = padding / 2;
+    while padding - short > 1 {
+        let mid = short + (padding - short) / 2;
+        if stuck(mid) {
+            short = mid;
+        } else {
+            padding = mid;
+        }
+    }
+    remaining + padding
+}
+
+/// The address family RakNet writes for IPv6 addresses, this is `AF_INET6` on Windows,
+/// where most vanilla peers run.
+const AF_INET6: u16 = 23;
+
+/// What happens to the scope id of an IPv6 address when it is written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ScopeId {
+    /// Written as 0, the default.
+    #[default]
+    Zeroed,
+    /// Written as is, only useful when both peers share a link.
+    Kept,
+}
+
+/// A socket address as RakNet writes a `SystemAddress`.
+///
+/// ```rust
+/// use binary_util::interfaces::{Reader, Writer};
+/// use rak_rs::protocol::primitives::RakAddress;
+///
+/// let address = RakAddress("127.0.0.1:19132".parse().unwrap());
+/// let bytes = address.write_to_bytes().unwrap();
+/// assert_eq!(bytes.as_slice(), &[4, 0x80, 0xff, 0xff, 0xfe, 0x4a, 0xbc]);
+/// assert_eq!(RakAddress::read_from_slice(bytes.as_slice()).unwrap(), address);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RakAddress(pub SocketAddr);
+
+impl RakAddress {
+    /// Writes the address, choosing what happens to the scope id of an IPv6 address.
+    pub fn write_with(&self, buf: &mut ByteWriter, scope: ScopeId) -> std::io::Result<()> {
+        match self.0 {
+            SocketAddr::V4(address) => write_v4(buf, address.ip(), address.port()),
+            SocketAddr::V6(address) => match address.ip().to_ipv4_mapped() {
+                Some(ip) => write_v4(buf, &ip, address.port()),
+                None => {
+                    buf.write_u8(6)?;
+                    buf.write_u16_le(AF_INET6)?;
+                    buf.write_u16(address.port())?;
+                    buf.write_u32(address.flowinfo())?;
+                    buf.write(&address.ip().octets())?;
+                    buf.write_u32(match scope {
+                        ScopeId::Zeroed => 0,
+                        ScopeId::Kept => address.scope_id(),
+                    })
+                }
+            },
+        }
+    }
+}
+
+fn write_v4(buf: &mut ByteWriter, ip: &Ipv4Addr, port: u16) -> std::io::Result<()> {
+    buf.write_u8(4)?;
+    buf.write(&ip.octets().map(|octet| !octet))?;
+    buf.write_u16(port)
+}
+
+impl Reader<RakAddress> for RakAddress {
+    fn read(buf: &mut ByteReader) -> std::io::Result<RakAddress> {
+        // the version is only taken once it is known to be one, so an error points at it.
+        if let Some(&version) = buf.as_slice().first() {
+            if version != 4 && version != 6 {
+                return Err(DecodeErrorKind::InvalidAddressVersion(version).into());
+            }
+        }
+        match buf.read_u8()? {
+            4 => {
+                let mut octets = [0u8; 4];
+                buf.read(&mut octets)?;
+                let port = buf.read_u16()?;
+                Ok(RakAddress(SocketAddr::V4(SocketAddrV4::new(
+                    Ipv4Addr::from(octets.map(|octet| !octet)),
+                    port,
+                ))))
+            }
+            6 => {
+                // the family depends on the platform of the peer, and tells us nothing.
+                buf.read_u16_le()?;
+                let port = buf.read_u16()?;
+                let flow = buf.read_u32()?;
+                let mut octets = [0u8; 16];
+                buf.read(&mut octets)?;
+                let scope = buf.read_u32()?;
+                Ok(RakAddress(SocketAddr::V6(SocketAddrV6::new(
+                    Ipv6Addr::from(octets),
+                    port,
+                    flow,
+                    scope,
+                ))))
+            }
+            version => Err(DecodeErrorKind::InvalidAddressVersion(version).into()),
+        }
+    }
+}
+
+impl Writer for RakAddress {
+    fn write(&self, buf: &mut ByteWriter) -> std::io::Result<()> {
+        self.write_with(buf, ScopeId::default())
+    }
+}
+
+impl From<SocketAddr> for RakAddress {
+    fn from(address: SocketAddr) -> Self {
+        RakAddress(address)
+    }
+}
+
+impl From<RakAddress> for SocketAddr {
+    fn from(address: RakAddress) -> Self {
+        address.0
+    }
+}
@@ -0,0 +1,171 @@
+//! A compact set of sequence numbers, stored as sorted, non-overlapping, inclusive ranges.
+//!
+//! This is the same run-length structure ACK and NACK records use on the wire, so a gap of
+//! "everything from 10000 to 50000" is stored (and sent) as a single range instead of 40001 ids.
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+
+/// A set of `u32` sequence numbers, stored as sorted, non-overlapping, inclusive ranges.
+///
+/// ```rust
+/// use rak_rs::protocol::ranges::SequenceRanges;
+///
+/// let mut ranges = SequenceRanges::new();
+/// ranges.insert_range(10, 20);
+/// ranges.insert(21);
+/// ranges.insert(30);
+///
+/// assert_eq!(ranges.len(), 13);
+/// assert_eq!(ranges.iter_ranges().collect::<Vec<_>>(), vec![10..=21, 30..=30]);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SequenceRanges {
+    /// start -> end (inclusive)
+    ranges: BTreeMap<u32, u32>,
+}
+
+impl SequenceRanges {
+    pub fn new() -> Self {
+        Self {
+            ranges: BTreeMap::new(),
+        }
+    }
+
+    /// Inserts a single id, returns `false` if it was already in the set.
+    pub fn insert(&mut self, id: u32) -> bool {
+        if self.contains(id) {
+            return false;
+        }
+        self.insert_range(id, id);
+        true
+    }
+
+    /// Inserts every id from `start` to `end` (inclusive).
+    /// If `end` is lower than `start` the two are swapped.
+    pub fn insert_range(&mut self, start: u32, end: u32) {
+        let (mut start, mut end) = if end < start {
+            (end, start)
+        } else {
+            (start, end)
+        };
+
+        // merge with a range that ends right before (or overlaps) this one.
+        if let Some((&prev_start, &prev_end)) = self.ranges.range(..=start).next_back() {
+            if prev_end.saturating_add(1) >= start {
+                start = prev_start;
+                end = end.max(prev_end);
+                self.ranges.remove(&prev_start);
+            }
+        }
+
+        // merge with every range that starts within (or right after) this one.
+        while let Some((&next_start, &next_end)) =
+            self.ranges.range(start..=end.saturating_add(1)).next()
+        {
+            end = end.max(next_end);
+            self.ranges.remove(&next_start);
+        }
+
+        self.ranges.insert(start, end);
+    }
+
+    /// Removes a single id, returns `false` if it was not in the set.
+    pub fn remove(&mut self, id: u32) -> bool {
+        let (start, end) = match self.ranges.range(..=id).next_back() {
+            Some((&start, &end)) if end >= id => (start, end),
+            _ => return false,
+        };
+
+        self.ranges.remove(&start);
+        if start < id {
+            self.ranges.insert(start, id - 1);
+        }
+        if id < end {
+            self.ranges.insert(id + 1, end);
+        }
+        true
+    }
+
+    /// Removes every id from `start` to `end` (inclusive).
+    /// If `end` is lower than `start` the two are swapped.
+    pub fn remove_range(&mut self, start: u32, end: u32) {
+        let (start, end) = if end < start {
+            (end, start)
+        } else {
+            (start, end)
+        };
+
+        // a range that starts before this one keeps what is outside of it.
+        if let Some((&prev_start, &prev_end)) = self.ranges.range(..start).next_back() {
+            if prev_end >= start {
+                self.ranges.insert(prev_start, start - 1);
+                if prev_end > end {
+                    self.ranges.insert(end + 1, prev_end);
+                }
+            }
+        }
+
+        // every range that starts within this one only keeps what comes after it.
+        while let Some((&next_start, &next_end)) = self.ranges.range(start..=end).next() {
+            self.ranges.remove(&next_start);
+            if next_end > end {
+                self.ranges.insert(end + 1, next_end);
+            }
+        }
+    }
+
+    /// Whether the id is in the set.
+    pub fn contains(&self, id: u32) -> bool {
+        matches!(self.ranges.range(..=id).next_back(), Some((_, &end)) if end >= id)
+    }
+
+    /// The amount of ids in the set.
+    pub fn len(&self) -> usize {
+        self.ranges
+            .iter()
+            .map(|(start, end)| (end - start) as usize + 1)
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// The amount of ranges the set is stored as.
+    pub fn range_count(&self) -> usize {
+        self.ranges.len()
+    }
+
+    /// Removes every id from the set.
+    pub fn clear(&mut self) {
+        self.ranges.clear();
+    }
+
+    /// Iterates over the ranges in ascending order.
+    pub fn iter_ranges(&self) -> impl Iterator<Item = RangeInclusive<u32>> + '_ {
+        self.ranges.iter().map(|(start, end)| *start..=*end)
+    }
+
+    /// Lazily iterates over every id in ascending order.
+    pub fn iter_ids(&self) -> impl Iterator<Item = u32> + '_ {
+        self.iter_ranges().flatten()
+    }
+}
+
+impl FromIterator<u32> for SequenceRanges {
+    fn from_iter<T: IntoIterator<Item = u32>>(iter: T) -> Self {
+        let mut ranges = SequenceRanges::new();
+        for id in iter {
+            ranges.insert(id);
+        }
+        ranges
+    }
+}
+
+impl Extend<u32> for SequenceRanges {
+    fn extend<T: IntoIterator<Item = u32>>(&mut self, iter: T) {
+        for id in iter {
+            self.insert(id);
+        }
+    }
+}
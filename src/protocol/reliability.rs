@@ -53,7 +53,7 @@
 /// [`Reliable`]: crate::protocol::reliability::Reliability::Reliable
 /// [`ReliableOrd`]: crate::protocol::reliability::Reliability::ReliableOrd
 /// [`ReliableSeq`]: crate::protocol::reliability::Reliability::ReliableSeq
-#[derive(Clone, Debug, Copy)]
+#[derive(Clone, Debug, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum Reliability {
     /// Unreliable (with no ack)
@@ -74,24 +74,26 @@ pub enum Reliability {
 }
 
 impl Reliability {
+    /// Every reliability, indexed by the value of its bit flag.
+    const ALL: [Reliability; 8] = [
+        Reliability::Unreliable,
+        Reliability::UnreliableSeq,
+        Reliability::Reliable,
+        Reliability::ReliableOrd,
+        Reliability::ReliableSeq,
+        Reliability::UnreliableAck,
+        Reliability::ReliableAck,
+        Reliability::ReliableOrdAck,
+    ];
+
     /// Creates a new [`Reliability`] from the given flags.
     /// This is used internally to decode the reliability from the given
     /// bit flags.
     ///
     /// [`Reliability`]: crate::protocol::reliability::Reliability
     pub fn from_flags(flags: u8) -> Self {
-        match (flags & 224) >> 5 {
-            0 => Reliability::Unreliable,
-            1 => Reliability::UnreliableSeq,
-            2 => Reliability::Reliable,
-            3 => Reliability::ReliableOrd,
-            4 => Reliability::ReliableSeq,
-            5 => Reliability::UnreliableAck,
-            6 => Reliability::ReliableAck,
-            7 => Reliability::ReliableOrdAck,
-            // we shouldn't error, but we'll just return unreliable
-            _ => Reliability::Unreliable,
-        }
+        // the top 3 bits can only ever hold 0 to 7, so every value has a reliability.
+        Self::ALL[((flags & 224) >> 5) as usize]
     }
 
     /// Converts the [`Reliability`] into a bit flag.
@@ -113,15 +115,21 @@ impl Reliability {
     /// are either:
     /// - [`ReliableOrd`]
     /// - [`ReliableOrdAck`]
-    /// - [`UnreliableSeq`]
+    ///
+    /// Sequenced packets carry an order index as well, see [`Reliability::is_sequenced_or_ordered`].
     ///
     /// [`ReliableOrd`]: crate::protocol::reliability::Reliability::ReliableOrd
     /// [`ReliableOrdAck`]: crate::protocol::reliability::Reliability::ReliableOrdAck
-    /// [`UnreliableSeq`]: crate::protocol::reliability::Reliability::UnreliableSeq
+    /// [`Reliability::is_sequenced_or_ordered`]: crate::protocol::reliability::Reliability::is_sequenced_or_ordered
     pub fn is_ordered(&self) -> bool {
         match self {
-            Self::UnreliableSeq | Self::ReliableOrd | Self::ReliableOrdAck => true,
-            _ => false,
+            Self::ReliableOrd | Self::ReliableOrdAck => true,
+            Self::Unreliable
+            | Self::UnreliableSeq
+            | Self::Reliable
+            | Self::ReliableSeq
+            | Self::UnreliableAck
+            | Self::ReliableAck => false,
         }
     }
 
@@ -131,6 +139,7 @@ impl Reliability {
     /// - [`ReliableOrd`]
     /// - [`ReliableSeq`]
     /// - [`ReliableAck`]
+    /// - [`ReliableOrdAck`]
     ///
     /// Other reliabilities are not reliable, and will return `false`.
     ///
@@ -138,10 +147,15 @@ impl Reliability {
     /// [`ReliableOrd`]: crate::protocol::reliability::Reliability::ReliableOrd
     /// [`ReliableSeq`]: crate::protocol::reliability::Reliability::ReliableSeq
     /// [`ReliableAck`]: crate::protocol::reliability::Reliability::ReliableAck
+    /// [`ReliableOrdAck`]: crate::protocol::reliability::Reliability::ReliableOrdAck
     pub fn is_reliable(&self) -> bool {
         match self {
-            Self::Reliable | Self::ReliableOrd | Self::ReliableSeq | Self::ReliableOrdAck => true,
-            _ => false,
+            Self::Reliable
+            | Self::ReliableOrd
+            | Self::ReliableSeq
+            | Self::ReliableAck
+            | Self::ReliableOrdAck => true,
+            Self::Unreliable | Self::UnreliableSeq | Self::UnreliableAck => false,
         }
     }
 
@@ -157,10 +171,7 @@ impl Reliability {
     /// [`UnreliableSeq`]: crate::protocol::reliability::Reliability::UnreliableSeq
     /// [`UnreliableAck`]: crate::protocol::reliability::Reliability::UnreliableAck
     pub fn is_unreliable(&self) -> bool {
-        match self {
-            Self::Unreliable | Self::UnreliableSeq | Self::UnreliableAck => true,
-            _ => false,
-        }
+        !self.is_reliable()
     }
 
     /// Verifies whether or not the reliabilty is sequenced, meaning that the packets
@@ -180,7 +191,12 @@ impl Reliability {
     pub fn is_sequenced(&self) -> bool {
         match self {
             Self::UnreliableSeq | Self::ReliableSeq => true,
-            _ => false,
+            Self::Unreliable
+            | Self::Reliable
+            | Self::ReliableOrd
+            | Self::UnreliableAck
+            | Self::ReliableAck
+            | Self::ReliableOrdAck => false,
         }
     }
 
@@ -191,12 +207,7 @@ impl Reliability {
     /// [`Reliability::is_sequenced`]: crate::protocol::reliability::Reliability::is_sequenced
     /// [`Reliability::is_ordered`]: crate::protocol::reliability::Reliability::is_ordered
     pub fn is_sequenced_or_ordered(&self) -> bool {
-        match self {
-            Self::UnreliableSeq | Self::ReliableSeq | Self::ReliableOrd | Self::ReliableOrdAck => {
-                true
-            }
-            _ => false,
-        }
+        self.is_sequenced() || self.is_ordered()
     }
 
     /// Verifies that the reliability is an ack ([`Ack`]).
@@ -206,7 +217,11 @@ impl Reliability {
     pub fn is_ack(&self) -> bool {
         match self {
             Self::UnreliableAck | Self::ReliableAck | Self::ReliableOrdAck => true,
-            _ => false,
+            Self::Unreliable
+            | Self::UnreliableSeq
+            | Self::Reliable
+            | Self::ReliableOrd
+            | Self::ReliableSeq => false,
         }
     }
 }
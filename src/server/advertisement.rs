@@ -0,0 +1,272 @@
+//! The [`Motd`] a [`Listener`] advertises in its pongs.
+//!
+//! There are three ways to keep it up to date, from cheapest to most expensive per ping:
+//! - [`Listener::set_motd()`] pushes a new MOTD whenever something changes.
+//! - [`Listener::set_motd_refresher()`] evaluates a closure every interval, off the ping path.
+//!   This is the pattern to use when the MOTD comes from an expensive source, like a database.
+//! - [`Listener::set_motd_provider()`] evaluates a closure for every ping, within
+//!   [`Listener::motd_budget`]. A ping the closure can't answer in time is answered with the
+//!   last MOTD instead.
+//!
+//! When the MOTD depends on who is asking, [`Listener::offline_ping_passthrough`] hands every
+//! ping to the application as a [`RakEvent::PingRequest`], which answers it with a
+//! [`PingResponder`].
+//!
+//! [`Listener`]: crate::server::Listener
+//! [`Listener::set_motd()`]: crate::server::Listener::set_motd
+//! [`Listener::set_motd_refresher()`]: crate::server::Listener::set_motd_refresher
+//! [`Listener::set_motd_provider()`]: crate::server::Listener::set_motd_provider
+//! [`Listener::motd_budget`]: crate::server::Listener::motd_budget
+//! [`Listener::offline_ping_passthrough`]: crate::server::Listener::offline_ping_passthrough
+//! [`RakEvent::PingRequest`]: crate::connection::event::RakEvent::PingRequest
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::channel::oneshot;
+
+#[cfg(feature = "async_std")]
+use async_std::{future::timeout, task};
+#[cfg(feature = "async_tokio")]
+use tokio::{task, time::timeout};
+
+use crate::protocol::mcpe::motd::Motd;
+use crate::rakrs_debug;
+
+/// The default time a provider has to produce the MOTD for a ping.
+pub const DEFAULT_MOTD_BUDGET: Duration = Duration::from_millis(1);
+/// The amount of overruns in a row after which a warning is logged.
+pub const MOTD_OVERRUN_WARNING: u64 = 16;
+/// The default time the application has to answer a [`RakEvent::PingRequest`].
+///
+/// [`RakEvent::PingRequest`]: crate::connection::event::RakEvent::PingRequest
+pub const DEFAULT_PING_RESPONSE_DEADLINE: Duration = Duration::from_millis(50);
+
+/// Produces the MOTD for a ping, see [`Listener::set_motd_provider()`].
+///
+/// This is called outside of the runtime, it may block, but should be quick.
+///
+/// [`Listener::set_motd_provider()`]: crate::server::Listener::set_motd_provider
+pub type MotdProvider = Arc<dyn Fn() -> Motd + Send + Sync>;
+
+/// How a ping handed to the application is answered, see [`PingResponder`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PingReply {
+    /// Advertises the MOTD. Without the `mcpe` feature, pongs carry no MOTD, so this
+    /// answers with a bare pong.
+    Motd(Motd),
+    /// Advertises these bytes as is where the MOTD would be, for games that don't
+    /// use the MCPE format.
+    Raw(Vec<u8>),
+    /// Doesn't answer at all, the server looks offline to the peer.
+    Decline,
+}
+
+/// What a ping is answered with when the application doesn't answer it within
+/// [`Listener::ping_response_deadline`].
+///
+/// [`Listener::ping_response_deadline`]: crate::server::Listener::ping_response_deadline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PingFallback {
+    /// The last MOTD the listener advertised.
+    #[default]
+    Cached,
+    /// Nothing, as if the application declined.
+    Silence,
+}
+
+/// Answers the ping of a [`RakEvent::PingRequest`].
+///
+/// Only the first answer counts, and only if it is given within
+/// [`Listener::ping_response_deadline`], later ones are dropped. It can be sent to another
+/// task, so the answer may be looked up asynchronously.
+///
+/// [`RakEvent::PingRequest`]: crate::connection::event::RakEvent::PingRequest
+/// [`Listener::ping_response_deadline`]: crate::server::Listener::ping_response_deadline
+#[derive(Clone)]
+pub struct PingResponder(Arc<Mutex<Option<oneshot::Sender<PingReply>>>>);
+
+impl PingResponder {
+    pub(crate) fn new() -> (Self, oneshot::Receiver<PingReply>) {
+        let (sender, receiver) = oneshot::channel();
+        (Self(Arc::new(Mutex::new(Some(sender)))), receiver)
+    }
+
+    /// Answers the ping with `motd`, returns `false` if it was answered already or it is
+    /// too late.
+    pub fn respond(&self, motd: Motd) -> bool {
+        self.reply(PingReply::Motd(motd))
+    }
+
+    /// Answers the ping with a raw advertisement, see [`PingReply::Raw`].
+    pub fn respond_raw(&self, advertisement: Vec<u8>) -> bool {
+        self.reply(PingReply::Raw(advertisement))
+    }
+
+    /// Leaves the ping unanswered.
+    pub fn decline(&self) -> bool {
+        self.reply(PingReply::Decline)
+    }
+
+    /// Answers the ping, returns `false` if it was answered already or it is too late.
+    pub fn reply(&self, reply: PingReply) -> bool {
+        let sender = self
+            .0
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .take();
+        match sender {
+            // the listener stops waiting once the deadline passed.
+            Some(sender) => sender.send(reply).is_ok(),
+            None => false,
+        }
+    }
+}
+
+impl std::fmt::Debug for PingResponder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PingResponder").finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for PingResponder {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for PingResponder {}
+
+/// The MOTD a listener advertises, shared between the listener and its tasks.
+pub struct Advertisement {
+    /// The MOTD served when there is no provider, or the provider overran its budget.
+    cached: Mutex<Motd>,
+    provider: Mutex<Option<MotdProvider>>,
+    /// Whether the provider is being evaluated, it is never evaluated twice at once.
+    evaluating: AtomicBool,
+    overruns: AtomicU64,
+    /// The overruns since the provider last answered in time.
+    streak: AtomicU64,
+    /// Bumped whenever the refresher is replaced, a refresher stops once it is outdated.
+    refresher: AtomicU64,
+}
+
+impl Advertisement {
+    pub fn new(motd: Motd) -> Self {
+        Self {
+            cached: Mutex::new(motd),
+            provider: Mutex::new(None),
+            evaluating: AtomicBool::new(false),
+            overruns: AtomicU64::new(0),
+            streak: AtomicU64::new(0),
+            refresher: AtomicU64::new(0),
+        }
+    }
+
+    /// The MOTD that was advertised last.
+    pub fn cached(&self) -> Motd {
+        self.cached
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Replaces the MOTD, it is served until something replaces it again.
+    pub fn set(&self, motd: Motd) {
+        *self
+            .cached
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner) = motd;
+    }
+
+    /// Evaluates `provider` for every ping from now on, `None` serves the cached MOTD.
+    pub fn set_provider(&self, provider: Option<MotdProvider>) {
+        *self
+            .provider
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner) = provider;
+    }
+
+    /// The amount of pings the provider couldn't answer within its budget.
+    pub fn overruns(&self) -> u64 {
+        self.overruns.load(Ordering::Relaxed)
+    }
+
+    /// Takes over from the previous refresher, returning the generation a refresher runs as.
+    pub(crate) fn next_refresher(&self) -> u64 {
+        self.refresher.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Whether a refresher of the given generation should keep running.
+    pub(crate) fn is_refresher(&self, generation: u64) -> bool {
+        self.refresher.load(Ordering::Relaxed) == generation
+    }
+
+    /// Whether a refresher keeps the MOTD up to date.
+    pub(crate) fn is_refreshed(&self) -> bool {
+        self.refresher.load(Ordering::Relaxed) != 0
+    }
+
+    /// The MOTD to answer a ping with.
+    ///
+    /// The provider is evaluated on a blocking thread, if it doesn't answer within `budget`
+    /// the cached MOTD is served, and the provider replaces it once it's done.
+    pub async fn current(self: &Arc<Self>, budget: Duration) -> Motd {
+        let provider = match self
+            .provider
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone()
+        {
+            Some(provider) => provider,
+            None => return self.cached(),
+        };
+
+        // a slow provider is still busy with an earlier ping, don't pile up behind it.
+        if self.evaluating.swap(true, Ordering::Acquire) {
+            self.overrun();
+            return self.cached();
+        }
+
+        let advertisement = self.clone();
+        let evaluation = task::spawn_blocking(move || {
+            let motd = catch_unwind(AssertUnwindSafe(|| provider()));
+            if let Ok(motd) = &motd {
+                advertisement.set(motd.clone());
+            }
+            advertisement.evaluating.store(false, Ordering::Release);
+            motd.ok()
+        });
+
+        #[cfg(feature = "async_std")]
+        let motd = timeout(budget, evaluation).await.ok().flatten();
+        #[cfg(feature = "async_tokio")]
+        let motd = timeout(budget, evaluation)
+            .await
+            .ok()
+            .and_then(Result::ok)
+            .flatten();
+
+        match motd {
+            Some(motd) => {
+                self.streak.store(0, Ordering::Relaxed);
+                motd
+            }
+            None => {
+                self.overrun();
+                self.cached()
+            }
+        }
+    }
+
+    fn overrun(&self) {
+        self.overruns.fetch_add(1, Ordering::Relaxed);
+        if self.streak.fetch_add(1, Ordering::Relaxed) + 1 == MOTD_OVERRUN_WARNING {
+            rakrs_debug!(
+                "[SERVER] The MOTD provider overran its budget on {} pings in a row! Consider pushing the MOTD with Listener::set_motd(), or evaluating it with Listener::set_motd_refresher().",
+                MOTD_OVERRUN_WARNING
+            );
+        }
+    }
+}
@@ -7,106 +7,116 @@
 #[allow(unused)]
 /// Server events module. Handles things like updating the MOTD
 /// for certain connections. This is a notifier channel.
+pub mod advertisement;
 pub mod event;
+#[cfg(feature = "query")]
+pub mod query;
+pub mod registry;
 
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicI32, AtomicU64, Ordering};
+use std::sync::Mutex as StdMutex;
+use std::time::{Duration, Instant};
 use std::{net::SocketAddr, sync::Arc};
 
 #[cfg(feature = "async_std")]
 use async_std::{
-    channel::{bounded, Receiver, Sender},
+    channel::{bounded, Receiver, Sender, TrySendError},
+    future::timeout,
     net::UdpSocket,
-    sync::Mutex,
-    task::{self},
+    task::{self, sleep},
 };
 #[cfg(feature = "async_std")]
 use futures::{select, FutureExt};
 
 use binary_util::interfaces::{Reader, Writer};
-use binary_util::ByteReader;
+use binary_util::ByteWriter;
 
 #[cfg(feature = "async_tokio")]
 use tokio::{
     net::UdpSocket,
     select,
     sync::mpsc::channel as bounded,
+    sync::mpsc::error::TrySendError,
     sync::mpsc::{Receiver, Sender},
-    sync::Mutex,
     task::{self},
+    time::{sleep, timeout},
 };
 
-use crate::connection::{ConnMeta, Connection};
+use crate::connection::controller::budget::{
+    BudgetShare, BytesPerSec, SendBudget, SendBudgetStats, DEFAULT_SEND_OVERDRAFT,
+};
+use crate::connection::controller::liveness::Janitor;
+use crate::connection::descriptor::SessionDescriptor;
+use crate::connection::event::RakEvent;
+use crate::connection::options::{AddressBinding, ConnOptions, MigrationOptions};
+use crate::connection::{ConnHandle, ConnMeta, Connection};
 use crate::error::server::ServerError;
 use crate::notify::Notify;
+use crate::protocol::ack::Ack;
+use crate::protocol::frame::FramePacket;
+use crate::protocol::index::DatagramSeq;
 use crate::protocol::mcpe::motd::Motd;
 use crate::protocol::packet::offline::{
-    IncompatibleProtocolVersion, OfflinePacket, OpenConnectReply, SessionInfoReply, UnconnectedPong,
+    IncompatibleProtocolVersion, OfflinePacket, OpenConnectReply, OpenConnectRequest,
+    SessionInfoReply, SessionInfoRequest, UnconnectedPong,
 };
-use crate::protocol::packet::RakPacket;
+use crate::protocol::packet::online::{Capabilities, Disconnect};
+use crate::protocol::packet::{PacketId, RakPacket};
+use crate::protocol::primitives::decode;
+use crate::protocol::ranges::SequenceRanges;
 use crate::protocol::Magic;
 use crate::rakrs_debug;
-use crate::util::to_address_token;
-
-pub(crate) type Session = (ConnMeta, Sender<Vec<u8>>);
+pub use crate::util::PossiblySocketAddr;
+use crate::util::{current_epoch, socket, to_address_token};
 
-/// This is a helper enum that allows you to pass in a `SocketAddr` or a `&str` to the `Listener::bind` function.
-/// This is useful for when you want to bind to a specific address, but you don't want to parse it yourself.
-///
-/// This Trait will successfully parse the following:
-/// - `SocketAddr::new("127.0.0.1:19132")`
-/// - `"127.0.0.1:19132"`
-/// - `String::from("127.0.0.1:19132")`
-pub enum PossiblySocketAddr<'a> {
-    SocketAddr(SocketAddr),
-    Str(&'a str),
-    String(String),
-    ActuallyNot,
-}
-
-impl PossiblySocketAddr<'_> {
-    pub fn to_socket_addr(self) -> Option<SocketAddr> {
-        match self {
-            PossiblySocketAddr::SocketAddr(addr) => Some(addr),
-            PossiblySocketAddr::Str(addr) => {
-                // we need to parse it
-                Some(addr.parse::<SocketAddr>().unwrap())
-            }
-            PossiblySocketAddr::String(addr) => {
-                // same as above, except less elegant >_<
-                Some(addr.clone().as_str().parse::<SocketAddr>().unwrap())
-            }
-            _ => None,
-        }
-    }
-}
+use self::advertisement::{
+    Advertisement, MotdProvider, PingFallback, PingReply, PingResponder, DEFAULT_MOTD_BUDGET,
+    DEFAULT_PING_RESPONSE_DEADLINE,
+};
+#[cfg(feature = "query")]
+use self::query::{
+    Query, QueryInfo, QueryInfoProvider, QueryRequest, DEFAULT_QUERY_RATE,
+    DEFAULT_QUERY_TOKEN_LIFETIME,
+};
+use self::registry::{ConnInfo, Registry};
 
-impl From<&str> for PossiblySocketAddr<'_> {
-    fn from(s: &str) -> Self {
-        PossiblySocketAddr::String(s.to_string())
-    }
-}
+pub(crate) type Session = (ConnMeta, Sender<Vec<u8>>, ConnHandle);
 
-impl From<String> for PossiblySocketAddr<'_> {
-    fn from(s: String) -> Self {
-        PossiblySocketAddr::String(s)
-    }
-}
+/// Where the connections that moved are now and how many times they moved, by the address
+/// they were made from, see [`AddressBinding::AllowMigration`].
+type Migrated = StdMutex<HashMap<SocketAddr, (SocketAddr, u32)>>;
 
-impl From<SocketAddr> for PossiblySocketAddr<'_> {
-    fn from(s: SocketAddr) -> Self {
-        PossiblySocketAddr::SocketAddr(s)
-    }
-}
+/// The default time between two passes of the janitor, see [`Listener::janitor_interval`].
+pub const DEFAULT_JANITOR_INTERVAL: Duration = Duration::from_secs(5);
+/// The amount of events a listener holds until [`Listener::recv_event()`] reads them.
+/// A ping handed to the application while they are all unread gets the fallback answer.
+pub const LISTENER_EVENT_BACKLOG: usize = 64;
+/// The amount of datagrams a connection holds until it handled them. The ones past it are
+/// dropped rather than holding up the other connections of the listener, the peer resends
+/// them like lost ones. See [`ListenerStats::datagrams_shed`].
+pub const DATAGRAM_BACKLOG: usize = 128;
+/// The largest mtu a listener agrees to, larger requests are answered with this one.
+pub const MAX_MTU_SIZE: u16 = 2048;
+/// The default amount of receives in a row that have to fail before the socket is given up on,
+/// see [`Listener::socket_failure_threshold`].
+pub const DEFAULT_SOCKET_FAILURE_THRESHOLD: usize = 32;
+/// The amount of datagrams of a connection from another port of its host that are only
+/// counted, the next one is reported, see [`AddressBinding::Strict`].
+pub const SPOOFED_DATAGRAM_THRESHOLD: u64 = 8;
 
-impl std::fmt::Display for PossiblySocketAddr<'_> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            PossiblySocketAddr::SocketAddr(addr) => write!(f, "{}", addr),
-            PossiblySocketAddr::Str(addr) => write!(f, "{}", addr),
-            PossiblySocketAddr::String(addr) => write!(f, "{}", addr),
-            PossiblySocketAddr::ActuallyNot => write!(f, "Not a valid address!"),
-        }
-    }
+/// Statistics collected by a [`Listener`], see [`Listener::stats()`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ListenerStats {
+    /// The amount of pings the MOTD provider couldn't answer within [`Listener::motd_budget`],
+    /// they were answered with the last MOTD instead.
+    pub motd_overruns: u64,
+    /// The amount of datagrams dropped because their connection had [`DATAGRAM_BACKLOG`]
+    /// datagrams it didn't handle yet, like while it waited on the application.
+    pub datagrams_shed: u64,
+    /// The amount of query answers dropped because they exceeded [`Listener::query_rate`].
+    #[cfg(feature = "query")]
+    pub queries_limited: u64,
 }
 
 /// The main server struct, this is responsible for listening to connections, and dispatching them to a handler.
@@ -205,22 +215,88 @@ pub struct Listener {
     /// If mcpe is true, this is the default MOTD, this is
     /// the default MOTD to send to the client. You can change this later by setting
     /// a motd in the `Conn` struct.
+    ///
+    /// This is read when the listener starts, use [`Listener::set_motd()`] once it is running.
     pub motd: Motd,
+    /// How long the closure given to [`Listener::set_motd_provider()`] may take to answer a ping.
+    pub motd_budget: Duration,
+    /// Hands every ping to the application as a [`RakEvent::PingRequest`], read with
+    /// [`Listener::recv_event()`], instead of answering it with the MOTD. This is for MOTDs
+    /// that depend on who is asking, like a banner per region, or hiding the server from
+    /// addresses that are not allowed on it. This is read when the listener starts.
+    ///
+    /// [`RakEvent::PingRequest`]: crate::connection::event::RakEvent::PingRequest
+    pub offline_ping_passthrough: bool,
+    /// How long the application has to answer a [`RakEvent::PingRequest`], after which the ping
+    /// is answered with the [`Listener::ping_fallback`].
+    ///
+    /// [`RakEvent::PingRequest`]: crate::connection::event::RakEvent::PingRequest
+    pub ping_response_deadline: Duration,
+    /// How a ping handed to the application is answered when it isn't in time.
+    pub ping_fallback: PingFallback,
+    /// How long the challenge token of a query handshake is accepted, see [`query`].
+    /// This is read when the listener starts.
+    #[cfg(feature = "query")]
+    pub query_token_lifetime: Duration,
+    /// The bytes per second the answers to queries may take together, the ones past it are
+    /// dropped. This is read when the listener starts.
+    #[cfg(feature = "query")]
+    pub query_rate: BytesPerSec,
+    /// Answers the queries of the listener.
+    #[cfg(feature = "query")]
+    query: Arc<Query>,
+    /// The MOTD that is advertised, shared with the tasks of the listener.
+    advertisement: Arc<Advertisement>,
     /// A server Id, passed in unconnected pong.
     pub id: u64,
     /// Supported versions
     pub versions: &'static [u8],
+    /// The options applied to every connection accepted by this listener.
+    pub conn_options: ConnOptions,
+    /// Limits the bytes sent by all connections together, for hosts with a metered uplink.
+    /// While the budget is contended, every connection gets an equal share of it.
+    /// `None` (the default) doesn't limit anything. This is applied when the listener starts.
+    pub global_send_budget: Option<BytesPerSec>,
+    /// How many bytes immediate frames may send beyond an exhausted [`Listener::global_send_budget`].
+    pub send_budget_overdraft: u64,
+    /// How often the listener looks for connections whose tasks died, like after a panic while
+    /// handling a packet. Such a connection is only removed once it was found dead on two
+    /// passes in a row, and is closed with [`DisconnectReason::Internal`].
+    /// An idle connection is never removed by this, its tasks keep running.
+    ///
+    /// [`DisconnectReason::Internal`]: crate::connection::event::DisconnectReason::Internal
+    pub janitor_interval: Duration,
+    /// How many receives in a row have to fail, with an error that concerns the socket rather
+    /// than a single datagram, before the listener gives up on the socket. It then closes every
+    /// connection with [`DisconnectReason::SocketFailure`] and stops, see
+    /// [`Listener::wait_stopped()`]. This is read when the listener starts.
+    ///
+    /// [`DisconnectReason::SocketFailure`]: crate::connection::event::DisconnectReason::SocketFailure
+    pub socket_failure_threshold: usize,
+    /// The kind of error the socket failed with, once the listener gave up on it.
+    socket_failure: Arc<StdMutex<Option<std::io::ErrorKind>>>,
+    /// The OS error every receive fails with, zero when they don't, see [`Listener::fail_recvs()`].
+    recv_fault: Arc<AtomicI32>,
+    /// The amount of datagrams dropped for a connection that fell behind.
+    datagrams_shed: Arc<AtomicU64>,
+    /// The bucket shared by every connection, once the listener started with a budget.
+    send_budget: Option<Arc<StdMutex<SendBudget>>>,
     /// Whether or not the server is being served.
     serving: bool,
     /// The current socket.
     sock: Option<Arc<UdpSocket>>,
     /// A Hashmap off all current connections along with a sending channel
     /// and some meta data like the time of connection, and the requested MTU_Size
-    connections: Arc<Mutex<HashMap<SocketAddr, Session>>>,
+    connections: Arc<Registry<Session>>,
+    /// Sessions imported from another listener, these are resumed once the listener starts.
+    imported: Vec<SessionDescriptor>,
     /// The recieve communication channel, This is used to dispatch connections between a handle
     /// It allows you to use the syntax sugar for `Listener::accept()`.
     recv_comm: Receiver<Connection>,
     send_comm: Sender<Connection>,
+    /// The events of the listener itself, read with [`Listener::recv_event()`].
+    recv_event: Receiver<RakEvent>,
+    send_event: Sender<RakEvent>,
     // TODO, fix this!
     // send_evnt: Sender<(ServerEvent, oneshot::Sender<ServerEventResponse>)>,
     // pub recv_evnt: Arc<Mutex<mpsc::Receiver<(ServerEvent, oneshot::Sender<ServerEventResponse>)>>>,
@@ -233,6 +309,11 @@ pub struct Listener {
     // cleanup: Arc<Condvar>,
 }
 
+/// Another name for [`Listener`].
+pub type Server = Listener;
+/// Another name for [`Listener`].
+pub type RakNetServer = Listener;
+
 impl Listener {
     /// Binds a new listener to the specified address provided, this will error if the address is invalid or already in use.
     /// This will not start the listener, you must call [`Listener::start`] to start listening to connections.
@@ -250,6 +331,31 @@ impl Listener {
     /// [`Listener::start`]: struct.Listener.html#method.start
     pub async fn bind<I: for<'a> Into<PossiblySocketAddr<'a>>>(
         address: I,
+    ) -> Result<Self, ServerError> {
+        Self::bind_with_options(address, ConnOptions::default()).await
+    }
+
+    /// Binds a new listener like [`Listener::bind`], using the given options for the socket,
+    /// and for every connection that is accepted.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// use rak_rs::connection::options::ConnOptions;
+    /// use rak_rs::server::Listener;
+    ///
+    /// async fn start() {
+    ///     let options = ConnOptions {
+    ///         bind_device: Some("eth0".to_string()),
+    ///         ..Default::default()
+    ///     };
+    ///     let mut server = Listener::bind_with_options("0.0.0.0:19132", options).await.unwrap();
+    /// }
+    /// ```
+    ///
+    /// [`Listener::bind`]: struct.Listener.html#method.bind
+    pub async fn bind_with_options<I: for<'a> Into<PossiblySocketAddr<'a>>>(
+        address: I,
+        options: ConnOptions,
     ) -> Result<Self, ServerError> {
         let a: PossiblySocketAddr = address.into();
         let address_r: Option<SocketAddr> = a.to_socket_addr();
@@ -260,9 +366,24 @@ impl Listener {
 
         let address = address_r.unwrap();
 
-        let sock = match UdpSocket::bind(address).await {
-            Ok(s) => s,
-            Err(_) => return Err(ServerError::AddrBindErr),
+        let sock = match options.bind_device.as_deref() {
+            Some(device) => {
+                match socket::bind_udp(address, Some(device)).and_then(socket::into_async) {
+                    Ok(s) => s,
+                    Err(e) => {
+                        rakrs_debug!("listener: {}", e);
+                        return Err(match e.kind() {
+                            std::io::ErrorKind::AddrInUse
+                            | std::io::ErrorKind::AddrNotAvailable => ServerError::AddrBindErr,
+                            _ => ServerError::BindDeviceErr,
+                        });
+                    }
+                }
+            }
+            None => match UdpSocket::bind(address).await {
+                Ok(s) => s,
+                Err(_) => return Err(ServerError::AddrBindErr),
+            },
         };
 
         rakrs_debug!(true, "listener: Bound to {}", address);
@@ -272,6 +393,7 @@ impl Listener {
 
         // This channel is a Communication channel for when `Connection` structs are initialized.
         let (send_comm, recv_comm) = bounded::<Connection>(10);
+        let (send_event, recv_event) = bounded::<RakEvent>(LISTENER_EVENT_BACKLOG);
         // This channel is responsible for handling and dispatching events between clients.
         // Oneshot will garauntee this event is intended for the client whom requested the event.
         // TODO: Fix with new event system
@@ -282,13 +404,36 @@ impl Listener {
             sock: Some(Arc::new(sock)),
             id: server_id,
             versions: &[10, 11],
+            conn_options: options,
+            global_send_budget: None,
+            send_budget_overdraft: DEFAULT_SEND_OVERDRAFT,
+            janitor_interval: DEFAULT_JANITOR_INTERVAL,
+            socket_failure_threshold: DEFAULT_SOCKET_FAILURE_THRESHOLD,
+            socket_failure: Arc::new(StdMutex::new(None)),
+            recv_fault: Arc::new(AtomicI32::new(0)),
+            datagrams_shed: Arc::new(AtomicU64::new(0)),
+            send_budget: None,
+            advertisement: Arc::new(Advertisement::new(motd.clone())),
             motd,
+            motd_budget: DEFAULT_MOTD_BUDGET,
+            offline_ping_passthrough: false,
+            ping_response_deadline: DEFAULT_PING_RESPONSE_DEADLINE,
+            ping_fallback: PingFallback::Cached,
+            #[cfg(feature = "query")]
+            query_token_lifetime: DEFAULT_QUERY_TOKEN_LIFETIME,
+            #[cfg(feature = "query")]
+            query_rate: DEFAULT_QUERY_RATE,
+            #[cfg(feature = "query")]
+            query: Arc::new(Query::new(DEFAULT_QUERY_TOKEN_LIFETIME, DEFAULT_QUERY_RATE)),
             send_comm,
             recv_comm,
+            send_event,
+            recv_event,
             // send_evnt,
             // recv_evnt: Arc::new(Mutex::new(recv_evnt)),
             serving: false,
-            connections: Arc::new(Mutex::new(HashMap::new())),
+            connections: Arc::new(Registry::default()),
+            imported: Vec::new(),
             // closer: Arc::new(Semaphore::new(0)),
             closed: Arc::new(Notify::new()),
             // cleanup: Arc::new(Notify::new()),
@@ -323,13 +468,45 @@ impl Listener {
         let send_comm = self.send_comm.clone();
         // let send_evt = self.send_evnt.clone();
         let server_id = self.id.clone();
-        #[cfg(feature = "mcpe")]
-        let default_motd = self.motd.clone();
+        let advertisement = self.advertisement.clone();
+        #[cfg(any(feature = "mcpe", feature = "query"))]
+        let motd_budget = self.motd_budget;
+        #[cfg(feature = "query")]
+        let query = self.query.clone();
+        #[cfg(feature = "query")]
+        self.query
+            .configure(self.query_token_lifetime, self.query_rate);
+        let send_event = self.send_event.clone();
+        let ping_passthrough = self.offline_ping_passthrough;
+        let ping_deadline = self.ping_response_deadline;
+        let ping_fallback = self.ping_fallback;
+        // a refresher that runs already knows better than the motd the listener was bound with.
+        if !self.advertisement.is_refreshed() {
+            self.advertisement.set(self.motd.clone());
+        }
         let connections = self.connections.clone();
         let closer = self.closed.clone();
         let connections2 = self.connections.clone();
         let closer2 = self.closed.clone();
+        let migrated = Arc::new(Migrated::default());
+        let migrated2 = migrated.clone();
         let versions = self.versions.clone();
+        let conn_options = self.conn_options.clone();
+        let ecn = socket::apply_ecn_async(&*socket, conn_options.enable_ecn);
+        let read_budget = self.conn_options.read_budget.max(1);
+        self.send_budget = self.global_send_budget.map(|rate| {
+            Arc::new(StdMutex::new(SendBudget::new(
+                rate,
+                self.send_budget_overdraft,
+                Instant::now(),
+            )))
+        });
+        let send_budget = self.send_budget.clone();
+        let janitor_interval = self.janitor_interval;
+        let failure_threshold = self.socket_failure_threshold.max(1);
+        let socket_failure = self.socket_failure.clone();
+        let recv_fault = self.recv_fault.clone();
+        let datagrams_shed = self.datagrams_shed.clone();
 
         self.serving = true;
 
@@ -339,46 +516,153 @@ impl Listener {
         let (cs, mut client_close_recv) = bounded::<SocketAddr>(10);
         let client_close_send = Arc::new(cs);
 
+        // sessions handed over by another listener are established right away,
+        // so the first datagram of their peers is not mistaken for an unknown session.
+        let mut resumed = Vec::new();
+        for descriptor in self.imported.drain(..) {
+            let (net_send, net_recv) = bounded::<Vec<u8>>(DATAGRAM_BACKLOG);
+            let connection = Connection::new(
+                descriptor.address,
+                &socket,
+                net_recv,
+                client_close_send.clone(),
+                descriptor.mtu_size,
+                conn_options.clone(),
+            )
+            .await
+            .with_ecn(ecn);
+            connection.restore(&descriptor).await;
+            connection
+                .set_send_budget(send_budget.clone().map(BudgetShare::new))
+                .await;
+            rakrs_debug!(true, "Resumed imported session for {}", descriptor.address);
+
+            self.connections
+                .insert(
+                    descriptor.address,
+                    (descriptor.meta(), net_send, connection.handle()),
+                )
+                .await;
+            resumed.push(connection);
+        }
+
+        if !resumed.is_empty() {
+            let send_comm = send_comm.clone();
+            task::spawn(async move {
+                for connection in resumed {
+                    if send_comm.send(connection).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
         task::spawn(async move {
             // We allocate here to prevent constant allocation of this array
             let mut buf: [u8; 2048] = [0; 2048];
-            #[cfg(feature = "mcpe")]
-            let motd_default = default_motd.clone();
+            // the datagrams handled since this loop last yielded.
+            let mut handled: usize = 0;
+            // the receives in a row that failed because of the socket.
+            let mut failures: usize = 0;
             loop {
                 let length: usize;
                 let origin: SocketAddr;
 
+                // a burst of traffic keeps the socket ready, so this loop has to give the
+                // other tasks on the runtime (like the connection ticks) a chance to run.
+                if handled >= read_budget {
+                    handled = 0;
+                    task::yield_now().await;
+                }
+                handled += 1;
+
                 macro_rules! recv_body {
                     ($recv: ident) => {
                         match $recv {
                             Ok((l, o)) => {
                                 length = l;
                                 origin = o;
+                                failures = 0;
                             }
                             Err(e) => {
-                                match e.kind() {
-                                    std::io::ErrorKind::ConnectionReset => {
-                                        continue;
-                                    },
-                                    _ => {
-                                        rakrs_debug!(true, "[SERVER-SOCKET] Failed to recieve packet! {}", e);
-                                        continue;
-                                    }
+                                if !socket::is_fatal_recv_error(&e) {
+                                    failures = 0;
+                                    continue;
                                 }
+                                rakrs_debug!(true, "[SERVER-SOCKET] Failed to recieve packet! {}", e);
+                                failures += 1;
+                                if failures < failure_threshold {
+                                    continue;
+                                }
+
+                                // the socket is gone, nothing will ever reach the connections again.
+                                rakrs_debug!("[SERVER-SOCKET] The socket failed {} times in a row, shutting down! {}", failures, e);
+                                *socket_failure.lock().unwrap() = Some(e.kind());
+                                let handles = connections
+                                    .snapshot(|_, (_, _, handle)| handle.clone())
+                                    .await;
+                                for handle in handles {
+                                    handle.fail().await;
+                                }
+                                connections.clear().await;
+                                closer.notify().await;
+                                break;
                             }
                         }
 
                         // Do a quick check to see if this a valid raknet packet, otherwise we're going to handle it normally
-                        if let Ok(pk) = OfflinePacket::read(&mut ByteReader::from(&buf[..length])) {
+                        let offline = decode::<OfflinePacket>(&buf[..length]);
+                        if let Err(error) = &offline {
+                            if !error.is_unknown_packet() {
+                                rakrs_debug!(
+                                    true,
+                                    "[{}] Failed to decode an offline packet, {}: {}",
+                                    to_address_token(origin),
+                                    error,
+                                    error.hexdump(&buf[..length])
+                                );
+                            }
+                        }
+                        if let Ok(pk) = offline {
                             // Offline packets are not buffered to the user.
                             // The reason for this is because we don't wish for the user to be able to disrupt
                             // raknet protocol, and handshaking.
                             match pk {
-                                OfflinePacket::UnconnectedPing(_) => {
+                                OfflinePacket::UnconnectedPing(ping) => {
+                                    if ping_passthrough {
+                                        let fallback = match ping_fallback {
+                                            PingFallback::Cached => PingReply::Motd(advertisement.cached()),
+                                            PingFallback::Silence => PingReply::Decline,
+                                        };
+                                        let (respond, reply) = PingResponder::new();
+                                        let event = RakEvent::PingRequest {
+                                            addr: origin,
+                                            client_guid: ping.client_id,
+                                            respond,
+                                        };
+
+                                        // the application is behind on its events, it would not answer in time.
+                                        if send_event.try_send(event).is_err() {
+                                            send_pong(&socket, origin, server_id, fallback).await;
+                                            continue;
+                                        }
+
+                                        let socket = socket.clone();
+                                        task::spawn(async move {
+                                            let reply = match timeout(ping_deadline, reply).await {
+                                                Ok(Ok(reply)) => reply,
+                                                // too late, or the responder was dropped without an answer.
+                                                _ => fallback,
+                                            };
+                                            send_pong(&socket, origin, server_id, reply).await;
+                                        });
+                                        continue;
+                                    }
+
                                     // let (resp_tx, resp_rx) =
                                     //     oneshot::channel::<ServerEventResponse>();
                                     #[cfg(feature = "mcpe")]
-                                    let motd: Motd = motd_default.clone();
+                                    let motd: Motd = advertisement.current(motd_budget).await;
 
                                     // if let Err(e) = send_evt.try_send((
                                     //         ServerEvent::RefreshMotdRequest(origin, motd.clone()),
@@ -421,100 +705,45 @@ impl Listener {
                                     send_packet_to_socket(&socket, resp.into(), origin).await;
                                     continue;
                                 }
-                                OfflinePacket::OpenConnectRequest(mut pk) => {
-                                    // TODO make a constant for this
-                                    if !versions.contains(&pk.protocol) {
-                                        let resp = IncompatibleProtocolVersion {
-                                            protocol: pk.protocol,
-                                            magic: Magic::new(),
-                                            server_id,
-                                        };
-
-                                        rakrs_debug!("[{}] Sent ({}) which is invalid RakNet protocol. Version is incompatible with server.", pk.protocol, to_address_token(*&origin));
-
-                                        send_packet_to_socket(&socket, resp.into(), origin).await;
-                                        continue;
-                                    }
-
-                                    rakrs_debug!(
-                                        true,
-                                        "[{}] Client requested Mtu Size: {}",
-                                        to_address_token(*&origin),
-                                        pk.mtu_size
-                                    );
-
-                                    if pk.mtu_size > 2048 {
-                                        rakrs_debug!(
-                                            true,
-                                            "[{}] Client requested Mtu Size: {} which is larger than the maximum allowed size of 2048",
-                                            to_address_token(*&origin),
-                                            pk.mtu_size
-                                        );
-                                        pk.mtu_size = 2048;
-                                    }
-
-                                    let resp = OpenConnectReply {
-                                        server_id,
-                                        // TODO allow encryption
-                                        security: false,
-                                        magic: Magic::new(),
-                                        // TODO make this configurable, this is sent to the client to change
-                                        // it's mtu size, right now we're using what the client prefers.
-                                        // however in some cases this may not be the preferred use case, for instance
-                                        // on servers with larger worlds, you may want a larger mtu size, or if
-                                        // your limited on network bandwith
-                                        mtu_size: pk.mtu_size,
-                                    };
-                                    send_packet_to_socket(&socket, resp.into(), origin).await;
+                                OfflinePacket::OpenConnectRequest(pk) => {
+                                    // until the session exists every request is answered on its own, so a client
+                                    // probing upwards gets the larger mtu it asks for. The session locks it.
+                                    let locked = connections.with(&origin, |session| session.0.mtu_size).await;
+                                    let resp = open_connect_reply(&versions, server_id, origin, pk, locked);
+                                    send_packet_to_socket(&socket, resp, origin).await;
                                     continue;
                                 }
                                 OfflinePacket::SessionInfoRequest(pk) => {
-                                    let resp = SessionInfoReply {
-                                        server_id,
-                                        client_address: origin,
-                                        magic: Magic::new(),
-                                        mtu_size: pk.mtu_size,
-                                        security: false,
-                                    };
-
                                     // This is a valid packet, let's check if a session exists, if not, we should create it.
                                     // Event if the connection is only in offline mode.
-                                    let mut sessions = connections.lock().await;
+                                    // The sessions are never locked across an await, so a slow connection can't stall this loop.
+                                    let locked = connections.with(&origin, |session| session.0.mtu_size).await;
+                                    let resp = session_info_reply(server_id, origin, &pk, locked);
 
-                                    if !sessions.contains_key(&origin) {
+                                    if locked.is_none() {
                                         rakrs_debug!(true, "Creating new session for {}", origin);
-                                        let meta = ConnMeta::new(0);
-                                        let (net_send, net_recv) = bounded::<Vec<u8>>(10);
+                                        let meta = ConnMeta::new(resp.mtu_size);
+                                        let (net_send, net_recv) = bounded::<Vec<u8>>(DATAGRAM_BACKLOG);
                                         let connection =
-                                            Connection::new(origin, &socket, net_recv, client_close_send.clone(), pk.mtu_size).await;
+                                            Connection::new(origin, &socket, net_recv, client_close_send.clone(), resp.mtu_size, conn_options.clone()).await.with_ecn(ecn);
+                                        connection.set_send_budget(send_budget.clone().map(BudgetShare::new)).await;
                                         rakrs_debug!(true, "Created Session for {}", origin);
 
                                         // Add the connection to the available connections list.
                                         // we're using the name "sessions" here to differeniate
                                         // for some reason the reciever likes to be dropped, so we're saving it here.
-                                        sessions.insert(origin, (meta, net_send));
+                                        connections.insert(origin, (meta, net_send, connection.handle())).await;
 
                                         // notify the connection communicator
                                         if let Err(err) = send_comm.send(connection).await {
                                             let connection = err.0;
                                             // there was an error, and we should terminate this connection immediately.
                                             rakrs_debug!("[{}] Error while communicating with internal connection channel! Connection withdrawn.", to_address_token(connection.address));
-                                            sessions.remove(&origin);
+                                            connections.remove(&origin).await;
                                             continue;
                                         }
                                     }
 
-                                    // update the sessions mtuSize, this is referred to internally, we also will send this event to the client
-                                    // event channel. However we are not expecting a response.
-
-                                    sessions.get_mut(&origin).unwrap().0.mtu_size = pk.mtu_size;
-                                    rakrs_debug!(
-                                        true,
-                                        "[{}] Updated mtu size to {}",
-                                        to_address_token(origin),
-                                        pk.mtu_size
-                                    );
-
                                     // let (resp_tx, resp_rx) = oneshot::channel::<ServerEventResponse>();
 
                                     // if let Err(_) = timeout(Duration::from_millis(5), resp_rx).await {
@@ -546,14 +775,56 @@ impl Listener {
                         }
 
                         // Packet may be valid, but we'll let the connection decide this
-                        let mut sessions = connections.lock().await;
-                        if sessions.contains_key(&origin) {
-                            if let Err(_) = sessions[&origin].1.send(buf[..length].to_vec()).await {
-                                rakrs_debug!(true, "[{}] Failed when handling recieved packet! Could not pass over to internal connection, the channel might be closed! (Removed the connection)", to_address_token(*&origin));
-                                sessions.remove(&origin);
+                        let mut net = connections.with(&origin, |session| session.1.clone()).await;
+                        // a frame set of a session from another port of its host is spoofed, unless
+                        // the session may follow its peer there.
+                        if net.is_none() {
+                            if let Some((bound, session)) = bound_session(&connections, origin, &buf[..length]).await {
+                                match conn_options.address_binding {
+                                    AddressBinding::AllowMigration(options)
+                                        if migrate(&connections, &migrated, bound, origin, &session.2, options).await =>
+                                    {
+                                        rakrs_debug!(true, "[{}] Connection moved to {}", to_address_token(bound), to_address_token(origin));
+                                        net = Some(session.1);
+                                    }
+                                    _ => {
+                                        session.2.spoofed(bound, origin);
+                                        continue;
+                                    }
+                                }
+                            }
+                        }
+                        #[cfg(feature = "query")]
+                        if net.is_none() && QueryRequest::is_query(&buf[..length]) {
+                            query
+                                .answer(&buf[..length], origin, &socket, &advertisement, motd_budget)
+                                .await;
+                            continue;
+                        }
+                        // a peer that closed resends its disconnect until it is acknowledged, the
+                        // session may be gone already when it does.
+                        if net.is_none() {
+                            if let Some(ack) = disconnect_ack(&buf[..length]) {
+                                let _ = socket.send_to(&ack, origin).await;
+                                continue;
+                            }
+                        }
+                        if let Some(net) = net {
+                            // a connection that fell behind drops what doesn't fit, this loop
+                            // is shared by every connection and never waits on one of them.
+                            match net.try_send(buf[..length].to_vec()) {
+                                Ok(()) => {}
+                                Err(TrySendError::Full(_)) => {
+                                    datagrams_shed.fetch_add(1, Ordering::Relaxed);
+                                }
+                                Err(TrySendError::Closed(_)) => {
+                                    rakrs_debug!(true, "[{}] Failed when handling recieved packet! Could not pass over to internal connection, the channel might be closed! (Removed the connection)", to_address_token(*&origin));
+                                    if let Some((_, _, handle)) = connections.remove(&origin).await {
+                                        handle.reclaim().await;
+                                    }
+                                }
                             }
                         }
-                        drop(sessions);
                     };
                 }
 
@@ -563,7 +834,7 @@ impl Listener {
                         rakrs_debug!(true, "[SERVER] [NETWORK] Server has recieved the shutdown notification!");
                         break;
                     }
-                    recv = socket.recv_from(&mut buf).fuse() => {
+                    recv = recv_from(&socket, &mut buf, &recv_fault).fuse() => {
                        recv_body!(recv);
                     }
                 }
@@ -574,7 +845,7 @@ impl Listener {
                         rakrs_debug!(true, "[SERVER] [NETWORK] Server has recieved the shutdown notification!");
                         break;
                     }
-                    recv = socket.recv_from(&mut buf) => {
+                    recv = recv_from(&socket, &mut buf, &recv_fault) => {
                         recv_body!(recv);
                     }
                 }
@@ -584,7 +855,36 @@ impl Listener {
         task::spawn(async move {
             // here we loop and recv from the client_close_recv channel
             // and remove the connection from the hashmap
+            let mut janitor = Janitor::new();
+            let mut next_pass = Instant::now() + janitor_interval;
             loop {
+                macro_rules! janitor_pass {
+                    () => {
+                        next_pass = Instant::now() + janitor_interval;
+                        let handles = connections2
+                            .snapshot(|address, (_, _, handle)| (*address, handle.clone()))
+                            .await;
+                        let dead = janitor.pass(
+                            handles
+                                .iter()
+                                .map(|(address, handle)| (*address, handle.liveness())),
+                        );
+                        for (address, dead) in handles.iter().filter(|(address, _)| dead.contains(address)) {
+                            // only the connection that was found dead, not one that replaced it since.
+                            let removed = connections2
+                                .remove_if(address, |(_, _, handle)| handle.is(dead))
+                                .await;
+                            if let Some((_, _, handle)) = removed {
+                                rakrs_debug!(true, "[SERVER] [Cleanup] Reclaiming connection for {}", to_address_token(*address));
+                                migrated2.lock().unwrap().remove(&handle.address());
+                                handle.reclaim().await;
+                            }
+                        }
+                    };
+                }
+
+                let until_pass = next_pass.saturating_duration_since(Instant::now());
+
                 #[cfg(feature = "async_std")]
                 select! {
                     _ = closer2.wait().fuse() => {
@@ -593,12 +893,15 @@ impl Listener {
                     }
                     addr = client_close_recv.recv().fuse() => {
                         if let Ok(addr) = addr {
+                            // a connection that moved is told apart by the address it was made from.
+                            let addr = migrated2.lock().unwrap().remove(&addr).map_or(addr, |(current, _)| current);
                             rakrs_debug!(true, "[SERVER] [Cleanup] Removing connection for {}", to_address_token(addr));
-                            let mut c = connections2.lock().await;
-                            c.remove(&addr);
-                            drop(c);
+                            connections2.remove(&addr).await;
                         }
                     }
+                    _ = sleep(until_pass).fuse() => {
+                        janitor_pass!();
+                    }
                 }
 
                 #[cfg(feature = "async_tokio")]
@@ -609,14 +912,26 @@ impl Listener {
                     }
                     addr = client_close_recv.recv() => {
                         if let Some(addr) = addr {
+                            // a connection that moved is told apart by the address it was made from.
+                            let addr = migrated2.lock().unwrap().remove(&addr).map_or(addr, |(current, _)| current);
                             rakrs_debug!(true, "[SERVER] [Cleanup] Removing connection for {}", to_address_token(addr));
-                            let mut c = connections2.lock().await;
-                            c.remove(&addr);
-                            drop(c);
+                            connections2.remove(&addr).await;
                         }
                     }
+                    _ = sleep(until_pass) => {
+                        janitor_pass!();
+                    }
                 }
             }
+
+            // the sessions that are left share the socket, so they can't outlive the listener.
+            let handles = connections2
+                .snapshot(|_, (_, _, handle)| handle.clone())
+                .await;
+            for handle in handles {
+                handle.abandon().await;
+            }
+            connections2.clear().await;
         });
 
         return Ok(());
@@ -684,20 +999,313 @@ impl Listener {
         if !self.serving {
             Err(ServerError::NotListening)
         } else {
-            let receiver = self.recv_comm.recv().await;
-            return match receiver {
-                #[cfg(feature = "async_std")]
-                Ok(c) => Ok(c),
+            let closed = self.closed.clone();
+            // a listener only stops on its own once its socket failed.
+            #[cfg(feature = "async_std")]
+            let receiver = select! {
+                c = self.recv_comm.recv().fuse() => c.ok(),
+                _ = closed.wait().fuse() => None,
+            };
+            #[cfg(feature = "async_tokio")]
+            let receiver = select! {
+                c = self.recv_comm.recv() => c,
+                _ = closed.wait() => None,
+            };
+            receiver.ok_or_else(|| self.stopped_error())
+        }
+    }
+
+    /// Recieves the next event of the listener itself, like a [`RakEvent::PingRequest`] when
+    /// [`Listener::offline_ping_passthrough`] is enabled. The events of a connection are read
+    /// from the connection.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// use rak_rs::connection::event::RakEvent;
+    ///
+    /// async fn answer_pings(mut server: Listener) {
+    ///     while let Ok(RakEvent::PingRequest { addr, respond, .. }) = server.recv_event().await {
+    ///         if addr.ip().is_loopback() {
+    ///             respond.respond(Motd::new(0, "19132"));
+    ///         } else {
+    ///             respond.decline();
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// [`RakEvent::PingRequest`]: crate::connection::event::RakEvent::PingRequest
+    pub async fn recv_event(&mut self) -> Result<RakEvent, ServerError> {
+        if !self.serving {
+            return Err(ServerError::NotListening);
+        }
+
+        let closed = self.closed.clone();
+        #[cfg(feature = "async_std")]
+        let event = select! {
+            event = self.recv_event.recv().fuse() => event.ok(),
+            _ = closed.wait().fuse() => None,
+        };
+        #[cfg(feature = "async_tokio")]
+        let event = select! {
+            event = self.recv_event.recv() => event,
+            _ = closed.wait() => None,
+        };
+        event.ok_or_else(|| self.stopped_error())
+    }
+
+    /// Waits until the listener stopped on its own, which it does once its socket failed for
+    /// good, see [`Listener::socket_failure_threshold`]. Resolves with
+    /// [`ServerError::SocketFailed`] then, and with `Ok` once [`Listener::stop`] was called.
+    pub async fn wait_stopped(&self) -> Result<(), ServerError> {
+        if self.serving {
+            self.closed.wait().await;
+        }
+        match *self.socket_failure.lock().unwrap() {
+            Some(kind) => Err(ServerError::SocketFailed(kind)),
+            None => Ok(()),
+        }
+    }
+
+    /// Why a listener that stopped can't be used anymore.
+    fn stopped_error(&self) -> ServerError {
+        match *self.socket_failure.lock().unwrap() {
+            Some(kind) => ServerError::SocketFailed(kind),
+            None => ServerError::Killed,
+        }
+    }
+
+    /// Makes every receive of the listener fail with the OS error `code` from the next one on,
+    /// as if its socket was closed out from under it. Only meant for testing how the listener
+    /// copes with that.
+    #[doc(hidden)]
+    pub fn fail_recvs(&self, code: i32) {
+        self.recv_fault.store(code, Ordering::Relaxed);
+    }
+
+    /// Describes every established session, and stops them without notifying their peers,
+    /// so they can be resumed by another listener with [`Listener::import_sessions`].
+    ///
+    /// Payloads that are still in flight are not part of a descriptor, so this should be called
+    /// once the connections have drained. Sessions that are still handshaking are left alone.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// use rak_rs::server::Listener;
+    ///
+    /// async fn upgrade(mut old: Listener, mut new: Listener) {
+    ///     let sessions = old.export_sessions().await;
+    ///     old.stop().await.unwrap();
+    ///
+    ///     new.import_sessions(sessions).unwrap();
+    ///     new.start().await.unwrap();
+    /// }
+    /// ```
+    ///
+    /// [`Listener::import_sessions`]: struct.Listener.html#method.import_sessions
+    pub async fn export_sessions(&self) -> Vec<SessionDescriptor> {
+        let handles = self
+            .connections
+            .snapshot(|_, (_, _, handle)| handle.clone())
+            .await;
+        let mut exported = Vec::new();
+
+        for handle in handles {
+            if let Some(descriptor) = handle.export().await {
+                handle.detach().await;
+                self.connections.remove(&descriptor.address).await;
+                rakrs_debug!(
+                    true,
+                    "Exported session for {}",
+                    to_address_token(descriptor.address)
+                );
+                exported.push(descriptor);
+            }
+        }
+
+        exported
+    }
+
+    /// Imports sessions exported by another listener with [`Listener::export_sessions`].
+    /// The sessions are resumed when the listener starts, and handed out by [`Listener::accept`]
+    /// like any other connection, without their peers handshaking again.
+    ///
+    /// This must be called before [`Listener::start`], and the listener is expected to receive
+    /// the datagrams of the peers, for instance by being bound to the same address.
+    ///
+    /// [`Listener::export_sessions`]: struct.Listener.html#method.export_sessions
+    /// [`Listener::accept`]: struct.Listener.html#method.accept
+    /// [`Listener::start`]: struct.Listener.html#method.start
+    pub fn import_sessions(&mut self, sessions: Vec<SessionDescriptor>) -> Result<(), ServerError> {
+        if self.serving {
+            return Err(ServerError::AlreadyOnline);
+        }
+
+        self.imported.extend(sessions);
+        Ok(())
+    }
+
+    /// Advertises `motd` from now on, this is the cheapest way to keep the MOTD up to date.
+    /// A provider set with [`Listener::set_motd_provider()`] replaces it on its next ping.
+    pub fn set_motd(&mut self, motd: Motd) {
+        self.advertisement.set(motd.clone());
+        self.motd = motd;
+    }
+
+    /// Evaluates `provider` to answer every ping, instead of advertising a fixed MOTD.
+    ///
+    /// The provider must not be async, and has [`Listener::motd_budget`] to answer. A ping it
+    /// can't answer in time gets the MOTD it produced last, and counts towards
+    /// [`ListenerStats::motd_overruns`]. If the provider is slow, like when it queries a database,
+    /// prefer [`Listener::set_motd_refresher()`].
+    pub fn set_motd_provider<F>(&mut self, provider: F)
+    where
+        F: Fn() -> Motd + Send + Sync + 'static,
+    {
+        self.advertisement
+            .set_provider(Some(Arc::new(provider) as MotdProvider));
+    }
+
+    /// Evaluates `provider` to answer every full stat of the [`query`] protocol, instead of
+    /// answering with [`QueryInfo::default()`].
+    ///
+    /// The provider is evaluated like the one of [`Listener::set_motd_provider()`], with
+    /// [`Listener::motd_budget`] to answer. A query it can't answer in time gets the info it
+    /// produced last.
+    #[cfg(feature = "query")]
+    pub fn set_query_provider<F>(&mut self, provider: F)
+    where
+        F: Fn() -> QueryInfo + Send + Sync + 'static,
+    {
+        self.query
+            .set_provider(Some(Arc::new(provider) as QueryInfoProvider));
+    }
+
+    /// Evaluates `refresher` right away, and every `interval` after, advertising the MOTD it
+    /// produces. It runs on a task of its own, so it never holds up a ping however long it takes.
+    ///
+    /// This replaces a provider set with [`Listener::set_motd_provider()`], and the previous
+    /// refresher. It stops once the listener is stopped.
+    pub fn set_motd_refresher<F>(&mut self, interval: Duration, refresher: F)
+    where
+        F: Fn() -> Motd + Send + Sync + 'static,
+    {
+        let advertisement = self.advertisement.clone();
+        let closer = self.closed.clone();
+        let refresher = Arc::new(refresher);
+        advertisement.set_provider(None);
+        let generation = advertisement.next_refresher();
+
+        task::spawn(async move {
+            while advertisement.is_refresher(generation) {
+                let refresher = refresher.clone();
                 #[cfg(feature = "async_std")]
-                Err(_) => Err(ServerError::Killed),
+                let motd = task::spawn_blocking(move || refresher()).await;
                 #[cfg(feature = "async_tokio")]
-                Some(c) => Ok(c),
+                let motd = match task::spawn_blocking(move || refresher()).await {
+                    Ok(motd) => motd,
+                    Err(_) => break,
+                };
+                // replaced while it was evaluated, the new refresher has the last word.
+                if !advertisement.is_refresher(generation) {
+                    break;
+                }
+                advertisement.set(motd);
+
+                #[cfg(feature = "async_std")]
+                select! {
+                    _ = closer.wait().fuse() => break,
+                    _ = sleep(interval).fuse() => {}
+                }
+
                 #[cfg(feature = "async_tokio")]
-                None => Err(ServerError::Killed),
-            };
+                select! {
+                    _ = closer.wait() => break,
+                    _ = sleep(interval) => {}
+                }
+            }
+        });
+    }
+
+    /// The statistics the listener collected since it was bound.
+    pub fn stats(&self) -> ListenerStats {
+        ListenerStats {
+            motd_overruns: self.advertisement.overruns(),
+            datagrams_shed: self.datagrams_shed.load(Ordering::Relaxed),
+            #[cfg(feature = "query")]
+            queries_limited: self.query.limited(),
         }
     }
 
+    /// How the [`Listener::global_send_budget`] has been used since the listener started,
+    /// `None` if it was started without one.
+    pub fn send_budget_stats(&self) -> Option<SendBudgetStats> {
+        self.send_budget.as_ref().map(|budget| {
+            budget
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .stats(Instant::now())
+        })
+    }
+
+    /// Describes every connection of the listener, including the ones still handshaking.
+    ///
+    /// This copies a [`ConnInfo`] for every connection, which is O(n) in the amount of connections.
+    /// With many connections, prefer [`Listener::for_each_connection`] or [`Listener::connections_page`].
+    ///
+    /// [`ConnInfo`]: registry/struct.ConnInfo.html
+    /// [`Listener::for_each_connection`]: struct.Listener.html#method.for_each_connection
+    /// [`Listener::connections_page`]: struct.Listener.html#method.connections_page
+    pub async fn connections(&self) -> Vec<ConnInfo> {
+        self.connections.snapshot(conn_info).await
+    }
+
+    /// Calls `f` with every connection of the listener, without copying all of them at once.
+    ///
+    /// The connections are visited a shard at a time, and `f` is called without any lock held,
+    /// so it may use the listener. A panic in `f` is caught, and the remaining connections are
+    /// still visited.
+    ///
+    /// ## Example
+    /// ```ignore
+    /// use rak_rs::server::Listener;
+    ///
+    /// async fn count_large(server: &Listener) -> usize {
+    ///     let mut large = 0;
+    ///     server
+    ///         .for_each_connection(|info| {
+    ///             if info.mtu_size > 1200 {
+    ///                 large += 1;
+    ///             }
+    ///         })
+    ///         .await;
+    ///     large
+    /// }
+    /// ```
+    pub async fn for_each_connection(&self, f: impl FnMut(&ConnInfo)) {
+        let panicked = self.connections.for_each(conn_info, f).await;
+        if panicked > 0 {
+            rakrs_debug!(
+                "[SERVER] {} connection callbacks panicked while iterating!",
+                panicked
+            );
+        }
+    }
+
+    /// Describes at most `limit` connections with an address after `cursor`, ordered by address.
+    /// This is meant for APIs that list connections a page at a time.
+    ///
+    /// Pass `None` to get the first page, and the returned cursor to get the next one. The cursor
+    /// is `None` once every connection was listed, and every connection that stays open is listed
+    /// exactly once.
+    pub async fn connections_page(
+        &self,
+        cursor: Option<SocketAddr>,
+        limit: usize,
+    ) -> (Vec<ConnInfo>, Option<SocketAddr>) {
+        self.connections.page(cursor, limit, conn_info).await
+    }
+
     /// Stops the Listener, effectively closing the socket and stopping the server.
     /// This will also close all connections, and prevent any new connections from being accepted,
     /// until [`Listener::start`] is called again.
@@ -714,6 +1322,120 @@ impl Listener {
     }
 }
 
+/// Dropping a listener stops it like [`Listener::stop`], its remaining connections are closed
+/// without telling their peers, who time out on their own.
+impl Drop for Listener {
+    fn drop(&mut self) {
+        self.closed.notify_now();
+    }
+}
+
+fn conn_info(address: &SocketAddr, (meta, _, handle): &Session) -> ConnInfo {
+    ConnInfo {
+        address: *address,
+        mtu_size: meta.mtu_size,
+        recv_time: handle.recv_time(),
+    }
+}
+
+/// The answer to an open connection request from `origin`, `locked` being the mtu of its
+/// session if it has one already.
+pub(crate) fn open_connect_reply(
+    versions: &[u8],
+    server_id: u64,
+    origin: SocketAddr,
+    mut pk: OpenConnectRequest,
+    locked: Option<u16>,
+) -> RakPacket {
+    // TODO make a constant for this
+    if !versions.contains(&pk.protocol) {
+        rakrs_debug!(
+            "[{}] Sent ({}) which is invalid RakNet protocol. Version is incompatible with server.",
+            pk.protocol,
+            to_address_token(origin)
+        );
+
+        return IncompatibleProtocolVersion {
+            protocol: pk.protocol,
+            magic: Magic::new(),
+            server_id,
+        }
+        .into();
+    }
+
+    rakrs_debug!(
+        true,
+        "[{}] Client requested Mtu Size: {}",
+        to_address_token(origin),
+        pk.mtu_size
+    );
+
+    if pk.mtu_size > MAX_MTU_SIZE {
+        rakrs_debug!(
+            true,
+            "[{}] Client requested Mtu Size: {} which is larger than the maximum allowed size of {}",
+            to_address_token(origin),
+            pk.mtu_size,
+            MAX_MTU_SIZE
+        );
+        pk.mtu_size = MAX_MTU_SIZE;
+    }
+
+    if let Some(locked) = locked {
+        pk.mtu_size = locked;
+    }
+
+    OpenConnectReply {
+        server_id,
+        // TODO allow encryption
+        security: false,
+        magic: Magic::new(),
+        // TODO make this configurable, this is sent to the client to change
+        // it's mtu size, right now we're using what the client prefers.
+        // however in some cases this may not be the preferred use case, for instance
+        // on servers with larger worlds, you may want a larger mtu size, or if
+        // your limited on network bandwith
+        mtu_size: pk.mtu_size,
+    }
+    .into()
+}
+
+/// The answer to a session info request from `origin`, `locked` being the mtu of its session
+/// if it has one already. The mtu of the reply is the one its session is created with.
+pub(crate) fn session_info_reply(
+    server_id: u64,
+    origin: SocketAddr,
+    pk: &SessionInfoRequest,
+    locked: Option<u16>,
+) -> SessionInfoReply {
+    SessionInfoReply {
+        server_id,
+        client_address: origin,
+        magic: Magic::new(),
+        // A session keeps the mtu it was created with, a retransmitted request is answered
+        // with it, whatever mtu the request carries.
+        mtu_size: locked.unwrap_or(pk.mtu_size.min(MAX_MTU_SIZE)),
+        security: false,
+        // tells a rak-rs client it may offer its extensions, others never asked.
+        capabilities: match pk.capabilities.crate_version() {
+            Some(_) => Capabilities::hello(),
+            None => Capabilities::NONE,
+        },
+    }
+}
+
+/// Receives from `socket`, or fails with the OS error in `fault` if there is one.
+async fn recv_from(
+    socket: &UdpSocket,
+    buf: &mut [u8],
+    fault: &AtomicI32,
+) -> std::io::Result<(usize, SocketAddr)> {
+    match fault.load(Ordering::Relaxed) {
+        0 => socket.recv_from(buf).await,
+        code => Err(std::io::Error::from_raw_os_error(code)),
+    }
+}
+
 async fn send_packet_to_socket(socket: &Arc<UdpSocket>, packet: RakPacket, origin: SocketAddr) {
     if let Err(e) = socket
         .send_to(&mut packet.write_to_bytes().unwrap().as_slice(), origin)
@@ -727,9 +1449,124 @@ async fn send_packet_to_socket(socket: &Arc<UdpSocket>, packet: RakPacket, origi
     }
 }
 
-pub(crate) fn current_epoch() -> u64 {
-    std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
+/// The session a frame set from `origin`, an address without a session, belongs to: the one
+/// session of another port of the same host that expects its sequence. `None` when there is no
+/// such session, or more than one, which the datagram can't be told apart for.
+async fn bound_session(
+    connections: &Registry<Session>,
+    origin: SocketAddr,
+    datagram: &[u8],
+) -> Option<(SocketAddr, Session)> {
+    if !matches!(datagram.first(), Some(0x80..=0x8f)) {
+        return None;
+    }
+    let sequence = DatagramSeq::read_from_slice(datagram.get(1..)?).ok()?;
+    let mut bound = None;
+    let sessions = connections
+        .on_host(&origin, |address, session| (*address, session.clone()))
+        .await;
+    for (address, session) in sessions {
+        if session.2.expects(sequence).await {
+            if bound.is_some() {
+                return None;
+            }
+            bound = Some((address, session));
+        }
+    }
+    bound
+}
+
+/// Moves the session of `handle` from `bound` to `origin`, where its peer moved, unless it
+/// moved [`MigrationOptions::max_migrations`] times already. Returns whether it moved.
+async fn migrate(
+    connections: &Registry<Session>,
+    migrated: &Migrated,
+    bound: SocketAddr,
+    origin: SocketAddr,
+    handle: &ConnHandle,
+    options: MigrationOptions,
+) -> bool {
+    let made_from = handle.address();
+    let migrations = migrated
+        .lock()
         .unwrap()
-        .as_secs() as u64
+        .get(&made_from)
+        .map_or(0, |(_, migrations)| *migrations);
+    if migrations >= options.max_migrations {
+        return false;
+    }
+    let Some(session) = connections
+        .remove_if(&bound, |(_, _, other)| other.is(handle))
+        .await
+    else {
+        return false;
+    };
+
+    handle.migrate(origin).await;
+    migrated
+        .lock()
+        .unwrap()
+        .insert(made_from, (origin, migrations + 1));
+    connections.insert(origin, session).await;
+    true
+}
+
+/// The acknowledgement of a frame set that carries a disconnect, for a peer whose session is
+/// gone, see [`ConnOptions::disconnect_linger`]. Anything else from such a peer is ignored.
+pub(crate) fn disconnect_ack(datagram: &[u8]) -> Option<Vec<u8>> {
+    if !matches!(datagram.first(), Some(0x80..=0x8f)) {
+        return None;
+    }
+    let packet = FramePacket::read_from_slice(datagram).ok()?;
+    if !packet
+        .frames
+        .iter()
+        .any(|frame| frame.body.first() == Some(&Disconnect::ID))
+    {
+        return None;
+    }
+
+    let mut sequence = SequenceRanges::new();
+    sequence.insert(packet.sequence.get());
+    let ack = Ack::from_ranges(&sequence, false).write_to_bytes().ok()?;
+    Some(ack.as_slice().to_vec())
+}
+
+/// Answers a ping with `reply`.
+async fn send_pong(socket: &Arc<UdpSocket>, origin: SocketAddr, server_id: u64, reply: PingReply) {
+    let pong = match reply {
+        PingReply::Motd(_motd) => UnconnectedPong {
+            timestamp: current_epoch(),
+            server_id,
+            magic: Magic::new(),
+            #[cfg(feature = "mcpe")]
+            motd: _motd,
+        }
+        .into(),
+        PingReply::Raw(advertisement) => {
+            // laid out like a pong with a MOTD, whatever the features.
+            let mut buf = ByteWriter::new();
+            let written = buf
+                // the id of an unconnected pong.
+                .write_u8(0x1c)
+                .and_then(|_| buf.write_u64(current_epoch()))
+                .and_then(|_| buf.write_u64(server_id))
+                .and_then(|_| Magic::new().write(&mut buf))
+                .and_then(|_| buf.write_u16(advertisement.len() as u16))
+                .and_then(|_| buf.write(&advertisement));
+            if written.is_ok() {
+                if let Err(e) = socket.send_to(buf.as_slice(), origin).await {
+                    rakrs_debug!(
+                        "[{}] Failed sending payload to socket! {}",
+                        to_address_token(origin),
+                        e
+                    );
+                }
+            }
+            return;
+        }
+        PingReply::Decline => return,
+    };
+
+    send_packet_to_socket(socket, pong, origin).await;
 }
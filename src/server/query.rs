@@ -0,0 +1,432 @@
+//! Answers the GS4 query protocol (also known as UT3 query) on the port of a [`Listener`],
+//! which hosting panels and server lists use to look up a Minecraft server.
+//!
+//! A query starts with a handshake, which hands the asking address a challenge token. The
+//! token has to be sent back with every stat request until it expires, so the answers, which
+//! are much larger than the requests, can't be aimed at a spoofed address. A basic stat
+//! answers with the [`Motd`], a full stat adds the [`QueryInfo`] of the server, see
+//! [`Listener::set_query_provider()`].
+//!
+//! Query datagrams start with `0xfe 0xfd`, which is no offline packet, and only datagrams of
+//! addresses without a connection are looked at, so they never get in the way of RakNet.
+//!
+//! [`Listener`]: crate::server::Listener
+//! [`Listener::set_query_provider()`]: crate::server::Listener::set_query_provider
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "async_std")]
+use async_std::{future::timeout, net::UdpSocket, task};
+#[cfg(feature = "async_tokio")]
+use tokio::{net::UdpSocket, task, time::timeout};
+
+use crate::connection::controller::budget::{BytesPerSec, SendBudget, DEFAULT_SEND_OVERDRAFT};
+use crate::protocol::mcpe::motd::Motd;
+use crate::rakrs_debug;
+use crate::util::to_address_token;
+
+use super::advertisement::Advertisement;
+
+/// The bytes every query datagram starts with.
+pub const QUERY_MAGIC: [u8; 2] = [0xfe, 0xfd];
+/// The type of a handshake, which asks for a challenge token.
+pub const QUERY_HANDSHAKE: u8 = 0x09;
+/// The type of a stat request, basic or full.
+pub const QUERY_STAT: u8 = 0x00;
+/// The default time a challenge token is accepted for, see [`Listener::query_token_lifetime`].
+///
+/// [`Listener::query_token_lifetime`]: crate::server::Listener::query_token_lifetime
+pub const DEFAULT_QUERY_TOKEN_LIFETIME: Duration = Duration::from_secs(30);
+/// The default bytes per second the answers to queries may take, see [`Listener::query_rate`].
+///
+/// [`Listener::query_rate`]: crate::server::Listener::query_rate
+pub const DEFAULT_QUERY_RATE: BytesPerSec = 64 * 1024;
+/// The most addresses that hold a challenge token at once, a handshake past it is not answered
+/// until a token expired.
+pub const MAX_QUERY_SESSIONS: usize = 4096;
+
+/// Only the low nibbles of a session id are echoed, like the reference implementation does.
+const SESSION_MASK: i32 = 0x0f0f_0f0f;
+/// What a full stat answer starts with, clients skip it.
+const FULL_STAT_PADDING: &[u8] = b"splitnum\0\x80\0";
+/// What separates the values of a full stat answer from the players.
+const PLAYERS_PADDING: &[u8] = b"\x01player_\0\0";
+
+/// A query datagram sent to the listener.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryRequest {
+    /// Asks for a challenge token.
+    Handshake { session: i32 },
+    /// Asks for the MOTD.
+    BasicStat { session: i32, token: i32 },
+    /// Asks for the MOTD, the [`QueryInfo`] and the players.
+    FullStat { session: i32, token: i32 },
+}
+
+impl QueryRequest {
+    /// Whether `datagram` is meant for the query protocol.
+    pub fn is_query(datagram: &[u8]) -> bool {
+        datagram.starts_with(&QUERY_MAGIC)
+    }
+
+    /// Reads a query datagram, `None` if it isn't a valid one.
+    pub fn parse(datagram: &[u8]) -> Option<Self> {
+        if !Self::is_query(datagram) || datagram.len() < 7 {
+            return None;
+        }
+        let session = i32::from_be_bytes(datagram[3..7].try_into().unwrap());
+        let payload = &datagram[7..];
+        match (datagram[2], payload.len()) {
+            (QUERY_HANDSHAKE, _) => Some(Self::Handshake { session }),
+            (QUERY_STAT, 4) => Some(Self::BasicStat {
+                session,
+                token: i32::from_be_bytes(payload.try_into().unwrap()),
+            }),
+            // the reference client pads a full stat request with four bytes.
+            (QUERY_STAT, 8) => Some(Self::FullStat {
+                session,
+                token: i32::from_be_bytes(payload[..4].try_into().unwrap()),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// What a full stat tells about the server, on top of its [`Motd`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryInfo {
+    /// The kind of game, `SMP` for survival multiplayer.
+    pub game_type: String,
+    /// The game the server runs, `MINECRAFTPE` for bedrock.
+    pub game_id: String,
+    /// The world the server runs, `None` advertises the second line of the MOTD.
+    pub map: Option<String>,
+    /// The server software and its plugins, conventionally `Software: Plugin 1.0; Other 2.1`.
+    pub plugins: String,
+    /// The names of the players online.
+    pub players: Vec<String>,
+    /// The address players connect to, `None` advertises the address of the listener.
+    pub host_ip: Option<IpAddr>,
+}
+
+impl Default for QueryInfo {
+    fn default() -> Self {
+        Self {
+            game_type: "SMP".into(),
+            game_id: "MINECRAFTPE".into(),
+            map: None,
+            plugins: String::new(),
+            players: Vec::new(),
+            host_ip: None,
+        }
+    }
+}
+
+/// Produces the [`QueryInfo`] for a full stat, see [`Listener::set_query_provider()`].
+///
+/// This is called outside of the runtime, it may block, but should be quick.
+///
+/// [`Listener::set_query_provider()`]: crate::server::Listener::set_query_provider
+pub type QueryInfoProvider = Arc<dyn Fn() -> QueryInfo + Send + Sync>;
+
+/// The challenge tokens handed out by the handshakes of a listener, by the address they were
+/// handed to.
+///
+/// This struct does not do any IO, it only decides which tokens are valid.
+#[derive(Debug, Clone)]
+pub struct QuerySessions {
+    tokens: HashMap<SocketAddr, (i32, Instant)>,
+    lifetime: Duration,
+    capacity: usize,
+}
+
+impl QuerySessions {
+    pub fn new(lifetime: Duration, capacity: usize) -> Self {
+        Self {
+            tokens: HashMap::new(),
+            lifetime,
+            capacity,
+        }
+    }
+
+    /// Hands `addr` a new token, replacing the one it had. Returns `None` while every session
+    /// is taken by a token that didn't expire yet.
+    pub fn issue(&mut self, addr: SocketAddr, now: Instant) -> Option<i32> {
+        if !self.tokens.contains_key(&addr) && self.tokens.len() >= self.capacity {
+            let lifetime = self.lifetime;
+            self.tokens
+                .retain(|_, (_, issued)| now.saturating_duration_since(*issued) < lifetime);
+            if self.tokens.len() >= self.capacity {
+                return None;
+            }
+        }
+
+        // the token is sent as a decimal string, clients expect it to be positive.
+        let token = rand::random::<i32>() & i32::MAX;
+        self.tokens.insert(addr, (token, now));
+        Some(token)
+    }
+
+    /// Whether `token` is the token `addr` was handed, and it didn't expire yet.
+    pub fn verify(&mut self, addr: SocketAddr, token: i32, now: Instant) -> bool {
+        match self.tokens.get(&addr) {
+            Some((issued_token, issued)) if *issued_token == token => {
+                if now.saturating_duration_since(*issued) < self.lifetime {
+                    return true;
+                }
+                self.tokens.remove(&addr);
+                false
+            }
+            _ => false,
+        }
+    }
+
+    /// The amount of addresses holding a token, expired ones included.
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+}
+
+/// The answer to a handshake.
+pub fn handshake_reply(session: i32, token: i32) -> Vec<u8> {
+    let mut buf = vec![QUERY_HANDSHAKE];
+    buf.extend_from_slice(&(session & SESSION_MASK).to_be_bytes());
+    push_str(&mut buf, &token.to_string());
+    buf
+}
+
+/// The answer to a basic stat.
+pub fn basic_stat_reply(session: i32, motd: &Motd, info: &QueryInfo, host: SocketAddr) -> Vec<u8> {
+    let mut buf = vec![QUERY_STAT];
+    buf.extend_from_slice(&(session & SESSION_MASK).to_be_bytes());
+    push_str(&mut buf, &motd.name);
+    push_str(&mut buf, &info.game_type);
+    push_str(&mut buf, info.map.as_deref().unwrap_or(&motd.sub_motd));
+    push_str(&mut buf, &motd.player_count.to_string());
+    push_str(&mut buf, &motd.player_max.to_string());
+    // the only little endian field of the protocol.
+    buf.extend_from_slice(&host_port(motd, host).to_le_bytes());
+    push_str(&mut buf, &info.host_ip.unwrap_or(host.ip()).to_string());
+    buf
+}
+
+/// The answer to a full stat.
+pub fn full_stat_reply(session: i32, motd: &Motd, info: &QueryInfo, host: SocketAddr) -> Vec<u8> {
+    let mut buf = vec![QUERY_STAT];
+    buf.extend_from_slice(&(session & SESSION_MASK).to_be_bytes());
+    buf.extend_from_slice(FULL_STAT_PADDING);
+    let values = [
+        ("hostname", motd.name.clone()),
+        ("gametype", info.game_type.clone()),
+        ("game_id", info.game_id.clone()),
+        ("version", motd.version.clone()),
+        ("plugins", info.plugins.clone()),
+        (
+            "map",
+            info.map.clone().unwrap_or_else(|| motd.sub_motd.clone()),
+        ),
+        ("numplayers", motd.player_count.to_string()),
+        ("maxplayers", motd.player_max.to_string()),
+        ("hostport", host_port(motd, host).to_string()),
+        ("hostip", info.host_ip.unwrap_or(host.ip()).to_string()),
+    ];
+    for (key, value) in values.iter() {
+        push_str(&mut buf, key);
+        push_str(&mut buf, value);
+    }
+    buf.push(0);
+    buf.extend_from_slice(PLAYERS_PADDING);
+    for player in info.players.iter() {
+        push_str(&mut buf, player);
+    }
+    buf.push(0);
+    buf
+}
+
+/// Writes `value` null terminated, a null within it would end it early.
+fn push_str(buf: &mut Vec<u8>, value: &str) {
+    buf.extend(value.bytes().filter(|byte| *byte != 0));
+    buf.push(0);
+}
+
+/// The port the MOTD advertises, or the one of the listener.
+fn host_port(motd: &Motd, host: SocketAddr) -> u16 {
+    motd.port.parse().unwrap_or(host.port())
+}
+
+/// The query state of a listener, shared between the listener and its tasks.
+pub struct Query {
+    sessions: Mutex<QuerySessions>,
+    /// The answers are drawn from this bucket, the ones that don't fit are dropped.
+    limiter: Mutex<SendBudget>,
+    /// The info served when there is no provider, or the provider overran its budget.
+    cached: Mutex<QueryInfo>,
+    provider: Mutex<Option<QueryInfoProvider>>,
+    /// Whether the provider is being evaluated, it is never evaluated twice at once.
+    evaluating: AtomicBool,
+    limited: AtomicU64,
+}
+
+impl Query {
+    pub fn new(lifetime: Duration, rate: BytesPerSec) -> Self {
+        Self {
+            sessions: Mutex::new(QuerySessions::new(lifetime, MAX_QUERY_SESSIONS)),
+            limiter: Mutex::new(SendBudget::new(
+                rate,
+                DEFAULT_SEND_OVERDRAFT,
+                Instant::now(),
+            )),
+            cached: Mutex::new(QueryInfo::default()),
+            provider: Mutex::new(None),
+            evaluating: AtomicBool::new(false),
+            limited: AtomicU64::new(0),
+        }
+    }
+
+    /// Applies the settings of the listener, the tokens handed out so far are forgotten.
+    pub fn configure(&self, lifetime: Duration, rate: BytesPerSec) {
+        *self.lock_sessions() = QuerySessions::new(lifetime, MAX_QUERY_SESSIONS);
+        *self
+            .limiter
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner) =
+            SendBudget::new(rate, DEFAULT_SEND_OVERDRAFT, Instant::now());
+    }
+
+    /// Evaluates `provider` for every full stat from now on, `None` serves the defaults.
+    pub fn set_provider(&self, provider: Option<QueryInfoProvider>) {
+        *self
+            .provider
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner) = provider;
+    }
+
+    /// The amount of answers dropped because they exceeded the rate.
+    pub fn limited(&self) -> u64 {
+        self.limited.load(Ordering::Relaxed)
+    }
+
+    /// Answers a query datagram from `origin`, a datagram that isn't a valid query, or carries
+    /// an invalid token, is dropped.
+    pub async fn answer(
+        self: &Arc<Self>,
+        datagram: &[u8],
+        origin: SocketAddr,
+        socket: &Arc<UdpSocket>,
+        advertisement: &Arc<Advertisement>,
+        budget: Duration,
+    ) {
+        let now = Instant::now();
+        let request = match QueryRequest::parse(datagram) {
+            Some(request) => request,
+            None => return,
+        };
+        let host = match socket.local_addr() {
+            Ok(host) => host,
+            Err(_) => return,
+        };
+
+        let reply = match request {
+            QueryRequest::Handshake { session } => match self.lock_sessions().issue(origin, now) {
+                Some(token) => handshake_reply(session, token),
+                None => return,
+            },
+            QueryRequest::BasicStat { session, token } => {
+                if !self.lock_sessions().verify(origin, token, now) {
+                    return;
+                }
+                let motd = advertisement.current(budget).await;
+                basic_stat_reply(session, &motd, &self.info(budget).await, host)
+            }
+            QueryRequest::FullStat { session, token } => {
+                if !self.lock_sessions().verify(origin, token, now) {
+                    return;
+                }
+                let motd = advertisement.current(budget).await;
+                full_stat_reply(session, &motd, &self.info(budget).await, host)
+            }
+        };
+
+        let allowed = self
+            .limiter
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .borrow(reply.len(), Instant::now());
+        if !allowed {
+            self.limited.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+
+        if let Err(e) = socket.send_to(reply.as_slice(), origin).await {
+            rakrs_debug!(
+                "[{}] Failed sending query answer to socket! {}",
+                to_address_token(origin),
+                e
+            );
+        }
+    }
+
+    fn lock_sessions(&self) -> std::sync::MutexGuard<'_, QuerySessions> {
+        self.sessions
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    fn cached(&self) -> QueryInfo {
+        self.cached
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone()
+    }
+
+    /// The info to answer a stat with, the provider is evaluated like the one of the MOTD is,
+    /// see [`Advertisement::current()`].
+    async fn info(self: &Arc<Self>, budget: Duration) -> QueryInfo {
+        let provider = match self
+            .provider
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone()
+        {
+            Some(provider) => provider,
+            None => return self.cached(),
+        };
+
+        // a slow provider is still busy with an earlier query, don't pile up behind it.
+        if self.evaluating.swap(true, Ordering::Acquire) {
+            return self.cached();
+        }
+
+        let query = self.clone();
+        let evaluation = task::spawn_blocking(move || {
+            let info = catch_unwind(AssertUnwindSafe(|| provider()));
+            if let Ok(info) = &info {
+                *query
+                    .cached
+                    .lock()
+                    .unwrap_or_else(std::sync::PoisonError::into_inner) = info.clone();
+            }
+            query.evaluating.store(false, Ordering::Release);
+            info.ok()
+        });
+
+        #[cfg(feature = "async_std")]
+        let info = timeout(budget, evaluation).await.ok().flatten();
+        #[cfg(feature = "async_tokio")]
+        let info = timeout(budget, evaluation)
+            .await
+            .ok()
+            .and_then(Result::ok)
+            .flatten();
+
+        info.unwrap_or_else(|| self.cached())
+    }
+}
@@ -0,0 +1,229 @@
+//! The sessions of a [`Listener`], spread over shards so no single lock covers all of them.
+//!
+//! Every method takes a shard lock only for as long as it needs to look at that shard, and
+//! never across an await point or a user callback. This makes it safe to iterate thousands of
+//! connections while they are being looked up, added and removed.
+//!
+//! [`Listener`]: crate::server::Listener
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeMap;
+use std::hash::BuildHasher;
+use std::net::SocketAddr;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+#[cfg(feature = "async_std")]
+use async_std::sync::Mutex;
+#[cfg(feature = "async_tokio")]
+use tokio::sync::Mutex;
+
+/// The amount of shards a [`Registry`] uses by default.
+pub const DEFAULT_SHARDS: usize = 16;
+
+/// A description of a connection held by a [`Listener`].
+///
+/// [`Listener`]: crate::server::Listener
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnInfo {
+    /// The address of the peer, this is what identifies the connection.
+    pub address: SocketAddr,
+    /// The mtu size the peer requested.
+    pub mtu_size: u16,
+    /// The time in seconds since the epoch a packet was last received from the peer.
+    pub recv_time: u64,
+}
+
+/// A map of sessions keyed by the address of their peer, split into shards with a lock each.
+///
+/// Entries are ordered by address within a shard, so [`Registry::page`] can resume from any
+/// address and visit every entry exactly once.
+pub struct Registry<V> {
+    shards: Box<[Mutex<BTreeMap<SocketAddr, V>>]>,
+    hasher: RandomState,
+}
+
+impl<V> Default for Registry<V> {
+    fn default() -> Self {
+        Self::new(DEFAULT_SHARDS)
+    }
+}
+
+impl<V> Registry<V> {
+    /// Creates a registry with the given amount of shards, at least one is always used.
+    pub fn new(shards: usize) -> Self {
+        Self {
+            shards: (0..shards.max(1))
+                .map(|_| Mutex::new(BTreeMap::new()))
+                .collect(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    fn shard(&self, address: &SocketAddr) -> &Mutex<BTreeMap<SocketAddr, V>> {
+        let index = self.hasher.hash_one(address) as usize % self.shards.len();
+        &self.shards[index]
+    }
+
+    /// Inserts a session, returning the one it replaced.
+    pub async fn insert(&self, address: SocketAddr, value: V) -> Option<V> {
+        self.shard(&address).lock().await.insert(address, value)
+    }
+
+    /// Removes a session, returning it if it existed.
+    pub async fn remove(&self, address: &SocketAddr) -> Option<V> {
+        self.shard(address).lock().await.remove(address)
+    }
+
+    /// Removes a session only if `f` holds for it, returning it if it was removed.
+    pub async fn remove_if(&self, address: &SocketAddr, f: impl FnOnce(&V) -> bool) -> Option<V> {
+        let mut shard = self.shard(address).lock().await;
+        if shard.get(address).is_some_and(f) {
+            shard.remove(address)
+        } else {
+            None
+        }
+    }
+
+    /// Removes every session.
+    pub async fn clear(&self) {
+        for shard in self.shards.iter() {
+            shard.lock().await.clear();
+        }
+    }
+
+    pub async fn contains(&self, address: &SocketAddr) -> bool {
+        self.shard(address).lock().await.contains_key(address)
+    }
+
+    /// Runs `f` on the session of the given address while its shard is locked.
+    /// `f` should be short, as every other session in the shard waits on it.
+    pub async fn with<R>(&self, address: &SocketAddr, f: impl FnOnce(&mut V) -> R) -> Option<R> {
+        self.shard(address).lock().await.get_mut(address).map(f)
+    }
+
+    /// Collects a view of every session of the host of `address`, whatever their port.
+    /// This locks every shard in turn, as the sessions of a host are spread over all of them.
+    pub async fn on_host<T>(
+        &self,
+        address: &SocketAddr,
+        view: impl Fn(&SocketAddr, &V) -> T,
+    ) -> Vec<T> {
+        let first = SocketAddr::new(address.ip(), 0);
+        let mut found = Vec::new();
+        for shard in self.shards.iter() {
+            let shard = shard.lock().await;
+            found.extend(
+                shard
+                    .range(first..)
+                    .take_while(|(other, _)| other.ip() == address.ip())
+                    .map(|(other, value)| view(other, value)),
+            );
+        }
+        found
+    }
+
+    /// The amount of sessions, this locks every shard in turn, so it can be off by the sessions
+    /// that were added or removed meanwhile.
+    pub async fn len(&self) -> usize {
+        let mut len = 0;
+        for shard in self.shards.iter() {
+            len += shard.lock().await.len();
+        }
+        len
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.len().await == 0
+    }
+
+    /// Collects a view of every session.
+    ///
+    /// This copies one entry for every session, prefer [`Registry::for_each`] or
+    /// [`Registry::page`] when there are many of them.
+    pub async fn snapshot<T>(&self, view: impl Fn(&SocketAddr, &V) -> T) -> Vec<T> {
+        let mut snapshot = Vec::new();
+        for shard in self.shards.iter() {
+            let shard = shard.lock().await;
+            snapshot.extend(shard.iter().map(|(address, value)| view(address, value)));
+        }
+        snapshot
+    }
+
+    /// Calls `f` with a view of every session, one shard at a time.
+    ///
+    /// The views of a shard are taken while it is locked, and `f` is called after the lock was
+    /// released, so `f` may use the registry itself. A panic in `f` is caught, and iteration
+    /// continues with the next session. Returns the amount of calls that panicked.
+    pub async fn for_each<T>(
+        &self,
+        view: impl Fn(&SocketAddr, &V) -> T,
+        mut f: impl FnMut(&T),
+    ) -> usize {
+        let mut panicked = 0;
+        let mut views = Vec::new();
+        for shard in self.shards.iter() {
+            {
+                let shard = shard.lock().await;
+                views.extend(shard.iter().map(|(address, value)| view(address, value)));
+            }
+
+            for item in views.drain(..) {
+                if catch_unwind(AssertUnwindSafe(|| f(&item))).is_err() {
+                    panicked += 1;
+                }
+            }
+        }
+        panicked
+    }
+
+    /// Views at most `limit` sessions with an address after `cursor`, in order of address.
+    ///
+    /// Returns the cursor to pass to get the next page, `None` once there are no more sessions.
+    /// Sessions that stay in the registry are visited exactly once across all pages.
+    pub async fn page<T>(
+        &self,
+        cursor: Option<SocketAddr>,
+        limit: usize,
+        view: impl Fn(&SocketAddr, &V) -> T,
+    ) -> (Vec<T>, Option<SocketAddr>) {
+        if limit == 0 {
+            return (Vec::new(), cursor);
+        }
+
+        let mut page: Vec<(SocketAddr, T)> = Vec::new();
+        let mut more = false;
+        for shard in self.shards.iter() {
+            let shard = shard.lock().await;
+            let range = match cursor {
+                Some(cursor) => shard.range((
+                    std::ops::Bound::Excluded(cursor),
+                    std::ops::Bound::Unbounded,
+                )),
+                None => shard.range(..),
+            };
+
+            // taking one more than the limit tells whether this shard has anything left.
+            let before = page.len();
+            page.extend(
+                range
+                    .take(limit + 1)
+                    .map(|(address, value)| (*address, view(address, value))),
+            );
+            if page.len() - before > limit {
+                more = true;
+            }
+        }
+
+        page.sort_by_key(|(address, _)| *address);
+        if page.len() > limit {
+            page.truncate(limit);
+            more = true;
+        }
+
+        let next = if more {
+            page.last().map(|(address, _)| *address)
+        } else {
+            None
+        };
+        (page.into_iter().map(|(_, item)| item).collect(), next)
+    }
+}
@@ -0,0 +1,1069 @@
+//! A deterministic simulation of a server and its clients.
+//!
+//! Everything runs on the calling thread under a virtual clock that only moves when
+//! [`SimWorld::step`] is called, datagrams travel over an in-memory link with a fixed latency,
+//! and every bit of randomness (loss, jitter, the spread of the retransmission timeouts, the
+//! ids of the nodes) is drawn from a generator seeded by the caller. The same seed therefore
+//! reproduces a run byte-for-byte, which makes reliability bugs that depend on the timing of a
+//! loss debuggable: print the seed, replay it.
+//!
+//! The nodes are the real thing. A client is a [`Client`] that goes through its handshake,
+//! a server answers it the way a [`Listener`] does and opens a [`Connection`] for it. Their
+//! tasks are never spawned, the world runs their drivers one turn at a time instead, the same
+//! turns the tasks would run: a datagram that arrived, a tick that is due, the messages a tick
+//! released. The world is the application of every node, it receives whatever they deliver.
+//!
+//! The unconnected ping a client sends before its handshake is left out, a server in the
+//! world is always there.
+//!
+//! ```ignore
+//! use std::time::Duration;
+//! use rak_rs::simulation::SimWorld;
+//! use rak_rs::Reliability;
+//!
+//! let mut world = SimWorld::new(0x5eed);
+//! let server = world.add_server();
+//! let client = world.add_client(server);
+//! world.set_loss(0.2);
+//!
+//! world.send(client, server, &[0xfe, 1, 2, 3], Reliability::ReliableOrd, 0);
+//! world.step(Duration::from_secs(5));
+//! world.assert_delivered(server, client, &[vec![0xfe, 1, 2, 3]]);
+//! ```
+//!
+//! [`Client`]: crate::client::Client
+//! [`Listener`]: crate::server::Listener
+//! [`Connection`]: crate::connection::Connection
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "async_std")]
+use async_std::sync::RwLock;
+#[cfg(feature = "async_tokio")]
+use tokio::sync::RwLock;
+
+use binary_util::interfaces::{Reader, Writer};
+use futures::executor::block_on;
+use futures::{pin_mut, select_biased, FutureExt};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::client::discovery::{self, DiscoveryStatus, OPEN_REPLY_TIMEOUT};
+use crate::client::handshake::{
+    self, ConnectStage, HandshakeTimings, Stage, CONNECT_RESEND_INTERVAL,
+};
+use crate::client::{Client, ClientDrivers};
+use crate::connection::controller::jitter::{RngProvider, HANDSHAKE_RESEND_JITTER};
+use crate::connection::event::RakEvent;
+use crate::connection::options::ConnOptions;
+use crate::connection::queue::SendQueue;
+use crate::connection::state::ConnectionState;
+use crate::connection::stats::ConnectionStats;
+use crate::connection::{ConnDrivers, Connection};
+use crate::error::client::HandshakeError;
+use crate::notify::Notify;
+use crate::protocol::frame::FramePacket;
+use crate::protocol::packet::decode_packet;
+use crate::protocol::packet::offline::{
+    IncompatibleProtocolVersion, OfflinePacket, OpenConnectReply, OpenConnectRequest,
+    SessionInfoReply,
+};
+use crate::protocol::packet::online::Capabilities;
+use crate::protocol::packet::{PacketId, RakPacket};
+use crate::protocol::primitives::decode;
+use crate::protocol::reliability::Reliability;
+use crate::server;
+
+/// How far the virtual clock moves between two turns of the nodes.
+pub const SIM_TICK: Duration = Duration::from_millis(10);
+
+/// The MTU every simulated client starts its handshake with.
+pub const SIM_MTU: u16 = 1400;
+
+/// The RakNet version of every simulated client.
+pub const SIM_VERSION: u8 = 11;
+
+/// The RakNet versions every simulated server accepts, the ones of a [`Listener`].
+///
+/// [`Listener`]: crate::server::Listener
+const SIM_VERSIONS: &[u8] = &[10, 11];
+
+/// The time since the unix epoch every world starts at, so the timestamps on the wire are the
+/// same on every run.
+const SIM_EPOCH: Duration = Duration::from_secs(1_700_000_000);
+
+/// How long a client waits for the reply to its session info request, like a real one.
+const SESSION_REPLY_TIMEOUT: Duration = Duration::from_secs(4);
+
+/// How many connection requests a client sends before it gives up, like a real one.
+const CONNECT_ATTEMPTS: u8 = 5;
+
+/// How long [`SimWorld::add_client`] steps the world for the handshake to complete.
+const HANDSHAKE_DEADLINE: Duration = Duration::from_secs(60);
+
+thread_local! {
+    /// The time the code under simulation reads, as an instant and as the time since the
+    /// unix epoch, `None` outside of a [`SimWorld`].
+    static VIRTUAL_NOW: Cell<Option<(Instant, Duration)>> = const { Cell::new(None) };
+}
+
+/// The current virtual time, if the calling thread is inside a [`SimWorld`].
+pub(crate) fn virtual_now() -> Option<Instant> {
+    VIRTUAL_NOW.with(Cell::get).map(|(now, _)| now)
+}
+
+/// The current virtual time since the unix epoch, if the calling thread is inside a
+/// [`SimWorld`].
+pub(crate) fn virtual_since_epoch() -> Option<Duration> {
+    VIRTUAL_NOW.with(Cell::get).map(|(_, since)| since)
+}
+
+/// Sets the virtual time for as long as it is held, restoring the previous one after.
+struct VirtualClock(Option<(Instant, Duration)>);
+
+impl VirtualClock {
+    fn set(now: Instant, since_epoch: Duration) -> Self {
+        Self(VIRTUAL_NOW.with(|clock| clock.replace(Some((now, since_epoch)))))
+    }
+}
+
+impl Drop for VirtualClock {
+    fn drop(&mut self) {
+        VIRTUAL_NOW.with(|clock| clock.set(self.0));
+    }
+}
+
+/// A server or client in a [`SimWorld`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodeId(pub usize);
+
+impl NodeId {
+    /// The address of the node on the link.
+    fn address(self) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], 10_000 + self.0 as u16))
+    }
+}
+
+/// Something that happened in a [`SimWorld`], `at` being the virtual time since it was created.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SimEvent {
+    /// A datagram was put on the link.
+    Sent {
+        at: Duration,
+        from: NodeId,
+        to: NodeId,
+        datagram: Vec<u8>,
+    },
+    /// A datagram was lost on the link, at random or as scripted.
+    Lost {
+        at: Duration,
+        from: NodeId,
+        to: NodeId,
+        size: usize,
+    },
+    /// A payload was delivered to `to`.
+    Delivered {
+        at: Duration,
+        from: NodeId,
+        to: NodeId,
+        payload: Vec<u8>,
+    },
+    /// The connection of `node` with `peer` emitted an event.
+    Event {
+        at: Duration,
+        node: NodeId,
+        peer: NodeId,
+        event: RakEvent,
+    },
+}
+
+/// Where the send queue of a node puts its datagrams, for the world to carry them.
+type Outbox = Arc<Mutex<Vec<Vec<u8>>>>;
+
+/// Decides whether a frame datagram going over a link is dropped.
+type DropRule = Box<dyn FnMut(&FramePacket) -> bool>;
+
+/// The connection a server opened for one of its clients.
+struct Accepted {
+    conn: Connection,
+    drivers: ConnDrivers,
+    outbox: Outbox,
+    /// The mtu the session was created with, the listener locks it.
+    mtu: u16,
+    /// Whether its packet handler still runs, it stops once the connection is closed.
+    alive: bool,
+}
+
+/// A server, answering the offline handshake the way a listener does.
+struct Server {
+    server_id: u64,
+    /// The connections of the server by the client they are with.
+    conns: BTreeMap<NodeId, Accepted>,
+    /// The replies to the offline handshake, they don't go through a connection.
+    offline: Vec<(NodeId, Vec<u8>)>,
+}
+
+/// How far a client got with its connection.
+enum Phase {
+    /// Sends an open connection request at every mtu of `ladder` in turn, each one given up
+    /// on at `resend_at`.
+    Open1 {
+        ladder: Vec<u16>,
+        rung: usize,
+        resend_at: Duration,
+    },
+    /// Waits for the reply to the session info request until `deadline`.
+    Open2 {
+        mtu: u16,
+        wanted: Capabilities,
+        deadline: Duration,
+    },
+    /// Waits for the server to accept the connection, resending the request at `resend_at`.
+    Connect {
+        stage: ConnectStage,
+        mtu: u16,
+        tries: u8,
+        resend_at: Duration,
+    },
+    /// The handshake completed, the drivers of the client run.
+    Connected {
+        drivers: Box<ClientDrivers>,
+        /// Whether the packet handler still runs, the tick may outlive it.
+        handling: bool,
+        ticking: bool,
+    },
+    Failed(HandshakeError),
+}
+
+/// A client, going through its handshake before its drivers run.
+struct SimClient {
+    client: Client,
+    server: NodeId,
+    /// The id the requests of the handshake carry, drawn from the seed of the world.
+    id: i64,
+    outbox: Outbox,
+    send_queue: Arc<RwLock<SendQueue>>,
+    closer: Arc<Notify>,
+    /// Spreads the resends of the handshake.
+    rng: RngProvider,
+    phase: Phase,
+}
+
+enum Node {
+    Server(Server),
+    Client(Box<SimClient>),
+}
+
+/// A server and its clients, simulated on one thread under a virtual clock.
+///
+/// Nothing happens between calls, [`SimWorld::step`] advances the clock by [`SIM_TICK`] at a
+/// time, delivering the datagrams that are due and giving every node its turn in a fixed
+/// order. Everything that happened is recorded in the [`transcript`](SimWorld::transcript),
+/// two worlds built and stepped the same way with the same seed produce the same transcript.
+pub struct SimWorld {
+    seed: u64,
+    rng: StdRng,
+    epoch: Instant,
+    elapsed: Duration,
+    loss: f64,
+    latency: Duration,
+    jitter: Duration,
+    options: ConnOptions,
+    nodes: Vec<Node>,
+    /// Datagrams on the link by their arrival and the order they were sent in.
+    link: BTreeMap<(Duration, u64), (NodeId, NodeId, Vec<u8>)>,
+    serial: u64,
+    drop_rules: Vec<(NodeId, NodeId, DropRule)>,
+    transcript: Vec<SimEvent>,
+}
+
+impl SimWorld {
+    /// Creates an empty world, all randomness is drawn from `seed`.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            epoch: Instant::now(),
+            elapsed: Duration::ZERO,
+            loss: 0.0,
+            latency: Duration::from_millis(20),
+            jitter: Duration::ZERO,
+            options: ConnOptions::default(),
+            nodes: Vec::new(),
+            link: BTreeMap::new(),
+            serial: 0,
+            drop_rules: Vec::new(),
+            transcript: Vec::new(),
+        }
+    }
+
+    /// The seed this world was created with.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The virtual time since this world was created.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// The chance of every datagram, in either direction, to be lost on the link.
+    ///
+    /// A handshake only survives the loss of what it resends, set the loss once the clients
+    /// are added for them to connect whatever the seed.
+    pub fn set_loss(&mut self, loss: f64) {
+        self.loss = loss.clamp(0.0, 1.0);
+    }
+
+    /// The one-way delay of the link, each datagram is delayed up to `jitter` more,
+    /// which may reorder them.
+    pub fn set_latency(&mut self, latency: Duration, jitter: Duration) {
+        self.latency = latency;
+        self.jitter = jitter;
+    }
+
+    /// The options of the nodes added from now on.
+    ///
+    /// Their [`rng_seed`] is drawn from the seed of the world, and the messages of a
+    /// connection wait for the world to receive them however long it takes, so
+    /// [`consumer_timeout`] is not used.
+    ///
+    /// [`rng_seed`]: ConnOptions::rng_seed
+    /// [`consumer_timeout`]: ConnOptions::consumer_timeout
+    pub fn set_options(&mut self, options: ConnOptions) {
+        self.options = options;
+    }
+
+    /// Sets [`ConnOptions::ordered_stall_limit`] on the nodes added from now on.
+    pub fn set_ordered_stall_limit(&mut self, limit: Option<Duration>) {
+        self.options.ordered_stall_limit = limit;
+    }
+
+    /// Adds a server, clients connect to it with [`SimWorld::add_client`].
+    pub fn add_server(&mut self) -> NodeId {
+        self.nodes.push(Node::Server(Server {
+            server_id: self.rng.gen(),
+            conns: BTreeMap::new(),
+            offline: Vec::new(),
+        }));
+        NodeId(self.nodes.len() - 1)
+    }
+
+    /// Adds a client and steps the world until it connected to `server`.
+    ///
+    /// # Panics
+    /// If the handshake failed, naming the seed.
+    pub fn add_client(&mut self, server: NodeId) -> NodeId {
+        let node = NodeId(self.nodes.len());
+        let server_address = server.address();
+        let client = Client::new(SIM_VERSION, SIM_MTU).with_options(self.node_options());
+        let outbox = Outbox::default();
+
+        let sim_client = {
+            let _clock = self.clock();
+            let queue = SendQueue::with_outbox(SIM_MTU, server_address, outbox.clone());
+            let (send_queue, rng, closer) = block_on(client.reset_session(queue, node.address()));
+            block_on(client.update_state(ConnectionState::Unidentified));
+
+            let mut sim_client = SimClient {
+                client,
+                server,
+                id: self.rng.gen(),
+                outbox,
+                send_queue,
+                closer,
+                rng,
+                phase: Phase::Open1 {
+                    ladder: discovery::mtu_ladder(SIM_MTU),
+                    rung: 0,
+                    resend_at: Duration::ZERO,
+                },
+            };
+            sim_client.open_request(self.elapsed);
+            sim_client
+        };
+        self.nodes.push(Node::Client(Box::new(sim_client)));
+        self.flush();
+
+        let deadline = self.elapsed + HANDSHAKE_DEADLINE;
+        while self.elapsed < deadline {
+            match &self.client(node).phase {
+                Phase::Connected { .. } => return node,
+                Phase::Failed(error) => panic!(
+                    "seed {:#x}: {:?} failed its handshake with {:?}: {:?}",
+                    self.seed, node, server, error
+                ),
+                _ => self.step(SIM_TICK),
+            }
+        }
+        panic!(
+            "seed {:#x}: {:?} did not connect to {:?} within {:?}",
+            self.seed, node, server, HANDSHAKE_DEADLINE
+        );
+    }
+
+    /// Drops every frame datagram from `from` to `to` that `rule` returns true for,
+    /// retransmissions included, on top of the random loss.
+    pub fn drop_datagrams(
+        &mut self,
+        from: NodeId,
+        to: NodeId,
+        rule: impl FnMut(&FramePacket) -> bool + 'static,
+    ) {
+        self.drop_rules.push((from, to, Box::new(rule)));
+    }
+
+    /// Sends `payload` from `from` to `to`, the way the application of `from` would, it goes
+    /// out on the next tick.
+    pub fn send(
+        &mut self,
+        from: NodeId,
+        to: NodeId,
+        payload: &[u8],
+        reliability: Reliability,
+        channel: u8,
+    ) {
+        let _clock = self.clock();
+        let sent = match &self.nodes[from.0] {
+            Node::Server(server) => block_on(server.accepted(to).conn.send_with(
+                payload,
+                reliability,
+                channel,
+                false,
+            ))
+            .map_err(|e| format!("{:?}", e)),
+            Node::Client(client) => block_on(client.client.send(payload, reliability, channel))
+                .map_err(|e| format!("{:?}", e)),
+        };
+        if let Err(e) = sent {
+            panic!(
+                "seed {:#x}: {:?} failed to send to {:?}: {}",
+                self.seed, from, to, e
+            );
+        }
+        self.flush();
+    }
+
+    /// Advances the virtual clock by `duration`, one [`SIM_TICK`] at a time.
+    pub fn step(&mut self, duration: Duration) {
+        let until = self.elapsed + duration;
+        while self.elapsed < until {
+            self.elapsed = (self.elapsed + SIM_TICK).min(until);
+            let _clock = self.clock();
+            self.deliver();
+            self.turn();
+        }
+    }
+
+    /// Everything that happened so far, in order.
+    pub fn transcript(&self) -> &[SimEvent] {
+        &self.transcript
+    }
+
+    /// The payloads `to` received from `from`, in the order they were delivered.
+    pub fn delivered(&self, to: NodeId, from: NodeId) -> Vec<Vec<u8>> {
+        self.transcript
+            .iter()
+            .filter_map(|event| match event {
+                SimEvent::Delivered {
+                    from: f,
+                    to: t,
+                    payload,
+                    ..
+                } if *f == from && *t == to => Some(payload.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The events the connection of `node` with `peer` emitted, in order.
+    pub fn events(&self, node: NodeId, peer: NodeId) -> Vec<RakEvent> {
+        self.transcript
+            .iter()
+            .filter_map(|event| match event {
+                SimEvent::Event {
+                    node: n,
+                    peer: p,
+                    event,
+                    ..
+                } if *n == node && *p == peer => Some(event.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The statistics of the connection of `node` with `peer`, from [`Connection::stats()`]
+    /// or [`Client::stats()`].
+    ///
+    /// [`Connection::stats()`]: crate::connection::Connection::stats
+    /// [`Client::stats()`]: crate::client::Client::stats
+    pub fn stats(&self, node: NodeId, peer: NodeId) -> ConnectionStats {
+        let _clock = self.clock();
+        match &self.nodes[node.0] {
+            Node::Server(server) => block_on(server.accepted(peer).conn.stats()),
+            Node::Client(client) => block_on(client.client.stats()),
+        }
+    }
+
+    /// The MTU the connection of `node` with `peer` currently fragments with.
+    pub fn mtu(&self, node: NodeId, peer: NodeId) -> u16 {
+        let _clock = self.clock();
+        match &self.nodes[node.0] {
+            Node::Server(server) => block_on(server.accepted(peer).conn.meta()).mtu_size,
+            Node::Client(client) => block_on(client.client.meta()).mtu_size,
+        }
+    }
+
+    /// Panics, naming the seed, unless `to` received exactly `expected` from `from`.
+    pub fn assert_delivered(&self, to: NodeId, from: NodeId, expected: &[Vec<u8>]) {
+        let delivered = self.delivered(to, from);
+        if delivered != expected {
+            let diverged = delivered
+                .iter()
+                .zip(expected)
+                .position(|(got, want)| got != want)
+                .unwrap_or_else(|| delivered.len().min(expected.len()));
+            panic!(
+                "seed {:#x}: {:?} received {} payloads from {:?}, expected {}, first difference at {}",
+                self.seed,
+                to,
+                delivered.len(),
+                from,
+                expected.len(),
+                diverged
+            );
+        }
+    }
+
+    /// Panics, naming the seed, unless the connection of `node` with `peer` emitted `event`.
+    pub fn assert_event(&self, node: NodeId, peer: NodeId, event: &RakEvent) {
+        let events = self.events(node, peer);
+        if !events.contains(event) {
+            panic!(
+                "seed {:#x}: {:?} never emitted {:?} for {:?}, emitted {:?}",
+                self.seed, node, event, peer, events
+            );
+        }
+    }
+
+    fn clock(&self) -> VirtualClock {
+        VirtualClock::set(self.epoch + self.elapsed, SIM_EPOCH + self.elapsed)
+    }
+
+    fn node_options(&mut self) -> ConnOptions {
+        let mut options = self.options.clone();
+        options.rng_seed = Some(self.rng.gen());
+        options.consumer_timeout = None;
+        options
+    }
+
+    fn client(&self, node: NodeId) -> &SimClient {
+        match &self.nodes[node.0] {
+            Node::Client(client) => client,
+            Node::Server(_) => panic!("{:?} is not a client", node),
+        }
+    }
+
+    /// Hands the datagrams that arrived by now to the nodes they were sent to.
+    fn deliver(&mut self) {
+        while let Some(entry) = self.link.first_entry() {
+            if entry.key().0 > self.elapsed {
+                break;
+            }
+            let (from, to, datagram) = entry.remove();
+            let at = self.elapsed;
+            let options = self.node_options();
+            match &mut self.nodes[to.0] {
+                Node::Server(server) => {
+                    server.receive(to, from, datagram, options, at, &mut self.transcript)
+                }
+                Node::Client(client) => client.receive(to, datagram, at, &mut self.transcript),
+            }
+            self.flush();
+        }
+    }
+
+    /// Gives every node its turn: the ticks that are due, and the messages they released.
+    fn turn(&mut self) {
+        let at = self.elapsed;
+        for (index, node) in self.nodes.iter_mut().enumerate() {
+            match node {
+                Node::Server(server) => server.turn(NodeId(index), at, &mut self.transcript),
+                Node::Client(client) => client.turn(NodeId(index), at, &mut self.transcript),
+            }
+        }
+        self.flush();
+    }
+
+    /// Puts what the nodes sent on the link, or loses it.
+    fn flush(&mut self) {
+        let mut sent = Vec::new();
+        for (index, node) in self.nodes.iter_mut().enumerate() {
+            let from = NodeId(index);
+            match node {
+                Node::Server(server) => {
+                    for (to, datagram) in server.offline.drain(..) {
+                        sent.push((from, to, datagram));
+                    }
+                    for (to, accepted) in server.conns.iter() {
+                        for datagram in std::mem::take(&mut *accepted.outbox.lock().unwrap()) {
+                            sent.push((from, *to, datagram));
+                        }
+                    }
+                }
+                Node::Client(client) => {
+                    for datagram in std::mem::take(&mut *client.outbox.lock().unwrap()) {
+                        sent.push((from, client.server, datagram));
+                    }
+                }
+            }
+        }
+        for (from, to, datagram) in sent {
+            self.transmit(from, to, datagram);
+        }
+    }
+
+    fn transmit(&mut self, from: NodeId, to: NodeId, datagram: Vec<u8>) {
+        let at = self.elapsed;
+        self.transcript.push(SimEvent::Sent {
+            at,
+            from,
+            to,
+            datagram: datagram.clone(),
+        });
+
+        // the random loss is drawn for every datagram, so scripted drops don't shift it.
+        let lost = self.loss > 0.0 && self.rng.gen_bool(self.loss);
+        if lost || self.scripted_drop(from, to, &datagram) {
+            self.transcript.push(SimEvent::Lost {
+                at,
+                from,
+                to,
+                size: datagram.len(),
+            });
+            return;
+        }
+
+        let mut delay = self.latency;
+        if !self.jitter.is_zero() {
+            delay += self.jitter.mul_f64(self.rng.gen::<f64>());
+        }
+        self.serial += 1;
+        self.link
+            .insert((at + delay, self.serial), (from, to, datagram));
+    }
+
+    fn scripted_drop(&mut self, from: NodeId, to: NodeId, datagram: &[u8]) -> bool {
+        if !(0x80..=0x8f).contains(&datagram[0]) {
+            return false;
+        }
+        let Ok(packet) = FramePacket::read_from_slice(datagram) else {
+            return false;
+        };
+        self.drop_rules
+            .iter_mut()
+            .any(|(f, t, rule)| *f == from && *t == to && rule(&packet))
+    }
+}
+
+impl Server {
+    fn accepted(&self, client: NodeId) -> &Accepted {
+        self.conns
+            .get(&client)
+            .expect("no connection between these nodes")
+    }
+
+    /// Handles a datagram from `from` the way a listener does, `options` being the ones of
+    /// the connection it may open.
+    fn receive(
+        &mut self,
+        node: NodeId,
+        from: NodeId,
+        datagram: Vec<u8>,
+        options: ConnOptions,
+        at: Duration,
+        transcript: &mut Vec<SimEvent>,
+    ) {
+        let origin = from.address();
+        let locked = self.conns.get(&from).map(|accepted| accepted.mtu);
+
+        if let Ok(pk) = decode::<OfflinePacket>(&datagram) {
+            let reply = match pk {
+                OfflinePacket::OpenConnectRequest(pk) => {
+                    server::open_connect_reply(SIM_VERSIONS, self.server_id, origin, pk, locked)
+                }
+                OfflinePacket::SessionInfoRequest(pk) => {
+                    let reply = server::session_info_reply(self.server_id, origin, &pk, locked);
+                    if locked.is_none() {
+                        let outbox = Outbox::default();
+                        let queue = SendQueue::with_outbox(reply.mtu_size, origin, outbox.clone());
+                        let (conn, drivers) = Connection::with_send_queue(origin, queue, options);
+                        self.conns.insert(
+                            from,
+                            Accepted {
+                                conn,
+                                drivers,
+                                outbox,
+                                mtu: reply.mtu_size,
+                                alive: true,
+                            },
+                        );
+                    }
+                    reply.into()
+                }
+                _ => return,
+            };
+            if let Ok(reply) = reply.write_to_bytes() {
+                self.offline.push((from, reply.as_slice().to_vec()));
+            }
+            return;
+        }
+
+        match self.conns.get_mut(&from) {
+            Some(accepted) if accepted.alive => {
+                let Accepted { conn, drivers, .. } = accepted;
+                let mut payloads = Vec::new();
+                drive(drivers.packets.datagram(datagram), conn, &mut payloads);
+                record(node, from, payloads, at, transcript);
+                accepted.settle(node, from, at, transcript);
+            }
+            _ => {
+                // a peer that closed resends its disconnect until it is acknowledged.
+                if let Some(ack) = server::disconnect_ack(&datagram) {
+                    self.offline.push((from, ack));
+                }
+            }
+        }
+    }
+
+    fn turn(&mut self, node: NodeId, at: Duration, transcript: &mut Vec<SimEvent>) {
+        for (client, accepted) in self.conns.iter_mut() {
+            if !accepted.alive {
+                continue;
+            }
+            let Accepted { conn, drivers, .. } = accepted;
+            let mut payloads = Vec::new();
+            if drivers.released.try_recv().is_ok() {
+                drive(drivers.packets.release(), conn, &mut payloads);
+            }
+            if drivers.tick_woken.try_recv().is_ok() {
+                drivers.ticker.wake();
+            }
+            if drivers.ticker.next_tick() <= crate::util::now() && !block_on(drivers.ticker.tick())
+            {
+                accepted.alive = false;
+            }
+            record(node, *client, payloads, at, transcript);
+            accepted.settle(node, *client, at, transcript);
+        }
+        // the listener forgets a connection once its tick stopped.
+        self.conns.retain(|_, accepted| accepted.alive);
+    }
+}
+
+impl Accepted {
+    /// Receives what the connection has for the application.
+    fn settle(&mut self, node: NodeId, peer: NodeId, at: Duration, transcript: &mut Vec<SimEvent>) {
+        if closed(&self.drivers.ticker.closer) {
+            self.alive = false;
+        }
+        while let Some(Ok(event)) = self.conn.recv_event().now_or_never() {
+            transcript.push(SimEvent::Event {
+                at,
+                node,
+                peer,
+                event,
+            });
+        }
+        let mut payloads = Vec::new();
+        while let Some(Ok(payload)) = self.conn.recv().now_or_never() {
+            payloads.push(payload);
+        }
+        record(node, peer, payloads, at, transcript);
+    }
+}
+
+impl SimClient {
+    /// Sends the open connection request of the current rung of the mtu ladder.
+    fn open_request(&mut self, at: Duration) {
+        let Phase::Open1 {
+            ladder,
+            rung,
+            resend_at,
+        } = &mut self.phase
+        else {
+            return;
+        };
+        let request = RakPacket::from(OpenConnectRequest {
+            protocol: SIM_VERSION,
+            mtu_size: ladder[*rung],
+        });
+        if let Ok(request) = request.write_to_bytes() {
+            self.outbox
+                .lock()
+                .unwrap()
+                .push(request.as_slice().to_vec());
+        }
+        *resend_at = at + self.rng.jitter(OPEN_REPLY_TIMEOUT, HANDSHAKE_RESEND_JITTER);
+    }
+
+    /// Handles a datagram of the server, the way the handshake or the packet task does.
+    fn receive(
+        &mut self,
+        node: NodeId,
+        datagram: Vec<u8>,
+        at: Duration,
+        transcript: &mut Vec<SimEvent>,
+    ) {
+        let server = self.server.address();
+        match &mut self.phase {
+            Phase::Open1 { .. } => {
+                if !matches!(
+                    datagram.first(),
+                    Some(&OpenConnectReply::ID | &IncompatibleProtocolVersion::ID)
+                ) {
+                    return;
+                }
+                self.phase = match discovery::open_reply(&datagram) {
+                    DiscoveryStatus::Discovered(mtu) => {
+                        let wanted = block_on(async {
+                            handshake::wanted_capabilities(
+                                &*self.send_queue.read().await,
+                                &*self.client.recv_queue().lock().await,
+                            )
+                        });
+                        let request = handshake::session_info_request(server, mtu, self.id, wanted);
+                        if let Ok(request) = RakPacket::from(request).write_to_bytes() {
+                            self.outbox
+                                .lock()
+                                .unwrap()
+                                .push(request.as_slice().to_vec());
+                        }
+                        Phase::Open2 {
+                            mtu,
+                            wanted,
+                            deadline: at + SESSION_REPLY_TIMEOUT,
+                        }
+                    }
+                    DiscoveryStatus::IncompatibleVersion => {
+                        Phase::Failed(HandshakeError::IncompatibleVersion)
+                    }
+                    _ => Phase::Failed(HandshakeError::Rejected {
+                        stage: Stage::Open1,
+                        timings: HandshakeTimings::default(),
+                    }),
+                };
+            }
+            Phase::Open2 { mtu, wanted, .. } => {
+                // anything else the server still had in flight starts with another id.
+                let Ok(reply) = decode_packet::<SessionInfoReply>(&datagram) else {
+                    return;
+                };
+                if reply.mtu_size != *mtu {
+                    self.phase = Phase::Failed(HandshakeError::Rejected {
+                        stage: Stage::Open2,
+                        timings: HandshakeTimings::default(),
+                    });
+                    return;
+                }
+                let stage = ConnectStage::new(
+                    self.id,
+                    handshake::offered_capabilities(&reply, *wanted),
+                    server,
+                );
+                // a request that could not be sent is retried like one that was never answered.
+                let _ =
+                    block_on(async { stage.request(&mut *self.send_queue.write().await).await });
+                self.phase = Phase::Connect {
+                    stage,
+                    mtu: *mtu,
+                    tries: 1,
+                    resend_at: at
+                        + self
+                            .rng
+                            .jitter(CONNECT_RESEND_INTERVAL, HANDSHAKE_RESEND_JITTER),
+                };
+            }
+            Phase::Connect { stage, mtu, .. } => {
+                let accepted = block_on(async {
+                    let recv_queue = self.client.recv_queue();
+                    let mut send_q = self.send_queue.write().await;
+                    let mut recv_q = recv_queue.lock().await;
+                    stage.datagram(&datagram, &mut send_q, &mut recv_q).await
+                });
+                match accepted {
+                    Ok(false) => {}
+                    Ok(true) => self.connected(),
+                    Err(()) => {
+                        self.phase = Phase::Failed(HandshakeError::TimedOut {
+                            last_stage: Stage::Connect,
+                            mtu: *mtu,
+                            timings: HandshakeTimings::default(),
+                        })
+                    }
+                }
+            }
+            Phase::Connected {
+                drivers, handling, ..
+            } => {
+                if *handling && !closed(&self.closer) {
+                    *handling = block_on(drivers.packets.datagram(datagram));
+                }
+                self.settle(node, at, transcript);
+            }
+            Phase::Failed(_) => {}
+        }
+    }
+
+    /// Completes the connection the way [`Client::connect()`] does once the handshake is done.
+    fn connected(&mut self) {
+        block_on(async {
+            self.client.update_state(ConnectionState::Identified).await;
+            self.client
+                .handshake_completed(&self.send_queue)
+                .await
+                .expect("failed to queue the first payload");
+            self.client.update_state(ConnectionState::Connected).await;
+        });
+        let drivers = Box::new(self.client.drivers(
+            self.send_queue.clone(),
+            self.closer.clone(),
+            self.server.address(),
+        ));
+        self.phase = Phase::Connected {
+            drivers,
+            handling: true,
+            ticking: true,
+        };
+    }
+
+    fn turn(&mut self, node: NodeId, at: Duration, transcript: &mut Vec<SimEvent>) {
+        match &mut self.phase {
+            Phase::Open1 {
+                ladder,
+                rung,
+                resend_at,
+            } if *resend_at <= at => {
+                *rung += 1;
+                if *rung < ladder.len() {
+                    self.open_request(at);
+                } else {
+                    self.phase = Phase::Failed(HandshakeError::TimedOut {
+                        last_stage: Stage::Open1,
+                        mtu: ladder[ladder.len() - 1],
+                        timings: HandshakeTimings::default(),
+                    });
+                }
+            }
+            Phase::Open2 { mtu, deadline, .. } if *deadline <= at => {
+                self.phase = Phase::Failed(HandshakeError::TimedOut {
+                    last_stage: Stage::Open2,
+                    mtu: *mtu,
+                    timings: HandshakeTimings::default(),
+                });
+            }
+            Phase::Connect {
+                stage,
+                mtu,
+                tries,
+                resend_at,
+            } if *resend_at <= at => {
+                *resend_at = at
+                    + self
+                        .rng
+                        .jitter(CONNECT_RESEND_INTERVAL, HANDSHAKE_RESEND_JITTER);
+                if *tries >= CONNECT_ATTEMPTS {
+                    self.phase = Phase::Failed(HandshakeError::TimedOut {
+                        last_stage: Stage::Connect,
+                        mtu: *mtu,
+                        timings: HandshakeTimings::default(),
+                    });
+                    return;
+                }
+                let _ =
+                    block_on(async { stage.request(&mut *self.send_queue.write().await).await });
+                *tries += 1;
+            }
+            Phase::Connected {
+                drivers,
+                handling,
+                ticking,
+            } => {
+                if closed(&self.closer) {
+                    *handling = false;
+                    *ticking = false;
+                }
+                if *handling && drivers.released.try_recv().is_ok() {
+                    *handling = block_on(drivers.packets.release());
+                }
+                if *ticking && drivers.ticker.next_tick() <= crate::util::now() {
+                    *ticking = block_on(drivers.ticker.tick());
+                }
+                self.settle(node, at, transcript);
+            }
+            _ => {}
+        }
+    }
+
+    /// Receives what the client has for the application.
+    fn settle(&mut self, node: NodeId, at: Duration, transcript: &mut Vec<SimEvent>) {
+        let peer = self.server;
+        while let Some(Ok(event)) = self.client.recv_event().now_or_never() {
+            transcript.push(SimEvent::Event {
+                at,
+                node,
+                peer,
+                event,
+            });
+        }
+        let mut payloads = Vec::new();
+        while let Some(Ok(payload)) = self.client.recv().now_or_never() {
+            payloads.push(payload);
+        }
+        record(node, peer, payloads, at, transcript);
+    }
+}
+
+/// Runs `turn` of a driver of `conn` to completion, receiving the messages it hands the
+/// application meanwhile, so it never waits on a full channel.
+fn drive<F: Future>(turn: F, conn: &mut Connection, payloads: &mut Vec<Vec<u8>>) -> F::Output {
+    block_on(async {
+        let turn = turn.fuse();
+        pin_mut!(turn);
+        loop {
+            select_biased! {
+                output = turn => return output,
+                payload = conn.recv().fuse() => match payload {
+                    Ok(payload) => payloads.push(payload),
+                    Err(_) => return turn.await,
+                },
+            }
+        }
+    })
+}
+
+/// Whether `notify` was notified, the tasks of a node stop once it is.
+fn closed(notify: &Notify) -> bool {
+    notify.wait().now_or_never().is_some()
+}
+
+fn record(
+    to: NodeId,
+    from: NodeId,
+    payloads: Vec<Vec<u8>>,
+    at: Duration,
+    transcript: &mut Vec<SimEvent>,
+) {
+    for payload in payloads {
+        transcript.push(SimEvent::Delivered {
+            at,
+            from,
+            to,
+            payload,
+        });
+    }
+}
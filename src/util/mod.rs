@@ -7,6 +7,7 @@ use std::{collections::HashMap, time::SystemTime};
 use tokio::time::sleep as async_sleep;
 
 pub(crate) mod debug;
+pub mod socket;
 
 #[derive(Debug, Clone)]
 pub struct SafeGenerator<T> {
@@ -27,11 +28,19 @@ where
 macro_rules! impl_gen {
     ($n: ty) => {
         impl SafeGenerator<$n> {
+            #[deprecated(
+                since = "0.3.3",
+                note = "the connection tracks its indexes in `protocol::index`"
+            )]
             pub fn next(&mut self) -> $n {
                 self.sequence = self.sequence.wrapping_add(1);
                 return self.sequence;
             }
 
+            #[deprecated(
+                since = "0.3.3",
+                note = "the connection tracks its indexes in `protocol::index`"
+            )]
             pub fn get(&self) -> $n {
                 self.sequence
             }
@@ -165,6 +174,10 @@ pub fn to_address_token(remote: SocketAddr) -> String {
     return address;
 }
 
+#[deprecated(
+    since = "0.3.3",
+    note = "parse the address with `ToSocketAddrs` instead"
+)]
 pub fn from_address_token(remote: String) -> SocketAddr {
     let mut parsed = remote
         .to_socket_addrs()
@@ -172,6 +185,95 @@ pub fn from_address_token(remote: String) -> SocketAddr {
     SocketAddr::from(parsed.next().unwrap())
 }
 
+#[deprecated(since = "0.3.3", note = "use the sleep of the runtime instead")]
 pub async fn sleep(duration: std::time::Duration) {
     async_sleep(duration).await;
 }
+
+/// The current instant, taken from the virtual clock while a [`SimWorld`] is stepping.
+///
+/// [`SimWorld`]: crate::simulation::SimWorld
+pub(crate) fn now() -> std::time::Instant {
+    #[cfg(feature = "simulation")]
+    if let Some(now) = crate::simulation::virtual_now() {
+        return now;
+    }
+    std::time::Instant::now()
+}
+
+/// The time since the unix epoch, taken from the virtual clock while a [`SimWorld`] is stepping.
+///
+/// [`SimWorld`]: crate::simulation::SimWorld
+pub(crate) fn since_epoch() -> std::time::Duration {
+    #[cfg(feature = "simulation")]
+    if let Some(since) = crate::simulation::virtual_since_epoch() {
+        return since;
+    }
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+}
+
+/// This is a helper enum that allows you to pass in a `SocketAddr` or a `&str` to the `Listener::bind` function.
+/// This is useful for when you want to bind to a specific address, but you don't want to parse it yourself.
+///
+/// This Trait will successfully parse the following:
+/// - `SocketAddr::new("127.0.0.1:19132")`
+/// - `"127.0.0.1:19132"`
+/// - `String::from("127.0.0.1:19132")`
+pub enum PossiblySocketAddr<'a> {
+    SocketAddr(SocketAddr),
+    Str(&'a str),
+    String(String),
+    ActuallyNot,
+}
+
+impl PossiblySocketAddr<'_> {
+    pub fn to_socket_addr(self) -> Option<SocketAddr> {
+        match self {
+            PossiblySocketAddr::SocketAddr(addr) => Some(addr),
+            PossiblySocketAddr::Str(addr) => {
+                // we need to parse it
+                Some(addr.parse::<SocketAddr>().unwrap())
+            }
+            PossiblySocketAddr::String(addr) => {
+                // same as above, except less elegant >_<
+                Some(addr.clone().as_str().parse::<SocketAddr>().unwrap())
+            }
+            _ => None,
+        }
+    }
+}
+
+impl From<&str> for PossiblySocketAddr<'_> {
+    fn from(s: &str) -> Self {
+        PossiblySocketAddr::String(s.to_string())
+    }
+}
+
+impl From<String> for PossiblySocketAddr<'_> {
+    fn from(s: String) -> Self {
+        PossiblySocketAddr::String(s)
+    }
+}
+
+impl From<SocketAddr> for PossiblySocketAddr<'_> {
+    fn from(s: SocketAddr) -> Self {
+        PossiblySocketAddr::SocketAddr(s)
+    }
+}
+
+impl std::fmt::Display for PossiblySocketAddr<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PossiblySocketAddr::SocketAddr(addr) => write!(f, "{}", addr),
+            PossiblySocketAddr::Str(addr) => write!(f, "{}", addr),
+            PossiblySocketAddr::String(addr) => write!(f, "{}", addr),
+            PossiblySocketAddr::ActuallyNot => write!(f, "Not a valid address!"),
+        }
+    }
+}
+
+pub(crate) fn current_epoch() -> u64 {
+    since_epoch().as_secs()
+}
@@ -0,0 +1,318 @@
+//! Creation of the UDP sockets used by the [`Listener`] and [`Client`], for when the
+//! socket needs to be configured before it is bound.
+//!
+//! [`Listener`]: crate::server::Listener
+//! [`Client`]: crate::client::Client
+use std::io;
+use std::net::SocketAddr;
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::rakrs_debug;
+
+/// The socket options rak-rs sets before binding a socket.
+///
+/// This is implemented for [`socket2::Socket`], and exists so the calls can be observed
+/// without needing the privileges the real options require.
+pub trait SocketOpts {
+    /// Pins all traffic of the socket to the given network interface.
+    fn bind_device(&self, device: &str, ipv6: bool) -> io::Result<()>;
+
+    /// Sets the ECN codepoint of every datagram the socket sends, through `IP_TOS` or
+    /// `IPV6_TCLASS`. This is unsupported unless the platform has a way to set it.
+    fn set_ecn(&self, codepoint: u32, ipv6: bool) -> io::Result<()> {
+        let _ = (codepoint, ipv6);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "marking ECN is not supported on this platform",
+        ))
+    }
+}
+
+impl SocketOpts for Socket {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn bind_device(&self, device: &str, _ipv6: bool) -> io::Result<()> {
+        // SO_BINDTODEVICE
+        Socket::bind_device(self, Some(device.as_bytes()))
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    fn bind_device(&self, device: &str, ipv6: bool) -> io::Result<()> {
+        // IP_BOUND_IF / IPV6_BOUND_IF
+        let index = interface_index(device)?;
+        if ipv6 {
+            self.bind_device_by_index_v6(Some(index))
+        } else {
+            self.bind_device_by_index_v4(Some(index))
+        }
+    }
+
+    #[cfg(windows)]
+    fn bind_device(&self, device: &str, ipv6: bool) -> io::Result<()> {
+        use std::os::windows::io::AsRawSocket;
+
+        const IPPROTO_IP: i32 = 0;
+        const IPPROTO_IPV6: i32 = 41;
+        const IP_UNICAST_IF: i32 = 31;
+        const IPV6_UNICAST_IF: i32 = 31;
+
+        #[link(name = "ws2_32")]
+        extern "system" {
+            fn setsockopt(s: usize, level: i32, name: i32, value: *const u8, len: i32) -> i32;
+        }
+
+        // windows interfaces are addressed by their index.
+        let index: u32 = device.parse().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("\"{}\" is not an interface index", device),
+            )
+        })?;
+
+        // IPv4 expects the index in network byte order, IPv6 in host byte order.
+        let (level, name, value) = if ipv6 {
+            (IPPROTO_IPV6, IPV6_UNICAST_IF, index)
+        } else {
+            (IPPROTO_IP, IP_UNICAST_IF, index.to_be())
+        };
+
+        let res = unsafe {
+            setsockopt(
+                self.as_raw_socket() as usize,
+                level,
+                name,
+                &value as *const u32 as *const u8,
+                std::mem::size_of::<u32>() as i32,
+            )
+        };
+
+        if res != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios",
+        windows
+    )))]
+    fn bind_device(&self, _device: &str, _ipv6: bool) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "binding to a device is not supported on this platform",
+        ))
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios"
+    ))]
+    fn set_ecn(&self, codepoint: u32, ipv6: bool) -> io::Result<()> {
+        if !ipv6 {
+            return self.set_tos_v4(codepoint);
+        }
+        self.set_tclass_v6(codepoint)?;
+        // a dual stack socket sends to IPv4 peers with the IPv4 option.
+        let _ = self.set_tos_v4(codepoint);
+        Ok(())
+    }
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+fn interface_index(device: &str) -> io::Result<std::num::NonZeroU32> {
+    let name = std::ffi::CString::new(device)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid interface name"))?;
+    let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+    std::num::NonZeroU32::new(index).ok_or_else(io::Error::last_os_error)
+}
+
+/// Applies `device` to the socket, if there is one.
+/// The returned error describes what went wrong, including the privileges needed on Linux.
+pub fn apply_bind_device<S: SocketOpts + ?Sized>(
+    sock: &S,
+    device: Option<&str>,
+    ipv6: bool,
+) -> io::Result<()> {
+    let device = match device {
+        Some(device) => device,
+        None => return Ok(()),
+    };
+
+    sock.bind_device(device, ipv6).map_err(|e| {
+        let hint = if cfg!(any(target_os = "linux", target_os = "android"))
+            && e.kind() == io::ErrorKind::PermissionDenied
+        {
+            " (SO_BINDTODEVICE requires CAP_NET_RAW)"
+        } else {
+            ""
+        };
+        io::Error::new(
+            e.kind(),
+            format!(
+                "failed to bind socket to device \"{}\": {}{}",
+                device, e, hint
+            ),
+        )
+    })
+}
+
+/// The ECN-capable transport codepoint ECT(0), in the two low bits of the TOS byte.
+pub const ECT_0: u32 = 0b10;
+
+/// How much of ECN a socket supports, see [`ConnOptions::enable_ecn`].
+///
+/// [`ConnOptions::enable_ecn`]: crate::connection::options::ConnOptions::enable_ecn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EcnSupport {
+    /// ECN was not enabled.
+    Off,
+    /// ECN was enabled, but the socket could not mark its datagrams.
+    Unavailable,
+    /// Every datagram sent is marked ECT(0). The marks of received datagrams can't be read,
+    /// as the runtimes don't give access to their ancillary data.
+    SendOnly,
+}
+
+impl EcnSupport {
+    /// Whether the datagrams sent are marked as ECN capable.
+    pub fn is_marking(&self) -> bool {
+        *self == Self::SendOnly
+    }
+}
+
+/// Marks every datagram of the socket ECT(0) if `enable` is set. This never fails, a socket
+/// that can't be marked is used as it is, which the returned support tells.
+pub fn apply_ecn<S: SocketOpts + ?Sized>(sock: &S, enable: bool, ipv6: bool) -> EcnSupport {
+    if !enable {
+        return EcnSupport::Off;
+    }
+
+    match sock.set_ecn(ECT_0, ipv6) {
+        Ok(()) => EcnSupport::SendOnly,
+        Err(e) => {
+            rakrs_debug!("Failed to enable ECN, sending unmarked: {}", e);
+            EcnSupport::Unavailable
+        }
+    }
+}
+
+/// Marks the datagrams of a socket handed to the runtime already, like [`apply_ecn`].
+#[cfg(unix)]
+pub(crate) fn apply_ecn_async<S: std::os::fd::AsRawFd>(sock: &S, enable: bool) -> EcnSupport {
+    // the descriptor is only borrowed for as long as `sock` is.
+    let fd = unsafe { std::os::fd::BorrowedFd::borrow_raw(sock.as_raw_fd()) };
+    let sock = socket2::SockRef::from(&fd);
+    let ipv6 = matches!(sock.local_addr().map(|a| a.is_ipv6()), Ok(true));
+    apply_ecn(&*sock, enable, ipv6)
+}
+
+/// Marks the datagrams of a socket handed to the runtime already, like [`apply_ecn`].
+#[cfg(windows)]
+pub(crate) fn apply_ecn_async<S: std::os::windows::io::AsRawSocket>(
+    sock: &S,
+    enable: bool,
+) -> EcnSupport {
+    use std::os::windows::io::BorrowedSocket;
+
+    // the socket is only borrowed for as long as `sock` is.
+    let raw = unsafe { BorrowedSocket::borrow_raw(sock.as_raw_socket()) };
+    let sock = socket2::SockRef::from(&raw);
+    let ipv6 = matches!(sock.local_addr().map(|a| a.is_ipv6()), Ok(true));
+    apply_ecn(&*sock, enable, ipv6)
+}
+
+/// Creates a UDP socket bound to `address`, pinned to `device` if one is given.
+///
+/// The returned socket is non-blocking, ready to be handed to the async runtime.
+pub fn bind_udp(address: SocketAddr, device: Option<&str>) -> io::Result<std::net::UdpSocket> {
+    let sock = Socket::new(
+        Domain::for_address(address),
+        Type::DGRAM,
+        Some(Protocol::UDP),
+    )?;
+
+    apply_bind_device(&sock, device, address.is_ipv6())?;
+
+    sock.bind(&address.into())?;
+    sock.set_nonblocking(true)?;
+    Ok(sock.into())
+}
+
+/// Hands a bound socket over to the async runtime.
+#[cfg(feature = "async_std")]
+pub(crate) fn into_async(sock: std::net::UdpSocket) -> io::Result<async_std::net::UdpSocket> {
+    Ok(async_std::net::UdpSocket::from(sock))
+}
+
+/// Hands a bound socket over to the async runtime.
+#[cfg(feature = "async_tokio")]
+pub(crate) fn into_async(sock: std::net::UdpSocket) -> io::Result<tokio::net::UdpSocket> {
+    tokio::net::UdpSocket::from_std(sock)
+}
+
+/// The OS error a send fails with when the socket is out of buffer space.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub const ENOBUFS: i32 = 105;
+/// The OS error a send fails with when the datagram is too large for the route.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub const EMSGSIZE: i32 = 90;
+
+/// The OS error a send fails with when the socket is out of buffer space (`WSAENOBUFS`).
+#[cfg(windows)]
+pub const ENOBUFS: i32 = 10055;
+/// The OS error a send fails with when the datagram is too large for the route (`WSAEMSGSIZE`).
+#[cfg(windows)]
+pub const EMSGSIZE: i32 = 10040;
+
+/// The OS error a send fails with when the socket is out of buffer space.
+#[cfg(not(any(target_os = "linux", target_os = "android", windows)))]
+pub const ENOBUFS: i32 = 55;
+/// The OS error a send fails with when the datagram is too large for the route.
+#[cfg(not(any(target_os = "linux", target_os = "android", windows)))]
+pub const EMSGSIZE: i32 = 40;
+
+/// Why a datagram could not be sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendFailure {
+    /// The socket is out of buffer space, bursts easily cause this on macOS and the BSDs.
+    /// Sending again shortly after usually works.
+    NoBuffers,
+    /// The datagram is too large for the route to the peer, its MTU dropped.
+    TooLarge,
+    /// Anything else.
+    Other,
+}
+
+impl SendFailure {
+    /// Tells the transient failures of a send apart from the rest.
+    pub fn classify(err: &io::Error) -> Self {
+        match err.raw_os_error() {
+            Some(ENOBUFS) => Self::NoBuffers,
+            Some(EMSGSIZE) => Self::TooLarge,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// Whether a receive failed because the socket itself is gone, like when its interface was
+/// removed or its descriptor closed out from under it. The other failures concern a single
+/// datagram, like the reset an unreachable peer causes, and the next receive may well work.
+pub fn is_fatal_recv_error(err: &io::Error) -> bool {
+    if matches!(err.raw_os_error(), Some(ENOBUFS) | Some(EMSGSIZE)) {
+        return false;
+    }
+    !matches!(
+        err.kind(),
+        io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::Interrupted
+            | io::ErrorKind::TimedOut
+    )
+}
@@ -0,0 +1,128 @@
+#![cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_std::{future::timeout, net::UdpSocket, task};
+use rak_rs::connection::options::ConnOptions;
+use rak_rs::connection::queue::{RecvQueue, SendQueue};
+use rak_rs::protocol::frame::{Frame, FramePacket};
+use rak_rs::protocol::index::DatagramSeq;
+use rak_rs::protocol::reliability::Reliability;
+use rak_rs::{Client, Listener};
+
+fn datagram(sequence: u32) -> FramePacket {
+    let mut packet = FramePacket::new();
+    packet.sequence = DatagramSeq::new(sequence);
+    packet
+        .frames
+        .push(Frame::new(Reliability::Unreliable, Some(&[0xfe])));
+    packet
+}
+
+async fn mock() -> (SendQueue, UdpSocket) {
+    let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+    let queue = SendQueue::new(1400, 12000, 5, socket, peer.local_addr().unwrap());
+    (queue, peer)
+}
+
+/// Every datagram the peer received so far.
+async fn received(peer: &UdpSocket) -> Vec<Vec<u8>> {
+    let mut datagrams = Vec::new();
+    let mut buf = [0u8; 2048];
+    while let Ok(Ok(len)) = timeout(Duration::from_millis(50), peer.recv(&mut buf)).await {
+        datagrams.push(buf[..len].to_vec());
+    }
+    datagrams
+}
+
+#[test]
+fn test_forced_flush_sends_the_pending_ranges() {
+    task::block_on(async {
+        let (mut send_q, peer) = mock().await;
+        let mut recv_q = RecvQueue::new();
+
+        for sequence in [0, 1, 2, 3, 4, 7, 9] {
+            recv_q.insert(datagram(sequence)).unwrap();
+        }
+        assert_eq!(recv_q.ack_debt(), 7);
+
+        assert_eq!(send_q.send_acks(&mut recv_q).await, 7);
+        assert_eq!(recv_q.ack_debt(), 0);
+        assert_eq!(
+            received(&peer).await,
+            vec![vec![
+                0xc0, 0, 3, // ACK with 3 records
+                0, 0, 0, 0, 4, 0, 0, // 0..=4
+                1, 7, 0, 0, // 7
+                1, 9, 0, 0, // 9
+            ]]
+        );
+
+        // the tick that follows has nothing left to acknowledge.
+        assert_eq!(send_q.send_acks(&mut recv_q).await, 0);
+        assert!(received(&peer).await.is_empty());
+        assert_eq!(recv_q.repeated_acks(), 0);
+    });
+}
+
+#[test]
+fn test_only_resent_datagrams_are_acknowledged_twice() {
+    task::block_on(async {
+        let (mut send_q, peer) = mock().await;
+        let mut recv_q = RecvQueue::new();
+
+        recv_q.insert(datagram(0)).unwrap();
+        recv_q.insert(datagram(1)).unwrap();
+        // a duplicate before the acknowledgement went out costs nothing.
+        assert!(recv_q.insert(datagram(1)).is_err());
+        assert_eq!(recv_q.ack_debt(), 2);
+        assert_eq!(recv_q.repeated_acks(), 0);
+        send_q.send_acks(&mut recv_q).await;
+
+        // the peer resent 1, it never saw our acknowledgement.
+        assert!(recv_q.insert(datagram(1)).is_err());
+        recv_q.insert(datagram(2)).unwrap();
+        assert_eq!(recv_q.ack_debt(), 2);
+        assert_eq!(recv_q.repeated_acks(), 1);
+        assert_eq!(send_q.send_acks(&mut recv_q).await, 2);
+
+        let datagrams = received(&peer).await;
+        assert_eq!(datagrams.len(), 2);
+        assert_eq!(datagrams[1], vec![0xc0, 0, 1, 0, 1, 0, 0, 2, 0, 0]);
+    });
+}
+
+#[test]
+fn test_connection_acknowledges_above_the_limit() {
+    task::block_on(async {
+        let mut server = Listener::bind("127.0.0.1:19205").await.unwrap();
+        server.conn_options = ConnOptions {
+            ack_immediately_above: Some(0),
+            ..Default::default()
+        };
+        server.start().await.unwrap();
+
+        let client = Client::new(11, 1400);
+        timeout(Duration::from_secs(10), client.connect("127.0.0.1:19205"))
+            .await
+            .expect("connect timed out")
+            .expect("failed to connect");
+        let mut conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+
+        for i in 0..20u8 {
+            client.send_ord(&[0xfe, i], 0).await.unwrap();
+            timeout(Duration::from_secs(5), conn.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            // acknowledged as soon as it arrived, before it was handed to us.
+            assert_eq!(conn.ack_debt().await, 0);
+        }
+
+        assert_eq!(conn.flush_acks().await, 0);
+    });
+}
@@ -0,0 +1,159 @@
+#![cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+//! Frame sets of an established connection sent from another port of its host, which are
+//! either spoofed or the peer moving there, depending on the binding of the listener.
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use async_std::{future::timeout, net::UdpSocket, task};
+use binary_util::interfaces::Writer;
+use rak_rs::connection::event::{RakEvent, ViolationKind};
+use rak_rs::connection::options::{AddressBinding, MigrationOptions};
+use rak_rs::connection::Connection;
+use rak_rs::protocol::frame::{Frame, FramePacket};
+use rak_rs::protocol::index::DatagramSeq;
+use rak_rs::server::SPOOFED_DATAGRAM_THRESHOLD;
+use rak_rs::{Client, Listener, Reliability, ServerOptions};
+
+async fn connect(address: &str, address_binding: AddressBinding) -> (Listener, Client, Connection) {
+    let options = ServerOptions {
+        address_binding,
+        ..Default::default()
+    };
+    let mut server = Listener::bind_with_options(address, options).await.unwrap();
+    server.start().await.unwrap();
+
+    let client = Client::new(11, 1400);
+    timeout(Duration::from_secs(10), client.connect(address))
+        .await
+        .expect("connect timed out")
+        .expect("failed to connect");
+    let conn = timeout(Duration::from_secs(5), server.accept())
+        .await
+        .unwrap()
+        .unwrap();
+    // only an established connection is bound.
+    let event = timeout(Duration::from_secs(5), conn.recv_event())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(event, RakEvent::Connected);
+    (server, client, conn)
+}
+
+/// Sends a frame set that looks like one of the client, with an unreliable user packet.
+async fn spoof(socket: &UdpSocket, server: &str, sequence: u32, body: &[u8]) {
+    let mut datagram = FramePacket::new();
+    datagram.sequence = DatagramSeq::new(sequence);
+    datagram
+        .frames
+        .push(Frame::new(Reliability::Unreliable, Some(body)));
+    socket
+        .send_to(datagram.write_to_bytes().unwrap().as_slice(), server)
+        .await
+        .unwrap();
+}
+
+async fn addresses(server: &Listener) -> Vec<SocketAddr> {
+    server
+        .connections()
+        .await
+        .iter()
+        .map(|info| info.address)
+        .collect()
+}
+
+/// Waits for the count of spoofed datagrams to reach `count`.
+async fn spoofed(conn: &Connection, count: u64) -> u64 {
+    for _ in 0..50 {
+        if conn.stats().await.spoofed_datagrams >= count {
+            break;
+        }
+        task::sleep(Duration::from_millis(20)).await;
+    }
+    conn.stats().await.spoofed_datagrams
+}
+
+#[test]
+fn test_strict_drops_and_reports_spoofed_datagrams() {
+    task::block_on(async {
+        const ADDRESS: &str = "127.0.0.1:19294";
+        let (server, client, mut conn) = connect(ADDRESS, AddressBinding::default()).await;
+        let attacker = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let from = attacker.local_addr().unwrap();
+
+        // a sequence nowhere near the window of the connection isn't one of its datagrams.
+        spoof(&attacker, ADDRESS, 1_000_000, &[0xfe, 0]).await;
+        for i in 0..=SPOOFED_DATAGRAM_THRESHOLD {
+            spoof(&attacker, ADDRESS, 100 + i as u32, &[0xfe, 1]).await;
+        }
+        assert_eq!(
+            spoofed(&conn, SPOOFED_DATAGRAM_THRESHOLD + 1).await,
+            SPOOFED_DATAGRAM_THRESHOLD + 1
+        );
+
+        // nothing was delivered or answered, the connection stays where it was made from.
+        client.send_ord(&[0xfe, 2], 0).await.unwrap();
+        let packet = timeout(Duration::from_secs(5), conn.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(packet, vec![0xfe, 2]);
+        let mut buf = [0u8; 2048];
+        assert!(
+            timeout(Duration::from_millis(500), attacker.recv_from(&mut buf))
+                .await
+                .is_err(),
+            "the spoofed source was answered"
+        );
+        assert_eq!(addresses(&server).await, vec![conn.address]);
+
+        let expected = RakEvent::ProtocolViolation {
+            kind: ViolationKind::SpoofedSource {
+                bound: conn.address,
+                from,
+            },
+        };
+        loop {
+            let event = timeout(Duration::from_secs(5), conn.recv_event())
+                .await
+                .expect("the spoofed source was never reported")
+                .unwrap();
+            if event == expected {
+                break;
+            }
+        }
+    });
+}
+
+#[test]
+fn test_migration_follows_the_peer() {
+    task::block_on(async {
+        const ADDRESS: &str = "127.0.0.1:19295";
+        let binding = AddressBinding::AllowMigration(MigrationOptions { max_migrations: 1 });
+        let (server, _client, mut conn) = connect(ADDRESS, binding).await;
+        let moved = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+        spoof(&moved, ADDRESS, 100, &[0xfe, 1]).await;
+        let packet = timeout(Duration::from_secs(5), conn.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(packet, vec![0xfe, 1]);
+        assert_eq!(addresses(&server).await, vec![moved.local_addr().unwrap()]);
+
+        // the connection sends to where its peer moved, the acknowledgement included.
+        conn.send(&[0xfe, 2], true).await.unwrap();
+        let mut buf = [0u8; 2048];
+        let (_, source) = timeout(Duration::from_secs(5), moved.recv_from(&mut buf))
+            .await
+            .expect("nothing was sent to where the peer moved")
+            .unwrap();
+        assert_eq!(source, ADDRESS.parse::<SocketAddr>().unwrap());
+
+        // it moved as often as it may, another port is now spoofed.
+        let attacker = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        spoof(&attacker, ADDRESS, 101, &[0xfe, 3]).await;
+        assert_eq!(spoofed(&conn, 1).await, 1);
+        assert_eq!(addresses(&server).await, vec![moved.local_addr().unwrap()]);
+    });
+}
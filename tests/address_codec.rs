@@ -0,0 +1,199 @@
+//! Property and edge case coverage of how addresses are put on the wire.
+//!
+//! The round trips run over seeded random addresses, so a failure is reproducible from the
+//! seed in its message.
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+
+use binary_util::interfaces::{Reader, Writer};
+use binary_util::io::ByteWriter;
+use rak_rs::protocol::packet::online::OnlinePacket;
+use rak_rs::protocol::primitives::{RakAddress, ScopeId};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const SEED: u64 = 0x52414b;
+const CASES: usize = 10_000;
+
+fn fixture(name: &str) -> Vec<u8> {
+    let path = format!(
+        "{}/tests/fixtures/vanilla/{}.hex",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    );
+    let text = std::fs::read_to_string(&path).unwrap();
+    let mut bytes = Vec::new();
+    for line in text.lines() {
+        let line = line.split('#').next().unwrap();
+        for token in line.split_whitespace() {
+            let (byte, count) = match token.split_once('x') {
+                Some((byte, count)) => (byte, count.parse().unwrap()),
+                None => (token, 1),
+            };
+            let byte = u8::from_str_radix(byte, 16).unwrap();
+            bytes.extend(std::iter::repeat(byte).take(count));
+        }
+    }
+    bytes
+}
+
+/// Any address, with the edge cases weighted in so they show up in every run.
+fn arbitrary(rng: &mut StdRng) -> SocketAddr {
+    let port = match rng.gen_range(0..4) {
+        0 => 0,
+        1 => u16::MAX,
+        _ => rng.gen(),
+    };
+    match rng.gen_range(0..5) {
+        0 => SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::from(rng.gen::<u32>()), port)),
+        1 => SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port)),
+        2 => SocketAddr::V6(SocketAddrV6::new(
+            Ipv4Addr::from(rng.gen::<u32>()).to_ipv6_mapped(),
+            port,
+            rng.gen(),
+            rng.gen(),
+        )),
+        3 => SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::UNSPECIFIED, port, 0, 0)),
+        _ => SocketAddr::V6(SocketAddrV6::new(
+            Ipv6Addr::from(rng.gen::<u128>()),
+            port,
+            rng.gen(),
+            rng.gen(),
+        )),
+    }
+}
+
+/// What an address reads back as after being written with the given scope handling.
+fn expected(address: SocketAddr, scope: ScopeId) -> SocketAddr {
+    match address {
+        SocketAddr::V6(v6) => match v6.ip().to_ipv4_mapped() {
+            Some(ip) => SocketAddr::V4(SocketAddrV4::new(ip, v6.port())),
+            None if scope == ScopeId::Zeroed => {
+                SocketAddr::V6(SocketAddrV6::new(*v6.ip(), v6.port(), v6.flowinfo(), 0))
+            }
+            None => address,
+        },
+        address => address,
+    }
+}
+
+fn encode(address: SocketAddr, scope: ScopeId) -> Vec<u8> {
+    let mut buf = ByteWriter::new();
+    RakAddress(address).write_with(&mut buf, scope).unwrap();
+    buf.as_slice().to_vec()
+}
+
+#[test]
+fn test_round_trips() {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    for case in 0..CASES {
+        let address = arbitrary(&mut rng);
+        for scope in [ScopeId::Zeroed, ScopeId::Kept] {
+            let bytes = encode(address, scope);
+            let decoded = RakAddress::read_from_slice(&bytes).unwrap().0;
+            assert_eq!(
+                decoded,
+                expected(address, scope),
+                "case {} of seed {:#x}: {} with {:?}",
+                case,
+                SEED,
+                address,
+                scope
+            );
+            assert_eq!(bytes.len(), if decoded.is_ipv4() { 7 } else { 29 });
+
+            // whatever was read is written back the same way.
+            assert_eq!(encode(decoded, scope), bytes);
+        }
+    }
+}
+
+#[test]
+fn test_truncated_buffers_are_errors() {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    for _ in 0..1000 {
+        let bytes = encode(arbitrary(&mut rng), ScopeId::Kept);
+        for cut in 0..bytes.len() {
+            assert!(
+                RakAddress::read_from_slice(&bytes[..cut]).is_err(),
+                "{:x?} cut at {} was read",
+                bytes,
+                cut
+            );
+        }
+    }
+}
+
+#[test]
+fn test_edge_cases() {
+    // v4-mapped addresses go out as native IPv4, even with a flow info and scope id.
+    let mapped: SocketAddr = "[::ffff:127.0.0.1]:19132".parse().unwrap();
+    assert_eq!(
+        encode(mapped, ScopeId::Kept),
+        vec![4, 0x80, 0xff, 0xff, 0xfe, 0x4a, 0xbc]
+    );
+
+    // the scope id is read, but not written unless asked to.
+    let scoped = SocketAddr::V6(SocketAddrV6::new("fe80::1".parse().unwrap(), 19132, 7, 3));
+    let kept = encode(scoped, ScopeId::Kept);
+    assert_eq!(&kept[25..], &[0, 0, 0, 3]);
+    assert_eq!(RakAddress::read_from_slice(&kept).unwrap().0, scoped);
+    assert_eq!(&encode(scoped, ScopeId::Zeroed)[25..], &[0, 0, 0, 0]);
+    assert_eq!(
+        RakAddress(scoped).write_to_bytes().unwrap().as_slice(),
+        &encode(scoped, ScopeId::Zeroed)[..]
+    );
+
+    // the flow info is kept either way.
+    assert_eq!(&kept[5..9], &[0, 0, 0, 7]);
+
+    // vanilla fills unused internal ids with unspecified addresses on port 0.
+    for unused in ["0.0.0.0:0", "255.255.255.255:0", "[::]:0"] {
+        let address: SocketAddr = unused.parse().unwrap();
+        assert_eq!(
+            RakAddress::read_from_slice(&encode(address, ScopeId::Zeroed))
+                .unwrap()
+                .0,
+            address
+        );
+    }
+
+    for version in [0u8, 5, 0x45, 0xff] {
+        let mut bytes = encode("127.0.0.1:19132".parse().unwrap(), ScopeId::Zeroed);
+        bytes[0] = version;
+        let error = RakAddress::read_from_slice(&bytes).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(
+            error.to_string().contains(&format!("version {}", version)),
+            "{}",
+            error
+        );
+    }
+}
+
+#[test]
+fn test_dual_stack_system_addresses() {
+    let bytes = fixture("connection_request_accepted_dual_stack");
+    let packet = OnlinePacket::read_from_slice(&bytes).unwrap();
+    assert_eq!(packet.write_to_bytes().unwrap().as_slice(), &bytes[..]);
+
+    match packet {
+        OnlinePacket::ConnectionAccept(pk) => {
+            assert_eq!(pk.client_address, "[::1]:54321".parse().unwrap());
+            let mut internal_ids = vec![
+                "127.0.0.1:19132".parse::<SocketAddr>().unwrap(),
+                "[fe80::1]:19132".parse().unwrap(),
+            ];
+            internal_ids.extend(["255.255.255.255:0".parse::<SocketAddr>().unwrap(); 8]);
+            assert_eq!(pk.internal_ids, internal_ids);
+            assert_eq!(pk.request_time, 0x2a);
+            assert_eq!(pk.timestamp, 0x100);
+        }
+        pk => panic!("{:?}", pk),
+    }
+
+    // the internal ids are counted from what is left, so a packet cut short can still read
+    // as one with fewer ids, but it never panics.
+    for cut in 0..bytes.len() {
+        let _ = OnlinePacket::read_from_slice(&bytes[..cut]);
+    }
+}
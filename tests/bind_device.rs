@@ -0,0 +1,61 @@
+use std::cell::RefCell;
+use std::io;
+use std::net::SocketAddr;
+
+use rak_rs::socket::{apply_bind_device, bind_udp, SocketOpts};
+
+#[derive(Default)]
+struct MockSocket {
+    calls: RefCell<Vec<(String, bool)>>,
+    fail: Option<io::ErrorKind>,
+}
+
+impl SocketOpts for MockSocket {
+    fn bind_device(&self, device: &str, ipv6: bool) -> io::Result<()> {
+        self.calls.borrow_mut().push((device.to_string(), ipv6));
+        match self.fail {
+            Some(kind) => Err(io::Error::from(kind)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[test]
+fn test_bind_device_attempted() {
+    let sock = MockSocket::default();
+
+    apply_bind_device(&sock, None, false).unwrap();
+    assert!(sock.calls.borrow().is_empty());
+
+    apply_bind_device(&sock, Some("eth0"), false).unwrap();
+    apply_bind_device(&sock, Some("wg0"), true).unwrap();
+    assert_eq!(
+        *sock.calls.borrow(),
+        vec![("eth0".to_string(), false), ("wg0".to_string(), true)]
+    );
+}
+
+#[test]
+fn test_bind_device_error_names_device() {
+    let sock = MockSocket {
+        fail: Some(io::ErrorKind::PermissionDenied),
+        ..Default::default()
+    };
+
+    let err = apply_bind_device(&sock, Some("eth0"), false).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    assert!(err.to_string().contains("eth0"));
+
+    #[cfg(target_os = "linux")]
+    assert!(err.to_string().contains("CAP_NET_RAW"));
+}
+
+#[test]
+fn test_bind_without_device() {
+    let address: SocketAddr = "127.0.0.1:0".parse().unwrap();
+    let sock = bind_udp(address, None).unwrap();
+    let local = sock.local_addr().unwrap();
+
+    assert!(local.ip().is_loopback());
+    assert_ne!(local.port(), 0);
+}
@@ -0,0 +1,84 @@
+#![cfg(feature = "async_std")]
+//! Drops an immediate send at each of its await points, and checks the peer still gets
+//! every message whole and in order.
+use std::future::Future;
+use std::sync::Arc;
+use std::task::Context;
+use std::time::Duration;
+
+use async_std::{future::timeout, net::UdpSocket, task};
+use binary_util::interfaces::Reader;
+use futures::channel::mpsc;
+use futures::task::noop_waker;
+use rak_rs::connection::queue::{RecvQueue, SendQueue};
+use rak_rs::protocol::ack::{Ack, Ackable};
+use rak_rs::protocol::frame::FramePacket;
+use rak_rs::protocol::reliability::Reliability;
+
+/// Feeds every frame set the peer received so far to `recv_q`.
+async fn receive(peer: &UdpSocket, recv_q: &mut RecvQueue) {
+    let mut buf = [0u8; 2048];
+    while let Ok(Ok(len)) = timeout(Duration::from_millis(50), peer.recv(&mut buf)).await {
+        if let 0x80..=0x8f = buf[0] {
+            recv_q
+                .insert(FramePacket::read_from_slice(&buf[..len]).unwrap())
+                .unwrap();
+        }
+    }
+}
+
+#[test]
+fn test_cancelled_send_is_delivered_whole() {
+    task::block_on(async {
+        let before = vec![0xfe, 0];
+        let big = (0..5000).map(|i| i as u8).collect::<Vec<u8>>();
+        let after = vec![0xfe, 2];
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        // the big message is split in 4 fragments, so there are 4 datagrams to cut it off before.
+        for cut in 0..4 {
+            let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+            let mut send_q = SendQueue::new(1400, 12000, 5, socket, peer.local_addr().unwrap());
+            send_q
+                .insert(&before, Reliability::ReliableOrd, true, Some(0))
+                .await
+                .unwrap();
+
+            let (permits, gate) = mpsc::unbounded();
+            send_q.gate_sends(gate);
+            {
+                let mut send =
+                    Box::pin(send_q.insert(&big, Reliability::ReliableOrd, true, Some(0)));
+                assert!(send.as_mut().poll(&mut cx).is_pending());
+                for _ in 0..cut {
+                    permits.unbounded_send(()).unwrap();
+                    assert!(send.as_mut().poll(&mut cx).is_pending(), "cut {}", cut);
+                }
+            }
+            drop(permits);
+
+            send_q
+                .insert(&after, Reliability::ReliableOrd, true, Some(0))
+                .await
+                .unwrap();
+            send_q.update().await;
+
+            // the datagram the send was cut off at never left, the peer asks for it again.
+            let mut recv_q = RecvQueue::new();
+            receive(&peer, &mut recv_q).await;
+            let missing = send_q.nack(Ack::from_ranges(recv_q.nack_queue(), true));
+            assert_eq!(missing.len(), 1, "cut {}", cut);
+            send_q.resend(missing).await;
+            receive(&peer, &mut recv_q).await;
+
+            assert_eq!(
+                recv_q.flush(),
+                vec![before.clone(), big.clone(), after.clone()],
+                "cut {}",
+                cut
+            );
+        }
+    });
+}
@@ -0,0 +1,289 @@
+#![cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+//! Simulates peers running other versions of rak-rs, by handing the listener the capabilities
+//! an older or a newer version would send.
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use async_std::{future::timeout, net::UdpSocket, task};
+use binary_util::interfaces::{Reader, Writer};
+use rak_rs::protocol::frame::{Frame, FramePacket};
+use rak_rs::protocol::index::{DatagramSeq, MessageIndex};
+use rak_rs::protocol::packet::offline::{OfflinePacket, SessionInfoRequest};
+use rak_rs::protocol::packet::online::{
+    Capabilities, ConnectionAccept, ConnectionRequest, CrateVersion, OnlinePacket,
+};
+use rak_rs::protocol::packet::RakPacket;
+use rak_rs::protocol::Magic;
+use rak_rs::{Client, ConnOptions, Listener, Reliability};
+
+/// The bytes of a [`ConnectionRequest`] before its capabilities.
+const REQUEST_HEADER: usize = 8 + 8 + 1;
+
+const FUTURE: CrateVersion = CrateVersion {
+    major: 9,
+    minor: 1,
+    patch: 2,
+};
+
+fn request(capabilities: Capabilities) -> Vec<u8> {
+    ConnectionRequest {
+        client_id: 7,
+        time: 1,
+        security: false,
+        capabilities,
+    }
+    .write_to_bytes()
+    .unwrap()
+    .as_slice()
+    .to_vec()
+}
+
+fn checksummed() -> ConnOptions {
+    ConnOptions {
+        datagram_checksum: true,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_versioned_encoding_round_trips() {
+    // nothing offered is nothing sent, vanilla peers keep seeing vanilla packets.
+    assert_eq!(request(Capabilities::NONE.offer()).len(), REQUEST_HEADER);
+
+    let offer = Capabilities::DATAGRAM_CHECKSUM.offer();
+    assert_eq!(offer.crate_version(), Some(CrateVersion::current()));
+    let bytes = request(offer);
+    assert_eq!(bytes[REQUEST_HEADER], Capabilities::TLV_MARKER);
+    assert_eq!(bytes[REQUEST_HEADER + 1], Capabilities::TLV_VERSION);
+    assert_eq!(
+        ConnectionRequest::read_from_slice(&bytes)
+            .unwrap()
+            .capabilities,
+        offer
+    );
+
+    // an older crate reads the marker as its flag byte, and must not find anything in it.
+    let legacy_flags = request(Capabilities::DATAGRAM_CHECKSUM)[REQUEST_HEADER];
+    assert_eq!(bytes[REQUEST_HEADER] & legacy_flags, 0);
+
+    // the version alone is enough to switch encodings.
+    let versioned = Capabilities::NONE.with_crate_version(FUTURE);
+    let decoded = ConnectionRequest::read_from_slice(&request(versioned))
+        .unwrap()
+        .capabilities;
+    assert_eq!(decoded, versioned);
+    assert!(decoded.is_empty());
+}
+
+#[test]
+fn test_unknown_capabilities_are_skipped() {
+    let mut bytes = request(Capabilities::NONE);
+    // a newer encoding, its capabilities are laid out the same.
+    bytes.extend_from_slice(&[Capabilities::TLV_MARKER, 7]);
+    // a capability we don't know about.
+    bytes.extend_from_slice(&[0x42, 0, 3, 0xaa, 0xbb, 0xcc]);
+    bytes.extend_from_slice(&[Capabilities::TLV_DATAGRAM_CHECKSUM, 0, 0]);
+    // a crate version with more to it than we know about.
+    bytes.extend_from_slice(&[
+        Capabilities::TLV_CRATE_VERSION,
+        0,
+        8,
+        0,
+        9,
+        0,
+        1,
+        0,
+        2,
+        0xff,
+        0xff,
+    ]);
+    // another unknown one, without a payload.
+    bytes.extend_from_slice(&[0x7f, 0, 0]);
+
+    let capabilities = ConnectionRequest::read_from_slice(&bytes)
+        .unwrap()
+        .capabilities;
+    assert_eq!(
+        capabilities,
+        Capabilities::DATAGRAM_CHECKSUM.with_crate_version(FUTURE)
+    );
+}
+
+#[test]
+fn test_truncated_capability_is_an_error() {
+    let mut bytes = request(Capabilities::NONE);
+    bytes.extend_from_slice(&[Capabilities::TLV_MARKER, 1, 0x42, 0, 9, 0xaa]);
+    assert!(ConnectionRequest::read_from_slice(&bytes).is_err());
+}
+
+#[test]
+fn test_extensions_disable_without_the_peer() {
+    let server = Capabilities::DATAGRAM_CHECKSUM.with_crate_version(CrateVersion::current());
+
+    // an older crate, or a vanilla peer.
+    for offer in [Capabilities::NONE, Capabilities::DATAGRAM_CHECKSUM] {
+        let reply = server.intersection(offer);
+        assert_eq!(reply, offer, "a legacy offer gets a legacy reply");
+        assert_eq!(reply.crate_version(), None);
+    }
+
+    // a newer crate that has checksums turned off, or doesn't support them anymore.
+    let reply = server.intersection(Capabilities::NONE.with_crate_version(FUTURE));
+    assert!(!reply.contains(Capabilities::DATAGRAM_CHECKSUM));
+    assert_eq!(reply.crate_version(), Some(CrateVersion::current()));
+
+    let reply = server.intersection(Capabilities::DATAGRAM_CHECKSUM.with_crate_version(FUTURE));
+    assert!(reply.contains(Capabilities::DATAGRAM_CHECKSUM));
+
+    // and the other way around, a server that doesn't do checksums.
+    let server = Capabilities::NONE.with_crate_version(CrateVersion::current());
+    let reply = server.intersection(Capabilities::DATAGRAM_CHECKSUM.offer());
+    assert!(!reply.contains(Capabilities::DATAGRAM_CHECKSUM));
+}
+
+#[test]
+fn test_accept_carries_versioned_capabilities() {
+    for internal_ids in [0, 5, 10, 20] {
+        let accept = ConnectionAccept {
+            client_address: "127.0.0.1:1".parse().unwrap(),
+            system_index: 0,
+            internal_ids: vec!["255.255.255.255:19132".parse().unwrap(); internal_ids],
+            request_time: 1,
+            timestamp: 2,
+            capabilities: Capabilities::DATAGRAM_CHECKSUM.with_crate_version(FUTURE),
+        };
+        let decoded =
+            ConnectionAccept::read_from_slice(accept.write_to_bytes().unwrap().as_slice()).unwrap();
+        assert_eq!(decoded.internal_ids, accept.internal_ids);
+        assert_eq!(decoded.request_time, 1);
+        assert_eq!(decoded.timestamp, 2);
+        assert_eq!(decoded.capabilities, accept.capabilities);
+    }
+}
+
+/// A peer that speaks just enough RakNet to send its connection request.
+struct MockPeer {
+    socket: UdpSocket,
+}
+
+impl MockPeer {
+    async fn open(server: &str) -> Self {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        socket.connect(server).await.unwrap();
+        let session = RakPacket::from(OfflinePacket::SessionInfoRequest(SessionInfoRequest {
+            magic: Magic::new(),
+            address: server.parse::<SocketAddr>().unwrap(),
+            mtu_size: 1400,
+            client_id: 7,
+            capabilities: Capabilities::NONE,
+        }));
+        socket
+            .send(session.write_to_bytes().unwrap().as_slice())
+            .await
+            .unwrap();
+        Self { socket }
+    }
+
+    /// Sends the request, and returns the capabilities the server agreed to.
+    async fn request(&self, body: &[u8]) -> Capabilities {
+        let mut body = body.to_vec();
+        body.insert(0, 0x09);
+        let mut frame = Frame::new(Reliability::Reliable, Some(&body));
+        frame.reliable_index = Some(MessageIndex::new(0));
+        let mut datagram = FramePacket::new();
+        datagram.sequence = DatagramSeq::new(0);
+        datagram.frames.push(frame);
+        self.socket
+            .send(datagram.write_to_bytes().unwrap().as_slice())
+            .await
+            .unwrap();
+
+        let mut buf = [0u8; 2048];
+        loop {
+            let len = timeout(Duration::from_secs(5), self.socket.recv(&mut buf))
+                .await
+                .expect("the server never answered")
+                .unwrap();
+            if let Ok(datagram) = FramePacket::read_from_slice(&buf[..len]) {
+                for frame in datagram.frames {
+                    if let Ok(OnlinePacket::ConnectionAccept(pk)) =
+                        OnlinePacket::read_from_slice(&frame.body)
+                    {
+                        return pk.capabilities;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_listener_negotiates_with_other_versions() {
+    task::block_on(async {
+        const ADDRESS: &str = "127.0.0.1:19206";
+        let mut server = Listener::bind_with_options(ADDRESS, checksummed())
+            .await
+            .unwrap();
+        server.start().await.unwrap();
+
+        // a newer crate, without checksums but with a capability we don't know about.
+        let newer = MockPeer::open(ADDRESS).await;
+        let conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+        let mut offer = request(Capabilities::NONE.with_crate_version(FUTURE));
+        offer.extend_from_slice(&[0x42, 0, 2, 0xaa, 0xbb]);
+        let reply = newer.request(&offer).await;
+        assert!(!reply.contains(Capabilities::DATAGRAM_CHECKSUM));
+        assert_eq!(reply.crate_version(), Some(CrateVersion::current()));
+        assert_eq!(conn.meta().await.peer_crate_version(), Some(FUTURE));
+
+        // an older crate, that only knows the legacy byte.
+        let older = MockPeer::open(ADDRESS).await;
+        let conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+        let reply = older.request(&request(Capabilities::NONE)).await;
+        assert_eq!(reply, Capabilities::NONE);
+        assert_eq!(conn.meta().await.peer_crate_version(), None);
+    });
+}
+
+#[test]
+fn test_client_advertises_its_version() {
+    task::block_on(async {
+        const ADDRESS: &str = "127.0.0.1:19207";
+        let mut server = Listener::bind_with_options(ADDRESS, checksummed())
+            .await
+            .unwrap();
+        server.start().await.unwrap();
+
+        let client = Client::new(11, 1400).with_options(checksummed());
+        timeout(Duration::from_secs(10), client.connect(ADDRESS))
+            .await
+            .expect("connect timed out")
+            .expect("failed to connect");
+        let conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            conn.meta().await.peer_crate_version(),
+            Some(CrateVersion::current())
+        );
+
+        // without an extension to offer, the client looks like any other RakNet peer.
+        let client = Client::new(11, 1400);
+        timeout(Duration::from_secs(10), client.connect(ADDRESS))
+            .await
+            .expect("connect timed out")
+            .expect("failed to connect");
+        let conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(conn.meta().await.peer_crate_version(), None);
+    });
+}
@@ -0,0 +1,103 @@
+#![cfg(feature = "async_std")]
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_std::{future::timeout, net::UdpSocket, task};
+use binary_util::interfaces::Reader;
+use rak_rs::connection::queue::{ChannelScheduler, SendQueue};
+use rak_rs::protocol::frame::FramePacket;
+use rak_rs::protocol::reliability::Reliability;
+
+/// A bulk transfer on channel 2, and a small message on channel 0 every tick, at the same
+/// reliability, over a window of 8 frames per tick.
+/// Returns how many ticks each small message waited before it was on the wire.
+async fn interactive_latency(frame_budget: Option<usize>) -> Vec<usize> {
+    let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+    let mut send_q = SendQueue::new(1400, 12000, 5, socket, peer.local_addr().unwrap());
+    send_q.set_frame_budget(frame_budget);
+
+    // far more than fits in a single tick.
+    for i in 0..400u16 {
+        let mut bulk = vec![0xfe, 2];
+        bulk.extend_from_slice(&i.to_be_bytes());
+        bulk.resize(1000, 0);
+        send_q
+            .insert(&bulk, Reliability::ReliableOrd, false, Some(2))
+            .await
+            .unwrap();
+    }
+
+    let mut waited = Vec::new();
+    let mut queued_at = Vec::new();
+    let mut buf = [0u8; 2048];
+    for tick in 0..TICKS {
+        send_q
+            .insert(
+                &[0xfe, 0, tick as u8],
+                Reliability::ReliableOrd,
+                false,
+                Some(0),
+            )
+            .await
+            .unwrap();
+        queued_at.push(tick);
+        send_q.update().await;
+
+        while let Ok(Ok(len)) = timeout(Duration::from_millis(20), peer.recv(&mut buf)).await {
+            let packet = FramePacket::read_from_slice(&buf[..len]).unwrap();
+            for frame in packet.frames {
+                if frame.order_channel == Some(0) && frame.body.len() == 3 {
+                    let queued = frame.body[2] as usize;
+                    // a resend of a message that was already seen doesn't count.
+                    if waited.len() == queued {
+                        waited.push(tick - queued_at[queued]);
+                    }
+                }
+            }
+        }
+    }
+    waited
+}
+
+const TICKS: usize = 30;
+
+#[test]
+fn test_bulk_channel_does_not_starve_others() {
+    task::block_on(async {
+        let waited = interactive_latency(Some(8)).await;
+        assert_eq!(waited.len(), TICKS, "interactive messages were never sent");
+        for (queued, waited) in waited.into_iter().enumerate() {
+            assert!(
+                waited <= 1,
+                "message {} waited {} ticks behind the bulk transfer",
+                queued,
+                waited
+            );
+        }
+    });
+}
+
+#[test]
+fn test_channel_order_is_preserved() {
+    let mut scheduler = ChannelScheduler::new(2);
+
+    for i in 0..5 {
+        scheduler.push(0, (0, i));
+        scheduler.push(1, (1, i));
+    }
+
+    let mut taken = Vec::new();
+    while !scheduler.is_empty() {
+        taken.extend(scheduler.take(3));
+    }
+
+    for channel in 0..2 {
+        let order = taken
+            .iter()
+            .filter(|(c, _)| *c == channel)
+            .map(|(_, i)| *i)
+            .collect::<Vec<_>>();
+        assert_eq!(order, vec![0, 1, 2, 3, 4]);
+    }
+}
@@ -0,0 +1,63 @@
+use std::time::{Duration, Instant};
+
+use rak_rs::{
+    connection::queue::RecvQueue,
+    protocol::{
+        frame::{Frame, FramePacket},
+        index::{DatagramSeq, OrderIndex},
+        reliability::Reliability,
+    },
+};
+
+fn ordered(sequence: u32, channel: u8, index: u32) -> FramePacket {
+    let mut frame = Frame::new(Reliability::ReliableOrd, Some(&[index as u8]));
+    frame.order_channel = Some(channel);
+    frame.order_index = Some(OrderIndex::new(index));
+
+    let mut packet = FramePacket::new();
+    packet.sequence = DatagramSeq::new(sequence);
+    packet.frames.push(frame);
+    packet
+}
+
+#[test]
+fn test_blocked_time_per_channel() {
+    let mut queue = RecvQueue::new();
+    let now = Instant::now();
+
+    queue.insert(ordered(0, 0, 0)).unwrap();
+    queue.insert(ordered(1, 3, 0)).unwrap();
+    // index 1 on channel 3 is late.
+    queue.insert(ordered(2, 3, 2)).unwrap();
+    queue.insert(ordered(3, 3, 3)).unwrap();
+    queue.poll_stalls(now);
+
+    let stats = queue.channel_stats(now + Duration::from_secs(2));
+    assert_eq!(stats.len(), 2);
+
+    let (zero, three) = (&stats[0], &stats[1]);
+    assert_eq!(zero.channel, 0);
+    assert_eq!(zero.delivered, 1);
+    assert_eq!(zero.head_gap, None);
+    assert_eq!(zero.blocked_for, Duration::ZERO);
+    assert_eq!(zero.total_blocked, Duration::ZERO);
+
+    assert_eq!(three.channel, 3);
+    assert_eq!(three.delivered, 1);
+    assert_eq!(three.buffered, 2);
+    assert_eq!(three.head_gap, Some(OrderIndex::new(1)));
+    assert_eq!(three.blocked_for, Duration::from_secs(2));
+
+    // the late message arrives after 3 seconds, unblocking the channel.
+    queue.insert(ordered(4, 3, 1)).unwrap();
+    queue.poll_stalls(now + Duration::from_secs(3));
+
+    let stats = queue.channel_stats(now + Duration::from_secs(10));
+    let three = &stats[1];
+    assert_eq!(three.delivered, 4);
+    assert_eq!(three.buffered, 0);
+    assert_eq!(three.head_gap, None);
+    assert_eq!(three.blocked_for, Duration::ZERO);
+    assert_eq!(three.total_blocked, Duration::from_secs(3));
+    assert_eq!(three.max_blocked, Duration::from_secs(3));
+}
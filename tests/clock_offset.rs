@@ -0,0 +1,165 @@
+use rak_rs::connection::controller::clock::{ClockEstimator, RakTime, CLOCK_SAMPLES};
+
+/// How far the clock of the mocked peer is ahead of ours.
+const OFFSET: RakTime = 123_456;
+
+/// Pings the mocked peer at `time`, taking `up` ms to get there and `down` ms to come back.
+fn ping(clock: &mut ClockEstimator, time: RakTime, up: RakTime, down: RakTime) -> bool {
+    clock.sample(time, time + up + OFFSET, time + up + down)
+}
+
+#[test]
+fn test_no_samples() {
+    let clock = ClockEstimator::new();
+    assert_eq!(clock.offset(), None);
+    assert_eq!(clock.peer_time_now(), None);
+}
+
+#[test]
+fn test_converges_within_asymmetry() {
+    let mut clock = ClockEstimator::new();
+    let delays = [(20, 20), (35, 15), (10, 30), (50, 10), (25, 25), (15, 35)];
+
+    for (i, (up, down)) in delays.iter().enumerate() {
+        assert!(ping(&mut clock, 1_000 * i as RakTime, *up, *down));
+    }
+
+    // every sample is off by half the difference between the way there and back.
+    let bound = delays
+        .iter()
+        .map(|(up, down)| (up - down).abs() / 2)
+        .max()
+        .unwrap();
+    let error = (clock.offset().unwrap() - OFFSET).abs();
+    assert!(error <= bound, "off by {}ms", error);
+}
+
+#[test]
+fn test_slow_samples_weigh_less() {
+    let mut clock = ClockEstimator::new();
+    for i in 0..5 {
+        ping(&mut clock, 1_000 * i, 10, 10);
+    }
+    // a pong that got stuck on the way back, the sample alone is off by 400ms.
+    ping(&mut clock, 5_000, 900, 100);
+
+    let error = (clock.offset().unwrap() - OFFSET).abs();
+    assert!(error <= 5, "off by {}ms", error);
+}
+
+#[test]
+fn test_follows_a_stepped_clock() {
+    let mut clock = ClockEstimator::new();
+    for i in 0..CLOCK_SAMPLES as RakTime {
+        ping(&mut clock, 1_000 * i, 10, 10);
+    }
+    assert_eq!(clock.offset(), Some(OFFSET));
+
+    // the peer set its clock back by a second, only the latest samples count.
+    for i in 0..CLOCK_SAMPLES as RakTime {
+        let time = 10_000 + 1_000 * i;
+        clock.sample(time, time + 10 + OFFSET - 1_000, time + 20);
+    }
+    assert_eq!(clock.offset(), Some(OFFSET - 1_000));
+}
+
+#[test]
+fn test_bogus_pongs_are_ignored() {
+    let mut clock = ClockEstimator::new();
+    // mtu probes are pongs to a ping that was never sent.
+    assert!(!clock.sample(0, 0, 1_700_000_000_000));
+    // a pong from the future.
+    assert!(!clock.sample(2_000, 2_000 + OFFSET, 1_000));
+    assert_eq!(clock.offset(), None);
+}
+
+#[cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+#[test]
+fn test_peers_on_one_clock_agree() {
+    use std::time::Duration;
+
+    use async_std::{future::timeout, task};
+    use rak_rs::connection::controller::clock::rak_time_now;
+    use rak_rs::{Client, Listener};
+
+    task::block_on(async {
+        const ADDRESS: &str = "127.0.0.1:19219";
+        let mut server = Listener::bind(ADDRESS).await.unwrap();
+        server.start().await.unwrap();
+
+        let client = Client::new(11, 1400);
+        timeout(Duration::from_secs(10), client.connect(ADDRESS))
+            .await
+            .expect("connect timed out")
+            .expect("failed to connect");
+        let conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+
+        for _ in 0..100 {
+            if client.clock_offset().is_some() && conn.clock_offset().is_some() {
+                break;
+            }
+            task::sleep(Duration::from_millis(50)).await;
+        }
+
+        // both ends read the same clock, so there is nothing but loopback delay to be off by.
+        let offset = client.clock_offset().expect("no pong reached the client");
+        assert!(offset.abs() <= 50, "client is off by {}ms", offset);
+        let offset = conn.clock_offset().expect("no pong reached the server");
+        assert!(offset.abs() <= 50, "server is off by {}ms", offset);
+
+        let drift = client.server_time_now().unwrap() - rak_time_now();
+        assert!(drift.abs() <= 50, "server time is off by {}ms", drift);
+    });
+}
+
+#[cfg(feature = "async_std")]
+#[test]
+fn test_ping_is_not_resent() {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use async_std::{future::timeout, net::UdpSocket, task};
+    use binary_util::interfaces::Reader;
+    use rak_rs::connection::queue::SendQueue;
+    use rak_rs::protocol::ack::{Ack, Ackable};
+    use rak_rs::protocol::frame::FramePacket;
+    use rak_rs::protocol::ranges::SequenceRanges;
+    use rak_rs::Reliability;
+
+    async fn recv(peer: &UdpSocket) -> FramePacket {
+        let mut buf = [0u8; 2048];
+        let len = timeout(Duration::from_secs(1), peer.recv(&mut buf))
+            .await
+            .expect("nothing was sent")
+            .unwrap();
+        FramePacket::read_from_slice(&buf[..len]).unwrap()
+    }
+
+    task::block_on(async {
+        let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let mut queue = SendQueue::new(1400, 12000, 5, socket, peer.local_addr().unwrap());
+
+        queue.ride_along_ping();
+        queue
+            .insert(&[0xfe, 1], Reliability::Reliable, true, None)
+            .await
+            .unwrap();
+        let sent = recv(&peer).await;
+        assert_eq!(sent.frames.len(), 2);
+
+        let mut ranges = SequenceRanges::new();
+        ranges.insert(sent.sequence.into());
+        let lost = queue.nack(Ack::from_ranges(&ranges, true));
+        queue.resend(lost).await;
+
+        // a resent ping would be stamped with the time of the first send.
+        let resent = recv(&peer).await;
+        assert_eq!(resent.sequence, sent.sequence);
+        assert_eq!(resent.frames.len(), 1);
+        assert_eq!(resent.frames[0].body, vec![0xfe, 1]);
+    });
+}
@@ -0,0 +1,117 @@
+#![cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use async_std::{future::timeout, net::UdpSocket, task};
+use binary_util::interfaces::Writer;
+use rak_rs::connection::event::DisconnectReason;
+use rak_rs::protocol::packet::offline::{OfflinePacket, SessionInfoRequest};
+use rak_rs::protocol::packet::online::Capabilities;
+use rak_rs::protocol::packet::RakPacket;
+use rak_rs::protocol::Magic;
+use rak_rs::{Client, Listener, RakEvent};
+
+#[test]
+fn test_closed_after_timeout() {
+    task::block_on(async {
+        let address = "127.0.0.1:19183";
+        let mut server = Listener::bind(address).await.unwrap();
+        server.start().await.unwrap();
+        let server_address: SocketAddr = address.parse().unwrap();
+
+        // a peer that opens a session and then goes silent.
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let session = RakPacket::from(OfflinePacket::SessionInfoRequest(SessionInfoRequest {
+            magic: Magic::new(),
+            address: server_address,
+            mtu_size: 1400,
+            client_id: 7,
+            capabilities: Capabilities::NONE,
+        }));
+        socket
+            .send_to(session.write_to_bytes().unwrap().as_slice(), address)
+            .await
+            .unwrap();
+        let conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let waiters: Vec<_> = (0..3)
+            .map(|_| {
+                let closed = conn.closed();
+                task::spawn(async move {
+                    let reason = closed.await;
+                    (reason, Instant::now())
+                })
+            })
+            .collect();
+
+        let mut resolved = Vec::new();
+        for waiter in waiters {
+            resolved.push(
+                timeout(Duration::from_secs(25), waiter)
+                    .await
+                    .expect("the connection never timed out"),
+            );
+        }
+
+        let first = resolved.iter().map(|(_, at)| *at).min().unwrap();
+        for (reason, at) in resolved {
+            assert_eq!(reason, DisconnectReason::TimedOut { one_way: false });
+            assert!(at - first < Duration::from_millis(100));
+        }
+        assert!(conn.is_closed().await);
+
+        // the reason is kept for anyone asking later.
+        let reason = timeout(Duration::from_millis(10), conn.closed())
+            .await
+            .expect("an already closed connection should resolve right away");
+        assert_eq!(reason, DisconnectReason::TimedOut { one_way: false });
+    });
+}
+
+#[test]
+fn test_closed_after_peer_disconnect() {
+    task::block_on(async {
+        let address = "127.0.0.1:19184";
+        let mut server = Listener::bind(address).await.unwrap();
+        server.start().await.unwrap();
+
+        let client = Client::new(11, 1400);
+        timeout(Duration::from_secs(10), client.connect(address))
+            .await
+            .expect("connect timed out")
+            .expect("failed to connect");
+        let conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let closed = conn.closed();
+        client.close().await;
+        assert_eq!(
+            timeout(Duration::from_millis(10), client.closed())
+                .await
+                .unwrap(),
+            DisconnectReason::Closed
+        );
+
+        let reason = timeout(Duration::from_secs(5), closed)
+            .await
+            .expect("the server never noticed the disconnect");
+        assert_eq!(reason, DisconnectReason::Closed);
+
+        // the event was queued before the connection resolved as closed.
+        loop {
+            let event = timeout(Duration::from_millis(10), conn.recv_event())
+                .await
+                .expect("the disconnect event should already be queued")
+                .unwrap();
+            if let RakEvent::Disconnected { reason } = event {
+                assert_eq!(reason, DisconnectReason::Closed);
+                break;
+            }
+        }
+    });
+}
@@ -0,0 +1,277 @@
+#![cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+//! Small reliable-ordered messages merged into one until the next tick, and split back by
+//! the peer.
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_std::{future::timeout, net::UdpSocket, task};
+use binary_util::interfaces::Reader;
+use rak_rs::connection::controller::coalesce::{split, Coalescer, COALESCED_ID};
+use rak_rs::connection::queue::{RecvQueue, SendQueue};
+use rak_rs::protocol::frame::FramePacket;
+use rak_rs::protocol::reliability::Reliability;
+use rak_rs::{Client, ConnOptions, Listener};
+
+fn message(i: usize) -> Vec<u8> {
+    vec![0xfe, i as u8, (i >> 8) as u8]
+}
+
+/// A queue sending to `peer`, coalescing channel 0 if the peer agreed to it.
+async fn send_queue(peer: &UdpSocket, agreed: bool) -> SendQueue {
+    let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+    let mut send_q = SendQueue::new(1400, 12000, 5, socket, peer.local_addr().unwrap());
+    send_q.set_coalesce_channels(vec![0]);
+    send_q.set_coalescing(agreed);
+    send_q
+}
+
+/// Every frame set the peer received so far.
+async fn wire(peer: &UdpSocket) -> Vec<FramePacket> {
+    let mut buf = [0u8; 2048];
+    let mut datagrams = Vec::new();
+    while let Ok(Ok(len)) = timeout(Duration::from_millis(50), peer.recv(&mut buf)).await {
+        if let 0x80..=0x8f = buf[0] {
+            datagrams.push(FramePacket::read_from_slice(&buf[..len]).unwrap());
+        }
+    }
+    datagrams
+}
+
+fn deliver(datagrams: Vec<FramePacket>) -> Vec<Vec<u8>> {
+    let mut recv_q = RecvQueue::new();
+    recv_q.set_coalescing(true);
+    for datagram in datagrams {
+        recv_q.insert(datagram).unwrap();
+    }
+    recv_q.flush()
+}
+
+#[test]
+fn test_bundle_round_trip() {
+    let mut coalescer = Coalescer::new(vec![3]);
+    assert!(!coalescer.coalesces(3));
+    coalescer.set_enabled(true);
+    assert!(coalescer.coalesces(3) && !coalescer.coalesces(0));
+
+    for i in 0..4 {
+        assert_eq!(coalescer.push(3, &message(i), 64), Ok(None));
+    }
+    let bundle = coalescer.take(3).unwrap();
+    assert_eq!(bundle[0], COALESCED_ID);
+    assert_eq!(
+        split(&bundle).unwrap(),
+        (0..4).map(message).collect::<Vec<_>>()
+    );
+
+    // a full bundle is handed back, the message starts the next one.
+    let big = vec![0xfe; 40];
+    assert_eq!(coalescer.push(3, &big, 64), Ok(None));
+    let full = coalescer.push(3, &big, 64).unwrap().unwrap();
+    assert_eq!(full, big, "a bundle of one goes out as the message itself");
+    assert_eq!(coalescer.pending(), 1);
+    assert_eq!(coalescer.push(3, &[0xfe; 62], 64), Err(&[0xfe; 62][..]));
+
+    // one that looks like a bundle stays wrapped.
+    let lookalike = vec![COALESCED_ID, 0, 0];
+    coalescer.take_all();
+    coalescer.push(3, &lookalike, 64).unwrap();
+    assert_eq!(split(&coalescer.take(3).unwrap()).unwrap(), vec![lookalike]);
+    assert_eq!(split(&[COALESCED_ID, 0, 9, 1]), None);
+    assert_eq!(split(&[0xfe, 1]), None);
+}
+
+#[test]
+fn test_one_frame_for_a_tick_of_messages() {
+    task::block_on(async {
+        let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut send_q = send_queue(&peer, true).await;
+
+        for i in 0..100 {
+            send_q
+                .insert(&message(i), Reliability::ReliableOrd, false, Some(0))
+                .await
+                .unwrap();
+        }
+        assert_eq!(send_q.pending(), 1);
+        send_q.update().await;
+
+        let datagrams = wire(&peer).await;
+        assert_eq!(datagrams.len(), 1);
+        assert_eq!(datagrams[0].frames.len(), 1);
+        assert_eq!(
+            deliver(datagrams),
+            (0..100).map(message).collect::<Vec<_>>()
+        );
+    });
+}
+
+#[test]
+fn test_order_kept_around_other_sends() {
+    task::block_on(async {
+        let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut send_q = send_queue(&peer, true).await;
+        let lookalike = vec![COALESCED_ID, 1, 2, 3];
+        let big = vec![0xfe; 5000];
+
+        send_q
+            .insert(&message(0), Reliability::ReliableOrd, false, Some(0))
+            .await
+            .unwrap();
+        send_q
+            .insert(&message(1), Reliability::ReliableOrd, false, Some(0))
+            .await
+            .unwrap();
+        // sent right away, after what was coalesced before it.
+        send_q
+            .insert(&message(2), Reliability::ReliableOrd, true, Some(0))
+            .await
+            .unwrap();
+        send_q
+            .insert(&lookalike, Reliability::ReliableOrd, false, Some(0))
+            .await
+            .unwrap();
+        send_q
+            .insert(&big, Reliability::ReliableOrd, false, Some(0))
+            .await
+            .unwrap();
+        send_q
+            .insert(&lookalike, Reliability::Reliable, false, None)
+            .await
+            .unwrap();
+        send_q.update().await;
+
+        let delivered = deliver(wire(&peer).await);
+        assert_eq!(delivered.len(), 6);
+        // the unordered one is not held back by the ordered ones.
+        assert_eq!(delivered[0], lookalike);
+        assert_eq!(
+            delivered[1..],
+            [message(0), message(1), message(2), lookalike, big]
+        );
+    });
+}
+
+#[test]
+fn test_nothing_coalesced_without_agreement() {
+    task::block_on(async {
+        let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut send_q = send_queue(&peer, false).await;
+
+        for i in 0..10 {
+            send_q
+                .insert(&message(i), Reliability::ReliableOrd, false, Some(0))
+                .await
+                .unwrap();
+        }
+        send_q.update().await;
+
+        let frames = wire(&peer)
+            .await
+            .into_iter()
+            .map(|datagram| datagram.frames.len())
+            .sum::<usize>();
+        assert_eq!(frames, 10);
+    });
+}
+
+#[test]
+fn test_coalesced_both_ways() {
+    task::block_on(async {
+        const ADDRESS: &str = "127.0.0.1:19236";
+        let options = ConnOptions {
+            coalesce_channels: vec![0],
+            ..Default::default()
+        };
+        let mut server = Listener::bind_with_options(ADDRESS, options.clone())
+            .await
+            .unwrap();
+        server.start().await.unwrap();
+
+        let client = Client::new(11, 1400).with_options(options);
+        timeout(Duration::from_secs(10), client.connect(ADDRESS))
+            .await
+            .expect("connect timed out")
+            .expect("failed to connect");
+        let mut conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let mut lookalike = vec![COALESCED_ID];
+        lookalike.extend_from_slice(&[0, 1, 0xfe]);
+        for i in 0..100 {
+            client.send_ord(&message(i), 0).await.unwrap();
+        }
+        client.send_ord(&lookalike, 0).await.unwrap();
+        for i in 0..100 {
+            let received = timeout(Duration::from_secs(5), conn.recv())
+                .await
+                .expect("the server got stuck")
+                .unwrap();
+            assert_eq!(received, message(i));
+        }
+        assert_eq!(conn.recv().await.unwrap(), lookalike);
+
+        // the server only coalesces once the handshake is complete.
+        timeout(Duration::from_secs(5), async {
+            while conn.send(&message(0), false).await.is_ok() {
+                if client.recv().await.unwrap() == message(0) {
+                    break;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        for i in 0..100 {
+            conn.send(&message(i), false).await.unwrap();
+        }
+        for i in 0..100 {
+            let received = timeout(Duration::from_secs(5), client.recv())
+                .await
+                .expect("the client got stuck")
+                .unwrap();
+            assert_eq!(received, message(i));
+        }
+    });
+}
+
+#[test]
+fn test_plain_client_is_not_coalesced_to() {
+    task::block_on(async {
+        const ADDRESS: &str = "127.0.0.1:19237";
+        let mut server = Listener::bind_with_options(
+            ADDRESS,
+            ConnOptions {
+                coalesce_channels: vec![0],
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        server.start().await.unwrap();
+
+        let client = Client::new(11, 1400);
+        timeout(Duration::from_secs(10), client.connect(ADDRESS))
+            .await
+            .expect("connect timed out")
+            .expect("failed to connect");
+        let conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let lookalike = vec![COALESCED_ID, 0, 1, 0xfe];
+        conn.send(&lookalike, false).await.unwrap();
+        for i in 0..20 {
+            conn.send(&message(i), false).await.unwrap();
+        }
+        assert_eq!(client.recv().await.unwrap(), lookalike);
+        for i in 0..20 {
+            let received = timeout(Duration::from_secs(5), client.recv())
+                .await
+                .expect("the client got stuck")
+                .unwrap();
+            assert_eq!(received, message(i));
+        }
+    });
+}
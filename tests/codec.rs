@@ -0,0 +1,135 @@
+#![cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+use std::io::{Error, ErrorKind};
+use std::time::Duration;
+
+use async_std::{future::timeout, task};
+use rak_rs::connection::codec::{Decoder, Encoder, LengthDelimited};
+use rak_rs::{Client, Listener};
+
+#[derive(Debug, Clone, PartialEq)]
+struct Move {
+    player: String,
+    x: i32,
+    y: i32,
+}
+
+/// A message protocol an application would put over its connections.
+struct MoveCodec(LengthDelimited);
+
+impl Encoder<&Move> for MoveCodec {
+    type Error = Error;
+
+    fn encode(&mut self, item: &Move, dst: &mut Vec<u8>) -> Result<(), Error> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&item.x.to_be_bytes());
+        body.extend_from_slice(&item.y.to_be_bytes());
+        body.extend_from_slice(item.player.as_bytes());
+        self.0.encode(body, dst)
+    }
+}
+
+impl Decoder for MoveCodec {
+    type Item = Move;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut Vec<u8>) -> Result<Option<Move>, Error> {
+        let body = match self.0.decode(src)? {
+            Some(body) if body.len() >= 8 => body,
+            Some(_) => return Err(Error::new(ErrorKind::InvalidData, "move too short")),
+            None => return Ok(None),
+        };
+        Ok(Some(Move {
+            x: i32::from_be_bytes(body[0..4].try_into().unwrap()),
+            y: i32::from_be_bytes(body[4..8].try_into().unwrap()),
+            player: String::from_utf8(body[8..].to_vec())
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e))?,
+        }))
+    }
+}
+
+fn moves() -> Vec<Move> {
+    (0..5)
+        .map(|i| Move {
+            player: format!("player {}", i),
+            x: i * 10,
+            y: -i,
+        })
+        .collect()
+}
+
+#[test]
+fn test_typed_round_trip() {
+    task::block_on(async {
+        let mut server = Listener::bind("127.0.0.1:19201").await.unwrap();
+        server.start().await.unwrap();
+
+        let client = Client::new(11, 1400);
+        timeout(Duration::from_secs(10), client.connect("127.0.0.1:19201"))
+            .await
+            .expect("connect timed out")
+            .expect("failed to connect");
+        let conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+        let mut framed = conn.framed(MoveCodec(LengthDelimited::new()));
+        let mut codec = MoveCodec(LengthDelimited::new());
+
+        let sent = moves();
+        let (first, rest) = sent.split_at(1);
+        // every payload starts with the id of the adapter.
+        let mut payload = vec![0xfe];
+        codec.encode(&first[0], &mut payload).unwrap();
+        client.send_ord(&payload, 0).await.unwrap();
+
+        // a length claiming more than the payload holds, a move with invalid utf-8 and a
+        // payload with another id.
+        client.send_ord(&[0xfe, 0, 0, 0, 9, 1, 2], 0).await.unwrap();
+        client
+            .send_ord(&[0xfe, 0, 0, 0, 9, 0, 0, 0, 0, 0, 0, 0, 0, 0xff], 0)
+            .await
+            .unwrap();
+        client.send_ord(&[0x86, 0, 0, 0, 0], 0).await.unwrap();
+
+        // two moves in a single payload are both decoded.
+        let mut payload = vec![0xfe];
+        for m in rest {
+            codec.encode(m, &mut payload).unwrap();
+        }
+        client.send_ord(&payload, 0).await.unwrap();
+
+        let mut received = Vec::new();
+        let mut errors = 0;
+        while received.len() < moves().len() {
+            match timeout(Duration::from_secs(5), framed.recv())
+                .await
+                .expect("the adapter stalled")
+                .unwrap()
+            {
+                Ok(m) => received.push(m),
+                Err(e) => {
+                    assert_eq!(e.kind(), ErrorKind::InvalidData);
+                    // the malformed payloads arrive between the first move and the rest.
+                    assert_eq!(received.len(), 1);
+                    errors += 1;
+                }
+            }
+        }
+        assert_eq!(received, moves());
+        assert_eq!(errors, 3);
+
+        // and back, on the channel the adapter was configured with.
+        framed.channel = 3;
+        for m in &moves() {
+            framed.send(m).await.unwrap();
+        }
+        for m in moves() {
+            let mut payload = timeout(Duration::from_secs(5), client.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(payload.remove(0), 0xfe);
+            assert_eq!(codec.decode_eof(&mut payload).unwrap(), Some(m));
+        }
+    });
+}
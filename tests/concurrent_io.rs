@@ -0,0 +1,119 @@
+#![cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+//! Sends, receives, statistics and teardown of a connection all at once, none of them may
+//! end up waiting on another for good.
+use std::time::Duration;
+
+use async_std::{future::timeout, task};
+use futures::join;
+use rak_rs::{Client, Listener};
+
+/// A message large enough to be split every few, so the receiving side has reassembling to do.
+fn message(i: usize) -> Vec<u8> {
+    let len = if i % 4 == 0 { 6_000 } else { 200 };
+    let mut body = vec![0xfe];
+    body.extend_from_slice(&(i as u32).to_be_bytes());
+    body.resize(len, i as u8);
+    body
+}
+
+#[test]
+fn test_send_recv_and_teardown_concurrently() {
+    task::block_on(async {
+        const ADDRESS: &str = "127.0.0.1:19230";
+        // the connection only buffers a hundred messages until they are read.
+        const MESSAGES: usize = 80;
+        let mut server = Listener::bind(ADDRESS).await.unwrap();
+        server.start().await.unwrap();
+
+        let client = Client::new(11, 1400);
+        timeout(Duration::from_secs(10), client.connect(ADDRESS))
+            .await
+            .expect("connect timed out")
+            .expect("failed to connect");
+        let mut conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let exchange = async {
+            join!(
+                // the server reassembles what the client sends while it sends itself.
+                async {
+                    for i in 0..MESSAGES {
+                        client.send_ord(&message(i), 0).await.unwrap();
+                    }
+                },
+                async {
+                    for i in 0..MESSAGES {
+                        conn.send(&message(i), i % 2 == 0).await.unwrap();
+                    }
+                },
+                async {
+                    for i in 0..MESSAGES {
+                        assert_eq!(client.recv().await.unwrap(), message(i));
+                    }
+                },
+                async {
+                    for _ in 0..MESSAGES {
+                        let stats = conn.stats().await;
+                        assert_eq!(stats.corrupt_datagrams, 0);
+                        assert_eq!(conn.meta().await.mtu_size, 1400);
+                        client.stats().await;
+                        task::yield_now().await;
+                    }
+                },
+            )
+        };
+        timeout(Duration::from_secs(20), exchange)
+            .await
+            .expect("the exchange got stuck");
+
+        for i in 0..MESSAGES {
+            let packet = timeout(Duration::from_secs(5), conn.recv())
+                .await
+                .expect("the server got stuck")
+                .unwrap();
+            assert_eq!(packet, message(i));
+        }
+
+        // torn down while the client keeps sending, and the stats keep being read.
+        let teardown = async {
+            join!(
+                async {
+                    for i in 0..MESSAGES {
+                        if client.send_ord(&message(i), 0).await.is_err() {
+                            break;
+                        }
+                    }
+                },
+                async {
+                    client.stats().await;
+                    conn.close().await;
+                    conn.stats().await;
+                },
+                async {
+                    conn_closed(&client).await;
+                },
+            )
+        };
+        timeout(Duration::from_secs(20), teardown)
+            .await
+            .expect("the teardown got stuck");
+    });
+}
+
+/// Reads the statistics of the client until the server closed the connection.
+async fn conn_closed(client: &Client) {
+    let closed = client.closed();
+    futures::pin_mut!(closed);
+    loop {
+        client.stats().await;
+        client.meta().await;
+        if timeout(Duration::from_millis(10), &mut closed)
+            .await
+            .is_ok()
+        {
+            break;
+        }
+    }
+}
@@ -0,0 +1,181 @@
+#![cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_std::{future::timeout, net::UdpSocket, task};
+use binary_util::interfaces::Reader;
+use rak_rs::client::pool::{connect_pool, PoolOptions};
+use rak_rs::protocol::frame::FramePacket;
+use rak_rs::protocol::packet::online::OnlinePacket;
+use rak_rs::{ClientError, Listener};
+
+const SERVER: &str = "127.0.0.1:19208";
+const CONCURRENCY: usize = 3;
+const DEADLINE: Duration = Duration::from_millis(2000);
+
+/// When the mocks saw a handshake start, and when they saw it end.
+type Handshakes = Arc<Mutex<Vec<(Instant, Option<Instant>)>>>;
+
+fn is_accept(datagram: &[u8]) -> bool {
+    match FramePacket::read_from_slice(datagram) {
+        Ok(datagram) => datagram.frames.iter().any(|frame| {
+            matches!(
+                OnlinePacket::read_from_slice(&frame.body),
+                Ok(OnlinePacket::ConnectionAccept(_))
+            )
+        }),
+        Err(_) => false,
+    }
+}
+
+/// Forwards a single client to the server, the handshake ends once the server accepted it.
+async fn relay(handshakes: Handshakes) -> SocketAddr {
+    let front = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+    let back = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+    back.connect(SERVER).await.unwrap();
+    let address = front.local_addr().unwrap();
+    let (client_send, client_recv) = async_std::channel::bounded::<(SocketAddr, usize)>(1);
+
+    {
+        let (front, back, handshakes) = (front.clone(), back.clone(), handshakes.clone());
+        task::spawn(async move {
+            let mut buf = [0u8; 2048];
+            let mut handshake = None;
+            loop {
+                let (len, origin) = front.recv_from(&mut buf).await.unwrap();
+                if handshake.is_none() {
+                    let mut handshakes = handshakes.lock().unwrap();
+                    handshakes.push((Instant::now(), None));
+                    handshake = Some(handshakes.len() - 1);
+                    let _ = client_send.try_send((origin, handshakes.len() - 1));
+                }
+                back.send(&buf[..len]).await.unwrap();
+            }
+        });
+    }
+
+    task::spawn(async move {
+        let (client, handshake) = client_recv.recv().await.unwrap();
+        let mut buf = [0u8; 2048];
+        loop {
+            let len = back.recv(&mut buf).await.unwrap();
+            if is_accept(&buf[..len]) {
+                let mut handshakes = handshakes.lock().unwrap();
+                handshakes[handshake].1.get_or_insert_with(Instant::now);
+            }
+            front.send_to(&buf[..len], client).await.unwrap();
+        }
+    });
+
+    address
+}
+
+/// Never answers, the handshake lasts until the pool gives up on it.
+async fn black_hole(handshakes: Handshakes) -> SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let address = socket.local_addr().unwrap();
+    task::spawn(async move {
+        let mut buf = [0u8; 2048];
+        socket.recv_from(&mut buf).await.unwrap();
+        // the pool started the handshake before this saw it, and gives up on it a deadline
+        // after that, keep clear of the end so this never counts it longer than the pool did.
+        let now = Instant::now();
+        handshakes
+            .lock()
+            .unwrap()
+            .push((now, Some(now + DEADLINE - Duration::from_millis(200))));
+        loop {
+            socket.recv_from(&mut buf).await.unwrap();
+        }
+    });
+    address
+}
+
+/// The most handshakes that overlapped.
+fn peak(handshakes: &[(Instant, Option<Instant>)]) -> usize {
+    handshakes
+        .iter()
+        .map(|(start, _)| {
+            handshakes
+                .iter()
+                .filter(|(other, end)| other <= start && end.map_or(true, |end| end > *start))
+                .count()
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+#[test]
+fn test_pool_bounds_concurrent_handshakes() {
+    task::block_on(async {
+        let mut server = Listener::bind(SERVER).await.unwrap();
+        server.start().await.unwrap();
+        task::spawn(async move {
+            let mut conns = Vec::new();
+            while let Ok(conn) = server.accept().await {
+                conns.push(conn);
+            }
+        });
+
+        let handshakes: Handshakes = Arc::new(Mutex::new(Vec::new()));
+        let mut targets = Vec::new();
+        let mut holes = Vec::new();
+        for i in 0..10 {
+            // the black holes are taken early, so they hold their slots while the rest connects.
+            if i == 1 || i == 2 {
+                let hole = black_hole(handshakes.clone()).await;
+                holes.push(hole);
+                targets.push(hole);
+            } else {
+                targets.push(relay(handshakes.clone()).await);
+            }
+        }
+
+        let mut options = PoolOptions::new(11, 1400);
+        options.concurrency = CONCURRENCY;
+        options.stagger = Duration::from_millis(20);
+        options.deadline = DEADLINE;
+
+        let started = Instant::now();
+        let mut pool = connect_pool(targets, options);
+        let mut clients = Vec::new();
+
+        for _ in 0..8 {
+            let outcome = timeout(Duration::from_secs(5), pool.next())
+                .await
+                .expect("a hung target held up the others")
+                .unwrap();
+            assert!(!holes.contains(&outcome.target));
+            clients.push(outcome.result.expect("failed to connect"));
+        }
+        // the holes hold two of the slots the whole time, the rest goes through one.
+        assert!(
+            started.elapsed() < DEADLINE,
+            "the connections should not wait on the black holes"
+        );
+
+        for _ in 0..2 {
+            let outcome = pool.next().await.unwrap();
+            assert!(holes.contains(&outcome.target));
+            assert!(matches!(outcome.result, Err(ClientError::TimedOut)));
+            assert!(outcome.elapsed >= DEADLINE);
+            assert!(outcome.elapsed < DEADLINE + Duration::from_millis(500));
+        }
+        assert!(pool.next().await.is_none());
+
+        let summary = pool.summary();
+        assert_eq!(summary.connected, 8);
+        assert_eq!(summary.failed, 2);
+        assert!(summary.peak_handshakes <= CONCURRENCY);
+
+        let handshakes = handshakes.lock().unwrap();
+        assert_eq!(handshakes.len(), 10);
+        let peak = peak(&handshakes);
+        assert!(
+            peak <= CONCURRENCY,
+            "{} handshakes were in progress at once",
+            peak
+        );
+    });
+}
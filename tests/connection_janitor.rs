@@ -0,0 +1,146 @@
+#![cfg(all(feature = "async_std", feature = "server"))]
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use async_std::{future::timeout, net::UdpSocket, task};
+use binary_util::interfaces::Writer;
+use rak_rs::connection::controller::liveness::{Janitor, Liveness};
+use rak_rs::connection::event::{DisconnectReason, RakEvent};
+use rak_rs::protocol::packet::offline::{OfflinePacket, SessionInfoRequest};
+use rak_rs::protocol::packet::online::Capabilities;
+use rak_rs::protocol::packet::RakPacket;
+use rak_rs::protocol::Magic;
+use rak_rs::Listener;
+
+const ADDRESS: &str = "127.0.0.1:19209";
+const INTERVAL: Duration = Duration::from_millis(100);
+
+fn address(port: u16) -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], port))
+}
+
+#[test]
+fn test_janitor_reclaims_only_dead_connections() {
+    let mut janitor = Janitor::new();
+    let healthy = Liveness::new();
+    let _tick = healthy.driver();
+    let _net = healthy.driver();
+    let crashed = Liveness::new();
+    let _tick_only = crashed.driver();
+
+    // found dead once, and reclaimed once it is still dead on the next pass.
+    healthy.tick();
+    crashed.tick();
+    assert!(janitor
+        .pass([(address(1), &healthy), (address(2), &crashed)])
+        .is_empty());
+    healthy.tick();
+    crashed.tick();
+    assert_eq!(
+        janitor.pass([(address(1), &healthy), (address(2), &crashed)]),
+        vec![address(2)]
+    );
+
+    // every driver runs, but the tick is stuck.
+    let stuck = Liveness::new();
+    let _drivers = (stuck.driver(), stuck.driver());
+    healthy.tick();
+    assert!(janitor
+        .pass([(address(1), &healthy), (address(3), &stuck)])
+        .is_empty());
+    healthy.tick();
+    assert!(janitor
+        .pass([(address(1), &healthy), (address(3), &stuck)])
+        .is_empty());
+    healthy.tick();
+    assert_eq!(
+        janitor.pass([(address(1), &healthy), (address(3), &stuck)]),
+        vec![address(3)]
+    );
+
+    // an idle connection whose tick is not due yet is left alone.
+    let idle = Liveness::new();
+    let _idle_drivers = (idle.driver(), idle.driver());
+    idle.tick_due(Instant::now() + Duration::from_secs(60));
+    for _ in 0..3 {
+        assert!(janitor
+            .pass([(address(1), &healthy), (address(5), &idle)])
+            .is_empty());
+        healthy.tick();
+    }
+
+    // a connection that replaced a dead one on the same address starts over.
+    let dead = Liveness::new();
+    assert!(janitor.pass([(address(4), &dead)]).is_empty());
+    let replaced = Liveness::new();
+    assert!(janitor.pass([(address(4), &replaced)]).is_empty());
+}
+
+async fn open_session(socket: &UdpSocket) {
+    let session = RakPacket::from(OfflinePacket::SessionInfoRequest(SessionInfoRequest {
+        magic: Magic::new(),
+        address: ADDRESS.parse().unwrap(),
+        mtu_size: 1400,
+        client_id: 7,
+        capabilities: Capabilities::NONE,
+    }));
+    socket
+        .send(session.write_to_bytes().unwrap().as_slice())
+        .await
+        .unwrap();
+}
+
+#[test]
+fn test_listener_reclaims_connection_whose_tasks_died() {
+    task::block_on(async {
+        let mut server = Listener::bind(ADDRESS).await.unwrap();
+        server.janitor_interval = INTERVAL;
+        server.start().await.unwrap();
+
+        let crashing = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        crashing.connect(ADDRESS).await.unwrap();
+        open_session(&crashing).await;
+        let conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let idle = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        idle.connect(ADDRESS).await.unwrap();
+        open_session(&idle).await;
+        let _idle_conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(server.connections().await.len(), 2);
+
+        conn.abort_tasks().await;
+
+        let event = timeout(Duration::from_secs(5), conn.recv_event())
+            .await
+            .expect("the connection was never reclaimed")
+            .unwrap();
+        assert_eq!(
+            event,
+            RakEvent::Disconnected {
+                reason: DisconnectReason::Internal
+            }
+        );
+        assert_eq!(conn.closed().await, DisconnectReason::Internal);
+        let left = server.connections().await;
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].address, idle.local_addr().unwrap());
+
+        // the address is free again.
+        open_session(&crashing).await;
+        let conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .expect("the address could not reconnect")
+            .unwrap();
+        assert_eq!(conn.address, crashing.local_addr().unwrap());
+
+        // the idle connection sends nothing at all, its tasks still run.
+        task::sleep(INTERVAL * 5).await;
+        assert_eq!(server.connections().await.len(), 2);
+    });
+}
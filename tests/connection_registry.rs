@@ -0,0 +1,192 @@
+#![cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_std::channel::{unbounded, Receiver, Sender};
+use async_std::{future::timeout, task};
+use rak_rs::server::registry::{ConnInfo, Registry};
+use rak_rs::{Client, Listener};
+
+const CONNECTIONS: u32 = 5000;
+
+fn address(i: u32) -> SocketAddr {
+    SocketAddr::from(([10, 0, (i >> 8) as u8, i as u8], 19132 + (i % 7) as u16))
+}
+
+fn info(address: &SocketAddr, _: &Sender<Vec<u8>>) -> ConnInfo {
+    ConnInfo {
+        address: *address,
+        mtu_size: 1400,
+        recv_time: 0,
+    }
+}
+
+async fn mock_registry() -> (Arc<Registry<Sender<Vec<u8>>>>, Receiver<Vec<u8>>) {
+    let registry = Arc::new(Registry::default());
+    let (send, recv) = unbounded();
+    for i in 0..CONNECTIONS {
+        registry.insert(address(i), send.clone()).await;
+    }
+    (registry, recv)
+}
+
+/// Sends to the mock connections like the listener would, until stopped.
+fn send_to_all(
+    registry: Arc<Registry<Sender<Vec<u8>>>>,
+    stop: Arc<AtomicBool>,
+) -> task::JoinHandle<usize> {
+    task::spawn(async move {
+        let mut sent = 0;
+        let mut i = 0;
+        while !stop.load(Ordering::Relaxed) {
+            let net = registry
+                .with(&address(i % CONNECTIONS), |net| net.clone())
+                .await;
+            if let Some(net) = net {
+                net.send(vec![0xfe]).await.unwrap();
+                sent += 1;
+            }
+            i += 1;
+            if i % 64 == 0 {
+                task::yield_now().await;
+            }
+        }
+        sent
+    })
+}
+
+#[test]
+fn test_for_each_never_deadlocks() {
+    task::block_on(async {
+        let (registry, _recv) = mock_registry().await;
+        let stop = Arc::new(AtomicBool::new(false));
+        let sender = send_to_all(registry.clone(), stop.clone());
+
+        let visited = AtomicUsize::new(0);
+        let panicked = timeout(
+            Duration::from_secs(20),
+            registry.for_each(info, |conn| {
+                visited.fetch_add(1, Ordering::Relaxed);
+                // the shard is not locked while the callback runs, so it can use the registry.
+                let found = task::block_on(registry.contains(&conn.address));
+                assert!(found);
+                if conn.address == address(42) {
+                    panic!("a broken callback");
+                }
+            }),
+        )
+        .await
+        .expect("iterating deadlocked");
+
+        stop.store(true, Ordering::Relaxed);
+        let sent = timeout(Duration::from_secs(5), sender)
+            .await
+            .expect("sending deadlocked");
+        assert!(sent > 0);
+
+        assert_eq!(panicked, 1);
+        assert_eq!(visited.load(Ordering::Relaxed), CONNECTIONS as usize);
+        // the panic didn't leave its shard locked.
+        assert!(registry.with(&address(42), |_| ()).await.is_some());
+    });
+}
+
+#[test]
+fn test_pages_visit_every_connection_once() {
+    task::block_on(async {
+        let (registry, _recv) = mock_registry().await;
+        let stop = Arc::new(AtomicBool::new(false));
+        let sender = send_to_all(registry.clone(), stop.clone());
+
+        let mut seen = HashSet::new();
+        let mut cursor = None;
+        let mut pages = 0;
+        loop {
+            let (page, next) = timeout(Duration::from_secs(5), registry.page(cursor, 97, info))
+                .await
+                .expect("paging deadlocked");
+            assert!(page.len() <= 97);
+            for conn in page {
+                assert!(
+                    seen.insert(conn.address),
+                    "{} was listed twice",
+                    conn.address
+                );
+            }
+            pages += 1;
+            match next {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+
+        stop.store(true, Ordering::Relaxed);
+        timeout(Duration::from_secs(5), sender).await.unwrap();
+
+        assert_eq!(seen.len(), CONNECTIONS as usize);
+        assert_eq!(pages, (CONNECTIONS as usize).div_ceil(97));
+        assert_eq!(registry.len().await, CONNECTIONS as usize);
+
+        // an empty page doesn't lose the cursor.
+        let (page, next) = registry.page(cursor, 0, info).await;
+        assert!(page.is_empty());
+        assert_eq!(next, cursor);
+    });
+}
+
+#[test]
+fn test_on_host_finds_every_port() {
+    task::block_on(async {
+        let (registry, _recv) = mock_registry().await;
+        let host = address(42);
+        let (send, _) = unbounded();
+        for port in [1, 19131, 65535] {
+            registry
+                .insert(SocketAddr::new(host.ip(), port), send.clone())
+                .await;
+        }
+
+        // the neighbouring hosts are left out, whatever shard they are in.
+        let mut found = registry
+            .on_host(&SocketAddr::new(host.ip(), 7), |address, _| *address)
+            .await;
+        found.sort();
+        assert_eq!(
+            found,
+            [1, 19131, host.port(), 65535].map(|port| SocketAddr::new(host.ip(), port))
+        );
+    });
+}
+
+#[test]
+fn test_listener_lists_connections() {
+    task::block_on(async {
+        let mut server = Listener::bind("127.0.0.1:19189").await.unwrap();
+        server.start().await.unwrap();
+
+        let client = Client::new(11, 1400);
+        timeout(Duration::from_secs(10), client.connect("127.0.0.1:19189"))
+            .await
+            .expect("connect timed out")
+            .expect("failed to connect");
+        let _conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let connections = server.connections().await;
+        assert_eq!(connections.len(), 1);
+        assert!(connections[0].mtu_size > 0);
+
+        let mut visited = Vec::new();
+        server.for_each_connection(|conn| visited.push(*conn)).await;
+        assert_eq!(visited, connections);
+
+        let (page, next) = server.connections_page(None, 10).await;
+        assert_eq!(page, connections);
+        assert_eq!(next, None);
+    });
+}
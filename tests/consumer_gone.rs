@@ -0,0 +1,122 @@
+#![cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+//! A connection whose application stopped receiving drops the messages of the peer, rather
+//! than holding up everything the peer sends, and delivers again once it is back.
+use std::time::{Duration, Instant};
+
+use async_std::{future::timeout, task};
+use rak_rs::connection::controller::consumer::ConsumerGuard;
+use rak_rs::{Client, Listener};
+
+#[test]
+fn test_gone_consumer_is_reported_once() {
+    let mut guard = ConsumerGuard::new(Some(Duration::from_secs(1)));
+    assert_eq!(guard.wait(), Some(Duration::from_secs(1)));
+
+    assert!(guard.dropped());
+    for _ in 0..100 {
+        assert!(!guard.dropped(), "only the first drop is reported");
+    }
+    assert!(guard.is_gone());
+    assert_eq!(guard.wait(), Some(Duration::ZERO));
+    assert_eq!(guard.dropped_count(), 101);
+
+    assert!(guard.delivered());
+    assert!(!guard.delivered());
+    assert_eq!(guard.wait(), Some(Duration::from_secs(1)));
+
+    assert_eq!(ConsumerGuard::new(None).wait(), None);
+}
+
+#[test]
+fn test_consumer_gone_and_back() {
+    task::block_on(async {
+        let address = "127.0.0.1:19240";
+        let mut server = Listener::bind(address).await.unwrap();
+        server.conn_options.consumer_timeout = Some(Duration::from_millis(200));
+        server.start().await.unwrap();
+
+        let client = Client::new(11, 1400);
+        timeout(Duration::from_secs(10), client.connect(address))
+            .await
+            .expect("connect timed out")
+            .expect("failed to connect");
+        let mut conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+
+        // the application doesn't receive, the first 100 fit in the channel.
+        for i in 0..150u8 {
+            client.send_ord(&[0xfe, i], 0).await.unwrap();
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while conn.stats().await.events_dropped_no_consumer < 50 {
+            assert!(Instant::now() < deadline, "the messages were never dropped");
+            task::sleep(Duration::from_millis(20)).await;
+        }
+
+        // everything was acknowledged, the peer has nothing to resend.
+        task::sleep(Duration::from_millis(500)).await;
+        assert_eq!(conn.stats().await.events_dropped_no_consumer, 50);
+        assert_eq!(client.stats().await.rtt.consecutive_timeouts, 0);
+        assert!(!conn.is_closed().await);
+
+        // the application is back, it gets what fit and then anything new.
+        for i in 0..100u8 {
+            let message = timeout(Duration::from_secs(1), conn.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(message, [0xfe, i]);
+        }
+        client.send_ord(&[0xfe, 200], 0).await.unwrap();
+        assert_eq!(
+            timeout(Duration::from_secs(5), conn.recv())
+                .await
+                .unwrap()
+                .unwrap(),
+            [0xfe, 200]
+        );
+        assert_eq!(conn.stats().await.events_dropped_no_consumer, 50);
+    });
+}
+
+#[test]
+fn test_client_keeps_reading_while_the_application_is_busy() {
+    task::block_on(async {
+        let address = "127.0.0.1:19291";
+        let mut server = Listener::bind(address).await.unwrap();
+        server.start().await.unwrap();
+
+        let client = Client::new(11, 1400);
+        timeout(Duration::from_secs(10), client.connect(address))
+            .await
+            .expect("connect timed out")
+            .expect("failed to connect");
+        let conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+
+        // far more than the channel of the client holds, which nothing receives from yet.
+        for i in 0..200u8 {
+            conn.send(&[0xfe, i], false).await.unwrap();
+        }
+
+        // the client still acknowledged everything, the server has nothing to resend.
+        task::sleep(Duration::from_millis(500)).await;
+        let stats = conn.stats().await;
+        assert_eq!(stats.rtt.consecutive_timeouts, 0);
+        assert_eq!(stats.timeout_retransmits, 0);
+
+        for i in 0..200u8 {
+            let message = timeout(Duration::from_secs(1), client.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(message, [0xfe, i]);
+        }
+        assert_eq!(client.stats().await.events_dropped_no_consumer, 0);
+    });
+}
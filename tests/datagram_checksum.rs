@@ -0,0 +1,342 @@
+#![cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_std::{future::timeout, net::UdpSocket, task};
+use binary_util::interfaces::{Reader, Writer};
+use rak_rs::connection::controller::checksum::{
+    crc32, seal, ChecksumGuard, ChecksumVerdict, CHECKSUM_FLAG, CHECKSUM_LEN,
+    CORRUPT_DATAGRAM_THRESHOLD,
+};
+use rak_rs::connection::event::ViolationKind;
+use rak_rs::connection::queue::RecvQueue;
+use rak_rs::protocol::frame::{Frame, FramePacket};
+use rak_rs::protocol::index::DatagramSeq;
+use rak_rs::protocol::packet::online::{Capabilities, ConnectionAccept, ConnectionRequest};
+use rak_rs::{Client, ConnOptions, Listener, RakEvent, Reliability};
+
+fn datagram(sequence: u32) -> Vec<u8> {
+    let mut pk = FramePacket::new();
+    pk.sequence = DatagramSeq::new(sequence);
+    pk.frames.push(Frame::new(
+        Reliability::Unreliable,
+        Some(&[0xfe, 0x01, 0x02]),
+    ));
+    pk.write_to_bytes().unwrap().as_slice().to_vec()
+}
+
+#[test]
+fn test_crc32() {
+    assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    assert_eq!(crc32(&[]), 0);
+}
+
+#[test]
+fn test_guard_verifies_trailers() {
+    let mut guard = ChecksumGuard::new(true);
+
+    // the peer hasn't started checksumming yet.
+    let plain = datagram(0);
+    assert_eq!(
+        guard.verify(&plain),
+        ChecksumVerdict::Valid { len: plain.len() }
+    );
+
+    let mut sealed = datagram(1);
+    seal(&mut sealed);
+    assert_eq!(sealed[0] & CHECKSUM_FLAG, CHECKSUM_FLAG);
+    assert_eq!(
+        guard.verify(&sealed),
+        ChecksumVerdict::Valid {
+            len: sealed.len() - CHECKSUM_LEN
+        }
+    );
+
+    // every byte is covered, including the header.
+    for i in 0..sealed.len() {
+        let mut damaged = sealed.clone();
+        damaged[i] ^= 0x10;
+        assert_ne!(
+            guard.verify(&damaged),
+            ChecksumVerdict::Valid {
+                len: sealed.len() - CHECKSUM_LEN
+            },
+            "flipping byte {} went unnoticed",
+            i
+        );
+    }
+
+    // once the peer checksums, a datagram without a trailer can only be damaged.
+    let mut guard = ChecksumGuard::new(true);
+    guard.verify(&sealed);
+    assert_eq!(guard.verify(&plain), ChecksumVerdict::Corrupt);
+    assert_eq!(guard.corrupt(), 1);
+
+    // a disabled guard leaves everything as it is.
+    let mut guard = ChecksumGuard::new(false);
+    assert_eq!(
+        guard.verify(&sealed),
+        ChecksumVerdict::Valid { len: sealed.len() }
+    );
+}
+
+#[test]
+fn test_damaging_path_is_reported_once() {
+    let mut queue = RecvQueue::new();
+    queue.set_datagram_checksum(true);
+
+    let mut sealed = datagram(0);
+    seal(&mut sealed);
+    let last = sealed.len() - 1;
+    sealed[last] ^= 0xff;
+
+    for _ in 0..CORRUPT_DATAGRAM_THRESHOLD * 2 {
+        assert!(queue.verify(&sealed).is_none());
+    }
+    assert_eq!(queue.corrupt_datagrams(), CORRUPT_DATAGRAM_THRESHOLD * 2);
+    assert_eq!(
+        queue.flush_events(),
+        vec![RakEvent::ProtocolViolation {
+            kind: ViolationKind::CorruptDatagrams
+        }]
+    );
+}
+
+#[test]
+fn test_capabilities_are_invisible_when_empty() {
+    let request = |capabilities| {
+        ConnectionRequest {
+            client_id: 7,
+            time: 1,
+            security: false,
+            capabilities,
+        }
+        .write_to_bytes()
+        .unwrap()
+        .as_slice()
+        .to_vec()
+    };
+    let vanilla = request(Capabilities::NONE);
+    assert_eq!(vanilla.len(), 8 + 8 + 1);
+    let offered = request(Capabilities::DATAGRAM_CHECKSUM);
+    assert_eq!(offered.len(), vanilla.len() + 1);
+    assert_eq!(
+        ConnectionRequest::read_from_slice(&offered)
+            .unwrap()
+            .capabilities,
+        Capabilities::DATAGRAM_CHECKSUM
+    );
+
+    for capabilities in [Capabilities::NONE, Capabilities::DATAGRAM_CHECKSUM] {
+        let accept = ConnectionAccept {
+            client_address: "127.0.0.1:1".parse().unwrap(),
+            system_index: 0,
+            internal_ids: vec!["255.255.255.255:19132".parse().unwrap(); 5],
+            request_time: 1,
+            timestamp: 2,
+            capabilities,
+        };
+        let decoded =
+            ConnectionAccept::read_from_slice(accept.write_to_bytes().unwrap().as_slice()).unwrap();
+        assert_eq!(decoded.internal_ids, accept.internal_ids);
+        assert_eq!(decoded.timestamp, 2);
+        assert_eq!(decoded.capabilities, capabilities);
+    }
+}
+
+/// What the proxy saw pass between the client and the server.
+#[derive(Default)]
+struct Observed {
+    /// The sequence of the datagram that was damaged.
+    damaged: Option<DatagramSeq>,
+    /// Whether the damaged datagram was sent again.
+    resent: bool,
+    /// Whether the server acknowledged the damaged datagram before it was sent again.
+    acked_early: bool,
+    /// The amount of datagrams with the checksum flag.
+    sealed: usize,
+}
+
+/// Forwards datagrams between a client and the server, damaging the first sealed datagram
+/// from the client that carries a `0xfe 0x42` payload.
+async fn proxy(listen: &str, server: &str) -> Arc<Mutex<Observed>> {
+    let observed = Arc::new(Mutex::new(Observed::default()));
+    let front = Arc::new(UdpSocket::bind(listen).await.unwrap());
+    let back = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+    back.connect(server).await.unwrap();
+    let client: Arc<Mutex<Option<SocketAddr>>> = Arc::new(Mutex::new(None));
+
+    {
+        let (front, back, client, observed) = (
+            front.clone(),
+            back.clone(),
+            client.clone(),
+            observed.clone(),
+        );
+        task::spawn(async move {
+            let mut buf = [0u8; 2048];
+            loop {
+                let (len, origin) = front.recv_from(&mut buf).await.unwrap();
+                *client.lock().unwrap() = Some(origin);
+                let mut datagram = buf[..len].to_vec();
+
+                if datagram[0] & 0xf0 == 0x80 && datagram[0] & CHECKSUM_FLAG != 0 {
+                    let mut observed = observed.lock().unwrap();
+                    observed.sealed += 1;
+
+                    if let Ok(pk) = FramePacket::read_from_slice(&datagram[..len - CHECKSUM_LEN]) {
+                        match observed.damaged {
+                            None if pk.frames.iter().any(|f| f.body.starts_with(&[0xfe, 0x42])) => {
+                                observed.damaged = Some(pk.sequence);
+                                datagram[len - CHECKSUM_LEN - 1] ^= 0xff;
+                            }
+                            Some(seq) if seq == pk.sequence => observed.resent = true,
+                            _ => {}
+                        }
+                    }
+                }
+
+                back.send(&datagram).await.unwrap();
+            }
+        });
+    }
+
+    let seen = observed.clone();
+    task::spawn(async move {
+        let mut buf = [0u8; 2048];
+        loop {
+            let len = back.recv(&mut buf).await.unwrap();
+            let datagram = &buf[..len];
+
+            {
+                let mut observed = seen.lock().unwrap();
+                if datagram[0] & 0xf0 == 0x80 && datagram[0] & CHECKSUM_FLAG != 0 {
+                    observed.sealed += 1;
+                }
+                if let Some(damaged) = observed.damaged {
+                    if !observed.resent && acked(datagram).contains(&damaged.get()) {
+                        observed.acked_early = true;
+                    }
+                }
+            }
+
+            let origin = *client.lock().unwrap();
+            if let Some(origin) = origin {
+                front.send_to(datagram, origin).await.unwrap();
+            }
+        }
+    });
+
+    observed
+}
+
+/// The sequences an ACK covers, the records are read by hand to stay off the crate internals.
+fn acked(datagram: &[u8]) -> Vec<u32> {
+    let mut sequences = Vec::new();
+    if datagram.len() < 3 || datagram[0] != 0xc0 {
+        return sequences;
+    }
+    let u24 = |b: &[u8]| u32::from_le_bytes([b[0], b[1], b[2], 0]);
+    let mut rest = &datagram[3..];
+    while let Some((&single, tail)) = rest.split_first() {
+        if single == 1 && tail.len() >= 3 {
+            sequences.push(u24(tail));
+            rest = &tail[3..];
+        } else if single == 0 && tail.len() >= 6 {
+            sequences.extend(u24(tail)..=u24(&tail[3..]));
+            rest = &tail[6..];
+        } else {
+            break;
+        }
+    }
+    sequences
+}
+
+fn checksummed() -> ConnOptions {
+    ConnOptions {
+        datagram_checksum: true,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_damaged_datagram_is_resent() {
+    task::block_on(async {
+        let mut server = Listener::bind_with_options("127.0.0.1:19185", checksummed())
+            .await
+            .unwrap();
+        server.start().await.unwrap();
+        let observed = proxy("127.0.0.1:19186", "127.0.0.1:19185").await;
+
+        let client = Client::new(11, 1400).with_options(checksummed());
+        timeout(Duration::from_secs(10), client.connect("127.0.0.1:19186"))
+            .await
+            .expect("connect timed out")
+            .expect("failed to connect");
+        let mut conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let payload = vec![0xfe, 0x42, 0x01, 0x02, 0x03];
+        client.send_ord(&payload, 0).await.unwrap();
+
+        let received = timeout(Duration::from_secs(10), conn.recv())
+            .await
+            .expect("the damaged payload was never resent")
+            .unwrap();
+        assert_eq!(received, payload);
+        assert!(timeout(Duration::from_millis(200), conn.recv())
+            .await
+            .is_err());
+
+        let observed = observed.lock().unwrap();
+        assert!(observed.damaged.is_some());
+        assert!(observed.resent);
+        assert!(
+            !observed.acked_early,
+            "the server acknowledged a damaged datagram"
+        );
+        drop(observed);
+        assert_eq!(conn.stats().await.corrupt_datagrams, 1);
+        assert_eq!(client.stats().await.corrupt_datagrams, 0);
+    });
+}
+
+#[test]
+fn test_no_trailer_without_agreement() {
+    task::block_on(async {
+        let mut server = Listener::bind_with_options("127.0.0.1:19187", checksummed())
+            .await
+            .unwrap();
+        server.start().await.unwrap();
+        let observed = proxy("127.0.0.1:19188", "127.0.0.1:19187").await;
+
+        // the client doesn't offer checksums, like any other RakNet peer.
+        let client = Client::new(11, 1400);
+        timeout(Duration::from_secs(10), client.connect("127.0.0.1:19188"))
+            .await
+            .expect("connect timed out")
+            .expect("failed to connect");
+        let mut conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+
+        client.send_ord(&[0xfe, 0x01], 0).await.unwrap();
+        let received = timeout(Duration::from_secs(5), conn.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(received, vec![0xfe, 0x01]);
+        conn.send(&[0xfe, 0x02], true).await.unwrap();
+        let received = timeout(Duration::from_secs(5), client.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(received, vec![0xfe, 0x02]);
+
+        assert_eq!(observed.lock().unwrap().sealed, 0);
+    });
+}
@@ -0,0 +1,204 @@
+#![cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+//! A session sealed with a pre-shared key carries everything it did in the clear, and the
+//! datagrams altered on the way are dropped rather than handed to the application.
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_std::{future::timeout, net::UdpSocket, task};
+use rak_rs::connection::transform::{
+    DatagramTransform, TransformError, XChaCha20Psk, PSK_NONCE_LEN, PSK_OVERHEAD,
+};
+use rak_rs::{Client, Connection, Listener, RakEvent};
+
+const KEY: [u8; 32] = [7; 32];
+
+/// Part of every payload, it never shows up on the link once the session is sealed.
+const MARKER: &[u8] = b"sealed-payload-marker";
+
+#[test]
+fn test_seal_and_open() {
+    let psk = XChaCha20Psk::new(&KEY);
+    let mut sealed = Vec::new();
+    psk.seal(3, b"\x84hello", &mut sealed);
+    assert_eq!(sealed.len(), 6 + PSK_OVERHEAD);
+    let mut opened = Vec::new();
+    psk.open(&sealed, &mut opened).unwrap();
+    assert_eq!(opened, b"\x84hello");
+
+    // a retransmission sealed with the same sequence looks nothing like the first send.
+    let mut again = Vec::new();
+    psk.seal(3, b"\x84hello", &mut again);
+    assert_ne!(sealed, again);
+
+    let mut out = Vec::new();
+    sealed[PSK_NONCE_LEN] ^= 1;
+    assert_eq!(psk.open(&sealed, &mut out), Err(TransformError::Rejected));
+    assert!(out.is_empty());
+    assert_eq!(
+        XChaCha20Psk::new(&[8; 32]).open(&again, &mut out),
+        Err(TransformError::Rejected)
+    );
+    assert_eq!(
+        psk.open(&again[..PSK_OVERHEAD - 1], &mut out),
+        Err(TransformError::Truncated)
+    );
+}
+
+/// What the link between the client and the server does to the datagrams on it.
+#[derive(Default)]
+struct Link {
+    /// Every n-th datagram, either way, is dropped, zero drops none.
+    drop_every: AtomicUsize,
+    /// Whether a bit of every datagram of the client is flipped.
+    tamper: AtomicBool,
+    /// The datagrams of the client, as they went over the link.
+    seen: Mutex<Vec<Vec<u8>>>,
+    relayed: AtomicUsize,
+}
+
+impl Link {
+    fn dropped(&self) -> bool {
+        let every = self.drop_every.load(Ordering::SeqCst);
+        every > 0 && self.relayed.fetch_add(1, Ordering::SeqCst) % every == every - 1
+    }
+}
+
+/// Relays one client on `relay` to `server`, through `link`.
+async fn relay(relay: &str, server: &str) -> Arc<Link> {
+    let relay = Arc::new(UdpSocket::bind(relay).await.unwrap());
+    let upstream = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+    upstream.connect(server).await.unwrap();
+    let link = Arc::new(Link::default());
+    let client = Arc::new(Mutex::new(None::<SocketAddr>));
+
+    let (relay_recv, upstream_send) = (relay.clone(), upstream.clone());
+    let (link_recv, client_recv) = (link.clone(), client.clone());
+    task::spawn(async move {
+        let mut buf = [0u8; 2048];
+        while let Ok((len, source)) = relay_recv.recv_from(&mut buf).await {
+            *client_recv.lock().unwrap() = Some(source);
+            if link_recv.tamper.load(Ordering::SeqCst) {
+                buf[len - 1] ^= 1;
+            }
+            link_recv.seen.lock().unwrap().push(buf[..len].to_vec());
+            if !link_recv.dropped() {
+                let _ = upstream_send.send(&buf[..len]).await;
+            }
+        }
+    });
+
+    let link_send = link.clone();
+    task::spawn(async move {
+        let mut buf = [0u8; 2048];
+        while let Ok(len) = upstream.recv(&mut buf).await {
+            let source = *client.lock().unwrap();
+            match source {
+                Some(source) if !link_send.dropped() => {
+                    let _ = relay.send_to(&buf[..len], source).await;
+                }
+                _ => {}
+            }
+        }
+    });
+    link
+}
+
+/// Connects a client to `server` through a relay on `relay`, and seals the session.
+async fn sealed_session(
+    server: &str,
+    relay_address: &str,
+) -> (Listener, Client, Connection, Arc<Link>) {
+    let mut listener = Listener::bind(server).await.unwrap();
+    listener.start().await.unwrap();
+    let link = relay(relay_address, server).await;
+
+    let client = Client::new(11, 1400);
+    timeout(Duration::from_secs(10), client.connect(relay_address))
+        .await
+        .expect("connect timed out")
+        .expect("failed to connect");
+    let conn = timeout(Duration::from_secs(5), listener.accept())
+        .await
+        .unwrap()
+        .unwrap();
+    timeout(Duration::from_secs(5), async {
+        while !matches!(conn.recv_event().await.unwrap(), RakEvent::Connected) {}
+    })
+    .await
+    .expect("the handshake never completed");
+
+    // the handshake went out in the clear, everything after it is sealed.
+    client.set_transform(Some(Arc::new(XChaCha20Psk::new(&KEY))));
+    conn.set_transform(Some(Arc::new(XChaCha20Psk::new(&KEY))));
+    link.seen.lock().unwrap().clear();
+    (listener, client, conn, link)
+}
+
+/// A distinct payload per message, the large ones get fragmented.
+fn payload(i: usize) -> Vec<u8> {
+    let len = if i % 10 == 0 { 3_000 } else { 100 + i };
+    let mut payload = vec![0xfe];
+    payload.extend_from_slice(MARKER);
+    payload.extend((0..len).map(|b| (i + b) as u8));
+    payload
+}
+
+#[test]
+fn test_lossy_session_through_psk() {
+    task::block_on(async {
+        let (_listener, client, mut conn, link) =
+            sealed_session("127.0.0.1:19286", "127.0.0.1:19287").await;
+        link.drop_every.store(5, Ordering::SeqCst);
+
+        for i in 0..40 {
+            client.send_ord(&payload(i), 0).await.unwrap();
+            conn.send(&payload(i), true).await.unwrap();
+        }
+        for i in 0..40 {
+            let received = timeout(Duration::from_secs(10), conn.recv())
+                .await
+                .expect("the server is missing a payload")
+                .unwrap();
+            assert_eq!(received, payload(i));
+            let received = timeout(Duration::from_secs(10), client.recv())
+                .await
+                .expect("the client is missing a payload")
+                .unwrap();
+            assert_eq!(received, payload(i));
+        }
+
+        let stats = client.stats().await;
+        assert!(stats.timeout_retransmits + stats.nack_retransmits > 0);
+        // nothing of the payloads was readable on the link.
+        let seen = link.seen.lock().unwrap();
+        assert!(!seen.is_empty());
+        assert!(seen.iter().all(|datagram| !datagram
+            .windows(MARKER.len())
+            .any(|window| window == MARKER)));
+    });
+}
+
+#[test]
+fn test_tampered_datagrams_are_rejected() {
+    task::block_on(async {
+        let (_listener, client, mut conn, link) =
+            sealed_session("127.0.0.1:19288", "127.0.0.1:19289").await;
+
+        link.tamper.store(true, Ordering::SeqCst);
+        client.send_ord(&payload(1), 0).await.unwrap();
+        assert!(timeout(Duration::from_millis(500), conn.recv())
+            .await
+            .is_err());
+        assert!(conn.stats().await.rejected_datagrams > 0);
+
+        // the payload is resent, and gets through once the link leaves it alone.
+        link.tamper.store(false, Ordering::SeqCst);
+        let received = timeout(Duration::from_secs(5), conn.recv())
+            .await
+            .expect("the payload was never resent")
+            .unwrap();
+        assert_eq!(received, payload(1));
+    });
+}
@@ -0,0 +1,233 @@
+//! Where and why a packet failed to decode, for fixture packets cut short or damaged.
+//!
+//! The fuzz case runs over seeded random buffers, so a failure is reproducible from the
+//! seed in its message.
+use rak_rs::error::decode::{DecodeError, DecodeErrorKind};
+use rak_rs::protocol::ack::Ack;
+use rak_rs::protocol::frame::FramePacket;
+use rak_rs::protocol::packet::offline::OfflinePacket;
+use rak_rs::protocol::packet::online::OnlinePacket;
+use rak_rs::protocol::packet::RakPacket;
+use rak_rs::protocol::primitives::{decode, decode_strict};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const SEED: u64 = 0x52414b;
+const CASES: usize = 10_000;
+
+fn fixture(name: &str) -> Vec<u8> {
+    let path = format!(
+        "{}/tests/fixtures/vanilla/{}.hex",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    );
+    let text = std::fs::read_to_string(&path).unwrap();
+    let mut bytes = Vec::new();
+    for line in text.lines() {
+        let line = line.split('#').next().unwrap();
+        for token in line.split_whitespace() {
+            let (byte, count) = match token.split_once('x') {
+                Some((byte, count)) => (byte, count.parse().unwrap()),
+                None => (token, 1),
+            };
+            let byte = u8::from_str_radix(byte, 16).unwrap();
+            bytes.extend(std::iter::repeat(byte).take(count));
+        }
+    }
+    bytes
+}
+
+fn eof(offset: usize, needed: usize, remaining: usize) -> (usize, DecodeErrorKind) {
+    (offset, DecodeErrorKind::UnexpectedEof { needed, remaining })
+}
+
+/// Cuts `name` to every length in `cuts`, and checks the error against what was expected.
+fn assert_cuts(name: &str, cuts: &[(usize, (usize, DecodeErrorKind))]) {
+    let packet = fixture(name);
+    for (len, (offset, kind)) in cuts {
+        let error = decode::<RakPacket>(&packet[..*len]).unwrap_err();
+        assert_eq!(
+            (error.offset, &error.kind),
+            (*offset, kind),
+            "{} cut to {} bytes",
+            name,
+            len
+        );
+        assert_eq!(error.packet_id, packet[..*len].first().copied());
+    }
+}
+
+#[test]
+fn test_cut_offline_packets() {
+    assert_cuts(
+        "unconnected_ping",
+        &[
+            (0, eof(0, 1, 0)),
+            (1, eof(1, 8, 0)),
+            (5, eof(1, 8, 4)),
+            (9, eof(9, 16, 0)),
+            (20, eof(9, 16, 11)),
+            (25, eof(25, 8, 0)),
+            (32, eof(25, 8, 7)),
+        ],
+    );
+    // the address is cut in its octets, after the version was read.
+    assert_cuts(
+        "open_connection_request_2",
+        &[
+            (17, eof(17, 1, 0)),
+            (18, eof(18, 4, 0)),
+            (21, eof(18, 4, 3)),
+            (24, eof(24, 2, 0)),
+            (27, eof(26, 8, 1)),
+        ],
+    );
+}
+
+#[test]
+fn test_cut_online_packets() {
+    assert_cuts("connected_ping", &[(1, eof(1, 8, 0)), (6, eof(1, 8, 5))]);
+    assert_cuts(
+        "new_incoming_connection",
+        &[(1, eof(1, 1, 0)), (4, eof(2, 4, 2))],
+    );
+}
+
+#[test]
+fn test_damaged_fields() {
+    let mut ping = fixture("unconnected_ping");
+    ping[12] ^= 0xff;
+    let error = decode::<OfflinePacket>(&ping).unwrap_err();
+    assert_eq!(
+        (error.offset, error.kind),
+        (9, DecodeErrorKind::InvalidMagic)
+    );
+
+    let mut request = fixture("open_connection_request_2");
+    request[17] = 5;
+    let error = decode::<RakPacket>(&request).unwrap_err();
+    assert_eq!(
+        (error.offset, error.kind),
+        (17, DecodeErrorKind::InvalidAddressVersion(5))
+    );
+
+    // payloads of the application are not packets, rather than broken ones.
+    for buffer in [&[0xfe, 1, 2][..], &[0x42]] {
+        let error = decode::<RakPacket>(buffer).unwrap_err();
+        assert_eq!(
+            (error.offset, error.kind.clone()),
+            (
+                0,
+                DecodeErrorKind::InvalidEnumValue {
+                    field: "packet id",
+                    value: buffer[0]
+                }
+            )
+        );
+        assert!(error.is_unknown_packet());
+        assert!(decode::<OnlinePacket>(buffer)
+            .unwrap_err()
+            .is_unknown_packet());
+    }
+    assert!(!decode::<RakPacket>(&ping).unwrap_err().is_unknown_packet());
+
+    let error = decode::<FramePacket>(&[0x42, 0, 0, 0]).unwrap_err();
+    assert_eq!(
+        (error.offset, error.kind),
+        (
+            0,
+            DecodeErrorKind::InvalidEnumValue {
+                field: "frame packet id",
+                value: 0x42
+            }
+        )
+    );
+}
+
+#[test]
+fn test_trailing_bytes_are_only_an_error_when_strict() {
+    let mut ping = fixture("connected_ping");
+    let len = ping.len();
+    ping.extend_from_slice(&[1, 2, 3]);
+
+    assert!(decode::<OnlinePacket>(&ping).is_ok());
+    let error = decode_strict::<OnlinePacket>(&ping).unwrap_err();
+    assert_eq!(
+        (error.offset, error.kind),
+        (len, DecodeErrorKind::TrailingBytes { count: 3 })
+    );
+    assert!(decode_strict::<OnlinePacket>(&ping[..len]).is_ok());
+}
+
+#[test]
+fn test_hexdump_marks_the_offset() {
+    let mut ping = fixture("unconnected_ping");
+    ping[10] = 0;
+    let error = decode::<RakPacket>(&ping).unwrap_err();
+    assert_eq!(
+        error.hexdump(&ping),
+        "0001: 00 00 00 00 00 00 00 2a [00] 00 ff 00 fe fe fe fe fd"
+    );
+
+    let error = decode::<RakPacket>(&ping[..3]).unwrap_err();
+    assert_eq!(error.hexdump(&ping[..3]), "0000: 01 [00] 00");
+    assert_eq!(
+        error.to_string(),
+        "packet 0x01 at offset 1: needed 8 bytes, 2 were left"
+    );
+}
+
+fn assert_sensible(error: &DecodeError, buffer: &[u8], seed: u64) {
+    assert!(
+        error.offset <= buffer.len(),
+        "seed {}: offset {} past {} bytes",
+        seed,
+        error.offset,
+        buffer.len()
+    );
+    assert_eq!(error.packet_id, buffer.first().copied(), "seed {}", seed);
+    if let DecodeErrorKind::UnexpectedEof { needed, remaining } = error.kind {
+        assert_eq!(error.offset + remaining, buffer.len(), "seed {}", seed);
+        assert!(needed > remaining, "seed {}: {}", seed, error);
+    }
+}
+
+#[test]
+fn test_fuzzed_errors_carry_an_offset_within_the_buffer() {
+    let fixtures = [
+        "unconnected_ping",
+        "unconnected_pong",
+        "open_connection_request_2",
+        "open_connection_reply_1",
+        "open_connection_reply_2",
+        "connected_ping",
+        "connection_request",
+        "connection_request_accepted",
+        "new_incoming_connection",
+    ]
+    .map(fixture);
+
+    for case in 0..CASES {
+        let seed = SEED + case as u64;
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut buffer = fixtures[rng.gen_range(0..fixtures.len())].clone();
+        for _ in 0..rng.gen_range(0..4) {
+            let at = rng.gen_range(0..buffer.len());
+            buffer[at] = rng.gen();
+        }
+        buffer.truncate(rng.gen_range(0..=buffer.len()));
+
+        if let Err(error) = decode::<RakPacket>(&buffer) {
+            assert_sensible(&error, &buffer, seed);
+        }
+        if let Err(error) = decode_strict::<OnlinePacket>(&buffer) {
+            assert_sensible(&error, &buffer, seed);
+        }
+        if let Err(error) = decode::<FramePacket>(&buffer) {
+            assert_sensible(&error, &buffer, seed);
+        }
+        if let Err(error) = decode::<Ack>(&buffer) {
+            assert_sensible(&error, &buffer, seed);
+        }
+    }
+}
@@ -0,0 +1,168 @@
+#![cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+//! Payloads sent with the defaults of a connection go out with its default reliability and
+//! channel, the ones sent with their own keep theirs.
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use async_std::{future::timeout, net::UdpSocket, task};
+use binary_util::interfaces::{Reader, Writer};
+use rak_rs::connection::options::ConnOptions;
+use rak_rs::protocol::frame::{Frame, FramePacket};
+use rak_rs::protocol::index::{DatagramSeq, MessageIndex};
+use rak_rs::protocol::packet::offline::{OfflinePacket, SessionInfoRequest};
+use rak_rs::protocol::packet::online::{
+    Capabilities, ConnectionRequest, NewConnection, OnlinePacket,
+};
+use rak_rs::protocol::packet::RakPacket;
+use rak_rs::protocol::Magic;
+use rak_rs::{Client, Listener, RakEvent, Reliability};
+
+const ADDRESS: &str = "127.0.0.1:19278";
+
+/// A peer that speaks just enough RakNet to complete the handshake and read the frames sent
+/// to it.
+struct MockPeer {
+    socket: UdpSocket,
+    sequence: u32,
+}
+
+impl MockPeer {
+    async fn send(&mut self, body: &[u8]) {
+        let mut frame = Frame::new(Reliability::Reliable, Some(body));
+        frame.reliable_index = Some(MessageIndex::new(self.sequence));
+
+        let mut datagram = FramePacket::new();
+        datagram.sequence = DatagramSeq::new(self.sequence);
+        datagram.frames.push(frame);
+        self.sequence += 1;
+
+        let buf = datagram.write_to_bytes().unwrap();
+        self.socket.send(buf.as_slice()).await.unwrap();
+    }
+
+    /// Receives frames until one carries `body`.
+    async fn recv_frame(&self, body: &[u8]) -> Frame {
+        let mut buf = [0u8; 2048];
+        loop {
+            let len = timeout(Duration::from_secs(5), self.socket.recv(&mut buf))
+                .await
+                .expect("the frame never arrived")
+                .unwrap();
+            if let Ok(datagram) = FramePacket::read_from_slice(&buf[..len]) {
+                if let Some(frame) = datagram.frames.into_iter().find(|f| f.body == body) {
+                    return frame;
+                }
+            }
+        }
+    }
+}
+
+fn online(packet: OnlinePacket) -> Vec<u8> {
+    packet.write_to_bytes().unwrap().as_slice().to_vec()
+}
+
+#[test]
+fn test_default_and_explicit_sends() {
+    task::block_on(async {
+        let mut server = Listener::bind(ADDRESS).await.unwrap();
+        server.conn_options = ConnOptions {
+            default_reliability: Reliability::ReliableSeq,
+            default_channel: 3,
+            ..Default::default()
+        };
+        server.start().await.unwrap();
+        let server_address: SocketAddr = ADDRESS.parse().unwrap();
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        socket.connect(ADDRESS).await.unwrap();
+        let session = RakPacket::from(OfflinePacket::SessionInfoRequest(SessionInfoRequest {
+            magic: Magic::new(),
+            address: server_address,
+            mtu_size: 1400,
+            client_id: 7,
+            capabilities: Capabilities::NONE,
+        }));
+        socket
+            .send(session.write_to_bytes().unwrap().as_slice())
+            .await
+            .unwrap();
+        let mut peer = MockPeer {
+            socket,
+            sequence: 0,
+        };
+
+        let conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+        peer.send(&online(OnlinePacket::from(ConnectionRequest {
+            client_id: 7,
+            time: 1,
+            security: false,
+            capabilities: Capabilities::NONE,
+        })))
+        .await;
+        peer.send(&online(OnlinePacket::from(NewConnection {
+            server_address,
+            system_address: vec![server_address; 10],
+            request_time: 1,
+            timestamp: 1,
+        })))
+        .await;
+        let event = timeout(Duration::from_secs(5), conn.recv_event())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(event, RakEvent::Connected));
+
+        // the defaults come from the options of the listener.
+        assert_eq!(conn.default_reliability(), Reliability::ReliableSeq);
+        assert_eq!(conn.default_channel(), 3);
+        conn.send_default(&[0xfe, 0x01], true).await.unwrap();
+        let frame = peer.recv_frame(&[0xfe, 0x01]).await;
+        assert_eq!(frame.reliability, Reliability::ReliableSeq);
+        assert_eq!(frame.order_channel, Some(3));
+
+        // an exception keeps its own reliability and channel.
+        conn.send_with(&[0xfe, 0x02], Reliability::ReliableOrd, 1, true)
+            .await
+            .unwrap();
+        let frame = peer.recv_frame(&[0xfe, 0x02]).await;
+        assert_eq!(frame.reliability, Reliability::ReliableOrd);
+        assert_eq!(frame.order_channel, Some(1));
+
+        // changing the defaults affects the sends after it.
+        conn.set_default_reliability(Reliability::ReliableOrd);
+        conn.set_default_channel(5);
+        conn.send_default(&[0xfe, 0x03], true).await.unwrap();
+        let frame = peer.recv_frame(&[0xfe, 0x03]).await;
+        assert_eq!(frame.reliability, Reliability::ReliableOrd);
+        assert_eq!(frame.order_channel, Some(5));
+
+        // the plain send is unchanged.
+        conn.send(&[0xfe, 0x04], true).await.unwrap();
+        let frame = peer.recv_frame(&[0xfe, 0x04]).await;
+        assert_eq!(frame.reliability, Reliability::ReliableOrd);
+        assert_eq!(frame.order_channel, Some(0));
+    });
+}
+
+#[test]
+fn test_client_defaults() {
+    let client = Client::new(11, 1400);
+    assert_eq!(client.default_reliability(), Reliability::ReliableOrd);
+    assert_eq!(client.default_channel(), 0);
+
+    let client = Client::new(11, 1400).with_options(ConnOptions {
+        default_reliability: Reliability::Reliable,
+        default_channel: 2,
+        ..Default::default()
+    });
+    assert_eq!(client.default_reliability(), Reliability::Reliable);
+    assert_eq!(client.default_channel(), 2);
+
+    client.set_default_reliability(Reliability::ReliableSeq);
+    client.set_default_channel(4);
+    assert_eq!(client.default_reliability(), Reliability::ReliableSeq);
+    assert_eq!(client.default_channel(), 4);
+}
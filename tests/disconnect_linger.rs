@@ -0,0 +1,187 @@
+#![cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+//! A closed connection resends its disconnect until the peer acknowledged it, for at most
+//! the linger window, so a lost disconnect doesn't leave the peer waiting for a timeout.
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_std::{future::timeout, net::UdpSocket, task};
+use binary_util::interfaces::{Reader, Writer};
+use rak_rs::connection::event::{DisconnectReason, RakEvent};
+use rak_rs::connection::options::DEFAULT_DISCONNECT_LINGER;
+use rak_rs::protocol::ack::ACK;
+use rak_rs::protocol::frame::{Frame, FramePacket};
+use rak_rs::protocol::index::{DatagramSeq, MessageIndex};
+use rak_rs::{Client, Listener, Reliability};
+
+/// Whether `datagram` is a frame set carrying a disconnect.
+fn is_disconnect(datagram: &[u8]) -> bool {
+    matches!(datagram.first(), Some(0x80..=0x8f))
+        && FramePacket::read_from_slice(datagram)
+            .map(|packet| packet.frames.iter().any(|f| f.body.first() == Some(&0x15)))
+            .unwrap_or(false)
+}
+
+/// What the link between the client and the server drops.
+#[derive(Default)]
+struct Link {
+    /// The disconnects of the client to drop before they get through.
+    drop_disconnects: AtomicUsize,
+    /// Whether everything sent to the client is dropped.
+    drop_to_client: AtomicBool,
+}
+
+/// Relays one client on `relay` to `server`, dropping what `link` says.
+async fn relay(relay: &str, server: &str) -> Arc<Link> {
+    let relay = Arc::new(UdpSocket::bind(relay).await.unwrap());
+    let upstream = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+    upstream.connect(server).await.unwrap();
+    let link = Arc::new(Link::default());
+    let client = Arc::new(Mutex::new(None::<SocketAddr>));
+
+    let (relay_recv, upstream_send) = (relay.clone(), upstream.clone());
+    let (link_recv, client_recv) = (link.clone(), client.clone());
+    task::spawn(async move {
+        let mut buf = [0u8; 2048];
+        while let Ok((len, source)) = relay_recv.recv_from(&mut buf).await {
+            *client_recv.lock().unwrap() = Some(source);
+            let dropped = is_disconnect(&buf[..len])
+                && link_recv
+                    .drop_disconnects
+                    .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                    .is_ok();
+            if !dropped {
+                let _ = upstream_send.send(&buf[..len]).await;
+            }
+        }
+    });
+
+    let link_send = link.clone();
+    task::spawn(async move {
+        let mut buf = [0u8; 2048];
+        while let Ok(len) = upstream.recv(&mut buf).await {
+            let source = *client.lock().unwrap();
+            match source {
+                Some(source) if !link_send.drop_to_client.load(Ordering::SeqCst) => {
+                    let _ = relay.send_to(&buf[..len], source).await;
+                }
+                _ => {}
+            }
+        }
+    });
+    link
+}
+
+#[test]
+fn test_lost_disconnect_is_resent() {
+    task::block_on(async {
+        const SERVER: &str = "127.0.0.1:19281";
+        const RELAY: &str = "127.0.0.1:19282";
+        let mut server = Listener::bind(SERVER).await.unwrap();
+        server.start().await.unwrap();
+        let link = relay(RELAY, SERVER).await;
+
+        let client = Client::new(11, 1400);
+        timeout(Duration::from_secs(10), client.connect(RELAY))
+            .await
+            .expect("connect timed out")
+            .expect("failed to connect");
+        let mut conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+        // a round trip, so the retransmission timeout is down from its initial second.
+        client.send_ord(&[0xfe, 0x01], 0).await.unwrap();
+        assert_eq!(conn.recv().await.unwrap(), vec![0xfe, 0x01]);
+        conn.send(&[0xfe, 0x02], true).await.unwrap();
+        assert_eq!(client.recv().await.unwrap(), vec![0xfe, 0x02]);
+
+        link.drop_disconnects.store(1, Ordering::SeqCst);
+        let started = Instant::now();
+        client.close().await;
+        // the server acknowledged the resent disconnect.
+        assert!(started.elapsed() < DEFAULT_DISCONNECT_LINGER);
+
+        let event = timeout(DEFAULT_DISCONNECT_LINGER, async {
+            loop {
+                match conn.recv_event().await.unwrap() {
+                    RakEvent::Disconnected { reason } => break reason,
+                    _ => continue,
+                }
+            }
+        })
+        .await
+        .expect("the server only learnt of the disconnect by timing out");
+        assert_eq!(event, DisconnectReason::Closed);
+        assert_eq!(link.drop_disconnects.load(Ordering::SeqCst), 0);
+    });
+}
+
+#[test]
+fn test_linger_ends_without_acknowledgement() {
+    task::block_on(async {
+        const SERVER: &str = "127.0.0.1:19283";
+        const RELAY: &str = "127.0.0.1:19284";
+        let mut server = Listener::bind(SERVER).await.unwrap();
+        server.start().await.unwrap();
+        let link = relay(RELAY, SERVER).await;
+
+        let client = Client::new(11, 1400);
+        timeout(Duration::from_secs(10), client.connect(RELAY))
+            .await
+            .expect("connect timed out")
+            .expect("failed to connect");
+        let mut conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+
+        // the client never hears of the disconnect, nor acknowledges it.
+        link.drop_to_client.store(true, Ordering::SeqCst);
+        let started = Instant::now();
+        timeout(DEFAULT_DISCONNECT_LINGER * 2, conn.close())
+            .await
+            .expect("the connection lingered past its window");
+        assert!(started.elapsed() >= DEFAULT_DISCONNECT_LINGER);
+        assert!(conn.is_closed().await);
+        timeout(Duration::from_millis(100), conn.closed())
+            .await
+            .expect("the connection was not torn down");
+    });
+}
+
+#[test]
+fn test_disconnect_without_session_is_acknowledged() {
+    task::block_on(async {
+        const SERVER: &str = "127.0.0.1:19285";
+        let mut server = Listener::bind(SERVER).await.unwrap();
+        server.start().await.unwrap();
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        socket.connect(SERVER).await.unwrap();
+        let datagram = |sequence: u32, body: &[u8]| {
+            let mut frame = Frame::new(Reliability::Reliable, Some(body));
+            frame.reliable_index = Some(MessageIndex::new(sequence));
+            let mut datagram = FramePacket::new();
+            datagram.sequence = DatagramSeq::new(sequence);
+            datagram.frames.push(frame);
+            datagram.write_to_bytes().unwrap().as_slice().to_vec()
+        };
+
+        socket.send(&datagram(9, &[0x15, 0x00])).await.unwrap();
+        let mut buf = [0u8; 2048];
+        let len = timeout(Duration::from_secs(1), socket.recv(&mut buf))
+            .await
+            .expect("the disconnect was not acknowledged")
+            .unwrap();
+        assert_eq!(buf[..len].first(), Some(&ACK));
+        assert!(server.connections().await.is_empty());
+
+        // anything else from an unknown peer is still ignored.
+        socket.send(&datagram(10, &[0xfe])).await.unwrap();
+        assert!(timeout(Duration::from_millis(300), socket.recv(&mut buf))
+            .await
+            .is_err());
+    });
+}
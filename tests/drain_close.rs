@@ -0,0 +1,160 @@
+#![cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_std::{channel::bounded, future::timeout, task};
+use rak_rs::connection::event::DisconnectReason;
+use rak_rs::connection::queue::SendQueueError;
+use rak_rs::socket::ENOBUFS;
+use rak_rs::{Client, DrainResult, Listener, RakEvent};
+
+/// `EBADF`, what every receive fails with once the descriptor was closed out from under it.
+const EBADF: i32 = 9;
+
+#[test]
+fn test_drain_then_transfer() {
+    task::block_on(async {
+        let mut server = Listener::bind("127.0.0.1:19162").await.unwrap();
+        server.start().await.unwrap();
+        let (result_send, result_recv) = bounded::<(u16, DrainResult)>(1);
+
+        task::spawn(async move {
+            let conn = Arc::new(server.accept().await.unwrap());
+
+            // keep sending until the connection starts draining.
+            let sender = conn.clone();
+            let sends = task::spawn(async move {
+                let mut accepted: u16 = 0;
+                loop {
+                    let id = accepted.to_be_bytes();
+                    match sender.send(&[0xfe, id[0], id[1]], true).await {
+                        Ok(_) => accepted += 1,
+                        Err(SendQueueError::Draining) => return accepted,
+                        Err(e) => panic!("unexpected send error {:?}", e),
+                    }
+                    task::yield_now().await;
+                }
+            });
+
+            task::sleep(Duration::from_millis(50)).await;
+            let result = conn
+                .begin_drain_and_close(DisconnectReason::Transferred, Duration::from_secs(5))
+                .await;
+            result_send.send((sends.await, result)).await.unwrap();
+        });
+
+        let client = Client::new(11, 1400);
+        timeout(Duration::from_secs(10), client.connect("127.0.0.1:19162"))
+            .await
+            .expect("connect timed out")
+            .expect("failed to connect");
+
+        // read the payloads while waiting for the disconnect, so the client never stalls.
+        let mut received = Vec::new();
+        let event = timeout(Duration::from_secs(15), async {
+            loop {
+                match timeout(Duration::from_millis(50), client.recv()).await {
+                    Ok(Ok(packet)) => {
+                        if packet[0] == 0xfe {
+                            received.push(u16::from_be_bytes([packet[1], packet[2]]));
+                        }
+                    }
+                    _ => {
+                        if let Ok(Ok(event)) =
+                            timeout(Duration::from_millis(10), client.recv_event()).await
+                        {
+                            return event;
+                        }
+                    }
+                }
+            }
+        })
+        .await
+        .expect("no disconnect event");
+        assert_eq!(
+            event,
+            RakEvent::Disconnected {
+                reason: DisconnectReason::Transferred
+            }
+        );
+
+        // everything queued before the disconnect has already been handed to us.
+        while let Ok(Ok(packet)) = timeout(Duration::from_millis(200), client.recv()).await {
+            if packet[0] == 0xfe {
+                received.push(u16::from_be_bytes([packet[1], packet[2]]));
+            }
+        }
+
+        let (accepted, result) = result_recv.recv().await.unwrap();
+        assert_eq!(result, DrainResult::Drained);
+        assert!(accepted > 0);
+        assert_eq!(received, (0..accepted).collect::<Vec<u16>>());
+    });
+}
+
+#[test]
+fn test_drain_gives_up_on_a_closed_connection() {
+    task::block_on(async {
+        let address = "127.0.0.1:19241";
+        let mut server = Listener::bind(address).await.unwrap();
+        server.start().await.unwrap();
+
+        let client = Client::new(11, 1400);
+        timeout(Duration::from_secs(10), client.connect(address))
+            .await
+            .expect("connect timed out")
+            .expect("failed to connect");
+        let conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+
+        // nothing of what is queued leaves, so the drain would take all of its timeout.
+        conn.fail_sends(std::iter::repeat(ENOBUFS).take(10_000))
+            .await;
+        for i in 0..5u8 {
+            conn.send(&[0xfe, i], false).await.unwrap();
+        }
+
+        let conn = Arc::new(conn);
+        let draining = conn.clone();
+        let drain = task::spawn(async move {
+            draining
+                .begin_drain_and_close(DisconnectReason::Transferred, Duration::from_secs(30))
+                .await
+        });
+        task::sleep(Duration::from_millis(50)).await;
+
+        // the listener loses its socket, which closes the connection from under the drain.
+        server.fail_recvs(EBADF);
+        client.send_ord(&[0xfe, 0], 0).await.unwrap();
+
+        assert_eq!(
+            timeout(Duration::from_secs(2), drain)
+                .await
+                .expect("the drain kept waiting"),
+            DrainResult::Aborted {
+                reason: DisconnectReason::SocketFailure
+            }
+        );
+        assert_eq!(
+            timeout(Duration::from_secs(1), conn.closed())
+                .await
+                .unwrap(),
+            DisconnectReason::SocketFailure
+        );
+
+        // a drain of a connection that is already closed doesn't wait at all.
+        assert_eq!(
+            timeout(
+                Duration::from_millis(100),
+                conn.begin_drain_and_close(DisconnectReason::Transferred, Duration::from_secs(30))
+            )
+            .await
+            .unwrap(),
+            DrainResult::Aborted {
+                reason: DisconnectReason::SocketFailure
+            }
+        );
+    });
+}
@@ -0,0 +1,86 @@
+#![cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use async_std::{future::timeout, net::UdpSocket, task};
+use rak_rs::connection::event::DisconnectReason;
+use rak_rs::{Client, Listener};
+
+/// Polls until the address can be bound again.
+async fn rebind(address: SocketAddr) -> bool {
+    for _ in 0..100 {
+        if UdpSocket::bind(address).await.is_ok() {
+            return true;
+        }
+        task::sleep(Duration::from_millis(20)).await;
+    }
+    false
+}
+
+#[test]
+fn test_dropped_client_releases_its_socket() {
+    task::block_on(async {
+        let mut server = Listener::bind("127.0.0.1:19197").await.unwrap();
+        server.start().await.unwrap();
+
+        let client = Client::new(11, 1400);
+        timeout(Duration::from_secs(10), client.connect("127.0.0.1:19197"))
+            .await
+            .expect("connect timed out")
+            .expect("failed to connect");
+        let conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let local = client.local_addr().unwrap();
+        let local = SocketAddr::new("0.0.0.0".parse().unwrap(), local.port());
+        let closed = client.closed();
+        drop(client);
+
+        // dropping doesn't await anything, the client is closed right away.
+        assert_eq!(
+            timeout(Duration::from_millis(100), closed).await.unwrap(),
+            DisconnectReason::Closed
+        );
+        assert!(rebind(local).await, "the client never released its socket");
+
+        // nothing was sent, so the server times the session out like any silent peer.
+        let reason = timeout(Duration::from_secs(25), conn.closed())
+            .await
+            .expect("the server never timed the session out");
+        assert_eq!(reason, DisconnectReason::TimedOut { one_way: false });
+    });
+}
+
+#[test]
+fn test_dropped_listener_releases_its_socket() {
+    task::block_on(async {
+        let address: SocketAddr = "127.0.0.1:19198".parse().unwrap();
+        let mut server = Listener::bind(address).await.unwrap();
+        server.start().await.unwrap();
+
+        let client = Client::new(11, 1400);
+        timeout(Duration::from_secs(10), client.connect(address))
+            .await
+            .expect("connect timed out")
+            .expect("failed to connect");
+        let conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+        let closed = conn.closed();
+
+        // the connection is kept, yet it can't keep the socket of the listener alive.
+        drop(server);
+        assert!(
+            rebind(address).await,
+            "the listener never released its socket"
+        );
+        assert_eq!(
+            timeout(Duration::from_secs(1), closed).await.unwrap(),
+            DisconnectReason::Shutdown
+        );
+        drop(conn);
+    });
+}
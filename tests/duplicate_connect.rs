@@ -0,0 +1,139 @@
+#![cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+//! Connects one client twice at once, through a relay that counts the handshakes reaching the server.
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_std::{future::timeout, net::UdpSocket, task};
+use rak_rs::error::client::ClientError;
+use rak_rs::{Client, Listener};
+
+/// The id of the second request of the offline handshake.
+const OPEN_CONNECT_REQUEST: u8 = 0x07;
+
+/// Relays every source on `relay` to `server` from a socket of its own, and returns the
+/// sources that got as far as the second stage of the handshake.
+async fn relay(relay: &str, server: &str) -> Arc<Mutex<Vec<SocketAddr>>> {
+    let relay = Arc::new(UdpSocket::bind(relay).await.unwrap());
+    let server: SocketAddr = server.parse().unwrap();
+    let handshakes = Arc::new(Mutex::new(Vec::new()));
+    let counted = handshakes.clone();
+
+    task::spawn(async move {
+        let mut upstreams: HashMap<SocketAddr, Arc<UdpSocket>> = HashMap::new();
+        let mut buf = [0u8; 2048];
+        while let Ok((len, source)) = relay.recv_from(&mut buf).await {
+            if buf[0] == OPEN_CONNECT_REQUEST {
+                let mut counted = counted.lock().unwrap();
+                if !counted.contains(&source) {
+                    counted.push(source);
+                }
+            }
+
+            if !upstreams.contains_key(&source) {
+                let upstream = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+                upstream.connect(server).await.unwrap();
+                let (upstream_recv, relay) = (upstream.clone(), relay.clone());
+                task::spawn(async move {
+                    let mut buf = [0u8; 2048];
+                    while let Ok(len) = upstream_recv.recv(&mut buf).await {
+                        let _ = relay.send_to(&buf[..len], source).await;
+                    }
+                });
+                upstreams.insert(source, upstream);
+            }
+            let _ = upstreams[&source].send(&buf[..len]).await;
+        }
+    });
+    handshakes
+}
+
+#[test]
+fn test_second_connect_is_refused() {
+    task::block_on(async {
+        const SERVER: &str = "127.0.0.1:19215";
+        const RELAY: &str = "127.0.0.1:19213";
+        let mut server = Listener::bind(SERVER).await.unwrap();
+        server.start().await.unwrap();
+        let handshakes = relay(RELAY, SERVER).await;
+
+        let client = Client::new(11, 1400);
+        let (first, second) = timeout(
+            Duration::from_secs(10),
+            futures::future::join(client.connect(RELAY), client.connect(RELAY)),
+        )
+        .await
+        .expect("connect timed out");
+
+        assert!(first.is_ok(), "{:?}", first);
+        assert!(
+            matches!(second, Err(ClientError::AlreadyConnecting)),
+            "{:?}",
+            second
+        );
+        assert_eq!(handshakes.lock().unwrap().len(), 1);
+
+        // connecting while connected is still an error of its own.
+        assert!(matches!(
+            client.connect(RELAY).await,
+            Err(ClientError::AlreadyOnline)
+        ));
+    });
+}
+
+#[test]
+fn test_second_connect_joins_the_first() {
+    task::block_on(async {
+        const SERVER: &str = "127.0.0.1:19216";
+        const RELAY: &str = "127.0.0.1:19217";
+        let mut server = Listener::bind(SERVER).await.unwrap();
+        server.start().await.unwrap();
+        let handshakes = relay(RELAY, SERVER).await;
+
+        let client = Client::new(11, 1400).join_existing(true);
+        let (first, second) = timeout(
+            Duration::from_secs(10),
+            futures::future::join(client.connect(RELAY), client.connect(RELAY)),
+        )
+        .await
+        .expect("connect timed out");
+
+        assert!(first.is_ok(), "{:?}", first);
+        assert!(second.is_ok(), "{:?}", second);
+        assert_eq!(handshakes.lock().unwrap().len(), 1);
+    });
+}
+
+#[test]
+fn test_reconnect_after_close() {
+    task::block_on(async {
+        const ADDRESS: &str = "127.0.0.1:19218";
+        let mut server = Listener::bind(ADDRESS).await.unwrap();
+        server.start().await.unwrap();
+
+        let client = Client::new(11, 1400);
+        for _ in 0..2 {
+            timeout(Duration::from_secs(10), client.connect(ADDRESS))
+                .await
+                .expect("connect timed out")
+                .expect("failed to connect");
+            let mut conn = timeout(Duration::from_secs(5), server.accept())
+                .await
+                .unwrap()
+                .unwrap();
+
+            client.send_ord(&[0xfe, 1, 2, 3], 0).await.unwrap();
+            let packet = timeout(Duration::from_secs(5), conn.recv())
+                .await
+                .expect("the message was lost")
+                .unwrap();
+            assert_eq!(packet, vec![0xfe, 1, 2, 3]);
+
+            client.close().await;
+            timeout(Duration::from_secs(1), client.closed())
+                .await
+                .expect("close did not fire");
+        }
+    });
+}
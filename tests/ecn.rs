@@ -0,0 +1,137 @@
+//! Datagrams are marked ECN capable when enabled, and traffic flows the same as without.
+use std::cell::RefCell;
+use std::io;
+
+use rak_rs::socket::{apply_ecn, EcnSupport, SocketOpts, ECT_0};
+
+#[derive(Default)]
+struct MockSocket {
+    calls: RefCell<Vec<(u32, bool)>>,
+    fail: Option<io::ErrorKind>,
+}
+
+impl SocketOpts for MockSocket {
+    fn bind_device(&self, _device: &str, _ipv6: bool) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn set_ecn(&self, codepoint: u32, ipv6: bool) -> io::Result<()> {
+        self.calls.borrow_mut().push((codepoint, ipv6));
+        match self.fail {
+            Some(kind) => Err(io::Error::from(kind)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Only sets what it has to, like a platform without ECN.
+struct PlainSocket;
+
+impl SocketOpts for PlainSocket {
+    fn bind_device(&self, _device: &str, _ipv6: bool) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_ecn_is_set_when_enabled() {
+    let sock = MockSocket::default();
+
+    assert_eq!(apply_ecn(&sock, false, false), EcnSupport::Off);
+    assert!(sock.calls.borrow().is_empty());
+
+    assert_eq!(apply_ecn(&sock, true, false), EcnSupport::SendOnly);
+    assert_eq!(apply_ecn(&sock, true, true), EcnSupport::SendOnly);
+    assert_eq!(*sock.calls.borrow(), vec![(ECT_0, false), (ECT_0, true)]);
+    assert!(EcnSupport::SendOnly.is_marking());
+}
+
+#[test]
+fn test_ecn_degrades_when_refused() {
+    let sock = MockSocket {
+        fail: Some(io::ErrorKind::PermissionDenied),
+        ..Default::default()
+    };
+    assert_eq!(apply_ecn(&sock, true, false), EcnSupport::Unavailable);
+    assert_eq!(sock.calls.borrow().len(), 1);
+
+    assert_eq!(apply_ecn(&PlainSocket, true, true), EcnSupport::Unavailable);
+    assert!(!EcnSupport::Unavailable.is_marking());
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+#[test]
+fn test_ecn_marks_the_socket() {
+    use socket2::{Domain, Protocol, Socket, Type};
+    use std::net::SocketAddr;
+
+    let address: SocketAddr = "127.0.0.1:0".parse().unwrap();
+    let sock = Socket::new(
+        Domain::for_address(address),
+        Type::DGRAM,
+        Some(Protocol::UDP),
+    )
+    .unwrap();
+
+    assert_eq!(apply_ecn(&sock, true, false), EcnSupport::SendOnly);
+    assert_eq!(sock.tos_v4().unwrap() & 0b11, ECT_0);
+}
+
+#[cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+#[test]
+fn test_traffic_flows_with_ecn() {
+    use std::time::Duration;
+
+    use async_std::{future::timeout, task};
+    use rak_rs::connection::options::ConnOptions;
+    use rak_rs::{Client, Listener};
+
+    task::block_on(async {
+        let address = "127.0.0.1:19242";
+        let options = ConnOptions {
+            enable_ecn: true,
+            ..Default::default()
+        };
+        let mut server = Listener::bind_with_options(address, options.clone())
+            .await
+            .unwrap();
+        server.start().await.unwrap();
+
+        let client = Client::new(11, 1400).with_options(options);
+        assert_eq!(client.ecn_supported(), EcnSupport::Off);
+        timeout(Duration::from_secs(10), client.connect(address))
+            .await
+            .expect("connect timed out")
+            .expect("failed to connect");
+        let mut conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        {
+            assert_eq!(client.ecn_supported(), EcnSupport::SendOnly);
+            assert_eq!(conn.ecn_supported(), EcnSupport::SendOnly);
+        }
+
+        for i in 0..20u8 {
+            client.send_ord(&[0xfe, i], 0).await.unwrap();
+        }
+        for i in 0..20u8 {
+            let message = timeout(Duration::from_secs(5), conn.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(message, [0xfe, i]);
+        }
+
+        conn.send(&[0xfe, 0xff], true).await.unwrap();
+        assert_eq!(
+            timeout(Duration::from_secs(5), client.recv())
+                .await
+                .unwrap()
+                .unwrap(),
+            [0xfe, 0xff]
+        );
+    });
+}
@@ -0,0 +1,195 @@
+#![cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+//! Lifecycle events are read ahead of a backlog of other events, and a connection flooded with
+//! messages doesn't hold up the events of another.
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use async_std::{future::timeout, task};
+use rak_rs::connection::event::{DisconnectReason, RakEvent, ViolationKind};
+use rak_rs::connection::queue::{event_queue, EVENT_BACKLOG, LIFECYCLE_BACKLOG};
+use rak_rs::protocol::index::OrderIndex;
+use rak_rs::{Client, Listener};
+
+fn gap(index: u32) -> RakEvent {
+    RakEvent::OrderedGap {
+        channel: 0,
+        skipped_index: OrderIndex::new(index),
+    }
+}
+
+const DISCONNECTED: RakEvent = RakEvent::Disconnected {
+    reason: DisconnectReason::Kicked,
+};
+
+#[test]
+fn test_lifecycle_events_are_never_crowded_out() {
+    task::block_on(async {
+        let (sender, receiver) = event_queue();
+
+        sender.try_send(RakEvent::Connected).unwrap();
+        for i in 0..100 {
+            let _ = sender.try_send(gap(i));
+        }
+        assert_eq!(sender.try_send(gap(100)), Err(gap(100)));
+        sender.try_send(DISCONNECTED).unwrap();
+
+        // the backlog that was queued before it still goes first.
+        assert_eq!(receiver.recv().await.unwrap(), RakEvent::Connected);
+        for i in 0..EVENT_BACKLOG as u32 {
+            assert_eq!(receiver.recv().await.unwrap(), gap(i));
+        }
+        assert_eq!(receiver.recv().await.unwrap(), DISCONNECTED);
+
+        drop(sender);
+        assert!(receiver.recv().await.is_err());
+    });
+}
+
+#[test]
+fn test_disconnect_is_never_dropped() {
+    task::block_on(async {
+        let (sender, receiver) = event_queue();
+        let suspected = |port: u16| RakEvent::SuspectedOneWayLoss {
+            addr: SocketAddr::from(([127, 0, 0, 1], port)),
+        };
+
+        for port in 0..LIFECYCLE_BACKLOG as u16 {
+            sender.try_send(suspected(port)).unwrap();
+        }
+        let full = suspected(LIFECYCLE_BACKLOG as u16);
+        assert_eq!(sender.try_send(full.clone()), Err(full));
+        sender.try_send(DISCONNECTED).unwrap();
+        // one that is waiting already is enough.
+        sender.try_send(DISCONNECTED).unwrap();
+
+        for port in 0..LIFECYCLE_BACKLOG as u16 {
+            assert_eq!(receiver.recv().await.unwrap(), suspected(port));
+        }
+        assert_eq!(receiver.recv().await.unwrap(), DISCONNECTED);
+        assert!(timeout(Duration::from_millis(50), receiver.recv())
+            .await
+            .is_err());
+
+        // once it was read, the next connection of a client can be told again.
+        sender.try_send(DISCONNECTED).unwrap();
+        assert_eq!(receiver.recv().await.unwrap(), DISCONNECTED);
+    });
+}
+
+#[test]
+fn test_lifecycle_events_go_ahead_of_later_events() {
+    task::block_on(async {
+        let (sender, receiver) = event_queue();
+
+        sender.try_send(gap(0)).unwrap();
+        sender.try_send(gap(1)).unwrap();
+        assert_eq!(receiver.recv().await.unwrap(), gap(0));
+
+        let suspected = RakEvent::SuspectedOneWayLoss {
+            addr: "127.0.0.1:19132".parse().unwrap(),
+        };
+        sender.try_send(suspected.clone()).unwrap();
+        sender.try_send(gap(2)).unwrap();
+        sender.try_send(DISCONNECTED).unwrap();
+
+        assert_eq!(receiver.recv().await.unwrap(), gap(1));
+        assert_eq!(receiver.recv().await.unwrap(), suspected);
+        // everything before the disconnect was read, it goes ahead of what came after.
+        sender.try_send(gap(3)).unwrap();
+        assert_eq!(receiver.recv().await.unwrap(), gap(2));
+        assert_eq!(receiver.recv().await.unwrap(), DISCONNECTED);
+        assert_eq!(receiver.recv().await.unwrap(), gap(3));
+    });
+}
+
+#[test]
+fn test_waiting_violations_are_not_queued_again() {
+    task::block_on(async {
+        let (sender, receiver) = event_queue();
+        let violation = RakEvent::ProtocolViolation {
+            kind: ViolationKind::OrderJump,
+        };
+
+        for _ in 0..1000 {
+            sender.try_send(violation.clone()).unwrap();
+        }
+        sender
+            .try_send(RakEvent::ProtocolViolation {
+                kind: ViolationKind::UnknownFlood,
+            })
+            .unwrap();
+        sender.try_send(DISCONNECTED).unwrap();
+
+        assert_eq!(receiver.recv().await.unwrap(), violation);
+        assert_eq!(
+            receiver.recv().await.unwrap(),
+            RakEvent::ProtocolViolation {
+                kind: ViolationKind::UnknownFlood
+            }
+        );
+        assert_eq!(receiver.recv().await.unwrap(), DISCONNECTED);
+
+        // once it was read, the next one is queued again.
+        sender.try_send(violation.clone()).unwrap();
+        assert_eq!(receiver.recv().await.unwrap(), violation);
+        assert!(timeout(Duration::from_millis(50), receiver.recv())
+            .await
+            .is_err());
+    });
+}
+
+#[test]
+fn test_flood_does_not_hold_up_another_disconnect() {
+    task::block_on(async {
+        let address: SocketAddr = "127.0.0.1:19243".parse().unwrap();
+        let mut server = Listener::bind(address).await.unwrap();
+        server.start().await.unwrap();
+
+        let flooding = Client::new(11, 1400);
+        timeout(Duration::from_secs(10), flooding.connect(address))
+            .await
+            .expect("connect timed out")
+            .expect("failed to connect");
+        let _flooded = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let leaving = Client::new(11, 1400);
+        timeout(Duration::from_secs(10), leaving.connect(address))
+            .await
+            .expect("connect timed out")
+            .expect("failed to connect");
+        let conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+
+        // nothing reads the messages of the flooded connection.
+        for i in 0..1000u32 {
+            flooding
+                .send_ord(&[0xfe, i as u8, (i >> 8) as u8], 0)
+                .await
+                .unwrap();
+        }
+        task::sleep(Duration::from_millis(100)).await;
+
+        let start = Instant::now();
+        leaving.close().await;
+        let disconnected = timeout(Duration::from_secs(5), async {
+            loop {
+                if let RakEvent::Disconnected { reason } = conn.recv_event().await.unwrap() {
+                    return reason;
+                }
+            }
+        })
+        .await
+        .expect("the disconnect never arrived");
+        assert_eq!(disconnected, DisconnectReason::Closed);
+        assert!(
+            start.elapsed() < Duration::from_millis(500),
+            "the disconnect took {:?}",
+            start.elapsed()
+        );
+    });
+}
@@ -0,0 +1,49 @@
+//! Every supported combination of features builds, tests included, so a server-only or
+//! client-only build never breaks unnoticed.
+//!
+//! This runs cargo once per combination, it only runs with the `feature-matrix` feature:
+//!
+//! ```sh
+//! cargo test --features feature-matrix --test feature_matrix
+//! ```
+use std::process::Command;
+
+const RUNTIMES: [&str; 2] = ["async_std", "async_tokio"];
+const SIDES: [&str; 3] = ["client", "server", "client,server"];
+
+/// Runs `cargo check` on every target with only `features` enabled.
+fn check(features: &str) -> bool {
+    Command::new(env!("CARGO"))
+        .args(["check", "--quiet", "--all-targets", "--no-default-features"])
+        .args(["--features", features])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        // the target directory of the running test is still locked.
+        .env(
+            "CARGO_TARGET_DIR",
+            format!("{}/target/feature-matrix", env!("CARGO_MANIFEST_DIR")),
+        )
+        .status()
+        .expect("cargo can't be run")
+        .success()
+}
+
+#[test]
+fn test_feature_combinations_build() {
+    let mut combinations = Vec::new();
+    for runtime in RUNTIMES {
+        for side in SIDES {
+            combinations.push(format!("{},{}", runtime, side));
+        }
+    }
+    combinations.push("async_std,client,server,mcpe".to_string());
+    combinations.push("ffi".to_string());
+    combinations.push("async_std,server,codec,simulation".to_string());
+    combinations.push("async_std,query".to_string());
+    combinations.push("async_tokio,query".to_string());
+
+    let failed: Vec<_> = combinations
+        .into_iter()
+        .filter(|features| !check(features))
+        .collect();
+    assert!(failed.is_empty(), "these don't build: {:?}", failed);
+}
@@ -0,0 +1,188 @@
+#![cfg(feature = "ffi")]
+//! The C interface, driven from rust the way a C program would, and its header.
+use std::ffi::CString;
+use std::process::Command;
+use std::ptr;
+use std::time::{Duration, Instant};
+
+use async_std::{future::timeout, task};
+use rak_rs::ffi::*;
+use rak_rs::{Listener, Reliability};
+
+fn source(path: &str) -> String {
+    std::fs::read_to_string(format!("{}/{}", env!("CARGO_MANIFEST_DIR"), path)).unwrap()
+}
+
+/// The names following `prefix` in `text`, up to the first character that can't be in one.
+fn names_after<'a>(text: &'a str, prefix: &str) -> Vec<&'a str> {
+    text.split(prefix)
+        .skip(1)
+        .map(|rest| {
+            let end = rest
+                .find(|c: char| !c.is_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            &rest[..end]
+        })
+        .collect()
+}
+
+fn screaming(name: &str) -> String {
+    let mut screaming = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            screaming.push('_');
+        }
+        screaming.push(c.to_ascii_uppercase());
+    }
+    screaming
+}
+
+#[test]
+fn test_header_declares_every_export() {
+    let ffi = source("src/ffi.rs");
+    let header = source("include/rakrs.h");
+
+    let exported = names_after(&ffi, "pub unsafe extern \"C\" fn ");
+    let declared = names_after(&header, " rakrs_");
+    assert_eq!(exported.len(), 6);
+    for function in &exported {
+        assert!(
+            declared.contains(&function.trim_start_matches("rakrs_")),
+            "{} is not in the header",
+            function
+        );
+    }
+    assert_eq!(declared.len(), exported.len(), "the header declares more");
+
+    for constant in names_after(&ffi, "pub const ") {
+        assert!(
+            header.contains(&format!("#define {} ", constant)),
+            "{}",
+            constant
+        );
+    }
+    for ty in ["RakrsStatus", "RakrsEventKind"] {
+        let body = ffi.split(&format!("pub enum {} {{", ty)).nth(1).unwrap();
+        let body = body.split('}').next().unwrap();
+        for line in body
+            .lines()
+            .map(str::trim)
+            .filter(|line| line.contains(" = "))
+        {
+            let (variant, value) = line.trim_end_matches(',').split_once(" = ").unwrap();
+            let constant = format!("{}_{} = {},", screaming(ty), screaming(variant), value);
+            assert!(
+                header.contains(&constant),
+                "{} is not in the header",
+                constant
+            );
+        }
+    }
+    for ty in ["RakrsOptions", "RakrsEvent"] {
+        assert!(
+            header.contains(&format!("typedef struct {} {{", ty)),
+            "{}",
+            ty
+        );
+    }
+    assert!(header.contains("typedef struct RakrsClient RakrsClient;"));
+
+    // the header has to be valid C, where a compiler is around to tell.
+    if let Ok(status) = Command::new("cc")
+        .args(["-fsyntax-only", "-Wall", "-Werror", "-x", "c"])
+        .arg(format!("{}/include/rakrs.h", env!("CARGO_MANIFEST_DIR")))
+        .status()
+    {
+        assert!(status.success(), "the header doesn't compile");
+    }
+}
+
+#[test]
+fn test_client_through_the_c_interface() {
+    const ADDRESS: &str = "127.0.0.1:19238";
+    let mut server = task::block_on(Listener::bind(ADDRESS)).unwrap();
+    task::block_on(server.start()).unwrap();
+    // the listener has to outlive the test, its connections close with it.
+    let accepted = task::spawn(async move {
+        let conn = server.accept().await.unwrap();
+        (server, conn)
+    });
+
+    unsafe {
+        let addr = CString::new(ADDRESS).unwrap();
+        let mut client: *mut RakrsClient = ptr::null_mut();
+        let opts = RakrsOptions {
+            connect_timeout_ms: 5000,
+            ..Default::default()
+        };
+        assert_eq!(
+            rakrs_client_connect(ptr::null(), &opts, &mut client),
+            RakrsStatus::InvalidArgument
+        );
+        assert_eq!(
+            rakrs_client_connect(addr.as_ptr(), &opts, &mut client),
+            RakrsStatus::Ok
+        );
+        let (_server, mut conn) =
+            task::block_on(timeout(Duration::from_secs(5), accepted)).unwrap();
+
+        let message = [0xfe, 1, 2, 3];
+        assert_eq!(
+            rakrs_client_send(client, message.as_ptr(), message.len(), 9),
+            RakrsStatus::InvalidArgument
+        );
+        assert_eq!(
+            rakrs_client_send(
+                client,
+                message.as_ptr(),
+                message.len(),
+                Reliability::ReliableOrd as u8
+            ),
+            RakrsStatus::Ok
+        );
+        assert_eq!(
+            task::block_on(timeout(Duration::from_secs(5), conn.recv()))
+                .unwrap()
+                .unwrap(),
+            message
+        );
+
+        // nothing happened yet, polling returns right away.
+        let mut event = RakrsEvent {
+            kind: RakrsEventKind::Message,
+            data: ptr::null_mut(),
+            len: 0,
+            reason: 0,
+        };
+        let started = Instant::now();
+        assert!(!rakrs_client_poll(client, &mut event));
+        assert!(started.elapsed() < Duration::from_millis(100));
+
+        task::block_on(conn.send(&[0xfe, 4, 5], false)).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !rakrs_client_poll(client, &mut event) {
+            assert!(Instant::now() < deadline, "the message never came");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(event.kind, RakrsEventKind::Message);
+        assert_eq!(
+            std::slice::from_raw_parts(event.data, event.len),
+            [0xfe, 4, 5]
+        );
+        rakrs_buffer_free(event.data, event.len);
+
+        assert_eq!(rakrs_client_disconnect(client), RakrsStatus::Ok);
+        assert!(rakrs_client_poll(client, &mut event));
+        assert_eq!(event.kind, RakrsEventKind::Disconnected);
+        assert!(event.data.is_null());
+        assert!(!rakrs_client_poll(client, &mut event));
+        assert_eq!(
+            rakrs_client_send(client, message.as_ptr(), message.len(), 0),
+            RakrsStatus::NotConnected
+        );
+
+        rakrs_client_destroy(client);
+        rakrs_client_destroy(ptr::null_mut());
+        rakrs_buffer_free(ptr::null_mut(), 0);
+    }
+}
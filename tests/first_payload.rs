@@ -0,0 +1,63 @@
+#![cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+//! A message the client hands to [`Client::first_payload()`] arrives before anything else.
+use std::time::Duration;
+
+use async_std::{future::timeout, task};
+use rak_rs::client::MAX_FIRST_PAYLOAD;
+use rak_rs::error::client::ClientError;
+use rak_rs::{Client, Listener, RakEvent};
+
+#[test]
+fn test_first_payload_arrives_first() {
+    task::block_on(async {
+        const ADDRESS: &str = "127.0.0.1:19231";
+        let mut server = Listener::bind(ADDRESS).await.unwrap();
+        server.start().await.unwrap();
+
+        let login = vec![0xfe, 0x01, 0x02, 0x03];
+        let client = Client::new(11, 1400).first_payload(login.clone());
+        timeout(Duration::from_secs(10), client.connect(ADDRESS))
+            .await
+            .expect("connect timed out")
+            .expect("failed to connect");
+        client.send_ord(&[0xfe, 0x04], 0).await.unwrap();
+
+        let mut conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+        let event = timeout(Duration::from_secs(5), conn.recv_event())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(event, RakEvent::Connected);
+
+        for expected in [login, vec![0xfe, 0x04]] {
+            let packet = timeout(Duration::from_secs(5), conn.recv())
+                .await
+                .expect("nothing arrived")
+                .unwrap();
+            assert_eq!(packet, expected);
+        }
+    });
+}
+
+#[test]
+fn test_oversized_first_payload_is_refused() {
+    task::block_on(async {
+        // nothing listens here, the payload is refused before the server is looked for.
+        const ADDRESS: &str = "127.0.0.1:19232";
+        let client = Client::new(11, 1400).first_payload(vec![0xfe; MAX_FIRST_PAYLOAD + 1]);
+        let result = timeout(Duration::from_secs(1), client.connect(ADDRESS))
+            .await
+            .expect("connect timed out");
+
+        assert_eq!(
+            result,
+            Err(ClientError::FirstPayloadTooLarge {
+                len: MAX_FIRST_PAYLOAD + 1,
+                max: MAX_FIRST_PAYLOAD,
+            })
+        );
+    });
+}
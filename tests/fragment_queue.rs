@@ -2,6 +2,7 @@ use rak_rs::{
     connection::queue::FragmentQueue,
     protocol::{
         frame::{FragmentMeta, Frame},
+        index::SplitId,
         reliability::Reliability,
     },
 };
@@ -18,30 +19,53 @@ fn test_proper_reordering() {
     // push slice 2 first, then slice 1, then slice 3
     queue
         .insert(
-            Frame::new(Reliability::ReliableOrd, Some(SLICE_TWO))
-                .with_meta(FragmentMeta::new(3, 11, 1)),
+            Frame::new(Reliability::ReliableOrd, Some(SLICE_TWO)).with_meta(FragmentMeta::new(
+                3,
+                SplitId::new(11),
+                1,
+            )),
         )
         .unwrap();
 
     queue
         .insert(
-            Frame::new(Reliability::ReliableOrd, Some(SLICE_ONE))
-                .with_meta(FragmentMeta::new(3, 11, 0)),
+            Frame::new(Reliability::ReliableOrd, Some(SLICE_ONE)).with_meta(FragmentMeta::new(
+                3,
+                SplitId::new(11),
+                0,
+            )),
         )
         .unwrap();
 
     queue
         .insert(
-            Frame::new(Reliability::ReliableOrd, Some(SLICE_THREE))
-                .with_meta(FragmentMeta::new(3, 11, 2)),
+            Frame::new(Reliability::ReliableOrd, Some(SLICE_THREE)).with_meta(FragmentMeta::new(
+                3,
+                SplitId::new(11),
+                2,
+            )),
         )
         .unwrap();
 
     // collect the fragments
-    let res = queue.collect(11);
+    let res = queue.collect(SplitId::new(11));
 
     assert_eq!(
         res.unwrap(),
         vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]
     );
 }
+
+#[test]
+fn test_split_ids_are_consecutive() {
+    let mut queue = FragmentQueue::new();
+    let body = vec![0u8; 4000];
+
+    let ids = (0..20)
+        .map(|_| queue.split_insert(&body, 1400).unwrap())
+        .collect::<Vec<SplitId>>();
+
+    for pair in ids.windows(2) {
+        assert_eq!(pair[1], pair[0].wrapping_add(1));
+    }
+}
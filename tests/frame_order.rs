@@ -0,0 +1,99 @@
+//! The frames of one datagram are delivered in the order they had on the wire.
+use rak_rs::{
+    connection::queue::RecvQueue,
+    protocol::{
+        frame::{Frame, FramePacket},
+        index::{DatagramSeq, MessageIndex, OrderIndex, SequenceIndex},
+        reliability::Reliability,
+    },
+};
+
+fn datagram(sequence: u32, frames: &[Frame]) -> FramePacket {
+    let mut packet = FramePacket::new();
+    packet.sequence = DatagramSeq::new(sequence);
+    packet.frames.extend_from_slice(frames);
+    packet
+}
+
+/// Reliable frames, the body of each is its reliable index.
+fn reliable(indexes: &[u32]) -> Vec<Frame> {
+    indexes
+        .iter()
+        .map(|index| {
+            let mut frame = Frame::new(Reliability::Reliable, Some(&[*index as u8]));
+            frame.reliable_index = Some(MessageIndex::new(*index));
+            frame
+        })
+        .collect()
+}
+
+/// Sequenced frames on channel 0, the body of each is its sequence index.
+fn sequenced(reliability: Reliability, indexes: &[u32]) -> Vec<Frame> {
+    indexes
+        .iter()
+        .map(|index| {
+            let mut frame = Frame::new(reliability, Some(&[*index as u8]));
+            if reliability.is_reliable() {
+                frame.reliable_index = Some(MessageIndex::new(*index));
+            }
+            frame.order_channel = Some(0);
+            frame.order_index = Some(OrderIndex::new(0));
+            frame.sequence_index = Some(SequenceIndex::new(*index));
+            frame
+        })
+        .collect()
+}
+
+#[test]
+fn test_reliable_frames_keep_wire_order() {
+    let mut queue = RecvQueue::new();
+
+    // the frames are not written in the order of their reliable indexes.
+    let frames = reliable(&[2, 0, 1]);
+    queue.insert(datagram(0, &frames)).unwrap();
+    assert_eq!(queue.flush(), vec![vec![2], vec![0], vec![1]]);
+}
+
+#[test]
+fn test_sequenced_frames_keep_wire_order() {
+    let mut queue = RecvQueue::new();
+
+    let frames = sequenced(Reliability::UnreliableSeq, &[1, 2, 3]);
+    queue.insert(datagram(0, &frames)).unwrap();
+    assert_eq!(queue.flush(), vec![vec![1], vec![2], vec![3]]);
+
+    let frames = sequenced(Reliability::ReliableSeq, &[4, 5, 6]);
+    queue.insert(datagram(1, &frames)).unwrap();
+    assert_eq!(queue.flush(), vec![vec![4], vec![5], vec![6]]);
+}
+
+#[test]
+fn test_retransmission_keeps_wire_order() {
+    let mut queue = RecvQueue::new();
+    let frames = reliable(&[2, 0, 1]);
+    let sequenced = sequenced(Reliability::ReliableSeq, &[3, 4, 5]);
+
+    // the first transmission of both datagrams was lost, they are resent with new sequences.
+    queue.insert(datagram(2, &frames)).unwrap();
+    assert_eq!(queue.flush(), vec![vec![2], vec![0], vec![1]]);
+    queue.insert(datagram(3, &sequenced)).unwrap();
+    assert_eq!(queue.flush(), vec![vec![3], vec![4], vec![5]]);
+
+    // a late copy of the first transmission delivers nothing twice.
+    queue.insert(datagram(0, &frames)).unwrap();
+    queue.insert(datagram(1, &sequenced)).unwrap();
+    assert!(queue.flush().is_empty());
+}
+
+#[test]
+fn test_partially_received_datagram_keeps_wire_order() {
+    let mut queue = RecvQueue::new();
+    let frames = reliable(&[0, 1, 2, 3]);
+
+    // the middle frame already arrived in another datagram.
+    queue.insert(datagram(0, &frames[1..2])).unwrap();
+    assert_eq!(queue.flush(), vec![vec![1]]);
+
+    queue.insert(datagram(1, &frames)).unwrap();
+    assert_eq!(queue.flush(), vec![vec![0], vec![2], vec![3]]);
+}
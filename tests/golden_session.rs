@@ -0,0 +1,384 @@
+#![cfg(all(feature = "async_std", feature = "server", not(feature = "mcpe")))]
+//! Pins every datagram the server sends during a scripted session to the bytes in
+//! `tests/fixtures/golden/server_session.hex`.
+//!
+//! The client is simulated with the requests in `tests/fixtures/vanilla`, and acknowledges
+//! every frame set right away so nothing is ever retransmitted. The server runs with the guid
+//! `1112131415161718`, its sequences start at zero. What is left nondeterministic is
+//! normalized before comparing:
+//!
+//! - the time the server puts in the unconnected pong, the connection accept and the
+//!   connected pong is zeroed,
+//! - the connected pings the server sends on its own, as keepalive or for the clock estimate,
+//!   are cut out of the frame sets they rode along in. They are sent on a timer,
+//! - acknowledgements are listed after the frame sets of the same step, they go out on the
+//!   tick of the server which may come before or after its reply.
+//!
+//! A deliberate change to the wire format is recorded by running the test with
+//! `RAKRS_BLESS_GOLDEN=1`, and reviewing the diff of the golden file.
+use std::fmt::Write as _;
+use std::time::Duration;
+
+use async_std::{future::timeout, net::UdpSocket, task};
+use binary_util::interfaces::Writer;
+use rak_rs::protocol::frame::{Frame, FramePacket};
+use rak_rs::protocol::index::{DatagramSeq, MessageIndex, OrderIndex};
+use rak_rs::{Listener, Reliability};
+
+const SERVER: &str = "127.0.0.1:19234";
+const CLIENT: &str = "127.0.0.1:19235";
+const GOLDEN: &str = "tests/fixtures/golden/server_session.hex";
+
+/// Reads a fixture from `tests/fixtures/vanilla`, see `tests/vanilla_handshake.rs`.
+fn fixture(name: &str) -> Vec<u8> {
+    let path = format!(
+        "{}/tests/fixtures/vanilla/{}.hex",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    );
+    hex(&std::fs::read_to_string(path).unwrap())
+}
+
+fn hex(text: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for line in text.lines() {
+        let line = line.split('#').next().unwrap();
+        for token in line.split_whitespace() {
+            let (byte, count) = match token.split_once('x') {
+                Some((byte, count)) => (byte, count.parse().unwrap()),
+                None => (token, 1),
+            };
+            let byte = u8::from_str_radix(byte, 16).unwrap();
+            bytes.extend(std::iter::repeat(byte).take(count));
+        }
+    }
+    bytes
+}
+
+/// A datagram the server sent, normalized, and the step of the script it was sent in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Recorded {
+    step: String,
+    datagram: Vec<u8>,
+}
+
+/// Splits a frame set into its header and the raw bytes of each frame, without the decoder
+/// of the crate, so the golden bytes don't depend on it.
+fn split_frames(datagram: &[u8]) -> (&[u8], Vec<&[u8]>) {
+    let (header, mut rest) = datagram.split_at(4);
+    let mut frames = Vec::new();
+    while !rest.is_empty() {
+        let flags = rest[0];
+        let reliability = flags >> 5;
+        let body = (u16::from_be_bytes([rest[1], rest[2]]) as usize + 7) / 8;
+        let mut len = 3;
+        if matches!(reliability, 2 | 3 | 4 | 6 | 7) {
+            len += 3;
+        }
+        if matches!(reliability, 1 | 4) {
+            len += 3;
+        }
+        if matches!(reliability, 1 | 3 | 4 | 7) {
+            len += 4;
+        }
+        if flags & 0x10 != 0 {
+            len += 10;
+        }
+        let (frame, next) = rest.split_at(len + body);
+        frames.push(frame);
+        rest = next;
+    }
+    (header, frames)
+}
+
+/// Zeroes the time the server put in a packet, and drops the connected pings it sends on its
+/// own. Returns `None` if nothing is left of the datagram.
+fn normalize(datagram: &[u8]) -> Option<Vec<u8>> {
+    match datagram[0] {
+        // unconnected pong, the time follows the id.
+        0x1c => {
+            let mut datagram = datagram.to_vec();
+            datagram[1..9].fill(0);
+            Some(datagram)
+        }
+        0x80..=0x8f => {
+            let (header, frames) = split_frames(datagram);
+            let mut normalized = header.to_vec();
+            for frame in frames {
+                let body_at =
+                    frame.len() - (u16::from_be_bytes([frame[1], frame[2]]) as usize + 7) / 8;
+                let mut frame = frame.to_vec();
+                match frame[body_at] {
+                    // connected ping
+                    0x00 => continue,
+                    // connected pong, the time of the server follows the time of the ping.
+                    0x03 => frame[body_at + 9..body_at + 17].fill(0),
+                    // connection accept, a vanilla request gets no capabilities, so the time
+                    // of the server ends it.
+                    0x10 => {
+                        let end = frame.len();
+                        frame[end - 8..].fill(0)
+                    }
+                    _ => {}
+                }
+                normalized.extend_from_slice(&frame);
+            }
+            (normalized.len() > 4).then_some(normalized)
+        }
+        _ => Some(datagram.to_vec()),
+    }
+}
+
+/// A client that speaks just enough RakNet to go through a session.
+struct Script {
+    socket: UdpSocket,
+    sequence: u32,
+    reliable_index: u32,
+    order_index: u32,
+    step: String,
+    recorded: Vec<Recorded>,
+}
+
+impl Script {
+    async fn send_offline(&mut self, step: &str, name: &str) {
+        self.step = step.to_string();
+        self.socket.send(&fixture(name)).await.unwrap();
+    }
+
+    async fn send_frame(&mut self, step: &str, body: &[u8], reliability: Reliability) {
+        self.step = step.to_string();
+        let mut frame = Frame::new(reliability, Some(body));
+        frame.reliable_index = Some(MessageIndex::new(self.reliable_index));
+        self.reliable_index += 1;
+        if reliability == Reliability::ReliableOrd {
+            frame.order_index = Some(OrderIndex::new(self.order_index));
+            frame.order_channel = Some(0);
+            self.order_index += 1;
+        }
+
+        let mut datagram = FramePacket::new();
+        datagram.sequence = DatagramSeq::new(self.sequence);
+        datagram.frames.push(frame);
+        self.sequence += 1;
+        self.socket
+            .send(datagram.write_to_bytes().unwrap().as_slice())
+            .await
+            .unwrap();
+    }
+
+    /// Records what the server sends until `count` datagrams of this step arrived, or
+    /// nothing arrived for a while. Frame sets are acknowledged right away.
+    async fn expect(&mut self, count: usize, wait: Duration) {
+        let mut frame_sets = Vec::new();
+        let mut acks = Vec::new();
+        let mut buf = [0u8; 2048];
+        while frame_sets.len() + acks.len() < count {
+            let len = match timeout(wait, self.socket.recv(&mut buf)).await {
+                Ok(len) => len.unwrap(),
+                Err(_) => break,
+            };
+            let datagram = &buf[..len];
+            if let 0x80..=0x8f = datagram[0] {
+                let sequence = [datagram[1], datagram[2], datagram[3]];
+                let ack = [
+                    0xc0,
+                    0x00,
+                    0x01,
+                    0x01,
+                    sequence[0],
+                    sequence[1],
+                    sequence[2],
+                ];
+                self.socket.send(&ack).await.unwrap();
+            }
+            let datagram = match normalize(datagram) {
+                Some(datagram) => datagram,
+                None => continue,
+            };
+            if datagram[0] == 0xc0 {
+                acks.push(datagram);
+            } else {
+                frame_sets.push(datagram);
+            }
+        }
+
+        for datagram in frame_sets.into_iter().chain(acks) {
+            self.recorded.push(Recorded {
+                step: self.step.clone(),
+                datagram,
+            });
+        }
+    }
+}
+
+fn render(recorded: &[Recorded]) -> String {
+    let mut text = String::from(
+        "# Every datagram the server sends during the session of tests/golden_session.rs.\n\
+         # Written by running the test with RAKRS_BLESS_GOLDEN=1, a line starting with `>`\n\
+         # starts a datagram and names the step of the script it was sent in.\n",
+    );
+    for recorded in recorded {
+        writeln!(text, "> {}", recorded.step).unwrap();
+        for line in recorded.datagram.chunks(16) {
+            let line = line
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<Vec<String>>();
+            writeln!(text, "{}", line.join(" ")).unwrap();
+        }
+    }
+    text
+}
+
+fn parse(text: &str) -> Vec<Recorded> {
+    let mut recorded: Vec<Recorded> = Vec::new();
+    for line in text.lines() {
+        if let Some(step) = line.strip_prefix("> ") {
+            recorded.push(Recorded {
+                step: step.to_string(),
+                datagram: Vec::new(),
+            });
+        } else if let Some(last) = recorded.last_mut() {
+            last.datagram.extend(hex(line));
+        }
+    }
+    recorded
+}
+
+#[test]
+fn test_server_session_matches_golden() {
+    task::block_on(async {
+        let mut server = Listener::bind(SERVER).await.unwrap();
+        server.id = 0x1112131415161718;
+        server.start().await.unwrap();
+
+        // the application echoes whatever it receives.
+        task::spawn(async move {
+            let mut conn = server.accept().await.unwrap();
+            while let Ok(packet) = conn.recv().await {
+                if conn.send(&packet, true).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let socket = UdpSocket::bind(CLIENT).await.unwrap();
+        socket.connect(SERVER).await.unwrap();
+        let mut script = Script {
+            socket,
+            sequence: 0,
+            reliable_index: 0,
+            order_index: 0,
+            step: String::new(),
+            recorded: Vec::new(),
+        };
+        let wait = Duration::from_secs(2);
+
+        script
+            .send_offline("unconnected ping", "unconnected_ping")
+            .await;
+        script.expect(1, wait).await;
+        script
+            .send_offline("open connection request 1", "open_connection_request_1")
+            .await;
+        script.expect(1, wait).await;
+        script
+            .send_offline("open connection request 2", "open_connection_request_2")
+            .await;
+        script.expect(1, wait).await;
+
+        script
+            .send_frame(
+                "connection request",
+                &fixture("connection_request"),
+                Reliability::Reliable,
+            )
+            .await;
+        script.expect(2, wait).await;
+        script
+            .send_frame(
+                "new incoming connection",
+                &fixture("new_incoming_connection"),
+                Reliability::ReliableOrd,
+            )
+            .await;
+        script.expect(1, wait).await;
+
+        for (i, packet) in [vec![0xfe, 0x01], vec![0xfe, 0x02, 0x03], vec![0xfe; 64]]
+            .iter()
+            .enumerate()
+        {
+            script
+                .send_frame(
+                    &format!("game packet {}", i),
+                    packet,
+                    Reliability::ReliableOrd,
+                )
+                .await;
+            script.expect(2, wait).await;
+        }
+
+        script
+            .send_frame(
+                "connected ping",
+                &fixture("connected_ping"),
+                Reliability::Reliable,
+            )
+            .await;
+        script.expect(2, wait).await;
+
+        script
+            .send_frame(
+                "disconnect",
+                &fixture("disconnection_notification"),
+                Reliability::Reliable,
+            )
+            .await;
+        // the server closes right away, anything it still sends is recorded as well.
+        script.expect(usize::MAX, Duration::from_millis(300)).await;
+
+        let path = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), GOLDEN);
+        if std::env::var_os("RAKRS_BLESS_GOLDEN").is_some() {
+            std::fs::write(&path, render(&script.recorded)).unwrap();
+            return;
+        }
+
+        let golden = parse(&std::fs::read_to_string(&path).unwrap());
+        for (i, (actual, expected)) in script.recorded.iter().zip(golden.iter()).enumerate() {
+            assert_eq!(
+                actual, expected,
+                "datagram {} differs from {}, run with RAKRS_BLESS_GOLDEN=1 if this is deliberate",
+                i, GOLDEN
+            );
+        }
+        assert_eq!(
+            script.recorded.len(),
+            golden.len(),
+            "the server sent a different amount of datagrams than {}:\n{}",
+            GOLDEN,
+            render(&script.recorded)
+        );
+    });
+}
+
+#[test]
+fn test_normalization_keeps_the_frames_it_doesnt_touch() {
+    let datagram = hex("84 07 00 00 \
+         60 00 10 01 00 00 00 00 00 00 fe 01 \
+         00 00 48 00 00 00 00 00 00 00 00 2a");
+    let (header, frames) = split_frames(&datagram);
+    assert_eq!(header, &[0x84, 0x07, 0x00, 0x00]);
+    assert_eq!(frames.len(), 2);
+    // the connected ping is cut out, the game packet is kept as is.
+    assert_eq!(normalize(&datagram).unwrap(), datagram[..16].to_vec());
+    assert_eq!(
+        normalize(
+            &datagram[..4]
+                .iter()
+                .chain(frames[1])
+                .copied()
+                .collect::<Vec<u8>>()
+        ),
+        None
+    );
+}
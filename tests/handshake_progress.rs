@@ -0,0 +1,224 @@
+#![cfg(all(feature = "async_std", feature = "client"))]
+//! Drives the client handshake against a mock server that stops at each stage.
+use std::time::Duration;
+
+use async_std::{future::timeout, net::UdpSocket, task};
+use binary_util::interfaces::Writer;
+use rak_rs::client::handshake::Stage;
+use rak_rs::client::PING_PROBES;
+use rak_rs::error::client::{ClientError, HandshakeError};
+use rak_rs::protocol::packet::offline::{IncompatibleProtocolVersion, OfflinePacket};
+use rak_rs::protocol::packet::RakPacket;
+use rak_rs::protocol::Magic;
+use rak_rs::Client;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Script {
+    SilentOpen1,
+    SilentOpen2,
+    SilentConnect,
+    WrongMtu,
+    Incompatible,
+    /// Drops everything, like a NAT eating every reply.
+    Blackhole,
+    /// Answers the ping, then everything else with junk.
+    Junk,
+    /// Answers everything with junk, the ping included.
+    JunkOnly,
+}
+
+const JUNK: &[u8] = b"hello";
+
+/// Reads a fixture of the vanilla handshake, see `tests/vanilla_handshake.rs` for the format.
+fn fixture(name: &str) -> Vec<u8> {
+    let path = format!(
+        "{}/tests/fixtures/vanilla/{}.hex",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    );
+    let text = std::fs::read_to_string(&path).unwrap();
+    let mut bytes = Vec::new();
+    for line in text.lines() {
+        let line = line.split('#').next().unwrap();
+        for token in line.split_whitespace() {
+            let (byte, count) = match token.split_once('x') {
+                Some((byte, count)) => (byte, count.parse().unwrap()),
+                None => (token, 1),
+            };
+            let byte = u8::from_str_radix(byte, 16).unwrap();
+            bytes.extend(std::iter::repeat(byte).take(count));
+        }
+    }
+    bytes
+}
+
+/// Answers the handshake of a single client until the script says to stop.
+async fn mock_server(socket: UdpSocket, script: Script) {
+    let mut buf = [0u8; 2048];
+    loop {
+        let (_, client) = socket.recv_from(&mut buf).await.unwrap();
+        let reply = match buf[0] {
+            _ if script == Script::Blackhole => continue,
+            _ if script == Script::JunkOnly => JUNK.to_vec(),
+            0x01 => fixture("unconnected_pong"),
+            _ if script == Script::Junk => JUNK.to_vec(),
+            0x05 if script == Script::SilentOpen1 => continue,
+            0x05 if script == Script::Incompatible => RakPacket::from(
+                OfflinePacket::IncompatibleProtocolVersion(IncompatibleProtocolVersion {
+                    protocol: 10,
+                    magic: Magic::new(),
+                    server_id: 1,
+                }),
+            )
+            .write_to_bytes()
+            .unwrap()
+            .as_slice()
+            .to_vec(),
+            0x05 => fixture("open_connection_reply_1"),
+            0x07 if script == Script::SilentOpen2 => continue,
+            0x07 => {
+                let mut reply = fixture("open_connection_reply_2");
+                if script == Script::WrongMtu {
+                    // the mtu is followed by the security flag.
+                    let at = reply.len() - 3;
+                    reply[at..at + 2].copy_from_slice(&576u16.to_be_bytes());
+                }
+                reply
+            }
+            // the connection request is never accepted.
+            _ => continue,
+        };
+        socket.send_to(&reply, client).await.unwrap();
+    }
+}
+
+async fn connect(port: u16, script: Script) -> ClientError {
+    let address = format!("127.0.0.1:{}", port);
+    let socket = UdpSocket::bind(&address).await.unwrap();
+    task::spawn(mock_server(socket, script));
+
+    let client = Client::new(11, 1400);
+    let result = timeout(Duration::from_secs(30), client.connect(address.as_str()))
+        .await
+        .expect("the handshake never gave up");
+    match result {
+        Err(error) => error,
+        result => panic!("the handshake ended with {:?}", result),
+    }
+}
+
+async fn handshake(port: u16, script: Script) -> HandshakeError {
+    match connect(port, script).await {
+        ClientError::Handshake(error) => error,
+        error => panic!("the handshake ended with {:?}", error),
+    }
+}
+
+#[test]
+fn test_open1_timeout() {
+    task::block_on(async {
+        let error = handshake(19192, Script::SilentOpen1).await;
+        match error {
+            HandshakeError::TimedOut {
+                last_stage, mtu, ..
+            } => {
+                assert_eq!(last_stage, Stage::Open1);
+                // every step of the ladder was tried, down to the smallest.
+                assert_eq!(mtu, 576);
+            }
+            error => panic!("{:?}", error),
+        }
+        assert_eq!(error.timings().unwrap().attempts, [6, 0, 0]);
+        assert!(error.advice().contains("connection requests"));
+    });
+}
+
+#[test]
+fn test_open2_timeout() {
+    task::block_on(async {
+        let error = handshake(19193, Script::SilentOpen2).await;
+        assert_eq!(
+            error,
+            HandshakeError::TimedOut {
+                last_stage: Stage::Open2,
+                mtu: 1400,
+                timings: error.timings().unwrap(),
+            }
+        );
+        let timings = error.timings().unwrap();
+        assert_eq!(timings.attempts, [1, 1, 0]);
+        assert!(timings.elapsed(Stage::Open2) >= Duration::from_secs(1));
+        assert!(error.advice().contains("retry with a smaller MTU"));
+    });
+}
+
+#[test]
+fn test_connect_timeout() {
+    task::block_on(async {
+        let error = handshake(19194, Script::SilentConnect).await;
+        assert_eq!(error.stage(), Stage::Connect);
+        assert!(matches!(error, HandshakeError::TimedOut { .. }));
+        assert_eq!(error.timings().unwrap().attempts, [1, 1, 5]);
+        assert!(error.advice().contains("never accepted the connection"));
+    });
+}
+
+#[test]
+fn test_rejected_and_incompatible() {
+    task::block_on(async {
+        let error = handshake(19195, Script::WrongMtu).await;
+        assert!(matches!(
+            error,
+            HandshakeError::Rejected {
+                stage: Stage::Open2,
+                ..
+            }
+        ));
+        assert!(error.advice().contains("something unexpected"));
+
+        let error = handshake(19196, Script::Incompatible).await;
+        assert_eq!(error, HandshakeError::IncompatibleVersion);
+        assert!(error.advice().contains("protocol version"));
+    });
+}
+
+#[test]
+fn test_no_inbound_traffic() {
+    task::block_on(async {
+        let error = handshake(19202, Script::Blackhole).await;
+        assert_eq!(
+            error,
+            HandshakeError::NoInboundTraffic {
+                probes: PING_PROBES
+            }
+        );
+        assert!(PING_PROBES >= 3);
+        assert!(error.timings().is_none());
+        assert!(error.advice().contains("NAT or firewall"));
+    });
+}
+
+#[test]
+fn test_junk_is_not_silence() {
+    task::block_on(async {
+        // anything that came back proves the replies get through.
+        let error = handshake(19203, Script::Junk).await;
+        assert!(matches!(
+            error,
+            HandshakeError::TimedOut {
+                last_stage: Stage::Open1,
+                ..
+            }
+        ));
+    });
+}
+
+#[test]
+fn test_junk_instead_of_pong() {
+    task::block_on(async {
+        assert_eq!(
+            connect(19204, Script::JunkOnly).await,
+            ClientError::ServerOffline
+        );
+    });
+}
@@ -0,0 +1,200 @@
+#![cfg(all(feature = "async_std", feature = "server"))]
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use async_std::{future::timeout, net::UdpSocket, task};
+use binary_util::interfaces::{Reader, Writer};
+use rak_rs::connection::controller::handshake::HandshakeGuard;
+use rak_rs::protocol::frame::{Frame, FramePacket};
+use rak_rs::protocol::index::{DatagramSeq, MessageIndex};
+use rak_rs::protocol::packet::offline::{OfflinePacket, SessionInfoRequest};
+use rak_rs::protocol::packet::online::{
+    Capabilities, ConnectionAccept, ConnectionRequest, NewConnection, OnlinePacket,
+};
+use rak_rs::protocol::packet::RakPacket;
+use rak_rs::protocol::Magic;
+use rak_rs::{Listener, RakEvent, Reliability};
+
+const ADDRESS: &str = "127.0.0.1:19181";
+
+fn accept(timestamp: i64) -> ConnectionAccept {
+    ConnectionAccept {
+        client_address: "127.0.0.1:1".parse().unwrap(),
+        system_index: 0,
+        internal_ids: Vec::new(),
+        request_time: 0,
+        timestamp,
+        capabilities: Capabilities::NONE,
+    }
+}
+
+#[test]
+fn test_guard_is_idempotent() {
+    let mut guard = HandshakeGuard::new();
+
+    let (first, fresh) = guard.request(accept(1), false);
+    assert!(fresh);
+    assert_eq!(first.timestamp, 1);
+
+    // a retransmitted request is answered with the first accept.
+    let (again, fresh) = guard.request(accept(2), false);
+    assert!(!fresh);
+    assert_eq!(again.timestamp, 1);
+
+    assert!(guard.complete(false));
+    assert!(!guard.complete(true));
+    assert!(!guard.complete(true));
+    assert_eq!(guard.duplicates(), 3);
+}
+
+/// A peer that speaks just enough RakNet to retransmit its handshake.
+struct MockPeer {
+    socket: UdpSocket,
+    sequence: u32,
+}
+
+impl MockPeer {
+    async fn send(&mut self, body: &[u8]) {
+        let mut frame = Frame::new(Reliability::Reliable, Some(body));
+        frame.reliable_index = Some(MessageIndex::new(self.sequence));
+
+        let mut datagram = FramePacket::new();
+        datagram.sequence = DatagramSeq::new(self.sequence);
+        datagram.frames.push(frame);
+        self.sequence += 1;
+
+        let buf = datagram.write_to_bytes().unwrap();
+        self.socket.send(buf.as_slice()).await.unwrap();
+    }
+
+    /// Receives frames until one matches.
+    async fn recv_frame(&self, matches: impl Fn(&[u8]) -> bool) -> Frame {
+        let mut buf = [0u8; 2048];
+        loop {
+            let len = timeout(Duration::from_secs(5), self.socket.recv(&mut buf))
+                .await
+                .expect("the server never answered")
+                .unwrap();
+            if let Ok(datagram) = FramePacket::read_from_slice(&buf[..len]) {
+                for frame in datagram.frames {
+                    if matches(&frame.body) {
+                        return frame;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn request() -> Vec<u8> {
+    OnlinePacket::from(ConnectionRequest {
+        client_id: 7,
+        time: 1,
+        security: false,
+        capabilities: Capabilities::NONE,
+    })
+    .write_to_bytes()
+    .unwrap()
+    .as_slice()
+    .to_vec()
+}
+
+fn new_connection(server: SocketAddr) -> Vec<u8> {
+    OnlinePacket::from(NewConnection {
+        server_address: server,
+        system_address: vec![server; 10],
+        request_time: 1,
+        timestamp: 1,
+    })
+    .write_to_bytes()
+    .unwrap()
+    .as_slice()
+    .to_vec()
+}
+
+fn is_accept(body: &[u8]) -> bool {
+    matches!(
+        OnlinePacket::read_from_slice(body),
+        Ok(OnlinePacket::ConnectionAccept(_))
+    )
+}
+
+#[test]
+fn test_retransmitted_handshake_is_ignored() {
+    task::block_on(async {
+        let mut server = Listener::bind(ADDRESS).await.unwrap();
+        server.start().await.unwrap();
+        let server_address: SocketAddr = ADDRESS.parse().unwrap();
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        socket.connect(ADDRESS).await.unwrap();
+        let session = RakPacket::from(OfflinePacket::SessionInfoRequest(SessionInfoRequest {
+            magic: Magic::new(),
+            address: server_address,
+            mtu_size: 1400,
+            client_id: 7,
+            capabilities: Capabilities::NONE,
+        }));
+        socket
+            .send(session.write_to_bytes().unwrap().as_slice())
+            .await
+            .unwrap();
+        let mut peer = MockPeer {
+            socket,
+            sequence: 0,
+        };
+
+        let mut conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+
+        peer.send(&request()).await;
+        let first_accept = peer.recv_frame(is_accept).await;
+        peer.send(&new_connection(server_address)).await;
+
+        let event = timeout(Duration::from_secs(5), conn.recv_event())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(event, RakEvent::Connected));
+
+        conn.send(&[0xfe, 0x00], true).await.unwrap();
+        let before = peer.recv_frame(|body| body == [0xfe, 0x00]).await;
+
+        // the server's acknowledgements got lost, so the peer sends both again.
+        // waiting makes sure a new accept would carry a different timestamp.
+        task::sleep(Duration::from_millis(1100)).await;
+        peer.send(&request()).await;
+        let second_accept = peer.recv_frame(is_accept).await;
+        assert_eq!(
+            first_accept.body, second_accept.body,
+            "a retransmitted request should get the same accept"
+        );
+        peer.send(&new_connection(server_address)).await;
+
+        // the connection is still established, and picks up where it was.
+        conn.send(&[0xfe, 0x01], true).await.unwrap();
+        let after = peer.recv_frame(|body| body == [0xfe, 0x01]).await;
+        assert_eq!(
+            after.order_index.unwrap().get(),
+            before.order_index.unwrap().get() + 1
+        );
+
+        peer.send(&[0xfe]).await;
+        let payload = timeout(Duration::from_secs(5), conn.recv())
+            .await
+            .expect("the connection stopped delivering")
+            .unwrap();
+        assert_eq!(payload, vec![0xfe]);
+        assert!(!conn.is_closed().await);
+
+        assert!(
+            timeout(Duration::from_millis(200), conn.recv_event())
+                .await
+                .is_err(),
+            "the handshake should only complete once"
+        );
+        assert_eq!(conn.stats().await.duplicate_handshakes, 2);
+    });
+}
@@ -0,0 +1,107 @@
+#![cfg(feature = "async_std")]
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_std::{future::timeout, net::UdpSocket, task};
+use binary_util::interfaces::Reader;
+use rak_rs::connection::queue::SendQueue;
+use rak_rs::protocol::ack::{Ack, Ackable};
+use rak_rs::protocol::frame::FramePacket;
+use rak_rs::protocol::index::{DatagramSeq, MessageIndex};
+use rak_rs::protocol::ranges::SequenceRanges;
+use rak_rs::protocol::reliability::Reliability;
+
+async fn recv_datagram(peer: &UdpSocket) -> Option<FramePacket> {
+    let mut buf = [0u8; 2048];
+    let len = timeout(Duration::from_millis(300), peer.recv(&mut buf))
+        .await
+        .ok()?
+        .unwrap();
+    Some(FramePacket::read_from_slice(&buf[..len]).unwrap())
+}
+
+#[test]
+fn test_ack_releases_the_acknowledged_datagram() {
+    task::block_on(async {
+        let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let mut send = SendQueue::new(1400, 0, 0, socket, peer.local_addr().unwrap());
+
+        // the unreliable datagrams move the datagram sequence ahead of the reliable index.
+        for _ in 0..2 {
+            send.insert(&[0xfe, 0], Reliability::Unreliable, true, None)
+                .await
+                .unwrap();
+            recv_datagram(&peer).await.expect("nothing was sent");
+        }
+        send.insert(&[0xfe, 1], Reliability::Reliable, true, None)
+            .await
+            .unwrap();
+        let reliable = recv_datagram(&peer).await.expect("nothing was sent");
+        assert_eq!(reliable.frames[0].body, vec![0xfe, 1]);
+
+        let mut ranges = SequenceRanges::new();
+        ranges.insert(reliable.sequence.into());
+        send.ack(Ack::from_ranges(&ranges, false));
+        send.update().await;
+
+        while let Some(datagram) = recv_datagram(&peer).await {
+            assert!(
+                datagram.frames.iter().all(|f| f.body != vec![0xfe, 1]),
+                "an acknowledged datagram was resent"
+            );
+        }
+    });
+}
+
+#[test]
+fn test_sequence_index_belongs_to_the_channel() {
+    task::block_on(async {
+        let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let mut send = SendQueue::new(1400, 0, 0, socket, peer.local_addr().unwrap());
+
+        // the first sequenced frame of two fresh channels, with datagrams sent in between.
+        let mut firsts = Vec::new();
+        for channel in [2, 3] {
+            for _ in 0..3 {
+                send.insert(&[0xfe, 0], Reliability::Unreliable, true, None)
+                    .await
+                    .unwrap();
+                recv_datagram(&peer).await.expect("nothing was sent");
+            }
+            send.insert(&[0xfe, 1], Reliability::UnreliableSeq, true, Some(channel))
+                .await
+                .unwrap();
+            let datagram = recv_datagram(&peer).await.expect("nothing was sent");
+            firsts.push(datagram.frames[0].sequence_index);
+        }
+
+        assert!(firsts[0].is_some());
+        assert_eq!(
+            firsts[0], firsts[1],
+            "the sequence index followed the datagram sequence"
+        );
+    });
+}
+
+#[test]
+fn test_indexes_start_at_zero() {
+    task::block_on(async {
+        let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let mut send = SendQueue::new(1400, 0, 0, socket, peer.local_addr().unwrap());
+
+        send.insert(&[0xfe, 0], Reliability::Reliable, true, None)
+            .await
+            .unwrap();
+        let datagram = recv_datagram(&peer).await.expect("nothing was sent");
+
+        // the peer's windows start at zero, anything else reads as a lost datagram.
+        assert_eq!(datagram.sequence, DatagramSeq::new(0));
+        assert_eq!(
+            datagram.frames[0].reliable_index,
+            Some(MessageIndex::new(0))
+        );
+    });
+}
@@ -0,0 +1,253 @@
+//! Interoperability tests against a reference RakNet implementation.
+//!
+//! These only run with the `interop-tests` feature, and skip themselves unless
+//! `RAKNET_REFERENCE_BIN` points at a reference echo server:
+//!
+//! ```sh
+//! RAKNET_REFERENCE_BIN=bundled RAKNET_REFERENCE_CLIENT_BIN=bundled \
+//!     cargo test --features interop-tests --test interop
+//! ```
+//!
+//! `bundled` runs `tests/interop/reference.py`, any other value is executed directly
+//! (or with `python3` if it ends in `.py`). A reference binary is expected to accept:
+//!
+//! - `server --port <port>`: echo every user packet (id `>= 0x86`) back reliable ordered,
+//!   and print a line starting with `DISCONNECTED` when a peer disconnects.
+//! - `client --address <addr> --scenario <name>`: run a scenario against our echo server,
+//!   exiting with `0` if it passed. The scenarios are `handshake`, `ordered`, `split`,
+//!   `keepalive` and `disconnect`. This one is optional, set `RAKNET_REFERENCE_CLIENT_BIN`.
+#![cfg(feature = "async_std")]
+mod reference;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_std::future::timeout;
+use async_std::net::UdpSocket;
+use async_std::task;
+use rak_rs::connection::event::DisconnectReason;
+use rak_rs::{Client, Listener, RakEvent, Reliability};
+
+use reference::{idle_time, run_client, ReferenceServer, CLIENT_ENV, SERVER_ENV};
+
+const MESSAGES: u32 = 1000;
+const SPLIT_SIZE: usize = 5 * 1024 * 1024;
+
+macro_rules! reference_server {
+    ($port: expr) => {
+        match ReferenceServer::start($port) {
+            Some(server) => server,
+            None => {
+                eprintln!("skipping, {} is not set", SERVER_ENV);
+                return;
+            }
+        }
+    };
+}
+
+/// Waits until the reference answers unconnected pings.
+async fn wait_until_up(address: &str) {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    socket.connect(address).await.unwrap();
+    let socket = Arc::new(socket);
+
+    for _ in 0..50 {
+        if let Ok(Ok(_)) = timeout(Duration::from_millis(200), Client::ping(socket.clone())).await {
+            return;
+        }
+    }
+    panic!("the reference server at {} never came up", address);
+}
+
+async fn connect(address: &str) -> Client {
+    wait_until_up(address).await;
+    let client = Client::new(10, 1400);
+    timeout(Duration::from_secs(10), client.connect(address))
+        .await
+        .expect("connect timed out")
+        .expect("failed to connect to the reference server");
+    client
+}
+
+/// Fails if the connection reported a disconnect.
+async fn assert_connected(client: &Client) {
+    if let Ok(Ok(RakEvent::Disconnected { reason })) =
+        timeout(Duration::from_millis(10), client.recv_event()).await
+    {
+        panic!("disconnected by the reference server: {:?}", reason);
+    }
+}
+
+/// A deterministic payload, so corruption anywhere in the transfer is caught.
+fn split_payload() -> Vec<u8> {
+    let mut payload = Vec::with_capacity(SPLIT_SIZE);
+    payload.push(0xfe);
+    let mut state: u32 = 0x9e37_79b9;
+    while payload.len() < SPLIT_SIZE {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        payload.push(state as u8);
+    }
+    payload
+}
+
+#[test]
+fn interop_client_handshake_and_disconnect() {
+    let server = reference_server!(19170);
+    task::block_on(async {
+        let client = connect(&server.address).await;
+        assert_connected(&client).await;
+        client.close().await;
+    });
+    assert!(
+        server.wait_for_line("DISCONNECTED", Duration::from_secs(5)),
+        "the reference server never saw our disconnect"
+    );
+}
+
+#[test]
+fn interop_client_reliable_ordered() {
+    let server = reference_server!(19171);
+    task::block_on(async {
+        let client = connect(&server.address).await;
+
+        for i in 0..MESSAGES {
+            let mut buf = vec![0xfe];
+            buf.extend_from_slice(&i.to_be_bytes());
+            client
+                .send(&buf, Reliability::ReliableOrd, 0)
+                .await
+                .unwrap();
+        }
+
+        for i in 0..MESSAGES {
+            let packet = timeout(Duration::from_secs(30), client.recv())
+                .await
+                .expect("echo timed out")
+                .unwrap();
+            assert_eq!(packet[0], 0xfe);
+            assert_eq!(u32::from_be_bytes(packet[1..5].try_into().unwrap()), i);
+        }
+
+        assert_connected(&client).await;
+        client.close().await;
+    });
+}
+
+#[test]
+fn interop_client_split_transfer() {
+    let server = reference_server!(19172);
+    task::block_on(async {
+        let client = connect(&server.address).await;
+        let payload = split_payload();
+
+        client
+            .send(&payload, Reliability::ReliableOrd, 0)
+            .await
+            .unwrap();
+
+        let echoed = timeout(Duration::from_secs(120), client.recv())
+            .await
+            .expect("split echo timed out")
+            .unwrap();
+        assert!(echoed == payload, "the split payload was corrupted");
+
+        assert_connected(&client).await;
+        client.close().await;
+    });
+}
+
+#[test]
+fn interop_client_keepalive() {
+    let server = reference_server!(19173);
+    task::block_on(async {
+        let client = connect(&server.address).await;
+
+        task::sleep(idle_time()).await;
+        assert_connected(&client).await;
+
+        client
+            .send(&[0xfe, 0x01], Reliability::ReliableOrd, 0)
+            .await
+            .unwrap();
+        let echoed = timeout(Duration::from_secs(5), client.recv())
+            .await
+            .expect("the connection did not survive being idle")
+            .unwrap();
+        assert_eq!(echoed, vec![0xfe, 0x01]);
+
+        client.close().await;
+    });
+}
+
+/// Runs our echo server and points the reference client at it.
+fn run_against_server(port: u16, scenario: &str, limit: Duration) {
+    if std::env::var_os(CLIENT_ENV).is_none() {
+        eprintln!("skipping, {} is not set", CLIENT_ENV);
+        return;
+    }
+
+    let address = format!("127.0.0.1:{}", port);
+    let disconnected = task::block_on(async {
+        let mut server = Listener::bind(address.as_str()).await.unwrap();
+        server.start().await.unwrap();
+
+        let echo = task::spawn(async move {
+            let mut conn = server.accept().await.unwrap();
+            while let Ok(packet) = conn.recv().await {
+                conn.send(&packet, false).await.unwrap();
+            }
+            loop {
+                match conn.recv_event().await {
+                    Ok(RakEvent::Connected) => continue,
+                    event => break event.ok(),
+                }
+            }
+        });
+
+        let client_address = address.clone();
+        let scenario = scenario.to_string();
+        let passed =
+            task::spawn_blocking(move || run_client(&client_address, &scenario, limit)).await;
+        assert_eq!(passed, Some(true), "the reference client failed");
+
+        timeout(Duration::from_secs(5), echo).await
+    });
+
+    assert!(
+        matches!(
+            disconnected,
+            Ok(Some(RakEvent::Disconnected {
+                reason: DisconnectReason::Closed
+            })) | Ok(None)
+        ),
+        "the connection did not close cleanly: {:?}",
+        disconnected
+    );
+}
+
+#[test]
+fn interop_server_handshake() {
+    run_against_server(19175, "handshake", Duration::from_secs(30));
+}
+
+#[test]
+fn interop_server_reliable_ordered() {
+    run_against_server(19176, "ordered", Duration::from_secs(60));
+}
+
+#[test]
+fn interop_server_split_transfer() {
+    run_against_server(19177, "split", Duration::from_secs(180));
+}
+
+#[test]
+fn interop_server_keepalive() {
+    run_against_server(19178, "keepalive", idle_time() + Duration::from_secs(30));
+}
+
+#[test]
+fn interop_server_disconnect() {
+    run_against_server(19179, "disconnect", Duration::from_secs(30));
+}
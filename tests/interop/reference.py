@@ -0,0 +1,605 @@
+#!/usr/bin/env python3
+"""A tiny RakNet peer used as the bundled reference for the interop tests.
+
+It only uses the python standard library, so it runs anywhere a `python3` is available,
+and implements the wire protocol as the original RakNet does (offline handshake,
+datagram sequencing, ACK/NACK, reliable ordered frames and split packets), independently
+of rak-rs.
+
+    reference.py server --port 19132
+        An echo server, every user packet (id >= 0x86) is sent back reliable ordered.
+        Prints `READY <port>` once listening and `DISCONNECTED <address>` when a peer leaves.
+
+    reference.py client --address 127.0.0.1:19132 --scenario ordered
+        Connects to an echo server and runs a scenario, exits with 0 if it passed.
+"""
+
+import argparse
+import os
+import random
+import socket
+import struct
+import sys
+import time
+
+MAGIC = bytes.fromhex("00ffff00fefefefefdfdfdfd12345678")
+PROTOCOL = 10
+MTU = 1492
+# IP header + UDP header
+UDP_HEADER = 28
+# datagram header + the largest frame header
+FRAME_OVERHEAD = 4 + 23
+
+ID_CONNECTED_PING = 0x00
+ID_UNCONNECTED_PING = 0x01
+ID_CONNECTED_PONG = 0x03
+ID_OPEN_CONNECTION_REQUEST_1 = 0x05
+ID_OPEN_CONNECTION_REPLY_1 = 0x06
+ID_OPEN_CONNECTION_REQUEST_2 = 0x07
+ID_OPEN_CONNECTION_REPLY_2 = 0x08
+ID_CONNECTION_REQUEST = 0x09
+ID_CONNECTION_REQUEST_ACCEPTED = 0x10
+ID_NEW_INCOMING_CONNECTION = 0x13
+ID_DISCONNECTION_NOTIFICATION = 0x15
+ID_UNCONNECTED_PONG = 0x1C
+ID_NACK = 0xA0
+ID_ACK = 0xC0
+ID_USER_PACKET_ENUM = 0x86
+
+UNRELIABLE = 0
+RELIABLE = 2
+RELIABLE_ORDERED = 3
+
+PING_INTERVAL = 5.0
+TIMEOUT = 10.0
+RESEND_AFTER = 0.5
+# the amount of datagrams that may be unacknowledged at once
+WINDOW = 512
+
+SCENARIO_MESSAGES = 1000
+SCENARIO_SPLIT_SIZE = 5 * 1024 * 1024
+SCENARIO_IDLE = float(os.environ.get("RAKNET_INTEROP_IDLE_SECS", "30"))
+
+
+def now_ms():
+    return int(time.time() * 1000)
+
+
+def u24(value):
+    return struct.pack("<I", value & 0xFFFFFF)[:3]
+
+
+def read_u24(data, offset):
+    return data[offset] | data[offset + 1] << 8 | data[offset + 2] << 16
+
+
+def encode_address(address):
+    host, port = address[0], address[1]
+    if ":" in host:
+        return (
+            b"\x06"
+            + struct.pack("<H", socket.AF_INET6)
+            + struct.pack(">HI", port, 0)
+            + socket.inet_pton(socket.AF_INET6, host)
+            + struct.pack(">I", 0)
+        )
+    return b"\x04" + bytes(~b & 0xFF for b in socket.inet_aton(host)) + struct.pack(">H", port)
+
+
+def address_size(data, offset):
+    return 29 if data[offset] == 6 else 7
+
+
+def encode_ranges(sequences):
+    """Encodes an ACK/NACK body the way RakNet does, as ranges of sequences."""
+    records = []
+    sequences = sorted(sequences)
+    start = prev = sequences[0]
+    for seq in sequences[1:] + [None]:
+        if seq is not None and seq == prev + 1:
+            prev = seq
+            continue
+        if start == prev:
+            records.append(b"\x01" + u24(start))
+        else:
+            records.append(b"\x00" + u24(start) + u24(prev))
+        if seq is not None:
+            start = prev = seq
+    return struct.pack(">H", len(records)) + b"".join(records)
+
+
+def decode_ranges(data):
+    count = struct.unpack_from(">H", data, 1)[0]
+    offset = 3
+    for _ in range(count):
+        single = data[offset]
+        start = read_u24(data, offset + 1)
+        if single:
+            end = start
+            offset += 4
+        else:
+            end = read_u24(data, offset + 4)
+            offset += 7
+        for seq in range(min(start, end), max(start, end) + 1):
+            yield seq
+
+
+class Frame:
+    __slots__ = ("reliability", "reliable_index", "order_index", "channel", "split", "body")
+
+    def __init__(self, reliability, body, channel=0):
+        self.reliability = reliability
+        self.reliable_index = None
+        self.order_index = None
+        self.channel = channel
+        self.split = None
+        self.body = body
+
+    def encode(self):
+        flags = self.reliability << 5
+        if self.split is not None:
+            flags |= 0x10
+        out = bytearray(struct.pack(">BH", flags, len(self.body) * 8))
+        if self.reliability in (RELIABLE, RELIABLE_ORDERED):
+            out += u24(self.reliable_index)
+        if self.reliability == RELIABLE_ORDERED:
+            out += u24(self.order_index) + bytes([self.channel])
+        if self.split is not None:
+            out += struct.pack(">IHI", *self.split)
+        out += self.body
+        return bytes(out)
+
+    @staticmethod
+    def decode_all(data):
+        frames = []
+        offset = 4
+        while offset < len(data):
+            flags = data[offset]
+            bits = struct.unpack_from(">H", data, offset + 1)[0]
+            offset += 3
+            frame = Frame(flags >> 5, b"")
+            if frame.reliability in (2, 3, 4, 6, 7):
+                frame.reliable_index = read_u24(data, offset)
+                offset += 3
+            if frame.reliability in (1, 4):
+                offset += 3
+            if frame.reliability in (1, 3, 4, 7):
+                frame.order_index = read_u24(data, offset)
+                frame.channel = data[offset + 3]
+                offset += 4
+            if flags & 0x10:
+                frame.split = struct.unpack_from(">IHI", data, offset)
+                offset += 10
+            length = (bits + 7) // 8
+            frame.body = bytes(data[offset : offset + length])
+            offset += length
+            frames.append(frame)
+        return frames
+
+
+class Peer:
+    """The reliability layer of a single connection."""
+
+    def __init__(self, sock, address, mtu, guid):
+        self.sock = sock
+        self.address = address
+        self.mtu = mtu
+        self.guid = guid
+        self.connected = False
+        self.closed = False
+        self.last_recv = time.monotonic()
+        self.last_ping = time.monotonic()
+
+        self.send_seq = 0
+        self.reliable_index = 0
+        self.order_index = {}
+        self.split_id = 0
+        self.outgoing = []
+        self.recovery = {}
+
+        self.acks = set()
+        self.nacks = set()
+        self.expected_seq = 0
+        self.received_reliable = set()
+        self.lowest_reliable = 0
+        self.splits = {}
+        self.order_expected = {}
+        self.order_held = {}
+        self.delivered = []
+
+    # sending
+
+    def send(self, body, reliability=RELIABLE_ORDERED, channel=0):
+        max_body = self.mtu - UDP_HEADER - FRAME_OVERHEAD
+        if len(body) <= max_body:
+            parts = [body]
+        else:
+            max_body -= 10
+            parts = [body[i : i + max_body] for i in range(0, len(body), max_body)]
+            # split packets are always sent reliable ordered.
+            reliability = RELIABLE_ORDERED
+
+        order_index = None
+        if reliability == RELIABLE_ORDERED:
+            order_index = self.order_index.get(channel, 0)
+            self.order_index[channel] = order_index + 1
+        split_id = self.split_id
+        if len(parts) > 1:
+            self.split_id = (self.split_id + 1) & 0xFFFF
+
+        for index, part in enumerate(parts):
+            frame = Frame(reliability, part, channel)
+            if frame.reliability != UNRELIABLE:
+                frame.reliable_index = self.reliable_index
+                self.reliable_index += 1
+            if frame.reliability == RELIABLE_ORDERED:
+                frame.order_index = order_index
+            if len(parts) > 1:
+                frame.split = (len(parts), split_id, index)
+            self.outgoing.append(frame)
+
+    def send_datagram(self, frame):
+        seq = self.send_seq
+        self.send_seq = (self.send_seq + 1) & 0xFFFFFF
+        self.sock.sendto(b"\x84" + u24(seq) + frame.encode(), self.address)
+        if frame.reliability != UNRELIABLE:
+            self.recovery[seq] = (time.monotonic(), frame)
+
+    def tick(self):
+        now = time.monotonic()
+
+        if self.acks:
+            self.sock.sendto(bytes([ID_ACK]) + encode_ranges(self.acks), self.address)
+            self.acks.clear()
+        if self.nacks:
+            self.sock.sendto(bytes([ID_NACK]) + encode_ranges(self.nacks), self.address)
+
+        # resend anything that took too long, under a new datagram sequence like RakNet does.
+        for seq, (sent, frame) in list(self.recovery.items()):
+            if now - sent > RESEND_AFTER:
+                del self.recovery[seq]
+                self.outgoing.insert(0, frame)
+
+        while self.outgoing and len(self.recovery) < WINDOW:
+            self.send_datagram(self.outgoing.pop(0))
+
+        if self.connected and now - self.last_ping > PING_INTERVAL:
+            self.last_ping = now
+            self.send(struct.pack(">Bq", ID_CONNECTED_PING, now_ms()), UNRELIABLE)
+
+    def pending(self):
+        return len(self.outgoing) + len(self.recovery)
+
+    # receiving
+
+    def on_datagram(self, data):
+        self.last_recv = time.monotonic()
+        pid = data[0]
+
+        if pid & 0x40:
+            for seq in decode_ranges(data):
+                self.recovery.pop(seq, None)
+            return
+        if pid & 0x20:
+            for seq in decode_ranges(data):
+                entry = self.recovery.pop(seq, None)
+                if entry is not None:
+                    self.outgoing.insert(0, entry[1])
+            return
+
+        seq = read_u24(data, 1)
+        self.acks.add(seq)
+        self.nacks.discard(seq)
+        if seq > self.expected_seq:
+            self.nacks.update(range(self.expected_seq, seq))
+        if seq >= self.expected_seq:
+            self.expected_seq = seq + 1
+
+        for frame in Frame.decode_all(data):
+            self.on_frame(frame)
+
+    def on_frame(self, frame):
+        if frame.reliable_index is not None:
+            if (
+                frame.reliable_index < self.lowest_reliable
+                or frame.reliable_index in self.received_reliable
+            ):
+                return
+            self.received_reliable.add(frame.reliable_index)
+            while self.lowest_reliable in self.received_reliable:
+                self.received_reliable.discard(self.lowest_reliable)
+                self.lowest_reliable += 1
+
+        body = frame.body
+        if frame.split is not None:
+            count, split_id, index = frame.split
+            parts = self.splits.setdefault(split_id, {})
+            parts[index] = body
+            if len(parts) < count:
+                return
+            body = b"".join(parts[i] for i in range(count))
+            del self.splits[split_id]
+
+        if frame.order_index is None:
+            self.delivered.append(body)
+            return
+
+        channel = frame.channel
+        expected = self.order_expected.get(channel, 0)
+        if frame.order_index < expected:
+            return
+        held = self.order_held.setdefault(channel, {})
+        held[frame.order_index] = body
+        while expected in held:
+            self.delivered.append(held.pop(expected))
+            expected += 1
+        self.order_expected[channel] = expected
+
+    def take(self):
+        delivered, self.delivered = self.delivered, []
+        return delivered
+
+
+def offline_pong(ping, guid):
+    motd = b"rak-rs interop reference"
+    return (
+        bytes([ID_UNCONNECTED_PONG])
+        + ping[1:9]
+        + struct.pack(">Q", guid)
+        + MAGIC
+        + struct.pack(">H", len(motd))
+        + motd
+    )
+
+
+def run_server(port):
+    sock = socket.socket(socket.AF_INET, socket.SOCK_DGRAM)
+    sock.bind(("127.0.0.1", port))
+    sock.settimeout(0.01)
+    guid = random.getrandbits(63)
+    peers = {}
+
+    print("READY", sock.getsockname()[1], flush=True)
+
+    while True:
+        try:
+            data, address = sock.recvfrom(4096)
+        except socket.timeout:
+            data = None
+
+        if data:
+            pid = data[0]
+            peer = peers.get(address)
+
+            if pid in (ID_UNCONNECTED_PING, 0x02):
+                sock.sendto(offline_pong(data, guid), address)
+            elif pid == ID_OPEN_CONNECTION_REQUEST_1:
+                mtu = min(len(data) + UDP_HEADER, MTU)
+                sock.sendto(
+                    bytes([ID_OPEN_CONNECTION_REPLY_1])
+                    + MAGIC
+                    + struct.pack(">QBH", guid, 0, mtu),
+                    address,
+                )
+            elif pid == ID_OPEN_CONNECTION_REQUEST_2:
+                offset = 17 + address_size(data, 17)
+                mtu = min(struct.unpack_from(">H", data, offset)[0], MTU)
+                peers[address] = Peer(sock, address, mtu, guid)
+                sock.sendto(
+                    bytes([ID_OPEN_CONNECTION_REPLY_2])
+                    + MAGIC
+                    + struct.pack(">Q", guid)
+                    + encode_address(address)
+                    + struct.pack(">HB", mtu, 0),
+                    address,
+                )
+            elif peer is not None and pid & 0x80:
+                peer.on_datagram(data)
+                for body in peer.take():
+                    server_message(peer, body)
+
+        for address, peer in list(peers.items()):
+            if peer.closed:
+                del peers[address]
+                continue
+            if time.monotonic() - peer.last_recv > TIMEOUT:
+                print("TIMEOUT", address, flush=True)
+                peer.send(bytes([ID_DISCONNECTION_NOTIFICATION]), RELIABLE_ORDERED)
+                peer.tick()
+                del peers[address]
+                continue
+            peer.tick()
+
+
+def server_message(peer, body):
+    pid = body[0]
+    if pid == ID_CONNECTED_PING:
+        peer.send(struct.pack(">Bqq", ID_CONNECTED_PONG, struct.unpack_from(">q", body, 1)[0], now_ms()), UNRELIABLE)
+    elif pid == ID_CONNECTION_REQUEST:
+        request_time = struct.unpack_from(">q", body, 9)[0]
+        internal = encode_address(("255.255.255.255", 0)) * 10
+        peer.send(
+            bytes([ID_CONNECTION_REQUEST_ACCEPTED])
+            + encode_address(peer.address)
+            + struct.pack(">h", 0)
+            + internal
+            + struct.pack(">qq", request_time, now_ms()),
+            RELIABLE_ORDERED,
+        )
+    elif pid == ID_NEW_INCOMING_CONNECTION:
+        peer.connected = True
+        print("CONNECTED", peer.address, flush=True)
+    elif pid == ID_DISCONNECTION_NOTIFICATION:
+        peer.closed = True
+        print("DISCONNECTED", peer.address, flush=True)
+    elif pid >= ID_USER_PACKET_ENUM:
+        peer.send(body, RELIABLE_ORDERED)
+
+
+class Client:
+    def __init__(self, address):
+        host, port = address.rsplit(":", 1)
+        self.remote = (host, int(port))
+        self.sock = socket.socket(socket.AF_INET, socket.SOCK_DGRAM)
+        self.sock.settimeout(0.01)
+        self.guid = random.getrandbits(63)
+        self.peer = None
+        self.disconnected = False
+
+    def offline(self, packet, expect, timeout=5.0):
+        deadline = time.monotonic() + timeout
+        last = 0
+        while time.monotonic() < deadline:
+            if time.monotonic() - last > 0.5:
+                self.sock.sendto(packet, self.remote)
+                last = time.monotonic()
+            try:
+                data, _ = self.sock.recvfrom(4096)
+            except socket.timeout:
+                continue
+            if data[0] == expect:
+                return data
+        raise RuntimeError("no reply to offline packet 0x%02x" % packet[0])
+
+    def connect(self):
+        request = bytes([ID_OPEN_CONNECTION_REQUEST_1]) + MAGIC + bytes([PROTOCOL])
+        request += bytes(MTU - UDP_HEADER - len(request))
+        reply = self.offline(request, ID_OPEN_CONNECTION_REPLY_1)
+        mtu = struct.unpack_from(">H", reply, 26)[0]
+
+        request = (
+            bytes([ID_OPEN_CONNECTION_REQUEST_2])
+            + MAGIC
+            + encode_address(self.remote)
+            + struct.pack(">Hq", mtu, self.guid)
+        )
+        self.offline(request, ID_OPEN_CONNECTION_REPLY_2)
+
+        self.peer = Peer(self.sock, self.remote, mtu, self.guid)
+        self.peer.send(struct.pack(">Bqq?", ID_CONNECTION_REQUEST, self.guid, now_ms(), False))
+
+        deadline = time.monotonic() + 5
+        while time.monotonic() < deadline:
+            for body in self.poll():
+                if body[0] == ID_CONNECTION_REQUEST_ACCEPTED:
+                    offset = 1 + address_size(body, 1) + 2
+                    addresses = []
+                    while len(body) - offset > 16:
+                        addresses.append(body[offset : offset + address_size(body, offset)])
+                        offset += address_size(body, offset)
+                    request_time, server_time = struct.unpack_from(">qq", body, offset)
+                    self.peer.send(
+                        bytes([ID_NEW_INCOMING_CONNECTION])
+                        + encode_address(self.remote)
+                        + b"".join(addresses)
+                        + struct.pack(">qq", server_time, now_ms())
+                    )
+                    self.peer.connected = True
+                    return
+        raise RuntimeError("the server did not accept the connection")
+
+    def poll(self):
+        """Pumps the socket once, returns the user packets that were delivered."""
+        try:
+            data, address = self.sock.recvfrom(4096)
+            if address == self.remote and data[0] & 0x80:
+                self.peer.on_datagram(data)
+        except socket.timeout:
+            pass
+        except ConnectionError:
+            pass
+
+        self.peer.tick()
+        out = []
+        for body in self.peer.take():
+            if body[0] == ID_CONNECTED_PING:
+                self.peer.send(
+                    struct.pack(">Bqq", ID_CONNECTED_PONG, struct.unpack_from(">q", body, 1)[0], now_ms()),
+                    UNRELIABLE,
+                )
+            elif body[0] == ID_DISCONNECTION_NOTIFICATION:
+                self.disconnected = True
+            elif body[0] != ID_CONNECTED_PONG:
+                out.append(body)
+        return out
+
+    def expect(self, count, timeout):
+        received = []
+        deadline = time.monotonic() + timeout
+        while len(received) < count and time.monotonic() < deadline:
+            if self.disconnected:
+                raise RuntimeError("the server disconnected us")
+            received.extend(self.poll())
+        return received
+
+    def disconnect(self):
+        self.peer.send(bytes([ID_DISCONNECTION_NOTIFICATION]))
+        deadline = time.monotonic() + 2
+        while self.peer.pending() and time.monotonic() < deadline:
+            self.poll()
+
+
+def check(condition, message):
+    if not condition:
+        raise RuntimeError(message)
+
+
+def run_client(address, scenario):
+    # built before connecting, the connection is not serviced while this runs.
+    payload = bytes([0xFE]) + random.Random(7).randbytes(SCENARIO_SPLIT_SIZE - 1)
+
+    client = Client(address)
+    client.connect()
+
+    if scenario == "ordered":
+        for i in range(SCENARIO_MESSAGES):
+            client.peer.send(struct.pack(">BI", 0xFE, i))
+        received = client.expect(SCENARIO_MESSAGES, 30)
+        expected = [struct.pack(">BI", 0xFE, i) for i in range(SCENARIO_MESSAGES)]
+        check(
+            received == expected,
+            "echoed messages are missing or out of order, got %d, first mismatch at %r"
+            % (len(received), next((i for i, (a, b) in enumerate(zip(received, expected)) if a != b), None)),
+        )
+    elif scenario == "split":
+        client.peer.send(payload)
+        received = client.expect(1, 120)
+        check(
+            received == [payload],
+            "the split payload was not echoed intact, got %r" % [len(body) for body in received],
+        )
+    elif scenario == "keepalive":
+        deadline = time.monotonic() + SCENARIO_IDLE
+        while time.monotonic() < deadline:
+            received = client.poll()
+            check(not received, "unexpected packets while idle: %r" % received)
+            check(not client.disconnected, "the server disconnected us while idle")
+        client.peer.send(b"\xfe\x01")
+        check(client.expect(1, 5) == [b"\xfe\x01"], "the connection did not survive being idle")
+    elif scenario not in ("handshake", "disconnect"):
+        raise RuntimeError("unknown scenario " + scenario)
+
+    client.disconnect()
+
+
+def main():
+    parser = argparse.ArgumentParser()
+    parser.add_argument("mode", choices=["server", "client"])
+    parser.add_argument("--port", type=int, default=19132)
+    parser.add_argument("--address", default="127.0.0.1:19132")
+    parser.add_argument("--scenario", default="handshake")
+    args = parser.parse_args()
+
+    if args.mode == "server":
+        run_server(args.port)
+    else:
+        try:
+            run_client(args.address, args.scenario)
+        except RuntimeError as e:
+            print("FAILED", e, file=sys.stderr, flush=True)
+            sys.exit(1)
+        print("PASSED", args.scenario, flush=True)
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,127 @@
+//! Finding, starting and talking to the reference implementation.
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Points at the reference echo server, `bundled` uses `tests/interop/reference.py`.
+pub const SERVER_ENV: &str = "RAKNET_REFERENCE_BIN";
+/// Points at the reference client, `bundled` uses `tests/interop/reference.py`.
+pub const CLIENT_ENV: &str = "RAKNET_REFERENCE_CLIENT_BIN";
+/// The python interpreter used for `.py` references, defaults to `python3`.
+pub const PYTHON_ENV: &str = "RAKNET_REFERENCE_PYTHON";
+/// How long the keepalive scenario stays idle, in seconds.
+pub const IDLE_ENV: &str = "RAKNET_INTEROP_IDLE_SECS";
+
+/// The command to run a reference binary, or `None` if the variable is not set.
+fn command(var: &str) -> Option<Command> {
+    let bin = std::env::var_os(var)?;
+    let bin = if bin == "bundled" {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/interop/reference.py")
+    } else {
+        PathBuf::from(bin)
+    };
+
+    if bin.extension().is_some_and(|ext| ext == "py") {
+        let python = std::env::var_os(PYTHON_ENV).unwrap_or_else(|| "python3".into());
+        let mut cmd = Command::new(python);
+        cmd.arg(bin);
+        Some(cmd)
+    } else {
+        Some(Command::new(bin))
+    }
+}
+
+/// How long the keepalive scenario idles for.
+pub fn idle_time() -> Duration {
+    let secs = std::env::var(IDLE_ENV)
+        .ok()
+        .and_then(|secs| secs.parse::<u64>().ok())
+        .unwrap_or(30);
+    Duration::from_secs(secs)
+}
+
+/// A running reference echo server, killed when dropped.
+pub struct ReferenceServer {
+    child: Child,
+    lines: Arc<Mutex<Vec<String>>>,
+    pub address: String,
+}
+
+impl ReferenceServer {
+    /// Starts the reference echo server on the given port, returns `None` when
+    /// [`SERVER_ENV`] is not set.
+    pub fn start(port: u16) -> Option<Self> {
+        let mut cmd = command(SERVER_ENV)?;
+        let mut child = cmd
+            .args(["server", "--port", &port.to_string()])
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("failed to start the reference server");
+
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let stdout = child.stdout.take().unwrap();
+        let output = lines.clone();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                output.lock().unwrap().push(line);
+            }
+        });
+
+        Some(Self {
+            child,
+            lines,
+            address: format!("127.0.0.1:{}", port),
+        })
+    }
+
+    /// Waits until the server printed a line starting with `prefix`.
+    pub fn wait_for_line(&self, prefix: &str, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            if self
+                .lines
+                .lock()
+                .unwrap()
+                .iter()
+                .any(|line| line.starts_with(prefix))
+            {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        false
+    }
+}
+
+impl Drop for ReferenceServer {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Runs a scenario of the reference client against `address`, returns `None` when
+/// [`CLIENT_ENV`] is not set, otherwise whether the scenario passed.
+pub fn run_client(address: &str, scenario: &str, timeout: Duration) -> Option<bool> {
+    let mut cmd = command(CLIENT_ENV)?;
+    let mut child = cmd
+        .args(["client", "--address", address, "--scenario", scenario])
+        .env(IDLE_ENV, idle_time().as_secs().to_string())
+        .spawn()
+        .expect("failed to start the reference client");
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            return Some(status.success());
+        }
+        if Instant::now() > deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Some(false);
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,205 @@
+#![cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_std::{future::timeout, net::UdpSocket, task};
+use binary_util::interfaces::{Reader, Writer};
+use rak_rs::connection::queue::SendQueue;
+use rak_rs::connection::state::ConnectionState;
+use rak_rs::protocol::ack::{Ack, Ackable};
+use rak_rs::protocol::frame::FramePacket;
+use rak_rs::protocol::packet::online::{ConnectedPing, OnlinePacket};
+use rak_rs::protocol::ranges::SequenceRanges;
+use rak_rs::{Client, Listener, Reliability};
+
+const INTERVAL: Duration = Duration::from_millis(100);
+
+fn ping() -> Vec<u8> {
+    OnlinePacket::ConnectedPing(ConnectedPing { time: 1 })
+        .write_to_bytes()
+        .unwrap()
+        .as_slice()
+        .to_vec()
+}
+
+fn is_ping(body: &[u8]) -> bool {
+    body.first() == Some(&0x00)
+}
+
+async fn mock() -> (SendQueue, UdpSocket) {
+    let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+    let queue = SendQueue::new(1400, 12000, 5, socket, peer.local_addr().unwrap());
+    (queue, peer)
+}
+
+/// Every frame set the peer received so far.
+async fn received(peer: &UdpSocket) -> Vec<FramePacket> {
+    let mut datagrams = Vec::new();
+    let mut buf = [0u8; 2048];
+    while let Ok(Ok(len)) = timeout(Duration::from_millis(50), peer.recv(&mut buf)).await {
+        datagrams.push(FramePacket::read_from_slice(&buf[..len]).unwrap());
+    }
+    datagrams
+}
+
+fn standalone_pings(datagrams: &[FramePacket]) -> usize {
+    datagrams
+        .iter()
+        .filter(|pk| pk.frames.len() == 1 && is_ping(&pk.frames[0].body))
+        .count()
+}
+
+#[test]
+fn test_streaming_needs_no_pings() {
+    task::block_on(async {
+        let (mut queue, peer) = mock().await;
+
+        for i in 0..30u8 {
+            queue
+                .insert(&[0xfe, i], Reliability::ReliableOrd, false, None)
+                .await
+                .unwrap();
+            assert!(!queue.keepalive(&ping(), INTERVAL).await);
+            queue.update().await;
+            task::sleep(Duration::from_millis(20)).await;
+        }
+
+        let datagrams = received(&peer).await;
+        assert_eq!(datagrams.len(), 30);
+        assert!(datagrams
+            .iter()
+            .all(|pk| pk.frames.iter().all(|f| !is_ping(&f.body))));
+
+        // after a pause, the ping rides along with the next payload.
+        task::sleep(INTERVAL).await;
+        queue
+            .insert(&[0xfe, 0xff], Reliability::ReliableOrd, false, None)
+            .await
+            .unwrap();
+        assert!(queue.keepalive(&ping(), INTERVAL).await);
+        queue.update().await;
+
+        let datagrams = received(&peer).await;
+        assert_eq!(datagrams.len(), 1);
+        assert_eq!(datagrams[0].frames.len(), 2);
+        assert_eq!(datagrams[0].frames[0].body, vec![0xfe, 0xff]);
+        assert!(is_ping(&datagrams[0].frames[1].body));
+    });
+}
+
+#[test]
+fn test_keepalive_is_not_resent() {
+    task::block_on(async {
+        let (mut queue, peer) = mock().await;
+
+        task::sleep(INTERVAL).await;
+        queue
+            .insert(&[0xfe, 1], Reliability::ReliableOrd, false, None)
+            .await
+            .unwrap();
+        assert!(queue.keepalive(&ping(), INTERVAL).await);
+        queue.update().await;
+        let datagrams = received(&peer).await;
+        assert_eq!(datagrams[0].frames.len(), 2);
+
+        let mut ranges = SequenceRanges::new();
+        ranges.insert(datagrams[0].sequence.into());
+        let lost = queue.nack(Ack::from_ranges(&ranges, true));
+        queue.resend(lost).await;
+
+        // only the payload is recovered, the keepalive was for the first send.
+        let resent = received(&peer).await;
+        assert_eq!(resent.len(), 1);
+        assert_eq!(resent[0].sequence, datagrams[0].sequence);
+        assert_eq!(resent[0].frames.len(), 1);
+        assert_eq!(resent[0].frames[0].body, vec![0xfe, 1]);
+    });
+}
+
+#[test]
+fn test_idle_connection_pings() {
+    task::block_on(async {
+        let (mut queue, peer) = mock().await;
+
+        for _ in 0..10 {
+            task::sleep(Duration::from_millis(50)).await;
+            queue.keepalive(&ping(), INTERVAL).await;
+            queue.update().await;
+        }
+
+        let datagrams = received(&peer).await;
+        let pings = standalone_pings(&datagrams);
+        assert_eq!(pings, datagrams.len());
+        assert!((3..=6).contains(&pings), "{} pings were sent", pings);
+    });
+}
+
+#[test]
+fn test_streaming_server_stays_alive_without_pings() {
+    task::block_on(async {
+        let mut server = Listener::bind("127.0.0.1:19200").await.unwrap();
+        server.start().await.unwrap();
+
+        // forwards everything, counting the pings the server sends on their own.
+        let front = Arc::new(UdpSocket::bind("127.0.0.1:19199").await.unwrap());
+        let back = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        back.connect("127.0.0.1:19200").await.unwrap();
+        let pings = Arc::new(AtomicUsize::new(0));
+        let (client_address, ready) = async_std::channel::bounded(1);
+        {
+            let (front, back) = (front.clone(), back.clone());
+            task::spawn(async move {
+                let mut buf = [0u8; 2048];
+                let mut known = false;
+                loop {
+                    let (len, origin) = front.recv_from(&mut buf).await.unwrap();
+                    if !known {
+                        client_address.send(origin).await.unwrap();
+                        known = true;
+                    }
+                    back.send(&buf[..len]).await.unwrap();
+                }
+            });
+        }
+        {
+            let pings = pings.clone();
+            task::spawn(async move {
+                let client = ready.recv().await.unwrap();
+                let mut buf = [0u8; 2048];
+                loop {
+                    let len = back.recv(&mut buf).await.unwrap();
+                    if buf[0] & 0xf0 == 0x80 {
+                        if let Ok(pk) = FramePacket::read_from_slice(&buf[..len]) {
+                            if standalone_pings(&[pk]) > 0 {
+                                pings.fetch_add(1, Ordering::Relaxed);
+                            }
+                        }
+                    }
+                    front.send_to(&buf[..len], client).await.unwrap();
+                }
+            });
+        }
+
+        let client = Client::new(11, 1400);
+        timeout(Duration::from_secs(10), client.connect("127.0.0.1:19199"))
+            .await
+            .expect("connect timed out")
+            .expect("failed to connect");
+        let conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+        let before = pings.load(Ordering::Relaxed);
+
+        // well past the keepalive interval of the server, while the client sends nothing.
+        for i in 0..40u8 {
+            conn.send(&[0xfe, i], false).await.unwrap();
+            task::sleep(Duration::from_millis(100)).await;
+        }
+
+        assert_eq!(pings.load(Ordering::Relaxed), before);
+        assert_eq!(*conn.state.lock().await, ConnectionState::Connected);
+    });
+}
@@ -0,0 +1,233 @@
+//! Every buffer of a connection is charged to a single ledger, which escalates from dropping
+//! unreliable messages, to refusing sends, to giving up on the peer.
+#![cfg(feature = "async_std")]
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_std::{future::timeout, net::UdpSocket, task};
+use binary_util::interfaces::Reader;
+use rak_rs::connection::controller::budget::{BudgetShare, SendBudget};
+use rak_rs::connection::controller::memory::{cost, MemBudget, ENTRY_OVERHEAD};
+use rak_rs::connection::event::DisconnectReason;
+use rak_rs::connection::queue::{RecvQueue, SendQueue, SendQueueError};
+use rak_rs::protocol::ack::{Ack, Ackable};
+use rak_rs::protocol::frame::{FragmentMeta, Frame, FramePacket};
+use rak_rs::protocol::index::{DatagramSeq, MessageIndex, OrderIndex, SplitId};
+use rak_rs::protocol::ranges::SequenceRanges;
+use rak_rs::protocol::RAKNET_HEADER_OVERHEAD;
+use rak_rs::Reliability;
+
+fn packet(sequence: u32, frame: Frame) -> FramePacket {
+    let mut packet = FramePacket::new();
+    packet.sequence = DatagramSeq::new(sequence);
+    packet.frames.push(frame);
+    packet
+}
+
+/// Fragment `index` of a message of 1000 byte fragments, ordered at `order`.
+fn fragment(sequence: u32, id: u16, size: u32, index: u32, order: u32) -> FramePacket {
+    let mut frame = Frame::new(Reliability::ReliableOrd, Some(&[index as u8; 1000]));
+    frame.reliable_index = Some(MessageIndex::new(sequence));
+    frame.order_channel = Some(0);
+    frame.order_index = Some(OrderIndex::new(order));
+    frame.fragment_meta = Some(FragmentMeta::new(size, SplitId::new(id), index));
+    packet(sequence, frame)
+}
+
+fn ordered(sequence: u32, order: u32, len: usize) -> FramePacket {
+    let mut frame = Frame::new(Reliability::ReliableOrd, Some(&vec![0xfe; len]));
+    frame.reliable_index = Some(MessageIndex::new(sequence));
+    frame.order_channel = Some(0);
+    frame.order_index = Some(OrderIndex::new(order));
+    packet(sequence, frame)
+}
+
+#[test]
+fn test_recv_ledger_follows_buffers() {
+    let budget = MemBudget::new(1024 * 1024);
+    let mut queue = RecvQueue::new();
+    queue.set_memory_budget(budget.clone());
+
+    // three of the four fragments of the first message.
+    for index in 0..3 {
+        queue.insert(fragment(index, 1, 4, index, 0)).unwrap();
+    }
+    assert_eq!(budget.used(), 3 * cost(1000));
+
+    // the message after it waits in its order channel.
+    queue.insert(ordered(3, 1, 200)).unwrap();
+    assert!(queue.flush().is_empty());
+    assert_eq!(budget.used(), 3 * cost(1000) + cost(200));
+    assert_eq!(queue.memory_charged(), budget.used());
+
+    // the last fragment releases both, they are held until flushed.
+    queue.insert(fragment(4, 1, 4, 3, 0)).unwrap();
+    assert_eq!(budget.used(), cost(4000) + cost(200));
+
+    let ready = queue.flush();
+    assert_eq!(ready.len(), 2);
+    assert_eq!(budget.used(), 0);
+    assert!(!queue.exceeds_budget());
+}
+
+#[test]
+fn test_recv_over_budget_drops_unreliable_then_exceeds() {
+    let budget = MemBudget::new(4096);
+    let mut queue = RecvQueue::new();
+    queue.set_memory_budget(budget.clone());
+
+    for index in 0..3 {
+        queue.insert(fragment(index, 1, 100, index, 0)).unwrap();
+    }
+    queue
+        .insert(packet(3, Frame::new(Reliability::Unreliable, Some(&[1]))))
+        .unwrap();
+    assert_eq!(queue.flush(), vec![vec![1]]);
+    assert!(!queue.exceeds_budget());
+
+    // fragments that never complete are all the peer has to send.
+    for index in 3..5 {
+        queue.insert(fragment(index + 1, 1, 100, index, 0)).unwrap();
+    }
+    // the reliable indexes past the unreliable datagram are held by the reliable window.
+    assert_eq!(budget.used(), 5 * cost(1000) + 2 * ENTRY_OVERHEAD);
+
+    // unreliable messages are dropped first, the fragments can't be.
+    queue
+        .insert(packet(6, Frame::new(Reliability::Unreliable, Some(&[2]))))
+        .unwrap();
+    assert!(queue.flush().is_empty());
+    assert!(queue.exceeds_budget());
+}
+
+#[test]
+fn test_fragments_are_credited_as_charged() {
+    let budget = MemBudget::new(1024 * 1024);
+    let mut queue = RecvQueue::new();
+    queue.set_memory_budget(budget.clone());
+
+    queue.insert(ordered(0, 1, 200)).unwrap();
+    queue.insert(fragment(1, 1, 2, 0, 0)).unwrap();
+    // the last fragment claims the message has far more of them than the first did.
+    queue.insert(fragment(2, 1, 100, 1, 0)).unwrap();
+
+    assert_eq!(queue.memory_charged(), cost(2000) + cost(200));
+    assert_eq!(queue.flush().len(), 2);
+    assert_eq!(budget.used(), 0);
+}
+
+#[test]
+fn test_gaps_are_charged() {
+    let budget = MemBudget::new(1024 * 1024);
+    let mut queue = RecvQueue::new();
+    queue.set_memory_budget(budget.clone());
+
+    // every other datagram is missing, each one held past the window start and asked for.
+    for sequence in (0..=20).step_by(2) {
+        queue
+            .insert(packet(
+                sequence,
+                Frame::new(Reliability::Unreliable, Some(&[1])),
+            ))
+            .unwrap();
+    }
+    assert_eq!(queue.flush().len(), 11);
+    assert_eq!(queue.nack_queue().range_count(), 10);
+    assert_eq!(budget.used(), 20 * ENTRY_OVERHEAD);
+
+    for sequence in (1..20).step_by(2) {
+        queue
+            .insert(packet(
+                sequence,
+                Frame::new(Reliability::Unreliable, Some(&[1])),
+            ))
+            .unwrap();
+    }
+    assert_eq!(queue.flush().len(), 10);
+    assert!(queue.nack_queue().is_empty());
+    assert_eq!(budget.used(), 0);
+}
+
+#[test]
+fn test_resource_abuse_round_trips() {
+    let packet = DisconnectReason::ResourceAbuse.to_packet();
+    assert_eq!(
+        DisconnectReason::from_packet(&packet),
+        Some(DisconnectReason::ResourceAbuse)
+    );
+}
+
+#[test]
+fn test_send_drops_unreliable_before_refusing() {
+    task::block_on(async {
+        let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let mut send = SendQueue::new(1400, 0, 0, socket, peer.local_addr().unwrap());
+        let budget = MemBudget::new(8192);
+        send.set_memory_budget(budget.clone());
+        // with a send budget, unreliable messages wait for the tick like the others.
+        let shared = Arc::new(Mutex::new(SendBudget::new(1000, 0, Instant::now())));
+        send.set_send_budget(Some(BudgetShare::new(shared)));
+        let stats = send.shared_stats();
+
+        for _ in 0..4 {
+            send.reserve(1000).unwrap();
+            send.insert(&[0xfe; 1000], Reliability::Unreliable, false, None)
+                .await
+                .unwrap();
+        }
+        for _ in 0..3 {
+            send.reserve(1000).unwrap();
+            send.insert(&[0xfe; 1000], Reliability::ReliableOrd, false, None)
+                .await
+                .unwrap();
+        }
+        assert_eq!(budget.used(), 7 * cost(1000));
+
+        // the next payload doesn't fit until the unreliable ones are gone.
+        send.reserve(1000).unwrap();
+        assert_eq!(budget.used(), 3 * cost(1000));
+        assert_eq!(stats.unreliable_dropped.load(Ordering::Relaxed), 4);
+
+        for _ in 0..4 {
+            send.insert(&[0xfe; 1000], Reliability::ReliableOrd, false, None)
+                .await
+                .unwrap();
+        }
+        assert_eq!(send.reserve(1000), Err(SendQueueError::OutOfBudget));
+        assert_eq!(budget.used(), 7 * cost(1000));
+        assert_eq!(send.memory_charged(), budget.used());
+    });
+}
+
+#[test]
+fn test_recovery_is_credited_on_ack() {
+    task::block_on(async {
+        let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let mut send = SendQueue::new(1400, 0, 0, socket, peer.local_addr().unwrap());
+        let budget = MemBudget::new(1024 * 1024);
+        send.set_memory_budget(budget.clone());
+
+        // split in three, every fragment waits for its acknowledgement.
+        send.insert(&[0xfe; 3000], Reliability::ReliableOrd, true, None)
+            .await
+            .unwrap();
+        let mut ranges = SequenceRanges::new();
+        let mut buf = [0u8; 2048];
+        let mut charged = 0;
+        while let Ok(Ok(len)) = timeout(Duration::from_millis(50), peer.recv(&mut buf)).await {
+            let datagram = FramePacket::read_from_slice(&buf[..len]).unwrap();
+            ranges.insert(datagram.sequence.into());
+            let frame = &datagram.frames[0];
+            charged +=
+                cost(frame.header_len() + frame.body.len() + RAKNET_HEADER_OVERHEAD as usize);
+        }
+        assert_eq!(ranges.len(), 3);
+        assert_eq!(budget.used(), charged);
+
+        send.ack(Ack::from_ranges(&ranges, false));
+        assert_eq!(budget.used(), 0);
+    });
+}
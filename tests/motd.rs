@@ -0,0 +1,91 @@
+use binary_util::interfaces::{Reader, Writer};
+use binary_util::ByteReader;
+use rak_rs::mcpe::motd::Gamemode;
+use rak_rs::Motd;
+
+/// A pong from third-party server software, with fields after the IPv6 port.
+const THIRD_PARTY: &str = "MCPE;§bSkyblock §7| §aPlay now;662;1.20.71;87;500;\
+    13253860892328930865;Hub 3;Creative;1;19132;19133;0;Nukkit-PetteriM1;region=eu;";
+
+#[test]
+fn test_third_party_extension_fields() {
+    let motd = Motd::parse(THIRD_PARTY).unwrap();
+
+    assert_eq!(motd.name, "§bSkyblock §7| §aPlay now");
+    assert_eq!(motd.protocol, 662);
+    assert_eq!(motd.player_count, 87);
+    assert_eq!(motd.server_guid, 13253860892328930865);
+    assert_eq!(motd.sub_motd, "Hub 3");
+    assert_eq!(motd.gamemode, Gamemode::Creative);
+    assert_eq!(motd.nintendo_limited, Some(false));
+    assert_eq!(motd.port, "19132");
+    assert_eq!(
+        motd.extra_fields,
+        vec!["0", "Nukkit-PetteriM1", "region=eu", ""]
+    );
+
+    // re-advertising the pong keeps it intact.
+    assert_eq!(motd.write(), THIRD_PARTY);
+}
+
+/// A xorshift generator, so every run covers the same MOTDs.
+struct Gen(u32);
+
+impl Gen {
+    fn next(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0
+    }
+
+    fn string(&mut self) -> String {
+        const ALPHABET: &[char] = &['a', 'Z', '7', ' ', ';', '\\', '§', '|', '='];
+        let len = self.next() % 12;
+        (0..len)
+            .map(|_| ALPHABET[self.next() as usize % ALPHABET.len()])
+            .collect()
+    }
+
+    fn motd(&mut self) -> Motd {
+        let mut motd = Motd::new(
+            self.next() as u64 * self.next() as u64,
+            self.next().to_string(),
+        );
+        motd.name = self.string();
+        motd.protocol = self.next() as u16;
+        motd.version = self.string();
+        motd.player_count = self.next();
+        motd.player_max = self.next();
+        motd.gamemode = match self.next() % 4 {
+            0 => Gamemode::Survival,
+            1 => Gamemode::Creative,
+            2 => Gamemode::Adventure,
+            _ => Gamemode::Spectator,
+        };
+        motd.sub_motd = self.string();
+        motd.ipv6_port = self.string();
+        motd.nintendo_limited = match self.next() % 3 {
+            0 => None,
+            1 => Some(true),
+            _ => Some(false),
+        };
+        motd.extra_fields = (0..self.next() % 4).map(|_| self.string()).collect();
+        motd
+    }
+}
+
+#[test]
+fn test_generated_round_trip() {
+    let mut gen = Gen(0x9e37_79b9);
+
+    for _ in 0..2000 {
+        let motd = gen.motd();
+        let written = motd.write();
+        assert_eq!(Motd::parse(&written).unwrap(), motd, "{}", written);
+
+        let bytes = motd.write_to_bytes().unwrap();
+        let read = Motd::read(&mut ByteReader::from(bytes.as_slice())).unwrap();
+        assert_eq!(read, motd);
+    }
+}
@@ -0,0 +1,160 @@
+#![cfg(all(feature = "async_std", feature = "server"))]
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_std::task;
+use rak_rs::server::advertisement::{Advertisement, MOTD_OVERRUN_WARNING};
+use rak_rs::Motd;
+
+const SLOW: Duration = Duration::from_millis(200);
+
+fn named(name: &str) -> Motd {
+    let mut motd = Motd::new(1, "19132");
+    motd.name = name.into();
+    motd
+}
+
+#[test]
+fn test_slow_provider_is_answered_from_cache() {
+    task::block_on(async {
+        let advertisement = Arc::new(Advertisement::new(named("cached")));
+        advertisement.set_provider(Some(Arc::new(|| {
+            std::thread::sleep(SLOW);
+            named("provided")
+        })));
+
+        for _ in 0..MOTD_OVERRUN_WARNING {
+            let started = Instant::now();
+            let motd = advertisement.current(Duration::from_millis(1)).await;
+            assert_eq!(motd.name, "cached");
+            assert!(
+                started.elapsed() < SLOW / 4,
+                "the ping waited on the provider"
+            );
+        }
+        // the first overran, and the others didn't wait for it to finish.
+        assert_eq!(advertisement.overruns(), MOTD_OVERRUN_WARNING as u64);
+
+        // once it is done, what it produced is served to the next pings that overrun.
+        task::sleep(SLOW * 2).await;
+        assert_eq!(advertisement.cached().name, "provided");
+    });
+}
+
+#[test]
+fn test_provider_within_budget_is_served() {
+    task::block_on(async {
+        let pings = Arc::new(AtomicU32::new(0));
+        let advertisement = Arc::new(Advertisement::new(named("cached")));
+        {
+            let pings = pings.clone();
+            advertisement.set_provider(Some(Arc::new(move || {
+                named(&format!("ping {}", pings.fetch_add(1, Ordering::Relaxed)))
+            })));
+        }
+
+        // generous, so a busy test machine can't make it overrun.
+        let budget = Duration::from_secs(2);
+        assert_eq!(advertisement.current(budget).await.name, "ping 0");
+        assert_eq!(advertisement.current(budget).await.name, "ping 1");
+        assert_eq!(advertisement.overruns(), 0);
+
+        advertisement.set_provider(None);
+        assert_eq!(advertisement.current(budget).await.name, "ping 1");
+        assert_eq!(pings.load(Ordering::Relaxed), 2);
+    });
+}
+
+#[cfg(feature = "mcpe")]
+mod listener {
+    use super::*;
+    use async_std::{future::timeout, net::UdpSocket};
+    use binary_util::interfaces::{Reader, Writer};
+    use rak_rs::protocol::packet::offline::{OfflinePacket, UnconnectedPing};
+    use rak_rs::protocol::packet::RakPacket;
+    use rak_rs::protocol::Magic;
+    use rak_rs::Listener;
+
+    /// Pings the listener, returning the name it advertised and how long it took to answer.
+    async fn ping(socket: &UdpSocket) -> (String, Duration) {
+        let ping = RakPacket::from(OfflinePacket::UnconnectedPing(UnconnectedPing {
+            timestamp: 0,
+            magic: Magic::new(),
+            client_id: 7,
+        }));
+        let started = Instant::now();
+        socket
+            .send(ping.write_to_bytes().unwrap().as_slice())
+            .await
+            .unwrap();
+
+        let mut buf = [0u8; 2048];
+        let len = timeout(Duration::from_secs(5), socket.recv(&mut buf))
+            .await
+            .expect("the ping was never answered")
+            .unwrap();
+        match OfflinePacket::read_from_slice(&buf[..len]).unwrap() {
+            OfflinePacket::UnconnectedPong(pong) => (pong.motd.name, started.elapsed()),
+            pk => panic!("answered with {:?}", pk),
+        }
+    }
+
+    async fn pinger(address: &str) -> UdpSocket {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        socket.connect(address).await.unwrap();
+        socket
+    }
+
+    #[test]
+    fn test_pings_are_answered_while_the_provider_is_slow() {
+        task::block_on(async {
+            const ADDRESS: &str = "127.0.0.1:19210";
+            let mut server = Listener::bind(ADDRESS).await.unwrap();
+            server.set_motd(named("cached"));
+            server.set_motd_provider(|| {
+                std::thread::sleep(SLOW);
+                named("provided")
+            });
+            server.start().await.unwrap();
+
+            let socket = pinger(ADDRESS).await;
+            for _ in 0..5 {
+                let (name, latency) = ping(&socket).await;
+                assert_eq!(name, "cached");
+                assert!(latency < SLOW / 4, "the ping waited {:?}", latency);
+            }
+            assert_eq!(server.stats().motd_overruns, 5);
+        });
+    }
+
+    #[test]
+    fn test_refresher_pushes_the_motd() {
+        task::block_on(async {
+            const ADDRESS: &str = "127.0.0.1:19211";
+            let mut server = Listener::bind(ADDRESS).await.unwrap();
+            let refreshes = Arc::new(AtomicU32::new(0));
+            {
+                let refreshes = refreshes.clone();
+                server.set_motd_refresher(Duration::from_millis(50), move || {
+                    std::thread::sleep(SLOW);
+                    named(&format!(
+                        "refresh {}",
+                        refreshes.fetch_add(1, Ordering::Relaxed)
+                    ))
+                });
+            }
+            server.start().await.unwrap();
+
+            let socket = pinger(ADDRESS).await;
+            let (_, latency) = ping(&socket).await;
+            assert!(latency < SLOW / 4, "the ping waited {:?}", latency);
+
+            task::sleep(SLOW * 3).await;
+            let (name, latency) = ping(&socket).await;
+            assert!(name.starts_with("refresh "), "advertised {}", name);
+            assert!(latency < SLOW / 4, "the ping waited {:?}", latency);
+            assert_eq!(server.stats().motd_overruns, 0);
+        });
+    }
+}
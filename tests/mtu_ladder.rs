@@ -0,0 +1,135 @@
+#![cfg(all(feature = "async_std", feature = "server"))]
+//! A client that probes the mtu upwards during the offline handshake gets the largest one it
+//! asked for, and the mtu doesn't move anymore once the session exists.
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use async_std::{future::timeout, net::UdpSocket, task};
+use binary_util::interfaces::{Reader, Writer};
+use rak_rs::protocol::packet::offline::{OfflinePacket, OpenConnectRequest, SessionInfoRequest};
+use rak_rs::protocol::packet::online::Capabilities;
+use rak_rs::protocol::packet::RakPacket;
+use rak_rs::protocol::Magic;
+use rak_rs::server::MAX_MTU_SIZE;
+use rak_rs::Listener;
+
+/// A client driving the offline handshake by hand.
+struct Prober {
+    socket: UdpSocket,
+    server: SocketAddr,
+}
+
+impl Prober {
+    async fn new(server: &str) -> Self {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        socket.connect(server).await.unwrap();
+        Self {
+            socket,
+            server: server.parse().unwrap(),
+        }
+    }
+
+    async fn exchange(&self, packet: OfflinePacket) -> OfflinePacket {
+        let packet = RakPacket::from(packet);
+        self.socket
+            .send(packet.write_to_bytes().unwrap().as_slice())
+            .await
+            .unwrap();
+
+        let mut buf = [0u8; 2048];
+        let len = timeout(Duration::from_secs(5), self.socket.recv(&mut buf))
+            .await
+            .expect("the server never answered")
+            .unwrap();
+        OfflinePacket::read_from_slice(&buf[..len]).unwrap()
+    }
+
+    /// Sends an `OpenConnectRequest` padded to `mtu_size`, returns the mtu of the reply.
+    async fn open(&self, mtu_size: u16) -> u16 {
+        let request = OfflinePacket::OpenConnectRequest(OpenConnectRequest {
+            protocol: 11,
+            mtu_size,
+        });
+        match self.exchange(request).await {
+            OfflinePacket::OpenConnectReply(reply) => reply.mtu_size,
+            pk => panic!("answered with {:?}", pk),
+        }
+    }
+
+    /// Sends a `SessionInfoRequest` with `mtu_size`, returns the mtu of the reply.
+    async fn session(&self, mtu_size: u16) -> u16 {
+        let request = OfflinePacket::SessionInfoRequest(SessionInfoRequest {
+            magic: Magic::new(),
+            address: self.server,
+            mtu_size,
+            client_id: 7,
+            capabilities: Capabilities::NONE,
+        });
+        match self.exchange(request).await {
+            OfflinePacket::SessionInfoReply(reply) => reply.mtu_size,
+            pk => panic!("answered with {:?}", pk),
+        }
+    }
+}
+
+#[test]
+fn test_upward_probes_raise_the_mtu() {
+    task::block_on(async {
+        const ADDRESS: &str = "127.0.0.1:19275";
+        let mut server = Listener::bind(ADDRESS).await.unwrap();
+        server.start().await.unwrap();
+        let prober = Prober::new(ADDRESS).await;
+
+        // the small probe made it, so the client tries larger ones.
+        assert_eq!(prober.open(576).await, 576);
+        assert_eq!(prober.open(1200).await, 1200);
+        assert_eq!(prober.open(1400).await, 1400);
+        // a retransmission of the last probe is answered the same.
+        assert_eq!(prober.open(1400).await, 1400);
+
+        assert_eq!(prober.session(1400).await, 1400);
+        let conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(conn.meta().await.mtu_size, 1400);
+        assert_eq!(server.connections().await[0].mtu_size, 1400);
+    });
+}
+
+#[test]
+fn test_mtu_is_locked_by_the_session() {
+    task::block_on(async {
+        const ADDRESS: &str = "127.0.0.1:19276";
+        let mut server = Listener::bind(ADDRESS).await.unwrap();
+        server.start().await.unwrap();
+        let prober = Prober::new(ADDRESS).await;
+
+        assert_eq!(prober.open(1200).await, 1200);
+        assert_eq!(prober.session(1200).await, 1200);
+        let conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+
+        // a retransmitted request gets the same reply, a revised one doesn't change it.
+        assert_eq!(prober.session(1200).await, 1200);
+        assert_eq!(prober.session(1400).await, 1200);
+        assert_eq!(prober.open(1400).await, 1200);
+
+        assert_eq!(conn.meta().await.mtu_size, 1200);
+        assert_eq!(server.connections().await[0].mtu_size, 1200);
+    });
+}
+
+#[test]
+fn test_mtu_is_clamped() {
+    task::block_on(async {
+        const ADDRESS: &str = "127.0.0.1:19277";
+        let mut server = Listener::bind(ADDRESS).await.unwrap();
+        server.start().await.unwrap();
+        let prober = Prober::new(ADDRESS).await;
+
+        assert_eq!(prober.session(MAX_MTU_SIZE + 500).await, MAX_MTU_SIZE);
+    });
+}
@@ -0,0 +1,282 @@
+use std::time::{Duration, Instant};
+
+use rak_rs::connection::controller::loss::{BlackholeDetector, LossBySize, BLACKHOLE_MIN_SAMPLES};
+use rak_rs::connection::controller::mtu::{MtuProber, MTU_PROBE_TIMEOUT};
+use rak_rs::protocol::index::DatagramSeq;
+
+const INTERVAL: Duration = Duration::from_secs(300);
+
+#[test]
+fn test_probe_raises_mtu_when_path_heals() {
+    let start = Instant::now();
+    let mut prober = MtuProber::new(576, 1506, INTERVAL, start);
+
+    // nothing happens before the interval elapses
+    assert_eq!(prober.poll(start + Duration::from_secs(10)), None);
+
+    let now = start + INTERVAL;
+    assert_eq!(prober.poll(now), Some(1200));
+    prober.sent(DatagramSeq::new(10), 1200, now);
+
+    // the probe is in flight, we shouldn't probe again.
+    assert_eq!(prober.poll(now + Duration::from_millis(500)), None);
+    assert_eq!(
+        prober.ack(DatagramSeq::new(10), now + Duration::from_millis(600)),
+        Some(1200)
+    );
+    assert_eq!(prober.mtu(), 1200);
+
+    // the next step is only probed after another interval.
+    let later = now + Duration::from_millis(600);
+    assert_eq!(prober.poll(later + Duration::from_secs(1)), None);
+    assert_eq!(prober.poll(later + INTERVAL), Some(1400));
+}
+
+#[test]
+fn test_probe_backs_off_when_path_does_not_heal() {
+    let start = Instant::now();
+    let mut prober = MtuProber::new(576, 1506, INTERVAL, start);
+
+    let now = start + INTERVAL;
+    assert_eq!(prober.poll(now), Some(1200));
+    prober.sent(DatagramSeq::new(1), 1200, now);
+
+    // first loss, we retry immediately
+    let now = now + MTU_PROBE_TIMEOUT;
+    assert_eq!(prober.poll(now), Some(1200));
+    prober.sent(DatagramSeq::new(2), 1200, now);
+
+    // second loss, we stay put and back off.
+    let now = now + MTU_PROBE_TIMEOUT;
+    assert_eq!(prober.poll(now), None);
+    assert_eq!(prober.mtu(), 576);
+
+    // a late ack for a lost probe does nothing.
+    assert_eq!(prober.ack(DatagramSeq::new(2), now), None);
+    assert_eq!(prober.mtu(), 576);
+
+    // the interval has doubled.
+    assert_eq!(prober.poll(now + INTERVAL), None);
+    assert_eq!(prober.poll(now + INTERVAL * 2), Some(1200));
+}
+
+#[test]
+fn test_probe_disabled_when_interval_is_zero() {
+    let start = Instant::now();
+    let mut prober = MtuProber::new(576, 1506, Duration::ZERO, start);
+
+    assert!(!prober.is_enabled());
+    assert_eq!(prober.poll(start + Duration::from_secs(3600)), None);
+}
+
+#[test]
+fn test_probe_stops_at_top_of_ladder() {
+    let start = Instant::now();
+    let mut prober = MtuProber::new(1506, 1506, INTERVAL, start);
+
+    assert_eq!(prober.next_step(), None);
+    assert_eq!(prober.poll(start + INTERVAL), None);
+}
+
+#[test]
+fn test_probe_never_exceeds_negotiated_mtu() {
+    let start = Instant::now();
+    let mut prober = MtuProber::new(1400, 1400, INTERVAL, start);
+
+    // a connection at the MTU it negotiated has nothing to probe for.
+    assert_eq!(prober.next_step(), None);
+    assert_eq!(prober.poll(start + INTERVAL * 10), None);
+
+    // a ceiling between two steps is probed as is.
+    let mut prober = MtuProber::new(1200, 1450, INTERVAL, start);
+    let now = start + INTERVAL;
+    assert_eq!(prober.poll(now), Some(1400));
+    prober.sent(DatagramSeq::new(1), 1400, now);
+    prober.ack(DatagramSeq::new(1), now);
+    let now = now + INTERVAL;
+    assert_eq!(prober.poll(now), Some(1450));
+    prober.sent(DatagramSeq::new(2), 1450, now);
+    assert_eq!(prober.ack(DatagramSeq::new(2), now), Some(1450));
+    assert_eq!(prober.next_step(), None);
+}
+
+/// Counts `count` datagrams of `size`, `lost` of them retransmitted.
+fn record(loss: &mut LossBySize, size: usize, count: u64, lost: u64) {
+    for i in 0..count {
+        loss.record_sent(size);
+        if i < lost {
+            loss.record_retransmitted(size);
+        }
+    }
+}
+
+#[test]
+fn test_blackhole_steps_down_and_climbs_back() {
+    let start = Instant::now();
+    let mut prober = MtuProber::new(1400, 1400, INTERVAL, start);
+    let below = prober.step_below().unwrap();
+    assert_eq!(below, 1200);
+
+    // small datagrams get lost as much as large ones, that's no black hole.
+    let mut detector = BlackholeDetector::new();
+    let mut loss = LossBySize::default();
+    record(&mut loss, 600, 20, 12);
+    record(&mut loss, 1400, 20, 12);
+    assert!(!detector.is_blackhole(&loss, below));
+
+    // too few large datagrams were sent to tell.
+    let mut detector = BlackholeDetector::new();
+    let mut loss = LossBySize::default();
+    record(&mut loss, 600, 20, 0);
+    record(
+        &mut loss,
+        1400,
+        BLACKHOLE_MIN_SAMPLES - 1,
+        BLACKHOLE_MIN_SAMPLES - 1,
+    );
+    assert!(!detector.is_blackhole(&loss, below));
+
+    // only the datagrams too large for the step below are lost.
+    record(&mut loss, 1400, 1, 1);
+    assert!(detector.is_blackhole(&loss, below));
+    prober.step_down(below, start);
+    detector.reset();
+    assert_eq!(prober.mtu(), 1200);
+
+    // what was lost before the step down doesn't count towards the next one.
+    assert!(!detector.is_blackhole(&loss, prober.step_below().unwrap()));
+
+    // the path healed, the MTU is probed back up to what was negotiated, and no further.
+    assert_eq!(prober.poll(start + Duration::from_secs(1)), None);
+    let now = start + INTERVAL;
+    assert_eq!(prober.poll(now), Some(1400));
+    prober.sent(DatagramSeq::new(3), 1400, now);
+    assert_eq!(prober.ack(DatagramSeq::new(3), now), Some(1400));
+    assert_eq!(prober.poll(now + INTERVAL * 10), None);
+}
+
+#[test]
+fn test_loss_buckets() {
+    assert_eq!(LossBySize::bucket(0), 0);
+    assert_eq!(LossBySize::bucket(300), 0);
+    assert_eq!(LossBySize::bucket(301), 1);
+    assert_eq!(LossBySize::bucket(1200), 3);
+    assert_eq!(LossBySize::bucket(1400), 4);
+    assert_eq!(LossBySize::bucket(1492), 5);
+
+    let mut loss = LossBySize::default();
+    record(&mut loss, 100, 10, 1);
+    record(&mut loss, 1300, 10, 8);
+    assert_eq!(loss.ratio(0), 0.1);
+    assert_eq!(loss.ratio(4), 0.8);
+    assert_eq!(loss.ratio(2), 0.0);
+    assert_eq!(loss.split(1200), ((10, 0.1), (10, 0.8)));
+
+    let earlier = loss;
+    record(&mut loss, 1300, 2, 2);
+    let since = loss.since(&earlier);
+    assert_eq!(since.sent[4], 2);
+    assert_eq!(since.ratio(4), 1.0);
+    assert_eq!(since.sent[0], 0);
+}
+
+#[test]
+fn test_refused_datagram_steps_down_at_once() {
+    let start = Instant::now();
+    let mut prober = MtuProber::new(1400, 1400, INTERVAL, start);
+
+    // no need to wait for timeouts, the socket said so.
+    assert_eq!(prober.too_large(1350, start), Some(1200));
+    assert_eq!(prober.mtu(), 1200);
+
+    // a route that can't even carry 1100 bytes goes further down in one go.
+    assert_eq!(prober.too_large(1100, start), Some(576));
+    assert_eq!(prober.mtu(), 576);
+    assert_eq!(prober.too_large(500, start), None);
+
+    // probing resumes an interval later.
+    assert_eq!(prober.poll(start + INTERVAL), Some(1200));
+}
+
+#[test]
+fn test_refused_probe_is_just_lost() {
+    let start = Instant::now();
+    let mut prober = MtuProber::new(1200, 1400, INTERVAL, start);
+
+    let now = start + INTERVAL;
+    assert_eq!(prober.poll(now), Some(1400));
+    prober.sent(DatagramSeq::new(3), 1400, now);
+
+    assert_eq!(prober.too_large(1400, now), None);
+    assert_eq!(prober.mtu(), 1200);
+}
+
+#[cfg(feature = "async_std")]
+#[test]
+fn test_probe_raises_the_mtu_of_a_send_queue() {
+    use std::sync::Arc;
+
+    use async_std::{future::timeout, net::UdpSocket, task};
+    use binary_util::interfaces::Reader;
+    use rak_rs::connection::controller::mtu::MTU_PROBE_ID;
+    use rak_rs::connection::controller::rtt::INITIAL_RTO;
+    use rak_rs::connection::queue::{RecvQueue, SendQueue};
+    use rak_rs::protocol::ack::{Ack, Ackable};
+    use rak_rs::protocol::frame::FramePacket;
+    use rak_rs::protocol::ranges::SequenceRanges;
+    use rak_rs::Reliability;
+
+    async fn received(peer: &UdpSocket) -> Vec<(usize, FramePacket)> {
+        let mut datagrams = Vec::new();
+        let mut buf = [0u8; 2048];
+        while let Ok(Ok(len)) = timeout(Duration::from_millis(50), peer.recv(&mut buf)).await {
+            datagrams.push((len, FramePacket::read_from_slice(&buf[..len]).unwrap()));
+        }
+        datagrams
+    }
+
+    task::block_on(async {
+        let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let mut send = SendQueue::new(1400, 0, 0, socket, peer.local_addr().unwrap());
+        send.set_mtu_reprobe_interval(Duration::from_millis(200));
+
+        // the path drops every large datagram, until the MTU steps down.
+        for i in 0..BLACKHOLE_MIN_SAMPLES as u8 {
+            send.insert(&[i; 1300], Reliability::Reliable, true, None)
+                .await
+                .unwrap();
+        }
+        let lost = received(&peer).await;
+        task::sleep(INITIAL_RTO).await;
+        send.update().await;
+        assert_eq!(send.mtu_size(), 1200);
+
+        // the path healed, every datagram gets through and is acknowledged.
+        let mut ranges = SequenceRanges::new();
+        for (_, datagram) in lost.iter().chain(received(&peer).await.iter()) {
+            ranges.insert(datagram.sequence.into());
+        }
+        send.ack(Ack::from_ranges(&ranges, false));
+
+        task::sleep(Duration::from_millis(200)).await;
+        send.update().await;
+        let probes = received(&peer).await;
+        assert_eq!(probes.len(), 1, "no probe was sent");
+        let (len, probe) = &probes[0];
+        assert!(*len > 1200, "the probe is only {} bytes", len);
+        assert_eq!(probe.frames.len(), 1);
+        assert_eq!(probe.frames[0].reliability, Reliability::Unreliable);
+        assert_eq!(probe.frames[0].body[0], MTU_PROBE_ID);
+
+        // the peer acknowledges the probe, but has nothing to hand out.
+        let mut recv = RecvQueue::new();
+        recv.insert(probe.clone()).unwrap();
+        assert!(recv.flush().is_empty(), "the probe was handed out");
+        let acked = recv.ack_flush();
+        assert_eq!(acked.len(), 1);
+
+        send.ack(Ack::from_ranges(&acked, false));
+        assert_eq!(send.mtu_size(), 1400);
+    });
+}
@@ -0,0 +1,114 @@
+#![cfg(all(feature = "async_std", feature = "server"))]
+//! A peer that keeps reaching the listener, but never hears back from it.
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_std::{future::timeout, net::UdpSocket, task};
+use binary_util::interfaces::Writer;
+use rak_rs::connection::controller::one_way::OneWayDetector;
+use rak_rs::connection::event::DisconnectReason;
+use rak_rs::protocol::frame::{Frame, FramePacket};
+use rak_rs::protocol::index::{DatagramSeq, MessageIndex};
+use rak_rs::protocol::packet::offline::{OfflinePacket, SessionInfoRequest};
+use rak_rs::protocol::packet::online::Capabilities;
+use rak_rs::protocol::packet::RakPacket;
+use rak_rs::protocol::Magic;
+use rak_rs::{Listener, RakEvent, Reliability};
+
+#[test]
+fn test_detector_needs_the_peer_to_be_heard() {
+    let mut detector = OneWayDetector::new(Some(Duration::from_secs(5)));
+    let heard = Duration::from_secs(1);
+
+    assert!(!detector.check(None, heard));
+    assert!(!detector.check(Some(Duration::from_secs(4)), heard));
+    assert!(detector.check(Some(Duration::from_secs(5)), heard));
+    // only reported once.
+    assert!(!detector.check(Some(Duration::from_secs(6)), heard));
+    assert!(detector.suspected());
+
+    // the peer going quiet afterwards doesn't lift it, an acknowledgement does.
+    assert!(!detector.check(Some(Duration::from_secs(20)), Duration::from_secs(14)));
+    assert!(detector.suspected());
+    assert!(!detector.check(Some(Duration::from_millis(10)), heard));
+    assert!(!detector.suspected());
+
+    // a peer that wasn't heard from since is just gone.
+    assert!(!detector.check(Some(Duration::from_secs(6)), Duration::from_secs(7)));
+    assert!(!detector.suspected());
+
+    let mut disabled = OneWayDetector::new(None);
+    assert!(!disabled.check(Some(Duration::from_secs(60)), heard));
+}
+
+#[test]
+fn test_one_way_loss_is_reported_before_the_timeout() {
+    task::block_on(async {
+        const ADDRESS: &str = "127.0.0.1:19233";
+        let mut server = Listener::bind(ADDRESS).await.unwrap();
+        server.conn_options.one_way_fail_threshold = Some(Duration::from_millis(500));
+        server.start().await.unwrap();
+        let server_address: SocketAddr = ADDRESS.parse().unwrap();
+
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        socket.connect(ADDRESS).await.unwrap();
+        let session = RakPacket::from(OfflinePacket::SessionInfoRequest(SessionInfoRequest {
+            magic: Magic::new(),
+            address: server_address,
+            mtu_size: 1400,
+            client_id: 7,
+            capabilities: Capabilities::NONE,
+        }));
+        socket
+            .send(session.write_to_bytes().unwrap().as_slice())
+            .await
+            .unwrap();
+        let conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+
+        // the peer keeps sending, but neither reads nor acknowledges anything.
+        let talking = Arc::new(AtomicBool::new(true));
+        let peer = {
+            let (socket, talking) = (socket.clone(), talking.clone());
+            task::spawn(async move {
+                let mut sequence = 0;
+                while talking.load(Ordering::Relaxed) {
+                    let mut frame = Frame::new(Reliability::Reliable, Some(&[0xfe, 0x00]));
+                    frame.reliable_index = Some(MessageIndex::new(sequence));
+                    let mut datagram = FramePacket::new();
+                    datagram.sequence = DatagramSeq::new(sequence);
+                    datagram.frames.push(frame);
+                    sequence += 1;
+                    let buf = datagram.write_to_bytes().unwrap();
+                    socket.send(buf.as_slice()).await.unwrap();
+                    task::sleep(Duration::from_millis(100)).await;
+                }
+            })
+        };
+
+        conn.send(&[0xfe, 0x01], true).await.unwrap();
+        let event = timeout(Duration::from_secs(5), conn.recv_event())
+            .await
+            .expect("the one-way loss was never noticed")
+            .unwrap();
+        assert_eq!(
+            event,
+            RakEvent::SuspectedOneWayLoss {
+                addr: socket.local_addr().unwrap()
+            }
+        );
+        assert!(!conn.is_closed().await);
+
+        // the peer gives up on us.
+        talking.store(false, Ordering::Relaxed);
+        peer.await;
+        let reason = timeout(Duration::from_secs(25), conn.closed())
+            .await
+            .expect("the connection never timed out");
+        assert_eq!(reason, DisconnectReason::TimedOut { one_way: true });
+    });
+}
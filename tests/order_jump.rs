@@ -0,0 +1,145 @@
+//! Ordered and sequenced messages far ahead of their channel are dropped, rather than leaving
+//! everything in between missing.
+use std::time::Instant;
+
+use rak_rs::{
+    connection::{
+        descriptor::ChannelCounters,
+        event::{RakEvent, ViolationKind},
+        options::DEFAULT_ORDER_JUMP_LIMIT,
+        queue::RecvQueue,
+    },
+    protocol::{
+        frame::{Frame, FramePacket},
+        index::{DatagramSeq, OrderIndex, SequenceIndex, U24_MAX},
+        reliability::Reliability,
+    },
+};
+
+fn packet(sequence: u32, frame: Frame) -> FramePacket {
+    let mut packet = FramePacket::new();
+    packet.sequence = DatagramSeq::new(sequence);
+    packet.frames.push(frame);
+    packet
+}
+
+fn ordered(sequence: u32, index: u32) -> FramePacket {
+    let mut frame = Frame::new(Reliability::ReliableOrd, Some(&[index as u8]));
+    frame.order_channel = Some(0);
+    frame.order_index = Some(OrderIndex::new(index));
+    packet(sequence, frame)
+}
+
+fn sequenced(sequence: u32, index: u32) -> FramePacket {
+    let mut frame = Frame::new(Reliability::UnreliableSeq, Some(&[index as u8]));
+    frame.order_channel = Some(0);
+    frame.order_index = Some(OrderIndex::new(0));
+    frame.sequence_index = Some(SequenceIndex::new(index));
+    packet(sequence, frame)
+}
+
+fn violations(queue: &mut RecvQueue) -> usize {
+    queue
+        .flush_events()
+        .iter()
+        .filter(|event| {
+            **event
+                == RakEvent::ProtocolViolation {
+                    kind: ViolationKind::OrderJump,
+                }
+        })
+        .count()
+}
+
+#[test]
+fn test_ordered_jump_is_dropped() {
+    let mut queue = RecvQueue::new();
+
+    queue.insert(ordered(0, 0)).unwrap();
+    assert_eq!(queue.flush(), vec![vec![0]]);
+
+    // expected is 1, a million ahead of it would leave the channel waiting for good.
+    queue.insert(ordered(1, 1_000_001)).unwrap();
+    assert!(queue.flush().is_empty());
+    assert_eq!(queue.order_jumps(), 1);
+    assert_eq!(violations(&mut queue), 1);
+
+    // nothing was buffered, so nothing is missing either.
+    let stats = queue.channel_stats(Instant::now());
+    assert_eq!(stats[0].buffered, 0);
+    assert_eq!(stats[0].head_gap, None);
+
+    // the channel carries on with the messages within the bound.
+    queue.insert(ordered(2, 2)).unwrap();
+    queue.insert(ordered(3, 1)).unwrap();
+    assert_eq!(queue.flush(), vec![vec![1], vec![2]]);
+
+    // the bound is counted from the expected index, which is now 3.
+    let furthest = 3 + DEFAULT_ORDER_JUMP_LIMIT;
+    queue.insert(ordered(4, furthest + 1)).unwrap();
+    assert_eq!(queue.order_jumps(), 2);
+    queue.insert(ordered(5, furthest)).unwrap();
+    assert_eq!(queue.order_jumps(), 2);
+    assert_eq!(violations(&mut queue), 1);
+    let stats = queue.channel_stats(Instant::now());
+    assert_eq!(stats[0].buffered, 1);
+    assert_eq!(stats[0].head_gap, Some(OrderIndex::new(3)));
+}
+
+#[test]
+fn test_ordered_jump_across_index_wrap() {
+    let mut queue = RecvQueue::new();
+    queue.set_order_jump_limit(16);
+
+    let mut counters = queue.counters();
+    counters.channels.push(ChannelCounters {
+        channel: 0,
+        sequence_index: 0,
+        order_index: U24_MAX - 1,
+    });
+    queue.restore_counters(&counters);
+
+    // the first indexes past the wrap are still a small step ahead.
+    queue.insert(ordered(0, 2)).unwrap();
+    assert_eq!(queue.order_jumps(), 0);
+    queue.insert(ordered(1, 20)).unwrap();
+    assert_eq!(queue.order_jumps(), 1);
+
+    queue.insert(ordered(2, U24_MAX - 1)).unwrap();
+    queue.insert(ordered(3, U24_MAX)).unwrap();
+    queue.insert(ordered(4, 0)).unwrap();
+    queue.insert(ordered(5, 1)).unwrap();
+    assert_eq!(queue.flush().len(), 5);
+}
+
+#[test]
+fn test_sequenced_jump_is_dropped() {
+    let mut queue = RecvQueue::new();
+    queue.set_order_jump_limit(100);
+
+    queue.insert(sequenced(0, 1)).unwrap();
+    queue.insert(sequenced(1, 50)).unwrap();
+    assert_eq!(queue.flush(), vec![vec![1], vec![50]]);
+
+    // 51 is expected next.
+    queue.insert(sequenced(2, 152)).unwrap();
+    assert!(queue.flush().is_empty());
+    assert_eq!(queue.order_jumps(), 1);
+    assert_eq!(violations(&mut queue), 1);
+
+    // the dropped message didn't move the channel, everything in bound still arrives.
+    queue.insert(sequenced(3, 151)).unwrap();
+    queue.insert(sequenced(4, 40)).unwrap();
+    assert_eq!(queue.flush(), vec![vec![151], vec![40]]);
+    assert_eq!(queue.order_jumps(), 1);
+
+    // a session handed to another listener keeps where its channels were.
+    let counters = queue.counters();
+    assert_eq!(counters.channels[0].sequence_index, 151);
+    let mut resumed = RecvQueue::new();
+    resumed.set_order_jump_limit(100);
+    resumed.restore_counters(&counters);
+    resumed.insert(sequenced(5, 200)).unwrap();
+    assert_eq!(resumed.flush(), vec![vec![200]]);
+    assert_eq!(resumed.order_jumps(), 0);
+}
@@ -0,0 +1,185 @@
+use std::time::{Duration, Instant};
+
+use rak_rs::{
+    connection::queue::{OrderedQueue, RecvQueue},
+    protocol::{
+        frame::{Frame, FramePacket},
+        index::{DatagramSeq, OrderIndex, U24_MAX},
+        reliability::Reliability,
+    },
+};
+
+fn ordered(sequence: u32, index: u32) -> FramePacket {
+    let mut frame = Frame::new(Reliability::ReliableOrd, Some(&[index as u8]));
+    frame.order_channel = Some(0);
+    frame.order_index = Some(OrderIndex::new(index));
+
+    let mut packet = FramePacket::new();
+    packet.sequence = DatagramSeq::new(sequence);
+    packet.frames.push(frame);
+    packet
+}
+
+#[cfg(feature = "simulation")]
+const SEEDS: [u64; 4] = [0x5eed, 0xbad5eed, 0x1234_5678, 0xdead_beef];
+
+#[cfg(feature = "simulation")]
+#[test]
+fn test_stall_releases_successors() {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use rak_rs::connection::event::RakEvent;
+    use rak_rs::simulation::SimWorld;
+
+    for seed in SEEDS {
+        let mut world = SimWorld::new(seed);
+        world.set_ordered_stall_limit(Some(Duration::from_secs(2)));
+        let server = world.add_server();
+        let client = world.add_client(server);
+
+        // message 1 is lost, retransmissions included, until it is let through very late.
+        let lost = Rc::new(Cell::new(true));
+        {
+            let lost = lost.clone();
+            world.drop_datagrams(client, server, move |packet| {
+                lost.get() && packet.frames.iter().any(|frame| frame.body == [0xfe, 1])
+            });
+        }
+
+        for i in 0..4u8 {
+            world.send(client, server, &[0xfe, i], Reliability::ReliableOrd, 0);
+            world.step(Duration::from_millis(10));
+        }
+        world.step(Duration::from_secs(1));
+        world.assert_delivered(server, client, &[vec![0xfe, 0]]);
+
+        // the new connection of the handshake holds index 0, message 1 has index 2.
+        world.step(Duration::from_secs(2));
+        world.assert_event(
+            server,
+            client,
+            &RakEvent::OrderedGap {
+                channel: 0,
+                skipped_index: OrderIndex::new(2),
+            },
+        );
+        world.assert_delivered(
+            server,
+            client,
+            &[vec![0xfe, 0], vec![0xfe, 2], vec![0xfe, 3]],
+        );
+
+        // a very late arrival is discarded rather than delivered out of order.
+        lost.set(false);
+        world.step(Duration::from_secs(2));
+        world.assert_delivered(
+            server,
+            client,
+            &[vec![0xfe, 0], vec![0xfe, 2], vec![0xfe, 3]],
+        );
+
+        // the channel keeps going after the gap.
+        world.send(client, server, &[0xfe, 4], Reliability::ReliableOrd, 0);
+        world.step(Duration::from_secs(1));
+        world.assert_delivered(
+            server,
+            client,
+            &[vec![0xfe, 0], vec![0xfe, 2], vec![0xfe, 3], vec![0xfe, 4]],
+        );
+    }
+}
+
+#[test]
+fn test_strict_ordering_by_default() {
+    let mut queue = RecvQueue::new();
+    let now = Instant::now();
+
+    queue.insert(ordered(0, 1)).unwrap();
+    queue.poll_stalls(now);
+    queue.poll_stalls(now + Duration::from_secs(3600));
+
+    assert!(queue.flush_events().is_empty());
+    assert!(queue.flush().is_empty());
+}
+
+#[test]
+fn test_stall_across_index_wrap() {
+    let mut queue: OrderedQueue<u32> = OrderedQueue::new();
+    let start = OrderIndex::new(U24_MAX - 1);
+    queue.window = (start, start);
+    let now = Instant::now();
+
+    // U24_MAX is lost, the ones after the wrap are buffered behind it.
+    assert!(queue.insert(start, 0));
+    assert!(queue.insert(OrderIndex::new(0), 2));
+    assert!(queue.insert(OrderIndex::new(1), 3));
+    assert_eq!(queue.flush(), vec![0]);
+    assert!(queue.is_blocked());
+    assert_eq!(
+        queue.missing().iter_ids().collect::<Vec<u32>>(),
+        vec![U24_MAX]
+    );
+
+    assert!(queue.skip_stalled(now, Duration::from_secs(1)).is_empty());
+    assert_eq!(
+        queue.skip_stalled(now + Duration::from_secs(1), Duration::from_secs(1)),
+        vec![OrderIndex::new(U24_MAX)]
+    );
+    assert_eq!(queue.flush(), vec![2, 3]);
+    assert!(!queue.is_blocked());
+
+    // the skipped index is behind the window now, even though it is the larger number.
+    assert!(!queue.insert(OrderIndex::new(U24_MAX), 1));
+    assert!(queue.insert(OrderIndex::new(2), 4));
+    assert_eq!(queue.flush(), vec![4]);
+}
+
+#[test]
+fn test_gap_straddling_wrap_is_missing() {
+    let mut queue: OrderedQueue<u32> = OrderedQueue::new();
+    let start = OrderIndex::new(U24_MAX - 2);
+    queue.window = (start, start);
+
+    assert!(queue.insert(OrderIndex::new(2), 0));
+    assert_eq!(
+        queue.missing().iter_ids().collect::<Vec<u32>>(),
+        vec![0, 1, U24_MAX - 2, U24_MAX - 1, U24_MAX]
+    );
+}
+
+#[cfg(feature = "simulation")]
+#[test]
+fn test_tick_releases_successors_while_nothing_arrives() {
+    use rak_rs::simulation::SimWorld;
+
+    for seed in SEEDS {
+        let mut world = SimWorld::new(seed);
+        world.set_ordered_stall_limit(Some(Duration::from_millis(500)));
+        let server = world.add_server();
+        let client = world.add_client(server);
+
+        // drops the datagram carrying the second message, and every datagram of the server
+        // after the third, so nothing arrives that would have the packet handler notice the
+        // stall.
+        let mut silenced = false;
+        world.drop_datagrams(server, client, move |packet| {
+            let carries = |body: &[u8]| packet.frames.iter().any(|f| f.body == body);
+            if silenced || carries(&[0xfe, 1]) {
+                return true;
+            }
+            silenced = carries(&[0xfe, 2]);
+            false
+        });
+
+        for i in 0..3u8 {
+            world.send(server, client, &[0xfe, i], Reliability::ReliableOrd, 0);
+            world.step(Duration::from_millis(10));
+        }
+
+        world.step(Duration::from_millis(200));
+        world.assert_delivered(client, server, &[vec![0xfe, 0]]);
+        world.step(Duration::from_secs(1));
+        world.assert_delivered(client, server, &[vec![0xfe, 0], vec![0xfe, 2]]);
+    }
+}
@@ -0,0 +1,155 @@
+//! Only the packet enums read and write the packet id, every packet decodes the same through
+//! them as on its own.
+use std::fmt::Debug;
+
+use binary_util::interfaces::{Reader, Writer};
+use rak_rs::error::decode::DecodeErrorKind;
+use rak_rs::protocol::packet::offline::*;
+use rak_rs::protocol::packet::online::*;
+use rak_rs::protocol::packet::{decode_packet, PacketId, RakPacket};
+use rak_rs::protocol::Magic;
+
+fn fixture(name: &str) -> Vec<u8> {
+    let path = format!(
+        "{}/tests/fixtures/vanilla/{}.hex",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    );
+    let text = std::fs::read_to_string(&path).unwrap();
+    let mut bytes = Vec::new();
+    for line in text.lines() {
+        let line = line.split('#').next().unwrap();
+        for token in line.split_whitespace() {
+            let (byte, count) = match token.split_once('x') {
+                Some((byte, count)) => (byte, count.parse().unwrap()),
+                None => (token, 1),
+            };
+            let byte = u8::from_str_radix(byte, 16).unwrap();
+            bytes.extend(std::iter::repeat(byte).take(count));
+        }
+    }
+    bytes
+}
+
+/// Decodes `bytes` through [`RakPacket`] and as a `P` on its own, both have to agree.
+fn assert_both<P>(bytes: &[u8])
+where
+    P: PacketId + Reader<P> + From<RakPacket> + Debug,
+{
+    let name = std::any::type_name::<P>();
+    assert_eq!(bytes[0], P::ID, "{} starts with another id", name);
+
+    let dispatched = RakPacket::read_from_slice(bytes).unwrap();
+    assert_eq!(dispatched.id(), P::ID, "{}", name);
+    let dispatched = format!("{:?}", P::from(dispatched));
+    let direct = format!("{:?}", decode_packet::<P>(bytes).unwrap());
+    assert_eq!(dispatched, direct, "{}", name);
+
+    // every other packet turns it down by its id, before reading any of it.
+    let other = decode_packet::<Disconnect>(bytes);
+    if P::ID != Disconnect::ID {
+        let error = other.unwrap_err();
+        assert!(error.is_unknown_packet(), "{}: {:?}", name, error);
+    }
+}
+
+/// Writes `packet` through [`RakPacket`] and decodes it both ways.
+fn assert_written<P>(packet: P)
+where
+    P: PacketId + Reader<P> + From<RakPacket> + Into<RakPacket> + Debug + Clone,
+{
+    let expected = format!("{:?}", packet);
+    let bytes = packet.into().write_to_bytes().unwrap();
+    assert_both::<P>(bytes.as_slice());
+    assert_eq!(
+        format!("{:?}", decode_packet::<P>(bytes.as_slice()).unwrap()),
+        expected
+    );
+}
+
+#[test]
+fn test_fixtures_decode_the_same_both_ways() {
+    assert_both::<UnconnectedPing>(&fixture("unconnected_ping"));
+    assert_both::<OpenConnectRequest>(&fixture("open_connection_request_1"));
+    assert_both::<OpenConnectReply>(&fixture("open_connection_reply_1"));
+    assert_both::<SessionInfoRequest>(&fixture("open_connection_request_2"));
+    assert_both::<SessionInfoReply>(&fixture("open_connection_reply_2"));
+    assert_both::<ConnectedPing>(&fixture("connected_ping"));
+    assert_both::<ConnectionRequest>(&fixture("connection_request"));
+    assert_both::<ConnectionAccept>(&fixture("connection_request_accepted"));
+    assert_both::<ConnectionAccept>(&fixture("connection_request_accepted_dual_stack"));
+    assert_both::<NewConnection>(&fixture("new_incoming_connection"));
+    assert_both::<Disconnect>(&fixture("disconnection_notification"));
+    #[cfg(not(feature = "mcpe"))]
+    assert_both::<UnconnectedPong>(&fixture("unconnected_pong"));
+}
+
+#[test]
+fn test_written_packets_decode_the_same_both_ways() {
+    assert_written(IncompatibleProtocolVersion {
+        protocol: 11,
+        magic: Magic::new(),
+        server_id: 7,
+    });
+    assert_written(OpenConnectReply {
+        magic: Magic::new(),
+        server_id: 7,
+        security: false,
+        mtu_size: 1400,
+    });
+    assert_written(SessionInfoReply {
+        magic: Magic::new(),
+        server_id: 7,
+        client_address: "127.0.0.1:19132".parse().unwrap(),
+        mtu_size: 1400,
+        security: false,
+        capabilities: Capabilities::NONE,
+    });
+    assert_written(ConnectedPong {
+        ping_time: 1,
+        pong_time: 2,
+    });
+    assert_written(LostConnection {});
+    assert_written(Disconnect {});
+}
+
+#[test]
+fn test_replies_are_not_mistaken_for_each_other() {
+    // what the handshake reads its session reply from, while open replies may still arrive.
+    let open_reply = RakPacket::from(OpenConnectReply {
+        magic: Magic::new(),
+        server_id: 7,
+        security: false,
+        mtu_size: 1400,
+    })
+    .write_to_bytes()
+    .unwrap();
+
+    let error = decode_packet::<SessionInfoReply>(open_reply.as_slice()).unwrap_err();
+    assert_eq!(error.packet_id, Some(OpenConnectReply::ID));
+    assert_eq!(error.offset, 0);
+
+    let error = decode_packet::<SessionInfoReply>(&[]).unwrap_err();
+    assert_eq!(
+        error.kind,
+        DecodeErrorKind::UnexpectedEof {
+            needed: 1,
+            remaining: 0
+        }
+    );
+
+    // the offsets of a broken packet count its id.
+    let cut = &open_reply.as_slice()[..5];
+    let error = decode_packet::<OpenConnectReply>(cut).unwrap_err();
+    assert_eq!(error.packet_id, Some(OpenConnectReply::ID));
+    assert_eq!(
+        (error.offset, error.kind),
+        (
+            1,
+            DecodeErrorKind::UnexpectedEof {
+                needed: 16,
+                remaining: 4
+            }
+        )
+    );
+}
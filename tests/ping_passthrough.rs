@@ -0,0 +1,124 @@
+#![cfg(all(feature = "async_std", feature = "server"))]
+//! Pings answered by the application with [`Listener::offline_ping_passthrough`].
+use std::time::{Duration, Instant};
+
+use async_std::{future::timeout, net::UdpSocket, task};
+use binary_util::interfaces::{Reader, Writer};
+use rak_rs::connection::event::RakEvent;
+use rak_rs::protocol::packet::offline::{OfflinePacket, UnconnectedPing};
+use rak_rs::protocol::packet::RakPacket;
+use rak_rs::protocol::Magic;
+use rak_rs::server::advertisement::PingFallback;
+use rak_rs::Listener;
+
+/// The id of the reply to an unconnected ping.
+const UNCONNECTED_PONG: u8 = 0x1c;
+/// The size of a pong up to where the MOTD starts.
+const PONG_HEADER: usize = 1 + 8 + 8 + 16;
+
+async fn passthrough(address: &str, fallback: PingFallback) -> Listener {
+    let mut server = Listener::bind(address).await.unwrap();
+    server.offline_ping_passthrough = true;
+    server.ping_fallback = fallback;
+    server.start().await.unwrap();
+    server
+}
+
+async fn pinger(address: &str) -> UdpSocket {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    socket.connect(address).await.unwrap();
+    socket
+}
+
+/// Pings with `client_id`, returning the pong if it was answered.
+async fn ping(socket: &UdpSocket, client_id: i64) -> Option<Vec<u8>> {
+    let ping = RakPacket::from(OfflinePacket::UnconnectedPing(UnconnectedPing {
+        timestamp: 0,
+        magic: Magic::new(),
+        client_id,
+    }));
+    socket
+        .send(ping.write_to_bytes().unwrap().as_slice())
+        .await
+        .unwrap();
+
+    let mut buf = [0u8; 2048];
+    let len = timeout(Duration::from_millis(500), socket.recv(&mut buf))
+        .await
+        .ok()?
+        .unwrap();
+    Some(buf[..len].to_vec())
+}
+
+#[test]
+fn test_reply_per_address() {
+    task::block_on(async {
+        const ADDRESS: &str = "127.0.0.1:19227";
+        let mut server = passthrough(ADDRESS, PingFallback::Cached).await;
+        task::spawn(async move {
+            while let Ok(RakEvent::PingRequest {
+                addr,
+                client_guid,
+                respond,
+            }) = server.recv_event().await
+            {
+                // answered from another task, as a lookup would.
+                task::spawn(async move {
+                    let banner = format!("{} {}", client_guid, addr);
+                    assert!(respond.respond_raw(banner.into_bytes()));
+                });
+            }
+        });
+
+        for client_id in [1, 2] {
+            let socket = pinger(ADDRESS).await;
+            let pong = ping(&socket, client_id).await.expect("not answered");
+            assert_eq!(pong[0], UNCONNECTED_PONG);
+            assert_eq!(
+                String::from_utf8(pong[PONG_HEADER + 2..].to_vec()).unwrap(),
+                format!("{} {}", client_id, socket.local_addr().unwrap())
+            );
+        }
+    });
+}
+
+#[test]
+fn test_declined_ping_is_not_answered() {
+    task::block_on(async {
+        const ADDRESS: &str = "127.0.0.1:19228";
+        let mut server = passthrough(ADDRESS, PingFallback::Cached).await;
+        task::spawn(async move {
+            while let Ok(RakEvent::PingRequest { respond, .. }) = server.recv_event().await {
+                assert!(respond.decline());
+            }
+        });
+
+        assert_eq!(ping(&pinger(ADDRESS).await, 7).await, None);
+    });
+}
+
+#[test]
+fn test_late_reply_falls_back() {
+    task::block_on(async {
+        const ADDRESS: &str = "127.0.0.1:19229";
+        let mut server = passthrough(ADDRESS, PingFallback::Cached).await;
+        let late = task::spawn(async move {
+            let Ok(RakEvent::PingRequest { respond, .. }) = server.recv_event().await else {
+                panic!("no ping request");
+            };
+            task::sleep(Duration::from_millis(200)).await;
+            respond.respond_raw(b"too late".to_vec())
+        });
+
+        let socket = pinger(ADDRESS).await;
+        let started = Instant::now();
+        let pong = ping(&socket, 7).await.expect("the fallback was not sent");
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert!(matches!(
+            OfflinePacket::read_from_slice(&pong),
+            Ok(OfflinePacket::UnconnectedPong(_))
+        ));
+        assert!(!pong.ends_with(b"too late"));
+        assert!(!late.await, "the late reply was taken");
+    });
+}
@@ -0,0 +1,121 @@
+#![cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+//! Connects through a relay that, like some load balancers, answers pings from the port that
+//! was asked but everything else from the port above it.
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_std::{future::timeout, net::UdpSocket, task};
+use rak_rs::error::client::{ClientError, HandshakeError};
+use rak_rs::{Client, Listener, RakEvent};
+
+/// The id of the reply to an unconnected ping.
+const UNCONNECTED_PONG: u8 = 0x1c;
+
+/// Relays `front` and the port above it to `server`, answering from the port above `front`
+/// unless it is a pong.
+async fn relay(front: &str, server: &str) {
+    let front: SocketAddr = front.parse().unwrap();
+    let side = SocketAddr::new(front.ip(), front.port() + 1);
+    let front = Arc::new(UdpSocket::bind(front).await.unwrap());
+    let side = Arc::new(UdpSocket::bind(side).await.unwrap());
+    let upstream = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+    upstream.connect(server).await.unwrap();
+    let client = Arc::new(std::sync::Mutex::new(None));
+
+    for listen in [front.clone(), side.clone()] {
+        let (upstream, client) = (upstream.clone(), client.clone());
+        task::spawn(async move {
+            let mut buf = [0u8; 2048];
+            while let Ok((len, source)) = listen.recv_from(&mut buf).await {
+                *client.lock().unwrap() = Some(source);
+                let _ = upstream.send(&buf[..len]).await;
+            }
+        });
+    }
+
+    task::spawn(async move {
+        let mut buf = [0u8; 2048];
+        while let Ok(len) = upstream.recv(&mut buf).await {
+            let Some(client) = *client.lock().unwrap() else {
+                continue;
+            };
+            let reply = if buf[0] == UNCONNECTED_PONG {
+                &front
+            } else {
+                &side
+            };
+            let _ = reply.send_to(&buf[..len], client).await;
+        }
+    });
+}
+
+#[test]
+fn test_port_mismatch_is_refused() {
+    task::block_on(async {
+        const SERVER: &str = "127.0.0.1:19223";
+        const FRONT: &str = "127.0.0.1:19221";
+        let mut server = Listener::bind(SERVER).await.unwrap();
+        server.start().await.unwrap();
+        relay(FRONT, SERVER).await;
+
+        // the replies never reach the connected socket, so this takes every attempt of stage 1.
+        let client = Client::new(11, 1400);
+        let result = timeout(Duration::from_secs(30), client.connect(FRONT))
+            .await
+            .expect("connect timed out");
+
+        assert_eq!(
+            result,
+            Err(ClientError::Handshake(HandshakeError::PortMismatch {
+                expected: 19221,
+                observed: 19222,
+            }))
+        );
+    });
+}
+
+#[test]
+fn test_port_rebind_follows_the_server() {
+    task::block_on(async {
+        const SERVER: &str = "127.0.0.1:19226";
+        const FRONT: &str = "127.0.0.1:19224";
+        let mut server = Listener::bind(SERVER).await.unwrap();
+        server.start().await.unwrap();
+        relay(FRONT, SERVER).await;
+
+        let client = Client::new(11, 1400).allow_port_rebind(true);
+        timeout(Duration::from_secs(20), client.connect(FRONT))
+            .await
+            .expect("connect timed out")
+            .expect("failed to connect");
+        let mut conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let event = timeout(Duration::from_secs(1), client.recv_event())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            event,
+            RakEvent::PortRebound {
+                requested: FRONT.parse().unwrap(),
+                peer: "127.0.0.1:19225".parse().unwrap(),
+            }
+        );
+        assert_eq!(
+            client.meta().await.rebound_from(),
+            Some(FRONT.parse().unwrap())
+        );
+
+        // the rest of the connection goes through the port the server answered from.
+        client.send_ord(&[0xfe, 1, 2, 3], 0).await.unwrap();
+        let packet = timeout(Duration::from_secs(5), conn.recv())
+            .await
+            .expect("nothing arrived")
+            .unwrap();
+        assert_eq!(packet, vec![0xfe, 1, 2, 3]);
+    });
+}
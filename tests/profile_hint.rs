@@ -0,0 +1,133 @@
+//! Traffic hints size the structures of a connection up front, within its memory budget.
+use rak_rs::connection::controller::memory::{MemBudget, ENTRY_OVERHEAD};
+use rak_rs::connection::controller::profile::{Capacity, LatencyClass, Profile};
+use rak_rs::protocol::RAKNET_HEADER_FRAME_OVERHEAD;
+
+const MTU: u16 = 1400;
+const PAYLOAD: usize = (MTU - RAKNET_HEADER_FRAME_OVERHEAD) as usize;
+
+fn burst(bytes: usize, rate: usize) -> Profile {
+    Profile {
+        expected_burst_bytes: bytes,
+        expected_message_rate: rate,
+        latency_class: LatencyClass::Internet,
+    }
+}
+
+#[test]
+fn test_capacity_covers_the_burst() {
+    let budget = MemBudget::new(8 * 1024 * 1024);
+    let capacity = burst(PAYLOAD * 100 + 1, 50).capacity(MTU, &budget);
+    assert_eq!(
+        capacity,
+        Capacity {
+            frames: 101,
+            datagrams: 101,
+            splits: 50,
+            messages: 50,
+        }
+    );
+
+    assert_eq!(burst(0, 0).capacity(MTU, &budget), Capacity::default());
+}
+
+#[test]
+fn test_capacity_is_clamped_to_the_budget() {
+    let budget = MemBudget::new(64 * 1024);
+    let capacity = burst(usize::MAX, usize::MAX).capacity(MTU, &budget);
+    let entries = 64 * 1024 / ENTRY_OVERHEAD;
+    assert_eq!(capacity.frames, (64 * 1024 + PAYLOAD - 1) / PAYLOAD);
+    assert_eq!(capacity.messages, entries);
+    assert!(capacity.splits <= capacity.frames);
+
+    // a full budget leaves no room for a hint at all.
+    let budget = MemBudget::new(0);
+    assert_eq!(
+        burst(1024 * 1024, 100).capacity(MTU, &budget),
+        Capacity::default()
+    );
+}
+
+#[cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+mod connection {
+    use std::time::Duration;
+
+    use async_std::{future::timeout, task};
+    use rak_rs::connection::controller::profile::{LatencyClass, Profile};
+    use rak_rs::connection::options::{ConnOptions, DEFAULT_FRAME_BUDGET};
+    use rak_rs::{Client, Connection, Listener};
+
+    async fn connect(address: &str, options: ConnOptions) -> (Listener, Client, Connection) {
+        let mut server = Listener::bind(address).await.unwrap();
+        server.conn_options = options;
+        server.start().await.unwrap();
+
+        let client = Client::new(11, 1400);
+        timeout(Duration::from_secs(10), client.connect(address))
+            .await
+            .expect("connect timed out")
+            .expect("failed to connect");
+        let conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+        (server, client, conn)
+    }
+
+    #[test]
+    fn test_hint_presizes_the_queues() {
+        task::block_on(async {
+            let options = ConnOptions {
+                frame_budget: Some(DEFAULT_FRAME_BUDGET),
+                lan_frame_budget: Some(4096),
+                ..Default::default()
+            };
+            let (_server, _client, conn) = connect("127.0.0.1:19279", options).await;
+
+            let hint = Profile {
+                expected_burst_bytes: 1024 * 1024,
+                expected_message_rate: 500,
+                latency_class: LatencyClass::Internet,
+            };
+            let applied = conn.hint_profile(hint).await;
+            assert!(applied.frames > 700);
+            let capacity = conn.capacity().await;
+            assert!(capacity.frames >= applied.frames);
+            assert!(capacity.datagrams >= applied.datagrams);
+            assert!(capacity.splits >= applied.splits);
+            assert!(capacity.messages >= applied.messages);
+            // only a peer on the same network gets a larger frame budget.
+            assert_eq!(conn.frame_budget().await, Some(DEFAULT_FRAME_BUDGET));
+
+            conn.hint_profile(Profile {
+                latency_class: LatencyClass::Lan,
+                ..hint
+            })
+            .await;
+            assert_eq!(conn.frame_budget().await, Some(4096));
+        });
+    }
+
+    #[test]
+    fn test_hint_beyond_the_budget_is_clamped() {
+        task::block_on(async {
+            let options = ConnOptions {
+                max_connection_memory: 256 * 1024,
+                ..Default::default()
+            };
+            let (_server, _client, conn) = connect("127.0.0.1:19280", options).await;
+
+            let applied = conn
+                .hint_profile(Profile {
+                    expected_burst_bytes: 1024 * 1024 * 1024,
+                    expected_message_rate: 1_000_000,
+                    latency_class: LatencyClass::Lan,
+                })
+                .await;
+            assert!(applied.frames <= 256 * 1024 / 1300 + 1);
+            assert!(applied.messages <= 256 * 1024 / 64);
+            // without a frame budget for the network, a hint leaves it as it is.
+            assert_eq!(conn.frame_budget().await, None);
+        });
+    }
+}
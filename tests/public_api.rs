@@ -0,0 +1,96 @@
+#![cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+use std::time::Duration;
+
+use async_std::{future::timeout, task};
+use rak_rs::{
+    discover_lan, ping, Client, ClientEvent, ClientOptions, ConnId, Connection, ConnectionStats,
+    DisconnectReason, RakError, RakNetServer, Server, ServerOptions,
+};
+
+async fn echo(mut conn: Connection) {
+    let peer: ConnId = conn.address;
+    assert!(peer.ip().is_loopback());
+
+    while let Ok(packet) = conn.recv().await {
+        // only echo game packets back, then hand the peer over.
+        if packet[0] == 0xfe {
+            conn.send(&packet, true).await.unwrap();
+            conn.begin_drain_and_close(DisconnectReason::Transferred, Duration::from_secs(2))
+                .await;
+            return;
+        }
+    }
+}
+
+async fn session(client: &Client, address: &str) -> Result<Vec<u8>, RakError> {
+    client.connect(address).await?;
+    client.send_ord(&[0xfe, 1, 2, 3], 0).await?;
+
+    loop {
+        let packet = client.recv().await.unwrap();
+        if packet[0] == 0xfe {
+            return Ok(packet);
+        }
+    }
+}
+
+#[test]
+fn test_root_api_session() {
+    task::block_on(async {
+        let mut server = Server::bind("127.0.0.1:19161").await.unwrap();
+        server.conn_options = ServerOptions::default();
+        server.start().await.unwrap();
+
+        task::spawn(async move {
+            loop {
+                if let Ok(conn) = server.accept().await {
+                    task::spawn(echo(conn));
+                }
+            }
+        });
+
+        timeout(Duration::from_secs(10), ping("127.0.0.1:19161"))
+            .await
+            .expect("ping timed out")
+            .expect("server did not answer the ping");
+
+        let client = Client::new(11, 1400).with_options(ClientOptions::default());
+        let packet = timeout(Duration::from_secs(10), session(&client, "127.0.0.1:19161"))
+            .await
+            .expect("session timed out")
+            .expect("session failed");
+        assert_eq!(packet, vec![0xfe, 1, 2, 3]);
+        let stats: ConnectionStats = client.stats().await;
+        assert!(stats.bytes_sent > 0);
+
+        let reason = timeout(Duration::from_secs(10), async {
+            loop {
+                if let Ok(ClientEvent::Disconnected { reason }) = client.recv_event().await {
+                    return reason;
+                }
+            }
+        })
+        .await
+        .expect("no disconnect event");
+        assert_eq!(reason, DisconnectReason::Transferred);
+
+        client.close().await;
+    });
+}
+
+#[test]
+fn test_root_api_discovery() {
+    task::block_on(async {
+        let mut server = RakNetServer::bind("0.0.0.0:19290").await.unwrap();
+        server.start().await.unwrap();
+
+        // broadcasts may not leave a sandbox, the server only has to be among what was found.
+        if let Ok(found) = discover_lan(19290, Duration::from_millis(500)).await {
+            if !found.is_empty() {
+                assert!(found.iter().any(|(address, _)| address.port() == 19290));
+            }
+        }
+
+        server.stop().await.unwrap();
+    });
+}
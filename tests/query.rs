@@ -0,0 +1,172 @@
+#![cfg(all(feature = "async_std", feature = "query"))]
+//! The GS4 query protocol, spoken by a minimal query client.
+use std::time::Duration;
+
+use async_std::{future::timeout, net::UdpSocket, task};
+use rak_rs::server::query::{QueryInfo, QueryRequest, QUERY_HANDSHAKE, QUERY_STAT};
+use rak_rs::{Listener, Motd};
+
+const SESSION: i32 = 0x0102_0304;
+
+/// A query client as a hosting panel would implement it.
+struct QueryClient {
+    socket: UdpSocket,
+}
+
+impl QueryClient {
+    async fn new(server: &str) -> Self {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        socket.connect(server).await.unwrap();
+        Self { socket }
+    }
+
+    async fn request(&self, kind: u8, payload: &[u8]) -> Option<Vec<u8>> {
+        let mut request = vec![0xfe, 0xfd, kind];
+        request.extend_from_slice(&SESSION.to_be_bytes());
+        request.extend_from_slice(payload);
+        self.socket.send(&request).await.unwrap();
+
+        let mut buf = [0u8; 2048];
+        let len = timeout(Duration::from_millis(300), self.socket.recv(&mut buf))
+            .await
+            .ok()?
+            .unwrap();
+        let reply = buf[..len].to_vec();
+        assert_eq!(reply[0], kind);
+        // only the low nibbles of the session are echoed.
+        assert_eq!(reply[1..5], (SESSION & 0x0f0f_0f0f).to_be_bytes());
+        Some(reply[5..].to_vec())
+    }
+
+    async fn handshake(&self) -> i32 {
+        let reply = self.request(QUERY_HANDSHAKE, &[]).await.unwrap();
+        strings(&reply)[0].parse().unwrap()
+    }
+
+    async fn basic_stat(&self, token: i32) -> Option<Vec<u8>> {
+        self.request(QUERY_STAT, &token.to_be_bytes()).await
+    }
+
+    async fn full_stat(&self, token: i32) -> Option<Vec<u8>> {
+        let mut payload = token.to_be_bytes().to_vec();
+        payload.extend_from_slice(&[0; 4]);
+        self.request(QUERY_STAT, &payload).await
+    }
+}
+
+/// Splits null terminated strings.
+fn strings(buf: &[u8]) -> Vec<String> {
+    buf.split(|byte| *byte == 0)
+        .map(|s| String::from_utf8_lossy(s).into_owned())
+        .collect()
+}
+
+async fn listener(address: &str, token_lifetime: Duration) -> Listener {
+    let mut server = Listener::bind(address).await.unwrap();
+    let mut motd = Motd::new(server.id, "19132");
+    motd.name = "Query Test".into();
+    motd.sub_motd = "world".into();
+    motd.player_count = 2;
+    motd.player_max = 20;
+    server.motd = motd;
+    server.query_token_lifetime = token_lifetime;
+    server.set_query_provider(|| QueryInfo {
+        plugins: "rak-rs: Query 1.0".into(),
+        players: vec!["alice".into(), "bob".into()],
+        ..Default::default()
+    });
+    server.start().await.unwrap();
+    server
+}
+
+#[test]
+fn test_request_parsing() {
+    let mut datagram = vec![0xfe, 0xfd, QUERY_STAT, 0, 0, 0, 1, 0, 0, 0, 7];
+    assert_eq!(
+        QueryRequest::parse(&datagram),
+        Some(QueryRequest::BasicStat {
+            session: 1,
+            token: 7
+        })
+    );
+    datagram.extend_from_slice(&[0; 4]);
+    assert_eq!(
+        QueryRequest::parse(&datagram),
+        Some(QueryRequest::FullStat {
+            session: 1,
+            token: 7
+        })
+    );
+    assert_eq!(QueryRequest::parse(&datagram[..5]), None);
+    assert_eq!(QueryRequest::parse(&[0x01, 0, 0, 0, 0, 0, 0]), None);
+}
+
+#[test]
+fn test_basic_stat() {
+    task::block_on(async {
+        let _server = listener("127.0.0.1:19261", Duration::from_secs(30)).await;
+        let client = QueryClient::new("127.0.0.1:19261").await;
+
+        // a stat without a handshake is not answered.
+        assert_eq!(client.basic_stat(1234).await, None);
+
+        let token = client.handshake().await;
+        assert!(token >= 0);
+        let reply = client.basic_stat(token).await.unwrap();
+        let fields = strings(&reply);
+        assert_eq!(fields[..5], ["Query Test", "SMP", "world", "2", "20"]);
+        // the port is little endian, followed by the address.
+        let rest = &reply[reply.len() - "127.0.0.1".len() - 3..];
+        assert_eq!(u16::from_le_bytes([rest[0], rest[1]]), 19132);
+        assert_eq!(strings(&rest[2..])[0], "127.0.0.1");
+    });
+}
+
+#[test]
+fn test_full_stat() {
+    task::block_on(async {
+        let _server = listener("127.0.0.1:19262", Duration::from_secs(30)).await;
+        let client = QueryClient::new("127.0.0.1:19262").await;
+        let token = client.handshake().await;
+        let reply = client.full_stat(token).await.unwrap();
+
+        assert!(reply.starts_with(b"splitnum\0\x80\0"));
+        let (values, players) = reply[11..]
+            .windows(10)
+            .position(|w| w == b"\x01player_\0\0")
+            .map(|at| (&reply[11..11 + at], &reply[11 + at + 10..]))
+            .unwrap();
+        let values = strings(values);
+        let value = |key: &str| {
+            let at = values.iter().position(|k| k == key).unwrap();
+            values[at + 1].clone()
+        };
+        assert_eq!(value("hostname"), "Query Test");
+        assert_eq!(value("game_id"), "MINECRAFTPE");
+        assert_eq!(value("plugins"), "rak-rs: Query 1.0");
+        assert_eq!(value("map"), "world");
+        assert_eq!(value("numplayers"), "2");
+        assert_eq!(value("maxplayers"), "20");
+        assert_eq!(value("hostport"), "19132");
+        assert_eq!(strings(players)[..3], ["alice", "bob", ""]);
+    });
+}
+
+#[test]
+fn test_expired_token_is_rejected() {
+    task::block_on(async {
+        let _server = listener("127.0.0.1:19263", Duration::from_millis(200)).await;
+        let client = QueryClient::new("127.0.0.1:19263").await;
+        let token = client.handshake().await;
+        assert!(client.basic_stat(token).await.is_some());
+        // a token of another address, or a made up one, is no good either.
+        assert_eq!(client.basic_stat(token.wrapping_add(1)).await, None);
+
+        task::sleep(Duration::from_millis(300)).await;
+        assert_eq!(client.full_stat(token).await, None);
+
+        // a new handshake gets a new token.
+        let token = client.handshake().await;
+        assert!(client.full_stat(token).await.is_some());
+    });
+}
@@ -0,0 +1,165 @@
+#![cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_std::{future::timeout, net::UdpSocket, task};
+use binary_util::interfaces::Reader;
+use rak_rs::connection::queue::{RecvQueue, SendQueue};
+use rak_rs::protocol::frame::{Frame, FramePacket};
+use rak_rs::protocol::reliability::Reliability;
+use rak_rs::{Client, Listener};
+
+/// A message on `channel`, tagged with its channel and place so the far end can tell them apart.
+fn message(channel: u8, i: u8, len: usize) -> Vec<u8> {
+    let mut body = vec![0xfe, channel, i];
+    body.resize(len, i);
+    body
+}
+
+/// The session a peer sends: ordered messages on two channels, with a split message in between.
+fn session() -> Vec<(u8, Vec<u8>)> {
+    vec![
+        (0, message(0, 0, 10)),
+        (1, message(1, 0, 10)),
+        (0, message(0, 1, 5000)),
+        (1, message(1, 1, 10)),
+        (0, message(0, 2, 10)),
+        (1, message(1, 2, 3000)),
+        (0, message(0, 3, 10)),
+    ]
+}
+
+/// Sends the session from a queue of its own and records the frames it put on the wire.
+async fn record(session: &[(u8, Vec<u8>)]) -> Vec<Frame> {
+    let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+    let mut send_q = SendQueue::new(1400, 12000, 5, socket, peer.local_addr().unwrap());
+
+    for (channel, body) in session {
+        send_q
+            .insert(body, Reliability::ReliableOrd, false, Some(*channel))
+            .await
+            .unwrap();
+    }
+    send_q.update().await;
+
+    let mut frames = Vec::new();
+    let mut buf = [0u8; 2048];
+    while let Ok(Ok(len)) = timeout(Duration::from_millis(200), peer.recv(&mut buf)).await {
+        frames.extend(FramePacket::read_from_slice(&buf[..len]).unwrap().frames);
+    }
+    frames
+}
+
+/// The payloads of `delivered` on `channel`, in the order they were delivered.
+fn on_channel(delivered: &[Vec<u8>], channel: u8) -> Vec<Vec<u8>> {
+    delivered
+        .iter()
+        .filter(|body| body[1] == channel)
+        .cloned()
+        .collect()
+}
+
+#[test]
+fn test_forwarded_frames_are_remapped() {
+    task::block_on(async {
+        let session = session();
+        let recorded = record(&session).await;
+        assert!(recorded.iter().any(|frame| frame.fragment_meta.is_some()));
+
+        let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let mut proxy = SendQueue::new(1400, 12000, 5, socket, peer.local_addr().unwrap());
+
+        // the proxy talked to the far end before, so none of its indexes match the recording.
+        proxy
+            .insert(
+                &message(0, 0xff, 4000),
+                Reliability::ReliableOrd,
+                false,
+                Some(0),
+            )
+            .await
+            .unwrap();
+        proxy
+            .insert(
+                &message(1, 0xff, 10),
+                Reliability::ReliableOrd,
+                false,
+                Some(1),
+            )
+            .await
+            .unwrap();
+        for frame in recorded.iter() {
+            proxy.insert_frame(frame.into(), None, false).await.unwrap();
+        }
+        proxy.update().await;
+
+        // the datagrams arrive in reverse, the far end has to reorder them.
+        let mut datagrams = Vec::new();
+        let mut buf = [0u8; 2048];
+        while let Ok(Ok(len)) = timeout(Duration::from_millis(200), peer.recv(&mut buf)).await {
+            datagrams.push(FramePacket::read_from_slice(&buf[..len]).unwrap());
+        }
+        let mut recv_q = RecvQueue::new();
+        for datagram in datagrams.into_iter().rev() {
+            recv_q.insert(datagram).unwrap();
+        }
+
+        let delivered = recv_q
+            .flush()
+            .into_iter()
+            .filter(|body| body[2] != 0xff)
+            .collect::<Vec<_>>();
+        for channel in 0..2 {
+            let sent = session
+                .iter()
+                .filter(|(c, _)| *c == channel)
+                .map(|(_, body)| body.clone())
+                .collect::<Vec<_>>();
+            assert_eq!(on_channel(&delivered, channel), sent);
+        }
+    });
+}
+
+#[test]
+fn test_proxy_forwards_a_session() {
+    task::block_on(async {
+        const ADDRESS: &str = "127.0.0.1:19214";
+        let mut server = Listener::bind(ADDRESS).await.unwrap();
+        server.start().await.unwrap();
+
+        let client = Client::new(11, 1400);
+        timeout(Duration::from_secs(10), client.connect(ADDRESS))
+            .await
+            .expect("connect timed out")
+            .expect("failed to connect");
+        let conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let session = session();
+        for frame in record(&session).await.iter() {
+            conn.send_raw_frame(frame.into(), None).await.unwrap();
+        }
+
+        let mut delivered = Vec::new();
+        for _ in 0..session.len() {
+            let packet = timeout(Duration::from_secs(5), client.recv())
+                .await
+                .expect("a forwarded message was lost")
+                .unwrap();
+            delivered.push(packet);
+        }
+
+        for channel in 0..2 {
+            let sent = session
+                .iter()
+                .filter(|(c, _)| *c == channel)
+                .map(|(_, body)| body.clone())
+                .collect::<Vec<_>>();
+            assert_eq!(on_channel(&delivered, channel), sent);
+        }
+    });
+}
@@ -0,0 +1,100 @@
+#![cfg(all(feature = "async_std", feature = "server"))]
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_std::{future::timeout, net::UdpSocket, task};
+use binary_util::interfaces::{Reader, Writer};
+use rak_rs::protocol::frame::{Frame, FramePacket};
+use rak_rs::protocol::index::{DatagramSeq, MessageIndex};
+use rak_rs::protocol::packet::offline::{OfflinePacket, SessionInfoRequest};
+use rak_rs::protocol::packet::online::{Capabilities, ConnectionRequest, OnlinePacket};
+use rak_rs::protocol::packet::RakPacket;
+use rak_rs::protocol::Magic;
+use rak_rs::{Listener, Reliability};
+
+const ADDRESS: &str = "127.0.0.1:19182";
+
+#[test]
+fn test_flood_does_not_starve_connections() {
+    // a single worker, so the read loop and the connection share it.
+    std::env::set_var("ASYNC_STD_THREAD_COUNT", "1");
+
+    task::block_on(async {
+        let mut server = Listener::bind(ADDRESS).await.unwrap();
+        server.start().await.unwrap();
+        let server_address: SocketAddr = ADDRESS.parse().unwrap();
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        socket.connect(ADDRESS).await.unwrap();
+        let session = RakPacket::from(OfflinePacket::SessionInfoRequest(SessionInfoRequest {
+            magic: Magic::new(),
+            address: server_address,
+            mtu_size: 1400,
+            client_id: 7,
+            capabilities: Capabilities::NONE,
+        }));
+        socket
+            .send(session.write_to_bytes().unwrap().as_slice())
+            .await
+            .unwrap();
+        let _conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+
+        // another peer keeps the server socket busy with garbage.
+        let flooding = Arc::new(AtomicBool::new(true));
+        let flood = {
+            let flooding = flooding.clone();
+            std::thread::spawn(move || {
+                let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+                while flooding.load(Ordering::Relaxed) {
+                    let _ = socket.send_to(&[0xff; 32], ADDRESS);
+                }
+            })
+        };
+        task::sleep(Duration::from_millis(200)).await;
+
+        let request = OnlinePacket::from(ConnectionRequest {
+            client_id: 7,
+            time: 1,
+            security: false,
+            capabilities: Capabilities::NONE,
+        })
+        .write_to_bytes()
+        .unwrap();
+        let mut frame = Frame::new(Reliability::Reliable, Some(request.as_slice()));
+        frame.reliable_index = Some(MessageIndex::new(0));
+        let mut datagram = FramePacket::new();
+        datagram.sequence = DatagramSeq::new(0);
+        datagram.frames.push(frame);
+        socket
+            .send(datagram.write_to_bytes().unwrap().as_slice())
+            .await
+            .unwrap();
+
+        let answered = timeout(Duration::from_secs(5), async {
+            let mut buf = [0u8; 2048];
+            loop {
+                let len = socket.recv(&mut buf).await.unwrap();
+                if let Ok(datagram) = FramePacket::read_from_slice(&buf[..len]) {
+                    if datagram.frames.iter().any(|frame| {
+                        matches!(
+                            OnlinePacket::read_from_slice(&frame.body),
+                            Ok(OnlinePacket::ConnectionAccept(_))
+                        )
+                    }) {
+                        return;
+                    }
+                }
+            }
+        })
+        .await;
+
+        flooding.store(false, Ordering::Relaxed);
+        flood.join().unwrap();
+        assert!(answered.is_ok(), "the connection was starved by the flood");
+    });
+}
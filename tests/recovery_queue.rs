@@ -0,0 +1,164 @@
+//! The ring of a [`RecoveryQueue`] against a map doing the same, under random operations.
+use std::collections::HashMap;
+
+use proptest::collection::vec;
+use proptest::prelude::*;
+use rak_rs::connection::queue::{NetQueue, RecoveryQueue};
+use rak_rs::protocol::index::{unwrapped, DatagramSeq, U24_MAX};
+
+/// The operations start a little before the sequences wrap around.
+const BASE: DatagramSeq = DatagramSeq::new(U24_MAX - 1_000);
+
+/// How far past the base the sequences of the operations go, unless the queue is smaller.
+const SPREAD: u32 = 4_096;
+
+#[derive(Debug, Clone)]
+enum Op {
+    InsertId(u32, u32),
+    Insert(u32),
+    Remove(u32),
+    Get(u32),
+    HeldIn(u32, u32),
+    GetAll,
+    Flush,
+}
+
+/// An operation on the sequences up to `spread` past the base.
+fn op(spread: u32) -> impl Strategy<Value = Op> {
+    prop_oneof![
+        4 => (0..spread, any::<u32>()).prop_map(|(offset, item)| Op::InsertId(offset, item)),
+        1 => any::<u32>().prop_map(Op::Insert),
+        3 => (0..spread).prop_map(Op::Remove),
+        2 => (0..spread).prop_map(Op::Get),
+        2 => (0..spread, 0..spread).prop_map(|(first, last)| Op::HeldIn(first, last)),
+        1 => Just(Op::GetAll),
+        1 => Just(Op::Flush),
+    ]
+}
+
+/// What the queue holds, by sequence, with the eviction spelled out.
+struct Model {
+    held: HashMap<DatagramSeq, u32>,
+    capacity: usize,
+}
+
+impl Model {
+    fn insert(&mut self, seq: DatagramSeq, item: u32) {
+        let capacity = self.capacity;
+        let too_old = self
+            .held
+            .keys()
+            .any(|held| seq.is_before(*held) && seq.distance_to(*held) as usize >= capacity);
+        if too_old {
+            return;
+        }
+        self.held
+            .retain(|held, _| !(held.is_before(seq) && held.distance_to(seq) as usize >= capacity));
+        self.held.insert(seq, item);
+    }
+
+    fn held_in(&self, first: DatagramSeq, last: DatagramSeq) -> Vec<DatagramSeq> {
+        unwrapped(first, last)
+            .into_iter()
+            .flat_map(|(first, last)| {
+                let mut held = self
+                    .held
+                    .keys()
+                    .filter(|seq| (first..=last).contains(&seq.get()))
+                    .copied()
+                    .collect::<Vec<_>>();
+                held.sort_unstable();
+                held
+            })
+            .collect()
+    }
+
+    /// Everything held, the oldest first.
+    fn all(&self) -> Vec<(DatagramSeq, u32)> {
+        let mut all = self
+            .held
+            .iter()
+            .map(|(seq, item)| (*seq, *item))
+            .collect::<Vec<_>>();
+        all.sort_unstable_by_key(|(seq, _)| BASE.distance_to(*seq));
+        all
+    }
+}
+
+fn check(mut queue: RecoveryQueue<u32>, capacity: usize, ops: Vec<Op>) {
+    let mut model = Model {
+        held: HashMap::new(),
+        capacity,
+    };
+
+    for op in ops {
+        match op {
+            Op::InsertId(offset, item) => {
+                queue.insert_id(BASE.wrapping_add(offset), item);
+                model.insert(BASE.wrapping_add(offset), item);
+            }
+            Op::Insert(item) => {
+                let seq = queue.insert(item).unwrap();
+                assert_eq!(seq, DatagramSeq::new(model.held.len() as u32));
+                model.insert(seq, item);
+            }
+            Op::Remove(offset) => {
+                let seq = BASE.wrapping_add(offset);
+                assert_eq!(queue.remove(seq).ok(), model.held.remove(&seq));
+            }
+            Op::Get(offset) => {
+                let seq = BASE.wrapping_add(offset);
+                assert_eq!(queue.get(seq).ok(), model.held.get(&seq));
+            }
+            Op::HeldIn(first, last) => {
+                let (first, last) = (BASE.wrapping_add(first), BASE.wrapping_add(last));
+                assert_eq!(queue.held_in(first..=last), model.held_in(first, last));
+            }
+            Op::GetAll => assert_eq!(queue.get_all(), model.all()),
+            Op::Flush => {
+                let items = model.all().into_iter().map(|(_, item)| item);
+                assert_eq!(queue.flush().unwrap(), items.collect::<Vec<_>>());
+                model.held.clear();
+            }
+        }
+        assert_eq!(queue.len(), model.held.len());
+        assert_eq!(queue.is_empty(), model.held.is_empty());
+    }
+}
+
+proptest! {
+    #[test]
+    fn test_ring_matches_a_map(ops in vec(op(SPREAD), 1..300)) {
+        check(RecoveryQueue::new(), usize::MAX, ops);
+    }
+
+    // the sequences spread over twice the capacity, so that some but not all are evicted.
+    #[test]
+    fn test_capacity_evicts_like_a_map(
+        (capacity, ops) in (1usize..600).prop_flat_map(|capacity| {
+            (Just(capacity), vec(op(2 * capacity as u32), 1..300))
+        })
+    ) {
+        check(RecoveryQueue::with_capacity(capacity), capacity, ops);
+    }
+}
+
+#[test]
+fn test_capacity_evicts_the_oldest() {
+    let mut queue = RecoveryQueue::with_capacity(4);
+    for seq in [U24_MAX - 1, U24_MAX, 0, 1] {
+        queue.insert_id(DatagramSeq::new(seq), seq);
+    }
+
+    // five sequences would be too wide, so the oldest one goes.
+    queue.insert_id(DatagramSeq::new(2), 2);
+    assert_eq!(queue.len(), 4);
+    assert!(queue.get(DatagramSeq::new(U24_MAX - 1)).is_err());
+
+    // one that old would be too wide as well, it is not held.
+    queue.insert_id(DatagramSeq::new(U24_MAX - 1), 0);
+    assert_eq!(
+        queue.held_in(DatagramSeq::new(U24_MAX - 1)..=DatagramSeq::new(2)),
+        [U24_MAX, 0, 1, 2].map(DatagramSeq::new)
+    );
+}
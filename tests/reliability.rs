@@ -0,0 +1,253 @@
+#![cfg(feature = "async_std")]
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_std::{future::timeout, net::UdpSocket, task};
+use binary_util::interfaces::{Reader, Writer};
+use rak_rs::connection::controller::rtt::{INITIAL_RTO, MIN_RESEND_SPACING};
+use rak_rs::connection::queue::{RecvQueue, SendQueue};
+use rak_rs::protocol::ack::{Ack, Ackable};
+use rak_rs::protocol::frame::{FragmentMeta, Frame, FramePacket};
+use rak_rs::protocol::index::{DatagramSeq, SplitId, U24_MAX};
+use rak_rs::protocol::ranges::SequenceRanges;
+use rak_rs::Reliability;
+
+const ALL: [Reliability; 8] = [
+    Reliability::Unreliable,
+    Reliability::UnreliableSeq,
+    Reliability::Reliable,
+    Reliability::ReliableOrd,
+    Reliability::ReliableSeq,
+    Reliability::UnreliableAck,
+    Reliability::ReliableAck,
+    Reliability::ReliableOrdAck,
+];
+
+#[test]
+fn test_flags_round_trip() {
+    for reliability in ALL {
+        assert_eq!(Reliability::from_flags(reliability.to_flags()), reliability);
+        // the fragment bit and the unused bits don't change the reliability.
+        assert_eq!(
+            Reliability::from_flags(reliability.to_flags() | 0x1f),
+            reliability
+        );
+
+        let mut frame = Frame::new(reliability, Some(&[0xfe, 0x01]));
+        frame.order_channel = Some(3);
+        let decoded = Frame::read_from_slice(frame.write_to_bytes().unwrap().as_slice()).unwrap();
+        assert_eq!(decoded.reliability, reliability);
+        assert_eq!(decoded.body, vec![0xfe, 0x01]);
+    }
+}
+
+#[test]
+fn test_header_len_matches_the_encoding() {
+    for reliability in ALL {
+        for fragmented in [false, true] {
+            let mut frame = Frame::new(reliability, Some(&[0xfe; 10]));
+            if fragmented {
+                frame = frame.with_meta(FragmentMeta::new(2, SplitId::new(1), 0));
+            }
+            let encoded = frame.write_to_bytes().unwrap();
+            assert_eq!(frame.header_len() + 10, encoded.as_slice().len());
+        }
+    }
+}
+
+#[test]
+fn test_peer_receives_requested_reliability() {
+    task::block_on(async {
+        let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let mut send = SendQueue::new(1400, 0, 0, socket, peer.local_addr().unwrap());
+        let mut recv = RecvQueue::new();
+
+        for (i, reliability) in ALL.into_iter().enumerate() {
+            let body = vec![0xfe, i as u8];
+            send.insert(&body, reliability, true, Some(1))
+                .await
+                .unwrap();
+
+            let mut buf = [0u8; 2048];
+            let len = timeout(Duration::from_secs(5), peer.recv(&mut buf))
+                .await
+                .expect("nothing was sent")
+                .unwrap();
+            let datagram = FramePacket::read_from_slice(&buf[..len]).unwrap();
+            assert_eq!(datagram.frames.len(), 1);
+            let frame = &datagram.frames[0];
+            assert_eq!(
+                frame.reliability, reliability,
+                "the peer decoded a different reliability"
+            );
+            assert_eq!(frame.body, body);
+            assert_eq!(frame.reliable_index.is_some(), reliability.is_reliable());
+            assert_eq!(frame.sequence_index.is_some(), reliability.is_sequenced());
+            if reliability.is_sequenced_or_ordered() {
+                assert_eq!(frame.order_channel, Some(1));
+            }
+
+            recv.insert(datagram).unwrap();
+            assert_eq!(recv.flush(), vec![body]);
+        }
+    });
+}
+
+/// The datagrams the peer received within a short wait.
+async fn received(peer: &UdpSocket) -> Vec<FramePacket> {
+    let mut datagrams = Vec::new();
+    let mut buf = [0u8; 2048];
+    while let Ok(Ok(len)) = timeout(Duration::from_millis(50), peer.recv(&mut buf)).await {
+        datagrams.push(FramePacket::read_from_slice(&buf[..len]).unwrap());
+    }
+    datagrams
+}
+
+fn ack(sequence: DatagramSeq) -> Ack {
+    let mut ranges = SequenceRanges::new();
+    ranges.insert(sequence.into());
+    Ack::from_ranges(&ranges, false)
+}
+
+#[test]
+fn test_lost_datagram_is_resent_after_rto() {
+    task::block_on(async {
+        let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let mut send = SendQueue::new(1400, 0, 0, socket, peer.local_addr().unwrap());
+        let rtt = send.rtt_stats();
+
+        // the peer never acknowledges this one, as if it was lost.
+        send.insert(&[0xfe, 1], Reliability::Reliable, true, None)
+            .await
+            .unwrap();
+        let lost = received(&peer).await;
+        assert_eq!(lost.len(), 1);
+
+        // nothing is resent before the timeout.
+        send.update().await;
+        assert!(received(&peer).await.is_empty());
+
+        task::sleep(INITIAL_RTO).await;
+        send.update().await;
+        let resent = received(&peer).await;
+        assert_eq!(resent.len(), 1, "the lost datagram was not resent");
+        assert_eq!(resent[0].sequence, lost[0].sequence);
+        assert_eq!(resent[0].frames[0].body, vec![0xfe, 1]);
+        {
+            let stats = *rtt.lock().unwrap();
+            assert_eq!(stats.consecutive_timeouts, 1);
+            assert_eq!(stats.rto, INITIAL_RTO * 2);
+            assert_eq!(stats.rtt, None);
+        }
+
+        // a retransmission can't be timed, so acknowledging it only stops the resends.
+        send.ack(ack(lost[0].sequence));
+        assert_eq!(rtt.lock().unwrap().rtt, None);
+
+        // a datagram that is acknowledged the first time it was sent is timed.
+        send.insert(&[0xfe, 2], Reliability::Reliable, true, None)
+            .await
+            .unwrap();
+        let delivered = received(&peer).await;
+        assert_eq!(delivered.len(), 1);
+        send.ack(ack(delivered[0].sequence));
+
+        let stats = *rtt.lock().unwrap();
+        let sample = stats.rtt.expect("the acknowledgement was not sampled");
+        assert!(sample >= Duration::from_millis(40), "sampled {:?}", sample);
+        assert!(stats.rto < INITIAL_RTO * 2);
+        assert_eq!(stats.consecutive_timeouts, 0);
+
+        // everything is acknowledged, so nothing is ever resent.
+        task::sleep(INITIAL_RTO * 2).await;
+        send.update().await;
+        assert!(received(&peer).await.is_empty());
+        assert_eq!(send.pending(), 0);
+    });
+}
+
+fn nack(sequence: DatagramSeq) -> Ack {
+    let mut ranges = SequenceRanges::new();
+    ranges.insert(sequence.into());
+    Ack::from_ranges(&ranges, true)
+}
+
+#[test]
+fn test_rto_and_nack_resend_once() {
+    task::block_on(async {
+        let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let mut send = SendQueue::new(1400, 0, 0, socket, peer.local_addr().unwrap());
+        let stats = send.shared_stats();
+        let counters = || {
+            (
+                stats.timeout_retransmits.load(Ordering::Relaxed),
+                stats.nack_retransmits.load(Ordering::Relaxed),
+                stats.suppressed_retransmits.load(Ordering::Relaxed),
+            )
+        };
+
+        send.insert(&[0xfe, 1], Reliability::Reliable, true, None)
+            .await
+            .unwrap();
+        let lost = received(&peer).await;
+        assert_eq!(lost.len(), 1);
+
+        // a NACK for a datagram sent only once is always answered, the peer asking again
+        // before the resend could have reached it is not.
+        let missing = send.nack(nack(lost[0].sequence));
+        send.resend(missing).await;
+        assert_eq!(counters(), (0, 1, 0));
+        let missing = send.nack(nack(lost[0].sequence));
+        send.resend(missing).await;
+        assert_eq!(counters(), (0, 1, 1));
+        assert_eq!(received(&peer).await.len(), 1);
+
+        // the timeout expires, and the NACK for the same loss arrives in the same tick.
+        task::sleep(INITIAL_RTO.mul_f64(1.2)).await;
+        send.update().await;
+        let missing = send.nack(nack(lost[0].sequence));
+        assert_eq!(missing.len(), 1);
+        send.resend(missing).await;
+
+        let resent = received(&peer).await;
+        assert_eq!(resent.len(), 1, "the datagram was resent twice");
+        assert_eq!(resent[0].sequence, lost[0].sequence);
+        assert_eq!(counters(), (1, 1, 2));
+
+        // a round trip later, the next NACK is answered again.
+        task::sleep(MIN_RESEND_SPACING).await;
+        let missing = send.nack(nack(lost[0].sequence));
+        send.resend(missing).await;
+        assert_eq!(received(&peer).await.len(), 1);
+        assert_eq!(counters(), (1, 2, 2));
+    });
+}
+
+#[test]
+fn test_wide_records_only_visit_held_datagrams() {
+    task::block_on(async {
+        let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let mut send = SendQueue::new(1400, 0, 0, socket, peer.local_addr().unwrap());
+
+        for i in 0..3u8 {
+            send.insert(&[0xfe, i], Reliability::Reliable, true, None)
+                .await
+                .unwrap();
+        }
+        assert_eq!(received(&peer).await.len(), 3);
+
+        // a single record for every sequence there is, only the three held are resent.
+        let mut everything = SequenceRanges::new();
+        everything.insert_range(0, U24_MAX);
+        let missing = send.nack(Ack::from_ranges(&everything, true));
+        assert_eq!(missing.len(), 3);
+
+        send.ack(Ack::from_ranges(&everything, false));
+        assert_eq!(send.pending(), 0);
+    });
+}
@@ -0,0 +1,113 @@
+//! The receive window stays bounded whatever the peer sends, and keeps working across the wrap
+//! of the 24-bit datagram sequence.
+use rak_rs::connection::controller::window::{ReliableWindow, WindowError, DEFAULT_WINDOW_SIZE};
+use rak_rs::connection::descriptor::RecvCounters;
+use rak_rs::connection::queue::{RecvQueue, RecvQueueError};
+use rak_rs::protocol::frame::{Frame, FramePacket};
+use rak_rs::protocol::index::{DatagramSeq, U24_MAX};
+use rak_rs::protocol::reliability::Reliability;
+
+fn unreliable(sequence: u32) -> FramePacket {
+    let mut packet = FramePacket::new();
+    packet.sequence = DatagramSeq::new(sequence);
+    packet
+        .frames
+        .push(Frame::new(Reliability::Unreliable, Some(&[1, 2, 3])));
+    packet
+}
+
+fn nacked(queue: &RecvQueue) -> Vec<u32> {
+    queue.nack_queue().iter_ids().collect()
+}
+
+#[test]
+fn test_lost_unreliable_datagram_keeps_memory_bounded() {
+    let mut window = ReliableWindow::<DatagramSeq>::new();
+    let mut queue = RecvQueue::new();
+
+    // datagram 0 is lost, and never sent again.
+    for sequence in 1..=100_000 {
+        window.insert(DatagramSeq::new(sequence)).unwrap();
+        assert!(window.len() < DEFAULT_WINDOW_SIZE as usize);
+
+        queue.insert(unreliable(sequence)).unwrap();
+        assert_eq!(queue.flush().len(), 1);
+        assert!(queue.nack_queue().len() <= 1);
+    }
+
+    // the gap was given up on once it was the size of the window behind.
+    assert!(window.is_empty());
+    assert_eq!(window.range().0, DatagramSeq::new(100_001));
+    assert!(queue.nack_queue().is_empty());
+}
+
+#[test]
+fn test_window_crosses_the_wrap() {
+    let mut window = ReliableWindow::<DatagramSeq>::new();
+    window.reset(DatagramSeq::new(U24_MAX - 10));
+    for sequence in (U24_MAX - 10..=U24_MAX).chain(0..=10) {
+        window.insert(DatagramSeq::new(sequence)).unwrap();
+    }
+    assert_eq!(window.range().0, DatagramSeq::new(11));
+    assert_eq!(
+        window.insert(DatagramSeq::new(U24_MAX)),
+        Err(WindowError::Duplicate)
+    );
+
+    let mut queue = RecvQueue::new();
+    queue.restore_counters(&RecvCounters {
+        datagram_seq: U24_MAX - 2,
+        reliable_index: 0,
+        channels: Vec::new(),
+    });
+
+    // one datagram is lost on either side of the wrap.
+    for sequence in [U24_MAX - 2, U24_MAX, 1] {
+        queue.insert(unreliable(sequence)).unwrap();
+    }
+    assert_eq!(nacked(&queue), vec![0, U24_MAX - 1]);
+
+    queue.insert(unreliable(U24_MAX - 1)).unwrap();
+    assert_eq!(nacked(&queue), vec![0]);
+    queue.insert(unreliable(0)).unwrap();
+    assert!(queue.nack_queue().is_empty());
+    assert_eq!(queue.flush().len(), 5);
+}
+
+#[test]
+fn test_forged_sequence_does_not_stretch_window() {
+    let mut window = ReliableWindow::<DatagramSeq>::new();
+    let mut queue = RecvQueue::new();
+    for sequence in 0..10 {
+        window.insert(DatagramSeq::new(sequence)).unwrap();
+        queue.insert(unreliable(sequence)).unwrap();
+    }
+
+    // right behind the window, across the wrap.
+    assert_eq!(
+        window.insert(DatagramSeq::new(U24_MAX)),
+        Err(WindowError::Duplicate)
+    );
+    assert!(matches!(
+        queue.insert(unreliable(U24_MAX)),
+        Err(RecvQueueError::OldSeq)
+    ));
+
+    // as far ahead of the window as a sequence can be.
+    let forged = DatagramSeq::new(10 + U24_MAX / 2);
+    assert_eq!(window.insert(forged), Err(WindowError::TooFarAhead));
+    assert!(matches!(
+        queue.insert(unreliable(forged.get())),
+        Err(RecvQueueError::TooFarAhead)
+    ));
+
+    assert_eq!(window.range().0, DatagramSeq::new(10));
+    assert!(window.is_empty());
+    assert!(queue.nack_queue().is_empty());
+
+    // the datagrams that are actually sent next are still taken.
+    window.insert(DatagramSeq::new(10)).unwrap();
+    queue.insert(unreliable(10)).unwrap();
+    queue.insert(unreliable(12)).unwrap();
+    assert_eq!(nacked(&queue), vec![11]);
+}
@@ -0,0 +1,133 @@
+#![cfg(feature = "async_std")]
+//! The spread of the retry timers, drawn from seeded providers so a failure is reproducible
+//! from the seed in its message.
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_std::{net::UdpSocket, task};
+use rak_rs::connection::controller::jitter::{RngProvider, HANDSHAKE_RESEND_JITTER, RTO_JITTER};
+use rak_rs::connection::controller::rtt::INITIAL_RTO;
+use rak_rs::connection::queue::SendQueue;
+use rak_rs::protocol::reliability::Reliability;
+
+const SEED: u64 = 0x52414b;
+const CASES: usize = 10_000;
+
+fn address(port: u16) -> SocketAddr {
+    SocketAddr::from(([10, 0, 0, 1], port))
+}
+
+#[test]
+fn test_jitter_stays_within_its_bounds() {
+    let mut rng = RngProvider::seeded(SEED);
+    let interval = Duration::from_secs(2);
+    let (mut below, mut above) = (false, false);
+
+    for case in 0..CASES {
+        let resend = rng.jitter(interval, HANDSHAKE_RESEND_JITTER);
+        assert!(
+            resend >= interval.mul_f64(0.75) && resend <= interval.mul_f64(1.25),
+            "case {}: {:?}",
+            case,
+            resend
+        );
+        below |= resend < interval.mul_f64(0.8);
+        above |= resend > interval.mul_f64(1.2);
+
+        let factor = rng.factor(RTO_JITTER);
+        assert!((0.9..=1.1).contains(&factor), "case {}: {}", case, factor);
+
+        let full = rng.full_jitter(interval);
+        assert!(full <= interval, "case {}: {:?}", case, full);
+
+        let attempt = (case % 12) as u32;
+        let cap = Duration::from_secs(30);
+        let wait = rng.backoff(Duration::from_millis(100), attempt, cap);
+        assert!(
+            wait <= Duration::from_millis(100 << attempt).min(cap),
+            "case {}: {:?} for attempt {}",
+            case,
+            wait,
+            attempt
+        );
+    }
+    assert!(below && above, "the whole spread is used");
+
+    assert_eq!(rng.factor(0.0), 1.0);
+    assert!(
+        (0.0..=2.0).contains(&rng.factor(5.0)),
+        "the spread is clamped"
+    );
+    assert!(
+        rng.backoff(Duration::from_secs(1), u32::MAX, Duration::from_secs(60))
+            <= Duration::from_secs(60)
+    );
+}
+
+#[test]
+fn test_connections_draw_their_own_timers() {
+    let timers = |mut rng: RngProvider| {
+        (0..8)
+            .map(|_| rng.jitter(Duration::from_secs(2), HANDSHAKE_RESEND_JITTER))
+            .collect::<Vec<_>>()
+    };
+
+    // reproducible with a seed, while the addresses keep connections apart.
+    let first = timers(RngProvider::for_address(Some(SEED), address(1)));
+    assert_eq!(
+        first,
+        timers(RngProvider::for_address(Some(SEED), address(1)))
+    );
+    assert_ne!(
+        first,
+        timers(RngProvider::for_address(Some(SEED), address(2)))
+    );
+    assert_ne!(
+        first,
+        timers(RngProvider::for_address(Some(SEED + 1), address(1)))
+    );
+    assert_ne!(
+        timers(RngProvider::for_address(None, address(1))),
+        timers(RngProvider::for_address(None, address(1)))
+    );
+
+    let mut parent = RngProvider::seeded(SEED);
+    assert_ne!(timers(parent.fork()), timers(parent.fork()));
+}
+
+/// When the single reliable datagram sent by a queue drawing from `rng` is due again.
+async fn retransmit_after(rng: RngProvider) -> Duration {
+    let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+    let mut send_q = SendQueue::new(1400, 12000, 5, socket, peer.local_addr().unwrap());
+    send_q.set_rng(rng);
+
+    send_q
+        .insert(&[0xfe, 1, 2, 3], Reliability::Reliable, true, None)
+        .await
+        .unwrap();
+    send_q.next_retransmit(Instant::now()).unwrap()
+}
+
+#[test]
+fn test_retransmission_timeout_is_jittered() {
+    task::block_on(async {
+        let mut deadlines = Vec::new();
+        for port in 0..8 {
+            let due = retransmit_after(RngProvider::for_address(Some(SEED), address(port))).await;
+            // the send itself takes a moment of the timeout.
+            assert!(
+                due <= INITIAL_RTO.mul_f64(1.0 + RTO_JITTER)
+                    && due + Duration::from_millis(100) >= INITIAL_RTO.mul_f64(1.0 - RTO_JITTER),
+                "port {}: {:?}",
+                port,
+                due
+            );
+            deadlines.push(due);
+        }
+        deadlines.sort();
+        deadlines.dedup_by(|a, b| a.abs_diff(*b) < Duration::from_millis(5));
+        assert!(deadlines.len() > 1, "every connection retransmits together");
+    });
+}
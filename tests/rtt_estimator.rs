@@ -0,0 +1,60 @@
+use std::time::Duration;
+
+use rak_rs::connection::controller::rtt::{RttEstimator, INITIAL_RTO, MAX_RTO, MIN_RTO};
+
+#[test]
+fn test_no_samples() {
+    let rtt = RttEstimator::new();
+    let stats = rtt.stats();
+
+    assert_eq!(stats.rtt, None);
+    assert_eq!(stats.rtt_variance, None);
+    assert_eq!(stats.rto, INITIAL_RTO);
+    assert_eq!(stats.consecutive_timeouts, 0);
+}
+
+#[test]
+fn test_variance_tracks_jitter() {
+    let mut rtt = RttEstimator::new();
+
+    // 100ms with +-20ms of jitter.
+    for i in 0..200 {
+        let sample = if i % 2 == 0 { 80 } else { 120 };
+        rtt.sample(Duration::from_millis(sample));
+    }
+
+    let stats = rtt.stats();
+    let mean = stats.rtt.unwrap().as_millis();
+    let variance = stats.rtt_variance.unwrap().as_millis();
+
+    assert!((95..=105).contains(&mean), "rtt was {}ms", mean);
+    assert!((15..=25).contains(&variance), "variance was {}ms", variance);
+    assert!(stats.rto >= MIN_RTO && stats.rto <= MAX_RTO);
+
+    // a steady link settles the variance down.
+    for _ in 0..200 {
+        rtt.sample(Duration::from_millis(100));
+    }
+
+    assert!(rtt.stats().rtt_variance.unwrap() < Duration::from_millis(2));
+}
+
+#[test]
+fn test_timeouts_back_off() {
+    let mut rtt = RttEstimator::new();
+    rtt.sample(Duration::from_millis(200));
+    let rto = rtt.rto();
+
+    rtt.timeout();
+    rtt.timeout();
+    assert_eq!(rtt.rto(), rto * 4);
+    assert_eq!(rtt.stats().consecutive_timeouts, 2);
+
+    for _ in 0..16 {
+        rtt.timeout();
+    }
+    assert_eq!(rtt.rto(), MAX_RTO);
+
+    rtt.sample(Duration::from_millis(200));
+    assert_eq!(rtt.stats().consecutive_timeouts, 0);
+}
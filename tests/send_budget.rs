@@ -0,0 +1,157 @@
+use std::time::{Duration, Instant};
+
+use rak_rs::connection::controller::budget::{SendBudget, BUDGET_BURST, DEFAULT_SEND_OVERDRAFT};
+
+const RATE: u64 = 200_000;
+const DATAGRAM: usize = 1200;
+const TICK: Duration = Duration::from_millis(50);
+
+/// Runs three connections that always have something to send for the given time,
+/// returning the bytes each of them sent.
+fn bulk(budget: &mut SendBudget, start: Instant, run: Duration, rotate: bool) -> [u64; 3] {
+    let ids = [budget.register(), budget.register(), budget.register()];
+    let mut sent = [0u64; 3];
+    let ticks = (run.as_millis() / TICK.as_millis()) as u32;
+
+    for tick in 0..ticks {
+        let now = start + TICK * tick;
+        for turn in 0..3 {
+            let i = if rotate {
+                (turn + tick as usize) % 3
+            } else {
+                turn
+            };
+            let grant = budget.grant(ids[i], now);
+            let mut spent = 0;
+            while spent < grant {
+                budget.spend(ids[i], DATAGRAM, now);
+                spent += DATAGRAM;
+            }
+            sent[i] += spent as u64;
+        }
+    }
+    sent
+}
+
+fn assert_within(actual: f64, expected: f64, tolerance: f64, what: &str) {
+    assert!(
+        (actual - expected).abs() <= expected * tolerance,
+        "{} was {}, expected {} ±{}%",
+        what,
+        actual,
+        expected,
+        tolerance * 100.0
+    );
+}
+
+#[test]
+fn test_budget_is_shared_fairly() {
+    for rotate in [true, false] {
+        let start = Instant::now();
+        let run = Duration::from_secs(20);
+        let mut budget = SendBudget::new(RATE, DEFAULT_SEND_OVERDRAFT, start);
+        let sent = bulk(&mut budget, start, run, rotate);
+
+        let total: u64 = sent.iter().sum();
+        assert_within(
+            total as f64 / run.as_secs_f64(),
+            RATE as f64,
+            0.05,
+            "the aggregate rate",
+        );
+
+        let mean = total as f64 / 3.0;
+        for (i, sent) in sent.iter().enumerate() {
+            assert_within(
+                *sent as f64,
+                mean,
+                0.2,
+                &format!("connection {} (rotating: {})", i, rotate),
+            );
+        }
+
+        let stats = budget.stats(start + run);
+        assert_eq!(stats.sent, total);
+        assert_within(stats.utilization as f64, 1.0, 0.05, "the utilization");
+        assert!(stats.throttled > Duration::ZERO);
+    }
+}
+
+#[test]
+fn test_single_connection_uses_the_whole_budget() {
+    let start = Instant::now();
+    let mut budget = SendBudget::new(RATE, DEFAULT_SEND_OVERDRAFT, start);
+    let id = budget.register();
+
+    // an idle bucket allows a burst of one tick.
+    let burst = (RATE as f64 * BUDGET_BURST.as_secs_f64()) as usize;
+    assert_eq!(budget.grant(id, start), burst);
+    assert!(!budget.is_contended(start));
+
+    budget.spend(id, burst, start);
+    assert!(budget.is_contended(start));
+    assert_eq!(budget.grant(id, start), 0);
+    assert_eq!(budget.grant(id, start + TICK), burst);
+}
+
+#[test]
+fn test_immediate_frames_borrow_up_to_the_overdraft() {
+    let start = Instant::now();
+    let mut budget = SendBudget::new(RATE, 4000, start);
+    let id = budget.register();
+    let burst = budget.grant(id, start);
+    budget.spend(id, burst, start);
+
+    assert!(budget.borrow(3000, start));
+    assert!(budget.borrow(1000, start));
+    assert!(!budget.borrow(1, start));
+
+    // the borrowed bytes are paid back before anything else is granted.
+    assert_eq!(budget.grant(id, start + TICK), 0);
+    assert!(budget.grant(id, start + TICK * 2) > 0);
+}
+
+#[cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+#[test]
+fn test_listener_stays_under_its_budget() {
+    use async_std::{future::timeout, task};
+    use rak_rs::{Client, Listener};
+
+    task::block_on(async {
+        let mut server = Listener::bind("127.0.0.1:19191").await.unwrap();
+        server.global_send_budget = Some(20_000);
+        assert!(server.send_budget_stats().is_none());
+        server.start().await.unwrap();
+
+        let client = Client::new(11, 1400);
+        timeout(Duration::from_secs(10), client.connect("127.0.0.1:19191"))
+            .await
+            .expect("connect timed out")
+            .expect("failed to connect");
+        let conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let started = Instant::now();
+        for i in 0..60u8 {
+            let mut payload = vec![0xfe, i];
+            payload.resize(1000, i);
+            conn.send(&payload, false).await.unwrap();
+        }
+        for i in 0..60u8 {
+            let received = timeout(Duration::from_secs(10), client.recv())
+                .await
+                .expect("the budget stalled the connection")
+                .unwrap();
+            assert_eq!(received[1], i);
+        }
+
+        // 60 KB at 20 KB/s can't arrive much sooner than the budget refills.
+        assert!(started.elapsed() >= Duration::from_secs(2));
+        let stats = server.send_budget_stats().unwrap();
+        assert_eq!(stats.budget, 20_000);
+        assert!(stats.sent >= 60_000);
+        assert!(stats.throttled > Duration::ZERO);
+    });
+}
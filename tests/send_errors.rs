@@ -0,0 +1,155 @@
+#![cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+//! Scripts the errors a struggling socket returns and checks that sending recovers from them.
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_std::{future::timeout, net::UdpSocket, task};
+use binary_util::interfaces::Reader;
+use rak_rs::connection::queue::{RecvQueue, SendQueue, ENOBUFS_BACKOFF};
+use rak_rs::connection::state::ConnectionState;
+use rak_rs::protocol::frame::FramePacket;
+use rak_rs::socket::{EMSGSIZE, ENOBUFS};
+use rak_rs::{Client, Listener, Reliability};
+
+async fn mock() -> (SendQueue, UdpSocket) {
+    let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+    let queue = SendQueue::new(1400, 12000, 5, socket, peer.local_addr().unwrap());
+    (queue, peer)
+}
+
+/// The raw datagrams the peer received so far.
+async fn received(peer: &UdpSocket) -> Vec<Vec<u8>> {
+    let mut datagrams = Vec::new();
+    let mut buf = [0u8; 2048];
+    while let Ok(Ok(len)) = timeout(Duration::from_millis(50), peer.recv(&mut buf)).await {
+        datagrams.push(buf[..len].to_vec());
+    }
+    datagrams
+}
+
+fn sequences(datagrams: &[Vec<u8>]) -> Vec<u32> {
+    datagrams
+        .iter()
+        .map(|buf| FramePacket::read_from_slice(buf).unwrap().sequence.get())
+        .collect()
+}
+
+async fn burst(queue: &mut SendQueue) {
+    for i in 0..3u8 {
+        queue
+            .insert(&[0xfe, i], Reliability::ReliableOrd, false, None)
+            .await
+            .unwrap();
+    }
+}
+
+#[test]
+fn test_enobufs_holds_back_the_whole_flush() {
+    task::block_on(async {
+        let (mut queue, peer) = mock().await;
+        queue.fail_sends([ENOBUFS]);
+
+        burst(&mut queue).await;
+        queue.update().await;
+        assert!(received(&peer).await.is_empty());
+        assert_eq!(queue.enobufs_backoffs(), 1);
+
+        // the same datagrams go out on the next tick, in order.
+        task::sleep(ENOBUFS_BACKOFF).await;
+        queue.update().await;
+        assert_eq!(sequences(&received(&peer).await), vec![0, 1, 2]);
+
+        // nothing was lost, so nothing counts as a retransmission.
+        assert_eq!(queue.rtt_stats().lock().unwrap().consecutive_timeouts, 0);
+        assert_eq!(queue.pending(), 3);
+    });
+}
+
+#[test]
+fn test_enobufs_backs_off_again() {
+    task::block_on(async {
+        let (mut queue, peer) = mock().await;
+        queue.fail_sends([ENOBUFS, ENOBUFS]);
+
+        burst(&mut queue).await;
+        queue.update().await;
+        task::sleep(ENOBUFS_BACKOFF).await;
+        queue.update().await;
+        assert!(received(&peer).await.is_empty());
+        assert_eq!(queue.enobufs_backoffs(), 2);
+
+        // acknowledgements wait their turn behind the datagrams held back.
+        let mut recv_q = RecvQueue::new();
+        recv_q.insert(FramePacket::new()).unwrap();
+        assert_eq!(queue.send_acks(&mut recv_q).await, 1);
+        task::sleep(ENOBUFS_BACKOFF).await;
+        queue.update().await;
+        let datagrams = received(&peer).await;
+        assert_eq!(datagrams.len(), 4);
+        assert_eq!(sequences(&datagrams[..3]), vec![0, 1, 2]);
+        assert_eq!(datagrams[3][0], 0xc0);
+    });
+}
+
+#[test]
+fn test_emsgsize_steps_the_mtu_down() {
+    task::block_on(async {
+        let (mut queue, peer) = mock().await;
+        queue.fail_sends([EMSGSIZE]);
+
+        queue
+            .insert(&[0xfe; 1300], Reliability::ReliableOrd, false, None)
+            .await
+            .unwrap();
+        queue.update().await;
+        assert!(received(&peer).await.is_empty());
+        assert_eq!(queue.mtu_size(), 1200);
+        assert_eq!(queue.enobufs_backoffs(), 0);
+
+        // new messages are split for the route as it is now.
+        queue
+            .insert(&[0xfe; 1300], Reliability::ReliableOrd, false, None)
+            .await
+            .unwrap();
+        queue.update().await;
+        let datagrams = received(&peer).await;
+        assert!(datagrams.len() > 1);
+        assert!(datagrams.iter().all(|buf| buf.len() + 28 <= 1200));
+    });
+}
+
+#[test]
+fn test_connection_survives_a_struggling_socket() {
+    task::block_on(async {
+        const ADDRESS: &str = "127.0.0.1:19220";
+        let mut server = Listener::bind(ADDRESS).await.unwrap();
+        server.start().await.unwrap();
+
+        let client = Client::new(11, 1400);
+        timeout(Duration::from_secs(10), client.connect(ADDRESS))
+            .await
+            .expect("connect timed out")
+            .expect("failed to connect");
+        let conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+
+        conn.fail_sends([ENOBUFS, ENOBUFS, EMSGSIZE, ENOBUFS]).await;
+        for i in 0..10u8 {
+            conn.send(&[0xfe, i], false).await.unwrap();
+            task::sleep(Duration::from_millis(20)).await;
+        }
+
+        for i in 0..10u8 {
+            let packet = timeout(Duration::from_secs(5), client.recv())
+                .await
+                .expect("a message was lost")
+                .unwrap();
+            assert_eq!(packet, vec![0xfe, i]);
+        }
+        assert!(conn.stats().await.enobufs_backoffs >= 1);
+        assert_eq!(*conn.state.lock().await, ConnectionState::Connected);
+    });
+}
@@ -0,0 +1,111 @@
+use std::collections::BTreeSet;
+
+use rak_rs::connection::queue::{OrderedQueue, RecoveryQueue};
+use rak_rs::protocol::ack::{Ack, RangeRecord, Record};
+use rak_rs::protocol::index::{DatagramSeq, OrderIndex, U24_MAX};
+use rak_rs::protocol::ranges::SequenceRanges;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+#[test]
+fn test_matches_btreeset() {
+    let mut rng = StdRng::seed_from_u64(0x5eed);
+
+    for _ in 0..50 {
+        let mut ranges = SequenceRanges::new();
+        let mut set = BTreeSet::<u32>::new();
+
+        for _ in 0..500 {
+            match rng.gen_range(0..5) {
+                0 => {
+                    let id = rng.gen_range(0..300);
+                    assert_eq!(ranges.insert(id), set.insert(id));
+                }
+                1 => {
+                    let start = rng.gen_range(0..300);
+                    let end = rng.gen_range(0..300);
+                    ranges.insert_range(start, end);
+                    set.extend(start.min(end)..=start.max(end));
+                }
+                2 => {
+                    let id = rng.gen_range(0..300);
+                    assert_eq!(ranges.remove(id), set.remove(&id));
+                }
+                3 => {
+                    let start = rng.gen_range(0..300);
+                    let end = rng.gen_range(0..300);
+                    ranges.remove_range(start, end);
+                    set.retain(|id| !(start.min(end)..=start.max(end)).contains(id));
+                }
+                _ => {
+                    let id = rng.gen_range(0..300);
+                    assert_eq!(ranges.contains(id), set.contains(&id));
+                }
+            }
+
+            assert_eq!(ranges.len(), set.len());
+        }
+
+        assert_eq!(
+            ranges.iter_ids().collect::<Vec<u32>>(),
+            set.iter().copied().collect::<Vec<u32>>()
+        );
+
+        // the ranges must be sorted, and never touch each other.
+        let stored = ranges.iter_ranges().collect::<Vec<_>>();
+        for pair in stored.windows(2) {
+            assert!(pair[0].end() + 1 < *pair[1].start());
+        }
+    }
+}
+
+#[test]
+fn test_large_gap_single_range() {
+    let mut ranges = SequenceRanges::new();
+    ranges.insert_range(10_000, 109_999);
+
+    assert_eq!(ranges.len(), 100_000);
+    assert_eq!(ranges.range_count(), 1);
+
+    let mut ord_qu: OrderedQueue<Vec<u8>> = OrderedQueue::new();
+    ord_qu.insert(OrderIndex::new(0), vec![0]);
+    ord_qu.insert(OrderIndex::new(100_001), vec![1]);
+
+    let missing = ord_qu.missing();
+    assert_eq!(missing.len(), 100_000);
+    assert_eq!(missing.range_count(), 1);
+    assert_eq!(missing.iter_ranges().next(), Some(1..=100_000));
+}
+
+#[test]
+fn test_ack_record_wraps_around() {
+    // 0xfffffe, 0xffffff, 0 and 1, as a peer whose sequence wrapped acknowledges them.
+    let ack = Ack::new(
+        1,
+        false,
+        vec![Record::Range(RangeRecord {
+            start: DatagramSeq::new(0xff_fffe),
+            end: DatagramSeq::new(1),
+        })],
+    );
+    assert_eq!(
+        ack.sequence_ranges().collect::<Vec<_>>(),
+        vec![
+            DatagramSeq::new(0xff_fffe)..=DatagramSeq::new(U24_MAX),
+            DatagramSeq::new(0)..=DatagramSeq::new(1),
+        ]
+    );
+    let ranges = ack.ranges();
+    assert_eq!(ranges.len(), 4);
+    assert!(ranges.contains(U24_MAX) && ranges.contains(0));
+    assert!(!ranges.contains(2) && !ranges.contains(0xff_fffd));
+
+    let mut queue = RecoveryQueue::new();
+    for seq in [0xff_fffd, 0xff_ffff, 0, 5] {
+        queue.insert_id(DatagramSeq::new(seq), ());
+    }
+    assert_eq!(
+        queue.held_in(DatagramSeq::new(0xff_fffe)..=DatagramSeq::new(1)),
+        vec![DatagramSeq::new(U24_MAX), DatagramSeq::new(0)]
+    );
+}
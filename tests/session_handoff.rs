@@ -0,0 +1,96 @@
+#![cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+use std::time::Duration;
+
+use async_std::{future::timeout, task};
+use binary_util::interfaces::{Reader, Writer};
+use binary_util::ByteReader;
+use rak_rs::connection::descriptor::SessionDescriptor;
+use rak_rs::{Client, Connection, Listener, RakEvent, Reliability};
+
+const ADDRESS: &str = "127.0.0.1:19180";
+
+async fn exchange(client: &mut Client, conn: &mut Connection, ids: std::ops::Range<u32>) {
+    for i in ids.clone() {
+        let mut buf = vec![0xfe];
+        buf.extend_from_slice(&i.to_be_bytes());
+        client
+            .send(&buf, Reliability::ReliableOrd, 0)
+            .await
+            .unwrap();
+        conn.send(&buf, false).await.unwrap();
+    }
+
+    for i in ids {
+        let packet = timeout(Duration::from_secs(5), conn.recv())
+            .await
+            .expect("the server never received the stream")
+            .unwrap();
+        assert_eq!(u32::from_be_bytes(packet[1..5].try_into().unwrap()), i);
+
+        let packet = timeout(Duration::from_secs(5), client.recv())
+            .await
+            .expect("the client never received the stream")
+            .unwrap();
+        assert_eq!(u32::from_be_bytes(packet[1..5].try_into().unwrap()), i);
+    }
+}
+
+#[test]
+fn test_session_handoff() {
+    task::block_on(async {
+        let mut old = Listener::bind(ADDRESS).await.unwrap();
+        old.start().await.unwrap();
+
+        let mut client = Client::new(11, 1400);
+        timeout(Duration::from_secs(10), client.connect(ADDRESS))
+            .await
+            .expect("connect timed out")
+            .expect("failed to connect");
+        let mut conn = timeout(Duration::from_secs(5), old.accept())
+            .await
+            .unwrap()
+            .unwrap();
+
+        exchange(&mut client, &mut conn, 0..50).await;
+
+        let sessions = old.export_sessions().await;
+        assert_eq!(sessions.len(), 1);
+        old.stop().await.unwrap();
+        drop(conn);
+        drop(old);
+
+        // the descriptors are passed over as bytes, like they would be to another process.
+        let bytes = sessions[0].write_to_bytes().unwrap();
+        let descriptor = SessionDescriptor::read(&mut ByteReader::from(bytes.as_slice())).unwrap();
+        assert_eq!(descriptor, sessions[0]);
+
+        let mut new = None;
+        for _ in 0..50 {
+            if let Ok(listener) = Listener::bind(ADDRESS).await {
+                new = Some(listener);
+                break;
+            }
+            task::sleep(Duration::from_millis(20)).await;
+        }
+        let mut new = new.expect("the old listener never released its socket");
+        new.import_sessions(vec![descriptor]).unwrap();
+        new.start().await.unwrap();
+
+        let mut conn = timeout(Duration::from_secs(5), new.accept())
+            .await
+            .expect("the imported session was never handed out")
+            .unwrap();
+        assert_eq!(conn.address, sessions[0].address);
+
+        exchange(&mut client, &mut conn, 50..100).await;
+
+        if let Ok(Ok(RakEvent::Disconnected { reason })) =
+            timeout(Duration::from_millis(10), client.recv_event()).await
+        {
+            panic!(
+                "the client was disconnected during the handoff: {:?}",
+                reason
+            );
+        }
+    });
+}
@@ -0,0 +1,141 @@
+//! Clients and a server driven by [`SimWorld`], every run of a seed is identical, so a failure is
+//! reproducible from the seed in its message.
+use std::time::Duration;
+
+use rak_rs::{
+    connection::controller::loss::LossBySize,
+    protocol::{frame::FramePacket, RAKNET_HEADER_OVERHEAD},
+    simulation::{NodeId, SimEvent, SimWorld, SIM_MTU},
+    Reliability,
+};
+
+const SEEDS: [u64; 4] = [0x5eed, 0xbad5eed, 0x1234_5678, 0xdead_beef];
+
+/// A distinct user packet per message, long enough that the large ones get fragmented.
+fn payload(i: usize) -> Vec<u8> {
+    let len = if i % 25 == 0 { 4_000 } else { 64 + i % 300 };
+    std::iter::once(0xfe)
+        .chain((1..len).map(|b| (i + b) as u8))
+        .collect()
+}
+
+/// Sends `count` reliable-ordered messages both ways over a link losing `loss` of all datagrams
+/// once the client connected.
+fn bulk_transfer(seed: u64, loss: f64, count: usize) -> SimWorld {
+    let mut world = SimWorld::new(seed);
+    world.set_latency(Duration::from_millis(20), Duration::from_millis(15));
+    let server = world.add_server();
+    let client = world.add_client(server);
+    world.set_loss(loss);
+
+    for i in 0..count {
+        world.send(client, server, &payload(i), Reliability::ReliableOrd, 0);
+        world.send(server, client, &payload(i), Reliability::ReliableOrd, 1);
+        if i % 10 == 0 {
+            world.step(Duration::from_millis(50));
+        }
+    }
+    world.step(Duration::from_secs(30));
+    world
+}
+
+#[test]
+fn test_lossy_bulk_transfer() {
+    let expected = (0..200).map(payload).collect::<Vec<_>>();
+    for seed in SEEDS {
+        let world = bulk_transfer(seed, 0.1, expected.len());
+        let (server, client) = (NodeId(0), NodeId(1));
+        world.assert_delivered(server, client, &expected);
+        world.assert_delivered(client, server, &expected);
+        assert!(
+            world
+                .transcript()
+                .iter()
+                .any(|event| matches!(event, SimEvent::Lost { .. })),
+            "seed {:#x}: nothing was lost",
+            seed
+        );
+    }
+}
+
+#[test]
+fn test_same_seed_same_transcript() {
+    let first = bulk_transfer(0x5eed, 0.2, 60);
+    let second = bulk_transfer(0x5eed, 0.2, 60);
+    assert!(!first.transcript().is_empty());
+    assert_eq!(first.transcript(), second.transcript());
+
+    let other = bulk_transfer(0xbad5eed, 0.2, 60);
+    assert_ne!(first.transcript(), other.transcript());
+}
+
+/// The size of `packet` on the wire as the MTU counts it.
+fn wire_size(packet: &FramePacket) -> usize {
+    packet
+        .frames
+        .iter()
+        .map(|frame| frame.header_len() + frame.body.len())
+        .sum::<usize>()
+        + RAKNET_HEADER_OVERHEAD as usize
+}
+
+/// A user packet of `len` bytes.
+fn message(i: u8, len: usize) -> Vec<u8> {
+    let mut message = vec![i; len];
+    message[0] = 0xfe;
+    message
+}
+
+/// Sends small and large messages from a client at once, over a link set up by `link`.
+fn mixed_sizes(
+    seed: u64,
+    link: impl FnOnce(&mut SimWorld, NodeId, NodeId),
+) -> (SimWorld, NodeId, NodeId) {
+    let mut world = SimWorld::new(seed);
+    let server = world.add_server();
+    let client = world.add_client(server);
+    link(&mut world, client, server);
+
+    for i in 0..40u8 {
+        world.send(client, server, &message(i, 100), Reliability::Reliable, 0);
+        world.send(client, server, &message(i, 1300), Reliability::Reliable, 0);
+    }
+    world.step(Duration::from_secs(5));
+    (world, client, server)
+}
+
+#[test]
+fn test_loss_by_size_shows_a_size_filter() {
+    for seed in SEEDS {
+        // the path drops every datagram over 1000 bytes, the small ones get through.
+        let (world, client, server) = mixed_sizes(seed, |world, client, server| {
+            world.drop_datagrams(client, server, |packet| wire_size(packet) > 1000)
+        });
+        let loss = world.stats(client, server).loss_by_size;
+        let (small, large) = (LossBySize::bucket(160), LossBySize::bucket(1360));
+        // the pings of the connection are small too.
+        assert!(loss.sent[small] >= 40, "seed {:#x}", seed);
+        assert_eq!(loss.sent[large], 40, "seed {:#x}", seed);
+        assert_eq!(loss.ratio(small), 0.0, "seed {:#x}", seed);
+        assert_eq!(loss.ratio(large), 1.0, "seed {:#x}", seed);
+
+        // the detector took the skew for a black hole.
+        assert!(world.mtu(client, server) < SIM_MTU, "seed {:#x}", seed);
+    }
+}
+
+#[test]
+fn test_loss_of_any_size_keeps_the_mtu() {
+    for seed in SEEDS {
+        let (world, client, server) = mixed_sizes(seed, |world, _, _| world.set_loss(0.2));
+        let loss = world.stats(client, server).loss_by_size;
+        assert!(
+            loss.retransmitted.iter().sum::<u64>() > 0,
+            "seed {:#x}",
+            seed
+        );
+
+        // the large datagrams are lost as much as the small ones, that's no black hole.
+        assert_eq!(world.mtu(client, server), SIM_MTU, "seed {:#x}", seed);
+    }
+}
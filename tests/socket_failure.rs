@@ -0,0 +1,105 @@
+#![cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+//! A listener whose socket fails for good closes its connections and stops, rather than
+//! spinning on the errors.
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use async_std::{future::timeout, net::UdpSocket, task};
+use rak_rs::connection::event::{DisconnectReason, RakEvent};
+use rak_rs::error::server::ServerError;
+use rak_rs::socket::is_fatal_recv_error;
+use rak_rs::{Client, Listener};
+
+/// `EBADF`, what every receive fails with once the descriptor was closed out from under it.
+const EBADF: i32 = 9;
+
+#[test]
+fn test_fatal_errors_are_told_apart() {
+    use std::io::{Error, ErrorKind};
+
+    assert!(is_fatal_recv_error(&Error::from_raw_os_error(EBADF)));
+    assert!(is_fatal_recv_error(&Error::from(ErrorKind::NotConnected)));
+    for kind in [
+        ErrorKind::ConnectionReset,
+        ErrorKind::ConnectionRefused,
+        ErrorKind::WouldBlock,
+        ErrorKind::Interrupted,
+    ] {
+        assert!(!is_fatal_recv_error(&Error::from(kind)), "{:?}", kind);
+    }
+}
+
+#[test]
+fn test_failed_socket_closes_every_connection() {
+    task::block_on(async {
+        let address: SocketAddr = "127.0.0.1:19239".parse().unwrap();
+        let mut server = Listener::bind(address).await.unwrap();
+        server.start().await.unwrap();
+
+        let mut clients = Vec::new();
+        let mut conns = Vec::new();
+        for _ in 0..3 {
+            let client = Client::new(11, 1400);
+            timeout(Duration::from_secs(10), client.connect(address))
+                .await
+                .expect("connect timed out")
+                .expect("failed to connect");
+            conns.push(
+                timeout(Duration::from_secs(5), server.accept())
+                    .await
+                    .unwrap()
+                    .unwrap(),
+            );
+            clients.push(client);
+        }
+
+        server.fail_recvs(EBADF);
+        // the receive in progress still gets its datagram, the ones after it fail.
+        clients[0].send_ord(&[0xfe, 1], 0).await.unwrap();
+
+        let failed = ServerError::SocketFailed(std::io::Error::from_raw_os_error(EBADF).kind());
+        assert_eq!(
+            timeout(Duration::from_secs(5), server.wait_stopped())
+                .await
+                .expect("the listener never gave up on its socket"),
+            Err(failed)
+        );
+        assert_eq!(server.accept().await.err(), Some(failed));
+        assert_eq!(server.recv_event().await.err(), Some(failed));
+
+        for conn in &conns {
+            assert_eq!(
+                timeout(Duration::from_secs(1), conn.closed())
+                    .await
+                    .unwrap(),
+                DisconnectReason::SocketFailure
+            );
+            let disconnected = timeout(Duration::from_secs(1), async {
+                while let Ok(event) = conn.recv_event().await {
+                    if let RakEvent::Disconnected { reason } = event {
+                        return reason;
+                    }
+                }
+                panic!("the connection was never told");
+            })
+            .await
+            .unwrap();
+            assert_eq!(disconnected, DisconnectReason::SocketFailure);
+            assert!(conn.is_closed().await);
+        }
+        assert!(server.connections().await.is_empty());
+
+        // nothing of the connections holds on to the socket, only the listener does.
+        drop(server);
+        let mut released = false;
+        for _ in 0..100 {
+            if UdpSocket::bind(address).await.is_ok() {
+                released = true;
+                break;
+            }
+            task::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(released, "the socket was never released");
+        drop(conns);
+    });
+}
@@ -0,0 +1,123 @@
+#![cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+//! A snapshot of the statistics never mixes counters from before and after an update.
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use async_std::{future::timeout, task};
+use futures::join;
+use rak_rs::connection::stats::{SharedStats, MIN_DATAGRAM_SIZE};
+use rak_rs::{Client, ConnectionStats, Listener};
+
+#[test]
+fn test_snapshot_is_never_torn() {
+    let stats = Arc::new(SharedStats::default());
+    // stands in for the send queue, which every update and publish holds.
+    let queue = Arc::new(Mutex::new(()));
+
+    let writer = {
+        let (stats, queue) = (stats.clone(), queue.clone());
+        thread::spawn(move || {
+            for i in 0..200_000u64 {
+                let _queue = queue.lock().unwrap();
+                stats.datagrams_sent.fetch_add(1, Ordering::Relaxed);
+                stats.bytes_sent.fetch_add(10, Ordering::Relaxed);
+                if i % 7 == 0 {
+                    stats.publish();
+                }
+            }
+        })
+    };
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let stats = stats.clone();
+            thread::spawn(move || {
+                let mut last = stats.snapshot();
+                for _ in 0..200_000 {
+                    let counters = stats.snapshot();
+                    assert_eq!(counters.bytes_sent, counters.datagrams_sent * 10);
+                    assert!(counters.epoch >= last.epoch);
+                    assert!(counters.datagrams_sent >= last.datagrams_sent);
+                    last = counters;
+                }
+            })
+        })
+        .collect();
+
+    writer.join().unwrap();
+    for reader in readers {
+        reader.join().unwrap();
+    }
+}
+
+/// What has to hold for every snapshot, and from one snapshot to the next.
+fn check(stats: &ConnectionStats, last: &ConnectionStats) {
+    assert!(stats.bytes_sent >= stats.datagrams_sent * MIN_DATAGRAM_SIZE as u64);
+    assert!((0.0..=1.0).contains(&stats.loss_ratio()));
+    assert!(stats.epoch >= last.epoch);
+    assert!(stats.datagrams_sent >= last.datagrams_sent);
+    assert!(stats.bytes_sent >= last.bytes_sent);
+    assert!(stats.timeout_retransmits >= last.timeout_retransmits);
+    assert!(stats.nack_retransmits >= last.nack_retransmits);
+    assert!(stats.repeated_acks >= last.repeated_acks);
+}
+
+#[test]
+fn test_stats_under_load() {
+    task::block_on(async {
+        const ADDRESS: &str = "127.0.0.1:19271";
+        const MESSAGES: usize = 2_000;
+        let mut server = Listener::bind(ADDRESS).await.unwrap();
+        server.start().await.unwrap();
+
+        let client = Client::new(11, 1400);
+        timeout(Duration::from_secs(10), client.connect(ADDRESS))
+            .await
+            .expect("connect timed out")
+            .expect("failed to connect");
+        let conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let load = async {
+            join!(
+                async {
+                    for i in 0..MESSAGES {
+                        let len = if i % 16 == 0 { 4_000 } else { 100 };
+                        conn.send(&vec![0xfe; len], i % 2 == 0).await.unwrap();
+                    }
+                },
+                async {
+                    for _ in 0..MESSAGES {
+                        client.recv().await.unwrap();
+                    }
+                },
+                async {
+                    let mut last = ConnectionStats::default();
+                    for _ in 0..MESSAGES {
+                        let stats = conn.stats().await;
+                        check(&stats, &last);
+                        last = stats;
+                    }
+                },
+                async {
+                    let mut last = ConnectionStats::default();
+                    for _ in 0..MESSAGES {
+                        let stats = client.stats().await;
+                        check(&stats, &last);
+                        last = stats;
+                    }
+                }
+            )
+        };
+        timeout(Duration::from_secs(30), load)
+            .await
+            .expect("the load did not finish");
+
+        let stats = conn.stats().await;
+        assert!(stats.datagrams_sent > 0);
+        assert!(stats.average_datagram_size() >= MIN_DATAGRAM_SIZE as u64);
+    });
+}
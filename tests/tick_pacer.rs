@@ -0,0 +1,191 @@
+#![cfg(feature = "async_std")]
+//! When the tick of a connection runs, driven by a clock the test moves by hand.
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_std::{net::UdpSocket, task};
+use rak_rs::connection::controller::jitter::RTO_JITTER;
+use rak_rs::connection::controller::rtt::RttEstimator;
+use rak_rs::connection::controller::tick::{TickDemand, TickPacer};
+use rak_rs::connection::queue::SendQueue;
+use rak_rs::protocol::reliability::Reliability;
+
+const MIN: Duration = Duration::from_millis(10);
+const MAX: Duration = Duration::from_millis(50);
+const KEEPALIVE: Duration = Duration::from_secs(3);
+
+/// Ticks from `start` until `done` says the tick at that time did its job, returns when that
+/// was and how many ticks it took. `wakes` pull the ticks in, as received datagrams would.
+fn run(
+    pacer: &TickPacer,
+    start: Instant,
+    mut wakes: Vec<Duration>,
+    demand: impl Fn(Instant) -> TickDemand,
+    done: impl Fn(Instant) -> bool,
+) -> (Instant, usize) {
+    let mut now = start;
+    let mut ticks = 0;
+    let mut next_tick = pacer.next_tick(now, &demand(now));
+    wakes.reverse();
+    loop {
+        // a wake before the tick only moves it.
+        while let Some(wake) = wakes.last().map(|wake| start + *wake) {
+            if wake >= next_tick {
+                break;
+            }
+            next_tick = pacer.wake(next_tick, wake);
+            wakes.pop();
+        }
+        now = next_tick;
+        ticks += 1;
+        if done(now) {
+            return (now, ticks);
+        }
+        next_tick = pacer.next_tick(now, &demand(now));
+    }
+}
+
+#[test]
+fn test_fast_rtt_retransmits_within_a_min_tick() {
+    let pacer = TickPacer::new(MIN, MAX);
+    let sent = Instant::now();
+
+    for rto in [1, 7, 23, 49, 50, 51, 130, 999].map(Duration::from_millis) {
+        let due = sent + rto;
+        for wakes in [
+            vec![],
+            vec![Duration::from_millis(3), rto.saturating_sub(MIN / 2)],
+        ] {
+            let (retransmitted, _) = run(
+                &pacer,
+                sent,
+                wakes,
+                |now| TickDemand {
+                    backlog: false,
+                    ack_debt: false,
+                    retransmit_in: Some(due.saturating_duration_since(now)),
+                    idle_in: KEEPALIVE,
+                },
+                |now| now >= due,
+            );
+            assert!(
+                retransmitted <= due + MIN,
+                "rto {:?} retransmitted {:?} late",
+                rto,
+                retransmitted - due
+            );
+        }
+    }
+}
+
+#[test]
+fn test_in_flight_ticks_at_least_every_max() {
+    let pacer = TickPacer::new(MIN, MAX);
+    let demand = TickDemand {
+        backlog: false,
+        ack_debt: false,
+        retransmit_in: Some(Duration::from_secs(1)),
+        idle_in: KEEPALIVE,
+    };
+    assert_eq!(pacer.interval(&demand), MAX);
+    assert_eq!(
+        pacer.interval(&TickDemand {
+            retransmit_in: Some(Duration::ZERO),
+            ..demand
+        }),
+        MIN
+    );
+    assert_eq!(
+        pacer.interval(&TickDemand {
+            backlog: true,
+            ..demand
+        }),
+        MIN
+    );
+    assert_eq!(
+        pacer.interval(&TickDemand {
+            retransmit_in: None,
+            ack_debt: true,
+            ..demand
+        }),
+        MAX
+    );
+
+    // a max below the min is raised to it.
+    let pacer = TickPacer::new(MAX, MIN);
+    assert_eq!((pacer.min(), pacer.max()), (MAX, MAX));
+}
+
+#[test]
+fn test_idle_connection_ticks_when_keepalive_is_due() {
+    let pacer = TickPacer::new(MIN, MAX);
+    let last_sent = Instant::now();
+    let due = last_sent + KEEPALIVE;
+
+    let (keepalive, ticks) = run(
+        &pacer,
+        last_sent,
+        vec![],
+        |now| TickDemand {
+            backlog: false,
+            ack_debt: false,
+            retransmit_in: None,
+            idle_in: KEEPALIVE.saturating_sub(now - last_sent),
+        },
+        |now| now >= due,
+    );
+    assert!(keepalive >= due && keepalive <= due + MIN);
+    assert_eq!(ticks, 1);
+
+    // a datagram of the peer is handled right away, and the tick after it waits again.
+    let (keepalive, ticks) = run(
+        &pacer,
+        last_sent,
+        vec![Duration::from_secs(1)],
+        |now| TickDemand {
+            backlog: false,
+            ack_debt: false,
+            retransmit_in: None,
+            idle_in: KEEPALIVE.saturating_sub(now - last_sent),
+        },
+        |now| now >= due,
+    );
+    assert!(keepalive >= due && keepalive <= due + MIN);
+    assert_eq!(ticks, 2);
+}
+
+#[test]
+fn test_send_queue_reports_what_the_tick_waits_on() {
+    task::block_on(async {
+        let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let mut send_q = SendQueue::new(1400, 12000, 5, socket, peer.local_addr().unwrap());
+
+        let now = Instant::now();
+        assert_eq!(send_q.next_retransmit(now), None);
+        assert!(!send_q.has_backlog());
+
+        send_q
+            .insert(&[0xfe, 1], Reliability::ReliableOrd, false, Some(0))
+            .await
+            .unwrap();
+        assert!(send_q.has_backlog());
+        send_q.update().await;
+        assert!(!send_q.has_backlog());
+
+        let now = Instant::now();
+        let retransmit_in = send_q.next_retransmit(now).expect("nothing in flight");
+        // the timeout is spread by the jitter, a datagram may be due a little after it.
+        let rto = RttEstimator::new().rto().mul_f64(1.0 + RTO_JITTER);
+        assert!(retransmit_in > Duration::ZERO && retransmit_in <= rto);
+        assert!(send_q.keepalive_in(KEEPALIVE, now) > KEEPALIVE - Duration::from_secs(1));
+        assert_eq!(
+            send_q.next_retransmit(now + Duration::from_secs(60)),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(
+            send_q.keepalive_in(KEEPALIVE, now + KEEPALIVE),
+            Duration::ZERO
+        );
+    });
+}
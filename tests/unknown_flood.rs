@@ -0,0 +1,158 @@
+use std::time::{Duration, Instant};
+
+#[cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+use async_std::{future::timeout, task};
+use rak_rs::connection::controller::flood::{FloodVerdict, UnknownFloodGuard, USER_PACKET_ID};
+#[cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+use rak_rs::connection::event::{RakEvent, ViolationKind};
+#[cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+use rak_rs::connection::options::ConnOptions;
+#[cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+use rak_rs::{Client, Listener};
+
+/// An id below the user range that RakNet doesn't assign.
+const CUSTOM_ID: u8 = 0x42;
+
+#[test]
+fn test_flood_suppressed_when_unclaimed() {
+    let mut guard = UnknownFloodGuard::new(Some(0.5), Duration::from_secs(5), &[]);
+    let start = Instant::now();
+
+    // some regular traffic first.
+    for i in 0..16 {
+        guard.known(start + Duration::from_millis(i));
+    }
+
+    let mut verdicts = Vec::new();
+    for i in 0..200 {
+        verdicts.push(guard.unknown(CUSTOM_ID, start + Duration::from_millis(16 + i)));
+    }
+
+    let flooded = verdicts
+        .iter()
+        .filter(|v| **v == FloodVerdict::Flooded)
+        .count();
+    assert_eq!(flooded, 1, "the violation should only be reported once");
+    assert!(guard.is_suppressing());
+    assert_eq!(verdicts.last(), Some(&FloodVerdict::Suppress));
+    assert!(guard.suppressed() > 150);
+
+    // the flood stops, and the peer goes back to normal traffic.
+    let later = start + Duration::from_secs(10);
+    for i in 0..64 {
+        guard.known(later + Duration::from_millis(i));
+    }
+    assert!(!guard.is_suppressing());
+    assert_eq!(
+        guard.unknown(CUSTOM_ID, later + Duration::from_millis(64)),
+        FloodVerdict::Deliver
+    );
+}
+
+#[test]
+fn test_flood_exempt_when_claimed() {
+    let mut guard = UnknownFloodGuard::new(Some(0.5), Duration::from_secs(5), &[CUSTOM_ID]);
+    let start = Instant::now();
+
+    for i in 0..200 {
+        assert_eq!(
+            guard.unknown(CUSTOM_ID, start + Duration::from_millis(i)),
+            FloodVerdict::Deliver
+        );
+    }
+
+    assert!(!guard.is_suppressing());
+    assert_eq!(guard.suppressed(), 0);
+}
+
+#[test]
+fn test_flood_disabled_by_default() {
+    let mut guard = UnknownFloodGuard::new(None, Duration::from_secs(5), &[]);
+    let start = Instant::now();
+
+    for i in 0..200 {
+        assert_eq!(
+            guard.unknown(CUSTOM_ID, start + Duration::from_millis(i)),
+            FloodVerdict::Deliver
+        );
+    }
+}
+
+#[test]
+fn test_user_ids_are_claimed_by_default() {
+    let mut guard = UnknownFloodGuard::new(Some(0.5), Duration::from_secs(5), &[]);
+    let start = Instant::now();
+
+    for i in 0..200u8 {
+        let id = USER_PACKET_ID.saturating_add(i);
+        assert!(guard.is_claimed(id));
+        assert_eq!(
+            guard.unknown(id, start + Duration::from_millis(i.into())),
+            FloodVerdict::Deliver
+        );
+    }
+    assert!(!guard.is_claimed(USER_PACKET_ID - 1));
+    assert_eq!(guard.suppressed(), 0);
+}
+
+#[cfg(all(feature = "async_std", feature = "client", feature = "server"))]
+#[test]
+fn test_game_packets_are_not_a_flood() {
+    task::block_on(async {
+        const ADDRESS: &str = "127.0.0.1:19212";
+        let options = ConnOptions {
+            unknown_flood_threshold: Some(0.5),
+            ..Default::default()
+        };
+        let mut server = Listener::bind_with_options(ADDRESS, options).await.unwrap();
+        server.start().await.unwrap();
+
+        let client = Client::new(11, 1400);
+        timeout(Duration::from_secs(10), client.connect(ADDRESS))
+            .await
+            .expect("connect timed out")
+            .expect("failed to connect");
+        let mut conn = timeout(Duration::from_secs(5), server.accept())
+            .await
+            .unwrap()
+            .unwrap();
+
+        // nothing but game packets, far more than the guard needs to trust the ratio.
+        for i in 0..100u8 {
+            client.send_ord(&[0xfe, i], 0).await.unwrap();
+        }
+        for i in 0..100u8 {
+            let packet = timeout(Duration::from_secs(5), conn.recv())
+                .await
+                .expect("a game packet was dropped")
+                .unwrap();
+            assert_eq!(packet, vec![0xfe, i]);
+        }
+        assert_eq!(conn.unknown_suppressed(), 0);
+
+        // packets with an id RakNet doesn't know are a flood, once they outnumber the rest.
+        for i in 0..300u16 {
+            client.send_ord(&[CUSTOM_ID, i as u8], 0).await.unwrap();
+        }
+        // the connection only reads on while the application takes what it delivers.
+        let mut delivered = 0;
+        while let Ok(Ok(_)) = timeout(Duration::from_secs(1), conn.recv()).await {
+            delivered += 1;
+        }
+        assert!(delivered < 300, "the flood was delivered");
+
+        let mut flooded = false;
+        while let Ok(Ok(event)) = timeout(Duration::from_secs(2), conn.recv_event()).await {
+            if event
+                == (RakEvent::ProtocolViolation {
+                    kind: ViolationKind::UnknownFlood,
+                })
+            {
+                flooded = true;
+                break;
+            }
+        }
+        assert!(flooded, "the flood was never reported");
+        assert_eq!(conn.unknown_suppressed(), 300 - delivered);
+    });
+}
@@ -0,0 +1,154 @@
+#![cfg(feature = "async_std")]
+//! Unreliable messages skip the bookkeeping of the other reliabilities, they don't allocate
+//! once the queue is warmed up, and are the first to go when the socket struggles.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_std::{future::timeout, net::UdpSocket, task};
+use binary_util::interfaces::Reader;
+use rak_rs::connection::controller::budget::{BudgetShare, SendBudget, DEFAULT_SEND_OVERDRAFT};
+use rak_rs::connection::queue::SendQueue;
+use rak_rs::protocol::frame::FramePacket;
+use rak_rs::protocol::RAKNET_HEADER_FRAME_OVERHEAD;
+use rak_rs::socket::ENOBUFS;
+use rak_rs::Reliability;
+
+/// Counts the allocations made by the current thread.
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAlloc = CountingAlloc;
+
+async fn mock() -> (SendQueue, UdpSocket) {
+    let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+    let queue = SendQueue::new(1400, 12000, 5, socket, peer.local_addr().unwrap());
+    (queue, peer)
+}
+
+/// The frame sets the peer received so far.
+async fn received(peer: &UdpSocket) -> Vec<FramePacket> {
+    let mut packets = Vec::new();
+    let mut buf = [0u8; 2048];
+    while let Ok(Ok(len)) = timeout(Duration::from_millis(50), peer.recv(&mut buf)).await {
+        packets.push(FramePacket::read_from_slice(&buf[..len]).unwrap());
+    }
+    packets
+}
+
+#[test]
+fn test_unreliable_send_does_not_allocate() {
+    task::block_on(async {
+        let (mut queue, peer) = mock().await;
+        let message = [0xfe, 1, 2, 3, 4, 5, 6, 7];
+
+        for _ in 0..16 {
+            queue
+                .insert(&message, Reliability::Unreliable, true, None)
+                .await
+                .unwrap();
+        }
+
+        let before = ALLOCATIONS.with(Cell::get);
+        for _ in 0..32 {
+            queue
+                .insert(&message, Reliability::Unreliable, true, None)
+                .await
+                .unwrap();
+        }
+        assert_eq!(ALLOCATIONS.with(Cell::get) - before, 0);
+        assert_eq!(queue.pending(), 0);
+
+        let packets = received(&peer).await;
+        assert_eq!(packets.len(), 48);
+        for (sequence, packet) in packets.iter().enumerate() {
+            assert_eq!(packet.sequence.get(), sequence as u32);
+            assert_eq!(packet.frames.len(), 1);
+            assert_eq!(packet.frames[0].reliability, Reliability::Unreliable);
+            assert_eq!(packet.frames[0].body, message);
+        }
+    });
+}
+
+#[test]
+fn test_unreliable_is_dropped_under_backoff() {
+    task::block_on(async {
+        let (mut queue, peer) = mock().await;
+        queue.fail_sends([ENOBUFS]);
+
+        queue
+            .insert(&[0xfe, 0], Reliability::Unreliable, true, None)
+            .await
+            .unwrap();
+        // still backing off, this one isn't even tried.
+        queue
+            .insert(&[0xfe, 1], Reliability::Unreliable, true, None)
+            .await
+            .unwrap();
+        assert_eq!(queue.enobufs_backoffs(), 1);
+        assert_eq!(queue.unreliable_dropped(), 2);
+        assert_eq!(
+            queue
+                .shared_stats()
+                .unreliable_dropped
+                .load(std::sync::atomic::Ordering::Relaxed),
+            2
+        );
+
+        // nothing was held back for later.
+        queue.update().await;
+        assert!(received(&peer).await.is_empty());
+    });
+}
+
+#[test]
+fn test_immediate_unreliable_is_charged_once() {
+    task::block_on(async {
+        let (mut queue, peer) = mock().await;
+        let budget = Arc::new(Mutex::new(SendBudget::new(
+            1_000_000,
+            DEFAULT_SEND_OVERDRAFT,
+            Instant::now(),
+        )));
+        queue.set_send_budget(Some(BudgetShare::new(budget.clone())));
+        let message = [0xfe, 1, 2, 3];
+
+        for ride_along in [false, true] {
+            // a ride-along sends the packet down the regular path.
+            if ride_along {
+                queue.ride_along_ping();
+            }
+            let before = budget.lock().unwrap().stats(Instant::now()).sent;
+            queue
+                .insert(&message, Reliability::Unreliable, true, None)
+                .await
+                .unwrap();
+            assert_eq!(received(&peer).await.len(), 1);
+
+            let sent = budget.lock().unwrap().stats(Instant::now()).sent - before;
+            assert_eq!(
+                sent,
+                (message.len() + RAKNET_HEADER_FRAME_OVERHEAD as usize) as u64,
+                "the budget was not charged once with a ride-along: {}",
+                ride_along
+            );
+        }
+    });
+}
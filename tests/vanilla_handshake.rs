@@ -0,0 +1,320 @@
+#![cfg(all(feature = "async_std", feature = "client"))]
+//! Pins the handshake to how vanilla RakNet 4 puts it on the wire.
+//!
+//! The fixtures in `tests/fixtures/vanilla` are laid out field by field after `RakPeer.cpp`
+//! and `BitStream::Write(SystemAddress)`. Every file is whitespace separated hex, `#` starts
+//! a comment and `00x1354` repeats a byte. The server is `127.0.0.1:19190` with the guid
+//! `1112131415161718`, the client is `127.0.0.1:54321`.
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use async_std::{future::timeout, net::UdpSocket, task};
+use binary_util::interfaces::{Reader, Writer};
+use rak_rs::protocol::frame::{Frame, FramePacket};
+use rak_rs::protocol::index::{DatagramSeq, MessageIndex};
+use rak_rs::protocol::packet::offline::OfflinePacket;
+use rak_rs::protocol::packet::online::{Capabilities, ConnectionRequest, OnlinePacket};
+use rak_rs::protocol::packet::RakPacket;
+use rak_rs::{Client, ConnOptions, Reliability};
+
+const SERVER: &str = "127.0.0.1:19190";
+
+fn fixture(name: &str) -> Vec<u8> {
+    let path = format!(
+        "{}/tests/fixtures/vanilla/{}.hex",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    );
+    let text = std::fs::read_to_string(&path).unwrap();
+    let mut bytes = Vec::new();
+    for line in text.lines() {
+        let line = line.split('#').next().unwrap();
+        for token in line.split_whitespace() {
+            let (byte, count) = match token.split_once('x') {
+                Some((byte, count)) => (byte, count.parse().unwrap()),
+                None => (token, 1),
+            };
+            let byte = u8::from_str_radix(byte, 16).unwrap();
+            bytes.extend(std::iter::repeat(byte).take(count));
+        }
+    }
+    bytes
+}
+
+fn client_address() -> SocketAddr {
+    "127.0.0.1:54321".parse().unwrap()
+}
+
+fn server_address() -> SocketAddr {
+    SERVER.parse().unwrap()
+}
+
+fn offline(name: &str) -> OfflinePacket {
+    let bytes = fixture(name);
+    let packet = OfflinePacket::read_from_slice(&bytes).unwrap();
+    assert_eq!(
+        RakPacket::from(packet.clone())
+            .write_to_bytes()
+            .unwrap()
+            .as_slice(),
+        &bytes[..],
+        "{} doesn't encode back to the same bytes",
+        name
+    );
+    packet
+}
+
+fn online(name: &str) -> OnlinePacket {
+    let bytes = fixture(name);
+    let packet = OnlinePacket::read_from_slice(&bytes).unwrap();
+    assert_eq!(
+        packet.write_to_bytes().unwrap().as_slice(),
+        &bytes[..],
+        "{} doesn't encode back to the same bytes",
+        name
+    );
+    packet
+}
+
+#[test]
+fn test_offline_packets_conform() {
+    match offline("unconnected_ping") {
+        OfflinePacket::UnconnectedPing(pk) => {
+            assert_eq!(pk.timestamp, 0x2a);
+            assert_eq!(pk.client_id, 0x0102030405060708);
+        }
+        pk => panic!("decoded as {:?}", pk),
+    }
+    // the offline ping response is only understood with the mcpe feature.
+    match OfflinePacket::read_from_slice(&fixture("unconnected_pong")).unwrap() {
+        OfflinePacket::UnconnectedPong(pk) => {
+            assert_eq!(pk.timestamp, 0x2a);
+            assert_eq!(pk.server_id, 0x1112131415161718);
+        }
+        pk => panic!("decoded as {:?}", pk),
+    }
+    match offline("open_connection_request_1") {
+        OfflinePacket::OpenConnectRequest(pk) => {
+            assert_eq!(pk.protocol, 11);
+            assert_eq!(pk.mtu_size, 1400);
+        }
+        pk => panic!("decoded as {:?}", pk),
+    }
+    match offline("open_connection_reply_1") {
+        OfflinePacket::OpenConnectReply(pk) => {
+            assert_eq!(pk.server_id, 0x1112131415161718);
+            assert!(!pk.security);
+            assert_eq!(pk.mtu_size, 1400);
+        }
+        pk => panic!("decoded as {:?}", pk),
+    }
+    match offline("open_connection_request_2") {
+        OfflinePacket::SessionInfoRequest(pk) => {
+            assert_eq!(pk.address, server_address());
+            assert_eq!(pk.mtu_size, 1400);
+            assert_eq!(pk.client_id, 0x0102030405060708);
+        }
+        pk => panic!("decoded as {:?}", pk),
+    }
+    match offline("open_connection_reply_2") {
+        OfflinePacket::SessionInfoReply(pk) => {
+            assert_eq!(pk.server_id, 0x1112131415161718);
+            assert_eq!(pk.client_address, client_address());
+            assert_eq!(pk.mtu_size, 1400);
+            assert!(!pk.security);
+        }
+        pk => panic!("decoded as {:?}", pk),
+    }
+}
+
+#[test]
+fn test_online_packets_conform() {
+    match online("connection_request") {
+        OnlinePacket::ConnectionRequest(pk) => {
+            assert_eq!(pk.client_id, 0x0102030405060708);
+            assert_eq!(pk.time, 0x2a);
+            assert!(!pk.security);
+            assert_eq!(pk.capabilities, Capabilities::NONE);
+        }
+        pk => panic!("decoded as {:?}", pk),
+    }
+    match online("connection_request_accepted") {
+        OnlinePacket::ConnectionAccept(pk) => {
+            assert_eq!(pk.client_address, client_address());
+            assert_eq!(
+                pk.internal_ids,
+                vec!["255.255.255.255:0".parse::<SocketAddr>().unwrap(); 10]
+            );
+            assert_eq!(pk.request_time, 0x2a);
+            assert_eq!(pk.timestamp, 0x100);
+        }
+        pk => panic!("decoded as {:?}", pk),
+    }
+    match online("new_incoming_connection") {
+        OnlinePacket::NewConnection(pk) => {
+            assert_eq!(pk.server_address, server_address());
+            assert_eq!(pk.system_address, vec![server_address(); 10]);
+        }
+        pk => panic!("decoded as {:?}", pk),
+    }
+
+    // ipv6 addresses are a sockaddr_in6, with the family in little endian.
+    let address: SocketAddr = "[::1]:19132".parse().unwrap();
+    let mut bytes = vec![0x13, 0x06, 0x17, 0x00, 0x4a, 0xbc, 0, 0, 0, 0];
+    bytes.extend_from_slice(
+        &address
+            .ip()
+            .to_string()
+            .parse::<std::net::Ipv6Addr>()
+            .unwrap()
+            .octets(),
+    );
+    bytes.extend_from_slice(&[0; 4 + 16]);
+    match OnlinePacket::read_from_slice(&bytes).unwrap() {
+        OnlinePacket::NewConnection(pk) => {
+            assert_eq!(pk.server_address, address);
+            assert_eq!(
+                OnlinePacket::NewConnection(pk)
+                    .write_to_bytes()
+                    .unwrap()
+                    .as_slice(),
+                &bytes[..]
+            );
+        }
+        pk => panic!("decoded as {:?}", pk),
+    }
+}
+
+#[test]
+fn test_default_connection_request_is_vanilla() {
+    let request = OnlinePacket::ConnectionRequest(ConnectionRequest {
+        client_id: 0x0102030405060708,
+        time: 0x2a,
+        security: false,
+        capabilities: Capabilities::NONE.offer(),
+    });
+    assert_eq!(
+        request.write_to_bytes().unwrap().as_slice(),
+        &fixture("connection_request")[..]
+    );
+}
+
+/// Receives datagrams of `client` until one is a frame set, and returns the body of its first
+/// frame.
+async fn recv_frame(socket: &UdpSocket, client: SocketAddr) -> Vec<u8> {
+    let mut buf = [0u8; 2048];
+    loop {
+        let (len, from) = socket.recv_from(&mut buf).await.unwrap();
+        if from == client && buf[0] & 0x80 != 0 && buf[0] & 0x40 == 0 {
+            let datagram = FramePacket::read_from_slice(&buf[..len]).unwrap();
+            return datagram.frames[0].body.clone();
+        }
+    }
+}
+
+/// Receives the next datagram of `client`, leaving out what an earlier client still sends.
+async fn recv_from(socket: &UdpSocket, client: SocketAddr, buf: &mut [u8]) -> usize {
+    loop {
+        let (len, from) = socket.recv_from(buf).await.unwrap();
+        if from == client {
+            return len;
+        }
+    }
+}
+
+/// Plays the vanilla server to `client`, `hello` is whether it asks if the server runs rak-rs.
+async fn transcript(client: Client, hello: bool) {
+    let server = UdpSocket::bind(SERVER).await.unwrap();
+
+    let script = task::spawn(async move {
+        let mut buf = [0u8; 2048];
+
+        let expected = fixture("unconnected_ping");
+        let (len, client) = loop {
+            let (len, from) = server.recv_from(&mut buf).await.unwrap();
+            if buf[0] == expected[0] {
+                break (len, from);
+            }
+        };
+        assert_eq!(len, expected.len());
+        // the time and the guid of the ping are not pinned, the magic is.
+        assert_eq!(&buf[9..25], &expected[9..25]);
+        server
+            .send_to(&fixture("unconnected_pong"), client)
+            .await
+            .unwrap();
+
+        let len = recv_from(&server, client, &mut buf).await;
+        assert_eq!(&buf[..len], &fixture("open_connection_request_1")[..]);
+        server
+            .send_to(&fixture("open_connection_reply_1"), client)
+            .await
+            .unwrap();
+
+        let len = recv_from(&server, client, &mut buf).await;
+        let expected = fixture("open_connection_request_2");
+        let end = expected.len();
+        // the guid of the client is random, everything before it is not.
+        assert_eq!(&buf[..end - 8], &expected[..end - 8]);
+        let guid = buf[end - 8..end].to_vec();
+        // a vanilla server doesn't read past the guid.
+        let trailer = Capabilities::read_from_slice(&buf[end..len]).unwrap();
+        if hello {
+            assert_eq!(trailer, Capabilities::hello());
+        } else {
+            assert_eq!(len, end);
+        }
+        server
+            .send_to(&fixture("open_connection_reply_2"), client)
+            .await
+            .unwrap();
+
+        let request = recv_frame(&server, client).await;
+        let expected = fixture("connection_request");
+        assert_eq!(request.len(), expected.len());
+        assert_eq!(request[0], expected[0]);
+        assert_eq!(&request[1..9], &guid[..]);
+        // the time of the request is the only thing left out.
+        assert_eq!(request[17..], expected[17..]);
+
+        let mut frame = Frame::new(
+            Reliability::Reliable,
+            Some(&fixture("connection_request_accepted")),
+        );
+        frame.reliable_index = Some(MessageIndex::new(0));
+        let mut datagram = FramePacket::new();
+        datagram.sequence = DatagramSeq::new(0);
+        datagram.frames.push(frame);
+        server
+            .send_to(datagram.write_to_bytes().unwrap().as_slice(), client)
+            .await
+            .unwrap();
+
+        let new_connection = recv_frame(&server, client).await;
+        assert_eq!(new_connection, fixture("new_incoming_connection"));
+    });
+
+    timeout(Duration::from_secs(10), client.connect(SERVER))
+        .await
+        .expect("the handshake stalled")
+        .expect("failed to connect");
+    timeout(Duration::from_secs(5), script)
+        .await
+        .expect("the client never finished the handshake");
+    client.close().await;
+}
+
+#[test]
+fn test_handshake_against_vanilla_transcript() {
+    task::block_on(async {
+        transcript(Client::new(11, 1400), false).await;
+
+        // the server never answers the hello, so the extensions are never offered to it.
+        let options = ConnOptions {
+            datagram_checksum: true,
+            coalesce_channels: vec![0],
+            ..Default::default()
+        };
+        transcript(Client::new(11, 1400).with_options(options), true).await;
+    });
+}